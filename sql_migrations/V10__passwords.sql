@@ -0,0 +1,6 @@
+CREATE TABLE passwords (
+    user_id UUID NOT NULL PRIMARY KEY,
+    password_hash TEXT NOT NULL,
+    updated TIMESTAMPTZ NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
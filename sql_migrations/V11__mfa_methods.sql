@@ -0,0 +1,8 @@
+CREATE TABLE mfa_methods (
+    user_id UUID NOT NULL,
+    method SMALLINT NOT NULL,
+    created TIMESTAMPTZ NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_user_id_method ON mfa_methods(user_id, method);
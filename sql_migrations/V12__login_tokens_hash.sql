@@ -0,0 +1,6 @@
+-- Only a keyed hash of the login tokens is stored, the existing plain tokens are hashed by the service on startup
+-- as the key is not available to the migration.
+ALTER TABLE login_tokens ADD COLUMN token_hash VARCHAR(64) NULL;
+ALTER TABLE login_tokens ALTER COLUMN token DROP NOT NULL;
+
+CREATE UNIQUE INDEX idx_token_hash ON login_tokens(token_hash);
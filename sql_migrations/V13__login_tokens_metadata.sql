@@ -0,0 +1,10 @@
+ALTER TABLE login_tokens ADD COLUMN token_id UUID NOT NULL DEFAULT gen_random_uuid();
+ALTER TABLE login_tokens ALTER COLUMN token_id DROP DEFAULT;
+ALTER TABLE login_tokens ADD COLUMN kind SMALLINT NOT NULL DEFAULT 1;
+ALTER TABLE login_tokens ALTER COLUMN kind DROP DEFAULT;
+ALTER TABLE login_tokens ADD COLUMN name VARCHAR(64) NULL;
+ALTER TABLE login_tokens ADD COLUMN creation_ip INET NULL;
+ALTER TABLE login_tokens ADD COLUMN user_agent TEXT NULL;
+ALTER TABLE login_tokens ADD COLUMN last_used TIMESTAMPTZ NULL;
+
+CREATE UNIQUE INDEX idx_token_id ON login_tokens(token_id);
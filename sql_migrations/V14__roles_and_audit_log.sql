@@ -0,0 +1,21 @@
+CREATE TABLE user_roles (
+    user_id UUID NOT NULL,
+    role VARCHAR(32) NOT NULL,
+    granted TIMESTAMPTZ NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_user_id_role ON user_roles(user_id, role);
+
+-- No foreign keys, the log shall survive the deletion of the users
+CREATE TABLE audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    created TIMESTAMPTZ NOT NULL,
+    actor_id UUID NULL,
+    action VARCHAR(64) NOT NULL,
+    target_id UUID NULL,
+    details JSONB NOT NULL
+);
+
+CREATE INDEX idx_audit_log_actor_id ON audit_log(actor_id, created);
+CREATE INDEX idx_audit_log_target_id ON audit_log(target_id, created);
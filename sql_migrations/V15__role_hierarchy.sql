@@ -0,0 +1,7 @@
+-- A role implies its parent role (and transitively all the ancestors), ex. the parent of studio:owner is
+-- studio:admin and the parent of studio:admin is studio:member. Roles without a parent need no entry.
+CREATE TABLE roles (
+    role VARCHAR(32) PRIMARY KEY,
+    parent VARCHAR(32) NULL,
+    CONSTRAINT fkey_parent FOREIGN KEY(parent) REFERENCES roles(role) ON DELETE SET NULL
+);
@@ -0,0 +1,13 @@
+-- Permissions of the users scoped to a single resource, ex. (edit, studio, 42)
+CREATE TABLE grants (
+    user_id UUID NOT NULL,
+    action VARCHAR(64) NOT NULL,
+    resource_type VARCHAR(32) NOT NULL,
+    resource_id VARCHAR(64) NOT NULL,
+    granted TIMESTAMPTZ NOT NULL,
+    granted_by UUID NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_grants_user_action_resource ON grants(user_id, action, resource_type, resource_id);
+CREATE INDEX idx_grants_resource ON grants(resource_type, resource_id);
@@ -0,0 +1,2 @@
+-- Keyset pagination of the identities by the creation time
+CREATE INDEX idx_created ON identities(created, user_id);
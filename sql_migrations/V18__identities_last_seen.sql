@@ -0,0 +1,5 @@
+-- The last day the user was active, it is updated at most once a day
+ALTER TABLE identities
+    ADD last_seen DATE NULL;
+
+CREATE INDEX idx_last_seen ON identities(last_seen);
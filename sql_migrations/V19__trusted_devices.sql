@@ -0,0 +1,12 @@
+-- Browsers allowed to skip the second factor, the cookie of the browser holds the device_id
+CREATE TABLE trusted_devices (
+    user_id UUID NOT NULL,
+    device_id UUID NOT NULL,
+    user_agent TEXT NULL,
+    created TIMESTAMPTZ NOT NULL,
+    expire TIMESTAMPTZ NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_trusted_devices_device_id ON trusted_devices(device_id);
+CREATE INDEX idx_trusted_devices_user_id ON trusted_devices(user_id);
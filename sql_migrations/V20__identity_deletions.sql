@@ -0,0 +1,9 @@
+-- Accounts marked for deletion, they are purged after the grace period unless the user logs in
+CREATE TABLE identity_deletions (
+    user_id UUID NOT NULL PRIMARY KEY,
+    requested TIMESTAMPTZ NOT NULL,
+    purge_after TIMESTAMPTZ NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_identity_deletions_purge_after ON identity_deletions(purge_after);
@@ -0,0 +1,8 @@
+-- Accounts that shall not be deleted, ex. during an investigation
+CREATE TABLE legal_holds (
+    user_id UUID NOT NULL PRIMARY KEY,
+    reason TEXT NOT NULL,
+    placed TIMESTAMPTZ NOT NULL,
+    placed_by UUID NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
@@ -0,0 +1,11 @@
+-- The latest decision of the users on the optional data processing purposes
+CREATE TABLE consents (
+    user_id UUID NOT NULL,
+    purpose VARCHAR(64) NOT NULL,
+    granted BOOLEAN NOT NULL,
+    version VARCHAR(64) NOT NULL,
+    updated TIMESTAMPTZ NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_consents_user_id_purpose ON consents(user_id, purpose);
@@ -0,0 +1,12 @@
+-- Requests of the users to merge another identity (source) into their own, they are resolved by the administrators
+CREATE TABLE merge_requests (
+    request_id UUID NOT NULL PRIMARY KEY,
+    user_id UUID NOT NULL,
+    source_user_id UUID NOT NULL,
+    provider VARCHAR(64) NOT NULL,
+    created TIMESTAMPTZ NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE,
+    CONSTRAINT fkey_source_user_id FOREIGN KEY(source_user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_merge_requests_user_id_source_user_id ON merge_requests(user_id, source_user_id);
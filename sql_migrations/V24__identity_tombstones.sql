@@ -0,0 +1,9 @@
+-- The users merged into another user, the other services can remap the ownership of their data by the old user id
+CREATE TABLE identity_tombstones (
+    old_user_id UUID NOT NULL PRIMARY KEY,
+    user_id UUID NOT NULL,
+    merged TIMESTAMPTZ NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_identity_tombstones_user_id ON identity_tombstones(user_id);
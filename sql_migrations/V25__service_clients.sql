@@ -0,0 +1,9 @@
+-- The client credentials of the service identities, the client id of a service is its user id
+CREATE TABLE service_clients (
+    user_id UUID NOT NULL PRIMARY KEY,
+    secret_hash VARCHAR(64) NOT NULL,
+    -- space separated list of the allowed scopes
+    scopes TEXT NOT NULL,
+    created TIMESTAMPTZ NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
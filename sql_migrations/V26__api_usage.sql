@@ -0,0 +1,8 @@
+-- The daily number of the API requests of the identities, flushed periodically from the redis counters
+CREATE TABLE api_usage (
+    user_id UUID NOT NULL,
+    day DATE NOT NULL,
+    requests BIGINT NOT NULL,
+    PRIMARY KEY (user_id, day),
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
@@ -0,0 +1,14 @@
+-- The abuse reports of the other services on the identities
+CREATE TABLE abuse_flags (
+    flag_id UUID NOT NULL PRIMARY KEY,
+    user_id UUID NOT NULL,
+    category SMALLINT NOT NULL,
+    severity SMALLINT NOT NULL,
+    -- reference to the evidence kept by the reporter
+    evidence VARCHAR(256),
+    reporter VARCHAR(64) NOT NULL,
+    created TIMESTAMPTZ NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_abuse_flags_user_id_created ON abuse_flags(user_id, created);
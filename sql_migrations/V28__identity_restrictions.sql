@@ -0,0 +1,12 @@
+-- Restrictions of the users honored by the other services, ex. chat:muted. The user can still log in.
+CREATE TABLE identity_restrictions (
+    user_id UUID NOT NULL,
+    restriction VARCHAR(64) NOT NULL,
+    reason TEXT NOT NULL,
+    -- the restriction is permanent when not set
+    expire_at TIMESTAMPTZ NULL,
+    created TIMESTAMPTZ NOT NULL,
+    created_by UUID NULL,
+    PRIMARY KEY (user_id, restriction),
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
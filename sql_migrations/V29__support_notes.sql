@@ -0,0 +1,11 @@
+-- Free-text notes of the support staff on the identities
+CREATE TABLE support_notes (
+    note_id UUID NOT NULL PRIMARY KEY,
+    user_id UUID NOT NULL,
+    note TEXT NOT NULL,
+    author UUID NULL,
+    created TIMESTAMPTZ NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_support_notes_user_id_created ON support_notes(user_id, created);
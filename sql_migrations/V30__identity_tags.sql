@@ -0,0 +1,10 @@
+-- Labels of the identities to manage the cohorts, ex. beta-tester
+CREATE TABLE identity_tags (
+    user_id UUID NOT NULL,
+    tag VARCHAR(64) NOT NULL,
+    created TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (user_id, tag),
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_identity_tags_tag ON identity_tags(tag);
@@ -0,0 +1,10 @@
+-- Time-limited accounts, ex. closed beta or trial press accounts
+CREATE TABLE identity_expirations (
+    user_id UUID NOT NULL PRIMARY KEY,
+    expire_at TIMESTAMPTZ NOT NULL,
+    -- set when the sessions and the login tokens of the expired account have been removed
+    deactivated TIMESTAMPTZ NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_identity_expirations_expire_at ON identity_expirations(expire_at) WHERE deactivated IS NULL;
@@ -0,0 +1,10 @@
+-- The age declared at the registration, either the date of birth or an age bracket as configured
+CREATE TABLE identity_ages (
+    user_id UUID NOT NULL PRIMARY KEY,
+    birth_date DATE NULL,
+    age_bracket SMALLINT NULL,
+    declared TIMESTAMPTZ NOT NULL,
+    -- set when the adult age has been verified, ex. by an identity check of the support
+    verified_adult TIMESTAMPTZ NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
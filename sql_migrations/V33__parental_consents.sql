@@ -0,0 +1,12 @@
+-- The consents of the parents to the accounts of the minors
+CREATE TABLE parental_consents (
+    user_id UUID NOT NULL PRIMARY KEY,
+    parent_email VARCHAR(256) NOT NULL,
+    -- only the hash of the pending consent link is stored, it is cleared when the consent is granted
+    token_hash VARCHAR(64) NULL,
+    requested TIMESTAMPTZ NOT NULL,
+    granted TIMESTAMPTZ NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_parental_consents_token_hash ON parental_consents(token_hash);
@@ -0,0 +1,7 @@
+-- The region of the users recorded at the registration to select the regional policies
+CREATE TABLE identity_regions (
+    user_id UUID NOT NULL PRIMARY KEY,
+    region VARCHAR(32) NOT NULL,
+    created TIMESTAMPTZ NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
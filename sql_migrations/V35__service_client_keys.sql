@@ -0,0 +1,3 @@
+-- The public key of the services authenticating by signed assertions, the secret_hash is empty for the services
+-- without a secret
+ALTER TABLE service_clients ADD COLUMN public_key VARCHAR(64);
@@ -0,0 +1,17 @@
+-- The API tokens acting on behalf of a studio, only the hash of the tokens is stored. The members of a studio are
+-- given by the grants on the studio resource.
+CREATE TABLE studio_tokens (
+    token_id UUID NOT NULL PRIMARY KEY,
+    studio_id UUID NOT NULL,
+    token_hash VARCHAR(64) NOT NULL,
+    name VARCHAR(64) NOT NULL,
+    -- space separated list of the scopes
+    scopes TEXT NOT NULL,
+    created TIMESTAMPTZ NOT NULL,
+    created_by UUID NULL,
+    expire TIMESTAMPTZ NOT NULL,
+    CONSTRAINT fkey_studio_id FOREIGN KEY(studio_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_studio_tokens_hash ON studio_tokens(token_hash);
+CREATE INDEX idx_studio_tokens_studio ON studio_tokens(studio_id);
@@ -0,0 +1,10 @@
+-- The pending ownership transfers of the studios, a transfer is completed when the new owner accepts it.
+CREATE TABLE studio_transfers (
+    studio_id UUID NOT NULL PRIMARY KEY,
+    new_owner_id UUID NOT NULL,
+    requested_by UUID NOT NULL,
+    requested TIMESTAMPTZ NOT NULL,
+    expire TIMESTAMPTZ NOT NULL,
+    CONSTRAINT fkey_studio_id FOREIGN KEY(studio_id) REFERENCES identities(user_id) ON DELETE CASCADE,
+    CONSTRAINT fkey_new_owner_id FOREIGN KEY(new_owner_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
@@ -0,0 +1,15 @@
+-- The email is kept as entered for the delivery, the uniqueness and the lookup use the normalized form. The
+-- normalization depends on the configured policy, the existing addresses are normalized by the service after the
+-- migrations, see DBPool::migrate.
+ALTER TABLE identities
+    ADD normalized_email VARCHAR(256);
+
+DROP INDEX idx_email;
+CREATE UNIQUE INDEX idx_email ON identities(tenant_id, normalized_email);
+
+CREATE TABLE pending_email_normalizations (
+    user_id UUID NOT NULL PRIMARY KEY,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+INSERT INTO pending_email_normalizations SELECT user_id FROM identities WHERE email IS NOT NULL;
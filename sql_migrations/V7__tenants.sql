@@ -0,0 +1,13 @@
+ALTER TABLE identities
+    ADD tenant_id VARCHAR(64) NOT NULL DEFAULT 'default';
+
+DROP INDEX idx_name;
+DROP INDEX idx_email;
+CREATE UNIQUE INDEX idx_name ON identities(tenant_id, name);
+CREATE UNIQUE INDEX idx_email ON identities(tenant_id, email);
+
+ALTER TABLE external_logins
+    ADD tenant_id VARCHAR(64) NOT NULL DEFAULT 'default';
+
+DROP INDEX idx_provider_provider_id;
+CREATE UNIQUE INDEX idx_provider_provider_id ON external_logins(tenant_id, provider, provider_id);
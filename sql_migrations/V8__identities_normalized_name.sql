@@ -0,0 +1,19 @@
+-- Confusable characters cannot be mapped in SQL, existing names are migrated with NFKC and lowercase only and
+-- the service replaces them by the skeleton after the migrations, see DBPool::migrate.
+ALTER TABLE identities
+    ADD normalized_name VARCHAR(64);
+
+UPDATE identities SET normalized_name = lower(normalize(name, NFKC));
+
+ALTER TABLE identities
+    ALTER COLUMN normalized_name SET NOT NULL;
+
+DROP INDEX idx_name;
+CREATE UNIQUE INDEX idx_name ON identities(tenant_id, normalized_name);
+
+CREATE TABLE pending_name_normalizations (
+    user_id UUID NOT NULL PRIMARY KEY,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+INSERT INTO pending_name_normalizations SELECT user_id FROM identities;
@@ -0,0 +1,10 @@
+CREATE TABLE known_devices (
+    user_id UUID NOT NULL,
+    fingerprint VARCHAR(64) NOT NULL,
+    country VARCHAR(8) NOT NULL,
+    first_seen TIMESTAMPTZ NOT NULL,
+    last_seen TIMESTAMPTZ NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_user_id_fingerprint_country ON known_devices(user_id, fingerprint, country);
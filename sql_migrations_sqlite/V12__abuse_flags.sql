@@ -0,0 +1,12 @@
+CREATE TABLE abuse_flags (
+    flag_id BLOB NOT NULL PRIMARY KEY,
+    user_id BLOB NOT NULL,
+    category INTEGER NOT NULL,
+    severity INTEGER NOT NULL,
+    evidence TEXT,
+    reporter TEXT NOT NULL,
+    created TEXT NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_abuse_flags_user_id_created ON abuse_flags(user_id, created);
@@ -0,0 +1,10 @@
+CREATE TABLE identity_restrictions (
+    user_id BLOB NOT NULL,
+    restriction TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    expire_at TEXT NULL,
+    created TEXT NOT NULL,
+    created_by BLOB NULL,
+    PRIMARY KEY (user_id, restriction),
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
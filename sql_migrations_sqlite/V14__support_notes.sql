@@ -0,0 +1,10 @@
+CREATE TABLE support_notes (
+    note_id BLOB NOT NULL PRIMARY KEY,
+    user_id BLOB NOT NULL,
+    note TEXT NOT NULL,
+    author BLOB NULL,
+    created TEXT NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_support_notes_user_id_created ON support_notes(user_id, created);
@@ -0,0 +1,9 @@
+CREATE TABLE identity_tags (
+    user_id BLOB NOT NULL,
+    tag TEXT NOT NULL,
+    created TEXT NOT NULL,
+    PRIMARY KEY (user_id, tag),
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_identity_tags_tag ON identity_tags(tag);
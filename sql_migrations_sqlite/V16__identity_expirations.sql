@@ -0,0 +1,8 @@
+CREATE TABLE identity_expirations (
+    user_id BLOB NOT NULL PRIMARY KEY,
+    expire_at TEXT NOT NULL,
+    deactivated TEXT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_identity_expirations_expire_at ON identity_expirations(expire_at) WHERE deactivated IS NULL;
@@ -0,0 +1,8 @@
+CREATE TABLE identity_ages (
+    user_id BLOB NOT NULL PRIMARY KEY,
+    birth_date TEXT NULL,
+    age_bracket INTEGER NULL,
+    declared TEXT NOT NULL,
+    verified_adult TEXT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
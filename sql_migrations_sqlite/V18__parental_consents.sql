@@ -0,0 +1,10 @@
+CREATE TABLE parental_consents (
+    user_id BLOB NOT NULL PRIMARY KEY,
+    parent_email TEXT NOT NULL,
+    token_hash TEXT NULL,
+    requested TEXT NOT NULL,
+    granted TEXT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_parental_consents_token_hash ON parental_consents(token_hash);
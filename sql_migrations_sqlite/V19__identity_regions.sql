@@ -0,0 +1,6 @@
+CREATE TABLE identity_regions (
+    user_id BLOB NOT NULL PRIMARY KEY,
+    region TEXT NOT NULL,
+    created TEXT NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
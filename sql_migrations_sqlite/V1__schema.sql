@@ -0,0 +1,120 @@
+-- The schema of the SQLite backend, it matches the result of the Postgres migrations. The ids are stored as
+-- 16 byte blobs, the times as RFC3339 text, the json values as text.
+CREATE TABLE identities (
+    user_id BLOB NOT NULL PRIMARY KEY,
+    kind INTEGER NOT NULL,
+    created TEXT NOT NULL,
+    name TEXT NOT NULL,
+    normalized_name TEXT NOT NULL,
+    email TEXT NULL,
+    normalized_email TEXT NULL,
+    email_confirmed INTEGER NOT NULL DEFAULT 0,
+    profile_image TEXT NULL,
+    tenant_id TEXT NOT NULL DEFAULT 'default'
+);
+
+CREATE UNIQUE INDEX idx_name ON identities(tenant_id, normalized_name);
+CREATE UNIQUE INDEX idx_email ON identities(tenant_id, normalized_email);
+
+CREATE TABLE external_logins (
+    user_id BLOB NOT NULL,
+    provider TEXT NOT NULL,
+    provider_id TEXT NOT NULL,
+    linked TEXT NULL,
+    tenant_id TEXT NOT NULL DEFAULT 'default',
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_provider_provider_id ON external_logins(tenant_id, provider, provider_id);
+
+-- Replacement of the user_id_counter sequence
+CREATE TABLE user_id_counter (
+    id INTEGER PRIMARY KEY AUTOINCREMENT
+);
+
+-- Only the hashed tokens are supported, there is no plain token column
+CREATE TABLE login_tokens (
+    user_id BLOB NOT NULL,
+    token_id BLOB NOT NULL,
+    kind INTEGER NOT NULL,
+    token_hash TEXT NOT NULL,
+    created TEXT NOT NULL,
+    expire TEXT NOT NULL,
+    last_used TEXT NULL,
+    name TEXT NULL,
+    creation_ip TEXT NULL,
+    user_agent TEXT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_user_id ON login_tokens(user_id);
+CREATE UNIQUE INDEX idx_token_hash ON login_tokens(token_hash);
+CREATE UNIQUE INDEX idx_token_id ON login_tokens(token_id);
+
+CREATE TABLE known_devices (
+    user_id BLOB NOT NULL,
+    fingerprint TEXT NOT NULL,
+    country TEXT NOT NULL,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_user_id_fingerprint_country ON known_devices(user_id, fingerprint, country);
+
+CREATE TABLE passwords (
+    user_id BLOB NOT NULL PRIMARY KEY,
+    password_hash TEXT NOT NULL,
+    updated TEXT NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE TABLE mfa_methods (
+    user_id BLOB NOT NULL,
+    method INTEGER NOT NULL,
+    created TEXT NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_user_id_method ON mfa_methods(user_id, method);
+
+CREATE TABLE user_roles (
+    user_id BLOB NOT NULL,
+    role TEXT NOT NULL,
+    granted TEXT NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_user_id_role ON user_roles(user_id, role);
+
+-- No foreign keys, the log shall survive the deletion of the users
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created TEXT NOT NULL,
+    actor_id BLOB NULL,
+    action TEXT NOT NULL,
+    target_id BLOB NULL,
+    details TEXT NOT NULL
+);
+
+CREATE INDEX idx_audit_log_actor_id ON audit_log(actor_id, created);
+CREATE INDEX idx_audit_log_target_id ON audit_log(target_id, created);
+
+CREATE TABLE roles (
+    role TEXT PRIMARY KEY,
+    parent TEXT NULL,
+    CONSTRAINT fkey_parent FOREIGN KEY(parent) REFERENCES roles(role) ON DELETE SET NULL
+);
+
+CREATE TABLE grants (
+    user_id BLOB NOT NULL,
+    action TEXT NOT NULL,
+    resource_type TEXT NOT NULL,
+    resource_id TEXT NOT NULL,
+    granted TEXT NOT NULL,
+    granted_by BLOB NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_grants_user_action_resource ON grants(user_id, action, resource_type, resource_id);
+CREATE INDEX idx_grants_resource ON grants(resource_type, resource_id);
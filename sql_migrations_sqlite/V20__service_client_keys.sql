@@ -0,0 +1 @@
+ALTER TABLE service_clients ADD COLUMN public_key TEXT;
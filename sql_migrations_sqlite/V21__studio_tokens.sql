@@ -0,0 +1,14 @@
+CREATE TABLE studio_tokens (
+    token_id BLOB NOT NULL PRIMARY KEY,
+    studio_id BLOB NOT NULL,
+    token_hash TEXT NOT NULL,
+    name TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+    created TEXT NOT NULL,
+    created_by BLOB NULL,
+    expire TEXT NOT NULL,
+    CONSTRAINT fkey_studio_id FOREIGN KEY(studio_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_studio_tokens_hash ON studio_tokens(token_hash);
+CREATE INDEX idx_studio_tokens_studio ON studio_tokens(studio_id);
@@ -0,0 +1,9 @@
+CREATE TABLE studio_transfers (
+    studio_id BLOB NOT NULL PRIMARY KEY,
+    new_owner_id BLOB NOT NULL,
+    requested_by BLOB NOT NULL,
+    requested TEXT NOT NULL,
+    expire TEXT NOT NULL,
+    CONSTRAINT fkey_studio_id FOREIGN KEY(studio_id) REFERENCES identities(user_id) ON DELETE CASCADE,
+    CONSTRAINT fkey_new_owner_id FOREIGN KEY(new_owner_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
@@ -0,0 +1,11 @@
+CREATE TABLE trusted_devices (
+    user_id BLOB NOT NULL,
+    device_id BLOB NOT NULL,
+    user_agent TEXT NULL,
+    created TEXT NOT NULL,
+    expire TEXT NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_trusted_devices_device_id ON trusted_devices(device_id);
+CREATE INDEX idx_trusted_devices_user_id ON trusted_devices(user_id);
@@ -0,0 +1,8 @@
+CREATE TABLE identity_deletions (
+    user_id BLOB NOT NULL PRIMARY KEY,
+    requested TEXT NOT NULL,
+    purge_after TEXT NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_identity_deletions_purge_after ON identity_deletions(purge_after);
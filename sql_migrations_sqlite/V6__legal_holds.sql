@@ -0,0 +1,7 @@
+CREATE TABLE legal_holds (
+    user_id BLOB NOT NULL PRIMARY KEY,
+    reason TEXT NOT NULL,
+    placed TEXT NOT NULL,
+    placed_by BLOB NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
@@ -0,0 +1,10 @@
+CREATE TABLE consents (
+    user_id BLOB NOT NULL,
+    purpose TEXT NOT NULL,
+    granted INTEGER NOT NULL,
+    version TEXT NOT NULL,
+    updated TEXT NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_consents_user_id_purpose ON consents(user_id, purpose);
@@ -0,0 +1,11 @@
+CREATE TABLE merge_requests (
+    request_id BLOB NOT NULL PRIMARY KEY,
+    user_id BLOB NOT NULL,
+    source_user_id BLOB NOT NULL,
+    provider TEXT NOT NULL,
+    created TEXT NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE,
+    CONSTRAINT fkey_source_user_id FOREIGN KEY(source_user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_merge_requests_user_id_source_user_id ON merge_requests(user_id, source_user_id);
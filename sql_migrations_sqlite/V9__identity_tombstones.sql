@@ -0,0 +1,8 @@
+CREATE TABLE identity_tombstones (
+    old_user_id BLOB NOT NULL PRIMARY KEY,
+    user_id BLOB NOT NULL,
+    merged TEXT NOT NULL,
+    CONSTRAINT fkey_user_id FOREIGN KEY(user_id) REFERENCES identities(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_identity_tombstones_user_id ON identity_tombstones(user_id);
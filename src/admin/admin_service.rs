@@ -0,0 +1,100 @@
+use crate::{
+    admin::{self, enforce_ip_allowlist, IpAllowlist, LogFilter, TlsReloader},
+    app_config::AppConfig,
+    db::IdentityStatsManager,
+    email::EmailSender,
+    logging::AccessLog,
+};
+use axum::{
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use std::sync::Arc;
+
+struct Inner {
+    app_config: AppConfig,
+    tls_reloader: Option<TlsReloader>,
+    log_filter: Option<LogFilter>,
+    email_sender: EmailSender,
+    identity_stats: IdentityStatsManager,
+    access_log: Option<AccessLog>,
+}
+
+#[derive(Clone)]
+pub(in crate::admin) struct AdminServiceState(Arc<Inner>);
+
+impl AdminServiceState {
+    pub fn app_config(&self) -> &AppConfig {
+        &self.0.app_config
+    }
+
+    pub fn tls_reloader(&self) -> Option<&TlsReloader> {
+        self.0.tls_reloader.as_ref()
+    }
+
+    pub fn log_filter(&self) -> Option<&LogFilter> {
+        self.0.log_filter.as_ref()
+    }
+
+    pub fn email_sender(&self) -> &EmailSender {
+        &self.0.email_sender
+    }
+
+    pub fn identity_stats(&self) -> &IdentityStatsManager {
+        &self.0.identity_stats
+    }
+
+    pub fn access_log(&self) -> Option<&AccessLog> {
+        self.0.access_log.as_ref()
+    }
+}
+
+pub struct AdminServiceDependencies {
+    pub app_config: AppConfig,
+    pub ip_allowlist: Arc<IpAllowlist>,
+    pub tls_reloader: Option<TlsReloader>,
+    pub log_filter: Option<LogFilter>,
+    pub email_sender: EmailSender,
+    pub identity_stats: IdentityStatsManager,
+    pub access_log: Option<AccessLog>,
+}
+
+/// Service for the administrative endpoints. All the routes are restricted by the ip allowlist.
+pub struct AdminServiceBuilder {
+    state: AdminServiceState,
+    ip_allowlist: Arc<IpAllowlist>,
+}
+
+impl AdminServiceBuilder {
+    pub fn new(dependencies: AdminServiceDependencies) -> Self {
+        let state = AdminServiceState(Arc::new(Inner {
+            app_config: dependencies.app_config,
+            tls_reloader: dependencies.tls_reloader,
+            log_filter: dependencies.log_filter,
+            email_sender: dependencies.email_sender,
+            identity_stats: dependencies.identity_stats,
+            access_log: dependencies.access_log,
+        }));
+
+        Self {
+            state,
+            ip_allowlist: dependencies.ip_allowlist,
+        }
+    }
+
+    pub fn into_router<S>(self) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        Router::new()
+            .route("/tls/reload", post(admin::ep_reload_tls))
+            .route("/email/test", post(admin::ep_send_test_email))
+            .route("/config", get(admin::ep_get_config))
+            .route("/loglevel", get(admin::ep_get_log_level).put(admin::ep_set_log_level))
+            .route("/stats", get(admin::ep_get_stats))
+            .route("/latency", get(admin::ep_get_latency))
+            .layer(middleware::from_fn_with_state(self.ip_allowlist, enforce_ip_allowlist))
+            .with_state(self.state)
+    }
+}
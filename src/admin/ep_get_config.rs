@@ -0,0 +1,55 @@
+use crate::admin::AdminServiceState;
+use axum::{extract::State, http::StatusCode, Json};
+use serde_json::Value;
+use url::Url;
+
+/// The value of the redacted secrets.
+const REDACTED: &str = "<redacted>";
+
+/// Check if a configuration key holds a secret, ex. `sessionSecret`, `clientSecret`, `sqlCns`, `signingKey`.
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_lowercase();
+    ["secret", "password", "cns", "dsn", "connectionstring"]
+        .iter()
+        .any(|pattern| key.contains(pattern))
+        || key.ends_with("key")
+        || key == "token"
+}
+
+/// Remove the credentials of the urls, ex. the password of a connection string under a not secret key.
+fn redact_url(value: &str) -> Option<String> {
+    let mut url = Url::parse(value).ok()?;
+    if url.password().is_none() {
+        return None;
+    }
+    url.set_password(Some(REDACTED)).ok()?;
+    Some(url.to_string())
+}
+
+fn redact_secrets(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                if is_secret_key(key) && !value.is_object() && !value.is_null() {
+                    *value = Value::String(REDACTED.into());
+                } else {
+                    redact_secrets(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_secrets),
+        Value::String(text) => {
+            if let Some(redacted) = redact_url(text) {
+                *text = redacted;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Get the effective configuration of the service with the secrets redacted.
+pub(in crate::admin) async fn ep_get_config(State(state): State<AdminServiceState>) -> Result<Json<Value>, StatusCode> {
+    let mut config = serde_json::to_value(state.app_config()).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    redact_secrets(&mut config);
+    Ok(Json(config))
+}
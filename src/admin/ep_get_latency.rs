@@ -0,0 +1,10 @@
+use crate::{admin::AdminServiceState, logging::LatencyReport};
+use axum::{extract::State, http::StatusCode, Json};
+
+/// Get the latency histograms of the routes recorded by the access log, it is not found if the access log is disabled.
+pub(in crate::admin) async fn ep_get_latency(
+    State(state): State<AdminServiceState>,
+) -> Result<Json<LatencyReport>, StatusCode> {
+    let access_log = state.access_log().ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(access_log.latencies()))
+}
@@ -0,0 +1,56 @@
+use crate::{
+    admin::AdminServiceState,
+    db::{DBError, IdentityStats},
+};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use thiserror::Error as ThisError;
+
+const MAX_DAYS: u32 = 365;
+
+#[derive(Debug, ThisError)]
+pub(in crate::admin) enum Error {
+    #[error("At most {MAX_DAYS} days are supported")]
+    TooManyDays,
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            Error::TooManyDays => StatusCode::BAD_REQUEST,
+            Error::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::admin) struct StatsQuery {
+    #[serde(default = "default_days")]
+    days: u32,
+}
+
+fn default_days() -> u32 {
+    30
+}
+
+/// Get the aggregated statistics of the identities with the registrations of the last days.
+pub(in crate::admin) async fn ep_get_stats(
+    State(state): State<AdminServiceState>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<IdentityStats>, Error> {
+    if query.days > MAX_DAYS {
+        return Err(Error::TooManyDays);
+    }
+    let stats = state.identity_stats().get(query.days).await?;
+    Ok(Json(stats))
+}
@@ -0,0 +1,57 @@
+use crate::admin::{AdminServiceState, LogFilterError};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub(in crate::admin) enum Error {
+    #[error("Log reconfiguration is not enabled")]
+    LogFilterDisabled,
+    #[error(transparent)]
+    LogFilterError(#[from] LogFilterError),
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            Error::LogFilterDisabled => StatusCode::NOT_FOUND,
+            Error::LogFilterError(LogFilterError::InvalidFilter(_)) => StatusCode::BAD_REQUEST,
+            Error::LogFilterError(LogFilterError::Tracing(_, _)) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::admin) struct LogLevel {
+    /// The filter of the logs in the `EnvFilter` syntax, ex. `info,shine_identity=debug`.
+    filter: String,
+}
+
+/// Get the effective filter of the logs.
+pub(in crate::admin) async fn ep_get_log_level(
+    State(state): State<AdminServiceState>,
+) -> Result<Json<LogLevel>, Error> {
+    let log_filter = state.log_filter().ok_or(Error::LogFilterDisabled)?;
+    let filter = log_filter.filter().await?;
+    Ok(Json(LogLevel { filter }))
+}
+
+/// Set the filter of the logs without restarting the service. The filter is not persisted, the configured filter is
+/// restored on restart.
+pub(in crate::admin) async fn ep_set_log_level(
+    State(state): State<AdminServiceState>,
+    Json(request): Json<LogLevel>,
+) -> Result<Json<LogLevel>, Error> {
+    let log_filter = state.log_filter().ok_or(Error::LogFilterDisabled)?;
+    let filter = log_filter.set_filter(&request.filter).await?;
+    log::warn!("Log filter changed to {filter}");
+    Ok(Json(LogLevel { filter }))
+}
@@ -0,0 +1,34 @@
+use crate::admin::{AdminServiceState, TlsReloadError};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub(in crate::admin) enum Error {
+    #[error("TLS is not enabled")]
+    TlsDisabled,
+    #[error(transparent)]
+    TlsReloadError(#[from] TlsReloadError),
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            Error::TlsDisabled => StatusCode::NOT_FOUND,
+            Error::TlsReloadError(TlsReloadError::NotReloadable) => StatusCode::CONFLICT,
+            Error::TlsReloadError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+/// Reload the TLS certificate from the configured files without restarting the service.
+pub(in crate::admin) async fn ep_reload_tls(State(state): State<AdminServiceState>) -> Result<StatusCode, Error> {
+    let tls_reloader = state.tls_reloader().ok_or(Error::TlsDisabled)?;
+    tls_reloader.reload().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
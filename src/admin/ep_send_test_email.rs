@@ -0,0 +1,46 @@
+use crate::{admin::AdminServiceState, email::EmailError};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use shine_service::service::APP_NAME;
+use tera::Context;
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub(in crate::admin) enum SendTestEmailError {
+    #[error(transparent)]
+    EmailError(#[from] EmailError),
+}
+
+impl IntoResponse for SendTestEmailError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            SendTestEmailError::EmailError(EmailError::InvalidMailbox(_)) => StatusCode::BAD_REQUEST,
+            SendTestEmailError::EmailError(EmailError::QueueFull) => StatusCode::SERVICE_UNAVAILABLE,
+            SendTestEmailError::EmailError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::admin) struct SendTestEmail {
+    to: String,
+}
+
+/// Queue a test email to check the email delivery configuration.
+pub(in crate::admin) async fn ep_send_test_email(
+    State(state): State<AdminServiceState>,
+    Json(request): Json<SendTestEmail>,
+) -> Result<StatusCode, SendTestEmailError> {
+    let mut context = Context::new();
+    context.insert("app_name", APP_NAME);
+    state.email_sender().send(&request.to, "test", &context)?;
+    Ok(StatusCode::ACCEPTED)
+}
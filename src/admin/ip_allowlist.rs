@@ -0,0 +1,185 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IpAllowlistConfig {
+    /// Networks the admin and control endpoints can be reached from.
+    pub allowed_ranges: Vec<IpNet>,
+    /// Networks of the reverse proxies whose X-Forwarded-For header is trusted.
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+/// Restrict access to some endpoints based on the address of the client.
+pub struct IpAllowlist {
+    allowed_ranges: Vec<IpNet>,
+    trusted_proxies: Vec<IpNet>,
+}
+
+impl IpAllowlist {
+    pub fn new(config: &IpAllowlistConfig) -> Self {
+        Self {
+            allowed_ranges: config.allowed_ranges.clone(),
+            trusted_proxies: config.trusted_proxies.clone(),
+        }
+    }
+
+    fn is_trusted_proxy(&self, ip: &IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|net| net.contains(ip))
+    }
+
+    fn is_allowed(&self, ip: &IpAddr) -> bool {
+        self.allowed_ranges.iter().any(|net| net.contains(ip))
+    }
+
+    /// Find the address of the client. The X-Forwarded-For header is considered only when the request
+    /// arrived from a trusted proxy and it is processed from right to left skipping the trusted hops.
+    /// If the header is malformed, None is returned.
+    fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> Option<IpAddr> {
+        self.forwarded_client_ip(peer, headers, X_FORWARDED_FOR)
+    }
+
+    /// Find the address of the client using the given forwarding header, ex. `CF-Connecting-IP`. The same rules
+    /// apply as for the X-Forwarded-For header, the header of an untrusted peer is ignored.
+    pub fn forwarded_client_ip(&self, peer: IpAddr, headers: &HeaderMap, header: &str) -> Option<IpAddr> {
+        let mut hops = Vec::new();
+        for value in headers.get_all(header) {
+            let value = value.to_str().ok()?;
+            hops.extend(value.split(',').map(str::trim));
+        }
+
+        let mut client = peer;
+        for hop in hops.into_iter().rev() {
+            if !self.is_trusted_proxy(&client) {
+                break;
+            }
+            client = hop.parse().ok()?;
+        }
+
+        Some(client)
+    }
+}
+
+/// Middleware rejecting all the requests not originating from the allowed networks.
+pub async fn enforce_ip_allowlist<B>(
+    State(allowlist): State<Arc<IpAllowlist>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    match allowlist.client_ip(peer.ip(), request.headers()) {
+        Some(client) if allowlist.is_allowed(&client) => next.run(request).await,
+        Some(client) => {
+            log::warn!(
+                "Rejected request to {} from {client} (peer: {peer})",
+                request.uri().path()
+            );
+            StatusCode::FORBIDDEN.into_response()
+        }
+        None => {
+            log::warn!(
+                "Rejected request to {} with malformed forwarding header (peer: {peer})",
+                request.uri().path()
+            );
+            StatusCode::FORBIDDEN.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{IpAllowlist, IpAllowlistConfig, X_FORWARDED_FOR};
+    use axum::http::{HeaderMap, HeaderValue};
+    use std::net::IpAddr;
+
+    fn allowlist() -> IpAllowlist {
+        IpAllowlist::new(&IpAllowlistConfig {
+            allowed_ranges: vec!["127.0.0.0/8".parse().unwrap()],
+            trusted_proxies: vec!["10.0.0.0/8".parse().unwrap()],
+        })
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    fn headers(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(X_FORWARDED_FOR, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn forwarded_hops_are_parsed_from_right_to_left() {
+        let allowlist = allowlist();
+        let headers = headers(&["203.0.113.7, 198.51.100.1, 10.0.0.2"]);
+        assert_eq!(allowlist.client_ip(ip("10.0.0.1"), &headers), Some(ip("198.51.100.1")));
+
+        let headers = headers(&["203.0.113.7, 10.0.0.3, 10.0.0.2"]);
+        assert_eq!(allowlist.client_ip(ip("10.0.0.1"), &headers), Some(ip("203.0.113.7")));
+    }
+
+    #[test]
+    fn custom_header_is_trusted_only_from_proxies() {
+        let allowlist = allowlist();
+        let mut headers = HeaderMap::new();
+        headers.insert("cf-connecting-ip", HeaderValue::from_static("203.0.113.7"));
+        assert_eq!(
+            allowlist.forwarded_client_ip(ip("10.0.0.1"), &headers, "cf-connecting-ip"),
+            Some(ip("203.0.113.7"))
+        );
+        assert_eq!(
+            allowlist.forwarded_client_ip(ip("198.51.100.1"), &headers, "cf-connecting-ip"),
+            Some(ip("198.51.100.1"))
+        );
+    }
+
+    #[test]
+    fn untrusted_peer_is_the_client() {
+        let allowlist = allowlist();
+        let headers = headers(&["127.0.0.1"]);
+        assert_eq!(
+            allowlist.client_ip(ip("198.51.100.1"), &headers),
+            Some(ip("198.51.100.1"))
+        );
+        assert_eq!(
+            allowlist.client_ip(ip("10.0.0.1"), &HeaderMap::new()),
+            Some(ip("10.0.0.1"))
+        );
+    }
+
+    #[test]
+    fn malformed_hop_is_rejected() {
+        let allowlist = allowlist();
+        let headers = headers(&["203.0.113.7, not-an-ip"]);
+        assert_eq!(allowlist.client_ip(ip("10.0.0.1"), &headers), None);
+
+        // the hops beyond the first untrusted one are not parsed
+        let headers = headers(&["not-an-ip, 198.51.100.1"]);
+        assert_eq!(allowlist.client_ip(ip("10.0.0.1"), &headers), Some(ip("198.51.100.1")));
+    }
+
+    #[test]
+    fn multiple_header_values_are_joined() {
+        let allowlist = allowlist();
+        let headers = headers(&["203.0.113.7", "10.0.0.3, 10.0.0.2"]);
+        assert_eq!(allowlist.client_ip(ip("10.0.0.1"), &headers), Some(ip("203.0.113.7")));
+
+        let headers = headers(&["198.51.100.1", "203.0.113.7"]);
+        assert_eq!(allowlist.client_ip(ip("10.0.0.1"), &headers), Some(ip("203.0.113.7")));
+    }
+}
@@ -0,0 +1,77 @@
+use axum::{
+    body::{Body, Bytes},
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error as ThisError;
+use tower::ServiceExt;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, ThisError)]
+pub enum LogFilterError {
+    #[error("Invalid log filter: {0}")]
+    InvalidFilter(String),
+    #[error("Tracing service responded with {0}: {1}")]
+    Tracing(StatusCode, String),
+}
+
+#[derive(Deserialize)]
+struct TracingConfig {
+    filter: String,
+}
+
+/// The runtime filter of the logs and the traces. The subscriber and its reloadable `EnvFilter` are owned by the
+/// tracing service, the filter is read and replaced by the configuration routes of the service, thus both the `log`
+/// and the `tracing` events are affected.
+#[derive(Clone)]
+pub struct LogFilter(Router);
+
+impl LogFilter {
+    pub fn new(tracing_router: Router) -> Self {
+        Self(tracing_router)
+    }
+
+    /// Get the effective filter, ex. `info,shine_identity=debug`.
+    pub async fn filter(&self) -> Result<String, LogFilterError> {
+        let body = self.call(Method::GET, Body::empty()).await?;
+        let config: TracingConfig = serde_json::from_slice(&body)
+            .map_err(|err| LogFilterError::Tracing(StatusCode::OK, format!("Invalid config: {err}")))?;
+        Ok(config.filter)
+    }
+
+    /// Replace the filter and return the effective filter. The filter is not persisted, the configured filter is
+    /// restored on restart.
+    pub async fn set_filter(&self, filter: &str) -> Result<String, LogFilterError> {
+        filter
+            .parse::<EnvFilter>()
+            .map_err(|err| LogFilterError::InvalidFilter(format!("{err}")))?;
+
+        let body = json!({ "filter": filter }).to_string();
+        self.call(Method::PUT, Body::from(body)).await?;
+        self.filter().await
+    }
+
+    async fn call(&self, method: Method, body: Body) -> Result<Bytes, LogFilterError> {
+        let request = Request::builder()
+            .method(method)
+            .uri("/config")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .map_err(|err| LogFilterError::Tracing(StatusCode::INTERNAL_SERVER_ERROR, format!("{err}")))?;
+        let response = self.0.clone().oneshot(request).await.unwrap_or_else(|err| match err {});
+
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|err| LogFilterError::Tracing(status, format!("{err}")))?;
+        if !status.is_success() {
+            return Err(LogFilterError::Tracing(
+                status,
+                String::from_utf8_lossy(&body).into_owned(),
+            ));
+        }
+        Ok(body)
+    }
+}
@@ -0,0 +1,24 @@
+mod admin_service;
+pub use self::admin_service::*;
+mod ip_allowlist;
+pub use self::ip_allowlist::*;
+mod log_filter;
+pub use self::log_filter::*;
+mod tls_reloader;
+pub use self::tls_reloader::*;
+
+mod ep_get_config;
+pub(in crate::admin) use self::ep_get_config::*;
+mod ep_get_latency;
+pub(in crate::admin) use self::ep_get_latency::*;
+mod ep_get_stats;
+pub(in crate::admin) use self::ep_get_stats::*;
+mod ep_log_level;
+pub(in crate::admin) use self::ep_log_level::*;
+mod ep_reload_tls;
+pub(in crate::admin) use self::ep_reload_tls::*;
+mod ep_send_test_email;
+pub(in crate::admin) use self::ep_send_test_email::*;
+
+#[cfg(test)]
+mod test_admin_flows;
@@ -0,0 +1,43 @@
+use crate::test_support::{TestApp, TestClient};
+use axum::http::StatusCode;
+use serde_json::json;
+
+#[tokio::test]
+async fn access_log_records_latencies() {
+    let app = TestApp::with_config(|config| {
+        config["accessLog"] = json!({ "sampleRate": 0.0 });
+    })
+    .await;
+    let mut client = TestClient::new(&app.router);
+
+    client.get("/api/auth/providers").await;
+    client.get("/api/auth/providers").await;
+
+    let report = client.get("/api/admin/latency").await.json();
+    let histogram = &report["routes"]["GET /identity/api/auth/providers"];
+    assert_eq!(histogram["count"], json!(2));
+    assert_eq!(histogram["buckets"].as_array().unwrap().len(), 11);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn runtime_config_is_redacted() {
+    let app = TestApp::new().await;
+    let mut client = TestClient::new(&app.router);
+
+    let config = client.get("/api/admin/config").await.json();
+    assert_eq!(config["db"]["sqlCns"], json!("<redacted>"));
+    assert_eq!(config["auth"]["sessionSecret"], json!("<redacted>"));
+    assert_eq!(config["adminAllowlist"]["allowedRanges"], json!(["127.0.0.0/8"]));
+
+    log::info!("Without the tracing reconfiguration the log filter is not available...");
+    let response = client.get("/api/admin/loglevel").await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    let response = client
+        .put_json("/api/admin/loglevel", &json!({ "filter": "info,shine_identity=debug" }))
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    app.cleanup().await;
+}
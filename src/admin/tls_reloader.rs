@@ -0,0 +1,104 @@
+use crate::app_config::TlsConfig;
+use axum_server::tls_rustls::RustlsConfig;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub enum TlsReloadError {
+    #[error("Failed to load certificate")]
+    Load(#[from] io::Error),
+    #[error("Certificate is not loaded from file, reload is not supported")]
+    NotReloadable,
+}
+
+struct TlsFiles {
+    cert_file: PathBuf,
+    key_file: PathBuf,
+    reload_interval: Option<Duration>,
+}
+
+impl TlsFiles {
+    /// Get the last modification time of the certificate files.
+    fn modified(&self) -> Option<SystemTime> {
+        let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        Option::max(modified(&self.cert_file), modified(&self.key_file))
+    }
+}
+
+struct Inner {
+    rustls: RustlsConfig,
+    files: Option<TlsFiles>,
+}
+
+/// Keep track of the TLS configuration of the server allowing to swap the certificate without a restart.
+#[derive(Clone)]
+pub struct TlsReloader(Arc<Inner>);
+
+impl TlsReloader {
+    pub async fn new(config: &TlsConfig) -> Result<Self, TlsReloadError> {
+        let inner = match config {
+            TlsConfig::Pem { cert, key } => Inner {
+                rustls: RustlsConfig::from_pem(cert.as_bytes().to_vec(), key.as_bytes().to_vec()).await?,
+                files: None,
+            },
+            TlsConfig::File {
+                cert_file,
+                key_file,
+                reload_interval,
+            } => Inner {
+                rustls: RustlsConfig::from_pem_file(cert_file, key_file).await?,
+                files: Some(TlsFiles {
+                    cert_file: cert_file.clone(),
+                    key_file: key_file.clone(),
+                    reload_interval: reload_interval.map(Duration::from_secs),
+                }),
+            },
+        };
+
+        Ok(Self(Arc::new(inner)))
+    }
+
+    pub fn rustls_config(&self) -> RustlsConfig {
+        self.0.rustls.clone()
+    }
+
+    /// Reload the certificate from the files.
+    pub async fn reload(&self) -> Result<(), TlsReloadError> {
+        let files = self.0.files.as_ref().ok_or(TlsReloadError::NotReloadable)?;
+        self.0
+            .rustls
+            .reload_from_pem_file(&files.cert_file, &files.key_file)
+            .await?;
+        log::info!("TLS certificate reloaded from {:?}", files.cert_file);
+        Ok(())
+    }
+
+    /// Start a background task polling the certificate files and reloading them when modified.
+    pub fn spawn_watcher(&self) {
+        let Some(reload_interval) = self.0.files.as_ref().and_then(|files| files.reload_interval) else {
+            return;
+        };
+
+        let reloader = self.clone();
+        tokio::spawn(async move {
+            let files = reloader.0.files.as_ref().unwrap();
+            let mut last_modified = files.modified();
+            let mut interval = tokio::time::interval(reload_interval);
+            loop {
+                interval.tick().await;
+                let modified = files.modified();
+                if modified != last_modified {
+                    match reloader.reload().await {
+                        Ok(()) => last_modified = modified,
+                        Err(err) => log::warn!("Failed to reload TLS certificate: {err:?}"),
+                    }
+                }
+            }
+        });
+    }
+}
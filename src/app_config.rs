@@ -1,9 +1,18 @@
-use crate::db::NameGeneratorConfig;
+use crate::admin::IpAllowlistConfig;
+use crate::db::{
+    DeletionConfig, DevSeedConfig, EmailNormalizationConfig, FeatureFlagsConfig, NameGeneratorConfig,
+    TokenRevocationConfig,
+};
+use crate::email::EmailConfig;
+use crate::error_reporting::ErrorReporterConfig;
+use crate::logging::AccessLogConfig;
+use crate::secrets::SecretResolver;
 use crate::{auth, db::DBConfig};
-use config::ConfigError;
+use config::{ConfigError, Value};
 use serde::{Deserialize, Serialize};
 use shine_service::axum::tracing::TracingConfig;
 use shine_service::service::CoreConfig;
+use std::path::PathBuf;
 use thiserror::Error as ThisError;
 
 pub const SERVICE_NAME: &str = "identity";
@@ -18,12 +27,20 @@ impl From<PreInitConfigError> for ConfigError {
     }
 }
 
-/// The application configuration
+/// The TLS configuration, certificate and key are given either as PEM content or as file paths
 #[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct TlsConfig {
-    pub cert: String,
-    pub key: String,
+#[serde(untagged)]
+pub enum TlsConfig {
+    #[serde(rename_all = "camelCase")]
+    Pem { cert: String, key: String },
+
+    /// When reload interval (in seconds) is given, files are polled and reloaded on change
+    #[serde(rename_all = "camelCase")]
+    File {
+        cert_file: PathBuf,
+        key_file: PathBuf,
+        reload_interval: Option<u64>,
+    },
 }
 
 /// The application configuration
@@ -34,12 +51,32 @@ pub struct AppConfig {
     pub core: CoreConfig,
 
     pub tracing: TracingConfig,
+    /// The access log of the requests, it is disabled by default.
+    pub access_log: Option<AccessLogConfig>,
+    /// The reporting of the unexpected errors, they are only logged by default.
+    pub error_reporting: Option<ErrorReporterConfig>,
     pub db: DBConfig,
     pub auth: auth::AuthConfig,
     pub user_name: NameGeneratorConfig,
+    #[serde(default)]
+    pub email_normalization: EmailNormalizationConfig,
+    #[serde(default)]
+    pub token_revocation: TokenRevocationConfig,
+    /// The grace period of the account deletion.
+    #[serde(default)]
+    pub deletion: DeletionConfig,
+    /// The defaults of the feature flags, they can be overridden in redis.
+    #[serde(default)]
+    pub feature_flags: FeatureFlagsConfig,
+    pub email: EmailConfig,
+    /// Development data created at startup, see `DevSeedConfig`.
+    pub dev_seed: Option<DevSeedConfig>,
+    #[cfg(feature = "mock-provider")]
+    pub mock_provider: Option<crate::mock_provider::MockProviderConfig>,
 
     pub control_port: u16,
     pub allow_origins: Vec<String>,
+    pub admin_allowlist: IpAllowlistConfig,
     pub tls: Option<TlsConfig>,
 }
 
@@ -50,7 +87,14 @@ impl AppConfig {
         let config = builder.build().await?;
         log::debug!("configuration values: {:#?}", config);
 
-        let cfg: AppConfig = config.try_deserialize()?;
+        // replace the secret references before deserialization
+        let mut raw: Value = config.try_deserialize()?;
+        SecretResolver::default()
+            .resolve(&mut raw)
+            .await
+            .map_err(|err| ConfigError::Foreign(Box::new(err)))?;
+
+        let cfg: AppConfig = raw.try_deserialize()?;
         if pre_init != cfg.core {
             return Err(PreInitConfigError.into());
         }
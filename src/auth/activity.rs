@@ -0,0 +1,16 @@
+use crate::db::ActivityTracker;
+use axum::{extract::State, http::Request, middleware::Next, response::Response};
+use shine_service::service::CurrentUser;
+
+/// Middleware recording the activity of the authenticated users for the active user statistics.
+pub async fn track_activity<B>(
+    State(tracker): State<ActivityTracker>,
+    user: Option<CurrentUser>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if let Some(user) = &user {
+        tracker.record(user.user_id);
+    }
+    next.run(request).await
+}
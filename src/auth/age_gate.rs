@@ -0,0 +1,85 @@
+use crate::{
+    auth::ParentalConsentConfig,
+    db::{AgeBracket, AgeInfo},
+};
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+/// The earliest year of birth accepted at the registration.
+const MIN_BIRTH_YEAR: i32 = 1900;
+
+/// What is collected from the user at the registration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AgeCollection {
+    /// The date of birth, the accounts become adult automatically.
+    BirthDate,
+    /// Only an age bracket, the accounts stay minor until the adult age is verified.
+    AgeBracket,
+}
+
+fn default_adult_age() -> u32 {
+    18
+}
+
+/// The age gate of the registration.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgeGateConfig {
+    pub collect: AgeCollection,
+    /// The age from which the users are considered adults.
+    #[serde(default = "default_adult_age")]
+    pub adult_age: u32,
+    /// Reject the registrations without an age, when false the age can be declared later.
+    #[serde(default)]
+    pub required: bool,
+    /// Consent of the parents to the accounts of the minors, when not given no consent is required.
+    pub parental_consent: Option<ParentalConsentConfig>,
+}
+
+impl AgeGateConfig {
+    /// Check if the age given at the registration matches the collected kind of age.
+    pub fn is_valid_declaration(
+        &self,
+        birth_date: Option<NaiveDate>,
+        age_bracket: Option<AgeBracket>,
+        today: NaiveDate,
+    ) -> bool {
+        match self.collect {
+            AgeCollection::BirthDate => {
+                age_bracket.is_none() && birth_date.map_or(false, |date| date.year() >= MIN_BIRTH_YEAR && date <= today)
+            }
+            AgeCollection::AgeBracket => birth_date.is_none() && age_bracket.is_some(),
+        }
+    }
+
+    /// Check if a user is a minor, None is returned if the age is not known.
+    pub fn is_minor(&self, info: &AgeInfo, today: NaiveDate) -> Option<bool> {
+        if info.verified_adult_at.is_some() {
+            return Some(false);
+        }
+        self.is_declared_minor(info.birth_date, info.age_bracket, today)
+    }
+
+    /// Check if a declared age is of a minor, None is returned if no age was declared.
+    pub fn is_declared_minor(
+        &self,
+        birth_date: Option<NaiveDate>,
+        age_bracket: Option<AgeBracket>,
+        today: NaiveDate,
+    ) -> Option<bool> {
+        if let Some(birth_date) = birth_date {
+            return Some(years_between(birth_date, today) < self.adult_age);
+        }
+        age_bracket.map(|bracket| bracket != AgeBracket::Adult)
+    }
+}
+
+/// The number of the full years from the date of birth.
+fn years_between(birth_date: NaiveDate, today: NaiveDate) -> u32 {
+    let mut years = today.year() - birth_date.year();
+    if (today.month(), today.day()) < (birth_date.month(), birth_date.day()) {
+        years -= 1;
+    }
+    years.max(0) as u32
+}
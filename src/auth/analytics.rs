@@ -0,0 +1,70 @@
+use crate::{
+    auth::{AuthError, AuthServiceState, AuthSession},
+    db::AnalyticsEvent,
+    logging::user_hash,
+};
+use uuid::Uuid;
+
+/// An interactive login flow with an external provider tracked by the analytics events.
+#[derive(Clone, Debug)]
+pub(in crate::auth) struct AuthFlow {
+    pub id: Uuid,
+    pub provider: String,
+}
+
+impl AuthServiceState {
+    /// Start a new flow with an external provider.
+    pub(in crate::auth) fn start_auth_flow(&self, auth_session: &mut AuthSession, provider: &str) -> Uuid {
+        let flow = AuthFlow {
+            id: Uuid::new_v4(),
+            provider: provider.to_owned(),
+        };
+        let flow_id = flow.id;
+        auth_session.flow = Some(flow);
+        self.emit_flow_event(auth_session, AnalyticsEvent::LoginStarted, None);
+        flow_id
+    }
+
+    /// Continue a flow started by `start_auth_flow` on the callback of the provider.
+    pub(in crate::auth) fn resume_auth_flow(&self, auth_session: &mut AuthSession, flow_id: Uuid, provider: &str) {
+        auth_session.flow = Some(AuthFlow {
+            id: flow_id,
+            provider: provider.to_owned(),
+        });
+        self.emit_flow_event(auth_session, AnalyticsEvent::CallbackReceived, None);
+    }
+
+    /// Emit an event of the flow of the session, it is ignored if there is no flow in progress. The steps are also
+    /// logged with the hash of the user, the logs of a flow are correlated by the `flow_id` field.
+    pub(in crate::auth) fn emit_flow_event(
+        &self,
+        auth_session: &AuthSession,
+        event: AnalyticsEvent,
+        user_id: Option<Uuid>,
+    ) {
+        if let Some(flow) = &auth_session.flow {
+            let user = user_id.map(user_hash);
+            tracing::info!(
+                flow_id = %flow.id,
+                provider = %flow.provider,
+                user = user.as_deref(),
+                outcome = event.as_str(),
+                "Login flow step"
+            );
+            self.analytics().emit(flow.id, &flow.provider, event, user_id, None);
+        }
+    }
+
+    pub(in crate::auth) fn emit_flow_error(&self, auth_session: &AuthSession, error: &AuthError) {
+        if let Some(flow) = &auth_session.flow {
+            tracing::info!(
+                flow_id = %flow.id,
+                provider = %flow.provider,
+                outcome = error.kind(),
+                "Login flow failed"
+            );
+            self.analytics()
+                .emit(flow.id, &flow.provider, AnalyticsEvent::Error, None, Some(error.kind()));
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use crate::auth::{AuthServiceState, BearerToken};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use shine_service::service::CurrentUser;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const QUOTA_LIMIT_HEADER: &str = "x-ratelimit-limit";
+const QUOTA_REMAINING_HEADER: &str = "x-ratelimit-remaining";
+/// Seconds until the quota is reset.
+const QUOTA_RESET_HEADER: &str = "x-ratelimit-reset";
+
+/// The daily quota of the API requests. The days are counted in UTC.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiQuotaConfig {
+    /// Requests per day of the identities without an own quota.
+    pub daily_requests: u64,
+    /// Requests per day of the individual identities (ex. the API keys of the services) by their user id.
+    #[serde(default)]
+    pub identities: HashMap<Uuid, u64>,
+}
+
+impl ApiQuotaConfig {
+    pub fn daily_requests(&self, user_id: Uuid) -> u64 {
+        self.identities.get(&user_id).copied().unwrap_or(self.daily_requests)
+    }
+}
+
+/// Get the seconds until the quotas are reset, at the next midnight (UTC).
+fn seconds_until_reset(now: DateTime<Utc>) -> i64 {
+    let tomorrow = now.date_naive() + Duration::days(1);
+    let reset_at = tomorrow.and_hms_opt(0, 0, 0).expect("Midnight shall be a valid time");
+    (reset_at - now.naive_utc()).num_seconds()
+}
+
+fn set_quota_headers(headers: &mut HeaderMap, limit: u64, remaining: u64, reset: i64) {
+    headers.insert(QUOTA_LIMIT_HEADER, HeaderValue::from(limit));
+    headers.insert(QUOTA_REMAINING_HEADER, HeaderValue::from(remaining));
+    headers.insert(QUOTA_RESET_HEADER, HeaderValue::from(reset));
+}
+
+/// Middleware counting the API requests of the identities and rejecting them with `429 Too Many Requests` above
+/// the daily quota. The identity is the user of the session or of the bearer token, for a delegated token it is the
+/// acting service. The anonymous requests are not counted.
+/// When the counters are not available, the requests are let through, thus an outage of redis does not block the
+/// API.
+pub(in crate::auth) async fn enforce_api_quota<B>(
+    State(state): State<AuthServiceState>,
+    user: Option<CurrentUser>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let config = match state.api_quota_config() {
+        Some(config) => config,
+        None => return next.run(request).await,
+    };
+    let user_id = match request.extensions().get::<BearerToken>() {
+        Some(BearerToken::Session(user)) => user.user_id,
+        Some(BearerToken::Service(claims)) => claims.act.as_ref().map_or(claims.sub, |actor| actor.sub),
+        Some(BearerToken::Studio(claims)) => claims.sub,
+        None => match &user {
+            Some(user) => user.user_id,
+            None => return next.run(request).await,
+        },
+    };
+
+    let count = match state.api_quota_manager().consume(user_id).await {
+        Ok(count) => count,
+        Err(err) => {
+            log::warn!("Failed to count the API request of {user_id}: {err:?}");
+            return next.run(request).await;
+        }
+    };
+
+    let limit = config.daily_requests(user_id);
+    let reset = seconds_until_reset(state.clock().now());
+    if count > limit {
+        log::info!("API quota of {user_id} is exceeded");
+        let mut response = (StatusCode::TOO_MANY_REQUESTS, "API quota exceeded").into_response();
+        set_quota_headers(response.headers_mut(), limit, 0, reset);
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(reset));
+        return response;
+    }
+
+    let mut response = next.run(request).await;
+    set_quota_headers(response.headers_mut(), limit, limit - count, reset);
+    response
+}
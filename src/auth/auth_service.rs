@@ -1,18 +1,42 @@
 use crate::{
-    auth::{self, AuthSessionMeta, OAuth2Client, OIDCClient, TokenGenerator},
-    db::{IdentityManager, NameGenerator, SessionManager},
+    admin::{enforce_ip_allowlist, IpAllowlist},
+    auth::{
+        self, AgeGateConfig, ApiQuotaConfig, AuthSessionMeta, BotCheckConfig, CaptchaVerifier, ConnectionTicketConfig,
+        ConnectionTicketSigner, IpReputation, IpReputationConfig, JoinTokenConfig, LoginFrictionConfig,
+        LoginRiskConfig, NativeLogin, NativeLoginConfig, OAuth2Client, OIDCClient, PasswordPolicy,
+        PasswordPolicyConfig, ProviderClients, PwnedPasswords, PwnedPasswordsConfig, RegionConfig, ServiceTokenConfig,
+        ServiceTokenSigner, SsoConfig, SsoLogin, Tenant, TenantInfo, TenantResolver, TokenGenerator,
+        UnavailableProviders, UserContextConfig, UserContextSigner, DEBUG_PROVIDER, DEFAULT_PROVIDER_PROFILE,
+    },
+    db::{
+        AbuseFlagManager, AgeManager, AnalyticsEvents, ApiQuotaManager, AuditLog, BreakGlassStore, Clock,
+        ConsentManager, DeletionManager, DeviceManager, ExpirationManager, FeatureFlags, IdentityStore,
+        LoginLinkManager, MergeManager, MfaManager, MfaMethod, NameGenerator, NativeLoginManager, OpaqueTokenStore,
+        ParentalConsentManager, PasswordManager, PermissionManager, RateLimiter, RegionManager, RestrictionManager,
+        RoleManager, ServiceClientManager, SessionLimitConfig, SessionStore, SharedClock, SharedIdentityStore,
+        SharedSessionStore, SsoHandoffManager, StudioManager, SupportNoteManager, TagManager, TicketRedemption,
+        TokenRevocation, UserInvalidation, DEFAULT_TENANT_ID,
+    },
+    email::{EmailNotificationConfig, EmailSender},
+    error_reporting::ErrorReporting,
+};
+use axum::{
+    middleware,
+    routing::{delete, get, post, put},
+    Router,
 };
-use axum::{routing::get, Extension, Router};
 use chrono::Duration;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
+    iter,
     num::TryFromIntError,
     sync::Arc,
 };
 use tera::Tera;
 use thiserror::Error as ThisError;
 use url::Url;
+use uuid::Uuid;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -31,7 +55,11 @@ pub struct OAuth2Config {
     pub client_id: String,
     pub client_secret: String,
     pub scopes: Vec<String>,
-    pub redirect_url: String,
+    /// Override of the redirect url of the provider, when not given it is derived from the api url.
+    pub redirect_url: Option<String>,
+    /// Additional parameters of the authorization request, ex. `prompt=select_account`.
+    #[serde(default)]
+    pub auth_params: HashMap<String, String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -41,7 +69,37 @@ pub struct OIDCConfig {
     pub client_id: String,
     pub client_secret: String,
     pub scopes: Vec<String>,
-    pub redirect_url: String,
+    /// Override of the redirect url of the provider, when not given it is derived from the api url.
+    pub redirect_url: Option<String>,
+    /// Additional parameters of the authorization request, ex. `hd` for Google or `domain_hint` for Azure. The
+    /// `prompt` parameter replaces the default `prompt=login`.
+    #[serde(default)]
+    pub auth_params: HashMap<String, String>,
+    /// Time (in seconds) after the discovery document is refreshed.
+    #[serde(default = "OIDCConfig::default_discovery_ttl")]
+    pub discovery_ttl: u64,
+    /// Tolerated difference (in seconds) between the clocks of the provider and the service when the expiration,
+    /// issue and authentication times of the id tokens are checked.
+    #[serde(default = "OIDCConfig::default_clock_skew")]
+    pub clock_skew: u64,
+    /// Maximum age (in seconds) of the authentication at the provider. It is sent as `max_age` and the id tokens
+    /// with an older `auth_time` are rejected.
+    #[serde(default = "OIDCConfig::default_max_auth_age")]
+    pub max_auth_age: u64,
+}
+
+impl OIDCConfig {
+    fn default_discovery_ttl() -> u64 {
+        24 * 3600
+    }
+
+    fn default_clock_skew() -> u64 {
+        60
+    }
+
+    fn default_max_auth_age() -> u64 {
+        30 * 60
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -52,11 +110,310 @@ pub struct AuthSessionConfig {
     pub session_secret: String,
     pub external_login_secret: String,
     pub token_login_secret: String,
+    pub mfa_pending_secret: String,
+    /// Key of the trusted device cookie, when not given the browsers cannot be trusted to skip the second factor.
+    pub trusted_device_secret: Option<String>,
+    /// Key of the hash of the login tokens stored in the database.
+    pub token_hash_secret: String,
 
     pub session_max_duration: usize,
+    /// Limit of the simultaneous active sessions of a user, when not given the sessions are not limited.
+    pub session_limit: Option<SessionLimitConfig>,
     pub token_max_duration: usize,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TenantConfig {
+    pub home_url: Url,
+    pub api_url: Url,
+    /// Hosts resolved to this tenant. Independent of the host, tenant can also be selected by the `/t/{tenant}` path prefix.
+    pub hosts: Vec<String>,
+    pub cookie_name_suffix: Option<String>,
+    /// The provider profile used by the tenant, if not given, the default profile is used.
+    pub provider_profile: Option<String>,
+    /// The enabled providers of the profile, if not given, all the providers are enabled.
+    pub providers: Option<HashSet<String>>,
+}
+
+/// A named set of providers, ex. to use different client and redirect urls for the staging and production realms.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderProfileConfig {
+    #[serde(default)]
+    pub openid: HashMap<String, OIDCConfig>,
+    #[serde(default)]
+    pub oauth2: HashMap<String, OAuth2Config>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordConfig {
+    /// Enable the email and password login.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Page of the client to set the new password. The reset token is appended as the `token` query parameter.
+    /// When not given, password reset is disabled.
+    pub reset_page_url: Option<Url>,
+    /// Validity of the password reset tokens in seconds.
+    #[serde(default = "PasswordConfig::default_reset_token_duration")]
+    pub reset_token_duration: u64,
+    /// Maximum number of password reset requests for an email in the rate limit window.
+    #[serde(default = "PasswordConfig::default_reset_rate_limit")]
+    pub reset_rate_limit: u32,
+    /// Maximum number of login attempts for an email in the rate limit window.
+    #[serde(default = "PasswordConfig::default_login_rate_limit")]
+    pub login_rate_limit: u32,
+    /// The rate limit window in seconds.
+    #[serde(default = "PasswordConfig::default_rate_window")]
+    pub rate_window: u64,
+    /// The rules of the new passwords.
+    #[serde(default)]
+    pub policy: PasswordPolicyConfig,
+    /// Reject the passwords known from data breaches.
+    #[serde(default)]
+    pub pwned_passwords: PwnedPasswordsConfig,
+}
+
+impl PasswordConfig {
+    fn default_reset_token_duration() -> u64 {
+        3600
+    }
+
+    fn default_reset_rate_limit() -> u32 {
+        3
+    }
+
+    fn default_login_rate_limit() -> u32 {
+        10
+    }
+
+    fn default_rate_window() -> u64 {
+        3600
+    }
+
+    pub fn reset_token_duration(&self) -> Duration {
+        Duration::seconds(self.reset_token_duration as i64)
+    }
+
+    pub fn rate_window(&self) -> Duration {
+        Duration::seconds(self.rate_window as i64)
+    }
+}
+
+impl Default for PasswordConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            reset_page_url: None,
+            reset_token_duration: Self::default_reset_token_duration(),
+            reset_rate_limit: Self::default_reset_rate_limit(),
+            login_rate_limit: Self::default_login_rate_limit(),
+            rate_window: Self::default_rate_window(),
+            policy: PasswordPolicyConfig::default(),
+            pwned_passwords: PwnedPasswordsConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailMfaConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Validity of the one-time-codes in seconds.
+    #[serde(default = "EmailMfaConfig::default_code_duration")]
+    pub code_duration: u64,
+    /// Number of failed attempts after which the code is dropped.
+    #[serde(default = "EmailMfaConfig::default_max_attempts")]
+    pub max_attempts: u32,
+    /// Maximum number of codes sent for a pending login.
+    #[serde(default = "EmailMfaConfig::default_max_codes")]
+    pub max_codes: u32,
+    /// Override the default template of the code email.
+    pub template: Option<String>,
+}
+
+impl EmailMfaConfig {
+    fn default_code_duration() -> u64 {
+        300
+    }
+
+    fn default_max_attempts() -> u32 {
+        5
+    }
+
+    fn default_max_codes() -> u32 {
+        3
+    }
+
+    pub fn code_duration(&self) -> Duration {
+        Duration::seconds(self.code_duration as i64)
+    }
+}
+
+impl Default for EmailMfaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            code_duration: Self::default_code_duration(),
+            max_attempts: Self::default_max_attempts(),
+            max_codes: Self::default_max_codes(),
+            template: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MfaConfig {
+    /// Page of the client to enter the second factor. The login pages redirect here when a second factor
+    /// is required, the original target is appended as the `redirectUrl` query parameter.
+    pub page_url: Url,
+    /// The time in seconds to complete the second factor after the first one.
+    #[serde(default = "MfaConfig::default_pending_duration")]
+    pub pending_duration: u64,
+    #[serde(default)]
+    pub email: EmailMfaConfig,
+    /// The time in seconds a browser can skip the second factor after it has been trusted. It also requires the
+    /// `trustedDeviceSecret` of the session.
+    #[serde(default = "MfaConfig::default_trusted_device_duration")]
+    pub trusted_device_duration: u64,
+}
+
+impl MfaConfig {
+    fn default_pending_duration() -> u64 {
+        600
+    }
+
+    fn default_trusted_device_duration() -> u64 {
+        30 * 24 * 60 * 60
+    }
+
+    pub fn pending_duration(&self) -> Duration {
+        Duration::seconds(self.pending_duration as i64)
+    }
+
+    pub fn trusted_device_duration(&self) -> Duration {
+        Duration::seconds(self.trusted_device_duration as i64)
+    }
+
+    pub fn is_enabled(&self, method: MfaMethod) -> bool {
+        match method {
+            MfaMethod::Email => self.email.enabled,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupportLoginConfig {
+    /// Role required to create login links for the users.
+    #[serde(default = "SupportLoginConfig::default_role")]
+    pub role: String,
+    /// Validity of the login links in seconds.
+    #[serde(default = "SupportLoginConfig::default_link_duration")]
+    pub link_duration: u64,
+}
+
+impl SupportLoginConfig {
+    fn default_role() -> String {
+        "support".into()
+    }
+
+    fn default_link_duration() -> u64 {
+        900
+    }
+
+    pub fn link_duration(&self) -> Duration {
+        Duration::seconds(self.link_duration as i64)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapProviderId {
+    pub provider: String,
+    pub provider_id: String,
+}
+
+/// Users granted a role on their first login, thus a fresh deployment can be administered.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapRolesConfig {
+    #[serde(default = "BootstrapRolesConfig::default_role")]
+    pub role: String,
+    /// Emails as provided by the external login provider.
+    #[serde(default)]
+    pub emails: Vec<String>,
+    #[serde(default)]
+    pub provider_ids: Vec<BootstrapProviderId>,
+}
+
+impl BootstrapRolesConfig {
+    fn default_role() -> String {
+        "admin".into()
+    }
+}
+
+/// Emergency access for the case when the external providers or the email are not available.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BreakGlassConfig {
+    /// Argon2 hash of the credential in the PHC string format. It can be used only once and it has to be rotated
+    /// after the use.
+    pub credential_hash: String,
+    /// The user logged in by the credential.
+    pub user_id: Uuid,
+    /// Role added to the session, it is not granted to the user.
+    #[serde(default = "BreakGlassConfig::default_role")]
+    pub role: String,
+    /// Duration of the session in seconds.
+    #[serde(default = "BreakGlassConfig::default_session_duration")]
+    pub session_duration: u64,
+}
+
+impl BreakGlassConfig {
+    fn default_role() -> String {
+        "admin".into()
+    }
+
+    fn default_session_duration() -> u64 {
+        900
+    }
+
+    pub fn session_duration(&self) -> Duration {
+        Duration::seconds(self.session_duration as i64)
+    }
+}
+
+/// The optional data processing purposes the users can consent to.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsentConfig {
+    /// The current version of the purposes by their names. When the version of a purpose changes, the previous
+    /// consents of the purpose are not valid any more.
+    #[serde(default = "ConsentConfig::default_purposes")]
+    pub purposes: HashMap<String, String>,
+}
+
+impl ConsentConfig {
+    fn default_purposes() -> HashMap<String, String> {
+        HashMap::from([
+            ("analytics".to_owned(), "1".to_owned()),
+            ("marketingEmail".to_owned(), "1".to_owned()),
+        ])
+    }
+}
+
+impl Default for ConsentConfig {
+    fn default() -> Self {
+        Self {
+            purposes: Self::default_purposes(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AuthConfig {
@@ -66,8 +423,78 @@ pub struct AuthConfig {
     #[serde(flatten)]
     pub auth_session: AuthSessionConfig,
 
+    /// Providers of the default profile
     pub openid: HashMap<String, OIDCConfig>,
     pub oauth2: HashMap<String, OAuth2Config>,
+    /// Additional provider profiles beside the default one.
+    #[serde(default)]
+    pub provider_profiles: HashMap<String, ProviderProfileConfig>,
+
+    /// Additional tenants beside the default one.
+    #[serde(default)]
+    pub tenants: HashMap<String, TenantConfig>,
+
+    /// Email sent to the newly created users.
+    #[serde(default)]
+    pub welcome_email: EmailNotificationConfig,
+    /// Email sent on login from a new device or country.
+    #[serde(default)]
+    pub login_alert_email: EmailNotificationConfig,
+    /// Header with the country code of the client set by the reverse proxy, ex. `CF-IPCountry`.
+    pub country_header: Option<String>,
+    /// Header with the address of the client set by the reverse proxy, ex. `CF-Connecting-IP`. The header is
+    /// accepted only from the `trustedProxies` of the admin allowlist. When not given, the address of the peer is used.
+    pub client_ip_header: Option<String>,
+
+    /// Ask the user to confirm the account of the login token instead of a silent login.
+    #[serde(default)]
+    pub account_chooser: bool,
+    /// Enable the `/auth/debug/login?name=...` login without any provider for the local development. It is
+    /// refused by the release builds.
+    #[serde(default)]
+    pub debug_login: bool,
+
+    #[serde(default)]
+    pub password: PasswordConfig,
+    #[serde(default)]
+    pub consent: ConsentConfig,
+    /// Second factor of the login, when not given only a single factor is used.
+    pub mfa: Option<MfaConfig>,
+    /// Single-use login links created by the support, when not given the links are disabled.
+    pub support_login: Option<SupportLoginConfig>,
+    /// Signing of the user context shared with the downstream services, when not given no context is provided.
+    pub user_context: Option<UserContextConfig>,
+    /// Signing of the tokens of the service identities, when not given the client credentials grant is disabled.
+    pub service_token: Option<ServiceTokenConfig>,
+    /// Join tokens of the dedicated game servers signed by the service token key, when not given the join tokens
+    /// are disabled.
+    pub join_token: Option<JoinTokenConfig>,
+    /// Login of the native apps, when not given only the browser logins are supported.
+    pub native_login: Option<NativeLoginConfig>,
+    /// Single sign-on of the first-party apps on other domains, when not given the apps share only the session
+    /// cookie of the home domain.
+    pub sso: Option<SsoConfig>,
+    /// Signing of the tickets of the realtime connections, when not given the tickets are disabled.
+    pub connection_ticket: Option<ConnectionTicketConfig>,
+    /// Daily quota of the API requests of the identities, when not given the requests are not counted.
+    pub api_quota: Option<ApiQuotaConfig>,
+    /// Roles granted to the matching users on their first login.
+    pub bootstrap_roles: Option<BootstrapRolesConfig>,
+    /// Emergency access credential, when not given the emergency access is disabled.
+    pub break_glass: Option<BreakGlassConfig>,
+    /// Risk scoring of the logins, when not given all the logins are allowed.
+    pub login_risk: Option<LoginRiskConfig>,
+    /// Escalation of the failed password logins, when not given only the login rate limit is applied.
+    pub login_friction: Option<LoginFrictionConfig>,
+    /// Bot checks of the registration and password reset requests, when not given the requests are not checked.
+    pub bot_check: Option<BotCheckConfig>,
+    /// Reputation check of the client addresses on the login and registration, when not given the addresses are not
+    /// checked.
+    pub ip_reputation: Option<IpReputationConfig>,
+    /// Age collected at the registration to flag the minor accounts, when not given the age is not collected.
+    pub age_gate: Option<AgeGateConfig>,
+    /// Regions of the users selecting the regional policies, when not given the regions are not recorded.
+    pub region: Option<RegionConfig>,
 }
 
 #[derive(Debug, ThisError)]
@@ -88,19 +515,109 @@ pub enum AuthBuildError {
     InvalidUserInfoUrl(String),
     #[error("Invalid redirect url: {0}")]
     RedirectUrl(String),
-    #[error("Failed to discover open id: {0}")]
-    Discovery(String),
+    #[error("Authorization parameter ({1}) of provider {0} is set by the service")]
+    ReservedAuthParam(String, String),
+    #[error("Tenant ({0}) already registered")]
+    TenantConflict(String),
+    #[error("Unknown provider ({1}) for tenant {0}")]
+    TenantProvider(String, String),
+    #[error("Provider profile ({0}) already registered")]
+    ProviderProfileConflict(String),
+    #[error("Unknown provider profile ({1}) for tenant {0}")]
+    UnknownProviderProfile(String, String),
+    #[error("Invalid password policy: {0}")]
+    PasswordPolicy(String),
+    #[error("Pwned passwords client error: {0}")]
+    PwnedPasswords(String),
+    #[error("Captcha client error: {0}")]
+    Captcha(String),
+    #[error("IP reputation client error: {0}")]
+    IpReputation(String),
+    #[error("Invalid user context signing key: {0}")]
+    UserContext(String),
+    #[error("Invalid service token signing key: {0}")]
+    ServiceToken(String),
+    #[error("Join tokens require the service token config")]
+    JoinTokenWithoutServiceToken,
+    #[error("Invalid connection ticket signing key: {0}")]
+    ConnectionTicket(String),
+    #[error("Invalid sso config: {0}")]
+    SsoConfig(String),
+    #[error("Debug login is not allowed in release builds")]
+    DebugLoginInRelease,
 }
 
 struct Inner {
     tera: Tera,
-    identity_manager: IdentityManager,
-    session_manager: SessionManager,
+    identity_manager: SharedIdentityStore,
+    session_manager: SharedSessionStore,
     name_generator: NameGenerator,
+    device_manager: DeviceManager,
+    password_manager: PasswordManager,
+    mfa_manager: MfaManager,
+    rate_limiter: RateLimiter,
+    token_revocation: TokenRevocation,
+    role_manager: RoleManager,
+    permission_manager: PermissionManager,
+    audit_log: AuditLog,
+    break_glass_store: BreakGlassStore,
+    login_link_manager: LoginLinkManager,
+    user_invalidation: UserInvalidation,
+    analytics: AnalyticsEvents,
+    deletion_manager: DeletionManager,
+    expiration_manager: ExpirationManager,
+    consent_manager: ConsentManager,
+    merge_manager: MergeManager,
+    service_client_manager: ServiceClientManager,
+    studio_manager: StudioManager,
+    opaque_token_store: OpaqueTokenStore,
+    native_login_manager: NativeLoginManager,
+    sso_handoff_manager: SsoHandoffManager,
+    ticket_redemption: TicketRedemption,
+    api_quota_manager: ApiQuotaManager,
+    abuse_flag_manager: AbuseFlagManager,
+    restriction_manager: RestrictionManager,
+    support_note_manager: SupportNoteManager,
+    tag_manager: TagManager,
+    age_manager: AgeManager,
+    parental_consent_manager: ParentalConsentManager,
+    region_manager: RegionManager,
+    feature_flags: FeatureFlags,
+    email_sender: EmailSender,
+    error_reporting: ErrorReporting,
+    clock: SharedClock,
 
-    home_url: Url,
-    providers: Vec<String>,
     token_generator: TokenGenerator,
+    welcome_email: EmailNotificationConfig,
+    login_alert_email: EmailNotificationConfig,
+    country_header: Option<String>,
+    client_ip_header: Option<String>,
+    ip_allowlist: Arc<IpAllowlist>,
+    account_chooser: bool,
+    debug_login: bool,
+    password_config: PasswordConfig,
+    password_policy: PasswordPolicy,
+    pwned_passwords: Option<PwnedPasswords>,
+    consent_config: ConsentConfig,
+    mfa_config: Option<MfaConfig>,
+    support_login_config: Option<SupportLoginConfig>,
+    user_context_signer: Option<UserContextSigner>,
+    service_token_signer: Option<ServiceTokenSigner>,
+    join_token_config: Option<JoinTokenConfig>,
+    native_login: Option<NativeLogin>,
+    sso_login: Option<SsoLogin>,
+    connection_ticket_signer: Option<ConnectionTicketSigner>,
+    api_quota_config: Option<ApiQuotaConfig>,
+    bootstrap_roles: Option<BootstrapRolesConfig>,
+    break_glass_config: Option<BreakGlassConfig>,
+    login_risk_config: Option<LoginRiskConfig>,
+    login_friction_config: Option<LoginFrictionConfig>,
+    captcha_verifier: Option<CaptchaVerifier>,
+    bot_check_config: Option<BotCheckConfig>,
+    ip_reputation: Option<IpReputation>,
+    age_gate_config: Option<AgeGateConfig>,
+    region_config: Option<RegionConfig>,
+    unavailable_providers: UnavailableProviders,
 }
 
 #[derive(Clone)]
@@ -111,111 +628,709 @@ impl AuthServiceState {
         &self.0.tera
     }
 
-    pub fn identity_manager(&self) -> &IdentityManager {
-        &self.0.identity_manager
+    pub fn identity_manager(&self) -> &dyn IdentityStore {
+        &*self.0.identity_manager
     }
 
-    pub fn session_manager(&self) -> &SessionManager {
-        &self.0.session_manager
+    pub fn session_manager(&self) -> &dyn SessionStore {
+        &*self.0.session_manager
     }
 
     pub fn name_generator(&self) -> &NameGenerator {
         &self.0.name_generator
     }
 
+    pub fn device_manager(&self) -> &DeviceManager {
+        &self.0.device_manager
+    }
+
+    pub fn password_manager(&self) -> &PasswordManager {
+        &self.0.password_manager
+    }
+
+    pub fn mfa_manager(&self) -> &MfaManager {
+        &self.0.mfa_manager
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.0.rate_limiter
+    }
+
+    pub fn token_revocation(&self) -> &TokenRevocation {
+        &self.0.token_revocation
+    }
+
+    pub fn role_manager(&self) -> &RoleManager {
+        &self.0.role_manager
+    }
+
+    pub fn permission_manager(&self) -> &PermissionManager {
+        &self.0.permission_manager
+    }
+
+    pub fn audit_log(&self) -> &AuditLog {
+        &self.0.audit_log
+    }
+
+    pub fn break_glass_store(&self) -> &BreakGlassStore {
+        &self.0.break_glass_store
+    }
+
+    pub fn login_link_manager(&self) -> &LoginLinkManager {
+        &self.0.login_link_manager
+    }
+
+    pub fn user_invalidation(&self) -> &UserInvalidation {
+        &self.0.user_invalidation
+    }
+
+    pub fn analytics(&self) -> &AnalyticsEvents {
+        &self.0.analytics
+    }
+
+    pub fn deletion_manager(&self) -> &DeletionManager {
+        &self.0.deletion_manager
+    }
+
+    pub fn expiration_manager(&self) -> &ExpirationManager {
+        &self.0.expiration_manager
+    }
+
+    pub fn consent_manager(&self) -> &ConsentManager {
+        &self.0.consent_manager
+    }
+
+    pub fn merge_manager(&self) -> &MergeManager {
+        &self.0.merge_manager
+    }
+
+    pub fn service_client_manager(&self) -> &ServiceClientManager {
+        &self.0.service_client_manager
+    }
+
+    pub fn studio_manager(&self) -> &StudioManager {
+        &self.0.studio_manager
+    }
+
+    pub fn opaque_token_store(&self) -> &OpaqueTokenStore {
+        &self.0.opaque_token_store
+    }
+
+    pub fn native_login_manager(&self) -> &NativeLoginManager {
+        &self.0.native_login_manager
+    }
+
+    pub fn sso_handoff_manager(&self) -> &SsoHandoffManager {
+        &self.0.sso_handoff_manager
+    }
+
+    pub fn ticket_redemption(&self) -> &TicketRedemption {
+        &self.0.ticket_redemption
+    }
+
+    pub fn api_quota_manager(&self) -> &ApiQuotaManager {
+        &self.0.api_quota_manager
+    }
+
+    pub fn abuse_flag_manager(&self) -> &AbuseFlagManager {
+        &self.0.abuse_flag_manager
+    }
+
+    pub fn restriction_manager(&self) -> &RestrictionManager {
+        &self.0.restriction_manager
+    }
+
+    pub fn support_note_manager(&self) -> &SupportNoteManager {
+        &self.0.support_note_manager
+    }
+
+    pub fn tag_manager(&self) -> &TagManager {
+        &self.0.tag_manager
+    }
+
+    pub fn age_manager(&self) -> &AgeManager {
+        &self.0.age_manager
+    }
+
+    pub fn parental_consent_manager(&self) -> &ParentalConsentManager {
+        &self.0.parental_consent_manager
+    }
+
+    pub fn region_manager(&self) -> &RegionManager {
+        &self.0.region_manager
+    }
+
+    pub fn feature_flags(&self) -> &FeatureFlags {
+        &self.0.feature_flags
+    }
+
+    pub fn email_sender(&self) -> &EmailSender {
+        &self.0.email_sender
+    }
+
+    pub fn error_reporting(&self) -> &ErrorReporting {
+        &self.0.error_reporting
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        &*self.0.clock
+    }
+
     pub fn token(&self) -> &TokenGenerator {
         &self.0.token_generator
     }
 
-    pub fn home_url(&self) -> &Url {
-        &self.0.home_url
+    pub fn welcome_email(&self) -> &EmailNotificationConfig {
+        &self.0.welcome_email
+    }
+
+    pub fn login_alert_email(&self) -> &EmailNotificationConfig {
+        &self.0.login_alert_email
+    }
+
+    pub fn country_header(&self) -> Option<&str> {
+        self.0.country_header.as_deref()
+    }
+
+    pub fn client_ip_header(&self) -> Option<&str> {
+        self.0.client_ip_header.as_deref()
+    }
+
+    pub fn ip_allowlist(&self) -> &IpAllowlist {
+        &self.0.ip_allowlist
+    }
+
+    pub fn password_config(&self) -> &PasswordConfig {
+        &self.0.password_config
+    }
+
+    pub fn unavailable_providers(&self) -> &UnavailableProviders {
+        &self.0.unavailable_providers
+    }
+
+    pub fn consent_config(&self) -> &ConsentConfig {
+        &self.0.consent_config
+    }
+
+    pub fn password_policy(&self) -> &PasswordPolicy {
+        &self.0.password_policy
+    }
+
+    pub fn pwned_passwords(&self) -> Option<&PwnedPasswords> {
+        self.0.pwned_passwords.as_ref()
+    }
+
+    pub fn is_account_chooser_enabled(&self) -> bool {
+        self.0.account_chooser
+    }
+
+    pub fn is_debug_login_enabled(&self) -> bool {
+        self.0.debug_login
+    }
+
+    pub fn mfa_config(&self) -> Option<&MfaConfig> {
+        self.0.mfa_config.as_ref()
+    }
+
+    pub fn support_login_config(&self) -> Option<&SupportLoginConfig> {
+        self.0.support_login_config.as_ref()
+    }
+
+    pub fn user_context_signer(&self) -> Option<&UserContextSigner> {
+        self.0.user_context_signer.as_ref()
+    }
+
+    pub fn service_token_signer(&self) -> Option<&ServiceTokenSigner> {
+        self.0.service_token_signer.as_ref()
+    }
+
+    pub fn join_token_config(&self) -> Option<&JoinTokenConfig> {
+        self.0.join_token_config.as_ref()
+    }
+
+    pub fn native_login(&self) -> Option<&NativeLogin> {
+        self.0.native_login.as_ref()
+    }
+
+    pub fn sso_login(&self) -> Option<&SsoLogin> {
+        self.0.sso_login.as_ref()
+    }
+
+    pub fn connection_ticket_signer(&self) -> Option<&ConnectionTicketSigner> {
+        self.0.connection_ticket_signer.as_ref()
+    }
+
+    pub fn api_quota_config(&self) -> Option<&ApiQuotaConfig> {
+        self.0.api_quota_config.as_ref()
+    }
+
+    pub fn age_gate_config(&self) -> Option<&AgeGateConfig> {
+        self.0.age_gate_config.as_ref()
+    }
+
+    pub fn region_config(&self) -> Option<&RegionConfig> {
+        self.0.region_config.as_ref()
+    }
+
+    pub fn bootstrap_roles(&self) -> Option<&BootstrapRolesConfig> {
+        self.0.bootstrap_roles.as_ref()
+    }
+
+    pub fn break_glass_config(&self) -> Option<&BreakGlassConfig> {
+        self.0.break_glass_config.as_ref()
+    }
+
+    pub fn login_risk_config(&self) -> Option<&LoginRiskConfig> {
+        self.0.login_risk_config.as_ref()
+    }
+
+    pub fn login_friction_config(&self) -> Option<&LoginFrictionConfig> {
+        self.0.login_friction_config.as_ref()
+    }
+
+    pub(in crate::auth) fn captcha_verifier(&self) -> Option<&CaptchaVerifier> {
+        self.0.captcha_verifier.as_ref()
     }
 
-    pub fn providers(&self) -> &[String] {
-        &self.0.providers
+    pub fn bot_check_config(&self) -> Option<&BotCheckConfig> {
+        self.0.bot_check_config.as_ref()
+    }
+
+    pub(in crate::auth) fn ip_reputation(&self) -> Option<&IpReputation> {
+        self.0.ip_reputation.as_ref()
     }
 }
 
 pub struct AuthServiceDependencies {
     pub tera: Tera,
-    pub identity_manager: IdentityManager,
-    pub session_manager: SessionManager,
+    pub identity_manager: SharedIdentityStore,
+    pub session_manager: SharedSessionStore,
     pub name_generator: NameGenerator,
+    pub device_manager: DeviceManager,
+    pub password_manager: PasswordManager,
+    pub mfa_manager: MfaManager,
+    pub rate_limiter: RateLimiter,
+    pub token_revocation: TokenRevocation,
+    pub role_manager: RoleManager,
+    pub permission_manager: PermissionManager,
+    pub audit_log: AuditLog,
+    pub break_glass_store: BreakGlassStore,
+    pub login_link_manager: LoginLinkManager,
+    pub user_invalidation: UserInvalidation,
+    pub analytics: AnalyticsEvents,
+    pub deletion_manager: DeletionManager,
+    pub expiration_manager: ExpirationManager,
+    pub consent_manager: ConsentManager,
+    pub merge_manager: MergeManager,
+    pub service_client_manager: ServiceClientManager,
+    pub studio_manager: StudioManager,
+    pub opaque_token_store: OpaqueTokenStore,
+    pub native_login_manager: NativeLoginManager,
+    pub sso_handoff_manager: SsoHandoffManager,
+    pub ticket_redemption: TicketRedemption,
+    pub api_quota_manager: ApiQuotaManager,
+    pub abuse_flag_manager: AbuseFlagManager,
+    pub restriction_manager: RestrictionManager,
+    pub support_note_manager: SupportNoteManager,
+    pub tag_manager: TagManager,
+    pub age_manager: AgeManager,
+    pub parental_consent_manager: ParentalConsentManager,
+    pub region_manager: RegionManager,
+    pub feature_flags: FeatureFlags,
+    pub email_sender: EmailSender,
+    pub error_reporting: ErrorReporting,
+    /// Source of the time used by the expiration checks.
+    pub clock: SharedClock,
+    /// Networks of the services allowed to use the internal endpoints.
+    pub ip_allowlist: Arc<IpAllowlist>,
 }
 
 pub struct AuthServiceBuilder {
     state: AuthServiceState,
-    auth_session_meta: AuthSessionMeta,
-    openid_clients: Vec<OIDCClient>,
-    oauth2_clients: Vec<OAuth2Client>,
+    tenant_resolver: TenantResolver,
+    openid_clients: HashMap<String, ProviderClients<OIDCClient>>,
+    oauth2_clients: HashMap<String, ProviderClients<OAuth2Client>>,
+    ip_allowlist: Arc<IpAllowlist>,
+}
+
+/// Get the redirect url of a provider. It is derived from the api url unless it is overridden in the config. The
+/// override shall still end with the path of the auth page of the provider as the provider redirects there.
+fn provider_redirect_url(api_url: &Url, provider: &str, redirect_url: Option<&str>) -> Result<Url, AuthBuildError> {
+    match redirect_url {
+        Some(redirect_url) => {
+            let url =
+                Url::parse(redirect_url).map_err(|err| AuthBuildError::RedirectUrl(format!("{provider}: {err}")))?;
+            if !matches!(url.scheme(), "http" | "https") || !url.path().ends_with(&format!("/{provider}/auth")) {
+                return Err(AuthBuildError::RedirectUrl(format!(
+                    "{provider}: {url} is not the auth page of the provider"
+                )));
+            }
+            Ok(url)
+        }
+        None => {
+            let mut url = api_url.clone();
+            url.path_segments_mut()
+                .map_err(|_| AuthBuildError::RedirectUrl(format!("{provider}: api url is not a base url")))?
+                .pop_if_empty()
+                .extend([provider, "auth"]);
+            Ok(url)
+        }
+    }
+}
+
+/// The parameters of the authorization request set by the service, they cannot be configured.
+const RESERVED_AUTH_PARAMS: &[&str] = &[
+    "response_type",
+    "client_id",
+    "redirect_uri",
+    "scope",
+    "state",
+    "nonce",
+    "code_challenge",
+    "code_challenge_method",
+    "max_age",
+];
+
+fn check_auth_params(provider: &str, auth_params: &HashMap<String, String>) -> Result<(), AuthBuildError> {
+    match auth_params
+        .keys()
+        .find(|param| RESERVED_AUTH_PARAMS.contains(&param.as_str()))
+    {
+        Some(param) => Err(AuthBuildError::ReservedAuthParam(provider.to_owned(), param.clone())),
+        None => Ok(()),
+    }
+}
+
+/// Get the enabled providers of a tenant.
+fn tenant_providers(
+    tenant_id: &str,
+    provider_profile: &str,
+    enabled_providers: Option<&HashSet<String>>,
+    profile_providers: &HashMap<String, HashSet<String>>,
+) -> Result<Vec<String>, AuthBuildError> {
+    let available_providers = profile_providers
+        .get(provider_profile)
+        .ok_or_else(|| AuthBuildError::UnknownProviderProfile(tenant_id.to_owned(), provider_profile.to_owned()))?;
+
+    let mut providers: Vec<String> = match enabled_providers {
+        Some(enabled_providers) => {
+            if let Some(provider) = enabled_providers
+                .iter()
+                .find(|provider| !available_providers.contains(*provider))
+            {
+                return Err(AuthBuildError::TenantProvider(tenant_id.to_owned(), provider.clone()));
+            }
+            enabled_providers.iter().cloned().collect()
+        }
+        None => available_providers.iter().cloned().collect(),
+    };
+    providers.sort();
+    Ok(providers)
 }
 
 impl AuthServiceBuilder {
     pub async fn new(dependencies: AuthServiceDependencies, config: &AuthConfig) -> Result<Self, AuthBuildError> {
-        let mut providers = HashSet::new();
+        if config.debug_login && !cfg!(debug_assertions) {
+            return Err(AuthBuildError::DebugLoginInRelease);
+        }
 
         let token_max_duration = Duration::seconds(i64::try_from(config.auth_session.session_max_duration)?);
-        let token_generator = TokenGenerator::new(token_max_duration);
+        let token_generator = TokenGenerator::new(token_max_duration, dependencies.clock.clone());
+
+        if config.provider_profiles.contains_key(DEFAULT_PROVIDER_PROFILE) {
+            return Err(AuthBuildError::ProviderProfileConflict(
+                DEFAULT_PROVIDER_PROFILE.to_owned(),
+            ));
+        }
+        let provider_profiles = iter::once((DEFAULT_PROVIDER_PROFILE, &config.openid, &config.oauth2)).chain(
+            config
+                .provider_profiles
+                .iter()
+                .map(|(profile, profile_config)| (profile.as_str(), &profile_config.openid, &profile_config.oauth2)),
+        );
 
-        let mut openid_clients = Vec::new();
-        for (provider, provider_config) in &config.openid {
-            if !providers.insert(provider.clone()) {
-                return Err(AuthBuildError::ProviderConflict(provider.clone()));
+        let mut profile_providers = HashMap::new();
+        let unavailable_providers = UnavailableProviders::default();
+        let mut openid_clients = HashMap::<String, ProviderClients<OIDCClient>>::new();
+        let mut oauth2_clients = HashMap::<String, ProviderClients<OAuth2Client>>::new();
+        for (profile, openid, oauth2) in provider_profiles {
+            let mut providers = HashSet::new();
+
+            for (provider, provider_config) in openid {
+                if !providers.insert(provider.clone()) || oauth2_clients.contains_key(provider) {
+                    return Err(AuthBuildError::ProviderConflict(provider.clone()));
+                }
+
+                check_auth_params(provider, &provider_config.auth_params)?;
+                let redirect_url =
+                    provider_redirect_url(&config.api_url, provider, provider_config.redirect_url.as_deref())?;
+                let connect = OIDCClient::new(
+                    profile,
+                    provider,
+                    provider_config,
+                    redirect_url,
+                    unavailable_providers.clone(),
+                    dependencies.clock.clone(),
+                )
+                .await?;
+                openid_clients
+                    .entry(provider.clone())
+                    .or_default()
+                    .add(profile, connect);
             }
 
-            let connect = OIDCClient::new(provider, provider_config).await?;
-            openid_clients.push(connect);
-        }
+            for (provider, provider_config) in oauth2 {
+                if !providers.insert(provider.clone()) || openid_clients.contains_key(provider) {
+                    return Err(AuthBuildError::ProviderConflict(provider.clone()));
+                }
 
-        let mut oauth2_clients = Vec::new();
-        for (provider, provider_config) in &config.oauth2 {
-            if !providers.insert(provider.clone()) {
-                return Err(AuthBuildError::ProviderConflict(provider.clone()));
+                check_auth_params(provider, &provider_config.auth_params)?;
+                let redirect_url =
+                    provider_redirect_url(&config.api_url, provider, provider_config.redirect_url.as_deref())?;
+                let connect = OAuth2Client::new(provider, provider_config, redirect_url).await?;
+                oauth2_clients
+                    .entry(provider.clone())
+                    .or_default()
+                    .add(profile, connect);
             }
 
-            let connect = OAuth2Client::new(provider, provider_config).await?;
-            oauth2_clients.push(connect);
+            profile_providers.insert(profile.to_owned(), providers);
+        }
+        if config.debug_login
+            && (openid_clients.contains_key(DEBUG_PROVIDER) || oauth2_clients.contains_key(DEBUG_PROVIDER))
+        {
+            return Err(AuthBuildError::ProviderConflict(DEBUG_PROVIDER.to_owned()));
+        }
+
+        let mut tenant_resolver = {
+            let session_meta = AuthSessionMeta::new(
+                &config.home_url,
+                &config.api_url,
+                config.auth_session.cookie_name_suffix.as_deref(),
+                &config.auth_session,
+                dependencies.clock.clone(),
+            )
+            .map_err(|err| AuthBuildError::InvalidAuthSession(format!("{err}")))?;
+            let providers = tenant_providers(DEFAULT_TENANT_ID, DEFAULT_PROVIDER_PROFILE, None, &profile_providers)?;
+            TenantResolver::new(Tenant::new(TenantInfo {
+                id: DEFAULT_TENANT_ID.to_owned(),
+                home_url: config.home_url.clone(),
+                api_url: config.api_url.clone(),
+                provider_profile: DEFAULT_PROVIDER_PROFILE.to_owned(),
+                providers,
+                session_meta,
+            }))
+        };
+
+        for (tenant_id, tenant_config) in &config.tenants {
+            let provider_profile = tenant_config
+                .provider_profile
+                .as_deref()
+                .unwrap_or(DEFAULT_PROVIDER_PROFILE);
+            let providers = tenant_providers(
+                tenant_id,
+                provider_profile,
+                tenant_config.providers.as_ref(),
+                &profile_providers,
+            )?;
+            let session_meta = AuthSessionMeta::new(
+                &tenant_config.home_url,
+                &tenant_config.api_url,
+                tenant_config.cookie_name_suffix.as_deref(),
+                &config.auth_session,
+                dependencies.clock.clone(),
+            )
+            .map_err(|err| AuthBuildError::InvalidAuthSession(format!("{err}")))?;
+            let tenant = Tenant::new(TenantInfo {
+                id: tenant_id.clone(),
+                home_url: tenant_config.home_url.clone(),
+                api_url: tenant_config.api_url.clone(),
+                provider_profile: provider_profile.to_owned(),
+                providers,
+                session_meta,
+            });
+            tenant_resolver.add(tenant, &tenant_config.hosts)?;
+        }
+
+        let password_policy = PasswordPolicy::new(&config.password.policy)?;
+        let pwned_passwords = if config.password.pwned_passwords.enabled {
+            Some(PwnedPasswords::new(&config.password.pwned_passwords)?)
+        } else {
+            None
+        };
+
+        let captcha_verifier = config
+            .login_friction
+            .as_ref()
+            .and_then(|login_friction| login_friction.captcha.as_ref())
+            .map(CaptchaVerifier::new)
+            .transpose()?;
+
+        let ip_reputation = config
+            .ip_reputation
+            .as_ref()
+            .map(|ip_reputation| IpReputation::new(ip_reputation, dependencies.clock.clone()))
+            .transpose()?;
+
+        let user_context_signer = config.user_context.as_ref().map(UserContextSigner::new).transpose()?;
+        let service_token_signer = config
+            .service_token
+            .as_ref()
+            .map(|service_token| ServiceTokenSigner::new(service_token, config.api_url.as_str()))
+            .transpose()?;
+        if config.join_token.is_some() && service_token_signer.is_none() {
+            return Err(AuthBuildError::JoinTokenWithoutServiceToken);
         }
+        let native_login = config.native_login.as_ref().map(NativeLogin::new).transpose()?;
+        let sso_login = config.sso.as_ref().map(SsoLogin::new).transpose()?;
+        let connection_ticket_signer = config
+            .connection_ticket
+            .as_ref()
+            .map(ConnectionTicketSigner::new)
+            .transpose()?;
 
+        let ip_allowlist = dependencies.ip_allowlist;
         let state = AuthServiceState(Arc::new(Inner {
             tera: dependencies.tera,
             identity_manager: dependencies.identity_manager,
             session_manager: dependencies.session_manager,
             name_generator: dependencies.name_generator,
+            device_manager: dependencies.device_manager,
+            password_manager: dependencies.password_manager,
+            mfa_manager: dependencies.mfa_manager,
+            rate_limiter: dependencies.rate_limiter,
+            token_revocation: dependencies.token_revocation,
+            role_manager: dependencies.role_manager,
+            permission_manager: dependencies.permission_manager,
+            audit_log: dependencies.audit_log,
+            break_glass_store: dependencies.break_glass_store,
+            login_link_manager: dependencies.login_link_manager,
+            user_invalidation: dependencies.user_invalidation,
+            analytics: dependencies.analytics,
+            deletion_manager: dependencies.deletion_manager,
+            expiration_manager: dependencies.expiration_manager,
+            consent_manager: dependencies.consent_manager,
+            merge_manager: dependencies.merge_manager,
+            service_client_manager: dependencies.service_client_manager,
+            studio_manager: dependencies.studio_manager,
+            opaque_token_store: dependencies.opaque_token_store,
+            native_login_manager: dependencies.native_login_manager,
+            sso_handoff_manager: dependencies.sso_handoff_manager,
+            ticket_redemption: dependencies.ticket_redemption,
+            api_quota_manager: dependencies.api_quota_manager,
+            abuse_flag_manager: dependencies.abuse_flag_manager,
+            restriction_manager: dependencies.restriction_manager,
+            support_note_manager: dependencies.support_note_manager,
+            tag_manager: dependencies.tag_manager,
+            age_manager: dependencies.age_manager,
+            parental_consent_manager: dependencies.parental_consent_manager,
+            region_manager: dependencies.region_manager,
+            feature_flags: dependencies.feature_flags,
+            email_sender: dependencies.email_sender,
+            error_reporting: dependencies.error_reporting,
+            clock: dependencies.clock,
             token_generator,
-            home_url: config.home_url.to_owned(),
-            providers: providers.into_iter().collect(),
+            welcome_email: config.welcome_email.clone(),
+            login_alert_email: config.login_alert_email.clone(),
+            country_header: config.country_header.clone(),
+            client_ip_header: config.client_ip_header.clone(),
+            ip_allowlist: ip_allowlist.clone(),
+            account_chooser: config.account_chooser,
+            debug_login: config.debug_login,
+            password_config: config.password.clone(),
+            password_policy,
+            pwned_passwords,
+            consent_config: config.consent.clone(),
+            mfa_config: config.mfa.clone(),
+            support_login_config: config.support_login.clone(),
+            user_context_signer,
+            service_token_signer,
+            join_token_config: config.join_token.clone(),
+            native_login,
+            sso_login,
+            connection_ticket_signer,
+            api_quota_config: config.api_quota.clone(),
+            bootstrap_roles: config.bootstrap_roles.clone(),
+            break_glass_config: config.break_glass.clone(),
+            login_risk_config: config.login_risk.clone(),
+            login_friction_config: config.login_friction.clone(),
+            captcha_verifier,
+            bot_check_config: config.bot_check.clone(),
+            ip_reputation,
+            age_gate_config: config.age_gate.clone(),
+            region_config: config.region.clone(),
+            unavailable_providers,
         }));
 
-        let auth_session_meta =
-            AuthSessionMeta::new(config.home_url.clone(), config.api_url.clone(), &config.auth_session)
-                .map_err(|err| AuthBuildError::InvalidAuthSession(format!("{err}")))?;
-
         Ok(Self {
             state,
-            auth_session_meta,
+            tenant_resolver,
             openid_clients,
             oauth2_clients,
+            ip_allowlist,
         })
     }
 
+    /// Create the page and api routers. Beside the default routes, tenants can be selected
+    /// explicitly by the `/t/{tenant}` path prefix.
     pub fn into_router<S>(self) -> (Router<S>, Router<S>)
     where
         S: Clone + Send + Sync + 'static,
     {
+        let tenant_resolver = self.tenant_resolver.into_layer();
+
         let page_router = {
             let mut router = Router::new()
                 .route("/auth/logout", get(auth::page_logout))
-                .route("/auth/delete", get(auth::page_delete_user));
+                .route(
+                    "/auth/delete",
+                    get(auth::page_delete_user).post(auth::page_delete_user_confirm),
+                )
+                .route(
+                    "/auth/revoke",
+                    get(auth::page_revoke_session).post(auth::page_revoke_session_confirm),
+                )
+                .route("/auth/links", get(auth::page_links))
+                .route("/auth/links/merge", post(auth::page_merge_request))
+                .route(
+                    "/auth/links/unlink",
+                    get(auth::page_unlink).post(auth::page_unlink_confirm),
+                );
+
+            if self.state.support_login_config().is_some() {
+                router = router.route(
+                    "/auth/support/login",
+                    get(auth::page_support_login).post(auth::page_support_login_confirm),
+                );
+            }
+
+            if self.state.native_login().is_some() {
+                log::info!("Registering native app login");
+                router = router.route("/auth/native/login", get(auth::page_native_login));
+            }
+
+            if self.state.sso_login().is_some() {
+                log::info!("Registering single sign-on of the apps");
+                router = router.route("/auth/sso/login", get(auth::page_sso_login));
+            }
 
             router = router.nest(
                 "/auth/token",
                 Router::new().route("/login", get(auth::page_token_login)),
             );
+            router = router.route("/auth/silent", get(auth::ep_silent_refresh));
+
+            if self.state.is_debug_login_enabled() {
+                log::warn!("Registering debug login, it must not be enabled in production");
+                router = router.route("/auth/debug/login", get(auth::page_debug_login));
+            }
 
-            for client in self.openid_clients {
-                log::info!("Registering OpenId Connect provider {}", client.provider);
-                let path = format!("/auth/{}", client.provider);
+            for (provider, clients) in self.openid_clients {
+                log::info!(
+                    "Registering OpenId Connect provider {provider} for profiles {:?}",
+                    clients.profiles()
+                );
+                let path = format!("/auth/{provider}");
 
                 router = router.nest(
                     &path,
@@ -223,13 +1338,16 @@ impl AuthServiceBuilder {
                         .route("/login", get(auth::page_oidc_login))
                         .route("/link", get(auth::page_oidc_link))
                         .route("/auth", get(auth::page_oidc_auth))
-                        .layer(Extension(Arc::new(client))),
+                        .layer(clients.into_layer()),
                 );
             }
 
-            for client in self.oauth2_clients {
-                log::info!("Registering OAuth2 provider {}", client.provider);
-                let path = format!("/auth/{}", client.provider);
+            for (provider, clients) in self.oauth2_clients {
+                log::info!(
+                    "Registering OAuth2 provider {provider} for profiles {:?}",
+                    clients.profiles()
+                );
+                let path = format!("/auth/{provider}");
 
                 router = router.nest(
                     &path,
@@ -237,19 +1355,232 @@ impl AuthServiceBuilder {
                         .route("/login", get(auth::page_oauth2_login))
                         .route("/link", get(auth::page_oauth2_link))
                         .route("/auth", get(auth::page_oauth2_auth))
-                        .layer(Extension(Arc::new(client))),
+                        .layer(clients.into_layer()),
                 );
             }
 
-            router
-                .layer(self.auth_session_meta.into_layer())
-                .with_state(self.state.clone())
+            let router = router.layer(tenant_resolver.clone()).with_state(self.state.clone());
+            Router::new().nest("/t/:tenant", router.clone()).merge(router)
         };
 
-        let api_router = Router::new()
-            .route("/auth/userinfo", get(auth::ep_get_user_info))
-            .route("/auth/providers", get(auth::ep_get_auth_providers))
-            .with_state(self.state);
+        let api_router = {
+            let mut router = Router::new()
+                .route("/auth/userinfo", get(auth::ep_get_user_info))
+                .route("/auth/user/name", put(auth::ep_update_user_name))
+                .route(
+                    "/auth/user/tokens",
+                    get(auth::ep_get_user_tokens).delete(auth::ep_delete_user_tokens),
+                )
+                .route("/auth/user/tokens/:token_id", delete(auth::ep_delete_user_token))
+                .route("/auth/user/audit", get(auth::ep_get_user_audit))
+                .route("/auth/user/consents", get(auth::ep_get_user_consents))
+                .route("/auth/user/consents/:purpose", put(auth::ep_update_user_consent))
+                .route("/auth/studios", post(auth::ep_create_studio))
+                .route("/auth/studios/:studio_id", delete(auth::ep_delete_studio))
+                .route("/auth/studios/:studio_id/restore", post(auth::ep_restore_studio))
+                .route(
+                    "/auth/studios/:studio_id/transfer",
+                    post(auth::ep_transfer_studio).delete(auth::ep_cancel_studio_transfer),
+                )
+                .route(
+                    "/auth/studios/:studio_id/transfer/accept",
+                    post(auth::ep_accept_studio_transfer),
+                )
+                .route(
+                    "/auth/studios/:studio_id/members/:user_id",
+                    put(auth::ep_add_studio_member).delete(auth::ep_remove_studio_member),
+                )
+                .route(
+                    "/auth/studios/:studio_id/tokens",
+                    get(auth::ep_list_studio_tokens).post(auth::ep_create_studio_token),
+                )
+                .route(
+                    "/auth/studios/:studio_id/tokens/:token_id",
+                    delete(auth::ep_delete_studio_token),
+                )
+                .route("/auth/providers", get(auth::ep_get_auth_providers))
+                .route("/auth/feature-flags", get(auth::ep_list_feature_flags))
+                .route(
+                    "/auth/grants",
+                    put(auth::ep_grant_permission).delete(auth::ep_revoke_permission),
+                )
+                .route(
+                    "/auth/identities/:user_id/expiration",
+                    get(auth::ep_get_expiration).put(auth::ep_set_expiration),
+                )
+                .route(
+                    "/auth/identities/:user_id/legal-hold",
+                    get(auth::ep_get_legal_hold)
+                        .put(auth::ep_place_legal_hold)
+                        .delete(auth::ep_release_legal_hold),
+                )
+                .route("/auth/identities/:user_id/merge", post(auth::ep_merge_identity))
+                .route("/auth/identities/:user_id/abuse-flags", get(auth::ep_list_abuse_flags))
+                .route(
+                    "/auth/identities/:user_id/restrictions",
+                    get(auth::ep_list_restrictions),
+                )
+                .route(
+                    "/auth/identities/:user_id/restrictions/:restriction",
+                    put(auth::ep_set_restriction).delete(auth::ep_remove_restriction),
+                )
+                .route("/auth/identities/:user_id/tags", get(auth::ep_list_identity_tags))
+                .route(
+                    "/auth/identities/:user_id/tags/:tag",
+                    put(auth::ep_add_identity_tag).delete(auth::ep_remove_identity_tag),
+                )
+                .route(
+                    "/auth/identities/:user_id/notes",
+                    get(auth::ep_list_support_notes).post(auth::ep_add_support_note),
+                )
+                .route("/auth/merge-requests", get(auth::ep_list_merge_requests));
+
+            // endpoints of the other services
+            let internal_router = Router::new()
+                .route("/auth/validate", post(auth::ep_validate_session))
+                .route("/auth/permissions/check", post(auth::ep_check_permission))
+                .route("/auth/permissions/check-batch", post(auth::ep_check_permissions))
+                .route("/auth/consents/check", post(auth::ep_check_consent))
+                .route("/auth/tombstones/:user_id", get(auth::ep_find_tombstone))
+                .route("/auth/abuse-flags", post(auth::ep_report_abuse))
+                .route_layer(middleware::from_fn_with_state(
+                    self.ip_allowlist.clone(),
+                    enforce_ip_allowlist,
+                ));
+            router = router.merge(internal_router);
+
+            let password_config = self.state.password_config();
+            if password_config.enabled {
+                log::info!("Registering password login");
+                router = router
+                    .route("/auth/password/login", post(auth::ep_password_login))
+                    .route("/auth/password", put(auth::ep_set_password));
+
+                if password_config.reset_page_url.is_some() {
+                    router = router
+                        .route("/auth/password/reset", post(auth::ep_request_password_reset))
+                        .route("/auth/password/reset/confirm", post(auth::ep_confirm_password_reset));
+                }
+            }
+
+            if self.state.user_context_signer().is_some() {
+                router = router.route("/auth/user-context/key", get(auth::ep_get_user_context_key));
+            }
+
+            if self.state.service_token_signer().is_some() {
+                log::info!("Registering service identities");
+                router = router
+                    .route(
+                        "/auth/services",
+                        get(auth::ep_list_services).post(auth::ep_create_service),
+                    )
+                    .route("/auth/services/:user_id", delete(auth::ep_delete_service))
+                    .route("/auth/services/:user_id/secret", post(auth::ep_rotate_service_secret))
+                    .route(
+                        "/auth/services/:user_id/key",
+                        put(auth::ep_set_service_key).delete(auth::ep_delete_service_key),
+                    )
+                    .route("/auth/service/token", post(auth::ep_service_token))
+                    .route("/auth/service/introspect", post(auth::ep_introspect_service_token))
+                    .route("/auth/service/keys", get(auth::ep_get_service_token_keys));
+
+                if self.state.join_token_config().is_some() {
+                    log::info!("Registering join tokens");
+                    router = router.route("/auth/join-token", post(auth::ep_create_join_token));
+                }
+            }
+
+            if self.state.native_login().is_some() {
+                router = router.route("/auth/native/token", post(auth::ep_native_token));
+            }
+
+            if self.state.sso_login().is_some() {
+                router = router.route("/auth/sso/token", post(auth::ep_sso_token));
+            }
+
+            if self.state.connection_ticket_signer().is_some() {
+                log::info!("Registering connection tickets");
+                router = router
+                    .route("/auth/ticket", post(auth::ep_create_connection_ticket))
+                    .route("/auth/ticket/key", get(auth::ep_get_connection_ticket_key))
+                    .merge(
+                        Router::new()
+                            .route("/auth/ticket/redeem", post(auth::ep_redeem_connection_ticket))
+                            .route_layer(middleware::from_fn_with_state(
+                                self.ip_allowlist.clone(),
+                                enforce_ip_allowlist,
+                            )),
+                    );
+            }
+
+            if self.state.break_glass_config().is_some() {
+                log::warn!("Registering break-glass emergency access");
+                router = router.route("/auth/break-glass", post(auth::ep_break_glass));
+            }
+
+            if self.state.age_gate_config().is_some() {
+                log::info!("Registering age gate");
+                router = router.route("/auth/user/age", put(auth::ep_declare_age)).route(
+                    "/auth/identities/:user_id/age/verify-adult",
+                    post(auth::ep_verify_adult),
+                );
+            }
+
+            if self.state.parental_consent_config().is_some() {
+                log::info!("Registering parental consent");
+                router = router
+                    .route(
+                        "/auth/user/parental-consent",
+                        get(auth::ep_get_parental_consent).post(auth::ep_request_parental_consent),
+                    )
+                    .route("/auth/parental-consent/grant", post(auth::ep_grant_parental_consent));
+            }
+
+            if self.state.support_login_config().is_some() {
+                log::info!("Registering support login links");
+                router = router.route("/auth/support/login-link", post(auth::ep_create_login_link));
+            }
+
+            if let Some(mfa_config) = self.state.mfa_config() {
+                router = router
+                    .route("/auth/user/mfa", get(auth::ep_get_user_mfa))
+                    .route(
+                        "/auth/user/devices/trusted",
+                        get(auth::ep_get_trusted_devices).delete(auth::ep_delete_trusted_devices),
+                    )
+                    .route(
+                        "/auth/user/devices/trusted/:device_id",
+                        delete(auth::ep_delete_trusted_device),
+                    );
+
+                if mfa_config.email.enabled {
+                    log::info!("Registering email second factor");
+                    router = router
+                        .route("/auth/mfa/email/verify", post(auth::ep_verify_email_code))
+                        .route("/auth/mfa/email/resend", post(auth::ep_resend_email_code))
+                        .route(
+                            "/auth/user/mfa/email",
+                            put(auth::ep_enable_email_mfa).delete(auth::ep_disable_email_mfa),
+                        );
+                }
+            }
+
+            // the quota is checked after the bearer token is resolved
+            if self.state.api_quota_config().is_some() {
+                log::info!("Registering API quotas");
+                router = router.layer(middleware::from_fn_with_state(
+                    self.state.clone(),
+                    auth::enforce_api_quota,
+                ));
+            }
+            router = router.layer(middleware::from_fn_with_state(
+                self.state.clone(),
+                auth::resolve_bearer_token,
+            ));
+
+            let router = router.layer(tenant_resolver).with_state(self.state);
+            Router::new().nest("/t/:tenant", router.clone()).merge(router)
+        };
 
         (page_router, api_router)
     }
@@ -1,7 +1,11 @@
 use crate::{
-    auth::{auth_service_utils::UserCreateError, AuthError, AuthPage, AuthServiceState, AuthSession, ExternalUserInfo},
-    db::{ExternalLoginInfo, FindIdentity, IdentityError},
+    auth::{
+        auth_service_utils::UserCreateError, AuthError, AuthPage, AuthServiceState, AuthSession, ClientInfo,
+        ExternalUserInfo, MfaError, RiskDecision,
+    },
+    db::{AnalyticsEvent, ExternalLoginInfo, FeatureFlag, FindIdentity, Identity, IdentityError, UserChange},
 };
+use serde_json::json;
 use shine_service::service::APP_NAME;
 use url::Url;
 
@@ -18,6 +22,7 @@ impl AuthServiceState {
         assert!(auth_session.token_login.is_none());
 
         let external_login = ExternalLoginInfo {
+            tenant_id: auth_session.tenant().id().to_owned(),
             provider: provider.to_string(),
             provider_id: provider_id.to_string(),
         };
@@ -26,19 +31,72 @@ impl AuthServiceState {
         match self.identity_manager().link_user(user.user_id, &external_login).await {
             Ok(()) => {}
             Err(IdentityError::LinkProviderConflict) => {
-                return self.page_error(auth_session, AuthError::ProviderAlreadyUsed, error_url)
+                return self
+                    .page_link_conflict(auth_session, &external_login, target_url, error_url)
+                    .await
             }
             Err(err) => return self.page_internal_error(auth_session, err, error_url),
         };
 
         log::debug!("User {} linked to: {}", user.user_id, provider);
+        self.emit_flow_event(&auth_session, AnalyticsEvent::UserLinked, Some(user.user_id));
         self.page_redirect(auth_session, APP_NAME, target_url)
     }
 
+    /// Grant the bootstrap role to a newly registered user if it is matching the configuration.
+    /// Errors are not propagated to the login.
+    async fn bootstrap_roles(&self, identity: &Identity, external_login: &ExternalLoginInfo, email: Option<&str>) {
+        let config = match self.bootstrap_roles() {
+            Some(config) => config,
+            None => return,
+        };
+
+        let is_email_matching = email
+            .map(|email| config.emails.iter().any(|e| e.eq_ignore_ascii_case(email)))
+            .unwrap_or(false);
+        let is_provider_matching = config
+            .provider_ids
+            .iter()
+            .any(|p| p.provider == external_login.provider && p.provider_id == external_login.provider_id);
+        if !is_email_matching && !is_provider_matching {
+            return;
+        }
+
+        log::info!("Granting bootstrap role {} to {}", config.role, identity.user_id);
+        if let Err(err) = self.role_manager().grant_role(identity.user_id, &config.role).await {
+            log::error!("Failed to grant bootstrap role to {}: {err}", identity.user_id);
+            return;
+        }
+        if let Err(err) = self
+            .audit_log()
+            .record(
+                None,
+                "role.bootstrap",
+                Some(identity.user_id),
+                json!({
+                    "role": config.role,
+                    "tenantId": identity.tenant_id,
+                    "provider": external_login.provider,
+                }),
+            )
+            .await
+        {
+            log::error!("Failed to record bootstrap role of {}: {err}", identity.user_id);
+        }
+        if let Err(err) = self
+            .user_invalidation()
+            .invalidate(identity.user_id, UserChange::Roles)
+            .await
+        {
+            log::error!("Failed to publish the role change of {}: {err}", identity.user_id);
+        }
+    }
+
     pub(in crate::auth) async fn page_external_login(
         &self,
         mut auth_session: AuthSession,
         external_user_info: ExternalUserInfo,
+        client_info: &ClientInfo,
         target_url: Option<&Url>,
         error_url: Option<&Url>,
         create_token: bool,
@@ -47,6 +105,7 @@ impl AuthServiceState {
         assert!(auth_session.token_login.is_none());
 
         let external_login = ExternalLoginInfo {
+            tenant_id: auth_session.tenant().id().to_owned(),
             provider: external_user_info.provider.clone(),
             provider_id: external_user_info.provider_id.clone(),
         };
@@ -61,15 +120,36 @@ impl AuthServiceState {
             Ok(Some(identity)) => identity,
             // Create a new (linked) user
             Ok(None) => {
+                if !self.feature_flags().is_enabled(FeatureFlag::AllowRegistration).await {
+                    return self.page_error(auth_session, AuthError::RegistrationDisabled, error_url);
+                }
+                if self.check_ip_reputation(client_info).await != RiskDecision::Allow {
+                    return self.page_error(auth_session, AuthError::RegistrationRejected, error_url);
+                }
+                let require_email = self.feature_flags().is_enabled(FeatureFlag::RequireEmail).await
+                    || self
+                        .registration_policy(client_info)
+                        .map_or(false, |policy| policy.require_email);
+                if require_email && external_user_info.email.is_none() {
+                    return self.page_error(auth_session, AuthError::EmailRequired, error_url);
+                }
+
                 match self
                     .create_user_with_retry(
+                        &external_login.tenant_id,
                         external_user_info.name.as_deref(),
                         external_user_info.email.as_deref(),
                         Some(&external_login),
                     )
                     .await
                 {
-                    Ok(identity) => identity,
+                    Ok(identity) => {
+                        self.emit_flow_event(&auth_session, AnalyticsEvent::UserCreated, Some(identity.user_id));
+                        self.record_region(identity.user_id, client_info).await;
+                        self.bootstrap_roles(&identity, &external_login, external_user_info.email.as_deref())
+                            .await;
+                        identity
+                    }
                     Err(UserCreateError::IdentityError(IdentityError::LinkEmailConflict)) => {
                         return self.page_error(auth_session, AuthError::EmailAlreadyUsed, error_url)
                     }
@@ -79,9 +159,32 @@ impl AuthServiceState {
             Err(err) => return self.page_internal_error(auth_session, err, error_url),
         };
 
+        let risk = self.assess_login_risk(&identity, client_info).await;
+        if risk == RiskDecision::Block {
+            return self.page_error(auth_session, AuthError::LoginBlocked, error_url);
+        }
+        auth_session.last_provider = Some(external_login.provider.clone());
+
+        match self
+            .start_mfa(
+                &mut auth_session,
+                &identity,
+                create_token,
+                None,
+                target_url,
+                risk == RiskDecision::RequireMfa,
+            )
+            .await
+        {
+            Ok(Some(mfa_page_url)) => return self.page_redirect(auth_session, APP_NAME, Some(&mfa_page_url)),
+            Ok(None) => {}
+            Err(MfaError::Unavailable) => return self.page_error(auth_session, AuthError::LoginBlocked, error_url),
+            Err(err) => return self.page_internal_error(auth_session, err, error_url),
+        }
+
         // create a new token
         let token_login = if create_token {
-            match self.create_token_with_retry(identity.user_id).await {
+            match self.create_token_with_retry(identity, None, client_info).await {
                 Ok(token_login) => Some(token_login),
                 Err(err) => return self.page_internal_error(auth_session, err, error_url),
             }
@@ -90,11 +193,15 @@ impl AuthServiceState {
         };
 
         log::debug!("Identity created: {identity:#?}");
-        let user = match self.session_manager().create(&identity).await {
+        let user = match self.create_session(&identity, external_user_info.auth_context).await {
             Ok(user) => user,
-            Err(err) => return self.page_internal_error(auth_session, err, error_url),
+            Err(err) => return self.page_session_error(auth_session, err, error_url),
         };
 
+        self.check_login_device(auth_session.tenant(), &identity, &user, client_info)
+            .await;
+        self.restore_deleted_account(&identity).await;
+
         auth_session.token_login = token_login;
         auth_session.user = Some(user);
         self.page_redirect(auth_session, APP_NAME, target_url)
@@ -1,12 +1,18 @@
 use crate::{
-    auth::{auth_session::TokenLogin, AuthServiceState, AuthSession, TokenGeneratorError},
-    db::{ExternalLoginInfo, Identity, IdentityError, NameGeneratorError},
+    auth::{auth_session::TokenLogin, AuthServiceState, AuthSession, ClientInfo, Tenant, TokenGeneratorError},
+    db::{
+        DBSessionError, ExternalLoginInfo, Identity, IdentityError, NameGeneratorError, SessionAuthContext, TokenKind,
+        TokenMeta,
+    },
+    logging::{current_request_id, user_hash},
 };
 use axum::{
     http::StatusCode,
     response::{Html, IntoResponse, Response},
 };
-use shine_service::service::APP_NAME;
+use chrono::{Duration, Utc};
+use serde_json::json;
+use shine_service::service::{CurrentUser, APP_NAME};
 use std::fmt;
 use thiserror::Error as ThisError;
 use url::Url;
@@ -25,6 +31,7 @@ pub(in crate::auth) enum UserCreateError {
 impl AuthServiceState {
     pub(in crate::auth) async fn create_user_with_retry(
         &self,
+        tenant_id: &str,
         mut default_name: Option<&str>,
         email: Option<&str>,
         external_login: Option<&ExternalLoginInfo>,
@@ -38,18 +45,22 @@ impl AuthServiceState {
             }
             retry_count += 1;
 
-            let user_id = Uuid::new_v4();
+            let user_id = self.identity_manager().new_user_id();
+            // the name from the external provider is used only if it passes the name filter
             let user_name = match default_name.take() {
-                Some(name) => name.to_string(),
-                None => self.name_generator().generate_name().await?,
+                Some(name) if self.name_generator().validate_name(name).is_ok() => name.to_string(),
+                _ => self.name_generator().generate_name(Some(tenant_id)).await?,
             };
 
             match self
                 .identity_manager()
-                .create_user(user_id, &user_name, email, external_login)
+                .create_user(tenant_id, user_id, &user_name, email, external_login)
                 .await
             {
-                Ok(identity) => return Ok(identity),
+                Ok(identity) => {
+                    self.send_welcome_email(&identity, external_login);
+                    return Ok(identity);
+                }
                 Err(IdentityError::NameConflict) => continue,
                 Err(IdentityError::UserIdConflict) => continue,
                 Err(err) => return Err(UserCreateError::IdentityError(err)),
@@ -58,6 +69,232 @@ impl AuthServiceState {
     }
 }
 
+impl AuthServiceState {
+    /// Queue the welcome email for a new user, failing to send the email does not prevent the user creation.
+    fn send_welcome_email(&self, identity: &Identity, external_login: Option<&ExternalLoginInfo>) {
+        let config = self.welcome_email();
+        let email = match (config.enabled, &identity.email) {
+            (true, Some(email)) => email,
+            _ => return,
+        };
+
+        let mut context = tera::Context::new();
+        context.insert("app_name", APP_NAME);
+        context.insert("name", &identity.name);
+        context.insert("provider", &external_login.map(|login| &login.provider));
+        if let Err(err) = self.email_sender().send(email, config.template("welcome"), &context) {
+            log::error!("Failed to send welcome email to {}: {err}", identity.user_id);
+        }
+    }
+}
+
+impl AuthServiceState {
+    /// Create a new session for the identity with the authentication context reported by the provider, if any.
+    /// The sessions evicted to keep the limit of the active sessions are recorded in the audit log. The login to an
+    /// expired time-limited account is refused.
+    pub(in crate::auth) async fn create_session(
+        &self,
+        identity: &Identity,
+        auth_context: Option<SessionAuthContext>,
+    ) -> Result<CurrentUser, DBSessionError> {
+        if self.expiration_manager().is_expired(identity.user_id).await? {
+            tracing::info!(
+                user = %user_hash(identity.user_id),
+                outcome = "accountExpired",
+                "Login to an expired account is refused"
+            );
+            return Err(DBSessionError::AccountExpired);
+        }
+        let (user, evicted) = self.session_manager().create(identity, auth_context).await?;
+        if !evicted.is_empty() {
+            if let Err(err) = self
+                .audit_log()
+                .record(
+                    Some(identity.user_id),
+                    "session.evicted",
+                    Some(identity.user_id),
+                    json!({ "tenantId": identity.tenant_id, "sessions": evicted }),
+                )
+                .await
+            {
+                log::error!("Failed to record the evicted sessions of {}: {err}", identity.user_id);
+            }
+        }
+        Ok(user)
+    }
+
+    /// Find the identity of a login token. A revoked token is deleted and the use of a valid token is recorded.
+    /// The token of a user of another tenant is not accepted.
+    pub(in crate::auth) async fn find_login_token(
+        &self,
+        tenant: &Tenant,
+        user_id: Uuid,
+        token: &str,
+    ) -> Result<Option<Identity>, IdentityError> {
+        let (identity, token_info) = match self.identity_manager().find_token(token).await? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+
+        if identity.tenant_id != tenant.id() {
+            tracing::info!(
+                provider = "token",
+                user = %user_hash(user_id),
+                outcome = "tenantMismatch",
+                "Token of another tenant used"
+            );
+            return Ok(None);
+        }
+
+        if self
+            .token_revocation()
+            .is_revoked(token_info.token_id, identity.user_id, token_info.created_at)
+            .await?
+        {
+            tracing::info!(
+                provider = "token",
+                user = %user_hash(user_id),
+                outcome = "revokedToken",
+                "Revoked token used"
+            );
+            if let Err(err) = self.identity_manager().delete_token(user_id, token).await {
+                log::warn!("Failed to delete the revoked token of {user_id}: {err}");
+            }
+            return Ok(None);
+        }
+
+        if let Err(err) = self.identity_manager().touch_token(token).await {
+            log::warn!("Failed to record the use of the token of {user_id}: {err}");
+        }
+        Ok(Some(identity))
+    }
+
+    /// Cancel the pending deletion of the account on a login of the user, errors are not propagated to the login.
+    pub(in crate::auth) async fn restore_deleted_account(&self, identity: &Identity) {
+        match self.deletion_manager().restore(identity.user_id).await {
+            Ok(true) => {
+                tracing::info!(
+                    user = %user_hash(identity.user_id),
+                    outcome = "deletionCancelled",
+                    "Deletion has been cancelled by a login"
+                );
+                if let Err(err) = self
+                    .audit_log()
+                    .record(
+                        Some(identity.user_id),
+                        "identity.restore",
+                        Some(identity.user_id),
+                        json!({}),
+                    )
+                    .await
+                {
+                    log::error!("Failed to record the restore of {}: {err}", identity.user_id);
+                }
+            }
+            Ok(false) => {}
+            Err(err) => log::error!("Failed to restore the account of {}: {err}", identity.user_id),
+        }
+    }
+
+    /// Record the login with its device and send an alert email for a login from an unseen device or country.
+    /// The alert contains a link to revoke the new session, errors are not propagated to the login.
+    pub(in crate::auth) async fn check_login_device(
+        &self,
+        tenant: &Tenant,
+        identity: &Identity,
+        user: &CurrentUser,
+        client_info: &ClientInfo,
+    ) {
+        /// The time the revoke link of the alert email is valid.
+        const REVOKE_DURATION_DAYS: i64 = 7;
+
+        let check = match self
+            .device_manager()
+            .record_login(
+                identity.user_id,
+                &client_info.fingerprint(),
+                client_info.country.as_deref(),
+            )
+            .await
+        {
+            Ok(check) => check,
+            Err(err) => {
+                log::error!("Failed to record login device for {}: {err}", identity.user_id);
+                return;
+            }
+        };
+        if let Err(err) = self
+            .audit_log()
+            .record(
+                Some(identity.user_id),
+                "login",
+                Some(identity.user_id),
+                json!({
+                    "tenantId": tenant.id(),
+                    "ip": client_info.ip,
+                    "country": client_info.country,
+                    "userAgent": client_info.user_agent,
+                    "newDevice": check.is_new_device,
+                    "newCountry": check.is_new_country,
+                }),
+            )
+            .await
+        {
+            log::error!("Failed to record the login of {}: {err}", identity.user_id);
+        }
+
+        let config = self.login_alert_email();
+        let email = match (config.enabled, &identity.email) {
+            (true, Some(email)) if check.is_new_device || check.is_new_country => email,
+            _ => return,
+        };
+
+        let revoke_token = match self.token().generate_token() {
+            Ok(token) => token,
+            Err(err) => {
+                log::error!("Failed to create revoke token for {}: {err}", identity.user_id);
+                return;
+            }
+        };
+        if let Err(err) = self
+            .session_manager()
+            .create_revoke_token(
+                &revoke_token,
+                identity.user_id,
+                user.key,
+                Duration::days(REVOKE_DURATION_DAYS),
+            )
+            .await
+        {
+            log::error!("Failed to store revoke token for {}: {err}", identity.user_id);
+            return;
+        }
+        let mut revoke_url = tenant.api_url().clone();
+        revoke_url
+            .path_segments_mut()
+            .expect("Auth url shall be a base")
+            .pop_if_empty()
+            .push("revoke");
+        revoke_url.query_pairs_mut().append_pair("token", &revoke_token);
+
+        let mut context = tera::Context::new();
+        context.insert("app_name", APP_NAME);
+        context.insert("name", &identity.name);
+        context.insert("time", &Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string());
+        context.insert("device", &client_info.user_agent);
+        context.insert("country", &client_info.country);
+        context.insert("is_new_device", &check.is_new_device);
+        context.insert("is_new_country", &check.is_new_country);
+        context.insert("revoke_url", revoke_url.as_str());
+        if let Err(err) = self
+            .email_sender()
+            .send(email, config.template("login_alert"), &context)
+        {
+            log::error!("Failed to send login alert email to {}: {err}", identity.user_id);
+        }
+    }
+}
+
 #[derive(Debug, ThisError)]
 pub(in crate::auth) enum TokenCreateError {
     #[error("Retry limit reach for token creation")]
@@ -70,8 +307,25 @@ pub(in crate::auth) enum TokenCreateError {
 
 impl AuthServiceState {
     // Create a new login token for the given user.
-    pub(in crate::auth) async fn create_token_with_retry(&self, user_id: Uuid) -> Result<TokenLogin, TokenCreateError> {
+    pub(in crate::auth) async fn create_token_with_retry(
+        &self,
+        identity: &Identity,
+        name: Option<&str>,
+        client_info: &ClientInfo,
+    ) -> Result<TokenLogin, TokenCreateError> {
+        /// The maximum length of the token labels.
+        const MAX_NAME_LENGTH: usize = 64;
+
+        let name = name.map(|name| name.chars().take(MAX_NAME_LENGTH).collect::<String>());
+        let meta = TokenMeta {
+            kind: TokenKind::Persistent,
+            name: name.as_deref(),
+            creation_ip: client_info.ip,
+            user_agent: Some(client_info.user_agent.as_str()).filter(|user_agent| !user_agent.is_empty()),
+        };
+
         const MAX_RETRY_COUNT: usize = 10;
+        let user_id = identity.user_id;
         let mut retry_count = 0;
         loop {
             log::debug!("Creating new token for user {user_id}, retry: {retry_count:#?}");
@@ -83,15 +337,33 @@ impl AuthServiceState {
             let token = self.token().generate_token()?;
             match self
                 .identity_manager()
-                .create_token(user_id, &token, &self.token().max_duration())
+                .create_token(user_id, &token, self.token().expire_at(), &meta)
                 .await
             {
-                Ok(token) => {
+                Ok(token_info) => {
+                    if let Err(err) = self
+                        .audit_log()
+                        .record(
+                            Some(user_id),
+                            "token.create",
+                            Some(user_id),
+                            json!({
+                                "tokenId": token_info.token_id,
+                                "name": meta.name,
+                                "ip": meta.creation_ip,
+                                "userAgent": meta.user_agent,
+                            }),
+                        )
+                        .await
+                    {
+                        log::error!("Failed to record the token creation of {user_id}: {err}");
+                    }
                     return Ok(TokenLogin {
                         user_id,
-                        token: token.token,
-                        expires: token.expire_at,
-                    })
+                        tenant_id: identity.tenant_id.clone(),
+                        token,
+                        expires: token_info.expire_at,
+                    });
                 }
                 Err(IdentityError::TokenConflict) => continue,
                 Err(err) => return Err(TokenCreateError::IdentityError(err)),
@@ -127,6 +399,77 @@ pub(in crate::auth) enum AuthError {
     ProviderAlreadyUsed,
     #[error("Email has already been linked to another user already")]
     EmailAlreadyUsed,
+    #[error("Provider is not available")]
+    ProviderNotAvailable,
+    #[error("Session revoke link is invalid or has expired")]
+    InvalidRevokeToken,
+    #[error("Login link is invalid or has expired")]
+    InvalidLoginLink,
+    #[error("Email is not matching the account")]
+    EmailNotMatching,
+    #[error("Too many active sessions, logout from another device first")]
+    TooManySessions,
+    #[error("Provider is not linked")]
+    ProviderNotLinked,
+    #[error("The last login method cannot be removed")]
+    LastLoginMethod,
+    #[error("Missing user name")]
+    MissingUserName,
+    #[error("Login has been refused, please try again later")]
+    LoginBlocked,
+    #[error("Registration has been refused")]
+    RegistrationRejected,
+    #[error("Registration is disabled at the moment")]
+    RegistrationDisabled,
+    #[error("Login again to confirm the operation")]
+    ReauthRequired,
+    #[error("The account cannot be deleted at the moment, please contact the support")]
+    DeletionRefused,
+    #[error("Login request of the application is invalid")]
+    InvalidAppLogin,
+    #[error("Account has expired")]
+    AccountExpired,
+    #[error("Age is required for the registration")]
+    AgeRequired,
+    #[error("Email is required for the registration")]
+    EmailRequired,
+}
+
+impl AuthError {
+    /// A short name of the error without the details, ex. for the analytics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AuthError::LogoutRequired => "logoutRequired",
+            AuthError::LoginRequired => "loginRequired",
+            AuthError::MissingExternalLogin => "missingExternalLogin",
+            AuthError::MissingNonce => "missingNonce",
+            AuthError::InvalidCSRF => "invalidCsrf",
+            AuthError::FailedExternalUserInfo => "failedExternalUserInfo",
+            AuthError::TokenInvalid => "tokenInvalid",
+            AuthError::TokenExpired => "tokenExpired",
+            AuthError::SessionExpired => "sessionExpired",
+            AuthError::InternalServerError(_) => "internalServerError",
+            AuthError::ProviderAlreadyUsed => "providerAlreadyUsed",
+            AuthError::EmailAlreadyUsed => "emailAlreadyUsed",
+            AuthError::ProviderNotAvailable => "providerNotAvailable",
+            AuthError::InvalidRevokeToken => "invalidRevokeToken",
+            AuthError::InvalidLoginLink => "invalidLoginLink",
+            AuthError::EmailNotMatching => "emailNotMatching",
+            AuthError::TooManySessions => "tooManySessions",
+            AuthError::ProviderNotLinked => "providerNotLinked",
+            AuthError::LastLoginMethod => "lastLoginMethod",
+            AuthError::MissingUserName => "missingUserName",
+            AuthError::LoginBlocked => "loginBlocked",
+            AuthError::RegistrationRejected => "registrationRejected",
+            AuthError::RegistrationDisabled => "registrationDisabled",
+            AuthError::ReauthRequired => "reauthRequired",
+            AuthError::DeletionRefused => "deletionRefused",
+            AuthError::InvalidAppLogin => "invalidAppLogin",
+            AuthError::AccountExpired => "accountExpired",
+            AuthError::AgeRequired => "ageRequired",
+            AuthError::EmailRequired => "emailRequired",
+        }
+    }
 }
 
 pub(in crate::auth) struct AuthPage {
@@ -148,10 +491,13 @@ impl AuthServiceState {
         response: AuthError,
         target_url: Option<&Url>,
     ) -> AuthPage {
+        self.emit_flow_error(&auth_session, &response);
+
         let mut context = tera::Context::new();
-        context.insert("redirect_url", target_url.unwrap_or(self.home_url()));
+        context.insert("redirect_url", target_url.unwrap_or(auth_session.tenant().home_url()));
         //context.insert("response", &response);
         context.insert("detail", &response.to_string());
+        context.insert("request_id", &current_request_id());
         let html = self
             .tera()
             .render("ooops.html", &context)
@@ -164,17 +510,56 @@ impl AuthServiceState {
         }
     }
 
+    /// Show the error page of an unexpected error, the error is also reported with the provider of the flow.
     pub(in crate::auth) fn page_internal_error<E: fmt::Debug>(
         &self,
         auth_session: AuthSession,
         err: E,
         target_url: Option<&Url>,
     ) -> AuthPage {
-        self.page_error(
-            auth_session,
-            AuthError::InternalServerError(format!("{err:?}")),
-            target_url,
-        )
+        let detail = format!("{err:?}");
+        let provider = auth_session.flow.as_ref().map(|flow| flow.provider.as_str());
+        self.error_reporting()
+            .report("auth.page", &detail, &[("provider", provider.unwrap_or("-"))]);
+
+        self.page_error(auth_session, AuthError::InternalServerError(detail), target_url)
+    }
+
+    pub(in crate::auth) fn page_session_error(
+        &self,
+        auth_session: AuthSession,
+        err: DBSessionError,
+        target_url: Option<&Url>,
+    ) -> AuthPage {
+        match err {
+            DBSessionError::SessionLimitReached => {
+                self.page_error(auth_session, AuthError::TooManySessions, target_url)
+            }
+            DBSessionError::AccountExpired => self.page_account_expired(auth_session, target_url),
+            err => self.page_internal_error(auth_session, err, target_url),
+        }
+    }
+
+    fn page_account_expired(&self, auth_session: AuthSession, target_url: Option<&Url>) -> AuthPage {
+        self.emit_flow_error(&auth_session, &AuthError::AccountExpired);
+
+        let mut context = tera::Context::new();
+        context.insert("app_name", APP_NAME);
+        context.insert(
+            "redirect_url",
+            target_url.unwrap_or(auth_session.tenant().home_url()).as_str(),
+        );
+        context.insert("request_id", &current_request_id());
+        let html = self
+            .tera()
+            .render("account_expired.html", &context)
+            .expect("Failed to generate account_expired.html template");
+
+        AuthPage {
+            status: StatusCode::FORBIDDEN,
+            auth_session: Some(auth_session),
+            html,
+        }
     }
 
     pub(in crate::auth) fn page_redirect(
@@ -186,7 +571,10 @@ impl AuthServiceState {
         let mut context = tera::Context::new();
         context.insert("title", APP_NAME);
         context.insert("target", target);
-        context.insert("redirect_url", redirect_url.unwrap_or(self.home_url()).as_str());
+        context.insert(
+            "redirect_url",
+            redirect_url.unwrap_or(auth_session.tenant().home_url()).as_str(),
+        );
         let html = self
             .tera()
             .render("redirect.html", &context)
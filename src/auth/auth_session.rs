@@ -1,26 +1,30 @@
-use crate::auth::AuthSessionConfig;
+use crate::{
+    auth::{AuthFlow, AuthSessionConfig, Tenant},
+    db::{MfaMethod, SharedClock, DEFAULT_TENANT_ID},
+    logging::{RedactedToken, RedactedUser},
+};
 use async_trait::async_trait;
 use axum::{
     extract::FromRequestParts,
-    http::request::Parts,
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
     response::{IntoResponse, IntoResponseParts, Response, ResponseParts},
-    Extension, RequestPartsExt,
+    RequestPartsExt,
 };
 use axum_extra::extract::{
     cookie::{Cookie, Expiration, Key, SameSite},
-    SignedCookieJar,
+    CookieJar, SignedCookieJar,
 };
 use base64::{engine::general_purpose::STANDARD as B64, Engine};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use shine_service::service::CurrentUser;
-use std::{convert::Infallible, sync::Arc};
+use std::{convert::Infallible, fmt};
 use thiserror::Error as ThisError;
 use time::{Duration, OffsetDateTime};
 use url::Url;
 use uuid::Uuid;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub(in crate::auth) struct ExternalLogin {
     #[serde(rename = "pv")]
     pub pkce_code_verifier: String,
@@ -36,18 +40,83 @@ pub(in crate::auth) struct ExternalLogin {
     // indicates if login was made to link the account to the user of the given session
     #[serde(rename = "l")]
     pub linked_user: Option<CurrentUser>,
+    /// Id of the flow for the analytics events.
+    #[serde(rename = "f", default)]
+    pub flow_id: Option<Uuid>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub(in crate::auth) struct TokenLogin {
     #[serde(rename = "u")]
     pub user_id: Uuid,
+    /// The tenant of the user, the cookies issued before the tenant was stored belong to the default tenant.
+    #[serde(rename = "tn", default = "default_tenant_id")]
+    pub tenant_id: String,
     #[serde(rename = "t")]
     pub token: String,
     #[serde(rename = "e")]
     pub expires: DateTime<Utc>,
 }
 
+fn default_tenant_id() -> String {
+    DEFAULT_TENANT_ID.to_owned()
+}
+
+impl fmt::Debug for ExternalLogin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExternalLogin")
+            .field("pkce_code_verifier", &RedactedToken(&self.pkce_code_verifier))
+            .field("csrf_state", &RedactedToken(&self.csrf_state))
+            .field("nonce", &self.nonce.as_deref().map(RedactedToken))
+            .field("target_url", &self.target_url)
+            .field("error_url", &self.error_url)
+            .field("remember_me", &self.remember_me)
+            .field("linked_user", &self.linked_user.as_ref().map(RedactedUser))
+            .field("flow_id", &self.flow_id)
+            .finish()
+    }
+}
+
+impl fmt::Debug for TokenLogin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenLogin")
+            .field("user_id", &self.user_id)
+            .field("tenant_id", &self.tenant_id)
+            .field("token", &RedactedToken(&self.token))
+            .field("expires", &self.expires)
+            .finish()
+    }
+}
+
+/// A login with a completed first factor waiting for the second factor.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(in crate::auth) struct MfaPending {
+    #[serde(rename = "u")]
+    pub user_id: Uuid,
+    #[serde(rename = "tn", default = "default_tenant_id")]
+    pub tenant_id: String,
+    #[serde(rename = "m")]
+    pub methods: Vec<MfaMethod>,
+    #[serde(rename = "r")]
+    pub remember_me: bool,
+    #[serde(rename = "n")]
+    pub token_name: Option<String>,
+    #[serde(rename = "e")]
+    pub expires: DateTime<Utc>,
+}
+
+/// A browser trusted by the user to skip the second factor. The device can be revoked, thus it has to be
+/// checked in the database too.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(in crate::auth) struct TrustedDevice {
+    #[serde(rename = "u")]
+    pub user_id: Uuid,
+    #[serde(rename = "d")]
+    pub device_id: Uuid,
+    #[serde(rename = "e")]
+    pub expires: DateTime<Utc>,
+}
+
 #[derive(Debug, ThisError)]
 pub(in crate::auth) enum AuthSessionError {
     #[error("Missing or invalid domain for application home")]
@@ -68,17 +137,47 @@ struct CookieSettings {
     path: String,
 }
 
-/// Layer to configure auth related cookie.
+/// Settings of a cookie without a signature, it shall not hold anything sensitive.
+#[derive(Clone)]
+struct PlainCookieSettings {
+    name: String,
+    domain: String,
+    path: String,
+}
+
+/// The last provider is remembered for a year after the last login.
+const LAST_PROVIDER_DURATION_DAYS: i64 = 365;
+
+/// The cookie of the last provider is not signed, thus only a well-formed name of a provider is accepted.
+fn is_valid_provider_name(provider: &str) -> bool {
+    !provider.is_empty()
+        && provider.len() <= 64
+        && provider
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Configuration of the auth related cookies of a tenant.
 #[derive(Clone)]
 pub(in crate::auth) struct AuthSessionMeta {
     user: CookieSettings,
     external_login: CookieSettings,
     token_login: CookieSettings,
+    mfa_pending: CookieSettings,
+    trusted_device: Option<CookieSettings>,
+    last_provider: PlainCookieSettings,
+    clock: SharedClock,
 }
 
 impl AuthSessionMeta {
-    pub fn new(home_url: Url, auth_base: Url, config: &AuthSessionConfig) -> Result<Self, AuthSessionError> {
-        let cookie_name_suffix = config.cookie_name_suffix.as_deref().unwrap_or_default();
+    pub fn new(
+        home_url: &Url,
+        auth_base: &Url,
+        cookie_name_suffix: Option<&str>,
+        config: &AuthSessionConfig,
+        clock: SharedClock,
+    ) -> Result<Self, AuthSessionError> {
+        let cookie_name_suffix = cookie_name_suffix.unwrap_or_default();
         let home_domain = home_url.domain().ok_or(AuthSessionError::MissingHomeDomain)?;
         let auth_domain = auth_base.domain().ok_or(AuthSessionError::MissingDomain)?.to_string();
         let auth_path = auth_base.path().to_string();
@@ -120,52 +219,157 @@ impl AuthSessionMeta {
             CookieSettings {
                 name: format!("eid{}", cookie_name_suffix),
                 secret,
-                domain: auth_domain,
-                path: auth_path,
+                domain: auth_domain.clone(),
+                path: auth_path.clone(),
+            }
+        };
+
+        let mfa_pending = {
+            let key = B64
+                .decode(&config.mfa_pending_secret)
+                .map_err(|err| AuthSessionError::InvalidSecret(format!("{err}")))?;
+            let secret = Key::try_from(&key[..]).map_err(|err| AuthSessionError::InvalidSecret(format!("{err}")))?;
+            CookieSettings {
+                name: format!("mid{}", cookie_name_suffix),
+                secret,
+                domain: auth_domain.clone(),
+                path: auth_path.clone(),
             }
         };
 
+        // the api and the pages of the auth service are on different paths
+        let last_provider = PlainCookieSettings {
+            name: format!("lpr{}", cookie_name_suffix),
+            domain: auth_domain.clone(),
+            path: "/".into(),
+        };
+
+        let trusted_device = match &config.trusted_device_secret {
+            Some(trusted_device_secret) => {
+                let key = B64
+                    .decode(trusted_device_secret)
+                    .map_err(|err| AuthSessionError::InvalidSecret(format!("{err}")))?;
+                let secret =
+                    Key::try_from(&key[..]).map_err(|err| AuthSessionError::InvalidSecret(format!("{err}")))?;
+                Some(CookieSettings {
+                    name: format!("did{}", cookie_name_suffix),
+                    secret,
+                    domain: auth_domain,
+                    path: auth_path,
+                })
+            }
+            None => None,
+        };
+
         Ok(Self {
             user,
             external_login,
             token_login,
+            mfa_pending,
+            trusted_device,
+            last_provider,
+            clock,
         })
     }
 
-    pub fn into_layer(self) -> Extension<Arc<Self>> {
-        Extension(Arc::new(self))
+    /// Get the provider of the last login of the browser, it is only a hint for the login pages.
+    pub fn parse_last_provider(&self, headers: &HeaderMap) -> Option<String> {
+        CookieJar::from_headers(headers)
+            .get(&self.last_provider.name)
+            .map(|cookie| cookie.value().to_owned())
+            .filter(|provider| is_valid_provider_name(provider))
+    }
+
+    /// Get the user from the session cookie of the request. The session is not checked, see `SessionStore::find_session`.
+    pub fn parse_user(&self, headers: &HeaderMap) -> Option<CurrentUser> {
+        SignedCookieJar::from_headers(headers, self.user.secret.clone())
+            .get(&self.user.name)
+            .and_then(|session| serde_json::from_str::<CurrentUser>(session.value()).ok())
+    }
+
+    /// Parse the value of a session cookie of the tenant. If the signature is not matching, None is returned.
+    pub fn parse_user_cookie(&self, value: &str) -> Option<CurrentUser> {
+        let cookie = HeaderValue::from_str(&format!("{}={}", self.user.name, value)).ok()?;
+        let headers = HeaderMap::from_iter([(header::COOKIE, cookie)]);
+        SignedCookieJar::from_headers(&headers, self.user.secret.clone())
+            .get(&self.user.name)
+            .and_then(|session| serde_json::from_str::<CurrentUser>(session.value()).ok())
+    }
+
+    /// Create the value of a session cookie of the tenant, ex. to pass the session as a bearer token to a client
+    /// without a cookie jar. The value is accepted by the `parse_user_cookie`.
+    pub fn create_user_cookie(&self, user: &CurrentUser) -> String {
+        let raw_data = serde_json::to_string(user).expect("Failed to serialize user");
+        let response = SignedCookieJar::new(self.user.secret.clone())
+            .add(Cookie::new(self.user.name.clone(), raw_data))
+            .into_response();
+        // the signed (and encoded) value is available only from the header
+        response
+            .headers()
+            .get(header::SET_COOKIE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .and_then(|cookie| cookie.split_once('='))
+            .map(|(_, value)| value.to_owned())
+            .expect("Missing signed session cookie")
     }
 }
 
 /// Handle all auth related cookie as an atomic entity. During authorization flow this
 /// structure the consistency between the auth related cookie.
 pub(in crate::auth) struct AuthSession {
-    meta: Arc<AuthSessionMeta>,
+    tenant: Tenant,
     pub user: Option<CurrentUser>,
     pub external_login: Option<ExternalLogin>,
     pub token_login: Option<TokenLogin>,
+    pub mfa_pending: Option<MfaPending>,
+    pub trusted_device: Option<TrustedDevice>,
+    /// The provider of the last login, it is kept after the logout to nudge the returning users to the provider
+    /// of their account, thus it is not cleared by `clear`.
+    pub last_provider: Option<String>,
+    /// The flow of the analytics events, it is not stored in the cookies.
+    pub flow: Option<AuthFlow>,
 }
 
 impl AuthSession {
     fn new(
-        meta: Arc<AuthSessionMeta>,
+        tenant: Tenant,
         user: Option<CurrentUser>,
         external_login: Option<ExternalLogin>,
         token_login: Option<TokenLogin>,
+        mfa_pending: Option<MfaPending>,
+        trusted_device: Option<TrustedDevice>,
+        last_provider: Option<String>,
     ) -> Self {
         Self {
-            meta,
+            tenant,
             user,
             external_login,
             token_login,
+            mfa_pending,
+            trusted_device,
+            last_provider,
+            flow: None,
         }
     }
 
-    /// Clear all the components.
+    /// The tenant the session belongs to.
+    pub fn tenant(&self) -> &Tenant {
+        &self.tenant
+    }
+
+    /// Check if the browser can be trusted to skip the second factor.
+    pub fn can_trust_device(&self) -> bool {
+        self.tenant.session_meta().trusted_device.is_some()
+    }
+
+    /// Clear all the components, except the last provider that is only a hint.
     pub fn clear(&mut self) {
         self.user.take();
         self.external_login.take();
         self.token_login.take();
+        self.mfa_pending.take();
+        self.trusted_device.take();
     }
 }
 
@@ -174,40 +378,55 @@ impl<S> FromRequestParts<S> for AuthSession
 where
     S: Send + Sync,
 {
-    type Rejection = Infallible;
+    type Rejection = StatusCode;
 
     /// Extract component from the cookie header:
     /// - If a component is compromised, it is set to None
     /// - If there is no signature or it is not matching to the component, and empty result is returned        
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        let Extension(meta) = parts
-            .extract::<Extension<Arc<AuthSessionMeta>>>()
-            .await
-            .expect("Missing AuthSessionMeta extension");
-
-        let mut user = SignedCookieJar::from_headers(&parts.headers, meta.user.secret.clone())
-            .get(&meta.user.name)
-            .and_then(|session| serde_json::from_str::<CurrentUser>(session.value()).ok());
+        let tenant = parts.extract::<Tenant>().await?;
+        let meta = tenant.session_meta();
+
+        let mut user = meta.parse_user(&parts.headers);
         let mut external_login = SignedCookieJar::from_headers(&parts.headers, meta.external_login.secret.clone())
             .get(&meta.external_login.name)
             .and_then(|session| serde_json::from_str::<ExternalLogin>(session.value()).ok());
         let mut token_login = SignedCookieJar::from_headers(&parts.headers, meta.token_login.secret.clone())
             .get(&meta.token_login.name)
             .and_then(|session| serde_json::from_str::<TokenLogin>(session.value()).ok());
+        let mut mfa_pending = SignedCookieJar::from_headers(&parts.headers, meta.mfa_pending.secret.clone())
+            .get(&meta.mfa_pending.name)
+            .and_then(|session| serde_json::from_str::<MfaPending>(session.value()).ok());
+        let mut trusted_device = meta.trusted_device.as_ref().and_then(|trusted_device| {
+            SignedCookieJar::from_headers(&parts.headers, trusted_device.secret.clone())
+                .get(&trusted_device.name)
+                .and_then(|session| serde_json::from_str::<TrustedDevice>(session.value()).ok())
+        });
+        let last_provider = meta.parse_last_provider(&parts.headers);
 
         log::debug!(
-            "Auth sessions before validation:\n  user:{:#?}\n  external_login:{:#?}\n  token_login:{:#?}\n",
-            user,
+            "Auth sessions before validation:\n  user:{:#?}\n  external_login:{:#?}\n  token_login:{:#?}\n  mfa_pending:{:#?}\n",
+            user.as_ref().map(RedactedUser),
             external_login,
             token_login,
+            mfa_pending,
         );
 
         // validation:
         // - if token has expired, it is deleted (browser should do it but it's a client, can be a faulty browser)
+        // - if token or the pending second factor was issued for another tenant, it is deleted (the tenants may
+        //   share the cookie secrets and names)
         // - user of token is not matching the user of the session, session is deleted
         // - if linked_account of the external login is not matching the session, external login is deleted
+        // - if the pending second factor has expired or there is a user already, it is deleted
+        // - if the trusted device has expired, it is deleted
 
-        if token_login.as_ref().map(|t| t.expires < Utc::now()).unwrap_or(true) {
+        let now = meta.clock.now();
+        if token_login
+            .as_ref()
+            .map(|t| t.expires < now || t.tenant_id != tenant.id())
+            .unwrap_or(true)
+        {
             token_login = None;
         }
         if token_login.as_ref().map(|t| t.user_id) != user.as_ref().map(|u| u.user_id) {
@@ -221,15 +440,35 @@ where
         {
             external_login = None;
         }
+        if user.is_some()
+            || mfa_pending
+                .as_ref()
+                .map(|m| m.expires < now || m.tenant_id != tenant.id())
+                .unwrap_or(true)
+        {
+            mfa_pending = None;
+        }
+        if trusted_device.as_ref().map(|t| t.expires < now).unwrap_or(true) {
+            trusted_device = None;
+        }
 
         log::debug!(
-            "Auth sessions after validation:\n  user:{:#?}\n  external_login:{:#?}\n  token_login:{:#?}\n",
-            user,
+            "Auth sessions after validation:\n  user:{:#?}\n  external_login:{:#?}\n  token_login:{:#?}\n  mfa_pending:{:#?}\n",
+            user.as_ref().map(RedactedUser),
             external_login,
             token_login,
+            mfa_pending,
         );
 
-        Ok(Self::new(meta, user, external_login, token_login))
+        Ok(Self::new(
+            tenant,
+            user,
+            external_login,
+            token_login,
+            mfa_pending,
+            trusted_device,
+            last_provider,
+        ))
     }
 }
 
@@ -258,6 +497,25 @@ fn create_jar<T: Serialize, X: Into<Expiration>>(
     SignedCookieJar::new(settings.secret.clone()).add(cookie)
 }
 
+fn create_plain_jar(settings: &PlainCookieSettings, value: Option<String>, expiration: OffsetDateTime) -> CookieJar {
+    let mut cookie = if let Some(value) = value {
+        let mut cookie = Cookie::new(settings.name.clone(), value);
+        cookie.set_expires(expiration);
+        cookie
+    } else {
+        let mut cookie = Cookie::named(settings.name.to_string());
+        cookie.set_expires(OffsetDateTime::now_utc() - Duration::days(1));
+        cookie
+    };
+
+    cookie.set_secure(true);
+    cookie.set_domain(settings.domain.clone());
+    cookie.set_path(settings.path.clone());
+    cookie.set_http_only(true);
+    cookie.set_same_site(SameSite::Lax);
+    CookieJar::new().add(cookie)
+}
+
 impl IntoResponseParts for AuthSession {
     type Error = Infallible;
 
@@ -266,29 +524,55 @@ impl IntoResponseParts for AuthSession {
     /// - If there is no component, all the cookies are deleted (including signature).
     fn into_response_parts(self, res: ResponseParts) -> Result<ResponseParts, Self::Error> {
         let Self {
-            meta,
+            tenant,
             user,
             external_login,
             token_login,
+            mfa_pending,
+            trusted_device,
+            last_provider,
+            flow: _,
         } = self;
+        let meta = tenant.session_meta();
         log::debug!(
-            "Auth sessions set headers:\n  user:{:#?}\n  external_login:{:#?}\n  token_login:{:#?}",
-            user,
+            "Auth sessions set headers:\n  user:{:#?}\n  external_login:{:#?}\n  token_login:{:#?}\n  mfa_pending:{:#?}",
+            user.as_ref().map(RedactedUser),
             external_login,
             token_login,
+            mfa_pending,
         );
 
-        let token_expiration = {
-            let time = token_login.as_ref().map(|t| t.expires).unwrap_or(Utc::now());
-            let naive_time = time.naive_utc();
+        let to_expiration = |time: Option<DateTime<Utc>>| {
+            let naive_time = time.unwrap_or_else(|| meta.clock.now()).naive_utc();
             OffsetDateTime::from_unix_timestamp(naive_time.timestamp()).unwrap()
         };
+        let token_expiration = to_expiration(token_login.as_ref().map(|t| t.expires));
+        let trusted_device_expiration = to_expiration(trusted_device.as_ref().map(|t| t.expires));
 
         let user = create_jar(&meta.user, &user, Expiration::Session);
         let external_login = create_jar(&meta.external_login, &external_login, Expiration::Session);
         let token_login = create_jar(&meta.token_login, &token_login, token_expiration);
+        let mfa_pending = create_jar(&meta.mfa_pending, &mfa_pending, Expiration::Session);
+        let trusted_device = meta
+            .trusted_device
+            .as_ref()
+            .map(|settings| create_jar(settings, &trusted_device, trusted_device_expiration));
+        let last_provider = create_plain_jar(
+            &meta.last_provider,
+            last_provider,
+            to_expiration(None) + Duration::days(LAST_PROVIDER_DURATION_DAYS),
+        );
 
-        Ok((user, external_login, token_login).into_response_parts(res).unwrap())
+        Ok((
+            user,
+            external_login,
+            token_login,
+            mfa_pending,
+            trusted_device,
+            last_provider,
+        )
+            .into_response_parts(res)
+            .unwrap())
     }
 }
 
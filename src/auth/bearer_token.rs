@@ -0,0 +1,208 @@
+use crate::{
+    auth::{AuthServiceState, ServiceTokenClaims, Tenant, TokenPrincipal},
+    db::DBError,
+};
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts, State},
+    http::{header, request::Parts, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    RequestPartsExt,
+};
+use chrono::{TimeZone, Utc};
+use shine_service::service::CurrentUser;
+
+/// The prefix of the API tokens of the studios, it tells them apart from the other tokens.
+pub(in crate::auth) const STUDIO_TOKEN_PREFIX: &str = "studio_";
+
+/// The bearer token of a request, resolved by the `resolve_bearer_token` middleware.
+#[derive(Clone)]
+pub(in crate::auth) enum BearerToken {
+    /// The session of a user, the token is the value of the session cookie.
+    Session(CurrentUser),
+    /// An access token issued by the service token endpoint.
+    Service(ServiceTokenClaims),
+    /// An API token of a studio, the subject of the claims is the studio.
+    Studio(ServiceTokenClaims),
+}
+
+/// The user of an API request authenticated either by the session cookie or by the session given as a bearer
+/// token, thus the clients without a cookie jar (ex. native game clients, scripts) can also call the API.
+/// The session has to belong to the tenant of the request.
+pub(in crate::auth) struct ApiUser(pub CurrentUser);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ApiUser
+where
+    S: Send + Sync,
+    AuthServiceState: FromRef<S>,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(BearerToken::Session(user)) = parts.extensions.get::<BearerToken>() {
+            return Ok(Self(user.clone()));
+        }
+
+        let state = AuthServiceState::from_ref(state);
+        let tenant = parts.extract::<Tenant>().await.map_err(IntoResponse::into_response)?;
+        let user = tenant
+            .session_meta()
+            .parse_user(&parts.headers)
+            .ok_or_else(|| StatusCode::UNAUTHORIZED.into_response())?;
+        match state
+            .session_manager()
+            .find_session(tenant.id(), user.user_id, user.key)
+            .await
+        {
+            Ok(Some(user)) => Ok(Self(user)),
+            Ok(None) => Err(StatusCode::UNAUTHORIZED.into_response()),
+            Err(err) => Err((StatusCode::INTERNAL_SERVER_ERROR, format!("{err:?}")).into_response()),
+        }
+    }
+}
+
+/// The service identity of an API request authenticated by an access token of the service token endpoint.
+pub(in crate::auth) struct ApiService(pub ServiceTokenClaims);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ApiService
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match parts.extensions.get::<BearerToken>() {
+            Some(BearerToken::Service(claims)) => Ok(Self(claims.clone())),
+            _ => Err((
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer error=\"invalid_token\"")],
+            )
+                .into_response()),
+        }
+    }
+}
+
+impl AuthServiceState {
+    /// Resolve an access token issued by the service token endpoint, either a JWT or an opaque token. None is
+    /// returned if the token is unknown, has expired, has been revoked or it was issued for another tenant.
+    pub async fn resolve_service_token(
+        &self,
+        tenant: &Tenant,
+        token: &str,
+    ) -> Result<Option<ServiceTokenClaims>, DBError> {
+        let signer = match self.service_token_signer() {
+            Some(signer) => signer,
+            None => return Ok(None),
+        };
+
+        let now = self.clock().now();
+        // the tokens of both formats are accepted, thus changing the format does not invalidate the issued tokens
+        let claims = if token.contains('.') {
+            signer.verify(token, now)
+        } else {
+            self.opaque_token_store()
+                .find(token)
+                .await?
+                .and_then(|claims| serde_json::from_str::<ServiceTokenClaims>(&claims).ok())
+                .filter(|claims| claims.exp > now.timestamp())
+        };
+        let claims = match claims.filter(|claims| claims.tenant == tenant.id()) {
+            Some(claims) => claims,
+            None => return Ok(None),
+        };
+
+        // the tokens are revoked one by one (by the jti) or all the tokens of the user issued before a time
+        let issued_at = match Utc.timestamp_opt(claims.iat, 0).single() {
+            Some(issued_at) => issued_at,
+            None => return Ok(None),
+        };
+        if self
+            .token_revocation()
+            .is_revoked(claims.jti, claims.sub, issued_at)
+            .await?
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(claims))
+    }
+
+    /// Resolve an API token of a studio. None is returned if the token is unknown, has expired (or it has been
+    /// revoked) or the studio belongs to another tenant.
+    pub async fn resolve_studio_token(
+        &self,
+        tenant: &Tenant,
+        token: &str,
+    ) -> Result<Option<ServiceTokenClaims>, DBError> {
+        let now = self.clock().now();
+        let token = self
+            .studio_manager()
+            .find_token(token)
+            .await?
+            .filter(|token| token.expire_at > now && token.tenant_id == tenant.id());
+        Ok(token.map(|token| ServiceTokenClaims {
+            iss: tenant.api_url().to_string(),
+            sub: token.studio_id,
+            principal: Some(TokenPrincipal::Studio),
+            aud: None,
+            tenant: token.tenant_id,
+            scope: token.scopes.join(" "),
+            act: None,
+            iat: token.created_at.timestamp(),
+            exp: token.expire_at.timestamp(),
+            jti: token.token_id,
+        }))
+    }
+}
+
+/// Middleware resolving the `Authorization: Bearer` token of the API requests, the token is available as the
+/// `BearerToken` extension. The requests with an invalid token are rejected, the requests without a bearer token
+/// are passed through.
+pub(in crate::auth) async fn resolve_bearer_token<B>(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let token = match request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        Some(token) => token.trim().to_owned(),
+        None => return next.run(request).await,
+    };
+
+    let resolved = match tenant.session_meta().parse_user_cookie(&token) {
+        Some(user) => state
+            .session_manager()
+            .find_session(tenant.id(), user.user_id, user.key)
+            .await
+            .map(|user| user.map(BearerToken::Session)),
+        None if token.starts_with(STUDIO_TOKEN_PREFIX) => state
+            .resolve_studio_token(&tenant, &token)
+            .await
+            .map(|claims| claims.map(BearerToken::Studio)),
+        None => state
+            .resolve_service_token(&tenant, &token)
+            .await
+            .map(|claims| claims.map(BearerToken::Service)),
+    };
+
+    match resolved {
+        Ok(Some(bearer_token)) => {
+            request.extensions_mut().insert(bearer_token);
+            next.run(request).await
+        }
+        Ok(None) => (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer error=\"invalid_token\"")],
+        )
+            .into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{err:?}")).into_response(),
+    }
+}
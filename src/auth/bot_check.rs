@@ -0,0 +1,92 @@
+use crate::auth::AuthServiceState;
+use axum::http::{header, HeaderMap};
+use serde::{Deserialize, Serialize};
+
+/// Cheap checks of the obvious bots on the registration like endpoints, performed before the database or the email
+/// sender is used.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BotCheckConfig {
+    /// Reject the requests with a filled `website` field. The forms shall render it hidden, thus only the bots fill it.
+    #[serde(default = "BotCheckConfig::default_honeypot")]
+    pub honeypot: bool,
+    /// The minimum time in milliseconds between the rendering and the submission of the form. When given, the
+    /// `formTime` field with the unix time of the rendering in milliseconds is required.
+    pub min_form_time: Option<u64>,
+    /// Reject the requests without the `User-Agent` or `Accept-Language` headers of the browsers.
+    #[serde(default = "BotCheckConfig::default_header_heuristics")]
+    pub header_heuristics: bool,
+    /// Reject the requests with a user agent containing any of these (case insensitive).
+    #[serde(default = "BotCheckConfig::default_blocked_user_agents")]
+    pub blocked_user_agents: Vec<String>,
+}
+
+impl BotCheckConfig {
+    fn default_honeypot() -> bool {
+        true
+    }
+
+    fn default_header_heuristics() -> bool {
+        true
+    }
+
+    fn default_blocked_user_agents() -> Vec<String> {
+        [
+            "curl",
+            "wget",
+            "python-requests",
+            "go-http-client",
+            "headlesschrome",
+            "phantomjs",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect()
+    }
+}
+
+/// The bot trap fields of a form.
+pub(in crate::auth) struct BotTrap<'a> {
+    pub honeypot: Option<&'a str>,
+    pub form_time: Option<i64>,
+}
+
+impl AuthServiceState {
+    /// Check the signs of a bot. When the request is rejected, the reason is returned.
+    pub(in crate::auth) fn detect_bot(&self, headers: &HeaderMap, trap: &BotTrap<'_>) -> Option<&'static str> {
+        let config = self.bot_check_config()?;
+
+        if config.honeypot && trap.honeypot.map(|value| !value.is_empty()).unwrap_or(false) {
+            return Some("honeypot");
+        }
+
+        if let Some(min_form_time) = config.min_form_time {
+            let now = self.clock().now().timestamp_millis();
+            match trap.form_time {
+                Some(form_time) if now - form_time >= min_form_time as i64 => {}
+                Some(_) => return Some("formTime"),
+                None => return Some("missingFormTime"),
+            }
+        }
+
+        if config.header_heuristics {
+            let user_agent = headers
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_lowercase();
+            if user_agent.is_empty() || !headers.contains_key(header::ACCEPT_LANGUAGE) {
+                return Some("headers");
+            }
+            if config
+                .blocked_user_agents
+                .iter()
+                .any(|blocked| user_agent.contains(&blocked.to_lowercase()))
+            {
+                return Some("userAgent");
+            }
+        }
+
+        None
+    }
+}
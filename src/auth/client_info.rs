@@ -0,0 +1,86 @@
+use crate::auth::AuthServiceState;
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header, request::Parts},
+};
+use ring::digest;
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
+
+/// Information about the client device of a request used to detect logins from unseen devices and locations.
+pub(in crate::auth) struct ClientInfo {
+    pub user_agent: String,
+    /// The country of the client as reported by the (geo aware) reverse proxy.
+    pub country: Option<String>,
+    /// The address of the client as reported by a trusted reverse proxy or the address of the peer.
+    pub ip: Option<IpAddr>,
+    /// The autonomous system of the client as reported by the reverse proxy.
+    pub asn: Option<u32>,
+    /// The region of the client, see `RegionConfig`.
+    pub region: Option<String>,
+}
+
+impl ClientInfo {
+    /// Get a stable identifier of the device.
+    pub fn fingerprint(&self) -> String {
+        let hash = digest::digest(&digest::SHA256, self.user_agent.as_bytes());
+        hex::encode(&hash.as_ref()[..16])
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AuthServiceState> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AuthServiceState) -> Result<Self, Self::Rejection> {
+        let header_str = |name: &str| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_owned())
+                .filter(|value| !value.is_empty())
+        };
+
+        let user_agent = header_str(header::USER_AGENT.as_str()).unwrap_or_default();
+        let country = state
+            .country_header()
+            .and_then(header_str)
+            .map(|country| country.to_uppercase());
+        // the header is trusted only from the proxies of the allowlist, otherwise any client could forge its address
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(peer)| peer.ip());
+        let ip = match state.client_ip_header() {
+            Some(client_ip_header) => peer.and_then(|peer| {
+                state
+                    .ip_allowlist()
+                    .forwarded_client_ip(peer, &parts.headers, client_ip_header)
+            }),
+            None => peer,
+        };
+
+        let asn = state
+            .login_risk_config()
+            .and_then(|config| config.asn_header.as_deref())
+            .and_then(header_str)
+            .and_then(|asn| asn.trim_start_matches("AS").parse().ok());
+
+        let region = state.region_config().and_then(|config| {
+            let header_region = config.header.as_deref().and_then(header_str);
+            config.resolve(header_region.as_deref(), country.as_deref())
+        });
+
+        Ok(Self {
+            user_agent,
+            country,
+            ip,
+            asn,
+            region,
+        })
+    }
+}
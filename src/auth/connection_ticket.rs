@@ -0,0 +1,92 @@
+use crate::auth::AuthBuildError;
+use base64::{
+    engine::general_purpose::{STANDARD as B64, URL_SAFE_NO_PAD as B64URL},
+    Engine,
+};
+use chrono::{DateTime, Duration, Utc};
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionTicketConfig {
+    /// Ed25519 key in PKCS#8 format, base64 encoded.
+    pub signing_key: String,
+    /// Validity of the tickets in seconds.
+    #[serde(default = "ConnectionTicketConfig::default_duration")]
+    pub duration: u64,
+}
+
+impl ConnectionTicketConfig {
+    fn default_duration() -> u64 {
+        30
+    }
+}
+
+/// A ticket to open a realtime connection (ex. WebSocket, UDP session) as the user.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct ConnectionTicket {
+    /// Unique id of the ticket, a ticket shall be accepted only once.
+    pub ticket_id: Uuid,
+    pub tenant_id: String,
+    pub user_id: Uuid,
+    pub name: String,
+    pub expire_at: DateTime<Utc>,
+}
+
+/// Sign the connection tickets. The tickets have the same `{payload}.{signature}` format as the user context,
+/// thus the realtime servers can verify them offline using the public key.
+pub(in crate::auth) struct ConnectionTicketSigner {
+    key_pair: Ed25519KeyPair,
+    duration: Duration,
+}
+
+impl ConnectionTicketSigner {
+    pub fn new(config: &ConnectionTicketConfig) -> Result<Self, AuthBuildError> {
+        let key = B64
+            .decode(&config.signing_key)
+            .map_err(|err| AuthBuildError::ConnectionTicket(format!("{err}")))?;
+        let key_pair =
+            Ed25519KeyPair::from_pkcs8(&key).map_err(|err| AuthBuildError::ConnectionTicket(format!("{err}")))?;
+
+        Ok(Self {
+            key_pair,
+            duration: Duration::seconds(config.duration as i64),
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The public key to verify the signature, url-safe base64 encoded.
+    pub fn public_key(&self) -> String {
+        B64URL.encode(self.key_pair.public_key().as_ref())
+    }
+
+    pub fn sign(&self, tenant_id: &str, user_id: Uuid, name: &str, now: DateTime<Utc>) -> String {
+        let ticket = ConnectionTicket {
+            ticket_id: Uuid::new_v4(),
+            tenant_id: tenant_id.to_owned(),
+            user_id,
+            name: name.to_owned(),
+            expire_at: now + self.duration,
+        };
+        let payload = B64URL.encode(serde_json::to_vec(&ticket).expect("Failed to serialize connection ticket"));
+        let signature = B64URL.encode(self.key_pair.sign(payload.as_bytes()).as_ref());
+        format!("{payload}.{signature}")
+    }
+
+    /// Verify the signature and the expiration of a ticket, None is returned for an invalid ticket.
+    pub fn verify(&self, ticket: &str, now: DateTime<Utc>) -> Option<ConnectionTicket> {
+        let (payload, signature) = ticket.split_once('.')?;
+        let signature = B64URL.decode(signature).ok()?;
+        UnparsedPublicKey::new(&ED25519, self.key_pair.public_key().as_ref())
+            .verify(payload.as_bytes(), &signature)
+            .ok()?;
+        let ticket: ConnectionTicket = serde_json::from_slice(&B64URL.decode(payload).ok()?).ok()?;
+        (ticket.expire_at > now).then_some(ticket)
+    }
+}
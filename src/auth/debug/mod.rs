@@ -0,0 +1,5 @@
+mod page_debug_login;
+pub(in crate::auth) use self::page_debug_login::*;
+
+#[cfg(test)]
+mod test_debug_flows;
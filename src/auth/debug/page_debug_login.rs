@@ -0,0 +1,53 @@
+use crate::auth::{AuthError, AuthPage, AuthServiceState, AuthSession, ClientInfo, ExternalUserInfo};
+use axum::extract::{Query, State};
+use serde::Deserialize;
+use url::Url;
+
+/// The name of the provider of the debug logins, the users are linked to it by their name.
+pub(in crate::auth) const DEBUG_PROVIDER: &str = "debug";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct RequestParams {
+    name: String,
+    redirect_url: Option<Url>,
+    error_url: Option<Url>,
+    remember_me: Option<bool>,
+}
+
+/// Login or register a user by the name without any external provider. It is available only for the local
+/// development in the debug builds.
+pub(in crate::auth) async fn page_debug_login(
+    State(state): State<AuthServiceState>,
+    Query(query): Query<RequestParams>,
+    client_info: ClientInfo,
+    auth_session: AuthSession,
+) -> AuthPage {
+    if auth_session.user.is_some() || auth_session.token_login.is_some() {
+        return state.page_error(auth_session, AuthError::LogoutRequired, query.error_url.as_ref());
+    }
+
+    let name = query.name.trim();
+    if name.is_empty() {
+        return state.page_error(auth_session, AuthError::MissingUserName, query.error_url.as_ref());
+    }
+
+    log::warn!("Debug login of {name}");
+    let external_user_info = ExternalUserInfo {
+        provider: DEBUG_PROVIDER.to_owned(),
+        provider_id: name.to_owned(),
+        name: Some(name.to_owned()),
+        email: None,
+        auth_context: None,
+    };
+    state
+        .page_external_login(
+            auth_session,
+            external_user_info,
+            &client_info,
+            query.redirect_url.as_ref(),
+            query.error_url.as_ref(),
+            query.remember_me.unwrap_or(false),
+        )
+        .await
+}
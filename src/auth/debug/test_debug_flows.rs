@@ -0,0 +1,33 @@
+use crate::test_support::{TestApp, TestClient};
+use axum::http::StatusCode;
+use serde_json::json;
+
+#[tokio::test]
+async fn debug_login() {
+    let app = TestApp::with_config(|config| {
+        config["auth"]["debugLogin"] = json!(true);
+    })
+    .await;
+    let mut client = TestClient::new(&app.router);
+
+    log::info!("Register a new user by the name...");
+    let response = client.get("/auth/debug/login?name=Alice").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(client.cookie("sid").is_some());
+    let response = client.get("/api/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let user_id = response.json()["userId"].clone();
+
+    log::info!("The provider is remembered after the logout...");
+    client.get("/auth/logout?scope=session").await;
+    assert!(client.cookie("sid").is_none());
+    assert_eq!(client.cookie("lpr"), Some("debug"));
+
+    log::info!("Login again with the same name...");
+    let response = client.get("/auth/debug/login?name=Alice").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = client.get("/api/auth/userinfo").await;
+    assert_eq!(response.json()["userId"], user_id);
+
+    app.cleanup().await;
+}
@@ -0,0 +1,122 @@
+use crate::{
+    auth::{AdminRole, AuthServiceState, RequireRole},
+    db::{AbuseCategory, AbuseFlag, AbuseSeverity, DBError, FindIdentity, IdentityError},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+const MAX_REPORTER_LENGTH: usize = 64;
+const MAX_EVIDENCE_LENGTH: usize = 256;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum AbuseFlagError {
+    #[error("User ({0}) not found")]
+    UserNotFound(Uuid),
+    #[error("Invalid reporter or evidence")]
+    InvalidRequest,
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for AbuseFlagError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            AbuseFlagError::UserNotFound(_) => StatusCode::NOT_FOUND,
+            AbuseFlagError::InvalidRequest => StatusCode::BAD_REQUEST,
+            AbuseFlagError::IdentityError(_) | AbuseFlagError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct ReportAbuse {
+    user_id: Uuid,
+    category: AbuseCategory,
+    severity: AbuseSeverity,
+    /// Reference to the evidence kept by the reporter.
+    evidence: Option<String>,
+    /// The name of the reporting service.
+    reporter: String,
+}
+
+/// Attach an abuse flag to a user, ex. by the chat moderation or the anti-cheat. This endpoint is available only
+/// from the allowed networks.
+pub(in crate::auth) async fn ep_report_abuse(
+    State(state): State<AuthServiceState>,
+    Json(request): Json<ReportAbuse>,
+) -> Result<(StatusCode, Json<AbuseFlag>), AbuseFlagError> {
+    if request.reporter.is_empty()
+        || request.reporter.len() > MAX_REPORTER_LENGTH
+        || request
+            .evidence
+            .as_ref()
+            .map_or(false, |evidence| evidence.len() > MAX_EVIDENCE_LENGTH)
+    {
+        return Err(AbuseFlagError::InvalidRequest);
+    }
+    if state
+        .identity_manager()
+        .find(FindIdentity::UserId(request.user_id))
+        .await?
+        .is_none()
+    {
+        return Err(AbuseFlagError::UserNotFound(request.user_id));
+    }
+
+    let flag = state
+        .abuse_flag_manager()
+        .add_flag(
+            request.user_id,
+            request.category,
+            request.severity,
+            request.evidence.as_deref(),
+            &request.reporter,
+        )
+        .await?;
+    state
+        .audit_log()
+        .record(
+            None,
+            "abuse_flag.report",
+            Some(flag.user_id),
+            json!({
+                "flagId": flag.flag_id,
+                "category": flag.category,
+                "severity": flag.severity,
+                "reporter": flag.reporter,
+            }),
+        )
+        .await?;
+
+    log::info!(
+        "Abuse flag ({:?}, {:?}) reported on {} by {}",
+        flag.category,
+        flag.severity,
+        flag.user_id,
+        flag.reporter
+    );
+    Ok((StatusCode::CREATED, Json(flag)))
+}
+
+/// List the abuse flags of a user, the latest first. Requires the admin role.
+pub(in crate::auth) async fn ep_list_abuse_flags(
+    State(state): State<AuthServiceState>,
+    _admin: RequireRole<AdminRole>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<Vec<AbuseFlag>>, AbuseFlagError> {
+    let flags = state.abuse_flag_manager().list_flags(user_id).await?;
+    Ok(Json(flags))
+}
@@ -0,0 +1,139 @@
+use crate::{
+    auth::{AdminRole, ApiUser, AuthServiceState, ParentalConsentError, RequireRole, PENDING_CONSENT_RESTRICTION},
+    db::{AgeBracket, DBError, FindIdentity, IdentityError, UserChange},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum AgeError {
+    #[error("User ({0}) not found")]
+    UserNotFound(Uuid),
+    #[error("Age gate is not configured")]
+    NotConfigured,
+    #[error("Invalid age")]
+    InvalidAge,
+    #[error("Age has already been declared")]
+    AlreadyDeclared,
+    #[error(transparent)]
+    ParentalConsent(#[from] ParentalConsentError),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for AgeError {
+    fn into_response(self) -> Response {
+        if let AgeError::ParentalConsent(err) = self {
+            return err.into_response();
+        }
+        let status_code = match &self {
+            AgeError::UserNotFound(_) | AgeError::NotConfigured => StatusCode::NOT_FOUND,
+            AgeError::InvalidAge => StatusCode::BAD_REQUEST,
+            AgeError::AlreadyDeclared => StatusCode::CONFLICT,
+            AgeError::ParentalConsent(_) | AgeError::IdentityError(_) | AgeError::DBError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct DeclareAgeRequest {
+    birth_date: Option<NaiveDate>,
+    age_bracket: Option<AgeBracket>,
+    /// Email of a parent to consent to the account of a minor.
+    parent_email: Option<String>,
+}
+
+/// Declare the age of the current user, ex. after a registration through an external provider. The age can be
+/// declared only once, it can be changed only by the verification of the adult age. The minor accounts are
+/// restricted until a parent consents, if it is required by the deployment.
+pub(in crate::auth) async fn ep_declare_age(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+    Json(request): Json<DeclareAgeRequest>,
+) -> Result<StatusCode, AgeError> {
+    let age_gate = state.age_gate_config().ok_or(AgeError::NotConfigured)?;
+    let today = state.clock().now().date_naive();
+    if !age_gate.is_valid_declaration(request.birth_date, request.age_bracket, today) {
+        return Err(AgeError::InvalidAge);
+    }
+
+    if !state
+        .age_manager()
+        .declare(user.user_id, request.birth_date, request.age_bracket)
+        .await?
+    {
+        return Err(AgeError::AlreadyDeclared);
+    }
+    if let Err(err) = state
+        .user_invalidation()
+        .invalidate(user.user_id, UserChange::Age)
+        .await
+    {
+        log::error!("Failed to publish the age change of {}: {err}", user.user_id);
+    }
+
+    if age_gate.is_declared_minor(request.birth_date, request.age_bracket, today) == Some(true) {
+        state
+            .require_parental_consent(user.user_id, &user.name, request.parent_email.as_deref())
+            .await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Mark a user as a verified adult, ex. after an identity check by the support. A pending parental consent is no
+/// longer required. Requires the admin role.
+pub(in crate::auth) async fn ep_verify_adult(
+    State(state): State<AuthServiceState>,
+    admin: RequireRole<AdminRole>,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, AgeError> {
+    if state
+        .identity_manager()
+        .find(FindIdentity::UserId(user_id))
+        .await?
+        .is_none()
+    {
+        return Err(AgeError::UserNotFound(user_id));
+    }
+
+    state.age_manager().verify_adult(user_id).await?;
+    state
+        .audit_log()
+        .record(Some(admin.user_id), "age.verify_adult", Some(user_id), json!({}))
+        .await?;
+    if let Err(err) = state.user_invalidation().invalidate(user_id, UserChange::Age).await {
+        log::error!("Failed to publish the age change of {user_id}: {err}");
+    }
+    if state
+        .restriction_manager()
+        .remove_restriction(user_id, PENDING_CONSENT_RESTRICTION)
+        .await?
+    {
+        if let Err(err) = state
+            .user_invalidation()
+            .invalidate(user_id, UserChange::Restrictions)
+            .await
+        {
+            log::error!("Failed to publish the restrictions of {user_id}: {err}");
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
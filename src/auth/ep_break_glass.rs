@@ -0,0 +1,160 @@
+use crate::{
+    auth::{AuthServiceState, AuthSession, ClientInfo},
+    db::{DBError, DBSessionError, FindIdentity, IdentityError},
+};
+use argon2::{
+    password_hash::{PasswordHash, PasswordVerifier},
+    Argon2,
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use ring::digest;
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error as ThisError;
+use tokio::task;
+
+/// Maximum number of attempts of a client address in the rate window.
+const MAX_ATTEMPTS: u32 = 5;
+/// The rate window in minutes.
+const RATE_WINDOW_MINUTES: i64 = 15;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum BreakGlassError {
+    #[error("Emergency access is not enabled")]
+    Disabled,
+    #[error("Logout required")]
+    LogoutRequired,
+    #[error("Unknown client address")]
+    UnknownClient,
+    #[error("Too many attempts")]
+    TooManyAttempts,
+    #[error("Invalid credential")]
+    InvalidCredential,
+    #[error("Credential has already been used, it has to be rotated")]
+    CredentialUsed,
+    #[error("Emergency user not found")]
+    UserNotFound,
+    #[error("Failed to verify credential: {0}")]
+    Hash(String),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    SessionError(#[from] DBSessionError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for BreakGlassError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            BreakGlassError::Disabled => StatusCode::NOT_FOUND,
+            BreakGlassError::LogoutRequired => StatusCode::CONFLICT,
+            BreakGlassError::UnknownClient => StatusCode::FORBIDDEN,
+            BreakGlassError::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
+            BreakGlassError::InvalidCredential | BreakGlassError::CredentialUsed => StatusCode::UNAUTHORIZED,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct BreakGlassLogin {
+    credential: String,
+}
+
+/// Login with the emergency credential. The session is short, it has the configured role and every attempt
+/// is recorded in the audit log. The credential can be used only once.
+pub(in crate::auth) async fn ep_break_glass(
+    State(state): State<AuthServiceState>,
+    client_info: ClientInfo,
+    mut auth_session: AuthSession,
+    Json(request): Json<BreakGlassLogin>,
+) -> Result<(AuthSession, StatusCode), BreakGlassError> {
+    let config = state.break_glass_config().ok_or(BreakGlassError::Disabled)?;
+    if auth_session.user.is_some() {
+        return Err(BreakGlassError::LogoutRequired);
+    }
+
+    // the attempts are limited by the client, thus a client guessing the credential cannot lock out the admins,
+    // but the clients without an address would share (and exhaust) a single limit, they are rejected instead
+    let ip = client_info.ip.ok_or(BreakGlassError::UnknownClient)?;
+    let rate_key = format!("break-glass:{ip}");
+    if !state
+        .rate_limiter()
+        .check(&rate_key, MAX_ATTEMPTS, chrono::Duration::minutes(RATE_WINDOW_MINUTES))
+        .await?
+    {
+        return Err(BreakGlassError::TooManyAttempts);
+    }
+
+    let credential_id = hex::encode(digest::digest(&digest::SHA256, config.credential_hash.as_bytes()));
+    let audit_details = json!({
+        "credentialId": credential_id,
+        "ip": ip,
+        "userAgent": client_info.user_agent,
+    });
+
+    let credential_hash = config.credential_hash.clone();
+    let is_valid = task::spawn_blocking(move || -> Result<bool, BreakGlassError> {
+        let hash = PasswordHash::new(&credential_hash).map_err(|err| BreakGlassError::Hash(format!("{err}")))?;
+        Ok(Argon2::default()
+            .verify_password(request.credential.as_bytes(), &hash)
+            .is_ok())
+    })
+    .await
+    .map_err(|err| BreakGlassError::Hash(format!("{err}")))??;
+
+    if !is_valid {
+        state
+            .audit_log()
+            .record(None, "break_glass.failed", Some(config.user_id), audit_details)
+            .await?;
+        return Err(BreakGlassError::InvalidCredential);
+    }
+    // the state of the credential is revealed only to the holders of the credential
+    if state.break_glass_store().is_used(&credential_id).await? {
+        return Err(BreakGlassError::CredentialUsed);
+    }
+    if !state.break_glass_store().mark_used(&credential_id).await? {
+        state
+            .audit_log()
+            .record(None, "break_glass.reused", Some(config.user_id), audit_details)
+            .await?;
+        return Err(BreakGlassError::CredentialUsed);
+    }
+
+    // the login is not performed if it cannot be audited
+    state
+        .audit_log()
+        .record(
+            Some(config.user_id),
+            "break_glass.login",
+            Some(config.user_id),
+            audit_details,
+        )
+        .await?;
+    log::warn!("Emergency access used by {}", config.user_id);
+
+    let identity = state
+        .identity_manager()
+        .find(FindIdentity::UserId(config.user_id))
+        .await?
+        .ok_or(BreakGlassError::UserNotFound)?;
+
+    let (user, _) = state
+        .session_manager()
+        .create_with_duration(&identity, vec![config.role.clone()], None, config.session_duration())
+        .await?;
+
+    auth_session.token_login = None;
+    auth_session.user = Some(user);
+    Ok((auth_session, StatusCode::NO_CONTENT))
+}
@@ -0,0 +1,140 @@
+use crate::{
+    auth::{ApiUser, AuthServiceState, Tenant},
+    db::DBError,
+    logging::user_hash,
+};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum ConnectionTicketError {
+    #[error("Connection tickets are not enabled")]
+    NotEnabled,
+    #[error("Session is invalid or has expired")]
+    InvalidSession,
+    #[error("Ticket is invalid, has expired or has been redeemed already")]
+    InvalidTicket,
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for ConnectionTicketError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            ConnectionTicketError::NotEnabled => StatusCode::NOT_FOUND,
+            ConnectionTicketError::InvalidSession | ConnectionTicketError::InvalidTicket => StatusCode::UNAUTHORIZED,
+            ConnectionTicketError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct CreatedConnectionTicket {
+    ticket: String,
+    expire_at: DateTime<Utc>,
+}
+
+/// Issue a short-lived, single-use ticket of the current user to authenticate a realtime connection (ex. WebSocket
+/// or UDP session), thus the realtime servers don't have to handle the session cookie.
+pub(in crate::auth) async fn ep_create_connection_ticket(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    ApiUser(user): ApiUser,
+) -> Result<Response, ConnectionTicketError> {
+    let signer = state
+        .connection_ticket_signer()
+        .ok_or(ConnectionTicketError::NotEnabled)?;
+    state
+        .session_manager()
+        .find_session(tenant.id(), user.user_id, user.key)
+        .await?
+        .ok_or(ConnectionTicketError::InvalidSession)?;
+
+    let now = state.clock().now();
+    let ticket = CreatedConnectionTicket {
+        ticket: signer.sign(tenant.id(), user.user_id, &user.name, now),
+        expire_at: now + signer.duration(),
+    };
+    tracing::info!(user = %user_hash(user.user_id), outcome = "ticketIssued", "Connection ticket issued");
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(ticket)).into_response())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct RedeemConnectionTicket {
+    ticket: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct RedeemedConnectionTicket {
+    user_id: Uuid,
+    name: String,
+    roles: Vec<String>,
+    restrictions: Vec<String>,
+}
+
+/// Redeem a connection ticket for the realtime servers. A ticket is accepted only once, the servers verifying the
+/// tickets offline shall track the redeemed tickets on their own. This endpoint is available only from the allowed
+/// networks.
+pub(in crate::auth) async fn ep_redeem_connection_ticket(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    Json(request): Json<RedeemConnectionTicket>,
+) -> Result<Json<RedeemedConnectionTicket>, ConnectionTicketError> {
+    let signer = state
+        .connection_ticket_signer()
+        .ok_or(ConnectionTicketError::NotEnabled)?;
+    let now = state.clock().now();
+    let ticket = signer
+        .verify(&request.ticket, now)
+        .filter(|ticket| ticket.tenant_id == tenant.id())
+        .ok_or(ConnectionTicketError::InvalidTicket)?;
+
+    if !state
+        .ticket_redemption()
+        .redeem(ticket.ticket_id, ticket.expire_at - now)
+        .await?
+    {
+        log::warn!("Connection ticket of {} redeemed again", ticket.user_id);
+        return Err(ConnectionTicketError::InvalidTicket);
+    }
+
+    let roles = state.role_manager().get_roles(ticket.user_id).await?;
+    let restrictions = state.restriction_manager().active_restrictions(ticket.user_id).await?;
+    Ok(Json(RedeemedConnectionTicket {
+        user_id: ticket.user_id,
+        name: ticket.name,
+        roles,
+        restrictions,
+    }))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct ConnectionTicketKey {
+    algorithm: &'static str,
+    public_key: String,
+}
+
+/// Get the public key to verify the connection tickets offline.
+pub(in crate::auth) async fn ep_get_connection_ticket_key(
+    State(state): State<AuthServiceState>,
+) -> Result<Json<ConnectionTicketKey>, StatusCode> {
+    let signer = state.connection_ticket_signer().ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(ConnectionTicketKey {
+        algorithm: "Ed25519",
+        public_key: signer.public_key(),
+    }))
+}
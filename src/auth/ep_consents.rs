@@ -0,0 +1,154 @@
+use crate::{
+    auth::{ApiUser, AuthServiceState},
+    db::DBError,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum ConsentError {
+    #[error("Unknown purpose: {0}")]
+    UnknownPurpose(String),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for ConsentError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            ConsentError::UnknownPurpose(_) => StatusCode::NOT_FOUND,
+            ConsentError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct UserConsent {
+    purpose: String,
+    /// If the consent is given to the current version of the purpose.
+    granted: bool,
+    current_version: String,
+    /// The version of the purpose the user decided on, None if the user has not decided yet.
+    version: Option<String>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+impl AuthServiceState {
+    /// Get the consents of a user for all the configured purposes.
+    pub(in crate::auth) async fn user_consents(&self, user_id: Uuid) -> Result<Vec<UserConsent>, DBError> {
+        let mut recorded = self.consent_manager().list_consents(user_id).await?;
+        let mut consents: Vec<_> = self
+            .consent_config()
+            .purposes
+            .iter()
+            .map(|(purpose, current_version)| {
+                let consent = recorded
+                    .iter()
+                    .position(|consent| &consent.purpose == purpose)
+                    .map(|index| recorded.swap_remove(index));
+                UserConsent {
+                    purpose: purpose.clone(),
+                    granted: consent
+                        .as_ref()
+                        .map(|consent| consent.granted && &consent.version == current_version)
+                        .unwrap_or(false),
+                    current_version: current_version.clone(),
+                    version: consent.as_ref().map(|consent| consent.version.clone()),
+                    updated_at: consent.map(|consent| consent.updated_at),
+                }
+            })
+            .collect();
+        consents.sort_by(|a, b| a.purpose.cmp(&b.purpose));
+        Ok(consents)
+    }
+}
+
+/// Get the consents of the current user.
+pub(in crate::auth) async fn ep_get_user_consents(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+) -> Result<Json<Vec<UserConsent>>, ConsentError> {
+    Ok(Json(state.user_consents(user.user_id).await?))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct UpdateConsent {
+    granted: bool,
+}
+
+/// Give or withdraw the consent of the current user to the current version of a purpose.
+pub(in crate::auth) async fn ep_update_user_consent(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+    Path(purpose): Path<String>,
+    Json(request): Json<UpdateConsent>,
+) -> Result<StatusCode, ConsentError> {
+    let version = state
+        .consent_config()
+        .purposes
+        .get(&purpose)
+        .ok_or_else(|| ConsentError::UnknownPurpose(purpose.clone()))?;
+
+    state
+        .consent_manager()
+        .set_consent(user.user_id, &purpose, request.granted, version)
+        .await?;
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "consent.update",
+            Some(user.user_id),
+            json!({ "purpose": purpose, "granted": request.granted, "version": version }),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct CheckConsent {
+    user_id: Uuid,
+    purpose: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct ConsentCheck {
+    granted: bool,
+}
+
+/// Check if a user has consented to the current version of a purpose. This endpoint is available only from the
+/// allowed networks.
+pub(in crate::auth) async fn ep_check_consent(
+    State(state): State<AuthServiceState>,
+    Json(request): Json<CheckConsent>,
+) -> Result<Json<ConsentCheck>, ConsentError> {
+    let version = state
+        .consent_config()
+        .purposes
+        .get(&request.purpose)
+        .ok_or_else(|| ConsentError::UnknownPurpose(request.purpose.clone()))?;
+
+    let granted = state
+        .consent_manager()
+        .find_consent(request.user_id, &request.purpose)
+        .await?
+        .map(|consent| consent.granted && &consent.version == version)
+        .unwrap_or(false);
+    Ok(Json(ConsentCheck { granted }))
+}
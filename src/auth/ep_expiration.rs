@@ -0,0 +1,87 @@
+use crate::{
+    auth::{AdminRole, AuthServiceState, RequireRole},
+    db::{DBError, FindIdentity, IdentityError},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum ExpirationError {
+    #[error("User ({0}) not found")]
+    UserNotFound(Uuid),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for ExpirationError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            ExpirationError::UserNotFound(_) => StatusCode::NOT_FOUND,
+            ExpirationError::IdentityError(_) | ExpirationError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct Expiration {
+    /// The account is permanent when not set.
+    expire_at: Option<DateTime<Utc>>,
+}
+
+/// Get the expiration of a user. Requires the admin role.
+pub(in crate::auth) async fn ep_get_expiration(
+    State(state): State<AuthServiceState>,
+    _admin: RequireRole<AdminRole>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<Expiration>, ExpirationError> {
+    let expire_at = state.expiration_manager().find_expiration(user_id).await?;
+    Ok(Json(Expiration { expire_at }))
+}
+
+/// Set or extend the expiration of a user, without an expiration the account is made permanent. An expired account
+/// can be used again after the extension. Requires the admin role.
+pub(in crate::auth) async fn ep_set_expiration(
+    State(state): State<AuthServiceState>,
+    admin: RequireRole<AdminRole>,
+    Path(user_id): Path<Uuid>,
+    Json(request): Json<Expiration>,
+) -> Result<StatusCode, ExpirationError> {
+    if state
+        .identity_manager()
+        .find(FindIdentity::UserId(user_id))
+        .await?
+        .is_none()
+    {
+        return Err(ExpirationError::UserNotFound(user_id));
+    }
+
+    state
+        .expiration_manager()
+        .set_expiration(user_id, request.expire_at)
+        .await?;
+    state
+        .audit_log()
+        .record(
+            Some(admin.user_id),
+            "identity.set_expiration",
+            Some(user_id),
+            json!({ "expireAt": request.expire_at }),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
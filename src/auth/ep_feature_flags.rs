@@ -0,0 +1,37 @@
+use crate::{
+    auth::{AdminRole, AuthServiceState, RequireRole},
+    db::{DBError, FeatureFlagState},
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum FeatureFlagError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for FeatureFlagError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            FeatureFlagError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+/// Get the effective state of the feature flags. The flags are read-only through the API, the overrides are set in
+/// redis.
+pub(in crate::auth) async fn ep_list_feature_flags(
+    State(state): State<AuthServiceState>,
+    _admin: RequireRole<AdminRole>,
+) -> Result<Json<Vec<FeatureFlagState>>, FeatureFlagError> {
+    let flags = state.feature_flags().list().await?;
+    Ok(Json(flags))
+}
@@ -1,7 +1,35 @@
-use crate::auth::AuthServiceState;
-use axum::{extract::State, Json};
+use crate::auth::{AuthServiceState, Tenant};
+use axum::{extract::State, http::HeaderMap, Json};
+use serde::Serialize;
 
-pub(in crate::auth) async fn ep_get_auth_providers(State(state): State<AuthServiceState>) -> Json<Vec<String>> {
-    let providers = state.providers().to_vec();
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct AuthProvider {
+    name: String,
+    /// The provider of the last login of the browser, the login pages shall nudge the user to this provider to avoid
+    /// a duplicate account.
+    is_last_used: bool,
+}
+
+/// Get the providers of the tenant, the providers without a successful discovery are not listed.
+pub(in crate::auth) async fn ep_get_auth_providers(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+) -> Json<Vec<AuthProvider>> {
+    let last_provider = tenant.session_meta().parse_last_provider(&headers);
+    let providers = tenant
+        .providers()
+        .iter()
+        .filter(|provider| {
+            state
+                .unavailable_providers()
+                .is_available(tenant.provider_profile(), provider)
+        })
+        .map(|provider| AuthProvider {
+            name: provider.clone(),
+            is_last_used: last_provider.as_ref() == Some(provider),
+        })
+        .collect();
     Json(providers)
 }
@@ -1,13 +1,16 @@
-use crate::{auth::AuthServiceState, db::IdentityError};
+use crate::{
+    auth::{ApiUser, AuthServiceState, AuthSession},
+    db::{DBError, IdentityError, SessionAuthContext, TokenKind},
+};
 use axum::{
     extract::State,
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use ring::digest;
 use serde::Serialize;
-use shine_service::service::CurrentUser;
 use thiserror::Error as ThisError;
 use uuid::Uuid;
 
@@ -17,13 +20,15 @@ pub(in crate::auth) enum Error {
     UserNotFound(Uuid),
     #[error(transparent)]
     IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         let status_code = match &self {
             Error::UserNotFound(_) => StatusCode::NOT_FOUND,
-            Error::IdentityError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::IdentityError(_) | Error::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
         (status_code, format!("{self:?}")).into_response()
@@ -36,27 +41,136 @@ pub(in crate::auth) struct UserInfo {
     user_id: Uuid,
     name: String,
     is_email_confirmed: bool,
+    roles: Vec<String>,
+    /// The active restrictions of the user, ex. `chat:muted`.
+    restrictions: Vec<String>,
+    /// If the user is a minor by the declared age, not given when the age is unknown or it is not collected.
+    is_minor: Option<bool>,
+    /// The region recorded at the registration, see `RegionConfig`.
+    region: Option<String>,
+    session_start: DateTime<Utc>,
     session_length: u64,
+    /// Kind of the login token of the device, if the user has chosen to be remembered.
+    token_kind: Option<TokenKind>,
+    /// The authentication context reported by the external provider at the login, ex. to require a multi-factor
+    /// login at the provider for the sensitive operations.
+    auth_context: Option<SessionAuthContext>,
+    /// Changes whenever any of the other fields (except the session length) changes, thus cached copies
+    /// can be invalidated.
+    version: String,
+    #[serde(skip)]
+    user_version: u64,
+}
+
+impl UserInfo {
+    fn version(&self) -> String {
+        let mut ctx = digest::Context::new(&digest::SHA256);
+        ctx.update(self.user_id.as_bytes());
+        ctx.update(self.name.as_bytes());
+        ctx.update(&[self.is_email_confirmed as u8]);
+        for role in &self.roles {
+            ctx.update(&[0]);
+            ctx.update(role.as_bytes());
+        }
+        ctx.update(&[1]);
+        for restriction in &self.restrictions {
+            ctx.update(&[0]);
+            ctx.update(restriction.as_bytes());
+        }
+        ctx.update(format!("{:?}", self.is_minor).as_bytes());
+        ctx.update(format!("{:?}", self.region).as_bytes());
+        ctx.update(&self.session_start.timestamp_micros().to_be_bytes());
+        ctx.update(format!("{:?}", self.token_kind).as_bytes());
+        ctx.update(format!("{:?}", self.auth_context).as_bytes());
+        ctx.update(&self.user_version.to_be_bytes());
+        hex::encode(&ctx.finish().as_ref()[..16])
+    }
 }
 
 /// Get the information about the current user. The cookie is not accessible
 /// from javascript, thus this endpoint can be used to get details about the current user.
+/// The response has an ETag of the version and `304 Not Modified` is returned if it is matching the
+/// `If-None-Match` header.
 pub(in crate::auth) async fn ep_get_user_info(
     State(state): State<AuthServiceState>,
-    user: CurrentUser,
-) -> Result<Json<UserInfo>, Error> {
+    ApiUser(user): ApiUser,
+    auth_session: AuthSession,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
     let identity = state
         .identity_manager()
         .find(crate::db::FindIdentity::UserId(user.user_id))
         .await?
         .ok_or(Error::UserNotFound(user.user_id))?;
 
-    let session_length = (Utc::now() - user.session_start).num_seconds();
+    let roles = state.user_roles(&user).await?;
+    let restrictions = state.restriction_manager().active_restrictions(user.user_id).await?;
+    let user_version = state.user_invalidation().version(user.user_id).await?;
+    let is_minor = match state.age_gate_config() {
+        Some(age_gate) => {
+            let today = state.clock().now().date_naive();
+            state
+                .age_manager()
+                .find(user.user_id)
+                .await?
+                .and_then(|info| age_gate.is_minor(&info, today))
+        }
+        None => None,
+    };
+    let region = match state.region_config() {
+        Some(_) => state.region_manager().find_region(user.user_id).await?,
+        None => None,
+    };
+
+    let token_kind = match auth_session.token_login.as_ref() {
+        Some(token_login) if token_login.user_id == user.user_id => state
+            .identity_manager()
+            .find_token(&token_login.token)
+            .await?
+            .map(|(_, token_info)| token_info.kind),
+        _ => None,
+    };
+
+    let auth_context = state
+        .session_manager()
+        .find_session_auth_context(user.user_id, user.key)
+        .await?;
+
+    let session_length = (state.clock().now() - user.session_start).num_seconds();
     let session_length = if session_length < 0 { 0 } else { session_length as u64 };
-    Ok(Json(UserInfo {
+    let mut user_info = UserInfo {
         user_id: user.user_id,
         name: user.name,
         is_email_confirmed: identity.is_email_confirmed,
+        roles,
+        restrictions,
+        is_minor,
+        region,
+        session_start: user.session_start,
         session_length,
-    }))
+        token_kind,
+        auth_context,
+        version: String::new(),
+        user_version,
+    };
+    user_info.version = user_info.version();
+
+    let etag = format!("\"{}\"", user_info.version);
+    let is_matching = headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == etag || tag == "*");
+    // the response depends on the session cookie, it shall not be shared by the caches
+    let cache_headers = [
+        (header::ETAG, etag),
+        (header::CACHE_CONTROL, "private, no-cache".to_owned()),
+    ];
+    if is_matching {
+        Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response())
+    } else {
+        Ok((cache_headers, Json(user_info)).into_response())
+    }
 }
@@ -0,0 +1,106 @@
+use crate::{
+    auth::{AdminRole, AuthServiceState, RequireRole},
+    db::{DBError, FindIdentity, IdentityError},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+const MAX_TAG_LENGTH: usize = 64;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum IdentityTagError {
+    #[error("User ({0}) not found")]
+    UserNotFound(Uuid),
+    #[error("Tag not found")]
+    TagNotFound,
+    #[error("Invalid tag")]
+    InvalidTag,
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for IdentityTagError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            IdentityTagError::UserNotFound(_) | IdentityTagError::TagNotFound => StatusCode::NOT_FOUND,
+            IdentityTagError::InvalidTag => StatusCode::BAD_REQUEST,
+            IdentityTagError::IdentityError(_) | IdentityTagError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+/// The tags are used in the search queries as a comma separated list, ex. `beta-tester,banned-wave-3`, thus only a
+/// safe set of characters is accepted.
+fn is_valid_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.len() <= MAX_TAG_LENGTH
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == ':' || c == '_' || c == '-')
+}
+
+/// List the tags of a user. Requires the admin role.
+pub(in crate::auth) async fn ep_list_identity_tags(
+    State(state): State<AuthServiceState>,
+    _admin: RequireRole<AdminRole>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<Vec<String>>, IdentityTagError> {
+    let tags = state.tag_manager().list_tags(user_id).await?;
+    Ok(Json(tags))
+}
+
+/// Tag a user, adding an existing tag is not an error. Requires the admin role.
+pub(in crate::auth) async fn ep_add_identity_tag(
+    State(state): State<AuthServiceState>,
+    admin: RequireRole<AdminRole>,
+    Path((user_id, tag)): Path<(Uuid, String)>,
+) -> Result<StatusCode, IdentityTagError> {
+    if !is_valid_tag(&tag) {
+        return Err(IdentityTagError::InvalidTag);
+    }
+    if state
+        .identity_manager()
+        .find(FindIdentity::UserId(user_id))
+        .await?
+        .is_none()
+    {
+        return Err(IdentityTagError::UserNotFound(user_id));
+    }
+
+    if state.tag_manager().add_tag(user_id, &tag).await? {
+        state
+            .audit_log()
+            .record(Some(admin.user_id), "tag.add", Some(user_id), json!({ "tag": tag }))
+            .await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Remove a tag of a user. Requires the admin role.
+pub(in crate::auth) async fn ep_remove_identity_tag(
+    State(state): State<AuthServiceState>,
+    admin: RequireRole<AdminRole>,
+    Path((user_id, tag)): Path<(Uuid, String)>,
+) -> Result<StatusCode, IdentityTagError> {
+    if !state.tag_manager().remove_tag(user_id, &tag).await? {
+        return Err(IdentityTagError::TagNotFound);
+    }
+    state
+        .audit_log()
+        .record(Some(admin.user_id), "tag.remove", Some(user_id), json!({ "tag": tag }))
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,142 @@
+use crate::{
+    auth::{ApiService, AuthServiceState, JoinTokenClaims, Tenant},
+    db::{DBError, FindIdentity, IdentityError, IdentityKind},
+};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+/// The maximum length of the server and match ids.
+const MAX_ID_LENGTH: usize = 128;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum JoinTokenError {
+    #[error("Join tokens are not enabled")]
+    Disabled,
+    #[error("Missing scope: {0}")]
+    MissingScope(String),
+    #[error("Delegated tokens cannot request join tokens")]
+    DelegatedToken,
+    #[error("Invalid server or match id")]
+    InvalidRequest,
+    #[error("User ({0}) not found")]
+    UserNotFound(Uuid),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for JoinTokenError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            JoinTokenError::Disabled | JoinTokenError::UserNotFound(_) => StatusCode::NOT_FOUND,
+            JoinTokenError::MissingScope(_) | JoinTokenError::DelegatedToken => StatusCode::FORBIDDEN,
+            JoinTokenError::InvalidRequest => StatusCode::BAD_REQUEST,
+            JoinTokenError::IdentityError(_) | JoinTokenError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct JoinTokenRequest {
+    user_id: Uuid,
+    /// The id of the game server the player is admitted to.
+    server_id: String,
+    match_id: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct JoinTokenResponse {
+    join_token: String,
+    expires_in: i64,
+}
+
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_ID_LENGTH && !id.chars().any(char::is_control)
+}
+
+/// Issue a join token of a player for a match on a dedicated game server. It is requested by a service (ex. the
+/// matchmaking) with the configured scope, the token is signed by the key of the service tokens, thus the servers
+/// can admit the players offline.
+pub(in crate::auth) async fn ep_create_join_token(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    ApiService(service): ApiService,
+    Json(request): Json<JoinTokenRequest>,
+) -> Result<Response, JoinTokenError> {
+    let (config, signer) = match (state.join_token_config(), state.service_token_signer()) {
+        (Some(config), Some(signer)) => (config, signer),
+        _ => return Err(JoinTokenError::Disabled),
+    };
+    if !service.scopes().contains(&config.scope) {
+        return Err(JoinTokenError::MissingScope(config.scope.clone()));
+    }
+    // only the service itself is trusted to assert the match of a player
+    if service.act.is_some() {
+        return Err(JoinTokenError::DelegatedToken);
+    }
+    if !is_valid_id(&request.server_id) || !is_valid_id(&request.match_id) {
+        return Err(JoinTokenError::InvalidRequest);
+    }
+
+    let identity = state
+        .identity_manager()
+        .find(FindIdentity::UserId(request.user_id))
+        .await?
+        .filter(|identity| matches!(identity.kind, IdentityKind::User) && identity.tenant_id == tenant.id())
+        .ok_or(JoinTokenError::UserNotFound(request.user_id))?;
+    let roles = state.role_manager().get_roles(identity.user_id).await?;
+    let restrictions = state
+        .restriction_manager()
+        .active_restrictions(identity.user_id)
+        .await?;
+
+    let now = state.clock().now();
+    let claims = JoinTokenClaims {
+        iss: signer.issuer().to_owned(),
+        sub: identity.user_id,
+        aud: request.server_id,
+        tenant: tenant.id().to_owned(),
+        name: identity.name,
+        roles,
+        restrictions,
+        match_id: request.match_id,
+        iat: now.timestamp(),
+        exp: (now + config.duration()).timestamp(),
+        jti: Uuid::new_v4(),
+    };
+
+    state
+        .audit_log()
+        .record(
+            Some(service.sub),
+            "token.join",
+            Some(identity.user_id),
+            json!({ "tenantId": tenant.id(), "serverId": claims.aud, "matchId": claims.match_id }),
+        )
+        .await?;
+
+    log::info!(
+        "Issuing join token for {} to server {} of match {}",
+        identity.user_id,
+        claims.aud,
+        claims.match_id
+    );
+    let response = JoinTokenResponse {
+        join_token: signer.sign(&claims),
+        expires_in: config.duration().num_seconds(),
+    };
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(response)).into_response())
+}
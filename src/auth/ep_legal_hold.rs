@@ -0,0 +1,122 @@
+use crate::{
+    auth::{AuthServiceState, ComplianceRole, RequireRole},
+    db::{DBError, FindIdentity, IdentityError},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum LegalHoldError {
+    #[error("User not found")]
+    UserNotFound,
+    #[error("Legal hold not found")]
+    HoldNotFound,
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for LegalHoldError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            LegalHoldError::UserNotFound | LegalHoldError::HoldNotFound => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct LegalHold {
+    reason: String,
+    placed_at: DateTime<Utc>,
+    placed_by: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct PlaceLegalHold {
+    reason: String,
+}
+
+/// Get the legal hold of a user. Requires the compliance role.
+pub(in crate::auth) async fn ep_get_legal_hold(
+    State(state): State<AuthServiceState>,
+    _officer: RequireRole<ComplianceRole>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<LegalHold>, LegalHoldError> {
+    let hold = state
+        .deletion_manager()
+        .find_legal_hold(user_id)
+        .await?
+        .ok_or(LegalHoldError::HoldNotFound)?;
+
+    Ok(Json(LegalHold {
+        reason: hold.reason,
+        placed_at: hold.placed_at,
+        placed_by: hold.placed_by,
+    }))
+}
+
+/// Place a legal hold on a user, the account cannot be deleted until the hold is released. Requires the
+/// compliance role.
+pub(in crate::auth) async fn ep_place_legal_hold(
+    State(state): State<AuthServiceState>,
+    officer: RequireRole<ComplianceRole>,
+    Path(user_id): Path<Uuid>,
+    Json(request): Json<PlaceLegalHold>,
+) -> Result<StatusCode, LegalHoldError> {
+    if state
+        .identity_manager()
+        .find(FindIdentity::UserId(user_id))
+        .await?
+        .is_none()
+    {
+        return Err(LegalHoldError::UserNotFound);
+    }
+
+    state
+        .deletion_manager()
+        .place_legal_hold(user_id, &request.reason, Some(officer.user_id))
+        .await?;
+    state
+        .audit_log()
+        .record(
+            Some(officer.user_id),
+            "legal_hold.place",
+            Some(user_id),
+            json!({ "reason": request.reason }),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Release the legal hold of a user. Requires the compliance role.
+pub(in crate::auth) async fn ep_release_legal_hold(
+    State(state): State<AuthServiceState>,
+    officer: RequireRole<ComplianceRole>,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, LegalHoldError> {
+    if !state.deletion_manager().release_legal_hold(user_id).await? {
+        return Err(LegalHoldError::HoldNotFound);
+    }
+    state
+        .audit_log()
+        .record(Some(officer.user_id), "legal_hold.release", Some(user_id), json!({}))
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,167 @@
+use crate::{
+    auth::{AdminRole, AuthServiceState, RequireRole},
+    db::{DBError, FindIdentity, IdentityError, MergeError, UserChange},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+/// The maximum number of the listed merge requests.
+const MAX_MERGE_REQUESTS: usize = 100;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum MergeIdentitiesError {
+    #[error("User not found")]
+    UserNotFound,
+    #[error("Identity cannot be merged into itself")]
+    SameUser,
+    #[error("Identities belong to different tenants")]
+    TenantMismatch,
+    #[error("Tombstone not found")]
+    TombstoneNotFound,
+    #[error(transparent)]
+    MergeError(#[from] MergeError),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for MergeIdentitiesError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            MergeIdentitiesError::UserNotFound
+            | MergeIdentitiesError::TombstoneNotFound
+            | MergeIdentitiesError::MergeError(MergeError::SourceNotFound) => StatusCode::NOT_FOUND,
+            MergeIdentitiesError::SameUser | MergeIdentitiesError::TenantMismatch => StatusCode::BAD_REQUEST,
+            MergeIdentitiesError::MergeError(MergeError::LegalHold) => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct MergeRequest {
+    request_id: Uuid,
+    user_id: Uuid,
+    source_user_id: Uuid,
+    provider: String,
+    created_at: DateTime<Utc>,
+}
+
+/// List the pending merge requests of the users, the oldest first. Requires the admin role.
+pub(in crate::auth) async fn ep_list_merge_requests(
+    State(state): State<AuthServiceState>,
+    _admin: RequireRole<AdminRole>,
+) -> Result<Json<Vec<MergeRequest>>, MergeIdentitiesError> {
+    let requests = state
+        .merge_manager()
+        .list_requests(MAX_MERGE_REQUESTS)
+        .await?
+        .into_iter()
+        .map(|request| MergeRequest {
+            request_id: request.request_id,
+            user_id: request.user_id,
+            source_user_id: request.source_user_id,
+            provider: request.provider,
+            created_at: request.created_at,
+        })
+        .collect();
+    Ok(Json(requests))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct MergeIdentity {
+    source_user_id: Uuid,
+}
+
+/// Merge the source identity into the user of the path. The source is deleted, its sessions are ended and
+/// the other services are notified by a `merged` invalidation to remap the ownership of their data. Requires
+/// the admin role.
+pub(in crate::auth) async fn ep_merge_identity(
+    State(state): State<AuthServiceState>,
+    admin: RequireRole<AdminRole>,
+    Path(user_id): Path<Uuid>,
+    Json(request): Json<MergeIdentity>,
+) -> Result<StatusCode, MergeIdentitiesError> {
+    let source_user_id = request.source_user_id;
+    if source_user_id == user_id {
+        return Err(MergeIdentitiesError::SameUser);
+    }
+    let identity = state
+        .identity_manager()
+        .find(FindIdentity::UserId(user_id))
+        .await?
+        .ok_or(MergeIdentitiesError::UserNotFound)?;
+    let source = state
+        .identity_manager()
+        .find(FindIdentity::UserId(source_user_id))
+        .await?
+        .ok_or(MergeIdentitiesError::MergeError(MergeError::SourceNotFound))?;
+    if identity.tenant_id != source.tenant_id {
+        return Err(MergeIdentitiesError::TenantMismatch);
+    }
+
+    state.merge_manager().merge(user_id, source_user_id).await?;
+    state
+        .audit_log()
+        .record(
+            Some(admin.user_id),
+            "identity.merge",
+            Some(user_id),
+            json!({ "sourceUserId": source_user_id, "sourceName": source.name }),
+        )
+        .await?;
+
+    // the merge is done, the errors beyond this point are only logged
+    if let Err(err) = state.session_manager().remove_all(source_user_id).await {
+        log::warn!(
+            "Failed to clear all sessions of the merged user {}: {:?}",
+            source_user_id,
+            err
+        );
+    }
+    if let Err(err) = state
+        .user_invalidation()
+        .invalidate_merged(source_user_id, user_id)
+        .await
+    {
+        log::error!("Failed to publish the merge of {source_user_id}: {err}");
+    }
+    if let Err(err) = state.user_invalidation().invalidate(user_id, UserChange::Roles).await {
+        log::error!("Failed to publish the role change of {user_id}: {err}");
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct Tombstone {
+    user_id: Uuid,
+}
+
+/// Find the user an identity was merged into. This endpoint is available only from the allowed networks.
+pub(in crate::auth) async fn ep_find_tombstone(
+    State(state): State<AuthServiceState>,
+    Path(old_user_id): Path<Uuid>,
+) -> Result<Json<Tombstone>, MergeIdentitiesError> {
+    let user_id = state
+        .merge_manager()
+        .find_tombstone(old_user_id)
+        .await?
+        .ok_or(MergeIdentitiesError::TombstoneNotFound)?;
+    Ok(Json(Tombstone { user_id }))
+}
@@ -0,0 +1,112 @@
+use crate::{
+    auth::{ApiUser, AuthServiceState, ParentalConsentError, PENDING_CONSENT_RESTRICTION},
+    db::UserChange,
+};
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Maximum number of the consent requests of a user in an hour.
+const CONSENT_REQUEST_RATE_LIMIT: u32 = 3;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct ParentalConsentInfo {
+    parent_email: String,
+    requested_at: DateTime<Utc>,
+    granted_at: Option<DateTime<Utc>>,
+}
+
+/// Get the parental consent of the current user.
+pub(in crate::auth) async fn ep_get_parental_consent(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+) -> Result<Json<ParentalConsentInfo>, ParentalConsentError> {
+    let consent = state
+        .parental_consent_manager()
+        .find_consent(user.user_id)
+        .await?
+        .ok_or(ParentalConsentError::NotPending)?;
+
+    Ok(Json(ParentalConsentInfo {
+        parent_email: consent.parent_email,
+        requested_at: consent.requested_at,
+        granted_at: consent.granted_at,
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct RequestParentalConsent {
+    parent_email: String,
+}
+
+/// Send a consent link to the parent of the current user, ex. when the email was not given at the registration or
+/// the link has expired.
+pub(in crate::auth) async fn ep_request_parental_consent(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+    Json(request): Json<RequestParentalConsent>,
+) -> Result<StatusCode, ParentalConsentError> {
+    let restrictions = state.restriction_manager().active_restrictions(user.user_id).await?;
+    if !restrictions.iter().any(|r| r == PENDING_CONSENT_RESTRICTION) {
+        return Err(ParentalConsentError::NotPending);
+    }
+
+    let rate_key = format!("parental-consent:{}", user.user_id);
+    if !state
+        .rate_limiter()
+        .check(&rate_key, CONSENT_REQUEST_RATE_LIMIT, Duration::hours(1))
+        .await?
+    {
+        return Err(ParentalConsentError::TooManyRequests);
+    }
+
+    state
+        .send_parental_consent_request(user.user_id, &user.name, &request.parent_email)
+        .await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct GrantParentalConsent {
+    token: String,
+}
+
+/// Grant the consent by the token of the link sent to the parent, the `pending-consent` restriction of the user is
+/// lifted.
+pub(in crate::auth) async fn ep_grant_parental_consent(
+    State(state): State<AuthServiceState>,
+    Json(request): Json<GrantParentalConsent>,
+) -> Result<StatusCode, ParentalConsentError> {
+    let config = state
+        .parental_consent_config()
+        .ok_or(ParentalConsentError::ConsentDisabled)?;
+
+    let user_id = state
+        .parental_consent_manager()
+        .grant_consent(&request.token, config.link_duration())
+        .await?
+        .ok_or(ParentalConsentError::InvalidToken)?;
+
+    state
+        .restriction_manager()
+        .remove_restriction(user_id, PENDING_CONSENT_RESTRICTION)
+        .await?;
+    state
+        .audit_log()
+        .record(None, "parental_consent.grant", Some(user_id), json!({}))
+        .await?;
+    if let Err(err) = state
+        .user_invalidation()
+        .invalidate(user_id, UserChange::Restrictions)
+        .await
+    {
+        log::error!("Failed to publish the restrictions of {user_id}: {err}");
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
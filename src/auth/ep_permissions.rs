@@ -0,0 +1,152 @@
+use crate::{
+    auth::{AdminRole, AuthServiceState, RequireRole},
+    db::{DBError, Resource},
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum PermissionsError {
+    #[error("Grant not found")]
+    GrantNotFound,
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for PermissionsError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            PermissionsError::GrantNotFound => StatusCode::NOT_FOUND,
+            PermissionsError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct Grant {
+    user_id: Uuid,
+    action: String,
+    #[serde(flatten)]
+    resource: Resource,
+}
+
+/// Grant a resource-scoped permission to a user. Requires the admin role.
+pub(in crate::auth) async fn ep_grant_permission(
+    State(state): State<AuthServiceState>,
+    admin: RequireRole<AdminRole>,
+    Json(request): Json<Grant>,
+) -> Result<StatusCode, PermissionsError> {
+    state
+        .permission_manager()
+        .grant(request.user_id, &request.action, &request.resource, Some(admin.user_id))
+        .await?;
+    state
+        .audit_log()
+        .record(
+            Some(admin.user_id),
+            "permission.grant",
+            Some(request.user_id),
+            json!({ "action": request.action, "resource": request.resource }),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Revoke a resource-scoped permission of a user. Requires the admin role.
+pub(in crate::auth) async fn ep_revoke_permission(
+    State(state): State<AuthServiceState>,
+    admin: RequireRole<AdminRole>,
+    Json(request): Json<Grant>,
+) -> Result<StatusCode, PermissionsError> {
+    if !state
+        .permission_manager()
+        .revoke(request.user_id, &request.action, &request.resource)
+        .await?
+    {
+        return Err(PermissionsError::GrantNotFound);
+    }
+    state
+        .audit_log()
+        .record(
+            Some(admin.user_id),
+            "permission.revoke",
+            Some(request.user_id),
+            json!({ "action": request.action, "resource": request.resource }),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct CheckPermission {
+    user_id: Uuid,
+    action: String,
+    #[serde(flatten)]
+    resource: Resource,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct PermissionCheck {
+    allowed: bool,
+}
+
+/// Check if a user may perform an action on a resource. This endpoint is available only from the allowed networks.
+pub(in crate::auth) async fn ep_check_permission(
+    State(state): State<AuthServiceState>,
+    Json(request): Json<CheckPermission>,
+) -> Result<Json<PermissionCheck>, PermissionsError> {
+    let allowed = state
+        .permission_manager()
+        .has_permission(request.user_id, &request.action, &request.resource)
+        .await?;
+    Ok(Json(PermissionCheck { allowed }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct CheckPermissions {
+    user_id: Uuid,
+    action: String,
+    resource_type: String,
+    resource_ids: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct PermissionsCheck {
+    /// The ids of the resources the action is permitted on.
+    allowed: Vec<String>,
+}
+
+/// Check the permission of an action for a list of resources of the same type, ex. to filter a list endpoint.
+/// This endpoint is available only from the allowed networks.
+pub(in crate::auth) async fn ep_check_permissions(
+    State(state): State<AuthServiceState>,
+    Json(request): Json<CheckPermissions>,
+) -> Result<Json<PermissionsCheck>, PermissionsError> {
+    let allowed = state
+        .permission_manager()
+        .filter_permitted(
+            request.user_id,
+            &request.action,
+            &request.resource_type,
+            &request.resource_ids,
+        )
+        .await?;
+    Ok(Json(PermissionsCheck { allowed }))
+}
@@ -0,0 +1,174 @@
+use crate::{
+    auth::{AdminRole, AuthServiceState, RequireRole},
+    db::{DBError, FindIdentity, IdentityError, Restriction, UserChange},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+const MAX_RESTRICTION_LENGTH: usize = 64;
+const MAX_REASON_LENGTH: usize = 256;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum RestrictionError {
+    #[error("User ({0}) not found")]
+    UserNotFound(Uuid),
+    #[error("Restriction not found")]
+    RestrictionNotFound,
+    #[error("Invalid restriction name")]
+    InvalidRestriction,
+    #[error("Invalid reason")]
+    InvalidReason,
+    #[error("Expiration is in the past")]
+    Expired,
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for RestrictionError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            RestrictionError::UserNotFound(_) | RestrictionError::RestrictionNotFound => StatusCode::NOT_FOUND,
+            RestrictionError::InvalidRestriction | RestrictionError::InvalidReason | RestrictionError::Expired => {
+                StatusCode::BAD_REQUEST
+            }
+            RestrictionError::IdentityError(_) | RestrictionError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+/// The restrictions are shared with the other services, thus only a safe set of characters is accepted,
+/// ex. `chat:muted`.
+fn is_valid_restriction(restriction: &str) -> bool {
+    !restriction.is_empty()
+        && restriction.len() <= MAX_RESTRICTION_LENGTH
+        && restriction
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == ':' || c == '_' || c == '-')
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct SetRestriction {
+    reason: String,
+    /// The restriction is permanent when not set.
+    expire_at: Option<DateTime<Utc>>,
+}
+
+/// List the active restrictions of a user. Requires the admin role.
+pub(in crate::auth) async fn ep_list_restrictions(
+    State(state): State<AuthServiceState>,
+    _admin: RequireRole<AdminRole>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<Vec<Restriction>>, RestrictionError> {
+    let restrictions = state.restriction_manager().list_restrictions(user_id).await?;
+    Ok(Json(restrictions))
+}
+
+/// Restrict a user, ex. mute in the chat. The user can still log in, the restriction is passed to the other services
+/// along the user. Requires the admin role.
+pub(in crate::auth) async fn ep_set_restriction(
+    State(state): State<AuthServiceState>,
+    admin: RequireRole<AdminRole>,
+    Path((user_id, restriction)): Path<(Uuid, String)>,
+    Json(request): Json<SetRestriction>,
+) -> Result<Json<Restriction>, RestrictionError> {
+    if !is_valid_restriction(&restriction) {
+        return Err(RestrictionError::InvalidRestriction);
+    }
+    if request.reason.is_empty() || request.reason.len() > MAX_REASON_LENGTH {
+        return Err(RestrictionError::InvalidReason);
+    }
+    if request
+        .expire_at
+        .map_or(false, |expire_at| expire_at <= state.clock().now())
+    {
+        return Err(RestrictionError::Expired);
+    }
+    if state
+        .identity_manager()
+        .find(FindIdentity::UserId(user_id))
+        .await?
+        .is_none()
+    {
+        return Err(RestrictionError::UserNotFound(user_id));
+    }
+
+    let restriction = state
+        .restriction_manager()
+        .set_restriction(
+            user_id,
+            &restriction,
+            &request.reason,
+            request.expire_at,
+            Some(admin.user_id),
+        )
+        .await?;
+    state
+        .audit_log()
+        .record(
+            Some(admin.user_id),
+            "restriction.set",
+            Some(user_id),
+            json!({
+                "restriction": restriction.restriction,
+                "reason": restriction.reason,
+                "expireAt": restriction.expire_at,
+            }),
+        )
+        .await?;
+    if let Err(err) = state
+        .user_invalidation()
+        .invalidate(user_id, UserChange::Restrictions)
+        .await
+    {
+        log::error!("Failed to publish the restriction change of {user_id}: {err}");
+    }
+
+    Ok(Json(restriction))
+}
+
+/// Lift a restriction of a user. Requires the admin role.
+pub(in crate::auth) async fn ep_remove_restriction(
+    State(state): State<AuthServiceState>,
+    admin: RequireRole<AdminRole>,
+    Path((user_id, restriction)): Path<(Uuid, String)>,
+) -> Result<StatusCode, RestrictionError> {
+    if !state
+        .restriction_manager()
+        .remove_restriction(user_id, &restriction)
+        .await?
+    {
+        return Err(RestrictionError::RestrictionNotFound);
+    }
+    state
+        .audit_log()
+        .record(
+            Some(admin.user_id),
+            "restriction.remove",
+            Some(user_id),
+            json!({ "restriction": restriction }),
+        )
+        .await?;
+    if let Err(err) = state
+        .user_invalidation()
+        .invalidate(user_id, UserChange::Restrictions)
+        .await
+    {
+        log::error!("Failed to publish the restriction change of {user_id}: {err}");
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,317 @@
+use crate::{
+    auth::{parse_public_key, AdminRole, AuthServiceState, RequireRole, Tenant},
+    db::{DBError, FindIdentity, Identity, IdentityError, IdentityKind, ServiceClientError, ServiceClientInfo},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+/// The maximum length of the name of a service.
+const MAX_NAME_LENGTH: usize = 64;
+
+/// Check the characters of a scope token (RFC 6749 section 3.3).
+pub(in crate::auth) fn is_valid_scope(scope: &str) -> bool {
+    !scope.is_empty()
+        && scope
+            .chars()
+            .all(|c| c == '!' || ('#'..='[').contains(&c) || (']'..='~').contains(&c))
+}
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum ServiceClientsError {
+    #[error("Missing or too long service name")]
+    InvalidName,
+    #[error("Invalid scope: {0}")]
+    InvalidScope(String),
+    #[error("Invalid public key, a base64url encoded Ed25519 key is expected")]
+    InvalidPublicKey,
+    #[error("Service ({0}) not found")]
+    ServiceNotFound(Uuid),
+    #[error("Failed to generate secret: {0}")]
+    TokenGenerator(String),
+    #[error(transparent)]
+    ServiceClientError(#[from] ServiceClientError),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for ServiceClientsError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            ServiceClientsError::InvalidName
+            | ServiceClientsError::InvalidScope(_)
+            | ServiceClientsError::InvalidPublicKey => StatusCode::BAD_REQUEST,
+            ServiceClientsError::ServiceNotFound(_) => StatusCode::NOT_FOUND,
+            ServiceClientsError::ServiceClientError(ServiceClientError::NameConflict) => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct CreateService {
+    name: String,
+    scopes: Vec<String>,
+    /// The public key of a service authenticating by signed assertions, no secret is generated for these services.
+    public_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct SetServiceKey {
+    public_key: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct ServiceInfo {
+    user_id: Uuid,
+    name: String,
+    scopes: Vec<String>,
+    created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    public_key: Option<String>,
+}
+
+impl From<ServiceClientInfo> for ServiceInfo {
+    fn from(info: ServiceClientInfo) -> Self {
+        Self {
+            user_id: info.user_id,
+            name: info.name,
+            scopes: info.scopes,
+            created_at: info.created_at,
+            public_key: info.public_key,
+        }
+    }
+}
+
+/// The credentials of a service, the secret is shown only once.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct ServiceCredentials {
+    client_id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_secret: Option<String>,
+}
+
+/// Check the format of an Ed25519 public key.
+fn check_public_key(public_key: &str) -> Result<(), ServiceClientsError> {
+    parse_public_key(public_key)
+        .map(|_| ())
+        .ok_or(ServiceClientsError::InvalidPublicKey)
+}
+
+impl AuthServiceState {
+    /// Find a service identity of the tenant.
+    async fn find_service(&self, tenant: &Tenant, user_id: Uuid) -> Result<Identity, ServiceClientsError> {
+        self.identity_manager()
+            .find(FindIdentity::UserId(user_id))
+            .await?
+            .filter(|identity| matches!(identity.kind, IdentityKind::Service) && identity.tenant_id == tenant.id())
+            .ok_or(ServiceClientsError::ServiceNotFound(user_id))
+    }
+}
+
+/// Create a service identity with client credentials. The secret is returned only in this response, when a public
+/// key is given the service has no secret.
+pub(in crate::auth) async fn ep_create_service(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    user: RequireRole<AdminRole>,
+    Json(request): Json<CreateService>,
+) -> Result<Json<ServiceCredentials>, ServiceClientsError> {
+    let name = request.name.trim();
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Err(ServiceClientsError::InvalidName);
+    }
+    if let Some(scope) = request.scopes.iter().find(|scope| !is_valid_scope(scope)) {
+        return Err(ServiceClientsError::InvalidScope(scope.clone()));
+    }
+    if let Some(public_key) = &request.public_key {
+        check_public_key(public_key)?;
+    }
+
+    let secret = match &request.public_key {
+        Some(_) => None,
+        None => Some(
+            state
+                .token()
+                .generate_token()
+                .map_err(|err| ServiceClientsError::TokenGenerator(format!("{err}")))?,
+        ),
+    };
+    let service = state
+        .service_client_manager()
+        .create_service(
+            tenant.id(),
+            state.identity_manager().new_user_id(),
+            name,
+            &request.scopes,
+            secret.as_deref(),
+            request.public_key.as_deref(),
+        )
+        .await?;
+
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "service.create",
+            Some(service.user_id),
+            json!({
+                "tenantId": tenant.id(),
+                "name": service.name,
+                "scopes": service.scopes,
+                "publicKey": service.public_key.is_some()
+            }),
+        )
+        .await?;
+
+    Ok(Json(ServiceCredentials {
+        client_id: service.user_id,
+        client_secret: secret,
+    }))
+}
+
+/// Get the service identities of the tenant.
+pub(in crate::auth) async fn ep_list_services(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    _user: RequireRole<AdminRole>,
+) -> Result<Json<Vec<ServiceInfo>>, ServiceClientsError> {
+    let services = state
+        .service_client_manager()
+        .list_services(tenant.id())
+        .await?
+        .into_iter()
+        .map(ServiceInfo::from)
+        .collect();
+    Ok(Json(services))
+}
+
+/// Replace the secret of a service, the previous secret is invalidated. The tokens issued already remain valid
+/// until they expire.
+pub(in crate::auth) async fn ep_rotate_service_secret(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    user: RequireRole<AdminRole>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<ServiceCredentials>, ServiceClientsError> {
+    let service = state.find_service(&tenant, user_id).await?;
+
+    let secret = state
+        .token()
+        .generate_token()
+        .map_err(|err| ServiceClientsError::TokenGenerator(format!("{err}")))?;
+    if !state.service_client_manager().set_secret(user_id, &secret).await? {
+        return Err(ServiceClientsError::ServiceNotFound(user_id));
+    }
+
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "service.rotate_secret",
+            Some(user_id),
+            json!({ "tenantId": tenant.id(), "name": service.name }),
+        )
+        .await?;
+
+    Ok(Json(ServiceCredentials {
+        client_id: user_id,
+        client_secret: Some(secret),
+    }))
+}
+
+/// Register (or replace) the public key of a service. The service can authenticate by the assertions signed by the
+/// private key, the previous key is invalidated immediately.
+pub(in crate::auth) async fn ep_set_service_key(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    user: RequireRole<AdminRole>,
+    Path(user_id): Path<Uuid>,
+    Json(request): Json<SetServiceKey>,
+) -> Result<StatusCode, ServiceClientsError> {
+    check_public_key(&request.public_key)?;
+    let service = state.find_service(&tenant, user_id).await?;
+    if !state
+        .service_client_manager()
+        .set_public_key(user_id, Some(&request.public_key))
+        .await?
+    {
+        return Err(ServiceClientsError::ServiceNotFound(user_id));
+    }
+
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "service.set_key",
+            Some(user_id),
+            json!({ "tenantId": tenant.id(), "name": service.name }),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Remove the public key of a service, the service can authenticate only by its secret, if it has any.
+pub(in crate::auth) async fn ep_delete_service_key(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    user: RequireRole<AdminRole>,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, ServiceClientsError> {
+    let service = state.find_service(&tenant, user_id).await?;
+    if !state.service_client_manager().set_public_key(user_id, None).await? {
+        return Err(ServiceClientsError::ServiceNotFound(user_id));
+    }
+
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "service.delete_key",
+            Some(user_id),
+            json!({ "tenantId": tenant.id(), "name": service.name }),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Delete a service identity with its credentials.
+pub(in crate::auth) async fn ep_delete_service(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    user: RequireRole<AdminRole>,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, ServiceClientsError> {
+    let service = state.find_service(&tenant, user_id).await?;
+    state.identity_manager().cascaded_delete(user_id).await?;
+
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "service.delete",
+            Some(user_id),
+            json!({ "tenantId": tenant.id(), "name": service.name }),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
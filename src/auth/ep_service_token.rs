@@ -0,0 +1,519 @@
+use crate::{
+    auth::{
+        is_valid_scope, AuthServiceState, ClientAssertion, Jwk, ServiceTokenClaims, ServiceTokenFormat,
+        ServiceTokenSigner, Tenant, TokenActor, STUDIO_TOKEN_PREFIX,
+    },
+    db::{DBError, FindIdentity, IdentityError, IdentityKind, ServiceClientInfo},
+};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Form, Json,
+};
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
+use chrono::Duration;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+const TOKEN_EXCHANGE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+const JWT_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:jwt";
+const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+/// The value of the session cookie of a user.
+const SESSION_TOKEN_TYPE: &str = "urn:shine:params:oauth:token-type:session";
+/// The maximum number of services in the delegation chain of an exchanged token.
+const MAX_DELEGATION_DEPTH: usize = 4;
+const JWT_BEARER_ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+/// The maximum lifetime of a client assertion in seconds, the assertions are kept until they expire to refuse the
+/// replays.
+const MAX_ASSERTION_LIFETIME: i64 = 5 * 60;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum ServiceTokenError {
+    #[error("Service tokens are not configured")]
+    Disabled,
+    #[error("Unsupported grant type")]
+    UnsupportedGrantType,
+    #[error("Invalid client credentials")]
+    InvalidClient,
+    #[error("Scope ({0}) is not allowed for the client")]
+    InvalidScope(String),
+    #[error("Missing parameter: {0}")]
+    MissingParameter(&'static str),
+    #[error("Unsupported subject token type: {0}")]
+    UnsupportedTokenType(String),
+    #[error("Subject token is invalid or has expired")]
+    InvalidSubjectToken,
+    #[error("Audience ({0}) is not a service of the tenant")]
+    InvalidTarget(String),
+    #[error("Delegation chain is too long")]
+    DelegationTooDeep,
+    #[error("Failed to generate token: {0}")]
+    TokenGenerator(String),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for ServiceTokenError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            ServiceTokenError::Disabled => StatusCode::NOT_FOUND,
+            ServiceTokenError::UnsupportedGrantType
+            | ServiceTokenError::InvalidScope(_)
+            | ServiceTokenError::MissingParameter(_)
+            | ServiceTokenError::UnsupportedTokenType(_)
+            | ServiceTokenError::InvalidSubjectToken
+            | ServiceTokenError::InvalidTarget(_)
+            | ServiceTokenError::DelegationTooDeep => StatusCode::BAD_REQUEST,
+            ServiceTokenError::InvalidClient => StatusCode::UNAUTHORIZED,
+            ServiceTokenError::TokenGenerator(_)
+            | ServiceTokenError::IdentityError(_)
+            | ServiceTokenError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+/// The client authentication of a request: the client secret, given either in the form or by the basic
+/// authorization header, or an assertion signed by the key of the client (RFC 7523 section 2.2).
+struct ClientAuthentication {
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    client_assertion_type: Option<String>,
+    client_assertion: Option<String>,
+}
+
+/// The client credentials grant (RFC 6749 section 4.4) or the token exchange (RFC 8693) request.
+#[derive(Deserialize)]
+pub(in crate::auth) struct ServiceTokenRequest {
+    grant_type: String,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    client_assertion_type: Option<String>,
+    client_assertion: Option<String>,
+    /// The requested scopes separated by space, when not given all the allowed scopes are granted.
+    scope: Option<String>,
+    /// The token of the user (or service) on whose behalf the client is acting.
+    subject_token: Option<String>,
+    subject_token_type: Option<String>,
+    /// The name of the service the exchanged token is restricted to.
+    audience: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(in crate::auth) struct ServiceTokenResponse {
+    access_token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    issued_token_type: Option<&'static str>,
+    token_type: &'static str,
+    expires_in: i64,
+    scope: String,
+}
+
+/// The token introspection request (RFC 7662), the client is authenticated by its credentials.
+#[derive(Deserialize)]
+pub(in crate::auth) struct IntrospectionRequest {
+    token: String,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    client_assertion_type: Option<String>,
+    client_assertion: Option<String>,
+}
+
+/// The state of an introspected token, only the `active` flag is present for the invalid tokens.
+#[derive(Serialize)]
+pub(in crate::auth) struct IntrospectionResponse {
+    active: bool,
+    #[serde(flatten)]
+    claims: Option<ServiceTokenClaims>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token_type: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+pub(in crate::auth) struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+/// Get the client id and secret from the basic authorization header.
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let encoded = value.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(B64.decode(encoded.trim()).ok()?).ok()?;
+    let (client_id, client_secret) = decoded.split_once(':')?;
+    Some((client_id.to_owned(), client_secret.to_owned()))
+}
+
+/// Get the requested scopes, they shall be a subset of the allowed scopes. When not given all the allowed scopes
+/// are granted.
+fn requested_scopes(scope: Option<&str>, allowed: &[String]) -> Result<Vec<String>, ServiceTokenError> {
+    match scope {
+        Some(scope) => {
+            let scopes: Vec<String> = scope.split_whitespace().map(str::to_owned).collect();
+            if let Some(scope) = scopes
+                .iter()
+                .find(|scope| !is_valid_scope(scope) || !allowed.contains(scope))
+            {
+                return Err(ServiceTokenError::InvalidScope(scope.clone()));
+            }
+            Ok(scopes)
+        }
+        None => Ok(allowed.to_vec()),
+    }
+}
+
+/// The subject of an exchanged token.
+struct TokenSubject {
+    user_id: Uuid,
+    /// The scopes of the subject token, None if the subject is not restricted (ex. a user session).
+    scopes: Option<Vec<String>>,
+    /// The audience of the subject token.
+    audience: Option<String>,
+    /// The delegation chain of the subject token.
+    actor: Option<TokenActor>,
+    expires_at: Option<i64>,
+}
+
+impl AuthServiceState {
+    async fn authenticate_client(
+        &self,
+        tenant: &Tenant,
+        headers: &HeaderMap,
+        authentication: ClientAuthentication,
+    ) -> Result<ServiceClientInfo, ServiceTokenError> {
+        let ClientAuthentication {
+            client_id,
+            client_secret,
+            client_assertion_type,
+            client_assertion,
+        } = authentication;
+        if let Some(assertion) = client_assertion {
+            if client_assertion_type.as_deref() != Some(JWT_BEARER_ASSERTION_TYPE) || client_secret.is_some() {
+                return Err(ServiceTokenError::InvalidClient);
+            }
+            return self
+                .authenticate_client_assertion(tenant, client_id.as_deref(), &assertion)
+                .await;
+        }
+
+        let (client_id, client_secret) = match (client_id, client_secret) {
+            (Some(client_id), Some(client_secret)) => (client_id, client_secret),
+            (None, None) => basic_credentials(headers).ok_or(ServiceTokenError::InvalidClient)?,
+            _ => return Err(ServiceTokenError::InvalidClient),
+        };
+        let client_id = Uuid::parse_str(&client_id).map_err(|_| ServiceTokenError::InvalidClient)?;
+        self.service_client_manager()
+            .authenticate(client_id, &client_secret)
+            .await?
+            .filter(|service| service.tenant_id == tenant.id())
+            .ok_or_else(|| {
+                log::info!("Invalid client credentials of {client_id}");
+                ServiceTokenError::InvalidClient
+            })
+    }
+
+    /// Authenticate a client by an assertion signed by its private key. The assertions are short-lived and they
+    /// can be used only once.
+    async fn authenticate_client_assertion(
+        &self,
+        tenant: &Tenant,
+        client_id: Option<&str>,
+        assertion: &str,
+    ) -> Result<ServiceClientInfo, ServiceTokenError> {
+        let signer = self.service_token_signer().ok_or(ServiceTokenError::Disabled)?;
+        let assertion = ClientAssertion::parse(assertion).ok_or(ServiceTokenError::InvalidClient)?;
+        let claims = &assertion.claims;
+        // the client is both the issuer and the subject of its assertions
+        if claims.iss != claims.sub || client_id.map_or(false, |client_id| client_id != claims.sub) {
+            return Err(ServiceTokenError::InvalidClient);
+        }
+        let client_id = Uuid::parse_str(&claims.sub).map_err(|_| ServiceTokenError::InvalidClient)?;
+
+        let service = self
+            .service_client_manager()
+            .find_service(client_id)
+            .await?
+            .filter(|service| service.tenant_id == tenant.id())
+            .filter(|service| service.public_key.as_deref().map_or(false, |key| assertion.verify(key)))
+            .ok_or_else(|| {
+                log::info!("Invalid client assertion of {client_id}");
+                ServiceTokenError::InvalidClient
+            })?;
+
+        let now = self.clock().now().timestamp();
+        if !claims.has_audience(signer.issuer()) || claims.exp <= now || claims.exp > now + MAX_ASSERTION_LIFETIME {
+            log::info!("Client assertion of {client_id} has expired or it is not for this service");
+            return Err(ServiceTokenError::InvalidClient);
+        }
+
+        // the jti is unique only for the client
+        let assertion_hash = digest::digest(&digest::SHA256, format!("{client_id}:{}", claims.jti).as_bytes());
+        let assertion_id = Uuid::from_slice(&assertion_hash.as_ref()[..16]).expect("Invalid digest length");
+        if !self
+            .ticket_redemption()
+            .redeem(assertion_id, Duration::seconds(claims.exp - now))
+            .await?
+        {
+            log::info!("Client assertion of {client_id} has been used already");
+            return Err(ServiceTokenError::InvalidClient);
+        }
+
+        Ok(service)
+    }
+
+    async fn find_token_subject(
+        &self,
+        tenant: &Tenant,
+        token: &str,
+        token_type: &str,
+    ) -> Result<TokenSubject, ServiceTokenError> {
+        match token_type {
+            JWT_TOKEN_TYPE | ACCESS_TOKEN_TYPE => {
+                let claims = self
+                    .resolve_service_token(tenant, token)
+                    .await?
+                    .ok_or(ServiceTokenError::InvalidSubjectToken)?;
+                Ok(TokenSubject {
+                    user_id: claims.sub,
+                    scopes: Some(claims.scopes()),
+                    audience: claims.aud,
+                    actor: claims.act,
+                    expires_at: Some(claims.exp),
+                })
+            }
+            SESSION_TOKEN_TYPE => {
+                let user = tenant
+                    .session_meta()
+                    .parse_user_cookie(token)
+                    .ok_or(ServiceTokenError::InvalidSubjectToken)?;
+                let user = self
+                    .session_manager()
+                    .find_session(tenant.id(), user.user_id, user.key)
+                    .await?
+                    .ok_or(ServiceTokenError::InvalidSubjectToken)?;
+                Ok(TokenSubject {
+                    user_id: user.user_id,
+                    scopes: None,
+                    audience: None,
+                    actor: None,
+                    expires_at: None,
+                })
+            }
+            _ => Err(ServiceTokenError::UnsupportedTokenType(token_type.to_owned())),
+        }
+    }
+}
+
+/// Create the access token of the claims in the configured format.
+async fn issue_token(
+    state: &AuthServiceState,
+    signer: &ServiceTokenSigner,
+    claims: &ServiceTokenClaims,
+) -> Result<String, ServiceTokenError> {
+    match signer.format() {
+        ServiceTokenFormat::Jwt => Ok(signer.sign(claims)),
+        ServiceTokenFormat::Opaque => {
+            let token = state
+                .token()
+                .generate_token()
+                .map_err(|err| ServiceTokenError::TokenGenerator(format!("{err}")))?;
+            let stored = serde_json::to_string(claims).expect("Failed to serialize token claims");
+            let duration = Duration::seconds(claims.exp - state.clock().now().timestamp());
+            state.opaque_token_store().store(&token, &stored, duration).await?;
+            Ok(token)
+        }
+    }
+}
+
+/// Issue a token for a service identity authenticated by its client credentials.
+async fn client_credentials_token(
+    state: &AuthServiceState,
+    signer: &ServiceTokenSigner,
+    service: ServiceClientInfo,
+    request: ServiceTokenRequest,
+) -> Result<ServiceTokenResponse, ServiceTokenError> {
+    let scopes = requested_scopes(request.scope.as_deref(), &service.scopes)?;
+
+    log::info!("Issuing token for service {} with scopes {scopes:?}", service.user_id);
+    let claims = signer.new_claims(service.user_id, &service.tenant_id, &scopes, state.clock().now());
+    Ok(ServiceTokenResponse {
+        access_token: issue_token(state, signer, &claims).await?,
+        issued_token_type: None,
+        token_type: "Bearer",
+        expires_in: signer.duration().num_seconds(),
+        scope: claims.scope,
+    })
+}
+
+/// Exchange the token of a user (or of a service) for a token restricted to the audience, thus the client can call
+/// the target service on behalf of the subject. The granted scopes are limited by both the subject token and the
+/// client, the client is appended to the delegation chain.
+async fn exchange_token(
+    state: &AuthServiceState,
+    signer: &ServiceTokenSigner,
+    tenant: &Tenant,
+    service: ServiceClientInfo,
+    request: ServiceTokenRequest,
+) -> Result<ServiceTokenResponse, ServiceTokenError> {
+    let subject_token = request
+        .subject_token
+        .ok_or(ServiceTokenError::MissingParameter("subject_token"))?;
+    let subject_token_type = request
+        .subject_token_type
+        .ok_or(ServiceTokenError::MissingParameter("subject_token_type"))?;
+    let audience = request
+        .audience
+        .ok_or(ServiceTokenError::MissingParameter("audience"))?;
+
+    let subject = state
+        .find_token_subject(tenant, &subject_token, &subject_token_type)
+        .await?;
+    // a token restricted to an audience can be exchanged only by that service
+    if subject.audience.as_ref().map_or(false, |aud| *aud != service.name) {
+        log::info!(
+            "Service {} is not the audience ({:?}) of the subject token",
+            service.user_id,
+            subject.audience
+        );
+        return Err(ServiceTokenError::InvalidSubjectToken);
+    }
+
+    let target = state
+        .identity_manager()
+        .find(FindIdentity::Name {
+            tenant_id: tenant.id(),
+            name: &audience,
+        })
+        .await?
+        .filter(|identity| matches!(identity.kind, IdentityKind::Service))
+        .ok_or_else(|| ServiceTokenError::InvalidTarget(audience.clone()))?;
+
+    let allowed: Vec<String> = match &subject.scopes {
+        Some(subject_scopes) => service
+            .scopes
+            .iter()
+            .filter(|scope| subject_scopes.contains(scope))
+            .cloned()
+            .collect(),
+        None => service.scopes.clone(),
+    };
+    let scopes = requested_scopes(request.scope.as_deref(), &allowed)?;
+
+    let actor = TokenActor {
+        sub: service.user_id,
+        act: subject.actor.map(Box::new),
+    };
+    let chain = actor.chain();
+    if chain.len() > MAX_DELEGATION_DEPTH {
+        return Err(ServiceTokenError::DelegationTooDeep);
+    }
+
+    let now = state.clock().now();
+    let mut claims = signer.new_claims(subject.user_id, tenant.id(), &scopes, now);
+    claims.aud = Some(target.name);
+    claims.act = Some(actor);
+    // the exchanged token shall not outlive the subject token
+    if let Some(expires_at) = subject.expires_at {
+        claims.exp = claims.exp.min(expires_at);
+    }
+
+    state
+        .audit_log()
+        .record(
+            Some(service.user_id),
+            "token.exchange",
+            Some(subject.user_id),
+            json!({ "tenantId": tenant.id(), "audience": claims.aud, "scope": claims.scope, "actors": chain }),
+        )
+        .await?;
+
+    log::info!(
+        "Issuing token for {} to service {} with audience {audience} and scopes {scopes:?}",
+        subject.user_id,
+        service.user_id
+    );
+    Ok(ServiceTokenResponse {
+        access_token: issue_token(state, signer, &claims).await?,
+        issued_token_type: Some(ACCESS_TOKEN_TYPE),
+        token_type: "Bearer",
+        expires_in: claims.exp - now.timestamp(),
+        scope: claims.scope,
+    })
+}
+
+/// Issue a token for a service identity authenticated by its client credentials, either for the service itself or
+/// by exchanging the token of a subject.
+pub(in crate::auth) async fn ep_service_token(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Form(mut request): Form<ServiceTokenRequest>,
+) -> Result<Response, ServiceTokenError> {
+    let signer = state.service_token_signer().ok_or(ServiceTokenError::Disabled)?;
+    if request.grant_type != "client_credentials" && request.grant_type != TOKEN_EXCHANGE_GRANT_TYPE {
+        return Err(ServiceTokenError::UnsupportedGrantType);
+    }
+
+    let authentication = ClientAuthentication {
+        client_id: request.client_id.take(),
+        client_secret: request.client_secret.take(),
+        client_assertion_type: request.client_assertion_type.take(),
+        client_assertion: request.client_assertion.take(),
+    };
+    let service = state.authenticate_client(&tenant, &headers, authentication).await?;
+
+    let response = if request.grant_type == TOKEN_EXCHANGE_GRANT_TYPE {
+        exchange_token(&state, signer, &tenant, service, request).await?
+    } else {
+        client_credentials_token(&state, signer, service, request).await?
+    };
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(response)).into_response())
+}
+
+/// Get the state and the claims of a token issued by the service token endpoint or of an API token of a studio
+/// (RFC 7662). The opaque tokens can be resolved only by this endpoint.
+pub(in crate::auth) async fn ep_introspect_service_token(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Form(request): Form<IntrospectionRequest>,
+) -> Result<Response, ServiceTokenError> {
+    if state.service_token_signer().is_none() {
+        return Err(ServiceTokenError::Disabled);
+    }
+    let authentication = ClientAuthentication {
+        client_id: request.client_id,
+        client_secret: request.client_secret,
+        client_assertion_type: request.client_assertion_type,
+        client_assertion: request.client_assertion,
+    };
+    state.authenticate_client(&tenant, &headers, authentication).await?;
+
+    let claims = if request.token.starts_with(STUDIO_TOKEN_PREFIX) {
+        state.resolve_studio_token(&tenant, &request.token).await?
+    } else {
+        state.resolve_service_token(&tenant, &request.token).await?
+    };
+    let response = IntrospectionResponse {
+        active: claims.is_some(),
+        token_type: claims.as_ref().map(|_| "Bearer"),
+        claims,
+    };
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(response)).into_response())
+}
+
+/// Get the public keys to verify the service tokens offline.
+pub(in crate::auth) async fn ep_get_service_token_keys(
+    State(state): State<AuthServiceState>,
+) -> Result<Json<JwkSet>, StatusCode> {
+    let signer = state.service_token_signer().ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(JwkSet {
+        keys: vec![signer.jwk()],
+    }))
+}
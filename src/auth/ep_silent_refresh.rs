@@ -0,0 +1,100 @@
+use crate::{
+    auth::{AuthServiceState, AuthSession, ClientInfo},
+    db::{DBError, DBSessionError, IdentityError},
+    logging::user_hash,
+};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum SilentRefreshError {
+    #[error(transparent)]
+    SessionError(#[from] DBSessionError),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for SilentRefreshError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            SilentRefreshError::SessionError(DBSessionError::SessionLimitReached) => StatusCode::CONFLICT,
+            SilentRefreshError::SessionError(DBSessionError::AccountExpired) => StatusCode::FORBIDDEN,
+            SilentRefreshError::SessionError(_)
+            | SilentRefreshError::IdentityError(_)
+            | SilentRefreshError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+/// Renew the session of the user without a page navigation, ex. from a hidden iframe or by a fetch of a single page
+/// app. An active session is extended, otherwise a new session is created from the login token. The response is
+/// either a `204` with the refreshed cookies or a `401` with the stale cookies removed, it never redirects.
+pub(in crate::auth) async fn ep_silent_refresh(
+    State(state): State<AuthServiceState>,
+    client_info: ClientInfo,
+    mut auth_session: AuthSession,
+) -> Result<Response, SilentRefreshError> {
+    if let Some((user_id, session_key)) = auth_session.user.as_ref().map(|u| (u.user_id, u.key)) {
+        let session = state
+            .session_manager()
+            .find_session(auth_session.tenant().id(), user_id, session_key)
+            .await?;
+        if session.is_some() && state.session_manager().touch(user_id, session_key).await? {
+            log::debug!("Session of {user_id} has been extended");
+            return Ok(refreshed(auth_session));
+        }
+        auth_session.user = None;
+    }
+
+    let identity = match auth_session.token_login.as_ref().map(|t| (t.user_id, t.token.clone())) {
+        Some((user_id, token)) => state
+            .find_login_token(auth_session.tenant(), user_id, &token)
+            .await?
+            .filter(|identity| identity.user_id == user_id),
+        None => None,
+    };
+    let identity = match identity {
+        Some(identity) => identity,
+        None => {
+            auth_session.token_login = None;
+            return Ok((
+                StatusCode::UNAUTHORIZED,
+                [(header::CACHE_CONTROL, "no-store")],
+                auth_session,
+            )
+                .into_response());
+        }
+    };
+
+    tracing::info!(
+        provider = "token",
+        user = %user_hash(identity.user_id),
+        outcome = "silentLogin",
+        "Silent login with token"
+    );
+    let user = state.create_session(&identity, None).await?;
+    state
+        .check_login_device(auth_session.tenant(), &identity, &user, &client_info)
+        .await;
+    state.restore_deleted_account(&identity).await;
+    auth_session.user = Some(user);
+
+    Ok(refreshed(auth_session))
+}
+
+fn refreshed(auth_session: AuthSession) -> Response {
+    (
+        StatusCode::NO_CONTENT,
+        [(header::CACHE_CONTROL, "no-store")],
+        auth_session,
+    )
+        .into_response()
+}
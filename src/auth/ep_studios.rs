@@ -0,0 +1,614 @@
+use crate::{
+    auth::{is_valid_scope, ApiUser, AuthServiceState, Tenant, STUDIO_TOKEN_PREFIX},
+    db::{
+        studio_resource, DBError, DeletionError, FindIdentity, Identity, IdentityError, IdentityKind, StudioError,
+        StudioTokenInfo, UserChange, STUDIO_MEMBER, STUDIO_OWNER,
+    },
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+/// The maximum length of the name of a studio and of a token.
+const MAX_NAME_LENGTH: usize = 64;
+/// The validity of the studio tokens when no expiration is given.
+const DEFAULT_TOKEN_DURATION_DAYS: i64 = 90;
+const MAX_TOKEN_DURATION_DAYS: i64 = 365;
+/// The time the new owner has to accept a transfer of the ownership.
+const TRANSFER_DURATION_DAYS: i64 = 7;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum StudiosError {
+    #[error("Missing or too long name")]
+    InvalidName,
+    #[error("Invalid scope: {0}")]
+    InvalidScope(String),
+    #[error("Expiration shall be in the future and at most {MAX_TOKEN_DURATION_DAYS} days ahead")]
+    InvalidExpiration,
+    #[error("Studio ({0}) not found")]
+    StudioNotFound(Uuid),
+    #[error("User ({0}) not found")]
+    UserNotFound(Uuid),
+    #[error("Token ({0}) not found")]
+    TokenNotFound(Uuid),
+    #[error("Only the members of the studio are allowed")]
+    NotMember,
+    #[error("Only the owners of the studio are allowed")]
+    NotOwner,
+    #[error("The owner cannot be removed from the studio")]
+    OwnerNotRemovable,
+    #[error("The ownership can be transferred only to an other member of the studio")]
+    InvalidNewOwner,
+    #[error("No pending transfer of the studio ({0})")]
+    TransferNotFound(Uuid),
+    #[error("Studio ({0}) is not marked for deletion")]
+    NotDeleted(Uuid),
+    #[error("Studio is under legal hold")]
+    LegalHold,
+    #[error("Failed to generate token: {0}")]
+    TokenGenerator(String),
+    #[error(transparent)]
+    StudioError(#[from] StudioError),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for StudiosError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            StudiosError::InvalidName
+            | StudiosError::InvalidScope(_)
+            | StudiosError::InvalidExpiration
+            | StudiosError::OwnerNotRemovable
+            | StudiosError::InvalidNewOwner
+            | StudiosError::NotDeleted(_) => StatusCode::BAD_REQUEST,
+            StudiosError::StudioNotFound(_)
+            | StudiosError::UserNotFound(_)
+            | StudiosError::TokenNotFound(_)
+            | StudiosError::TransferNotFound(_) => StatusCode::NOT_FOUND,
+            StudiosError::NotMember | StudiosError::NotOwner => StatusCode::FORBIDDEN,
+            StudiosError::LegalHold => StatusCode::CONFLICT,
+            StudiosError::StudioError(StudioError::NameConflict) => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+impl From<DeletionError> for StudiosError {
+    fn from(err: DeletionError) -> Self {
+        match err {
+            DeletionError::LegalHold => StudiosError::LegalHold,
+            DeletionError::IdentityError(err) => StudiosError::IdentityError(err),
+            DeletionError::DBError(err) => StudiosError::DBError(err),
+        }
+    }
+}
+
+fn check_name(name: &str) -> Result<&str, StudiosError> {
+    let name = name.trim();
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Err(StudiosError::InvalidName);
+    }
+    Ok(name)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct CreateStudio {
+    name: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct StudioInfo {
+    studio_id: Uuid,
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub(in crate::auth) struct StudioPath {
+    studio_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub(in crate::auth) struct StudioMemberPath {
+    studio_id: Uuid,
+    user_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub(in crate::auth) struct StudioTokenPath {
+    studio_id: Uuid,
+    token_id: Uuid,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct TransferStudio {
+    new_owner_id: Uuid,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct StudioTransfer {
+    new_owner_id: Uuid,
+    expire_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct StudioDeletion {
+    /// The time the studio is purged, None if it was deleted immediately.
+    purge_after: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct CreateStudioToken {
+    name: String,
+    scopes: Vec<String>,
+    expire_at: Option<DateTime<Utc>>,
+}
+
+/// The created token, it is shown only once.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct StudioTokenCredentials {
+    token_id: Uuid,
+    token: String,
+    expire_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct StudioToken {
+    token_id: Uuid,
+    name: String,
+    scopes: Vec<String>,
+    created_at: DateTime<Utc>,
+    created_by: Option<Uuid>,
+    expire_at: DateTime<Utc>,
+    is_expired: bool,
+}
+
+impl StudioToken {
+    fn new(token: StudioTokenInfo, now: DateTime<Utc>) -> Self {
+        Self {
+            token_id: token.token_id,
+            name: token.name,
+            scopes: token.scopes,
+            created_at: token.created_at,
+            created_by: token.created_by,
+            is_expired: token.expire_at <= now,
+            expire_at: token.expire_at,
+        }
+    }
+}
+
+impl AuthServiceState {
+    /// Find a studio of the tenant and check if the user is allowed the action (member or owner) on it.
+    async fn find_studio_for(
+        &self,
+        tenant: &Tenant,
+        studio_id: Uuid,
+        user_id: Uuid,
+        action: &str,
+    ) -> Result<Identity, StudiosError> {
+        let studio = self
+            .identity_manager()
+            .find(FindIdentity::UserId(studio_id))
+            .await?
+            .filter(|identity| matches!(identity.kind, IdentityKind::Studio) && identity.tenant_id == tenant.id())
+            .ok_or(StudiosError::StudioNotFound(studio_id))?;
+
+        if !self
+            .permission_manager()
+            .has_permission(user_id, action, &studio_resource(studio_id))
+            .await?
+        {
+            return Err(if action == STUDIO_OWNER {
+                StudiosError::NotOwner
+            } else {
+                StudiosError::NotMember
+            });
+        }
+        Ok(studio)
+    }
+
+    /// Notify the dependent services about the changed studio memberships, a failure is only logged.
+    async fn publish_studio_change(&self, user_ids: &[Uuid]) {
+        for user_id in user_ids {
+            if let Err(err) = self.user_invalidation().invalidate(*user_id, UserChange::Studios).await {
+                log::error!("Failed to publish the studio change of {user_id}: {err}");
+            }
+        }
+    }
+}
+
+/// Create a studio, the current user becomes its owner.
+pub(in crate::auth) async fn ep_create_studio(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    ApiUser(user): ApiUser,
+    Json(request): Json<CreateStudio>,
+) -> Result<Json<StudioInfo>, StudiosError> {
+    let name = check_name(&request.name)?;
+    let studio_id = state.identity_manager().new_user_id();
+    state
+        .studio_manager()
+        .create_studio(tenant.id(), studio_id, name, user.user_id)
+        .await?;
+
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "studio.create",
+            Some(studio_id),
+            json!({ "tenantId": tenant.id(), "name": name }),
+        )
+        .await?;
+
+    Ok(Json(StudioInfo {
+        studio_id,
+        name: name.to_owned(),
+    }))
+}
+
+/// Add a user to the members of a studio. Requires the owner of the studio.
+pub(in crate::auth) async fn ep_add_studio_member(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    ApiUser(user): ApiUser,
+    Path(path): Path<StudioMemberPath>,
+) -> Result<StatusCode, StudiosError> {
+    state
+        .find_studio_for(&tenant, path.studio_id, user.user_id, STUDIO_OWNER)
+        .await?;
+    state
+        .identity_manager()
+        .find(FindIdentity::UserId(path.user_id))
+        .await?
+        .filter(|identity| matches!(identity.kind, IdentityKind::User) && identity.tenant_id == tenant.id())
+        .ok_or(StudiosError::UserNotFound(path.user_id))?;
+
+    state
+        .permission_manager()
+        .grant(
+            path.user_id,
+            STUDIO_MEMBER,
+            &studio_resource(path.studio_id),
+            Some(user.user_id),
+        )
+        .await?;
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "studio.add_member",
+            Some(path.user_id),
+            json!({ "studioId": path.studio_id }),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Remove a member of a studio. Requires the owner of the studio, the owner cannot be removed.
+pub(in crate::auth) async fn ep_remove_studio_member(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    ApiUser(user): ApiUser,
+    Path(path): Path<StudioMemberPath>,
+) -> Result<StatusCode, StudiosError> {
+    state
+        .find_studio_for(&tenant, path.studio_id, user.user_id, STUDIO_OWNER)
+        .await?;
+    let resource = studio_resource(path.studio_id);
+    if state
+        .permission_manager()
+        .has_permission(path.user_id, STUDIO_OWNER, &resource)
+        .await?
+    {
+        return Err(StudiosError::OwnerNotRemovable);
+    }
+
+    if !state
+        .permission_manager()
+        .revoke(path.user_id, STUDIO_MEMBER, &resource)
+        .await?
+    {
+        return Err(StudiosError::UserNotFound(path.user_id));
+    }
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "studio.remove_member",
+            Some(path.user_id),
+            json!({ "studioId": path.studio_id }),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Create an API token acting on behalf of the studio. Requires the owner of the studio, the token is returned only
+/// in this response.
+pub(in crate::auth) async fn ep_create_studio_token(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    ApiUser(user): ApiUser,
+    Path(path): Path<StudioPath>,
+    Json(request): Json<CreateStudioToken>,
+) -> Result<Json<StudioTokenCredentials>, StudiosError> {
+    let name = check_name(&request.name)?;
+    if let Some(scope) = request.scopes.iter().find(|scope| !is_valid_scope(scope)) {
+        return Err(StudiosError::InvalidScope(scope.clone()));
+    }
+    let now = state.clock().now();
+    let expire_at = request
+        .expire_at
+        .unwrap_or(now + Duration::days(DEFAULT_TOKEN_DURATION_DAYS));
+    if expire_at <= now || expire_at > now + Duration::days(MAX_TOKEN_DURATION_DAYS) {
+        return Err(StudiosError::InvalidExpiration);
+    }
+    state
+        .find_studio_for(&tenant, path.studio_id, user.user_id, STUDIO_OWNER)
+        .await?;
+
+    let token = state
+        .token()
+        .generate_token()
+        .map_err(|err| StudiosError::TokenGenerator(format!("{err}")))?;
+    let token = format!("{STUDIO_TOKEN_PREFIX}{token}");
+    let token_id = state
+        .studio_manager()
+        .create_token(path.studio_id, &token, name, &request.scopes, user.user_id, expire_at)
+        .await?;
+
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "studio.create_token",
+            Some(path.studio_id),
+            json!({ "tokenId": token_id, "name": name, "scopes": request.scopes, "expireAt": expire_at }),
+        )
+        .await?;
+
+    Ok(Json(StudioTokenCredentials {
+        token_id,
+        token,
+        expire_at,
+    }))
+}
+
+/// List the API tokens of a studio. Requires a member of the studio.
+pub(in crate::auth) async fn ep_list_studio_tokens(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    ApiUser(user): ApiUser,
+    Path(path): Path<StudioPath>,
+) -> Result<Json<Vec<StudioToken>>, StudiosError> {
+    state
+        .find_studio_for(&tenant, path.studio_id, user.user_id, STUDIO_MEMBER)
+        .await?;
+
+    let now = state.clock().now();
+    let tokens = state
+        .studio_manager()
+        .list_tokens(path.studio_id)
+        .await?
+        .into_iter()
+        .map(|token| StudioToken::new(token, now))
+        .collect();
+    Ok(Json(tokens))
+}
+
+/// Revoke an API token of a studio, it is rejected immediately. Any member of the studio can revoke the tokens,
+/// ex. when a token has been leaked.
+pub(in crate::auth) async fn ep_delete_studio_token(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    ApiUser(user): ApiUser,
+    Path(path): Path<StudioTokenPath>,
+) -> Result<StatusCode, StudiosError> {
+    state
+        .find_studio_for(&tenant, path.studio_id, user.user_id, STUDIO_MEMBER)
+        .await?;
+
+    if !state
+        .studio_manager()
+        .delete_token(path.studio_id, path.token_id)
+        .await?
+    {
+        return Err(StudiosError::TokenNotFound(path.token_id));
+    }
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "studio.revoke_token",
+            Some(path.studio_id),
+            json!({ "tokenId": path.token_id }),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Request the transfer of the ownership to an other member of the studio. Requires the owner of the studio, the
+/// ownership changes only when the new owner accepts the transfer.
+pub(in crate::auth) async fn ep_transfer_studio(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    ApiUser(user): ApiUser,
+    Path(path): Path<StudioPath>,
+    Json(request): Json<TransferStudio>,
+) -> Result<Json<StudioTransfer>, StudiosError> {
+    state
+        .find_studio_for(&tenant, path.studio_id, user.user_id, STUDIO_OWNER)
+        .await?;
+    if request.new_owner_id == user.user_id
+        || !state
+            .permission_manager()
+            .has_permission(request.new_owner_id, STUDIO_MEMBER, &studio_resource(path.studio_id))
+            .await?
+    {
+        return Err(StudiosError::InvalidNewOwner);
+    }
+
+    let expire_at = state.clock().now() + Duration::days(TRANSFER_DURATION_DAYS);
+    state
+        .studio_manager()
+        .request_transfer(path.studio_id, request.new_owner_id, user.user_id, expire_at)
+        .await?;
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "studio.request_transfer",
+            Some(path.studio_id),
+            json!({ "newOwnerId": request.new_owner_id, "expireAt": expire_at }),
+        )
+        .await?;
+
+    Ok(Json(StudioTransfer {
+        new_owner_id: request.new_owner_id,
+        expire_at,
+    }))
+}
+
+/// Accept the pending transfer of a studio by the new owner. The previous owner remains a member of the studio.
+pub(in crate::auth) async fn ep_accept_studio_transfer(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    ApiUser(user): ApiUser,
+    Path(path): Path<StudioPath>,
+) -> Result<StatusCode, StudiosError> {
+    // the membership is checked again, the new owner could have been removed since the request
+    state
+        .find_studio_for(&tenant, path.studio_id, user.user_id, STUDIO_MEMBER)
+        .await?;
+
+    let previous_owners = state
+        .studio_manager()
+        .accept_transfer(path.studio_id, user.user_id)
+        .await?
+        .ok_or(StudiosError::TransferNotFound(path.studio_id))?;
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "studio.transfer",
+            Some(path.studio_id),
+            json!({ "previousOwners": previous_owners }),
+        )
+        .await?;
+
+    let mut changed = previous_owners;
+    changed.push(user.user_id);
+    state.publish_studio_change(&changed).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Cancel the pending transfer of a studio by the owner, or decline it by the new owner.
+pub(in crate::auth) async fn ep_cancel_studio_transfer(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    ApiUser(user): ApiUser,
+    Path(path): Path<StudioPath>,
+) -> Result<StatusCode, StudiosError> {
+    state
+        .find_studio_for(&tenant, path.studio_id, user.user_id, STUDIO_MEMBER)
+        .await?;
+    let transfer = state
+        .studio_manager()
+        .find_transfer(path.studio_id)
+        .await?
+        .ok_or(StudiosError::TransferNotFound(path.studio_id))?;
+    if transfer.new_owner_id != user.user_id {
+        state
+            .find_studio_for(&tenant, path.studio_id, user.user_id, STUDIO_OWNER)
+            .await?;
+    }
+
+    if !state.studio_manager().cancel_transfer(path.studio_id).await? {
+        return Err(StudiosError::TransferNotFound(path.studio_id));
+    }
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "studio.cancel_transfer",
+            Some(path.studio_id),
+            json!({ "newOwnerId": transfer.new_owner_id }),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Delete a studio. Requires the owner of the studio. The studio is marked for deletion for the grace period of the
+/// accounts, its tokens are rejected immediately and it can be restored by the owner until the purge. The purge
+/// removes the members and publishes the changes on the `user-invalidation` channel.
+pub(in crate::auth) async fn ep_delete_studio(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    ApiUser(user): ApiUser,
+    Path(path): Path<StudioPath>,
+) -> Result<Json<StudioDeletion>, StudiosError> {
+    state
+        .find_studio_for(&tenant, path.studio_id, user.user_id, STUDIO_OWNER)
+        .await?;
+
+    let purge_after = state.deletion_manager().delete(path.studio_id, None).await?;
+    state.studio_manager().cancel_transfer(path.studio_id).await?;
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "studio.delete",
+            Some(path.studio_id),
+            json!({ "purgeAfter": purge_after }),
+        )
+        .await?;
+
+    Ok(Json(StudioDeletion { purge_after }))
+}
+
+/// Restore a studio marked for deletion. Requires the owner of the studio.
+pub(in crate::auth) async fn ep_restore_studio(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    ApiUser(user): ApiUser,
+    Path(path): Path<StudioPath>,
+) -> Result<StatusCode, StudiosError> {
+    state
+        .find_studio_for(&tenant, path.studio_id, user.user_id, STUDIO_OWNER)
+        .await?;
+
+    if !state.deletion_manager().restore(path.studio_id).await? {
+        return Err(StudiosError::NotDeleted(path.studio_id));
+    }
+    state
+        .audit_log()
+        .record(Some(user.user_id), "studio.restore", Some(path.studio_id), json!({}))
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
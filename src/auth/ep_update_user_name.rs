@@ -0,0 +1,88 @@
+use crate::{
+    auth::{ApiUser, AuthServiceState},
+    db::{IdentityError, NameGeneratorError, UserChange},
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum UpdateUserNameError {
+    #[error("User ({0}) not found")]
+    UserNotFound(Uuid),
+    #[error("Name ({0}) is not allowed")]
+    NameRejected(String),
+    #[error("Name already taken")]
+    NameConflict,
+    #[error(transparent)]
+    NameGeneratorError(NameGeneratorError),
+    #[error(transparent)]
+    IdentityError(IdentityError),
+}
+
+impl From<NameGeneratorError> for UpdateUserNameError {
+    fn from(err: NameGeneratorError) -> Self {
+        match err {
+            NameGeneratorError::NameRejected(name) => UpdateUserNameError::NameRejected(name),
+            err => UpdateUserNameError::NameGeneratorError(err),
+        }
+    }
+}
+
+impl From<IdentityError> for UpdateUserNameError {
+    fn from(err: IdentityError) -> Self {
+        match err {
+            IdentityError::NameConflict => UpdateUserNameError::NameConflict,
+            err => UpdateUserNameError::IdentityError(err),
+        }
+    }
+}
+
+impl IntoResponse for UpdateUserNameError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            UpdateUserNameError::UserNotFound(_) => StatusCode::NOT_FOUND,
+            UpdateUserNameError::NameRejected(_) => StatusCode::BAD_REQUEST,
+            UpdateUserNameError::NameConflict => StatusCode::CONFLICT,
+            UpdateUserNameError::NameGeneratorError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            UpdateUserNameError::IdentityError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct UpdateUserName {
+    name: String,
+}
+
+/// Change the name of the current user. The name of the active sessions are updated on the next login.
+pub(in crate::auth) async fn ep_update_user_name(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+    Json(request): Json<UpdateUserName>,
+) -> Result<StatusCode, UpdateUserNameError> {
+    let name = request.name.trim();
+    state.name_generator().validate_name(name)?;
+
+    if !state.identity_manager().update_name(user.user_id, name).await? {
+        return Err(UpdateUserNameError::UserNotFound(user.user_id));
+    }
+    if let Err(err) = state
+        .user_invalidation()
+        .invalidate(user.user_id, UserChange::Name)
+        .await
+    {
+        log::error!("Failed to publish the name change of {}: {err}", user.user_id);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,137 @@
+use crate::{
+    auth::{ApiUser, AuthServiceState},
+    db::{AuditEntry, DBError},
+};
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error as ThisError;
+
+/// The actions of the audit log a user can download about their own account. The administrative actions (ex.
+/// roles, legal holds, support logins) are not shared with the users.
+const USER_SECURITY_EVENTS: &[&str] = &[
+    "login",
+    "login.risk",
+    "login.locked",
+    "session.evicted",
+    "token.create",
+    "device.trust",
+    "identity.restore",
+    "consent.update",
+];
+
+/// The maximum number of the downloaded events.
+const MAX_EVENTS: usize = 1000;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum UserAuditError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for UserAuditError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            UserAuditError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) enum AuditFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Deserialize)]
+pub(in crate::auth) struct AuditQuery {
+    format: Option<AuditFormat>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct UserSecurityEvent {
+    time: DateTime<Utc>,
+    action: String,
+    details: Value,
+}
+
+impl From<AuditEntry> for UserSecurityEvent {
+    fn from(entry: AuditEntry) -> Self {
+        Self {
+            time: entry.created_at,
+            action: entry.action,
+            details: entry.details,
+        }
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+fn to_csv(events: &[UserSecurityEvent]) -> String {
+    let mut csv = String::from("time,action,details\r\n");
+    for event in events {
+        csv.push_str(&csv_field(&event.time.to_rfc3339()));
+        csv.push(',');
+        csv.push_str(&csv_field(&event.action));
+        csv.push(',');
+        csv.push_str(&csv_field(&event.details.to_string()));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+/// Download the security events of the current user, the newest first. Only the events of the user's own
+/// account are returned.
+pub(in crate::auth) async fn ep_get_user_audit(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+    Query(query): Query<AuditQuery>,
+) -> Result<Response, UserAuditError> {
+    let events: Vec<UserSecurityEvent> = state
+        .audit_log()
+        .list_target_entries(user.user_id, USER_SECURITY_EVENTS, MAX_EVENTS)
+        .await?
+        .into_iter()
+        .map(UserSecurityEvent::from)
+        .collect();
+
+    let response = match query.format.unwrap_or_default() {
+        AuditFormat::Json => (
+            [(
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"security-events.json\"",
+            )],
+            Json(events),
+        )
+            .into_response(),
+        AuditFormat::Csv => (
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"security-events.csv\"",
+                ),
+            ],
+            to_csv(&events),
+        )
+            .into_response(),
+    };
+    Ok(response)
+}
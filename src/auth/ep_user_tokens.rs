@@ -0,0 +1,113 @@
+use crate::{
+    auth::{ApiUser, AuthServiceState},
+    db::{DBError, IdentityError, TokenKind},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum UserTokensError {
+    #[error("Token ({0}) not found")]
+    TokenNotFound(Uuid),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for UserTokensError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            UserTokensError::TokenNotFound(_) => StatusCode::NOT_FOUND,
+            UserTokensError::IdentityError(_) | UserTokensError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct UserToken {
+    token_id: Uuid,
+    kind: TokenKind,
+    name: Option<String>,
+    created_at: DateTime<Utc>,
+    expire_at: DateTime<Utc>,
+    is_expired: bool,
+    last_used: Option<DateTime<Utc>>,
+    creation_ip: Option<IpAddr>,
+    user_agent: Option<String>,
+}
+
+/// List the login tokens of the current user.
+pub(in crate::auth) async fn ep_get_user_tokens(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+) -> Result<Json<Vec<UserToken>>, UserTokensError> {
+    let tokens = state
+        .identity_manager()
+        .list_tokens(user.user_id)
+        .await?
+        .into_iter()
+        .map(|token| UserToken {
+            token_id: token.token_id,
+            kind: token.kind,
+            name: token.name,
+            created_at: token.created_at,
+            expire_at: token.expire_at,
+            is_expired: token.is_expired,
+            last_used: token.last_used,
+            creation_ip: token.creation_ip,
+            user_agent: token.user_agent,
+        })
+        .collect();
+    Ok(Json(tokens))
+}
+
+#[derive(Deserialize)]
+pub(in crate::auth) struct TokenPath {
+    token_id: Uuid,
+}
+
+/// Revoke a login token of the current user.
+pub(in crate::auth) async fn ep_delete_user_token(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+    Path(path): Path<TokenPath>,
+) -> Result<StatusCode, UserTokensError> {
+    if !state
+        .identity_manager()
+        .delete_token_by_id(user.user_id, path.token_id)
+        .await?
+    {
+        return Err(UserTokensError::TokenNotFound(path.token_id));
+    }
+    state
+        .token_revocation()
+        .revoke_token(path.token_id, state.token().max_duration())
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Revoke all the login tokens of the current user.
+pub(in crate::auth) async fn ep_delete_user_tokens(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+) -> Result<StatusCode, UserTokensError> {
+    state.identity_manager().delete_all_tokens(user.user_id).await?;
+    state
+        .token_revocation()
+        .revoke_user(user.user_id, state.token().max_duration())
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
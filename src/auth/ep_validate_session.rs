@@ -0,0 +1,135 @@
+use crate::{
+    auth::{AuthServiceState, Tenant, USER_CONTEXT_HEADER},
+    db::{DBError, SessionAuthContext},
+};
+use axum::{
+    extract::State,
+    http::{HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use shine_service::service::SessionKey;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum ValidateSessionError {
+    #[error("Either the session cookie or the user and session key shall be given")]
+    InvalidRequest,
+    #[error("Session is invalid or has expired")]
+    InvalidSession,
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for ValidateSessionError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            ValidateSessionError::InvalidRequest => StatusCode::BAD_REQUEST,
+            ValidateSessionError::InvalidSession => StatusCode::UNAUTHORIZED,
+            ValidateSessionError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct ValidateSession {
+    /// The value of the signed session cookie.
+    session_cookie: Option<String>,
+    user_id: Option<Uuid>,
+    session_key: Option<SessionKey>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct ValidatedUser {
+    user_id: Uuid,
+    name: String,
+    session_start: DateTime<Utc>,
+    roles: Vec<String>,
+    /// The active restrictions of the user, ex. `chat:muted`, to be honored by the service.
+    restrictions: Vec<String>,
+    /// The authentication context reported by the external provider at the login.
+    auth_context: Option<SessionAuthContext>,
+    /// Version of the user, the invalidations published with a newer version shall drop the cached copies.
+    version: u64,
+}
+
+/// Resolve a session for the other services. The session is given either by the value of the session cookie or by
+/// the user and session key, it is accepted only if it is still active. This endpoint is available only from the
+/// allowed networks.
+/// When the user context signing is enabled, the response also has the signed context in the `X-Shine-User`
+/// header to be forwarded to the downstream services.
+pub(in crate::auth) async fn ep_validate_session(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    Json(request): Json<ValidateSession>,
+) -> Result<Response, ValidateSessionError> {
+    let (user_id, session_key) = match (request.session_cookie, request.user_id, request.session_key) {
+        (Some(cookie), None, None) => {
+            let user = tenant
+                .session_meta()
+                .parse_user_cookie(&cookie)
+                .ok_or(ValidateSessionError::InvalidSession)?;
+            (user.user_id, user.key)
+        }
+        (None, Some(user_id), Some(session_key)) => (user_id, session_key),
+        _ => return Err(ValidateSessionError::InvalidRequest),
+    };
+
+    let user = state
+        .session_manager()
+        .find_session(tenant.id(), user_id, session_key)
+        .await?
+        .ok_or(ValidateSessionError::InvalidSession)?;
+    let roles = state.user_roles(&user).await?;
+    let restrictions = state.restriction_manager().active_restrictions(user.user_id).await?;
+    let auth_context = state
+        .session_manager()
+        .find_session_auth_context(user.user_id, user.key)
+        .await?;
+    let version = state.user_invalidation().version(user.user_id).await?;
+
+    let user_context = state
+        .user_context_signer()
+        .map(|signer| signer.sign(user.user_id, &user.name, &roles, &restrictions, user.session_start));
+
+    let mut response = Json(ValidatedUser {
+        user_id: user.user_id,
+        name: user.name,
+        session_start: user.session_start,
+        roles,
+        restrictions,
+        auth_context,
+        version,
+    })
+    .into_response();
+    if let Some(user_context) = user_context {
+        let value = HeaderValue::from_str(&user_context).expect("Signed user context shall be a valid header");
+        response.headers_mut().insert(USER_CONTEXT_HEADER, value);
+    }
+    Ok(response)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct UserContextKey {
+    algorithm: &'static str,
+    public_key: String,
+}
+
+/// Get the public key to verify the signed user contexts offline.
+pub(in crate::auth) async fn ep_get_user_context_key(
+    State(state): State<AuthServiceState>,
+) -> Result<Json<UserContextKey>, StatusCode> {
+    let signer = state.user_context_signer().ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(UserContextKey {
+        algorithm: "Ed25519",
+        public_key: signer.public_key(),
+    }))
+}
@@ -34,7 +34,7 @@ pub(in crate::auth) async fn get_github_user_email(
                 response.text().await.unwrap_or_default(),
             ));
         };
-        log::info!("{:?}", email_info);
+        log::debug!("Github returned {} email(s)", email_info.len());
 
         external_user_info.email = email_info
             .into_iter()
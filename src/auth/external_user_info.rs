@@ -1,17 +1,35 @@
-use crate::auth::{extensions, ExternalUserInfoExtensions};
+use crate::{
+    auth::{extensions, ExternalUserInfoExtensions},
+    db::SessionAuthContext,
+    logging::{Redacted, RedactedEmail},
+};
 use reqwest::header;
 use serde_json::Value as JsonValue;
 use shine_service::service::APP_NAME;
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 use thiserror::Error as ThisError;
 use url::Url;
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub(in crate::auth) struct ExternalUserInfo {
     pub provider: String,
     pub provider_id: String,
     pub name: Option<String>,
     pub email: Option<String>,
+    /// The authentication context reported by the provider, it is stored in the session.
+    pub auth_context: Option<SessionAuthContext>,
+}
+
+impl fmt::Debug for ExternalUserInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExternalUserInfo")
+            .field("provider", &self.provider)
+            .field("provider_id", &Redacted(&self.provider_id))
+            .field("name", &self.name.as_ref().map(Redacted))
+            .field("email", &self.email.as_deref().map(RedactedEmail))
+            .field("auth_context", &self.auth_context)
+            .finish()
+    }
 }
 
 #[derive(Debug, ThisError)]
@@ -56,7 +74,7 @@ pub(in crate::auth) async fn get_external_user_info(
             response.text().await.unwrap_or_default(),
         )));
     };
-    log::info!("external user info: {:?}", user_info);
+    log::debug!("External user info of {provider} received");
 
     let external_id_id = id_mapping.get("id").map(|s| s.as_str()).unwrap_or("id");
     let external_id = user_info
@@ -78,9 +96,10 @@ pub(in crate::auth) async fn get_external_user_info(
         provider_id: external_id,
         name,
         email,
+        auth_context: None,
     };
 
-    log::info!("Checking extensions: {:?}", extensions);
+    log::debug!("Checking extensions: {:?}", extensions);
     for extension in extensions {
         match extension {
             ExternalUserInfoExtensions::GithubEmail => {
@@ -0,0 +1,233 @@
+use crate::{
+    auth::{AuthBuildError, AuthServiceState, ClientInfo, RiskDecision},
+    db::SharedClock,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use ipnet::IpNet;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use shine_service::service::APP_NAME;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+use url::Url;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AbuseIpDbConfig {
+    pub api_key: String,
+    #[serde(default = "AbuseIpDbConfig::default_api_url")]
+    pub api_url: Url,
+    /// The address is considered malicious from this abuse confidence score (0-100).
+    #[serde(default = "AbuseIpDbConfig::default_min_confidence")]
+    pub min_confidence: u32,
+    /// Only the reports of the last days are considered.
+    #[serde(default = "AbuseIpDbConfig::default_max_age_days")]
+    pub max_age_days: u32,
+    /// Request timeout in milliseconds.
+    #[serde(default = "AbuseIpDbConfig::default_timeout")]
+    pub timeout: u64,
+}
+
+impl AbuseIpDbConfig {
+    fn default_api_url() -> Url {
+        Url::parse("https://api.abuseipdb.com/api/v2/check").unwrap()
+    }
+
+    fn default_min_confidence() -> u32 {
+        50
+    }
+
+    fn default_max_age_days() -> u32 {
+        30
+    }
+
+    fn default_timeout() -> u64 {
+        1000
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IpReputationProviderConfig {
+    AbuseIpDb(AbuseIpDbConfig),
+    /// A local list of the malicious networks.
+    List(Vec<IpNet>),
+}
+
+/// The action on the malicious addresses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IpReputationAction {
+    /// Only log the request.
+    Log,
+    /// Require a second factor on the login, refuse the registration.
+    Challenge,
+    /// Refuse the login and the registration.
+    Block,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IpReputationConfig {
+    pub provider: IpReputationProviderConfig,
+    #[serde(default = "IpReputationConfig::default_action")]
+    pub action: IpReputationAction,
+    /// The time in seconds the result of a lookup is cached locally.
+    #[serde(default = "IpReputationConfig::default_cache_duration")]
+    pub cache_duration: u64,
+}
+
+impl IpReputationConfig {
+    fn default_action() -> IpReputationAction {
+        IpReputationAction::Log
+    }
+
+    fn default_cache_duration() -> u64 {
+        3600
+    }
+
+    pub fn cache_duration(&self) -> Duration {
+        Duration::seconds(self.cache_duration as i64)
+    }
+}
+
+/// Source of the reputation of the addresses.
+#[async_trait]
+pub trait IpReputationProvider: 'static + Send + Sync {
+    /// Check if the address is known to be malicious.
+    async fn is_malicious(&self, ip: IpAddr) -> Result<bool, String>;
+}
+
+struct ListProvider(Vec<IpNet>);
+
+#[async_trait]
+impl IpReputationProvider for ListProvider {
+    async fn is_malicious(&self, ip: IpAddr) -> Result<bool, String> {
+        Ok(self.0.iter().any(|net| net.contains(&ip)))
+    }
+}
+
+struct AbuseIpDbProvider {
+    client: Client,
+    config: AbuseIpDbConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AbuseIpDbData {
+    abuse_confidence_score: u32,
+}
+
+#[derive(Deserialize)]
+struct AbuseIpDbResponse {
+    data: AbuseIpDbData,
+}
+
+#[async_trait]
+impl IpReputationProvider for AbuseIpDbProvider {
+    async fn is_malicious(&self, ip: IpAddr) -> Result<bool, String> {
+        let response = self
+            .client
+            .get(self.config.api_url.clone())
+            .query(&[
+                ("ipAddress", ip.to_string()),
+                ("maxAgeInDays", self.config.max_age_days.to_string()),
+            ])
+            .header("Key", &self.config.api_key)
+            .header("Accept", "application/json")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| format!("{err}"))?;
+        let response: AbuseIpDbResponse = response.json().await.map_err(|err| format!("{err}"))?;
+        Ok(response.data.abuse_confidence_score >= self.config.min_confidence)
+    }
+}
+
+/// Check the reputation of the client addresses. The results are cached locally, thus only the first request of
+/// an address waits for the provider.
+pub(in crate::auth) struct IpReputation {
+    provider: Arc<dyn IpReputationProvider>,
+    action: IpReputationAction,
+    cache_duration: Duration,
+    cache: Mutex<HashMap<IpAddr, (bool, DateTime<Utc>)>>,
+    clock: SharedClock,
+}
+
+impl IpReputation {
+    pub fn new(config: &IpReputationConfig, clock: SharedClock) -> Result<Self, AuthBuildError> {
+        let provider: Arc<dyn IpReputationProvider> = match &config.provider {
+            IpReputationProviderConfig::AbuseIpDb(abuse_ip_db) => {
+                let client = Client::builder()
+                    .timeout(std::time::Duration::from_millis(abuse_ip_db.timeout))
+                    .user_agent(APP_NAME)
+                    .build()
+                    .map_err(|err| AuthBuildError::IpReputation(format!("{err}")))?;
+                Arc::new(AbuseIpDbProvider {
+                    client,
+                    config: abuse_ip_db.clone(),
+                })
+            }
+            IpReputationProviderConfig::List(networks) => Arc::new(ListProvider(networks.clone())),
+        };
+
+        Ok(Self {
+            provider,
+            action: config.action,
+            cache_duration: config.cache_duration(),
+            cache: Mutex::new(HashMap::new()),
+            clock,
+        })
+    }
+
+    /// Check if the address is malicious. The check fails open, when the provider is not available the address is
+    /// accepted and the failure is not cached.
+    pub async fn is_malicious(&self, ip: IpAddr) -> bool {
+        let now = self.clock.now();
+        {
+            let mut cache = self.cache.lock().unwrap_or_else(|err| err.into_inner());
+            cache.retain(|_, (_, expire_at)| *expire_at > now);
+            if let Some((is_malicious, _)) = cache.get(&ip) {
+                return *is_malicious;
+            }
+        }
+
+        match self.provider.is_malicious(ip).await {
+            Ok(is_malicious) => {
+                let mut cache = self.cache.lock().unwrap_or_else(|err| err.into_inner());
+                cache.insert(ip, (is_malicious, now + self.cache_duration));
+                is_malicious
+            }
+            Err(err) => {
+                log::warn!("IP reputation lookup of {ip} failed: {err}");
+                false
+            }
+        }
+    }
+}
+
+impl AuthServiceState {
+    /// Check the reputation of the client and get the action to take. Without a known address or a reputation
+    /// config, the request is allowed.
+    pub(in crate::auth) async fn check_ip_reputation(&self, client_info: &ClientInfo) -> RiskDecision {
+        let (ip_reputation, ip) = match (self.ip_reputation(), client_info.ip) {
+            (Some(ip_reputation), Some(ip)) => (ip_reputation, ip),
+            _ => return RiskDecision::Allow,
+        };
+
+        if !ip_reputation.is_malicious(ip).await {
+            return RiskDecision::Allow;
+        }
+
+        log::info!("Request from a malicious address {ip}");
+        match ip_reputation.action {
+            IpReputationAction::Log => RiskDecision::Allow,
+            IpReputationAction::Challenge => RiskDecision::RequireMfa,
+            IpReputationAction::Block => RiskDecision::Block,
+        }
+    }
+}
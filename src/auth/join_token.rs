@@ -0,0 +1,49 @@
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinTokenConfig {
+    /// The scope of the service tokens allowed to request the join tokens, ex. the scope of the matchmaking service.
+    #[serde(default = "JoinTokenConfig::default_scope")]
+    pub scope: String,
+    /// Validity of the join tokens in seconds.
+    #[serde(default = "JoinTokenConfig::default_duration")]
+    pub duration: u64,
+}
+
+impl JoinTokenConfig {
+    fn default_scope() -> String {
+        "game:join".to_owned()
+    }
+
+    fn default_duration() -> u64 {
+        60
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::seconds(self.duration as i64)
+    }
+}
+
+/// The claims of the join tokens, they admit a player to a match on a dedicated game server. The tokens are signed
+/// by the key of the service tokens, thus the servers verify them offline by the published JWKS.
+#[derive(Debug, Serialize)]
+pub(in crate::auth) struct JoinTokenClaims {
+    pub iss: String,
+    /// The user id of the player.
+    pub sub: Uuid,
+    /// The id of the game server the token is restricted to.
+    pub aud: String,
+    pub tenant: String,
+    /// The display name of the player.
+    pub name: String,
+    pub roles: Vec<String>,
+    /// The active restrictions of the player, ex. `chat:muted`.
+    pub restrictions: Vec<String>,
+    pub match_id: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: Uuid,
+}
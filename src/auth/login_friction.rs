@@ -0,0 +1,295 @@
+use crate::{
+    auth::{AuthBuildError, AuthServiceState, ClientInfo},
+    db::{DBError, Identity},
+    email::EmailNotificationConfig,
+};
+use chrono::{Duration, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use shine_service::service::APP_NAME;
+use thiserror::Error as ThisError;
+use url::Url;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptchaConfig {
+    /// The verification endpoint of the provider, ex. `https://hcaptcha.com/siteverify`. The reCAPTCHA, hCaptcha
+    /// and Turnstile endpoints share the same protocol.
+    pub verify_url: Url,
+    pub secret: String,
+    /// Request timeout in milliseconds.
+    #[serde(default = "CaptchaConfig::default_timeout")]
+    pub timeout: u64,
+}
+
+impl CaptchaConfig {
+    fn default_timeout() -> u64 {
+        2000
+    }
+}
+
+/// The escalation of the failed logins. The failures are counted both for the client address and the account in
+/// a window, the stages are checked from the strictest.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginFrictionConfig {
+    /// The window of the failure counters in seconds.
+    #[serde(default = "LoginFrictionConfig::default_window")]
+    pub window: u64,
+    /// A captcha is required from this number of failures. Without a captcha config this stage is skipped.
+    #[serde(default = "LoginFrictionConfig::default_captcha_after")]
+    pub captcha_after: u32,
+    pub captcha: Option<CaptchaConfig>,
+    /// The logins are refused for `cooldown` seconds after each failure from this number of failures.
+    #[serde(default = "LoginFrictionConfig::default_cooldown_after")]
+    pub cooldown_after: u32,
+    #[serde(default = "LoginFrictionConfig::default_cooldown")]
+    pub cooldown: u64,
+    /// The logins are refused for `lock_duration` seconds from this number of failures and the owner of the account
+    /// is notified.
+    #[serde(default = "LoginFrictionConfig::default_lock_after")]
+    pub lock_after: u32,
+    #[serde(default = "LoginFrictionConfig::default_lock_duration")]
+    pub lock_duration: u64,
+    #[serde(default)]
+    pub lock_email: EmailNotificationConfig,
+}
+
+impl LoginFrictionConfig {
+    fn default_window() -> u64 {
+        3600
+    }
+
+    fn default_captcha_after() -> u32 {
+        3
+    }
+
+    fn default_cooldown_after() -> u32 {
+        5
+    }
+
+    fn default_cooldown() -> u64 {
+        60
+    }
+
+    fn default_lock_after() -> u32 {
+        10
+    }
+
+    fn default_lock_duration() -> u64 {
+        3600
+    }
+
+    pub fn window(&self) -> Duration {
+        Duration::seconds(self.window as i64)
+    }
+
+    pub fn cooldown(&self) -> Duration {
+        Duration::seconds(self.cooldown as i64)
+    }
+
+    pub fn lock_duration(&self) -> Duration {
+        Duration::seconds(self.lock_duration as i64)
+    }
+}
+
+/// Verify the captcha responses of the clients.
+pub(in crate::auth) struct CaptchaVerifier {
+    client: Client,
+    verify_url: Url,
+    secret: String,
+}
+
+#[derive(Deserialize)]
+struct CaptchaResponse {
+    success: bool,
+}
+
+impl CaptchaVerifier {
+    pub fn new(config: &CaptchaConfig) -> Result<Self, AuthBuildError> {
+        let client = Client::builder()
+            .timeout(std::time::Duration::from_millis(config.timeout))
+            .user_agent(APP_NAME)
+            .build()
+            .map_err(|err| AuthBuildError::Captcha(format!("{err}")))?;
+
+        Ok(Self {
+            client,
+            verify_url: config.verify_url.clone(),
+            secret: config.secret.clone(),
+        })
+    }
+
+    /// Check the response of a captcha. The check fails closed, when the provider is not available the response
+    /// is rejected.
+    pub async fn verify(&self, response: &str, client_info: &ClientInfo) -> bool {
+        let remote_ip = client_info.ip.map(|ip| ip.to_string());
+        let mut form = vec![("secret", self.secret.as_str()), ("response", response)];
+        if let Some(remote_ip) = &remote_ip {
+            form.push(("remoteip", remote_ip));
+        }
+
+        let response = match self.client.post(self.verify_url.clone()).form(&form).send().await {
+            Ok(response) => response,
+            Err(err) => {
+                log::warn!("Captcha verification failed: {err}");
+                return false;
+            }
+        };
+        match response.json::<CaptchaResponse>().await {
+            Ok(response) => response.success,
+            Err(err) => {
+                log::warn!("Invalid captcha verification response: {err}");
+                false
+            }
+        }
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum LoginFrictionError {
+    #[error("Captcha required")]
+    CaptchaRequired,
+    #[error("Captcha is invalid")]
+    InvalidCaptcha,
+    #[error("Too many failed logins, retry in {0} seconds")]
+    Cooldown(i64),
+    #[error("Login is locked for {0} seconds")]
+    Locked(i64),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+/// The counters of the failed logins of an attempt.
+pub(in crate::auth) struct LoginFailureKeys {
+    ip: Option<String>,
+    account: String,
+}
+
+impl LoginFailureKeys {
+    /// Create the keys of a login, the account shall be in a normalized form, ex. the normalized email.
+    pub fn new(client_info: &ClientInfo, tenant_id: &str, account: &str) -> Self {
+        Self {
+            ip: client_info.ip.map(|ip| format!("login-ip:{ip}")),
+            account: format!("login-account:{tenant_id}:{account}"),
+        }
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &str> {
+        self.ip.as_deref().into_iter().chain(Some(self.account.as_str()))
+    }
+}
+
+impl AuthServiceState {
+    /// Check the friction of a login attempt before the credentials are verified.
+    pub(in crate::auth) async fn check_login_friction(
+        &self,
+        keys: &LoginFailureKeys,
+        captcha_response: Option<&str>,
+        client_info: &ClientInfo,
+    ) -> Result<(), LoginFrictionError> {
+        let config = match self.login_friction_config() {
+            Some(config) => config,
+            None => return Ok(()),
+        };
+
+        let mut count = 0;
+        for key in keys.keys() {
+            let failures = self.rate_limiter().failures(key).await?;
+            if let Some(blocked_for) = failures.blocked_for {
+                return Err(if failures.count >= config.lock_after {
+                    LoginFrictionError::Locked(blocked_for.num_seconds())
+                } else {
+                    LoginFrictionError::Cooldown(blocked_for.num_seconds())
+                });
+            }
+            count = count.max(failures.count);
+        }
+
+        if count >= config.captcha_after {
+            if let Some(captcha_verifier) = self.captcha_verifier() {
+                let captcha_response = captcha_response.ok_or(LoginFrictionError::CaptchaRequired)?;
+                if !captcha_verifier.verify(captcha_response, client_info).await {
+                    return Err(LoginFrictionError::InvalidCaptcha);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Count a failed login and escalate the friction. When the account gets locked, the owner is notified.
+    pub(in crate::auth) async fn record_login_failure(
+        &self,
+        keys: &LoginFailureKeys,
+        identity: Option<&Identity>,
+        client_info: &ClientInfo,
+    ) -> Result<(), DBError> {
+        let config = match self.login_friction_config() {
+            Some(config) => config,
+            None => return Ok(()),
+        };
+
+        for key in keys.keys() {
+            let count = self.rate_limiter().record_failure(key, config.window()).await?;
+            if count >= config.lock_after {
+                self.rate_limiter().block(key, config.lock_duration()).await?;
+                if count == config.lock_after && key == keys.account {
+                    if let Some(identity) = identity {
+                        self.notify_login_lock(identity, client_info).await;
+                    }
+                }
+            } else if count >= config.cooldown_after {
+                self.rate_limiter().block(key, config.cooldown()).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Forget the failures of the account after a successful login.
+    pub(in crate::auth) async fn clear_login_failures(&self, keys: &LoginFailureKeys) -> Result<(), DBError> {
+        if self.login_friction_config().is_some() {
+            self.rate_limiter().clear_failures(&keys.account).await?;
+        }
+        Ok(())
+    }
+
+    async fn notify_login_lock(&self, identity: &Identity, client_info: &ClientInfo) {
+        let config = match self.login_friction_config() {
+            Some(config) => config,
+            None => return,
+        };
+
+        log::warn!("Login of {} has been locked", identity.user_id);
+        if let Err(err) = self
+            .audit_log()
+            .record(
+                None,
+                "login.locked",
+                Some(identity.user_id),
+                json!({ "ip": client_info.ip, "country": client_info.country }),
+            )
+            .await
+        {
+            log::error!("Failed to record the login lock of {}: {err}", identity.user_id);
+        }
+
+        let email = match (config.lock_email.enabled, &identity.email) {
+            (true, Some(email)) => email,
+            _ => return,
+        };
+        let mut context = tera::Context::new();
+        context.insert("app_name", APP_NAME);
+        context.insert("name", &identity.name);
+        context.insert("time", &Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string());
+        context.insert("lock_minutes", &config.lock_duration().num_minutes());
+        if let Err(err) = self
+            .email_sender()
+            .send(email, config.lock_email.template("login_locked"), &context)
+        {
+            log::error!("Failed to send login lock email to {}: {err}", identity.user_id);
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{
+    auth::{AuthServiceState, ClientInfo},
+    db::Identity,
+};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Scoring of the login attempts. The scores of the matching signals are summed and compared to the thresholds.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginRiskConfig {
+    /// Score of a login from a device not seen before.
+    #[serde(default = "LoginRiskConfig::default_new_device_score")]
+    pub new_device_score: u32,
+    /// Score of a login from a country not seen before.
+    #[serde(default = "LoginRiskConfig::default_new_country_score")]
+    pub new_country_score: u32,
+
+    /// Score of a login when the user has more than `velocity_limit` logins in `velocity_window` seconds.
+    #[serde(default = "LoginRiskConfig::default_velocity_score")]
+    pub velocity_score: u32,
+    #[serde(default = "LoginRiskConfig::default_velocity_limit")]
+    pub velocity_limit: u32,
+    #[serde(default = "LoginRiskConfig::default_velocity_window")]
+    pub velocity_window: u64,
+
+    /// Header with the autonomous system number of the client set by the reverse proxy.
+    pub asn_header: Option<String>,
+    /// Autonomous systems of the Tor exit nodes, VPN and hosting providers.
+    #[serde(default)]
+    pub risky_asns: Vec<u32>,
+    #[serde(default = "LoginRiskConfig::default_risky_asn_score")]
+    pub risky_asn_score: u32,
+
+    /// Score of a login for each severity level (1 to 3) of the most severe abuse flag of the user in the last
+    /// `abuse_flag_window` seconds. The abuse flags are not scored by default.
+    #[serde(default)]
+    pub abuse_flag_score: u32,
+    #[serde(default = "LoginRiskConfig::default_abuse_flag_window")]
+    pub abuse_flag_window: u64,
+
+    /// A second factor is required from this score.
+    #[serde(default = "LoginRiskConfig::default_mfa_threshold")]
+    pub mfa_threshold: u32,
+    /// The login is refused from this score.
+    #[serde(default = "LoginRiskConfig::default_block_threshold")]
+    pub block_threshold: u32,
+}
+
+impl LoginRiskConfig {
+    fn default_new_device_score() -> u32 {
+        20
+    }
+
+    fn default_new_country_score() -> u32 {
+        30
+    }
+
+    fn default_velocity_score() -> u32 {
+        30
+    }
+
+    fn default_velocity_limit() -> u32 {
+        10
+    }
+
+    fn default_velocity_window() -> u64 {
+        3600
+    }
+
+    fn default_risky_asn_score() -> u32 {
+        40
+    }
+
+    fn default_abuse_flag_window() -> u64 {
+        30 * 24 * 3600
+    }
+
+    fn default_mfa_threshold() -> u32 {
+        40
+    }
+
+    fn default_block_threshold() -> u32 {
+        80
+    }
+
+    pub fn velocity_window(&self) -> Duration {
+        Duration::seconds(self.velocity_window as i64)
+    }
+
+    pub fn abuse_flag_window(&self) -> Duration {
+        Duration::seconds(self.abuse_flag_window as i64)
+    }
+}
+
+/// The decisions ordered by their strictness.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) enum RiskDecision {
+    Allow,
+    RequireMfa,
+    Block,
+}
+
+impl AuthServiceState {
+    /// Score a login attempt of the user and decide how to proceed. The score is recorded in the audit log. The
+    /// signals failing to be checked are ignored, thus a database error does not prevent the logins.
+    /// The action of the IP reputation check is applied even if the risk scoring is not enabled.
+    pub(in crate::auth) async fn assess_login_risk(
+        &self,
+        identity: &Identity,
+        client_info: &ClientInfo,
+    ) -> RiskDecision {
+        let reputation = self.check_ip_reputation(client_info).await;
+        let config = match self.login_risk_config() {
+            Some(config) => config,
+            None => return reputation,
+        };
+
+        let mut score = 0;
+        let mut signals = Vec::new();
+
+        match self
+            .device_manager()
+            .check_device(
+                identity.user_id,
+                &client_info.fingerprint(),
+                client_info.country.as_deref(),
+            )
+            .await
+        {
+            Ok(check) => {
+                if check.is_new_device {
+                    score += config.new_device_score;
+                    signals.push("newDevice");
+                }
+                if check.is_new_country {
+                    score += config.new_country_score;
+                    signals.push("newCountry");
+                }
+            }
+            Err(err) => log::error!("Failed to check the login device of {}: {err}", identity.user_id),
+        }
+
+        let velocity_key = format!("login-velocity:{}", identity.user_id);
+        match self
+            .rate_limiter()
+            .check(&velocity_key, config.velocity_limit, config.velocity_window())
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                score += config.velocity_score;
+                signals.push("velocity");
+            }
+            Err(err) => log::error!("Failed to check the login velocity of {}: {err}", identity.user_id),
+        }
+
+        if client_info
+            .asn
+            .map(|asn| config.risky_asns.contains(&asn))
+            .unwrap_or(false)
+        {
+            score += config.risky_asn_score;
+            signals.push("riskyAsn");
+        }
+
+        if config.abuse_flag_score > 0 {
+            let since = self.clock().now() - config.abuse_flag_window();
+            match self
+                .abuse_flag_manager()
+                .max_severity_since(identity.user_id, since)
+                .await
+            {
+                Ok(Some(severity)) => {
+                    score += config.abuse_flag_score * severity.level();
+                    signals.push("abuseFlags");
+                }
+                Ok(None) => {}
+                Err(err) => log::error!("Failed to check the abuse flags of {}: {err}", identity.user_id),
+            }
+        }
+
+        if reputation != RiskDecision::Allow {
+            signals.push("ipReputation");
+        }
+
+        let decision = if score >= config.block_threshold {
+            RiskDecision::Block
+        } else if score >= config.mfa_threshold {
+            RiskDecision::RequireMfa
+        } else {
+            RiskDecision::Allow
+        };
+        let decision = decision.max(reputation);
+
+        log::debug!(
+            "Login risk of {}: {score} {signals:?} -> {decision:?}",
+            identity.user_id
+        );
+        if let Err(err) = self
+            .audit_log()
+            .record(
+                Some(identity.user_id),
+                "login.risk",
+                Some(identity.user_id),
+                json!({
+                    "score": score,
+                    "signals": signals,
+                    "decision": decision,
+                }),
+            )
+            .await
+        {
+            log::error!("Failed to record the login risk of {}: {err}", identity.user_id);
+        }
+
+        decision
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{
+    auth::{AuthServiceState, AuthSession, ClientInfo, MfaError, TokenCreateError},
+    db::{DBError, DBSessionError, FindIdentity, IdentityError, MfaCodeCheck, MfaMethod},
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum EmailCodeError {
+    #[error("There is no login waiting for a second factor")]
+    NoPendingLogin,
+    #[error("Second factor is not enabled")]
+    MfaDisabled,
+    #[error("Code is invalid")]
+    InvalidCode,
+    #[error("Code has expired")]
+    CodeExpired,
+    #[error("Too many codes requested")]
+    TooManyRequests,
+    #[error("User not found")]
+    UserNotFound,
+    #[error(transparent)]
+    MfaError(#[from] MfaError),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    TokenCreateError(#[from] TokenCreateError),
+    #[error(transparent)]
+    SessionError(#[from] DBSessionError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for EmailCodeError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            EmailCodeError::NoPendingLogin => StatusCode::UNAUTHORIZED,
+            EmailCodeError::MfaDisabled => StatusCode::NOT_FOUND,
+            EmailCodeError::InvalidCode => StatusCode::BAD_REQUEST,
+            EmailCodeError::CodeExpired => StatusCode::GONE,
+            EmailCodeError::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            EmailCodeError::SessionError(DBSessionError::SessionLimitReached) => StatusCode::CONFLICT,
+            EmailCodeError::SessionError(DBSessionError::AccountExpired) => StatusCode::FORBIDDEN,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct VerifyEmailCode {
+    code: String,
+    /// Skip the second factor on the next logins from this browser.
+    #[serde(default)]
+    trust_device: bool,
+}
+
+/// Complete a pending login with the emailed one-time-code. On success the session cookies are set.
+pub(in crate::auth) async fn ep_verify_email_code(
+    State(state): State<AuthServiceState>,
+    client_info: ClientInfo,
+    mut auth_session: AuthSession,
+    Json(request): Json<VerifyEmailCode>,
+) -> Result<(AuthSession, StatusCode), EmailCodeError> {
+    let config = state.mfa_config().ok_or(EmailCodeError::MfaDisabled)?;
+    let mfa_pending = auth_session.mfa_pending.clone().ok_or(EmailCodeError::NoPendingLogin)?;
+    if !mfa_pending.methods.contains(&MfaMethod::Email) {
+        return Err(EmailCodeError::MfaDisabled);
+    }
+
+    match state
+        .mfa_manager()
+        .verify_code(
+            mfa_pending.user_id,
+            MfaMethod::Email,
+            request.code.trim(),
+            config.email.max_attempts,
+        )
+        .await?
+    {
+        MfaCodeCheck::Valid => {}
+        MfaCodeCheck::Invalid => return Err(EmailCodeError::InvalidCode),
+        MfaCodeCheck::Expired => return Err(EmailCodeError::CodeExpired),
+    }
+
+    let identity = state
+        .identity_manager()
+        .find(FindIdentity::UserId(mfa_pending.user_id))
+        .await?
+        .ok_or(EmailCodeError::UserNotFound)?;
+
+    let token_login = if mfa_pending.remember_me {
+        Some(
+            state
+                .create_token_with_retry(&identity, mfa_pending.token_name.as_deref(), &client_info)
+                .await?,
+        )
+    } else {
+        None
+    };
+
+    if request.trust_device {
+        state.trust_device(&mut auth_session, &identity, &client_info).await?;
+    }
+
+    let user = state.create_session(&identity, None).await?;
+    state
+        .check_login_device(auth_session.tenant(), &identity, &user, &client_info)
+        .await;
+    state.restore_deleted_account(&identity).await;
+
+    auth_session.mfa_pending = None;
+    auth_session.token_login = token_login;
+    auth_session.user = Some(user);
+    Ok((auth_session, StatusCode::NO_CONTENT))
+}
+
+/// Send a new one-time-code for the pending login.
+pub(in crate::auth) async fn ep_resend_email_code(
+    State(state): State<AuthServiceState>,
+    auth_session: AuthSession,
+) -> Result<StatusCode, EmailCodeError> {
+    let config = state.mfa_config().ok_or(EmailCodeError::MfaDisabled)?;
+    let mfa_pending = auth_session
+        .mfa_pending
+        .as_ref()
+        .ok_or(EmailCodeError::NoPendingLogin)?;
+    if !mfa_pending.methods.contains(&MfaMethod::Email) {
+        return Err(EmailCodeError::MfaDisabled);
+    }
+
+    // the first code is sent by the login, thus one less is available here
+    let rate_key = format!("mfa-email-code:{}", mfa_pending.user_id.as_simple());
+    if !state
+        .rate_limiter()
+        .check(
+            &rate_key,
+            config.email.max_codes.saturating_sub(1),
+            config.pending_duration(),
+        )
+        .await?
+    {
+        return Err(EmailCodeError::TooManyRequests);
+    }
+
+    let identity = state
+        .identity_manager()
+        .find(FindIdentity::UserId(mfa_pending.user_id))
+        .await?
+        .ok_or(EmailCodeError::UserNotFound)?;
+    state.send_email_code(&identity).await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
@@ -0,0 +1,92 @@
+use crate::{
+    auth::{ApiUser, AuthServiceState},
+    db::DBError,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum TrustedDevicesError {
+    #[error("Device ({0}) not found")]
+    DeviceNotFound(Uuid),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for TrustedDevicesError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            TrustedDevicesError::DeviceNotFound(_) => StatusCode::NOT_FOUND,
+            TrustedDevicesError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct UserTrustedDevice {
+    device_id: Uuid,
+    user_agent: Option<String>,
+    created_at: DateTime<Utc>,
+    expire_at: DateTime<Utc>,
+}
+
+/// List the browsers skipping the second factor of the current user.
+pub(in crate::auth) async fn ep_get_trusted_devices(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+) -> Result<Json<Vec<UserTrustedDevice>>, TrustedDevicesError> {
+    let devices = state
+        .device_manager()
+        .list_trusted_devices(user.user_id, state.clock().now())
+        .await?
+        .into_iter()
+        .map(|device| UserTrustedDevice {
+            device_id: device.device_id,
+            user_agent: device.user_agent,
+            created_at: device.created_at,
+            expire_at: device.expire_at,
+        })
+        .collect();
+    Ok(Json(devices))
+}
+
+#[derive(Deserialize)]
+pub(in crate::auth) struct DevicePath {
+    device_id: Uuid,
+}
+
+/// Revoke a trusted device of the current user, the next login from the browser requires the second factor.
+pub(in crate::auth) async fn ep_delete_trusted_device(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+    Path(path): Path<DevicePath>,
+) -> Result<StatusCode, TrustedDevicesError> {
+    if !state
+        .device_manager()
+        .revoke_trusted_device(user.user_id, path.device_id)
+        .await?
+    {
+        return Err(TrustedDevicesError::DeviceNotFound(path.device_id));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Revoke all the trusted devices of the current user.
+pub(in crate::auth) async fn ep_delete_trusted_devices(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+) -> Result<StatusCode, TrustedDevicesError> {
+    state.device_manager().revoke_trusted_devices(user.user_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
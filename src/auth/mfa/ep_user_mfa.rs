@@ -0,0 +1,83 @@
+use crate::{
+    auth::{ApiUser, AuthServiceState},
+    db::{DBError, FindIdentity, IdentityError, MfaMethod},
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum UserMfaError {
+    #[error("User not found")]
+    UserNotFound,
+    #[error("An email is required for the second factor")]
+    MissingEmail,
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for UserMfaError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            UserMfaError::UserNotFound => StatusCode::NOT_FOUND,
+            UserMfaError::MissingEmail => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+/// Get the enabled second factors of the current user.
+pub(in crate::auth) async fn ep_get_user_mfa(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+) -> Result<Json<Vec<MfaMethod>>, UserMfaError> {
+    let config = state.mfa_config();
+    let methods = state
+        .mfa_manager()
+        .list_methods(user.user_id)
+        .await?
+        .into_iter()
+        .filter(|method| config.map(|config| config.is_enabled(*method)).unwrap_or(false))
+        .collect();
+    Ok(Json(methods))
+}
+
+/// Require an emailed one-time-code on the login of the current user.
+pub(in crate::auth) async fn ep_enable_email_mfa(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+) -> Result<StatusCode, UserMfaError> {
+    let identity = state
+        .identity_manager()
+        .find(FindIdentity::UserId(user.user_id))
+        .await?
+        .ok_or(UserMfaError::UserNotFound)?;
+    if identity.email.is_none() {
+        return Err(UserMfaError::MissingEmail);
+    }
+
+    state
+        .mfa_manager()
+        .enable_method(user.user_id, MfaMethod::Email)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(in crate::auth) async fn ep_disable_email_mfa(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+) -> Result<StatusCode, UserMfaError> {
+    state
+        .mfa_manager()
+        .disable_method(user.user_id, MfaMethod::Email)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,162 @@
+use crate::{
+    auth::{AuthServiceState, AuthSession, ClientInfo, MfaPending, TrustedDevice},
+    db::{DBError, Identity, MfaMethod},
+    email::EmailError,
+};
+use rand::{rngs::OsRng, Rng};
+use serde_json::json;
+use shine_service::service::APP_NAME;
+use thiserror::Error as ThisError;
+use url::Url;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum MfaError {
+    #[error("User has no email to send the code to")]
+    MissingEmail,
+    #[error(transparent)]
+    EmailError(#[from] EmailError),
+    #[error("No second factor is available for the user")]
+    Unavailable,
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl AuthServiceState {
+    /// Check if the login of the user requires a second factor. If so, the pending login is stored in the
+    /// session, the codes are sent and the url of the second factor page is returned.
+    /// When the second factor is `required` (ex. by the risk of the login) and the user has no method, the code is
+    /// sent in email if possible, otherwise the login is refused.
+    pub(in crate::auth) async fn start_mfa(
+        &self,
+        auth_session: &mut AuthSession,
+        identity: &Identity,
+        remember_me: bool,
+        token_name: Option<&str>,
+        target_url: Option<&Url>,
+        required: bool,
+    ) -> Result<Option<Url>, MfaError> {
+        let config = match self.mfa_config() {
+            Some(config) => config,
+            None if required => return Err(MfaError::Unavailable),
+            None => return Ok(None),
+        };
+
+        let mut methods: Vec<MfaMethod> = self
+            .mfa_manager()
+            .list_methods(identity.user_id)
+            .await?
+            .into_iter()
+            .filter(|method| config.is_enabled(*method))
+            .collect();
+        if methods.is_empty() {
+            if !required {
+                return Ok(None);
+            }
+            if !config.is_enabled(MfaMethod::Email) || identity.email.is_none() {
+                return Err(MfaError::Unavailable);
+            }
+            methods.push(MfaMethod::Email);
+        } else if !required && self.is_trusted_device(auth_session, identity).await? {
+            log::debug!("Second factor skipped on a trusted device for {}", identity.user_id);
+            return Ok(None);
+        }
+
+        if methods.contains(&MfaMethod::Email) {
+            self.send_email_code(identity).await?;
+        }
+
+        log::debug!("Second factor required for {}: {methods:?}", identity.user_id);
+        auth_session.user = None;
+        auth_session.mfa_pending = Some(MfaPending {
+            user_id: identity.user_id,
+            tenant_id: identity.tenant_id.clone(),
+            methods,
+            remember_me,
+            token_name: token_name.map(str::to_owned),
+            expires: self.clock().now() + config.pending_duration(),
+        });
+
+        let mut page_url = config.page_url.clone();
+        if let Some(target_url) = target_url {
+            page_url
+                .query_pairs_mut()
+                .append_pair("redirectUrl", target_url.as_str());
+        }
+        Ok(Some(page_url))
+    }
+
+    /// Check if the browser of the session has been trusted by the user to skip the second factor.
+    async fn is_trusted_device(&self, auth_session: &AuthSession, identity: &Identity) -> Result<bool, MfaError> {
+        match &auth_session.trusted_device {
+            Some(trusted_device) if trusted_device.user_id == identity.user_id => Ok(self
+                .device_manager()
+                .is_trusted_device(identity.user_id, trusted_device.device_id, self.clock().now())
+                .await?),
+            _ => Ok(false),
+        }
+    }
+
+    /// Trust the browser of the session to skip the second factor of the user on the next logins. When the trusted
+    /// devices are not enabled, it is ignored.
+    pub(in crate::auth) async fn trust_device(
+        &self,
+        auth_session: &mut AuthSession,
+        identity: &Identity,
+        client_info: &ClientInfo,
+    ) -> Result<(), MfaError> {
+        let config = match self.mfa_config() {
+            Some(config) if auth_session.can_trust_device() => config,
+            _ => return Ok(()),
+        };
+
+        let device_id = Uuid::new_v4();
+        let expires = self.clock().now() + config.trusted_device_duration();
+        let user_agent = Some(client_info.user_agent.as_str()).filter(|user_agent| !user_agent.is_empty());
+        self.device_manager()
+            .trust_device(identity.user_id, device_id, user_agent, expires)
+            .await?;
+        if let Err(err) = self
+            .audit_log()
+            .record(
+                Some(identity.user_id),
+                "device.trust",
+                Some(identity.user_id),
+                json!({ "deviceId": device_id, "userAgent": user_agent, "expireAt": expires }),
+            )
+            .await
+        {
+            log::error!("Failed to record the trusted device of {}: {err}", identity.user_id);
+        }
+
+        auth_session.trusted_device = Some(TrustedDevice {
+            user_id: identity.user_id,
+            device_id,
+            expires,
+        });
+        Ok(())
+    }
+
+    /// Generate a new one-time-code and send it in email. The previous code of the user is invalidated.
+    pub(in crate::auth) async fn send_email_code(&self, identity: &Identity) -> Result<(), MfaError> {
+        let config = match self.mfa_config() {
+            Some(config) => &config.email,
+            None => return Ok(()),
+        };
+        let email = identity.email.as_ref().ok_or(MfaError::MissingEmail)?;
+
+        let code = format!("{:06}", OsRng.gen_range(0..1_000_000));
+        self.mfa_manager()
+            .create_code(identity.user_id, MfaMethod::Email, &code, config.code_duration())
+            .await?;
+
+        let mut context = tera::Context::new();
+        context.insert("app_name", APP_NAME);
+        context.insert("name", &identity.name);
+        context.insert("code", &code);
+        context.insert("expires_in_minutes", &config.code_duration().num_minutes());
+        self.email_sender()
+            .send(email, config.template.as_deref().unwrap_or("mfa_code"), &context)?;
+        Ok(())
+    }
+}
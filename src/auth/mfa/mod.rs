@@ -0,0 +1,12 @@
+mod mfa_login;
+pub(in crate::auth) use self::mfa_login::*;
+
+mod ep_email_code;
+pub(in crate::auth) use self::ep_email_code::*;
+mod ep_user_mfa;
+pub(in crate::auth) use self::ep_user_mfa::*;
+mod ep_trusted_devices;
+pub(in crate::auth) use self::ep_trusted_devices::*;
+
+#[cfg(test)]
+mod test_mfa_flows;
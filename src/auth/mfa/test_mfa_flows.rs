@@ -0,0 +1,180 @@
+use crate::test_support::{TestApp, TestClient};
+use axum::http::StatusCode;
+use axum_extra::extract::cookie::Key;
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
+use serde_json::{json, Value};
+
+const EMAIL: &str = "player@example.com";
+const PASSWORD: &str = "correct horse battery";
+
+fn enable_mfa(config: &mut Value) {
+    config["auth"]["password"] = json!({ "enabled": true });
+    config["auth"]["mfa"] = json!({
+        "pageUrl": "http://localhost/mfa",
+        "email": { "enabled": true, "maxCodes": 2 }
+    });
+    config["auth"]["trustedDeviceSecret"] = json!(B64.encode(Key::generate().master()));
+}
+
+/// Create a password user requiring the email code and return a logged out client.
+async fn mfa_client(app: &TestApp) -> TestClient {
+    app.create_password_user(EMAIL, PASSWORD).await;
+    let mut client = TestClient::new(&app.router);
+    assert_eq!(login(&mut client).await, StatusCode::NO_CONTENT);
+    let response = client.put_json("/api/auth/user/mfa/email", &json!({})).await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    let response = client.get("/api/auth/user/mfa").await;
+    assert_eq!(response.json(), json!(["email"]));
+    client.get("/auth/logout?scope=session").await;
+    client
+}
+
+async fn login(client: &mut TestClient) -> StatusCode {
+    client
+        .post_json(
+            "/api/auth/password/login",
+            &json!({ "email": EMAIL, "password": PASSWORD }),
+        )
+        .await
+        .status
+}
+
+async fn receive_code(app: &TestApp) -> String {
+    app.mailbox.receive(EMAIL).await.code(6).expect("Missing code")
+}
+
+#[tokio::test]
+async fn email_code_login() {
+    let app = TestApp::with_config(enable_mfa).await;
+    let mut client = mfa_client(&app).await;
+
+    log::info!("Verify a code without a pending login...");
+    let response = client
+        .post_json("/api/auth/mfa/email/verify", &json!({ "code": "123456" }))
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    log::info!("The password login waits for the second factor...");
+    assert_eq!(login(&mut client).await, StatusCode::ACCEPTED);
+    assert!(client.cookie("sid").is_none());
+    let code = receive_code(&app).await;
+
+    log::info!("Verify an invalid code...");
+    let invalid_code = if code == "000000" { "111111" } else { "000000" };
+    let response = client
+        .post_json("/api/auth/mfa/email/verify", &json!({ "code": invalid_code }))
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(client.cookie("sid").is_none());
+
+    log::info!("Verify the emailed code...");
+    let response = client
+        .post_json("/api/auth/mfa/email/verify", &json!({ "code": code }))
+        .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    assert!(client.cookie("sid").is_some());
+    let response = client.get("/api/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn email_code_resend() {
+    let app = TestApp::with_config(enable_mfa).await;
+    let mut client = mfa_client(&app).await;
+
+    assert_eq!(login(&mut client).await, StatusCode::ACCEPTED);
+    let first_code = receive_code(&app).await;
+
+    log::info!("Resend the code, the previous code is invalidated...");
+    let response = client.post_json("/api/auth/mfa/email/resend", &json!({})).await;
+    assert_eq!(response.status, StatusCode::ACCEPTED);
+    let code = receive_code(&app).await;
+    if code != first_code {
+        let response = client
+            .post_json("/api/auth/mfa/email/verify", &json!({ "code": first_code }))
+            .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    log::info!("The number of the codes is limited...");
+    let response = client.post_json("/api/auth/mfa/email/resend", &json!({})).await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+
+    let response = client
+        .post_json("/api/auth/mfa/email/verify", &json!({ "code": code }))
+        .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn trusted_device_skips_email_code() {
+    let app = TestApp::with_config(enable_mfa).await;
+    let mut client = mfa_client(&app).await;
+
+    log::info!("Trust the browser with the code...");
+    assert_eq!(login(&mut client).await, StatusCode::ACCEPTED);
+    let code = receive_code(&app).await;
+    let response = client
+        .post_json(
+            "/api/auth/mfa/email/verify",
+            &json!({ "code": code, "trustDevice": true }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    assert!(client.cookie("did").is_some());
+    let devices = client.get("/api/auth/user/devices/trusted").await.json();
+    assert_eq!(devices.as_array().unwrap().len(), 1);
+    let device_id = devices[0]["deviceId"].as_str().unwrap().to_owned();
+
+    log::info!("The next login skips the second factor...");
+    client.get("/auth/logout?scope=session").await;
+    assert_eq!(login(&mut client).await, StatusCode::NO_CONTENT);
+    assert!(client.cookie("sid").is_some());
+
+    log::info!("Revoke the device...");
+    let response = client
+        .delete(&format!("/api/auth/user/devices/trusted/{device_id}"))
+        .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    let devices = client.get("/api/auth/user/devices/trusted").await.json();
+    assert_eq!(devices, json!([]));
+
+    log::info!("The code is required again...");
+    client.get("/auth/logout?scope=session").await;
+    assert_eq!(login(&mut client).await, StatusCode::ACCEPTED);
+    assert!(client.cookie("sid").is_none());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn trusted_device_expires() {
+    let app = TestApp::with_config(|config| {
+        enable_mfa(config);
+        config["auth"]["mfa"]["trustedDeviceDuration"] = json!(3600);
+    })
+    .await;
+    let mut client = mfa_client(&app).await;
+
+    assert_eq!(login(&mut client).await, StatusCode::ACCEPTED);
+    let code = receive_code(&app).await;
+    let response = client
+        .post_json(
+            "/api/auth/mfa/email/verify",
+            &json!({ "code": code, "trustDevice": true }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    client.get("/auth/logout?scope=session").await;
+
+    log::info!("Login after the expiration of the device...");
+    app.clock.advance(chrono::Duration::hours(2));
+    assert_eq!(login(&mut client).await, StatusCode::ACCEPTED);
+    assert!(client.cookie("sid").is_none());
+
+    app.cleanup().await;
+}
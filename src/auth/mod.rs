@@ -1,28 +1,145 @@
+mod activity;
+pub use self::activity::*;
 mod auth_service;
 pub use self::auth_service::*;
 mod auth_service_utils;
 pub(in crate::auth) use self::auth_service_utils::*;
 mod auth_service_external_auth;
 
+mod analytics;
+pub(in crate::auth) use self::analytics::*;
 mod auth_session;
 pub(in crate::auth) use self::auth_session::*;
+mod client_info;
+pub(in crate::auth) use self::client_info::*;
+mod login_risk;
+pub(in crate::auth) use self::login_risk::*;
+mod login_friction;
+pub(in crate::auth) use self::login_friction::*;
+mod bot_check;
+pub(in crate::auth) use self::bot_check::*;
+mod ip_reputation;
+pub(in crate::auth) use self::ip_reputation::*;
 mod external_user_info;
 pub(in crate::auth) use self::external_user_info::*;
+mod tenant;
+pub(in crate::auth) use self::tenant::*;
+mod provider_clients;
+pub(in crate::auth) use self::provider_clients::*;
+mod permission;
+pub(in crate::auth) use self::permission::*;
+mod user_context;
+pub(in crate::auth) use self::user_context::*;
+mod service_token;
+pub(in crate::auth) use self::service_token::*;
+mod bearer_token;
+pub(in crate::auth) use self::bearer_token::*;
+mod api_quota;
+pub(in crate::auth) use self::api_quota::*;
+mod age_gate;
+pub(in crate::auth) use self::age_gate::*;
+mod parental_consent;
+pub(in crate::auth) use self::parental_consent::*;
+mod region_policy;
+pub(in crate::auth) use self::region_policy::*;
+mod connection_ticket;
+pub(in crate::auth) use self::connection_ticket::*;
+mod join_token;
+pub(in crate::auth) use self::join_token::*;
 
+mod ep_abuse_flags;
+pub(in crate::auth) use self::ep_abuse_flags::*;
+mod ep_age;
+pub(in crate::auth) use self::ep_age::*;
+mod ep_parental_consent;
+pub(in crate::auth) use self::ep_parental_consent::*;
+mod ep_restrictions;
+pub(in crate::auth) use self::ep_restrictions::*;
+mod ep_identity_tags;
+pub(in crate::auth) use self::ep_identity_tags::*;
+mod ep_expiration;
+pub(in crate::auth) use self::ep_expiration::*;
+mod ep_break_glass;
+pub(in crate::auth) use self::ep_break_glass::*;
+mod ep_connection_ticket;
+pub(in crate::auth) use self::ep_connection_ticket::*;
+mod ep_consents;
+pub(in crate::auth) use self::ep_consents::*;
+mod ep_feature_flags;
+pub(in crate::auth) use self::ep_feature_flags::*;
 mod ep_get_auth_providers;
 pub(in crate::auth) use self::ep_get_auth_providers::*;
 mod ep_get_user_info;
 pub(in crate::auth) use self::ep_get_user_info::*;
+mod ep_join_token;
+pub(in crate::auth) use self::ep_join_token::*;
+mod ep_legal_hold;
+pub(in crate::auth) use self::ep_legal_hold::*;
+mod ep_merge_identities;
+pub(in crate::auth) use self::ep_merge_identities::*;
+mod ep_service_clients;
+pub(in crate::auth) use self::ep_service_clients::*;
+mod ep_studios;
+pub(in crate::auth) use self::ep_studios::*;
+mod ep_silent_refresh;
+pub(in crate::auth) use self::ep_silent_refresh::*;
+mod ep_service_token;
+pub(in crate::auth) use self::ep_service_token::*;
+mod ep_update_user_name;
+pub(in crate::auth) use self::ep_update_user_name::*;
+mod ep_permissions;
+pub(in crate::auth) use self::ep_permissions::*;
+mod ep_user_audit;
+pub(in crate::auth) use self::ep_user_audit::*;
+mod ep_user_tokens;
+pub(in crate::auth) use self::ep_user_tokens::*;
+mod ep_validate_session;
+pub(in crate::auth) use self::ep_validate_session::*;
 
+mod debug;
+pub(in crate::auth) use self::debug::*;
+mod mfa;
+pub(in crate::auth) use self::mfa::*;
 mod oauth2;
 pub(in crate::auth) use self::oauth2::*;
 mod oidc;
 pub(in crate::auth) use self::oidc::*;
+mod password;
+pub(in crate::auth) use self::password::*;
+mod support;
+pub(in crate::auth) use self::support::*;
+mod native;
+pub(in crate::auth) use self::native::*;
+mod sso;
+pub(in crate::auth) use self::sso::*;
 mod token;
 pub(in crate::auth) use self::token::*;
 mod page_logout;
 pub(in crate::auth) use self::page_logout::*;
 mod page_delete_user;
 pub(in crate::auth) use self::page_delete_user::*;
+mod page_revoke_session;
+pub(in crate::auth) use self::page_revoke_session::*;
+mod page_links;
+pub(in crate::auth) use self::page_links::*;
+mod page_link_conflict;
+pub(in crate::auth) use self::page_link_conflict::*;
+
+#[cfg(test)]
+mod test_age_flows;
+#[cfg(test)]
+mod test_auth_flows;
+#[cfg(test)]
+mod test_break_glass_flows;
+#[cfg(test)]
+mod test_deletion_flows;
+#[cfg(test)]
+mod test_login_friction_flows;
+#[cfg(test)]
+mod test_moderation_flows;
+#[cfg(test)]
+mod test_service_token_flows;
+#[cfg(test)]
+mod test_studio_flows;
 
 pub(in crate::auth) mod extensions;
@@ -0,0 +1,104 @@
+use crate::{
+    auth::{AuthServiceState, Tenant},
+    db::{DBError, DBSessionError, FindIdentity, IdentityError},
+};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Form, Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as B64URL, Engine};
+use ring::{constant_time, digest};
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum NativeTokenError {
+    #[error("Unsupported grant type")]
+    UnsupportedGrantType,
+    #[error("Code is invalid, has expired or the verifier is not matching")]
+    InvalidGrant,
+    #[error("User ({0}) not found")]
+    UserNotFound(Uuid),
+    #[error(transparent)]
+    SessionError(#[from] DBSessionError),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for NativeTokenError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            NativeTokenError::UnsupportedGrantType | NativeTokenError::InvalidGrant => StatusCode::BAD_REQUEST,
+            NativeTokenError::UserNotFound(_) => StatusCode::NOT_FOUND,
+            NativeTokenError::SessionError(DBSessionError::SessionLimitReached) => StatusCode::CONFLICT,
+            NativeTokenError::SessionError(DBSessionError::AccountExpired) => StatusCode::FORBIDDEN,
+            NativeTokenError::SessionError(_) | NativeTokenError::IdentityError(_) | NativeTokenError::DBError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+/// The exchange of the code of a native login (RFC 6749 section 4.1.3). The app is a public client, it is
+/// authenticated only by the PKCE verifier.
+#[derive(Deserialize)]
+pub(in crate::auth) struct NativeTokenRequest {
+    grant_type: String,
+    code: String,
+    code_verifier: String,
+    redirect_uri: String,
+}
+
+#[derive(Serialize)]
+pub(in crate::auth) struct NativeTokenResponse {
+    /// The session of the user to be used as a bearer token.
+    access_token: String,
+    token_type: &'static str,
+    user_id: Uuid,
+}
+
+/// Exchange the one-time code of a native login for a new session of the user. The session is returned as a bearer
+/// token, it is independent of the session of the browser used for the login.
+pub(in crate::auth) async fn ep_native_token(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    Form(request): Form<NativeTokenRequest>,
+) -> Result<Response, NativeTokenError> {
+    if request.grant_type != "authorization_code" {
+        return Err(NativeTokenError::UnsupportedGrantType);
+    }
+
+    let login = state
+        .native_login_manager()
+        .take(&request.code)
+        .await?
+        .filter(|login| login.tenant_id == tenant.id() && login.redirect_url == request.redirect_uri)
+        .ok_or(NativeTokenError::InvalidGrant)?;
+    let challenge = B64URL.encode(digest::digest(&digest::SHA256, request.code_verifier.as_bytes()));
+    if constant_time::verify_slices_are_equal(challenge.as_bytes(), login.code_challenge.as_bytes()).is_err() {
+        log::info!("Code verifier of the native login of {} is not matching", login.user_id);
+        return Err(NativeTokenError::InvalidGrant);
+    }
+
+    let identity = state
+        .identity_manager()
+        .find(FindIdentity::UserId(login.user_id))
+        .await?
+        .ok_or(NativeTokenError::UserNotFound(login.user_id))?;
+    let user = state.create_session(&identity, None).await?;
+
+    log::info!("Native login session created for {}", user.user_id);
+    let response = NativeTokenResponse {
+        access_token: tenant.session_meta().create_user_cookie(&user),
+        token_type: "Bearer",
+        user_id: user.user_id,
+    };
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(response)).into_response())
+}
@@ -0,0 +1,9 @@
+mod native_login;
+pub(in crate::auth) use self::native_login::*;
+mod page_native_login;
+pub(in crate::auth) use self::page_native_login::*;
+mod ep_native_token;
+pub(in crate::auth) use self::ep_native_token::*;
+
+#[cfg(test)]
+mod test_native_flows;
@@ -0,0 +1,77 @@
+use crate::auth::AuthBuildError;
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use url::{Host, Url};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NativeLoginConfig {
+    /// The allowed targets of the login result, either custom-scheme (ex. `com.example.game:/auth`) or loopback
+    /// (ex. `http://127.0.0.1/auth`) urls. The port of the loopback urls is not checked.
+    pub redirect_urls: Vec<String>,
+    /// Validity of the one-time codes in seconds.
+    #[serde(default = "NativeLoginConfig::default_code_duration")]
+    pub code_duration: u64,
+}
+
+impl NativeLoginConfig {
+    fn default_code_duration() -> u64 {
+        60
+    }
+}
+
+fn is_loopback(url: &Url) -> bool {
+    url.scheme() == "http"
+        && match url.host() {
+            Some(Host::Ipv4(ip)) => ip.is_loopback(),
+            Some(Host::Ipv6(ip)) => ip.is_loopback(),
+            _ => false,
+        }
+}
+
+/// The login of the native (public) clients, ex. game clients and mobile apps. They cannot keep a secret, thus the
+/// result of the login is returned by a one-time code bound to the PKCE challenge of the app (RFC 8252).
+pub(in crate::auth) struct NativeLogin {
+    redirect_urls: Vec<Url>,
+    code_duration: Duration,
+}
+
+impl NativeLogin {
+    pub fn new(config: &NativeLoginConfig) -> Result<Self, AuthBuildError> {
+        let redirect_urls = config
+            .redirect_urls
+            .iter()
+            .map(|url| {
+                let url = Url::parse(url).map_err(|err| AuthBuildError::RedirectUrl(format!("native login: {err}")))?;
+                // the web urls could be claimed by anyone, only the targets of the apps are accepted
+                if matches!(url.scheme(), "http" | "https") && !is_loopback(&url) {
+                    return Err(AuthBuildError::RedirectUrl(format!(
+                        "native login: {url} is neither a custom-scheme nor a loopback url"
+                    )));
+                }
+                Ok(url)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            redirect_urls,
+            code_duration: Duration::seconds(config.code_duration as i64),
+        })
+    }
+
+    pub fn code_duration(&self) -> Duration {
+        self.code_duration
+    }
+
+    /// Check if the url is one of the configured targets. The loopback urls are accepted on any port, as the apps
+    /// listen on an ephemeral port.
+    pub fn is_allowed_redirect(&self, url: &Url) -> bool {
+        url.fragment().is_none()
+            && self.redirect_urls.iter().any(|allowed| {
+                allowed.scheme() == url.scheme()
+                    && allowed.host() == url.host()
+                    && allowed.path() == url.path()
+                    && (is_loopback(allowed) || allowed.port() == url.port())
+            })
+    }
+}
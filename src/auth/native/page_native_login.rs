@@ -0,0 +1,111 @@
+use crate::{
+    auth::{AuthError, AuthPage, AuthServiceState, AuthSession},
+    db::NativeLoginCode,
+    logging::user_hash,
+};
+use axum::extract::{Query, RawQuery, State};
+use serde::Deserialize;
+use url::Url;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct RequestParams {
+    /// The target of the login result in the app.
+    redirect_url: Url,
+    code_challenge: String,
+    code_challenge_method: String,
+    /// Opaque value of the app returned with the code.
+    state: Option<String>,
+    /// The provider of the login, if the user has no session yet.
+    provider: Option<String>,
+}
+
+/// Check the PKCE challenge, only the S256 method is accepted (RFC 7636 section 4.2).
+fn is_valid_challenge(challenge: &str, method: &str) -> bool {
+    method == "S256"
+        && challenge.len() == 43
+        && challenge
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Start or complete the login of a native app. The app opens this page in the browser, if the user has no
+/// session, the login of the provider is started and the user is sent back here after the login. With a session,
+/// the user is redirected to the app with a one-time code, that can be exchanged for a session by the app.
+pub(in crate::auth) async fn page_native_login(
+    State(state): State<AuthServiceState>,
+    Query(query): Query<RequestParams>,
+    RawQuery(raw_query): RawQuery,
+    auth_session: AuthSession,
+) -> AuthPage {
+    let native_login = match state.native_login() {
+        Some(native_login) => native_login,
+        None => return state.page_error(auth_session, AuthError::InvalidAppLogin, None),
+    };
+    if !native_login.is_allowed_redirect(&query.redirect_url) {
+        log::info!("Native login to a not allowed target: {}", query.redirect_url);
+        return state.page_error(auth_session, AuthError::InvalidAppLogin, None);
+    }
+    if !is_valid_challenge(&query.code_challenge, &query.code_challenge_method) {
+        return state.page_error(auth_session, AuthError::InvalidAppLogin, None);
+    }
+
+    let user = match auth_session.user.clone() {
+        Some(user) => user,
+        None => {
+            let tenant = auth_session.tenant();
+            let provider = match query.provider.as_deref() {
+                Some(provider) if tenant.is_provider_enabled(provider) => provider,
+                _ => return state.page_error(auth_session, AuthError::LoginRequired, None),
+            };
+            let mut return_url = tenant.page_url(&["native", "login"]);
+            return_url.set_query(raw_query.as_deref());
+            let mut login_url = tenant.page_url(&[provider, "login"]);
+            login_url
+                .query_pairs_mut()
+                .append_pair("redirectUrl", return_url.as_str());
+            return state.page_redirect(auth_session, provider, Some(&login_url));
+        }
+    };
+
+    match state
+        .session_manager()
+        .find_session(auth_session.tenant().id(), user.user_id, user.key)
+        .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => return state.page_error(auth_session, AuthError::SessionExpired, None),
+        Err(err) => return state.page_internal_error(auth_session, err, None),
+    }
+
+    let code = match state.token().generate_token() {
+        Ok(code) => code,
+        Err(err) => return state.page_internal_error(auth_session, err, None),
+    };
+    let login = NativeLoginCode {
+        user_id: user.user_id,
+        tenant_id: auth_session.tenant().id().to_owned(),
+        redirect_url: query.redirect_url.to_string(),
+        code_challenge: query.code_challenge,
+    };
+    if let Err(err) = state
+        .native_login_manager()
+        .create(&code, &login, native_login.code_duration())
+        .await
+    {
+        return state.page_internal_error(auth_session, err, None);
+    }
+
+    tracing::info!(
+        provider = "native",
+        user = %user_hash(user.user_id),
+        outcome = "codeIssued",
+        "Native login code issued"
+    );
+    let mut target_url = query.redirect_url;
+    target_url.query_pairs_mut().append_pair("code", &code);
+    if let Some(app_state) = &query.state {
+        target_url.query_pairs_mut().append_pair("state", app_state);
+    }
+    state.page_redirect(auth_session, "the application", Some(&target_url))
+}
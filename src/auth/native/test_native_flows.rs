@@ -0,0 +1,61 @@
+use crate::test_support::{TestApp, TestClient};
+use axum::http::{header, StatusCode};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as B64URL, Engine};
+use ring::digest;
+use serde_json::json;
+use url::Url;
+
+#[tokio::test]
+async fn native_app_login() {
+    let app = TestApp::with_config(|config| {
+        config["auth"]["nativeLogin"] = json!({ "redirectUrls": ["com.example.game:/auth", "http://127.0.0.1/auth"] });
+    })
+    .await;
+    let mut client = TestClient::new(&app.router);
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let code_verifier = "native-app-code-verifier-0123456789-abcdefghijklmnop";
+    let code_challenge = B64URL.encode(digest::digest(&digest::SHA256, code_verifier.as_bytes()));
+    let login_path = |redirect_url: &str| {
+        format!(
+            "/auth/native/login?redirectUrl={}&codeChallenge={code_challenge}&codeChallengeMethod=S256&state=app-state",
+            url::form_urlencoded::byte_serialize(redirect_url.as_bytes()).collect::<String>()
+        )
+    };
+
+    log::info!("Targets not configured are rejected...");
+    let response = client.get(&login_path("https://example.com/auth")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.redirect_url().is_none());
+
+    log::info!("Loopback target on any port...");
+    let response = client.get(&login_path("http://127.0.0.1:51234/auth")).await;
+    let target = Url::parse(&response.redirect_url().expect("Missing redirect to the app")).unwrap();
+    assert_eq!(target.port(), Some(51234));
+    let query: std::collections::HashMap<_, _> = target.query_pairs().into_owned().collect();
+    assert_eq!(query["state"], "app-state");
+    let code = query["code"].clone();
+
+    log::info!("Exchange the code for a session...");
+    let mut native_client = TestClient::new(&app.router);
+    let fields = [
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("code_verifier", code_verifier),
+        ("redirect_uri", "http://127.0.0.1:51234/auth"),
+    ];
+    let response = native_client.post_form("/api/auth/native/token", &fields).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let access_token = response.json()["access_token"].as_str().unwrap().to_owned();
+
+    log::info!("The code can be used only once...");
+    let response = native_client.post_form("/api/auth/native/token", &fields).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    native_client.set_header(header::AUTHORIZATION, &format!("Bearer {access_token}"));
+    let response = native_client.get("/api/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    app.cleanup().await;
+}
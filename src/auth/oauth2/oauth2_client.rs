@@ -2,6 +2,7 @@ use crate::auth::{AuthBuildError, ExternalUserInfoExtensions, OAuth2Config};
 use oauth2::{basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, Scope, TokenUrl};
 use openidconnect::UserInfoUrl;
 use std::collections::HashMap;
+use url::Url;
 
 pub(in crate::auth) struct OAuth2Client {
     pub provider: String,
@@ -9,15 +10,15 @@ pub(in crate::auth) struct OAuth2Client {
     pub user_info_url: UserInfoUrl,
     pub user_info_mapping: HashMap<String, String>,
     pub extensions: Vec<ExternalUserInfoExtensions>,
+    pub auth_params: HashMap<String, String>,
     pub client: BasicClient,
 }
 
 impl OAuth2Client {
-    pub async fn new(provider: &str, config: &OAuth2Config) -> Result<Self, AuthBuildError> {
+    pub async fn new(provider: &str, config: &OAuth2Config, redirect_url: Url) -> Result<Self, AuthBuildError> {
         let client_id = ClientId::new(config.client_id.clone());
         let client_secret = ClientSecret::new(config.client_secret.clone());
-        let redirect_url = RedirectUrl::new(config.redirect_url.to_string())
-            .map_err(|err| AuthBuildError::RedirectUrl(format!("{err}")))?;
+        let redirect_url = RedirectUrl::from_url(redirect_url);
         let auth_url = AuthUrl::new(config.authorization_url.clone())
             .map_err(|err| AuthBuildError::InvalidAuthUrl(format!("{err}")))?;
         let token_url =
@@ -33,6 +34,7 @@ impl OAuth2Client {
             user_info_url,
             user_info_mapping: config.user_info_mapping.clone(),
             extensions: config.extensions.iter().cloned().collect(),
+            auth_params: config.auth_params.clone(),
             client,
         })
     }
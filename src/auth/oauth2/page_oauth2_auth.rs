@@ -1,13 +1,13 @@
-use crate::auth::{
-    get_external_user_info, AuthError, AuthPage, AuthServiceState, AuthSession, ExternalLogin, OAuth2Client,
-};
-use axum::{
-    extract::{Query, State},
-    Extension,
+use crate::{
+    auth::{
+        get_external_user_info, AuthError, AuthPage, AuthServiceState, AuthSession, ClientInfo, ExternalLogin,
+        OAuth2Client, ProviderClient,
+    },
+    logging::RedactedToken,
 };
+use axum::extract::{Query, State};
 use oauth2::{reqwest::async_http_client, AuthorizationCode, PkceCodeVerifier, TokenResponse};
 use serde::Deserialize;
-use std::sync::Arc;
 
 #[derive(Deserialize)]
 pub(in crate::auth) struct RequestParams {
@@ -18,10 +18,15 @@ pub(in crate::auth) struct RequestParams {
 /// Process the authentication redirect from the OAuth2 provider.
 pub(in crate::auth) async fn page_oauth2_auth(
     State(state): State<AuthServiceState>,
-    Extension(client): Extension<Arc<OAuth2Client>>,
+    ProviderClient(client): ProviderClient<OAuth2Client>,
     Query(query): Query<RequestParams>,
+    client_info: ClientInfo,
     mut auth_session: AuthSession,
 ) -> AuthPage {
+    if !auth_session.tenant().is_provider_enabled(&client.provider) {
+        return state.page_error(auth_session, AuthError::ProviderNotAvailable, None);
+    }
+
     let auth_code = AuthorizationCode::new(query.code);
     let auth_csrf_state = query.state;
 
@@ -33,15 +38,23 @@ pub(in crate::auth) async fn page_oauth2_auth(
         error_url,
         remember_me,
         linked_user,
+        flow_id,
         ..
     } = match auth_session.external_login.take() {
         Some(external_login) => external_login,
         None => return state.page_error(auth_session, AuthError::MissingExternalLogin, None),
     };
+    if let Some(flow_id) = flow_id {
+        state.resume_auth_flow(&mut auth_session, flow_id, &client.provider);
+    }
 
     // Check for Cross Site Request Forgery
     if csrf_state != auth_csrf_state {
-        log::debug!("CSRF test failed: [{csrf_state}], [{auth_csrf_state}]");
+        log::debug!(
+            "CSRF test failed: [{}], [{}]",
+            RedactedToken(&csrf_state),
+            RedactedToken(&auth_csrf_state)
+        );
         return state.page_error(auth_session, AuthError::InvalidCSRF, error_url.as_ref());
     }
 
@@ -69,7 +82,11 @@ pub(in crate::auth) async fn page_oauth2_auth(
         Ok(external_user_info) => external_user_info,
         _ => return state.page_error(auth_session, AuthError::FailedExternalUserInfo, error_url.as_ref()),
     };
-    log::info!("{:?}", external_user_info);
+    tracing::info!(
+        provider = %external_user_info.provider,
+        has_email = external_user_info.email.is_some(),
+        "External user info received"
+    );
 
     if linked_user.is_some() {
         state
@@ -86,6 +103,7 @@ pub(in crate::auth) async fn page_oauth2_auth(
             .page_external_login(
                 auth_session,
                 external_user_info,
+                &client_info,
                 target_url.as_ref(),
                 error_url.as_ref(),
                 remember_me,
@@ -1,11 +1,10 @@
-use crate::auth::{AuthError, AuthPage, AuthServiceState, AuthSession, ExternalLogin, OAuth2Client};
-use axum::{
-    extract::{Query, State},
-    Extension,
+use crate::{
+    auth::{AuthError, AuthPage, AuthServiceState, AuthSession, ExternalLogin, OAuth2Client, ProviderClient},
+    db::AnalyticsEvent,
 };
+use axum::extract::{Query, State};
 use oauth2::{CsrfToken, PkceCodeChallenge};
 use serde::Deserialize;
-use std::sync::Arc;
 use url::Url;
 
 #[derive(Deserialize)]
@@ -18,21 +17,28 @@ pub(in crate::auth) struct RequestParams {
 /// Link the current user to an OAuth2 provider.
 pub(in crate::auth) async fn page_oauth2_link(
     State(state): State<AuthServiceState>,
-    Extension(client): Extension<Arc<OAuth2Client>>,
+    ProviderClient(client): ProviderClient<OAuth2Client>,
     Query(query): Query<RequestParams>,
     mut auth_session: AuthSession,
 ) -> AuthPage {
+    if !auth_session.tenant().is_provider_enabled(&client.provider) {
+        return state.page_error(auth_session, AuthError::ProviderNotAvailable, query.error_url.as_ref());
+    }
+    let flow_id = state.start_auth_flow(&mut auth_session, &client.provider);
     if auth_session.user.is_none() {
         return state.page_error(auth_session, AuthError::LoginRequired, query.error_url.as_ref());
     }
 
     let (pkce_code_challenge, pkce_code_verifier) = PkceCodeChallenge::new_random_sha256();
-    let (authorize_url, csrf_state) = client
+    let mut authorize_request = client
         .client
         .authorize_url(CsrfToken::new_random)
         .add_scopes(client.scopes.clone())
-        .set_pkce_challenge(pkce_code_challenge)
-        .url();
+        .set_pkce_challenge(pkce_code_challenge);
+    for (name, value) in &client.auth_params {
+        authorize_request = authorize_request.add_extra_param(name.as_str(), value.as_str());
+    }
+    let (authorize_url, csrf_state) = authorize_request.url();
 
     auth_session.external_login = Some(ExternalLogin {
         pkce_code_verifier: pkce_code_verifier.secret().to_owned(),
@@ -42,7 +48,9 @@ pub(in crate::auth) async fn page_oauth2_link(
         error_url: query.error_url,
         remember_me: false,
         linked_user: auth_session.user.clone(),
+        flow_id: Some(flow_id),
     });
 
+    state.emit_flow_event(&auth_session, AnalyticsEvent::ProviderRedirect, None);
     state.page_redirect(auth_session, &client.provider, Some(&authorize_url))
 }
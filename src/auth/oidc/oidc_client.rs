@@ -1,34 +1,242 @@
-use crate::auth::{AuthBuildError, OIDCConfig};
+use crate::{
+    auth::{AuthBuildError, OIDCConfig},
+    db::SharedClock,
+};
 use oauth2::{reqwest::async_http_client, ClientId, ClientSecret, RedirectUrl, Scope};
 use openidconnect::{
-    core::{CoreClient, CoreProviderMetadata},
+    core::{CoreClient, CoreIdTokenVerifier, CoreJsonWebKeySet, CoreProviderMetadata},
     IssuerUrl,
 };
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+use url::Url;
+
+/// The first delay of the retry of a failed discovery, it is doubled by each failure.
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(5);
+/// The maximum delay of the retry of a failed discovery.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+/// The minimum time between two refreshes of the keys of a provider, thus the unknown key ids (ex. of forged
+/// tokens) cannot flood the provider.
+const MIN_KEYS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The OpenID Connect providers (by profile) without a successful discovery. They are hidden from the users until
+/// the discovery succeeds.
+#[derive(Clone, Default)]
+pub(in crate::auth) struct UnavailableProviders(Arc<RwLock<HashSet<(String, String)>>>);
+
+impl UnavailableProviders {
+    fn set_available(&self, profile: &str, provider: &str, available: bool) {
+        let mut providers = self.0.write().unwrap();
+        if available {
+            providers.remove(&(profile.to_owned(), provider.to_owned()));
+        } else {
+            providers.insert((profile.to_owned(), provider.to_owned()));
+        }
+    }
+
+    pub fn is_available(&self, profile: &str, provider: &str) -> bool {
+        let providers = self.0.read().unwrap();
+        !providers.contains(&(profile.to_owned(), provider.to_owned()))
+    }
+}
+
+struct Discovery {
+    issuer_url: IssuerUrl,
+    client_id: ClientId,
+    client_secret: ClientSecret,
+    redirect_url: RedirectUrl,
+}
+
+/// The result of a successful discovery.
+struct Discovered {
+    metadata: CoreProviderMetadata,
+    client: CoreClient,
+}
+
+impl Discovery {
+    fn create_client(&self, metadata: CoreProviderMetadata) -> Discovered {
+        let client = CoreClient::from_provider_metadata(
+            metadata.clone(),
+            self.client_id.clone(),
+            Some(self.client_secret.clone()),
+        )
+        .set_redirect_uri(self.redirect_url.clone());
+        Discovered { metadata, client }
+    }
+
+    async fn discover(&self) -> Result<Discovered, String> {
+        let metadata = CoreProviderMetadata::discover_async(self.issuer_url.clone(), async_http_client)
+            .await
+            .map_err(|err| format!("{err}"))?;
+        Ok(self.create_client(metadata))
+    }
+}
 
 pub(in crate::auth) struct OIDCClient {
     pub provider: String,
     pub scopes: Vec<Scope>,
-    pub client: CoreClient,
+    pub auth_params: HashMap<String, String>,
+    pub max_auth_age: Duration,
+    clock_skew: Duration,
+    clock: SharedClock,
+    discovery: Arc<Discovery>,
+    discovered: Arc<RwLock<Option<Discovered>>>,
+    last_keys_refresh: Mutex<Option<Instant>>,
 }
 
 impl OIDCClient {
-    pub async fn new(provider: &str, config: &OIDCConfig) -> Result<Self, AuthBuildError> {
-        let client_id = ClientId::new(config.client_id.clone());
-        let client_secret = ClientSecret::new(config.client_secret.clone());
-        let redirect_url = RedirectUrl::new(config.redirect_url.to_string())
-            .map_err(|err| AuthBuildError::RedirectUrl(format!("{err}")))?;
-        let discovery_url = IssuerUrl::new(config.discovery_url.clone())
-            .map_err(|err| AuthBuildError::InvalidIssuer(format!("{err}")))?;
-        let provider_metadata = CoreProviderMetadata::discover_async(discovery_url, async_http_client)
-            .await
-            .map_err(|err| AuthBuildError::Discovery(format!("{err}")))?;
-        let client = CoreClient::from_provider_metadata(provider_metadata, client_id, Some(client_secret))
-            .set_redirect_uri(redirect_url);
+    /// Create the client and start the background discovery of the provider. A failed discovery does not prevent
+    /// the start of the service, the provider is marked as unavailable and the discovery is retried with a backoff.
+    /// The discovered document is refreshed after the configured ttl, the previous document is kept until the
+    /// refresh succeeds.
+    pub async fn new(
+        profile: &str,
+        provider: &str,
+        config: &OIDCConfig,
+        redirect_url: Url,
+        unavailable_providers: UnavailableProviders,
+        clock: SharedClock,
+    ) -> Result<Self, AuthBuildError> {
+        let discovery = Arc::new(Discovery {
+            issuer_url: IssuerUrl::new(config.discovery_url.clone())
+                .map_err(|err| AuthBuildError::InvalidIssuer(format!("{err}")))?,
+            client_id: ClientId::new(config.client_id.clone()),
+            client_secret: ClientSecret::new(config.client_secret.clone()),
+            redirect_url: RedirectUrl::from_url(redirect_url),
+        });
+        let ttl = Duration::from_secs(config.discovery_ttl);
+
+        let initial_discovery = match discovery.discover().await {
+            Ok(discovered) => Some(discovered),
+            Err(err) => {
+                log::warn!("Failed to discover OpenId Connect provider {provider} ({profile}), retrying later: {err}");
+                unavailable_providers.set_available(profile, provider, false);
+                None
+            }
+        };
+        let mut retry_delay = if initial_discovery.is_some() {
+            None
+        } else {
+            Some(MIN_RETRY_DELAY)
+        };
+        let discovered = Arc::new(RwLock::new(initial_discovery));
+
+        let refresher = discovery.clone();
+        let refreshed = discovered.clone();
+        let (profile, provider_name) = (profile.to_owned(), provider.to_owned());
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(retry_delay.unwrap_or(ttl)).await;
+                match refresher.discover().await {
+                    Ok(discovered) => {
+                        let was_available = refreshed.write().unwrap().replace(discovered).is_some();
+                        if !was_available {
+                            log::info!("OpenId Connect provider {provider_name} ({profile}) is available");
+                            unavailable_providers.set_available(&profile, &provider_name, true);
+                        }
+                        retry_delay = None;
+                    }
+                    Err(err) => {
+                        log::warn!("Failed to discover OpenId Connect provider {provider_name} ({profile}): {err}");
+                        retry_delay = Some(
+                            retry_delay
+                                .map(|delay| (delay * 2).min(MAX_RETRY_DELAY))
+                                .unwrap_or(MIN_RETRY_DELAY),
+                        );
+                    }
+                }
+            }
+        });
 
         Ok(Self {
             provider: provider.to_string(),
             scopes: config.scopes.iter().map(|scope| Scope::new(scope.clone())).collect(),
-            client,
+            auth_params: config.auth_params.clone(),
+            max_auth_age: Duration::from_secs(config.max_auth_age),
+            clock_skew: Duration::from_secs(config.clock_skew),
+            clock,
+            discovery,
+            discovered,
+            last_keys_refresh: Mutex::new(None),
         })
     }
+
+    /// Get the client of the last successful discovery, None if the provider has not been discovered yet.
+    pub fn client(&self) -> Option<CoreClient> {
+        self.discovered
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|discovered| discovered.client.clone())
+    }
+
+    /// Create the verifier of the id tokens issued for the client. The expiration, issue and authentication times
+    /// are checked by the clock of the service allowing the configured skew. The `auth_time` is optional, as not
+    /// all the providers honor the `max_age` parameter.
+    pub fn id_token_verifier<'a>(&self, oidc_client: &'a CoreClient) -> CoreIdTokenVerifier<'a> {
+        let clock_skew = chrono::Duration::seconds(self.clock_skew.as_secs() as i64);
+        let max_auth_age = chrono::Duration::seconds(self.max_auth_age.as_secs() as i64);
+        let (clock, iat_clock, auth_time_clock) = (self.clock.clone(), self.clock.clone(), self.clock.clone());
+
+        oidc_client
+            .id_token_verifier()
+            // the expiration is checked against this time, thus shifting it back accepts the tokens expired recently
+            .set_time_fn(move || clock.now() - clock_skew)
+            .set_issue_time_verifier_fn(move |issue_time| {
+                let now = iat_clock.now();
+                if issue_time > now + clock_skew {
+                    Err(format!(
+                        "ID token is issued in the future at {issue_time} (current time is {now})"
+                    ))
+                } else {
+                    Ok(())
+                }
+            })
+            .set_auth_time_verifier_fn(move |auth_time| {
+                let now = auth_time_clock.now();
+                match auth_time {
+                    Some(auth_time) if auth_time > now + clock_skew => Err(format!(
+                        "Authentication is in the future at {auth_time} (current time is {now})"
+                    )),
+                    Some(auth_time) if auth_time + max_auth_age + clock_skew < now => Err(format!(
+                        "Authentication at {auth_time} is older than {} seconds",
+                        max_auth_age.num_seconds()
+                    )),
+                    _ => Ok(()),
+                }
+            })
+    }
+
+    /// Fetch the keys of the provider again, ex. when a token is signed by an unknown key after a key rotation.
+    /// The refreshes are rate limited, None is returned if the refresh was skipped or it has failed.
+    pub async fn refresh_keys(&self) -> Option<CoreClient> {
+        {
+            let mut last_refresh = self.last_keys_refresh.lock().unwrap();
+            if last_refresh.map_or(false, |last| last.elapsed() < MIN_KEYS_REFRESH_INTERVAL) {
+                log::info!(
+                    "Skipping the refresh of the keys of {}, refreshed recently",
+                    self.provider
+                );
+                return None;
+            }
+            *last_refresh = Some(Instant::now());
+        }
+
+        let metadata = self.discovered.read().unwrap().as_ref()?.metadata.clone();
+        let keys = match CoreJsonWebKeySet::fetch_async(metadata.jwks_uri(), async_http_client).await {
+            Ok(keys) => keys,
+            Err(err) => {
+                log::warn!("Failed to refresh the keys of {}: {err}", self.provider);
+                return None;
+            }
+        };
+        log::info!("Refreshed the keys of {}", self.provider);
+        let discovered = self.discovery.create_client(metadata.set_jwks(keys));
+        let client = discovered.client.clone();
+        *self.discovered.write().unwrap() = Some(discovered);
+        Some(client)
+    }
 }
@@ -1,12 +1,15 @@
-use crate::auth::{AuthError, AuthPage, AuthServiceState, AuthSession, ExternalLogin, ExternalUserInfo, OIDCClient};
-use axum::{
-    extract::{Query, State},
-    Extension,
+use crate::{
+    auth::{
+        AuthError, AuthPage, AuthServiceState, AuthSession, ClientInfo, ExternalLogin, ExternalUserInfo, OIDCClient,
+        ProviderClient,
+    },
+    db::SessionAuthContext,
+    logging::RedactedToken,
 };
+use axum::extract::{Query, State};
 use oauth2::{reqwest::async_http_client, AuthorizationCode, PkceCodeVerifier};
-use openidconnect::{Nonce, TokenResponse};
+use openidconnect::{ClaimsVerificationError, Nonce, SignatureVerificationError, TokenResponse};
 use serde::Deserialize;
-use std::sync::Arc;
 
 #[derive(Deserialize)]
 pub(in crate::auth) struct RequestParams {
@@ -17,10 +20,18 @@ pub(in crate::auth) struct RequestParams {
 /// Process the authentication redirect from the OpenID Connect provider.
 pub(in crate::auth) async fn page_oidc_auth(
     State(state): State<AuthServiceState>,
-    Extension(client): Extension<Arc<OIDCClient>>,
+    ProviderClient(client): ProviderClient<OIDCClient>,
     Query(query): Query<RequestParams>,
+    client_info: ClientInfo,
     mut auth_session: AuthSession,
 ) -> AuthPage {
+    if !auth_session.tenant().is_provider_enabled(&client.provider) {
+        return state.page_error(auth_session, AuthError::ProviderNotAvailable, None);
+    }
+    let Some(oidc_client) = client.client() else {
+        return state.page_error(auth_session, AuthError::ProviderNotAvailable, None);
+    };
+
     let auth_code = AuthorizationCode::new(query.code);
     let auth_csrf_state = query.state;
 
@@ -33,10 +44,14 @@ pub(in crate::auth) async fn page_oidc_auth(
         error_url,
         remember_me,
         linked_user,
+        flow_id,
     } = match auth_session.external_login.take() {
         Some(external_login) => external_login,
         None => return state.page_error(auth_session, AuthError::MissingExternalLogin, None),
     };
+    if let Some(flow_id) = flow_id {
+        state.resume_auth_flow(&mut auth_session, flow_id, &client.provider);
+    }
 
     let nonce = match nonce {
         Some(nonce) => nonce,
@@ -45,13 +60,16 @@ pub(in crate::auth) async fn page_oidc_auth(
 
     // Check for Cross Site Request Forgery
     if csrf_state != auth_csrf_state {
-        log::debug!("CSRF test failed: [{csrf_state}], [{auth_csrf_state}]");
+        log::debug!(
+            "CSRF test failed: [{}], [{}]",
+            RedactedToken(&csrf_state),
+            RedactedToken(&auth_csrf_state)
+        );
         return state.page_error(auth_session, AuthError::InvalidCSRF, error_url.as_ref());
     }
 
     // Exchange the code with a token.
-    let token = match client
-        .client
+    let token = match oidc_client
         .exchange_code(auth_code)
         .set_pkce_verifier(PkceCodeVerifier::new(pkce_code_verifier))
         .request_async(async_http_client)
@@ -61,15 +79,32 @@ pub(in crate::auth) async fn page_oidc_auth(
         Err(err) => return state.page_internal_error(auth_session, err, error_url.as_ref()),
     };
 
-    let claims = match token.id_token().and_then(|id_token| {
-        id_token
-            .claims(&client.client.id_token_verifier(), &Nonce::new(nonce))
-            .ok()
-    }) {
-        Some(claims) => claims,
-        _ => return state.page_error(auth_session, AuthError::FailedExternalUserInfo, error_url.as_ref()),
+    let id_token = match token.id_token() {
+        Some(id_token) => id_token,
+        None => return state.page_error(auth_session, AuthError::FailedExternalUserInfo, error_url.as_ref()),
+    };
+    let nonce = Nonce::new(nonce);
+    let mut verified_claims = id_token.claims(&client.id_token_verifier(&oidc_client), &nonce);
+    if let Err(ClaimsVerificationError::SignatureVerification(SignatureVerificationError::NoMatchingKey)) =
+        verified_claims
+    {
+        // the provider might have rotated its keys since the discovery
+        if let Some(oidc_client) = client.refresh_keys().await {
+            verified_claims = id_token.claims(&client.id_token_verifier(&oidc_client), &nonce);
+        }
+    }
+    let claims = match verified_claims {
+        Ok(claims) => claims,
+        Err(err) => {
+            tracing::info!(
+                provider = %client.provider,
+                outcome = "invalidIdToken",
+                "Failed to verify the id token: {err}"
+            );
+            return state.page_error(auth_session, AuthError::FailedExternalUserInfo, error_url.as_ref());
+        }
     };
-    log::debug!("Code exchange completed, claims: {claims:#?}");
+    log::debug!("Code exchange completed for {}", client.provider);
 
     let external_user_info = {
         let external_id = claims.subject().to_string();
@@ -78,15 +113,28 @@ pub(in crate::auth) async fn page_oidc_auth(
             .and_then(|n| n.get(None))
             .map(|n| n.as_str().to_owned());
         let email = claims.email().map(|n| n.as_str().to_owned());
+        let auth_context = SessionAuthContext {
+            acr: claims.auth_context_ref().map(|acr| acr.as_str().to_owned()),
+            amr: claims
+                .auth_method_refs()
+                .map(|amr| amr.iter().map(|method| method.as_str().to_owned()).collect())
+                .unwrap_or_default(),
+            auth_time: claims.auth_time(),
+        };
 
         ExternalUserInfo {
             provider: client.provider.clone(),
             provider_id: external_id,
             name,
             email,
+            auth_context: Some(auth_context),
         }
     };
-    log::info!("{:?}", external_user_info);
+    tracing::info!(
+        provider = %external_user_info.provider,
+        has_email = external_user_info.email.is_some(),
+        "External user info received"
+    );
 
     if linked_user.is_some() {
         state
@@ -103,6 +151,7 @@ pub(in crate::auth) async fn page_oidc_auth(
             .page_external_login(
                 auth_session,
                 external_user_info,
+                &client_info,
                 target_url.as_ref(),
                 error_url.as_ref(),
                 remember_me,
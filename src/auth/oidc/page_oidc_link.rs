@@ -1,16 +1,14 @@
-use crate::auth::{AuthError, AuthPage, AuthServiceState, AuthSession, ExternalLogin, OIDCClient};
-use axum::{
-    extract::{Query, State},
-    Extension,
+use crate::{
+    auth::{AuthError, AuthPage, AuthServiceState, AuthSession, ExternalLogin, OIDCClient, ProviderClient},
+    db::AnalyticsEvent,
 };
-use chrono::Duration;
+use axum::extract::{Query, State};
 use oauth2::{CsrfToken, PkceCodeChallenge};
 use openidconnect::{
     core::{CoreAuthPrompt, CoreAuthenticationFlow},
     Nonce,
 };
 use serde::Deserialize;
-use std::sync::Arc;
 use url::Url;
 
 #[derive(Deserialize)]
@@ -23,17 +21,23 @@ pub(in crate::auth) struct RequestParams {
 /// Link the current user to an OpenId Connect provider.
 pub(in crate::auth) async fn page_oidc_link(
     State(state): State<AuthServiceState>,
-    Extension(client): Extension<Arc<OIDCClient>>,
+    ProviderClient(client): ProviderClient<OIDCClient>,
     Query(query): Query<RequestParams>,
     mut auth_session: AuthSession,
 ) -> AuthPage {
+    if !auth_session.tenant().is_provider_enabled(&client.provider) {
+        return state.page_error(auth_session, AuthError::ProviderNotAvailable, query.error_url.as_ref());
+    }
+    let Some(oidc_client) = client.client() else {
+        return state.page_error(auth_session, AuthError::ProviderNotAvailable, query.error_url.as_ref());
+    };
+    let flow_id = state.start_auth_flow(&mut auth_session, &client.provider);
     if auth_session.user.is_none() {
         return state.page_error(auth_session, AuthError::LoginRequired, query.error_url.as_ref());
     }
 
     let (pkce_code_challenge, pkce_code_verifier) = PkceCodeChallenge::new_random_sha256();
-    let (authorize_url, csrf_state, nonce) = client
-        .client
+    let mut authorize_request = oidc_client
         .authorize_url(
             CoreAuthenticationFlow::AuthorizationCode,
             CsrfToken::new_random,
@@ -41,9 +45,14 @@ pub(in crate::auth) async fn page_oidc_link(
         )
         .add_scopes(client.scopes.clone())
         .set_pkce_challenge(pkce_code_challenge)
-        .set_max_age(Duration::minutes(30).to_std().unwrap())
-        .add_prompt(CoreAuthPrompt::Login)
-        .url();
+        .set_max_age(client.max_auth_age);
+    if !client.auth_params.contains_key("prompt") {
+        authorize_request = authorize_request.add_prompt(CoreAuthPrompt::Login);
+    }
+    for (name, value) in &client.auth_params {
+        authorize_request = authorize_request.add_extra_param(name.as_str(), value.as_str());
+    }
+    let (authorize_url, csrf_state, nonce) = authorize_request.url();
 
     auth_session.external_login = Some(ExternalLogin {
         pkce_code_verifier: pkce_code_verifier.secret().to_owned(),
@@ -53,7 +62,9 @@ pub(in crate::auth) async fn page_oidc_link(
         error_url: query.error_url,
         remember_me: false,
         linked_user: auth_session.user.clone(),
+        flow_id: Some(flow_id),
     });
 
+    state.emit_flow_event(&auth_session, AnalyticsEvent::ProviderRedirect, None);
     state.page_redirect(auth_session, &client.provider, Some(&authorize_url))
 }
@@ -1,38 +1,141 @@
-use crate::auth::{AuthError, AuthPage, AuthServiceState, AuthSession};
-use axum::extract::{Query, State};
+use crate::{
+    auth::{AuthError, AuthPage, AuthServiceState, AuthSession},
+    db::DeletionError,
+};
+use axum::{
+    extract::{Form, Query, State},
+    http::StatusCode,
+};
+use ring::digest;
 use serde::Deserialize;
-use shine_service::service::APP_NAME;
+use shine_service::service::{CurrentUser, APP_NAME};
 use url::Url;
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub(in crate::auth) struct RequestParams {
     redirect_url: Option<Url>,
+    login_url: Option<Url>,
     error_url: Option<Url>,
 }
 
-/// Delete he current user. This is not a soft delete, once executed there is no way back.
-/// Note, it only deletes the user and login credentials, but not the data of the user.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct ConfirmParams {
+    csrf: String,
+    redirect_url: Option<Url>,
+    error_url: Option<Url>,
+}
+
+/// The CSRF token of the deletion form. It is bound to the session, thus it is not stored anywhere.
+fn delete_csrf_token(user: &CurrentUser) -> String {
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    ctx.update(b"delete");
+    ctx.update(user.user_id.as_bytes());
+    ctx.update(user.key.to_hex().as_bytes());
+    hex::encode(ctx.finish())
+}
+
+impl AuthServiceState {
+    /// Check if the session was created recently enough to delete the account.
+    fn is_fresh_session(&self, user: &CurrentUser) -> bool {
+        self.clock().now() - user.session_start <= self.deletion_manager().reauth_window()
+    }
+}
+
+/// Ask for the confirmation before the current user is deleted. If the session is not fresh, the user has to log
+/// in again first.
 pub(in crate::auth) async fn page_delete_user(
     State(state): State<AuthServiceState>,
     Query(query): Query<RequestParams>,
-    mut auth_session: AuthSession,
+    auth_session: AuthSession,
 ) -> AuthPage {
-    let (user_id, user_key) = match auth_session.user.as_ref().map(|u| (u.user_id, u.key)) {
-        Some(user_id) => user_id,
+    let user = match auth_session.user.as_ref() {
+        Some(user) => user,
         None => return state.page_error(auth_session, AuthError::LoginRequired, query.error_url.as_ref()),
     };
 
+    let mut context = tera::Context::new();
+    context.insert("app_name", APP_NAME);
+    context.insert(
+        "cancel_url",
+        query
+            .redirect_url
+            .as_ref()
+            .unwrap_or(auth_session.tenant().home_url())
+            .as_str(),
+    );
+    if state.is_fresh_session(user) {
+        context.insert("csrf", &delete_csrf_token(user));
+        context.insert("redirect_url", &query.redirect_url.as_ref().map(Url::as_str));
+        context.insert("error_url", &query.error_url.as_ref().map(Url::as_str));
+    } else {
+        // the login token is also revoked, a silent token login is not a re-authentication
+        let mut logout_url = auth_session.tenant().page_url(&["logout"]);
+        logout_url.query_pairs_mut().append_pair("scope", "device").append_pair(
+            "redirectUrl",
+            query
+                .login_url
+                .as_ref()
+                .unwrap_or(auth_session.tenant().home_url())
+                .as_str(),
+        );
+        context.insert("reauth_url", logout_url.as_str());
+    }
+    let html = state
+        .tera()
+        .render("account_delete.html", &context)
+        .expect("Failed to generate account_delete.html template");
+
+    AuthPage {
+        status: StatusCode::OK,
+        auth_session: Some(auth_session),
+        html,
+    }
+}
+
+/// Delete the current user. With a deletion grace period the account is only marked for deletion and a login
+/// within the grace period restores it, otherwise there is no way back.
+/// Note, it only deletes the user and login credentials, but not the data of the user.
+pub(in crate::auth) async fn page_delete_user_confirm(
+    State(state): State<AuthServiceState>,
+    mut auth_session: AuthSession,
+    Form(form): Form<ConfirmParams>,
+) -> AuthPage {
+    let user = match auth_session.user.as_ref() {
+        Some(user) => user,
+        None => return state.page_error(auth_session, AuthError::LoginRequired, form.error_url.as_ref()),
+    };
+    if form.csrf != delete_csrf_token(user) {
+        return state.page_error(auth_session, AuthError::InvalidCSRF, form.error_url.as_ref());
+    }
+    if !state.is_fresh_session(user) {
+        return state.page_error(auth_session, AuthError::ReauthRequired, form.error_url.as_ref());
+    }
+    let (user_id, user_key) = (user.user_id, user.key);
+
     // validate session as this is a very risky operation
-    match state.session_manager().find_session(user_id, user_key).await {
-        Ok(None) => return state.page_error(auth_session, AuthError::SessionExpired, query.error_url.as_ref()),
-        Err(err) => return state.page_internal_error(auth_session, err, query.error_url.as_ref()),
+    match state
+        .session_manager()
+        .find_session(auth_session.tenant().id(), user_id, user_key)
+        .await
+    {
+        Ok(None) => return state.page_error(auth_session, AuthError::SessionExpired, form.error_url.as_ref()),
+        Err(err) => return state.page_internal_error(auth_session, err, form.error_url.as_ref()),
         Ok(Some(_)) => {}
     };
 
-    if let Err(err) = state.identity_manager().cascaded_delete(user_id).await {
-        return state.page_internal_error(auth_session, err, query.error_url.as_ref());
-    }
+    let grace_period = state
+        .user_region_policy(user_id)
+        .await
+        .and_then(|policy| policy.deletion_grace_period());
+    let purge_after = match state.deletion_manager().delete(user_id, grace_period).await {
+        Ok(purge_after) => purge_after,
+        Err(DeletionError::LegalHold) => {
+            return state.page_error(auth_session, AuthError::DeletionRefused, form.error_url.as_ref())
+        }
+        Err(err) => return state.page_internal_error(auth_session, err, form.error_url.as_ref()),
+    };
 
     // from this point there is no reason to keep session
     // errors beyond these points are irrelevant for the users and mostly just warnings.
@@ -41,5 +144,28 @@ pub(in crate::auth) async fn page_delete_user(
         log::warn!("Failed to clear all sessions for user {}: {:?}", user_id, err);
     }
 
-    state.page_redirect(auth_session, APP_NAME, query.redirect_url.as_ref())
+    match purge_after {
+        Some(purge_after) => {
+            let mut context = tera::Context::new();
+            context.insert("app_name", APP_NAME);
+            context.insert("purge_after", &purge_after.format("%Y-%m-%d %H:%M UTC").to_string());
+            context.insert(
+                "redirect_url",
+                form.redirect_url
+                    .as_ref()
+                    .unwrap_or(auth_session.tenant().home_url())
+                    .as_str(),
+            );
+            let html = state
+                .tera()
+                .render("account_deleted.html", &context)
+                .expect("Failed to generate account_deleted.html template");
+            AuthPage {
+                status: StatusCode::OK,
+                auth_session: Some(auth_session),
+                html,
+            }
+        }
+        None => state.page_redirect(auth_session, APP_NAME, form.redirect_url.as_ref()),
+    }
 }
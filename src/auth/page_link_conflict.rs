@@ -0,0 +1,159 @@
+use crate::{
+    auth::{AuthError, AuthPage, AuthServiceState, AuthSession},
+    db::{ExternalLoginInfo, FindIdentity},
+};
+use axum::{
+    extract::{Form, State},
+    http::StatusCode,
+};
+use ring::digest;
+use serde::Deserialize;
+use serde_json::json;
+use shine_service::service::{CurrentUser, APP_NAME};
+use url::Url;
+use uuid::Uuid;
+
+/// The token of the merge request form. It is bound to the session, the other identity and the provider, thus only
+/// the identity found in the link conflict can be requested for a merge.
+fn merge_request_token(user: &CurrentUser, source_user_id: Uuid, provider: &str) -> String {
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    ctx.update(b"merge");
+    ctx.update(user.user_id.as_bytes());
+    ctx.update(user.key.to_hex().as_bytes());
+    ctx.update(source_user_id.as_bytes());
+    ctx.update(provider.as_bytes());
+    hex::encode(ctx.finish())
+}
+
+impl AuthServiceState {
+    /// Explain that the external account is linked to another identity and offer to sign in with that identity or
+    /// to request the merge of the two identities.
+    pub(in crate::auth) async fn page_link_conflict(
+        &self,
+        auth_session: AuthSession,
+        external_login: &ExternalLoginInfo,
+        target_url: Option<&Url>,
+        error_url: Option<&Url>,
+    ) -> AuthPage {
+        let user = auth_session.user.as_ref().unwrap();
+        let source_user_id = match self
+            .identity_manager()
+            .find(FindIdentity::ExternalLogin(external_login))
+            .await
+        {
+            Ok(Some(identity)) => identity.user_id,
+            // the link was removed in the meantime
+            Ok(None) => return self.page_error(auth_session, AuthError::ProviderAlreadyUsed, error_url),
+            Err(err) => return self.page_internal_error(auth_session, err, error_url),
+        };
+        if source_user_id == user.user_id {
+            // already linked to the current user, nothing to resolve
+            return self.page_redirect(auth_session, APP_NAME, target_url);
+        }
+
+        let tenant = auth_session.tenant();
+        let return_url = target_url.unwrap_or(tenant.home_url());
+
+        // the login token is also revoked, otherwise the token login would sign in the current user again
+        let mut login_url = tenant.page_url(&[&external_login.provider, "login"]);
+        login_url
+            .query_pairs_mut()
+            .append_pair("redirectUrl", return_url.as_str());
+        let mut signin_url = tenant.page_url(&["logout"]);
+        signin_url
+            .query_pairs_mut()
+            .append_pair("scope", "device")
+            .append_pair("redirectUrl", login_url.as_str());
+
+        let mut context = tera::Context::new();
+        context.insert("app_name", APP_NAME);
+        context.insert("provider", &external_login.provider);
+        context.insert("signin_url", signin_url.as_str());
+        context.insert("merge_url", tenant.page_url(&["links", "merge"]).as_str());
+        context.insert("source_user_id", &source_user_id.to_string());
+        context.insert(
+            "token",
+            &merge_request_token(user, source_user_id, &external_login.provider),
+        );
+        context.insert("redirect_url", return_url.as_str());
+        context.insert("error_url", &error_url.map(Url::as_str));
+        let html = self
+            .tera()
+            .render("link_conflict.html", &context)
+            .expect("Failed to generate link_conflict.html template");
+
+        AuthPage {
+            status: StatusCode::CONFLICT,
+            auth_session: Some(auth_session),
+            html,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct MergeRequestParams {
+    source_user_id: Uuid,
+    provider: String,
+    token: String,
+    redirect_url: Option<Url>,
+    error_url: Option<Url>,
+}
+
+/// Request the merge of the identity found in a link conflict into the current user. The merge itself is
+/// performed by the administrators.
+pub(in crate::auth) async fn page_merge_request(
+    State(state): State<AuthServiceState>,
+    auth_session: AuthSession,
+    Form(form): Form<MergeRequestParams>,
+) -> AuthPage {
+    let user = match auth_session.user.as_ref() {
+        Some(user) => user,
+        None => return state.page_error(auth_session, AuthError::LoginRequired, form.error_url.as_ref()),
+    };
+    if form.token != merge_request_token(user, form.source_user_id, &form.provider) {
+        return state.page_error(auth_session, AuthError::InvalidCSRF, form.error_url.as_ref());
+    }
+    let user_id = user.user_id;
+
+    let request_id = match state
+        .merge_manager()
+        .create_request(user_id, form.source_user_id, &form.provider)
+        .await
+    {
+        Ok(request_id) => request_id,
+        Err(err) => return state.page_internal_error(auth_session, err, form.error_url.as_ref()),
+    };
+    if let Err(err) = state
+        .audit_log()
+        .record(
+            Some(user_id),
+            "merge.request",
+            Some(form.source_user_id),
+            json!({ "requestId": request_id, "provider": form.provider }),
+        )
+        .await
+    {
+        log::error!("Failed to record the merge request {request_id}: {err}");
+    }
+
+    let mut context = tera::Context::new();
+    context.insert("app_name", APP_NAME);
+    context.insert(
+        "redirect_url",
+        form.redirect_url
+            .as_ref()
+            .unwrap_or(auth_session.tenant().home_url())
+            .as_str(),
+    );
+    let html = state
+        .tera()
+        .render("merge_requested.html", &context)
+        .expect("Failed to generate merge_requested.html template");
+
+    AuthPage {
+        status: StatusCode::OK,
+        auth_session: Some(auth_session),
+        html,
+    }
+}
@@ -0,0 +1,173 @@
+use crate::auth::{AuthError, AuthPage, AuthServiceState, AuthSession};
+use axum::{
+    extract::{Form, Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use shine_service::service::APP_NAME;
+use uuid::Uuid;
+
+#[derive(Serialize)]
+struct ProviderLink {
+    provider: String,
+    linked: bool,
+    link_url: String,
+    unlink_url: String,
+}
+
+/// List the providers of the tenant with the link state of the current user.
+pub(in crate::auth) async fn page_links(State(state): State<AuthServiceState>, auth_session: AuthSession) -> AuthPage {
+    let user_id = match auth_session.user.as_ref().map(|u| u.user_id) {
+        Some(user_id) => user_id,
+        None => return state.page_error(auth_session, AuthError::LoginRequired, None),
+    };
+
+    let links = match state.identity_manager().get_links(user_id).await {
+        Ok(links) => links,
+        Err(err) => return state.page_internal_error(auth_session, err, None),
+    };
+
+    let tenant = auth_session.tenant();
+    let links_url = tenant.page_url(&["links"]);
+    let providers: Vec<_> = tenant
+        .providers()
+        .iter()
+        .map(|provider| {
+            let mut link_url = tenant.page_url(&[provider, "link"]);
+            link_url
+                .query_pairs_mut()
+                .append_pair("redirectUrl", links_url.as_str())
+                .append_pair("errorUrl", links_url.as_str());
+            let mut unlink_url = tenant.page_url(&["links", "unlink"]);
+            unlink_url.query_pairs_mut().append_pair("provider", provider);
+
+            ProviderLink {
+                provider: provider.clone(),
+                linked: links.iter().any(|link| &link.provider == provider),
+                link_url: link_url.to_string(),
+                unlink_url: unlink_url.to_string(),
+            }
+        })
+        .collect();
+
+    let mut context = tera::Context::new();
+    context.insert("app_name", APP_NAME);
+    context.insert("providers", &providers);
+    let html = state
+        .tera()
+        .render("links.html", &context)
+        .expect("Failed to generate links.html template");
+
+    AuthPage {
+        status: StatusCode::OK,
+        auth_session: Some(auth_session),
+        html,
+    }
+}
+
+#[derive(Deserialize)]
+pub(in crate::auth) struct UnlinkParams {
+    provider: String,
+}
+
+/// Ask for the confirmation before a provider is unlinked.
+pub(in crate::auth) async fn page_unlink(
+    State(state): State<AuthServiceState>,
+    Query(query): Query<UnlinkParams>,
+    auth_session: AuthSession,
+) -> AuthPage {
+    let user_id = match auth_session.user.as_ref().map(|u| u.user_id) {
+        Some(user_id) => user_id,
+        None => return state.page_error(auth_session, AuthError::LoginRequired, None),
+    };
+
+    if let Err(err) = state.check_unlink(user_id, &query.provider).await {
+        return state.page_error(auth_session, err, None);
+    }
+
+    let mut context = tera::Context::new();
+    context.insert("app_name", APP_NAME);
+    context.insert("provider", &query.provider);
+    context.insert("links_url", auth_session.tenant().page_url(&["links"]).as_str());
+    let html = state
+        .tera()
+        .render("unlink.html", &context)
+        .expect("Failed to generate unlink.html template");
+
+    AuthPage {
+        status: StatusCode::OK,
+        auth_session: Some(auth_session),
+        html,
+    }
+}
+
+/// Remove the link of a provider from the current user and return to the link management page.
+pub(in crate::auth) async fn page_unlink_confirm(
+    State(state): State<AuthServiceState>,
+    auth_session: AuthSession,
+    Form(form): Form<UnlinkParams>,
+) -> AuthPage {
+    let (user_id, user_key) = match auth_session.user.as_ref().map(|u| (u.user_id, u.key)) {
+        Some(user) => user,
+        None => return state.page_error(auth_session, AuthError::LoginRequired, None),
+    };
+
+    // validate session as the user may lose access to the account
+    match state
+        .session_manager()
+        .find_session(auth_session.tenant().id(), user_id, user_key)
+        .await
+    {
+        Ok(None) => return state.page_error(auth_session, AuthError::SessionExpired, None),
+        Err(err) => return state.page_internal_error(auth_session, err, None),
+        Ok(Some(_)) => {}
+    };
+
+    if let Err(err) = state.check_unlink(user_id, &form.provider).await {
+        return state.page_error(auth_session, err, None);
+    }
+
+    match state.identity_manager().unlink_user(user_id, &form.provider).await {
+        Ok(true) => {}
+        // the login methods have changed since the check
+        Ok(false) => {
+            let err = match state.check_unlink(user_id, &form.provider).await {
+                Err(err) => err,
+                Ok(()) => AuthError::LastLoginMethod,
+            };
+            return state.page_error(auth_session, err, None);
+        }
+        Err(err) => return state.page_internal_error(auth_session, err, None),
+    }
+
+    let links_url = auth_session.tenant().page_url(&["links"]);
+    state.page_redirect(auth_session, APP_NAME, Some(&links_url))
+}
+
+impl AuthServiceState {
+    /// Check if a provider can be unlinked without leaving the user without any login method.
+    async fn check_unlink(&self, user_id: Uuid, provider: &str) -> Result<(), AuthError> {
+        let links = self
+            .identity_manager()
+            .get_links(user_id)
+            .await
+            .map_err(|err| AuthError::InternalServerError(format!("{err:?}")))?;
+        if !links.iter().any(|link| link.provider == provider) {
+            return Err(AuthError::ProviderNotLinked);
+        }
+
+        if links.len() > 1 {
+            return Ok(());
+        }
+        let has_password = self
+            .password_manager()
+            .has_password(user_id)
+            .await
+            .map_err(|err| AuthError::InternalServerError(format!("{err:?}")))?;
+        if has_password {
+            Ok(())
+        } else {
+            Err(AuthError::LastLoginMethod)
+        }
+    }
+}
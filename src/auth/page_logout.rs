@@ -4,8 +4,23 @@ use serde::Deserialize;
 use shine_service::service::APP_NAME;
 use url::Url;
 
+/// The extent of a logout.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) enum LogoutScope {
+    /// Terminate the current session only, the device can log in again using its login token.
+    Session,
+    /// Terminate the current session and revoke the login token of the device.
+    #[default]
+    Device,
+    /// Terminate all the sessions and revoke all the login tokens of the user.
+    Everywhere,
+}
+
 #[derive(Deserialize)]
 pub(in crate::auth) struct LogoutRequest {
+    scope: Option<LogoutScope>,
+    /// Deprecated, replaced by the `scope`: `true` is the `everywhere` and `false` is the `device` scope.
     terminate_all: Option<bool>,
     redirect_url: Option<Url>,
     error_url: Option<Url>,
@@ -16,35 +31,68 @@ pub(in crate::auth) async fn page_logout(
     Query(query): Query<LogoutRequest>,
     mut auth_session: AuthSession,
 ) -> AuthPage {
-    if let Some((user_id, user_key)) = auth_session.user.as_ref().map(|u| (u.user_id, u.key)) {
-        match query.terminate_all.unwrap_or(false) {
-            false => {
-                if let Err(err) = state.identity_manager().delete_all_tokens(user_id).await {
-                    return state.page_internal_error(auth_session, err, query.error_url.as_ref());
-                }
+    let scope = query
+        .scope
+        .or(query.terminate_all.map(|all| match all {
+            true => LogoutScope::Everywhere,
+            false => LogoutScope::Device,
+        }))
+        .unwrap_or_default();
+    let user = auth_session.user.as_ref().map(|u| (u.user_id, u.key));
+    let token = auth_session.token_login.as_ref().map(|t| (t.user_id, t.token.clone()));
 
-                // from this point there is no reason to keep session
-                // errors beyond these points are irrelevant for the users and mostly just warnings.
-                auth_session.clear();
-                if let Err(err) = state.session_manager().remove_all(user_id).await {
-                    log::warn!("Failed to clear all sessions for user {}: {:?}", user_id, err);
+    match scope {
+        LogoutScope::Session => {}
+        LogoutScope::Device => {
+            if let Some((user_id, token)) = token {
+                let token_info = match state.identity_manager().find_token(&token).await {
+                    Ok(token_info) => token_info.map(|(_, token_info)| token_info),
+                    Err(err) => return state.page_internal_error(auth_session, err, query.error_url.as_ref()),
+                };
+                if let Err(err) = state.identity_manager().delete_token(user_id, &token).await {
+                    return state.page_internal_error(auth_session, err, query.error_url.as_ref());
                 }
-            }
-            true => {
-                if let Some(token) = auth_session.token_login.as_ref().map(|t| t.token.clone()) {
-                    if let Err(err) = state.identity_manager().delete_token(user_id, &token).await {
+                if let Some(token_info) = token_info {
+                    if let Err(err) = state
+                        .token_revocation()
+                        .revoke_token(token_info.token_id, state.token().max_duration())
+                        .await
+                    {
                         return state.page_internal_error(auth_session, err, query.error_url.as_ref());
                     }
                 }
-
-                // from this point there is no reason to keep session
-                // errors beyond these points are irrelevant for the users and mostly just warnings.
-                auth_session.clear();
-                if let Err(err) = state.session_manager().remove(user_id, user_key).await {
-                    log::warn!("Failed to clear session for user {}: {:?}", user_id, err);
+            }
+        }
+        LogoutScope::Everywhere => {
+            if let Some(user_id) = user.map(|(user_id, _)| user_id).or(token.map(|(user_id, _)| user_id)) {
+                if let Err(err) = state.identity_manager().delete_all_tokens(user_id).await {
+                    return state.page_internal_error(auth_session, err, query.error_url.as_ref());
+                }
+                if let Err(err) = state
+                    .token_revocation()
+                    .revoke_user(user_id, state.token().max_duration())
+                    .await
+                {
+                    return state.page_internal_error(auth_session, err, query.error_url.as_ref());
                 }
             }
+        }
+    };
+
+    // from this point there is no reason to keep session
+    // errors beyond these points are irrelevant for the users and mostly just warnings.
+    match scope {
+        LogoutScope::Session => auth_session.user = None,
+        LogoutScope::Device | LogoutScope::Everywhere => auth_session.clear(),
+    };
+    if let Some((user_id, user_key)) = user {
+        let result = match scope {
+            LogoutScope::Session | LogoutScope::Device => state.session_manager().remove(user_id, user_key).await,
+            LogoutScope::Everywhere => state.session_manager().remove_all(user_id).await,
         };
+        if let Err(err) = result {
+            log::warn!("Failed to clear sessions for user {}: {:?}", user_id, err);
+        }
     }
 
     state.page_redirect(auth_session, APP_NAME, query.redirect_url.as_ref())
@@ -0,0 +1,54 @@
+use crate::auth::{AuthError, AuthPage, AuthServiceState, AuthSession};
+use axum::{
+    extract::{Form, Query, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use shine_service::service::APP_NAME;
+
+#[derive(Deserialize)]
+pub(in crate::auth) struct RevokeRequest {
+    token: String,
+}
+
+/// Ask for the confirmation before the session of a login alert email is revoked. The link may be opened by a mail
+/// scanner or a link preview, thus the token is consumed only by the form.
+pub(in crate::auth) async fn page_revoke_session(
+    State(state): State<AuthServiceState>,
+    Query(query): Query<RevokeRequest>,
+    auth_session: AuthSession,
+) -> AuthPage {
+    let mut context = tera::Context::new();
+    context.insert("app_name", APP_NAME);
+    context.insert("token", &query.token);
+    let html = state
+        .tera()
+        .render("revoke_session.html", &context)
+        .expect("Failed to generate revoke_session.html template");
+
+    AuthPage {
+        status: StatusCode::OK,
+        auth_session: Some(auth_session),
+        html,
+    }
+}
+
+/// Revoke a session and all the login tokens of the user using the link of a login alert email.
+pub(in crate::auth) async fn page_revoke_session_confirm(
+    State(state): State<AuthServiceState>,
+    auth_session: AuthSession,
+    Form(form): Form<RevokeRequest>,
+) -> AuthPage {
+    let user_id = match state.session_manager().revoke_by_token(&form.token).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return state.page_error(auth_session, AuthError::InvalidRevokeToken, None),
+        Err(err) => return state.page_internal_error(auth_session, err, None),
+    };
+
+    log::info!("Session of user {user_id} revoked by login alert");
+    if let Err(err) = state.identity_manager().delete_all_tokens(user_id).await {
+        return state.page_internal_error(auth_session, err, None);
+    }
+
+    state.page_redirect(auth_session, APP_NAME, None)
+}
@@ -0,0 +1,153 @@
+use crate::{
+    auth::AuthServiceState,
+    db::{DBError, UserChange},
+    email::EmailError,
+};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::Duration;
+use lettre::Address;
+use serde::{Deserialize, Serialize};
+use shine_service::service::APP_NAME;
+use thiserror::Error as ThisError;
+use url::Url;
+use uuid::Uuid;
+
+/// The restriction of the minor accounts until a parent consents.
+pub const PENDING_CONSENT_RESTRICTION: &str = "pending-consent";
+
+/// The consent of the parents to the accounts of the minors.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParentalConsentConfig {
+    /// The page of the consent link, the token is added as the `token` query parameter.
+    pub consent_page_url: Url,
+    /// Validity of the consent links in seconds.
+    #[serde(default = "ParentalConsentConfig::default_link_duration")]
+    pub link_duration: u64,
+}
+
+impl ParentalConsentConfig {
+    fn default_link_duration() -> u64 {
+        7 * 24 * 3600
+    }
+
+    pub fn link_duration(&self) -> Duration {
+        Duration::seconds(self.link_duration as i64)
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum ParentalConsentError {
+    #[error("Parental consent is not enabled")]
+    ConsentDisabled,
+    #[error("Invalid email of the parent")]
+    InvalidEmail,
+    #[error("Parental consent is not pending")]
+    NotPending,
+    #[error("Too many consent requests")]
+    TooManyRequests,
+    #[error("Consent token is invalid or has expired")]
+    InvalidToken,
+    #[error("Failed to generate token: {0}")]
+    TokenGenerator(String),
+    #[error(transparent)]
+    EmailError(#[from] EmailError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for ParentalConsentError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            ParentalConsentError::ConsentDisabled => StatusCode::NOT_FOUND,
+            ParentalConsentError::InvalidEmail | ParentalConsentError::InvalidToken => StatusCode::BAD_REQUEST,
+            ParentalConsentError::NotPending => StatusCode::CONFLICT,
+            ParentalConsentError::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+impl AuthServiceState {
+    pub(in crate::auth) fn parental_consent_config(&self) -> Option<&ParentalConsentConfig> {
+        self.age_gate_config()
+            .and_then(|age_gate| age_gate.parental_consent.as_ref())
+    }
+
+    /// Restrict the account of a minor until a parent consents. The consent link is sent if the email of the parent
+    /// is known, otherwise it can be requested later by the user.
+    pub(in crate::auth) async fn require_parental_consent(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        parent_email: Option<&str>,
+    ) -> Result<(), ParentalConsentError> {
+        if self.parental_consent_config().is_none() {
+            return Ok(());
+        }
+
+        self.restriction_manager()
+            .set_restriction(
+                user_id,
+                PENDING_CONSENT_RESTRICTION,
+                "Parental consent is pending",
+                None,
+                None,
+            )
+            .await?;
+        if let Err(err) = self
+            .user_invalidation()
+            .invalidate(user_id, UserChange::Restrictions)
+            .await
+        {
+            log::error!("Failed to publish the restrictions of {user_id}: {err}");
+        }
+
+        if let Some(parent_email) = parent_email {
+            self.send_parental_consent_request(user_id, name, parent_email).await?;
+        }
+        Ok(())
+    }
+
+    /// Send a new consent link to the parent, the previous links of the user are invalidated.
+    pub(in crate::auth) async fn send_parental_consent_request(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        parent_email: &str,
+    ) -> Result<(), ParentalConsentError> {
+        let config = self
+            .parental_consent_config()
+            .ok_or(ParentalConsentError::ConsentDisabled)?;
+        if parent_email.parse::<Address>().is_err() {
+            return Err(ParentalConsentError::InvalidEmail);
+        }
+
+        let token = self
+            .token()
+            .generate_token()
+            .map_err(|err| ParentalConsentError::TokenGenerator(format!("{err}")))?;
+        if !self
+            .parental_consent_manager()
+            .request_consent(user_id, parent_email, &token)
+            .await?
+        {
+            return Err(ParentalConsentError::NotPending);
+        }
+
+        let mut consent_url = config.consent_page_url.clone();
+        consent_url.query_pairs_mut().append_pair("token", &token);
+
+        let mut context = tera::Context::new();
+        context.insert("app_name", APP_NAME);
+        context.insert("name", name);
+        context.insert("consent_url", consent_url.as_str());
+        self.email_sender().send(parent_email, "parental_consent", &context)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{
+    auth::{
+        AuthServiceState, AuthSession, ClientInfo, LoginFailureKeys, LoginFrictionError, MfaError, RiskDecision,
+        TokenCreateError,
+    },
+    db::{DBError, DBSessionError, FindIdentity, IdentityError, PasswordError},
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum PasswordLoginError {
+    #[error("Logout required")]
+    LogoutRequired,
+    #[error("Invalid email or password")]
+    InvalidCredentials,
+    #[error("Too many login attempts")]
+    TooManyAttempts,
+    #[error("Login has been refused")]
+    LoginBlocked,
+    #[error(transparent)]
+    LoginFriction(#[from] LoginFrictionError),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    PasswordError(#[from] PasswordError),
+    #[error(transparent)]
+    MfaError(#[from] MfaError),
+    #[error(transparent)]
+    TokenCreateError(#[from] TokenCreateError),
+    #[error(transparent)]
+    SessionError(#[from] DBSessionError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for PasswordLoginError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            PasswordLoginError::LogoutRequired => StatusCode::CONFLICT,
+            PasswordLoginError::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            PasswordLoginError::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
+            PasswordLoginError::LoginBlocked | PasswordLoginError::MfaError(MfaError::Unavailable) => {
+                StatusCode::FORBIDDEN
+            }
+            PasswordLoginError::LoginFriction(LoginFrictionError::CaptchaRequired) => StatusCode::PRECONDITION_REQUIRED,
+            PasswordLoginError::LoginFriction(LoginFrictionError::InvalidCaptcha) => StatusCode::BAD_REQUEST,
+            PasswordLoginError::LoginFriction(LoginFrictionError::Cooldown(_)) => StatusCode::TOO_MANY_REQUESTS,
+            PasswordLoginError::LoginFriction(LoginFrictionError::Locked(_)) => StatusCode::LOCKED,
+            PasswordLoginError::SessionError(DBSessionError::SessionLimitReached) => StatusCode::CONFLICT,
+            PasswordLoginError::SessionError(DBSessionError::AccountExpired) => StatusCode::FORBIDDEN,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct PasswordLogin {
+    email: String,
+    password: String,
+    #[serde(default)]
+    remember_me: bool,
+    /// Label of the login token, used only with remember me.
+    device_name: Option<String>,
+    /// Response of the captcha, required after repeated failures.
+    captcha: Option<String>,
+}
+
+/// Login with email and password. On success the session cookies are set, if a second factor is required
+/// the pending login is stored and `202 Accepted` is returned.
+pub(in crate::auth) async fn ep_password_login(
+    State(state): State<AuthServiceState>,
+    client_info: ClientInfo,
+    mut auth_session: AuthSession,
+    Json(request): Json<PasswordLogin>,
+) -> Result<(AuthSession, StatusCode), PasswordLoginError> {
+    if auth_session.user.is_some() {
+        return Err(PasswordLoginError::LogoutRequired);
+    }
+
+    let config = state.password_config();
+    let tenant_id = auth_session.tenant().id();
+    let email = state.identity_manager().normalize_email(&request.email);
+    let rate_key = format!("password-login:{tenant_id}:{email}");
+    if !state
+        .rate_limiter()
+        .check(&rate_key, config.login_rate_limit, config.rate_window())
+        .await?
+    {
+        return Err(PasswordLoginError::TooManyAttempts);
+    }
+
+    let failure_keys = LoginFailureKeys::new(&client_info, tenant_id, &email);
+    state
+        .check_login_friction(&failure_keys, request.captcha.as_deref(), &client_info)
+        .await?;
+
+    let identity = state
+        .identity_manager()
+        .find(FindIdentity::Email {
+            tenant_id,
+            email: &request.email,
+        })
+        .await?;
+    let is_valid = match &identity {
+        Some(identity) => {
+            state
+                .password_manager()
+                .verify_password(identity.user_id, &request.password)
+                .await?
+        }
+        None => state.password_manager().verify_unknown(&request.password).await?,
+    };
+    let identity = match identity {
+        Some(identity) if is_valid => identity,
+        identity => {
+            state
+                .record_login_failure(&failure_keys, identity.as_ref(), &client_info)
+                .await?;
+            return Err(PasswordLoginError::InvalidCredentials);
+        }
+    };
+    state.clear_login_failures(&failure_keys).await?;
+
+    let risk = state.assess_login_risk(&identity, &client_info).await;
+    if risk == RiskDecision::Block {
+        return Err(PasswordLoginError::LoginBlocked);
+    }
+
+    if state
+        .start_mfa(
+            &mut auth_session,
+            &identity,
+            request.remember_me,
+            request.device_name.as_deref(),
+            None,
+            risk == RiskDecision::RequireMfa,
+        )
+        .await?
+        .is_some()
+    {
+        return Ok((auth_session, StatusCode::ACCEPTED));
+    }
+
+    let token_login = if request.remember_me {
+        Some(
+            state
+                .create_token_with_retry(&identity, request.device_name.as_deref(), &client_info)
+                .await?,
+        )
+    } else {
+        None
+    };
+
+    let user = state.create_session(&identity, None).await?;
+    state
+        .check_login_device(auth_session.tenant(), &identity, &user, &client_info)
+        .await;
+    state.restore_deleted_account(&identity).await;
+
+    auth_session.token_login = token_login;
+    auth_session.user = Some(user);
+    Ok((auth_session, StatusCode::NO_CONTENT))
+}
@@ -0,0 +1,166 @@
+use crate::{
+    auth::{AuthServiceState, BotTrap, PasswordRejection, Tenant},
+    db::{DBError, FindIdentity, IdentityError, PasswordError},
+};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use shine_service::service::APP_NAME;
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum PasswordResetError {
+    #[error("Password reset is not enabled")]
+    ResetDisabled,
+    #[error("Too many password reset requests")]
+    TooManyRequests,
+    #[error("Password reset token is invalid or has expired")]
+    InvalidToken,
+    #[error(transparent)]
+    PasswordRejected(#[from] PasswordRejection),
+    #[error("Failed to generate token: {0}")]
+    TokenGenerator(String),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    PasswordError(#[from] PasswordError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for PasswordResetError {
+    fn into_response(self) -> Response {
+        let status_code = match self {
+            PasswordResetError::PasswordRejected(rejection) => return rejection.into_response(),
+            PasswordResetError::ResetDisabled => StatusCode::NOT_FOUND,
+            PasswordResetError::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            PasswordResetError::InvalidToken => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct RequestPasswordReset {
+    email: String,
+    /// Bot trap fields of the form.
+    website: Option<String>,
+    form_time: Option<i64>,
+}
+
+/// Send a password reset link to the given email. The response is the same whether the email
+/// belongs to a user or not, to prevent the enumeration of the users.
+pub(in crate::auth) async fn ep_request_password_reset(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    headers: HeaderMap,
+    Json(request): Json<RequestPasswordReset>,
+) -> Result<StatusCode, PasswordResetError> {
+    let config = state.password_config();
+    let reset_page_url = config
+        .reset_page_url
+        .as_ref()
+        .ok_or(PasswordResetError::ResetDisabled)?;
+
+    // the bots get the same response as the users, no email is sent
+    let bot_trap = BotTrap {
+        honeypot: request.website.as_deref(),
+        form_time: request.form_time,
+    };
+    if let Some(reason) = state.detect_bot(&headers, &bot_trap) {
+        log::info!("Password reset rejected as a bot ({reason})");
+        return Ok(StatusCode::ACCEPTED);
+    }
+
+    let email = state.identity_manager().normalize_email(&request.email);
+    let rate_key = format!("password-reset:{}:{email}", tenant.id());
+    if !state
+        .rate_limiter()
+        .check(&rate_key, config.reset_rate_limit, config.rate_window())
+        .await?
+    {
+        return Err(PasswordResetError::TooManyRequests);
+    }
+
+    let identity = state
+        .identity_manager()
+        .find(FindIdentity::Email {
+            tenant_id: tenant.id(),
+            email: &request.email,
+        })
+        .await?;
+    let (identity, email) = match identity {
+        Some(identity) => match identity.email.clone() {
+            Some(email) => (identity, email),
+            None => return Ok(StatusCode::ACCEPTED),
+        },
+        None => {
+            log::debug!("Password reset requested for an unknown email");
+            return Ok(StatusCode::ACCEPTED);
+        }
+    };
+
+    let token = state
+        .token()
+        .generate_token()
+        .map_err(|err| PasswordResetError::TokenGenerator(format!("{err}")))?;
+    state
+        .password_manager()
+        .create_reset_token(&token, identity.user_id, config.reset_token_duration())
+        .await?;
+
+    let mut reset_url = reset_page_url.clone();
+    reset_url.query_pairs_mut().append_pair("token", &token);
+
+    let mut context = tera::Context::new();
+    context.insert("app_name", APP_NAME);
+    context.insert("name", &identity.name);
+    context.insert("reset_url", reset_url.as_str());
+    if let Err(err) = state.email_sender().send(&email, "password_reset", &context) {
+        log::error!("Failed to send password reset email to {}: {err}", identity.user_id);
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct ConfirmPasswordReset {
+    token: String,
+    password: String,
+}
+
+/// Set the new password using a reset token. All the sessions and login tokens of the user are revoked.
+pub(in crate::auth) async fn ep_confirm_password_reset(
+    State(state): State<AuthServiceState>,
+    Json(request): Json<ConfirmPasswordReset>,
+) -> Result<StatusCode, PasswordResetError> {
+    state.check_new_password(&request.password, &[]).await?;
+
+    let user_id = state
+        .password_manager()
+        .take_reset_token(&request.token)
+        .await?
+        .ok_or(PasswordResetError::InvalidToken)?;
+
+    state
+        .password_manager()
+        .set_password(user_id, &request.password)
+        .await?;
+
+    state.identity_manager().delete_all_tokens(user_id).await?;
+    state
+        .token_revocation()
+        .revoke_user(user_id, state.token().max_duration())
+        .await?;
+    state.session_manager().remove_all(user_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,63 @@
+use crate::{
+    auth::{ApiUser, AuthServiceState, PasswordRejection},
+    db::PasswordError,
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum SetPasswordError {
+    #[error("Current password is invalid")]
+    InvalidCurrentPassword,
+    #[error(transparent)]
+    PasswordRejected(#[from] PasswordRejection),
+    #[error(transparent)]
+    PasswordError(#[from] PasswordError),
+}
+
+impl IntoResponse for SetPasswordError {
+    fn into_response(self) -> Response {
+        let status_code = match self {
+            SetPasswordError::PasswordRejected(rejection) => return rejection.into_response(),
+            SetPasswordError::InvalidCurrentPassword => StatusCode::FORBIDDEN,
+            SetPasswordError::PasswordError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct SetPassword {
+    /// Required if the user has already a password.
+    current_password: Option<String>,
+    password: String,
+}
+
+/// Set or change the password of the current user.
+pub(in crate::auth) async fn ep_set_password(
+    State(state): State<AuthServiceState>,
+    ApiUser(user): ApiUser,
+    Json(request): Json<SetPassword>,
+) -> Result<StatusCode, SetPasswordError> {
+    let password_manager = state.password_manager();
+
+    if password_manager.has_password(user.user_id).await? {
+        let current_password = request.current_password.as_deref().unwrap_or_default();
+        if !password_manager.verify_password(user.user_id, current_password).await? {
+            return Err(SetPasswordError::InvalidCurrentPassword);
+        }
+    }
+
+    state.check_new_password(&request.password, &[&user.name]).await?;
+
+    password_manager.set_password(user.user_id, &request.password).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
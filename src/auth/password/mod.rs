@@ -0,0 +1,16 @@
+mod pwned_passwords;
+pub use self::pwned_passwords::*;
+mod password_policy;
+pub use self::password_policy::*;
+mod password_check;
+pub(in crate::auth) use self::password_check::*;
+
+mod ep_password_login;
+pub(in crate::auth) use self::ep_password_login::*;
+mod ep_set_password;
+pub(in crate::auth) use self::ep_set_password::*;
+mod ep_password_reset;
+pub(in crate::auth) use self::ep_password_reset::*;
+
+#[cfg(test)]
+mod test_password_flows;
@@ -0,0 +1,45 @@
+use crate::auth::{AuthServiceState, PasswordViolation};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use thiserror::Error as ThisError;
+
+/// A new password was rejected, the response lists the violation codes.
+#[derive(Debug, ThisError, Serialize)]
+#[error("Password rejected: {violations:?}")]
+pub(in crate::auth) struct PasswordRejection {
+    pub violations: Vec<PasswordViolation>,
+}
+
+impl IntoResponse for PasswordRejection {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_REQUEST, Json(self)).into_response()
+    }
+}
+
+impl AuthServiceState {
+    /// Check if a password can be set as a new password.
+    pub(in crate::auth) async fn check_new_password(
+        &self,
+        password: &str,
+        user_inputs: &[&str],
+    ) -> Result<(), PasswordRejection> {
+        let violations = self.password_policy().check(password, user_inputs);
+        if !violations.is_empty() {
+            return Err(PasswordRejection { violations });
+        }
+
+        if let Some(pwned_passwords) = self.pwned_passwords() {
+            if pwned_passwords.is_breached(password).await {
+                return Err(PasswordRejection {
+                    violations: vec![PasswordViolation::Breached],
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
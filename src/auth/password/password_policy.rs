@@ -0,0 +1,271 @@
+use crate::auth::AuthBuildError;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, fs, path::PathBuf};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordPolicyConfig {
+    #[serde(default = "PasswordPolicyConfig::default_min_length")]
+    pub min_length: usize,
+    /// Upper limit of the password length to bound the cost of the hashing.
+    #[serde(default = "PasswordPolicyConfig::default_max_length")]
+    pub max_length: usize,
+    #[serde(default)]
+    pub require_lowercase: bool,
+    #[serde(default)]
+    pub require_uppercase: bool,
+    #[serde(default)]
+    pub require_digit: bool,
+    #[serde(default)]
+    pub require_symbol: bool,
+    /// Passwords that cannot be used, matched case-insensitively against the whole password.
+    #[serde(default)]
+    pub deny_list: Vec<String>,
+    /// Files with additional denied passwords, one password per line.
+    #[serde(default)]
+    pub deny_list_files: Vec<PathBuf>,
+    /// Minimum zxcvbn strength score (0-4) of the password, when not given the strength is not checked.
+    pub min_score: Option<u8>,
+}
+
+impl PasswordPolicyConfig {
+    fn default_min_length() -> usize {
+        8
+    }
+
+    fn default_max_length() -> usize {
+        128
+    }
+}
+
+impl Default for PasswordPolicyConfig {
+    fn default() -> Self {
+        Self {
+            min_length: Self::default_min_length(),
+            max_length: Self::default_max_length(),
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            deny_list: Vec::new(),
+            deny_list_files: Vec::new(),
+            min_score: None,
+        }
+    }
+}
+
+/// The rules violated by a password. The serialized names are used as codes by the clients.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PasswordViolation {
+    TooShort,
+    TooLong,
+    MissingLowercase,
+    MissingUppercase,
+    MissingDigit,
+    MissingSymbol,
+    DenyListed,
+    TooWeak,
+    Breached,
+}
+
+pub(in crate::auth) struct PasswordPolicy {
+    config: PasswordPolicyConfig,
+    deny_list: HashSet<String>,
+}
+
+impl PasswordPolicy {
+    pub fn new(config: &PasswordPolicyConfig) -> Result<Self, AuthBuildError> {
+        let mut deny_list: HashSet<String> = config.deny_list.iter().map(|p| p.to_lowercase()).collect();
+        for path in &config.deny_list_files {
+            let passwords = fs::read_to_string(path)
+                .map_err(|err| AuthBuildError::PasswordPolicy(format!("Failed to load {path:?}: {err}")))?;
+            deny_list.extend(
+                passwords
+                    .lines()
+                    .map(|p| p.trim())
+                    .filter(|p| !p.is_empty())
+                    .map(|p| p.to_lowercase()),
+            );
+        }
+
+        if config.min_score.map(|score| score > 4).unwrap_or(false) {
+            return Err(AuthBuildError::PasswordPolicy(
+                "The minimum score shall be in the 0-4 range".into(),
+            ));
+        }
+
+        Ok(Self {
+            config: config.clone(),
+            deny_list,
+        })
+    }
+
+    /// Collect all the violated rules of the password. The user inputs (name, email, etc.) are penalized
+    /// by the strength estimation.
+    pub fn check(&self, password: &str, user_inputs: &[&str]) -> Vec<PasswordViolation> {
+        let config = &self.config;
+        let mut violations = Vec::new();
+
+        let length = password.chars().count();
+        if length < config.min_length {
+            violations.push(PasswordViolation::TooShort);
+        }
+        if length > config.max_length {
+            // don't waste time on the other checks
+            violations.push(PasswordViolation::TooLong);
+            return violations;
+        }
+
+        if config.require_lowercase && !password.chars().any(char::is_lowercase) {
+            violations.push(PasswordViolation::MissingLowercase);
+        }
+        if config.require_uppercase && !password.chars().any(char::is_uppercase) {
+            violations.push(PasswordViolation::MissingUppercase);
+        }
+        if config.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            violations.push(PasswordViolation::MissingDigit);
+        }
+        if config.require_symbol && !password.chars().any(|c| !c.is_alphanumeric() && !c.is_whitespace()) {
+            violations.push(PasswordViolation::MissingSymbol);
+        }
+
+        if self.deny_list.contains(&password.to_lowercase()) {
+            violations.push(PasswordViolation::DenyListed);
+        }
+
+        if let Some(min_score) = config.min_score {
+            // zxcvbn fails only for empty passwords
+            let score = zxcvbn::zxcvbn(password, user_inputs)
+                .map(|entropy| entropy.score())
+                .unwrap_or(0);
+            if score < min_score {
+                violations.push(PasswordViolation::TooWeak);
+            }
+        }
+
+        violations
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PasswordPolicy, PasswordPolicyConfig, PasswordViolation::*};
+    use std::{env, fs};
+    use uuid::Uuid;
+
+    const STRONG_PASSWORD: &str = "k7#Qz!v9Lp@2mXw";
+
+    fn policy(config: PasswordPolicyConfig) -> PasswordPolicy {
+        PasswordPolicy::new(&config).unwrap()
+    }
+
+    #[test]
+    fn each_violation_is_reported() {
+        let policy = policy(PasswordPolicyConfig {
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+            ..Default::default()
+        });
+
+        assert_eq!(
+            policy.check("abc", &[]),
+            vec![TooShort, MissingUppercase, MissingDigit, MissingSymbol]
+        );
+        assert_eq!(
+            policy.check("ABCDEFGH", &[]),
+            vec![MissingLowercase, MissingDigit, MissingSymbol]
+        );
+        // whitespace is not a symbol
+        assert_eq!(policy.check("Abcdefg1 ", &[]), vec![MissingSymbol]);
+        assert!(policy.check("Abcdefg1!", &[]).is_empty());
+    }
+
+    #[test]
+    fn too_long_skips_the_other_checks() {
+        let policy = policy(PasswordPolicyConfig {
+            max_length: 10,
+            require_digit: true,
+            deny_list: vec!["aaaaaaaaaaa".into()],
+            min_score: Some(4),
+            ..Default::default()
+        });
+
+        assert_eq!(policy.check("aaaaaaaaaaa", &[]), vec![TooLong]);
+        // the length is counted in characters, not in bytes
+        assert!(!policy.check("éééééééééé", &[]).contains(&TooLong));
+    }
+
+    #[test]
+    fn deny_list_is_case_insensitive() {
+        let policy = policy(PasswordPolicyConfig {
+            deny_list: vec!["Password1".into()],
+            ..Default::default()
+        });
+
+        assert_eq!(policy.check("PASSWORD1", &[]), vec![DenyListed]);
+        assert!(policy.check("password12", &[]).is_empty());
+    }
+
+    #[test]
+    fn deny_list_files_are_loaded() {
+        let path = env::temp_dir().join(format!("deny-list-{}.txt", Uuid::new_v4().as_simple()));
+        fs::write(&path, "  hunter2hunter2  \n\nLetMeInNow\n").unwrap();
+        let policy = PasswordPolicy::new(&PasswordPolicyConfig {
+            deny_list: vec!["qwertyuiop".into()],
+            deny_list_files: vec![path.clone()],
+            ..Default::default()
+        });
+        fs::remove_file(&path).unwrap();
+        let policy = policy.unwrap();
+
+        assert_eq!(policy.check("HUNTER2HUNTER2", &[]), vec![DenyListed]);
+        assert_eq!(policy.check("letmeinnow", &[]), vec![DenyListed]);
+        assert_eq!(policy.check("qwertyuiop", &[]), vec![DenyListed]);
+        // the empty lines are skipped
+        assert_eq!(policy.check("", &[]), vec![TooShort]);
+    }
+
+    #[test]
+    fn missing_deny_list_file_is_rejected() {
+        let path = env::temp_dir().join(format!("deny-list-{}.txt", Uuid::new_v4().as_simple()));
+        assert!(PasswordPolicy::new(&PasswordPolicyConfig {
+            deny_list_files: vec![path],
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn min_score_bounds() {
+        assert!(PasswordPolicy::new(&PasswordPolicyConfig {
+            min_score: Some(5),
+            ..Default::default()
+        })
+        .is_err());
+
+        let policy_0 = policy(PasswordPolicyConfig {
+            min_score: Some(0),
+            ..Default::default()
+        });
+        assert!(policy_0.check("aaaaaaaa", &[]).is_empty());
+
+        let policy_4 = policy(PasswordPolicyConfig {
+            min_score: Some(4),
+            ..Default::default()
+        });
+        assert_eq!(policy_4.check("aaaaaaaa", &[]), vec![TooWeak]);
+        assert!(policy_4.check(STRONG_PASSWORD, &[]).is_empty());
+        // the user inputs are penalized
+        assert_eq!(policy_4.check(STRONG_PASSWORD, &[STRONG_PASSWORD]), vec![TooWeak]);
+        // the empty password is scored as the weakest
+        let policy_1 = policy(PasswordPolicyConfig {
+            min_length: 0,
+            min_score: Some(1),
+            ..Default::default()
+        });
+        assert_eq!(policy_1.check("", &[]), vec![TooWeak]);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::auth::AuthBuildError;
+use reqwest::{header, Client};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use shine_service::service::APP_NAME;
+use std::time::Duration;
+use url::Url;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PwnedPasswordsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "PwnedPasswordsConfig::default_api_url")]
+    pub api_url: Url,
+    /// Request timeout in milliseconds.
+    #[serde(default = "PwnedPasswordsConfig::default_timeout")]
+    pub timeout: u64,
+}
+
+impl PwnedPasswordsConfig {
+    fn default_api_url() -> Url {
+        Url::parse("https://api.pwnedpasswords.com/range/").unwrap()
+    }
+
+    fn default_timeout() -> u64 {
+        2000
+    }
+}
+
+impl Default for PwnedPasswordsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            api_url: Self::default_api_url(),
+            timeout: Self::default_timeout(),
+        }
+    }
+}
+
+/// Check passwords against the Have I Been Pwned range API. Only the first 5 characters of the SHA-1 hash
+/// of the password is sent (k-anonymity), the matching is performed locally.
+pub(in crate::auth) struct PwnedPasswords {
+    client: Client,
+    api_url: Url,
+}
+
+impl PwnedPasswords {
+    pub fn new(config: &PwnedPasswordsConfig) -> Result<Self, AuthBuildError> {
+        let client = Client::builder()
+            .timeout(Duration::from_millis(config.timeout))
+            .user_agent(APP_NAME)
+            .build()
+            .map_err(|err| AuthBuildError::PwnedPasswords(format!("{err}")))?;
+
+        Ok(Self {
+            client,
+            api_url: config.api_url.clone(),
+        })
+    }
+
+    /// Check if the password is known to be breached. The check fails open, when the API is not available
+    /// the password is accepted.
+    pub async fn is_breached(&self, password: &str) -> bool {
+        let hash = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, password.as_bytes());
+        let hash = hex::encode_upper(hash.as_ref());
+        let (prefix, suffix) = hash.split_at(5);
+
+        let url = match self.api_url.join(prefix) {
+            Ok(url) => url,
+            Err(err) => {
+                log::warn!("Invalid pwned passwords url: {err}");
+                return false;
+            }
+        };
+
+        let response = self
+            .client
+            .get(url)
+            .header("Add-Padding", "true")
+            .header(header::ACCEPT, "text/plain")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        let body = match response {
+            Ok(response) => response.text().await,
+            Err(err) => Err(err),
+        };
+
+        match body {
+            Ok(body) => body
+                .lines()
+                .filter_map(|line| line.trim().split_once(':'))
+                .any(|(candidate, count)| candidate == suffix && count != "0"),
+            Err(err) => {
+                log::warn!("Pwned passwords check failed, accepting password: {err}");
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use crate::test_support::{TestApp, TestClient};
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+
+const EMAIL: &str = "player@example.com";
+const PASSWORD: &str = "correct horse battery";
+
+fn enable_password(config: &mut Value) {
+    config["auth"]["password"] = json!({
+        "enabled": true,
+        "resetPageUrl": "http://localhost/reset",
+        "loginRateLimit": 5
+    });
+}
+
+#[tokio::test]
+async fn password_login() {
+    let app = TestApp::with_config(enable_password).await;
+    let user_id = app.create_password_user(EMAIL, PASSWORD).await;
+    let mut client = TestClient::new(&app.router);
+
+    log::info!("Login with invalid credentials...");
+    let response = client
+        .post_json(
+            "/api/auth/password/login",
+            &json!({ "email": "unknown@example.com", "password": PASSWORD }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = client
+        .post_json(
+            "/api/auth/password/login",
+            &json!({ "email": EMAIL, "password": "guess" }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert!(response.text().contains("InvalidCredentials"));
+    assert!(client.cookie("sid").is_none());
+
+    log::info!("Login with the password...");
+    let response = client
+        .post_json(
+            "/api/auth/password/login",
+            &json!({ "email": "Player@Example.com", "password": PASSWORD, "rememberMe": true }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    assert!(client.cookie("sid").is_some());
+    assert!(client.cookie("tid").is_some());
+    let user_info = client.get("/api/auth/userinfo").await.json();
+    assert_eq!(user_info["userId"], json!(user_id));
+
+    log::info!("A logged in user has to logout first...");
+    let response = client
+        .post_json(
+            "/api/auth/password/login",
+            &json!({ "email": EMAIL, "password": PASSWORD }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn password_login_attempts_are_limited() {
+    let app = TestApp::with_config(enable_password).await;
+    app.create_password_user(EMAIL, PASSWORD).await;
+    let mut client = TestClient::new(&app.router);
+
+    log::info!("Guess the password...");
+    for _ in 0..5 {
+        let response = client
+            .post_json(
+                "/api/auth/password/login",
+                &json!({ "email": EMAIL, "password": "guess" }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    }
+
+    log::info!("The email is limited even with the valid password...");
+    let response = client
+        .post_json(
+            "/api/auth/password/login",
+            &json!({ "email": EMAIL, "password": PASSWORD }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(client.cookie("sid").is_none());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn password_reset() {
+    let app = TestApp::with_config(enable_password).await;
+    app.create_password_user(EMAIL, PASSWORD).await;
+    let new_password = "staple battery horse correct";
+
+    log::info!("Login with a remembered token...");
+    let mut client = TestClient::new(&app.router);
+    let response = client
+        .post_json(
+            "/api/auth/password/login",
+            &json!({ "email": EMAIL, "password": PASSWORD, "rememberMe": true }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    let token_cookie = client.cookie("tid").expect("Missing login token").to_owned();
+
+    log::info!("Request a password reset...");
+    let mut reset_client = TestClient::new(&app.router);
+    let response = reset_client
+        .post_json("/api/auth/password/reset", &json!({ "email": EMAIL }))
+        .await;
+    assert_eq!(response.status, StatusCode::ACCEPTED);
+    let reset_token = app
+        .mailbox
+        .receive(EMAIL)
+        .await
+        .link_param("token")
+        .expect("Missing reset token");
+
+    log::info!("The new password has to follow the policy...");
+    let response = reset_client
+        .post_json(
+            "/api/auth/password/reset/confirm",
+            &json!({ "token": reset_token, "password": "short" }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.json()["violations"], json!(["tooShort"]));
+
+    log::info!("Set the new password...");
+    let response = reset_client
+        .post_json(
+            "/api/auth/password/reset/confirm",
+            &json!({ "token": reset_token, "password": new_password }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+
+    log::info!("The token can be used only once...");
+    let response = reset_client
+        .post_json(
+            "/api/auth/password/reset/confirm",
+            &json!({ "token": reset_token, "password": "another horse battery" }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(response.text().contains("InvalidToken"));
+
+    log::info!("The sessions and the login tokens are revoked...");
+    let response = client.get("/api/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let mut token_client = TestClient::new(&app.router);
+    token_client.set_cookie("tid", &token_cookie);
+    let response = token_client.get("/auth/silent").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    log::info!("Only the new password is accepted...");
+    let mut client = TestClient::new(&app.router);
+    let response = client
+        .post_json(
+            "/api/auth/password/login",
+            &json!({ "email": EMAIL, "password": PASSWORD }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = client
+        .post_json(
+            "/api/auth/password/login",
+            &json!({ "email": EMAIL, "password": new_password }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+
+    app.cleanup().await;
+}
@@ -0,0 +1,187 @@
+use crate::{
+    auth::{ApiUser, AuthServiceState},
+    db::DBError,
+};
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts, State},
+    http::{request::Parts, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json, RequestPartsExt,
+};
+use serde::Serialize;
+use shine_service::service::CurrentUser;
+use std::{marker::PhantomData, ops::Deref};
+
+/// Structured rejection of the permission checks.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct PermissionError {
+    #[serde(skip)]
+    status: StatusCode,
+    error: &'static str,
+    required_role: Option<String>,
+}
+
+impl PermissionError {
+    fn unauthorized() -> Self {
+        Self {
+            status: StatusCode::UNAUTHORIZED,
+            error: "unauthorized",
+            required_role: None,
+        }
+    }
+
+    fn missing_role(role: &str) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            error: "missingRole",
+            required_role: Some(role.to_owned()),
+        }
+    }
+
+    fn internal() -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            error: "internalError",
+            required_role: None,
+        }
+    }
+}
+
+impl IntoResponse for PermissionError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+/// A role required by the `RequireRole` extractor. The name is resolved from the state, thus roles with
+/// configurable names can also be checked.
+pub(in crate::auth) trait RoleName: Send + Sync {
+    fn name(state: &AuthServiceState) -> &str;
+}
+
+/// The administrators of the service.
+pub(in crate::auth) struct AdminRole;
+
+impl RoleName for AdminRole {
+    fn name(_state: &AuthServiceState) -> &str {
+        "admin"
+    }
+}
+
+/// The compliance officers managing the legal holds.
+pub(in crate::auth) struct ComplianceRole;
+
+impl RoleName for ComplianceRole {
+    fn name(_state: &AuthServiceState) -> &str {
+        "compliance"
+    }
+}
+
+/// The role allowed to create support login links as set in the configuration.
+pub(in crate::auth) struct SupportRole;
+
+impl RoleName for SupportRole {
+    fn name(state: &AuthServiceState) -> &str {
+        state
+            .support_login_config()
+            .map(|config| config.role.as_str())
+            .unwrap_or("support")
+    }
+}
+
+impl AuthServiceState {
+    /// Get the effective roles of the user of a session. The roles of the user are resolved on each call, thus the
+    /// changes take effect without a new login, and the roles granted only to the session are added.
+    pub(in crate::auth) async fn user_roles(&self, user: &CurrentUser) -> Result<Vec<String>, DBError> {
+        let mut roles = self.role_manager().get_roles(user.user_id).await?;
+        for role in self
+            .session_manager()
+            .find_session_roles(user.user_id, user.key)
+            .await?
+        {
+            if !roles.contains(&role) {
+                roles.push(role);
+            }
+        }
+        Ok(roles)
+    }
+}
+
+async fn check_role(state: &AuthServiceState, user: &CurrentUser, role: &str) -> Result<(), PermissionError> {
+    match state.user_roles(user).await {
+        Ok(roles) if roles.iter().any(|r| r == role) => Ok(()),
+        Ok(_) => {
+            log::warn!("User {} is missing the {role} role", user.user_id);
+            Err(PermissionError::missing_role(role))
+        }
+        Err(err) => {
+            log::error!("Failed to check the {role} role of {}: {err}", user.user_id);
+            Err(PermissionError::internal())
+        }
+    }
+}
+
+/// Extract the current user if it has the given role, ex. `RequireRole<AdminRole>`.
+pub(in crate::auth) struct RequireRole<R: RoleName> {
+    pub user: CurrentUser,
+    _role: PhantomData<R>,
+}
+
+impl<R: RoleName> Deref for RequireRole<R> {
+    type Target = CurrentUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.user
+    }
+}
+
+#[async_trait]
+impl<S, R> FromRequestParts<S> for RequireRole<R>
+where
+    S: Send + Sync,
+    AuthServiceState: FromRef<S>,
+    R: RoleName,
+{
+    type Rejection = PermissionError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state = AuthServiceState::from_ref(state);
+        let ApiUser(user) = parts
+            .extract_with_state::<ApiUser, _>(&state)
+            .await
+            .map_err(|_| PermissionError::unauthorized())?;
+        check_role(&state, &user, R::name(&state)).await?;
+
+        Ok(Self {
+            user,
+            _role: PhantomData,
+        })
+    }
+}
+
+impl FromRef<(AuthServiceState, String)> for AuthServiceState {
+    fn from_ref((state, _): &(AuthServiceState, String)) -> Self {
+        state.clone()
+    }
+}
+
+/// Middleware rejecting the requests of the users without the given role. To be used with
+/// `middleware::from_fn_with_state((state, role), require_role)` on routes where the user itself is not needed.
+pub(in crate::auth) async fn require_role<B>(
+    State((state, role)): State<(AuthServiceState, String)>,
+    user: Option<ApiUser>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let user = match user {
+        Some(ApiUser(user)) => user,
+        None => return PermissionError::unauthorized().into_response(),
+    };
+    match check_role(&state, &user, &role).await {
+        Ok(()) => next.run(request).await,
+        Err(err) => err.into_response(),
+    }
+}
@@ -0,0 +1,77 @@
+use crate::auth::Tenant;
+use async_trait::async_trait;
+use axum::{
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+    Extension, RequestPartsExt,
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// The profile of the providers configured at the root of the auth configuration.
+pub(in crate::auth) const DEFAULT_PROVIDER_PROFILE: &str = "default";
+
+/// The clients of a provider for each provider profile.
+pub(in crate::auth) struct ProviderClients<T> {
+    clients: HashMap<String, Arc<T>>,
+}
+
+impl<T> Default for ProviderClients<T> {
+    fn default() -> Self {
+        Self {
+            clients: HashMap::new(),
+        }
+    }
+}
+
+impl<T> ProviderClients<T>
+where
+    T: 'static + Send + Sync,
+{
+    pub fn add(&mut self, profile: &str, client: T) {
+        self.clients.insert(profile.to_owned(), Arc::new(client));
+    }
+
+    pub fn profiles(&self) -> Vec<&str> {
+        self.clients.keys().map(|profile| profile.as_str()).collect()
+    }
+
+    pub fn into_layer(self) -> Extension<Arc<Self>> {
+        Extension(Arc::new(self))
+    }
+}
+
+/// Extract the client of the provider from the profile of the current tenant.
+pub(in crate::auth) struct ProviderClient<T>(pub Arc<T>);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for ProviderClient<T>
+where
+    S: Send + Sync,
+    T: 'static + Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Extension(clients) = parts
+            .extract::<Extension<Arc<ProviderClients<T>>>>()
+            .await
+            .expect("Missing ProviderClients extension");
+        let tenant = parts.extract::<Tenant>().await?;
+
+        clients
+            .clients
+            .get(tenant.provider_profile())
+            .cloned()
+            .map(ProviderClient)
+            .ok_or(StatusCode::NOT_FOUND)
+    }
+}
+
+/// The maximum length of the login hint forwarded to the providers.
+const MAX_LOGIN_HINT_LENGTH: usize = 256;
+
+/// Check if a login hint of the client can be forwarded to the provider. The format is not checked as it differs
+/// by the providers (ex. email, user name, phone number).
+pub(in crate::auth) fn is_valid_login_hint(hint: &str) -> bool {
+    !hint.is_empty() && hint.len() <= MAX_LOGIN_HINT_LENGTH && !hint.chars().any(char::is_control)
+}
@@ -0,0 +1,86 @@
+use crate::auth::{AuthServiceState, ClientInfo};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// The maximum length of a region.
+const MAX_REGION_LENGTH: usize = 32;
+
+/// The policies of a region overriding the defaults of the deployment.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegionPolicy {
+    /// The grace period of the deleted accounts in seconds, it can only shorten the `DeletionConfig::grace_period`.
+    pub deletion_grace_period: Option<u64>,
+    /// The registration requires an email, thus the guest accounts are refused and the external providers have to
+    /// share the email of the user.
+    #[serde(default)]
+    pub require_email: bool,
+}
+
+impl RegionPolicy {
+    pub fn deletion_grace_period(&self) -> Option<Duration> {
+        self.deletion_grace_period
+            .map(|grace_period| Duration::seconds(grace_period as i64))
+    }
+}
+
+/// The regions of the users selecting the regional policies.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegionConfig {
+    /// Header with the region of the client set by the gateway of the services, ex. `X-Region`.
+    pub header: Option<String>,
+    /// The regions of the countries reported by the reverse proxy (see `countryHeader`), ex. `{"DE": "eu"}`.
+    #[serde(default)]
+    pub countries: HashMap<String, String>,
+    /// The region when it is not given by the header or the country.
+    pub default_region: Option<String>,
+    /// The policies by the region, the regions without a policy use the defaults of the deployment.
+    #[serde(default)]
+    pub policies: HashMap<String, RegionPolicy>,
+}
+
+impl RegionConfig {
+    /// Get the region of a client, the regions are lowercase.
+    pub fn resolve(&self, header_region: Option<&str>, country: Option<&str>) -> Option<String> {
+        header_region
+            .filter(|region| region.len() <= MAX_REGION_LENGTH)
+            .map(|region| region.to_lowercase())
+            .or_else(|| country.and_then(|country| self.countries.get(country).cloned()))
+            .or_else(|| self.default_region.clone())
+    }
+
+    pub fn policy(&self, region: Option<&str>) -> Option<&RegionPolicy> {
+        region.and_then(|region| self.policies.get(region))
+    }
+}
+
+impl AuthServiceState {
+    /// Get the policy of the region of a new user.
+    pub(in crate::auth) fn registration_policy(&self, client_info: &ClientInfo) -> Option<&RegionPolicy> {
+        self.region_config()?.policy(client_info.region.as_deref())
+    }
+
+    /// Get the policy of the recorded region of a user.
+    pub(in crate::auth) async fn user_region_policy(&self, user_id: Uuid) -> Option<RegionPolicy> {
+        let config = self.region_config()?;
+        match self.region_manager().find_region(user_id).await {
+            Ok(region) => config.policy(region.as_deref()).cloned(),
+            Err(err) => {
+                log::error!("Failed to get the region of {user_id}: {err}");
+                None
+            }
+        }
+    }
+
+    /// Record the region of a new user, failing to record it does not prevent the registration.
+    pub(in crate::auth) async fn record_region(&self, user_id: Uuid, client_info: &ClientInfo) {
+        if let Some(region) = &client_info.region {
+            if let Err(err) = self.region_manager().set_region(user_id, region).await {
+                log::error!("Failed to record the region of {user_id}: {err}");
+            }
+        }
+    }
+}
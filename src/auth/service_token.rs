@@ -0,0 +1,291 @@
+use crate::auth::AuthBuildError;
+use base64::{
+    engine::general_purpose::{STANDARD as B64, URL_SAFE_NO_PAD as B64URL},
+    Engine,
+};
+use chrono::{DateTime, Duration, Utc};
+use ring::{
+    digest,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519, ED25519_PUBLIC_KEY_LEN},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ServiceTokenFormat {
+    /// Signed JWTs, they can be verified offline.
+    #[default]
+    Jwt,
+    /// Random tokens, their claims are stored in redis and resolved by the introspection.
+    Opaque,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceTokenConfig {
+    /// Ed25519 key in PKCS#8 format, base64 encoded.
+    pub signing_key: String,
+    /// Validity of the issued tokens in seconds.
+    #[serde(default = "ServiceTokenConfig::default_duration")]
+    pub duration: u64,
+    /// The format of the issued access tokens.
+    #[serde(default)]
+    pub format: ServiceTokenFormat,
+}
+
+impl ServiceTokenConfig {
+    fn default_duration() -> u64 {
+        10 * 60
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::seconds(self.duration as i64)
+    }
+}
+
+/// The acting party of a delegated token (RFC 8693 section 4.1), the nested actors are the earlier members of the
+/// delegation chain.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(in crate::auth) struct TokenActor {
+    /// The user id of the acting service.
+    pub sub: Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Box<TokenActor>>,
+}
+
+impl TokenActor {
+    /// Get the services of the delegation chain, starting with the current actor.
+    pub fn chain(&self) -> Vec<Uuid> {
+        let mut chain = vec![self.sub];
+        let mut actor = &self.act;
+        while let Some(next) = actor {
+            chain.push(next.sub);
+            actor = &next.act;
+        }
+        chain
+    }
+}
+
+/// The kind of the subject of a token when it is not a user or a service.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) enum TokenPrincipal {
+    /// The token acts on behalf of a studio, the subject is the id of the studio.
+    Studio,
+}
+
+/// The claims of the tokens issued to the services.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(in crate::auth) struct ServiceTokenClaims {
+    pub iss: String,
+    /// The user id of the service, or of the user on whose behalf the actor is calling.
+    pub sub: Uuid,
+    /// The kind of the subject, not present for the users and the services.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub principal: Option<TokenPrincipal>,
+    /// The name of the service the token is restricted to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    pub tenant: String,
+    /// The granted scopes separated by space.
+    pub scope: String,
+    /// The delegation chain of an exchanged token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<TokenActor>,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: Uuid,
+}
+
+impl ServiceTokenClaims {
+    pub fn scopes(&self) -> Vec<String> {
+        self.scope.split_whitespace().map(str::to_owned).collect()
+    }
+}
+
+#[derive(Serialize)]
+struct JwtHeader<'a> {
+    alg: &'static str,
+    typ: &'static str,
+    kid: &'a str,
+}
+
+/// The public key in the JWK format.
+#[derive(Serialize)]
+pub(in crate::auth) struct Jwk {
+    kty: &'static str,
+    crv: &'static str,
+    alg: &'static str,
+    #[serde(rename = "use")]
+    key_use: &'static str,
+    kid: String,
+    x: String,
+}
+
+/// The audience of a client assertion, either a single value or a list.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AssertionAudience {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// The claims of the assertion a client authenticates with instead of a secret (RFC 7523 section 3).
+#[derive(Deserialize)]
+pub(in crate::auth) struct ClientAssertionClaims {
+    pub iss: String,
+    pub sub: String,
+    aud: AssertionAudience,
+    pub exp: i64,
+    pub jti: String,
+}
+
+impl ClientAssertionClaims {
+    pub fn has_audience(&self, audience: &str) -> bool {
+        match &self.aud {
+            AssertionAudience::One(aud) => aud == audience,
+            AssertionAudience::Many(auds) => auds.iter().any(|aud| aud == audience),
+        }
+    }
+}
+
+/// A JWT signed by the private key of a service client. The claims are available before the verification to find
+/// the key of the client.
+pub(in crate::auth) struct ClientAssertion<'a> {
+    signing_input: &'a str,
+    signature: Vec<u8>,
+    pub claims: ClientAssertionClaims,
+}
+
+impl<'a> ClientAssertion<'a> {
+    pub fn parse(assertion: &'a str) -> Option<Self> {
+        let (signing_input, signature) = assertion.rsplit_once('.')?;
+        let (_, claims) = signing_input.split_once('.')?;
+        Some(Self {
+            signing_input,
+            signature: B64URL.decode(signature).ok()?,
+            claims: serde_json::from_slice(&B64URL.decode(claims).ok()?).ok()?,
+        })
+    }
+
+    /// Verify the signature by the public key of the client. The header is not checked, only Ed25519 keys are
+    /// registered.
+    pub fn verify(&self, public_key: &str) -> bool {
+        match parse_public_key(public_key) {
+            Some(public_key) => UnparsedPublicKey::new(&ED25519, public_key)
+                .verify(self.signing_input.as_bytes(), &self.signature)
+                .is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// Parse an Ed25519 public key given by the base64url encoded raw key, as the `x` parameter of a JWK.
+pub(in crate::auth) fn parse_public_key(public_key: &str) -> Option<Vec<u8>> {
+    B64URL
+        .decode(public_key.trim_end_matches('='))
+        .ok()
+        .filter(|key| key.len() == ED25519_PUBLIC_KEY_LEN)
+}
+
+/// Sign the JWTs issued to the services, they can be verified offline by the public key published as a JWKS.
+pub(in crate::auth) struct ServiceTokenSigner {
+    key_pair: Ed25519KeyPair,
+    key_id: String,
+    issuer: String,
+    duration: Duration,
+    format: ServiceTokenFormat,
+}
+
+impl ServiceTokenSigner {
+    pub fn new(config: &ServiceTokenConfig, issuer: &str) -> Result<Self, AuthBuildError> {
+        let key = B64
+            .decode(&config.signing_key)
+            .map_err(|err| AuthBuildError::ServiceToken(format!("{err}")))?;
+        let key_pair =
+            Ed25519KeyPair::from_pkcs8(&key).map_err(|err| AuthBuildError::ServiceToken(format!("{err}")))?;
+        // the key id is derived from the key, thus a rotated key gets a new id
+        let key_id = hex::encode(&digest::digest(&digest::SHA256, key_pair.public_key().as_ref()).as_ref()[..8]);
+
+        Ok(Self {
+            key_pair,
+            key_id,
+            issuer: issuer.to_owned(),
+            duration: config.duration(),
+            format: config.format,
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn format(&self) -> ServiceTokenFormat {
+        self.format
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    pub fn jwk(&self) -> Jwk {
+        Jwk {
+            kty: "OKP",
+            crv: "Ed25519",
+            alg: "EdDSA",
+            key_use: "sig",
+            kid: self.key_id.clone(),
+            x: B64URL.encode(self.key_pair.public_key().as_ref()),
+        }
+    }
+
+    /// Create the claims of a token issued now for the subject with the given scopes.
+    pub fn new_claims(
+        &self,
+        subject: Uuid,
+        tenant_id: &str,
+        scopes: &[String],
+        now: DateTime<Utc>,
+    ) -> ServiceTokenClaims {
+        ServiceTokenClaims {
+            iss: self.issuer.clone(),
+            sub: subject,
+            principal: None,
+            aud: None,
+            tenant: tenant_id.to_owned(),
+            scope: scopes.join(" "),
+            act: None,
+            iat: now.timestamp(),
+            exp: (now + self.duration).timestamp(),
+            jti: Uuid::new_v4(),
+        }
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> String {
+        let header = JwtHeader {
+            alg: "EdDSA",
+            typ: "JWT",
+            kid: &self.key_id,
+        };
+        let header = B64URL.encode(serde_json::to_vec(&header).expect("Failed to serialize JWT header"));
+        let claims = B64URL.encode(serde_json::to_vec(claims).expect("Failed to serialize JWT claims"));
+        let signing_input = format!("{header}.{claims}");
+        let signature = B64URL.encode(self.key_pair.sign(signing_input.as_bytes()).as_ref());
+        format!("{signing_input}.{signature}")
+    }
+
+    /// Verify a token issued by this service and return its claims, None if the token is invalid or has expired.
+    /// Only the current key is accepted, thus the tokens signed before a key rotation are rejected.
+    pub fn verify(&self, token: &str, now: DateTime<Utc>) -> Option<ServiceTokenClaims> {
+        let (signing_input, signature) = token.rsplit_once('.')?;
+        let (_, claims) = signing_input.split_once('.')?;
+        let signature = B64URL.decode(signature).ok()?;
+        // the header is not checked, only EdDSA tokens are signed by this key
+        UnparsedPublicKey::new(&ED25519, self.key_pair.public_key().as_ref())
+            .verify(signing_input.as_bytes(), &signature)
+            .ok()?;
+        let claims: ServiceTokenClaims = serde_json::from_slice(&B64URL.decode(claims).ok()?).ok()?;
+        (claims.iss == self.issuer && claims.exp > now.timestamp()).then_some(claims)
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{
+    auth::{AuthServiceState, Tenant},
+    db::{DBError, DBSessionError, FindIdentity, IdentityError},
+};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Form, Json,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum SsoTokenError {
+    #[error("Single sign-on is not enabled")]
+    Disabled,
+    #[error("Unsupported grant type")]
+    UnsupportedGrantType,
+    #[error("Client authentication failed")]
+    InvalidClient,
+    #[error("Code is invalid, has expired or it was issued to an other app")]
+    InvalidGrant,
+    #[error("User ({0}) not found")]
+    UserNotFound(Uuid),
+    #[error(transparent)]
+    SessionError(#[from] DBSessionError),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for SsoTokenError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            SsoTokenError::Disabled => StatusCode::NOT_FOUND,
+            SsoTokenError::InvalidClient => StatusCode::UNAUTHORIZED,
+            SsoTokenError::UnsupportedGrantType | SsoTokenError::InvalidGrant => StatusCode::BAD_REQUEST,
+            SsoTokenError::UserNotFound(_) => StatusCode::NOT_FOUND,
+            SsoTokenError::SessionError(DBSessionError::SessionLimitReached) => StatusCode::CONFLICT,
+            SsoTokenError::SessionError(DBSessionError::AccountExpired) => StatusCode::FORBIDDEN,
+            SsoTokenError::SessionError(_) | SsoTokenError::IdentityError(_) | SsoTokenError::DBError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+/// The redemption of a handoff code by the app it was issued to.
+#[derive(Deserialize)]
+pub(in crate::auth) struct SsoTokenRequest {
+    grant_type: String,
+    code: String,
+    /// The id of the app in the `sso` configuration.
+    client_id: String,
+    /// The secret of the app in the `sso` configuration.
+    client_secret: String,
+}
+
+#[derive(Serialize)]
+pub(in crate::auth) struct SsoTokenResponse {
+    /// The local session of the user in the app, it is a session cookie value, that can be used as a bearer token.
+    access_token: String,
+    token_type: &'static str,
+    user_id: Uuid,
+}
+
+/// Redeem a handoff code for a new session of the user. The session is independent of the session of the auth
+/// domain, thus each app has a session of its own and it can be logged out (or revoked) separately.
+pub(in crate::auth) async fn ep_sso_token(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    Form(request): Form<SsoTokenRequest>,
+) -> Result<Response, SsoTokenError> {
+    let sso_login = state.sso_login().ok_or(SsoTokenError::Disabled)?;
+    if request.grant_type != "authorization_code" {
+        return Err(SsoTokenError::UnsupportedGrantType);
+    }
+    if !sso_login.verify_client(&request.client_id, &request.client_secret) {
+        return Err(SsoTokenError::InvalidClient);
+    }
+
+    let handoff = state
+        .sso_handoff_manager()
+        .take(&request.code)
+        .await?
+        .filter(|handoff| handoff.tenant_id == tenant.id() && handoff.app_id == request.client_id)
+        .ok_or(SsoTokenError::InvalidGrant)?;
+
+    let identity = state
+        .identity_manager()
+        .find(FindIdentity::UserId(handoff.user_id))
+        .await?
+        .ok_or(SsoTokenError::UserNotFound(handoff.user_id))?;
+    let user = state.create_session(&identity, None).await?;
+
+    log::info!("SSO session of the app {} created for {}", handoff.app_id, user.user_id);
+    let response = SsoTokenResponse {
+        access_token: tenant.session_meta().create_user_cookie(&user),
+        token_type: "Bearer",
+        user_id: user.user_id,
+    };
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(response)).into_response())
+}
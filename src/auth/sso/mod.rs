@@ -0,0 +1,9 @@
+mod sso_login;
+pub(in crate::auth) use self::sso_login::*;
+mod page_sso_login;
+pub(in crate::auth) use self::page_sso_login::*;
+mod ep_sso_token;
+pub(in crate::auth) use self::ep_sso_token::*;
+
+#[cfg(test)]
+mod test_sso_flows;
@@ -0,0 +1,111 @@
+use crate::{
+    auth::{AuthError, AuthPage, AuthServiceState, AuthSession},
+    db::SsoHandoffCode,
+    logging::user_hash,
+};
+use axum::extract::{Query, RawQuery, State};
+use serde::Deserialize;
+use url::Url;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct RequestParams {
+    app_id: String,
+    /// Opaque value of the app returned with the code.
+    state: Option<String>,
+    /// The provider of the login, if the user has no session yet. Without a provider the app is only told that a
+    /// login is required, thus it can check the login silently.
+    provider: Option<String>,
+}
+
+fn app_redirect(mut target_url: Url, params: &[(&str, &str)], app_state: Option<&str>) -> Url {
+    {
+        let mut query = target_url.query_pairs_mut();
+        query.extend_pairs(params);
+        if let Some(app_state) = app_state {
+            query.append_pair("state", app_state);
+        }
+    }
+    target_url
+}
+
+/// Hand the login of the auth domain over to a first-party app. The app opens this page when it has no local
+/// session, with a session the user is redirected to the app with a one-time code, that can be redeemed for a
+/// local session by the app. Without a session, the login of the provider is started and the user is sent back here
+/// after the login, or if no provider is given, the user is redirected to the app with a `login_required` error.
+pub(in crate::auth) async fn page_sso_login(
+    State(state): State<AuthServiceState>,
+    Query(query): Query<RequestParams>,
+    RawQuery(raw_query): RawQuery,
+    auth_session: AuthSession,
+) -> AuthPage {
+    let sso_login = match state.sso_login() {
+        Some(sso_login) => sso_login,
+        None => return state.page_error(auth_session, AuthError::InvalidAppLogin, None),
+    };
+    let redirect_url = match sso_login.redirect_url(&query.app_id) {
+        Some(redirect_url) => redirect_url.clone(),
+        None => {
+            log::info!("SSO login of an unknown app: {}", query.app_id);
+            return state.page_error(auth_session, AuthError::InvalidAppLogin, None);
+        }
+    };
+    let app_state = query.state.as_deref();
+    let login_required = app_redirect(redirect_url.clone(), &[("error", "login_required")], app_state);
+
+    let user = match auth_session.user.clone() {
+        Some(user) => user,
+        None => {
+            let tenant = auth_session.tenant();
+            let provider = match query.provider.as_deref() {
+                Some(provider) if tenant.is_provider_enabled(provider) => provider,
+                Some(_) => return state.page_error(auth_session, AuthError::ProviderNotAvailable, None),
+                None => return state.page_redirect(auth_session, "the application", Some(&login_required)),
+            };
+            let mut return_url = tenant.page_url(&["sso", "login"]);
+            return_url.set_query(raw_query.as_deref());
+            let mut login_url = tenant.page_url(&[provider, "login"]);
+            login_url
+                .query_pairs_mut()
+                .append_pair("redirectUrl", return_url.as_str());
+            return state.page_redirect(auth_session, provider, Some(&login_url));
+        }
+    };
+
+    match state
+        .session_manager()
+        .find_session(auth_session.tenant().id(), user.user_id, user.key)
+        .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => return state.page_redirect(auth_session, "the application", Some(&login_required)),
+        Err(err) => return state.page_internal_error(auth_session, err, None),
+    }
+
+    let code = match state.token().generate_token() {
+        Ok(code) => code,
+        Err(err) => return state.page_internal_error(auth_session, err, None),
+    };
+    let handoff = SsoHandoffCode {
+        user_id: user.user_id,
+        tenant_id: auth_session.tenant().id().to_owned(),
+        app_id: query.app_id.clone(),
+    };
+    if let Err(err) = state
+        .sso_handoff_manager()
+        .create(&code, &handoff, sso_login.code_duration())
+        .await
+    {
+        return state.page_internal_error(auth_session, err, None);
+    }
+
+    tracing::info!(
+        provider = "sso",
+        user = %user_hash(user.user_id),
+        app = %query.app_id,
+        outcome = "codeIssued",
+        "SSO handoff code issued"
+    );
+    let target_url = app_redirect(redirect_url, &[("code", code.as_str())], app_state);
+    state.page_redirect(auth_session, "the application", Some(&target_url))
+}
@@ -0,0 +1,102 @@
+use crate::auth::AuthBuildError;
+use chrono::Duration;
+use ring::{constant_time, digest};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use url::Url;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SsoAppConfig {
+    /// The page of the app receiving the handoff code, ex. `https://forum.example.com/auth/handoff`.
+    pub redirect_url: String,
+    /// The secret of the app authenticating the redemption of the handoff codes.
+    pub client_secret: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SsoConfig {
+    /// The first-party apps sharing the login of the auth domain by their app id.
+    pub apps: HashMap<String, SsoAppConfig>,
+    /// Validity of the handoff codes in seconds.
+    #[serde(default = "SsoConfig::default_code_duration")]
+    pub code_duration: u64,
+}
+
+impl SsoConfig {
+    fn default_code_duration() -> u64 {
+        30
+    }
+}
+
+/// The single sign-on of the first-party apps on other domains, ex. `play.example.com` and `forum.example.com`. The
+/// login happens on the auth domain and it is handed over to the app by a short-lived, single-use code, that the app
+/// redeems for a local session of its own.
+pub(in crate::auth) struct SsoLogin {
+    apps: HashMap<String, SsoApp>,
+    code_duration: Duration,
+}
+
+struct SsoApp {
+    redirect_url: Url,
+    secret_hash: digest::Digest,
+}
+
+impl SsoLogin {
+    pub fn new(config: &SsoConfig) -> Result<Self, AuthBuildError> {
+        if config.code_duration == 0 {
+            return Err(AuthBuildError::SsoConfig("codeDuration shall be positive".into()));
+        }
+
+        let apps = config
+            .apps
+            .iter()
+            .map(|(app_id, app)| {
+                let url = Url::parse(&app.redirect_url)
+                    .map_err(|err| AuthBuildError::RedirectUrl(format!("sso app {app_id}: {err}")))?;
+                // the code is as good as a session, it shall not travel in plain text
+                if url.scheme() != "https" || url.fragment().is_some() {
+                    return Err(AuthBuildError::RedirectUrl(format!(
+                        "sso app {app_id}: {url} is not an https url without a fragment"
+                    )));
+                }
+                if app.client_secret.is_empty() {
+                    return Err(AuthBuildError::SsoConfig(format!(
+                        "sso app {app_id}: missing clientSecret"
+                    )));
+                }
+                let app = SsoApp {
+                    redirect_url: url,
+                    secret_hash: digest::digest(&digest::SHA256, app.client_secret.as_bytes()),
+                };
+                Ok((app_id.clone(), app))
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+
+        Ok(Self {
+            apps,
+            code_duration: Duration::seconds(config.code_duration as i64),
+        })
+    }
+
+    pub fn code_duration(&self) -> Duration {
+        self.code_duration
+    }
+
+    /// Get the page of an app receiving the handoff codes.
+    pub fn redirect_url(&self, app_id: &str) -> Option<&Url> {
+        self.apps.get(app_id).map(|app| &app.redirect_url)
+    }
+
+    /// Check the credentials of an app redeeming a handoff code.
+    pub fn verify_client(&self, app_id: &str, client_secret: &str) -> bool {
+        match self.apps.get(app_id) {
+            Some(app) => {
+                let secret_hash = digest::digest(&digest::SHA256, client_secret.as_bytes());
+                constant_time::verify_slices_are_equal(app.secret_hash.as_ref(), secret_hash.as_ref()).is_ok()
+            }
+            None => false,
+        }
+    }
+}
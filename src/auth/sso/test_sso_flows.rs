@@ -0,0 +1,79 @@
+use crate::test_support::{TestApp, TestClient, TestResponse};
+use axum::http::{header, StatusCode};
+use serde_json::json;
+use url::Url;
+
+#[tokio::test]
+async fn sso_handoff_login() {
+    let app = TestApp::with_config(|config| {
+        config["auth"]["sso"] = json!({ "apps": { "forum": {
+            "redirectUrl": "https://forum.localhost/auth/handoff",
+            "clientSecret": "forum-secret"
+        } } });
+    })
+    .await;
+    let mut client = TestClient::new(&app.router);
+    let app_query = |response: TestResponse| {
+        let target = Url::parse(&response.redirect_url().expect("Missing redirect to the app")).unwrap();
+        assert_eq!(target.host_str(), Some("forum.localhost"));
+        let query: std::collections::HashMap<_, _> = target.query_pairs().into_owned().collect();
+        assert_eq!(query["state"], "app-state");
+        query
+    };
+
+    log::info!("Unknown apps are rejected...");
+    let response = client.get("/auth/sso/login?appId=shop&state=app-state").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.redirect_url().is_none());
+
+    log::info!("Without a session the app is told to login...");
+    let response = client.get("/auth/sso/login?appId=forum&state=app-state").await;
+    let query = app_query(response);
+    assert_eq!(query["error"], "login_required");
+    assert!(!query.contains_key("code"));
+
+    log::info!("With a session a code is handed over to the app...");
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let user_id = client.get("/api/auth/userinfo").await.json()["userId"].clone();
+    let response = client.get("/auth/sso/login?appId=forum&state=app-state").await;
+    let code = app_query(response)["code"].clone();
+
+    log::info!("The code can be redeemed only by the app it was issued to...");
+    let mut forum = TestClient::new(&app.router);
+    let fields = [
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("client_id", "shop"),
+        ("client_secret", "forum-secret"),
+    ];
+    let response = forum.post_form("/api/auth/sso/token", &fields).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let fields = [
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("client_id", "forum"),
+        ("client_secret", "wrong-secret"),
+    ];
+    let response = forum.post_form("/api/auth/sso/token", &fields).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let fields = [
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("client_id", "forum"),
+        ("client_secret", "forum-secret"),
+    ];
+    let response = forum.post_form("/api/auth/sso/token", &fields).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let access_token = response.json()["access_token"].as_str().unwrap().to_owned();
+    let response = forum.post_form("/api/auth/sso/token", &fields).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    log::info!("The local session of the app is the same user...");
+    forum.set_header(header::AUTHORIZATION, &format!("Bearer {access_token}"));
+    let response = forum.get("/api/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["userId"], user_id);
+
+    app.cleanup().await;
+}
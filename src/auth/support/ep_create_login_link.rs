@@ -0,0 +1,130 @@
+use crate::{
+    auth::{AuthServiceState, RequireRole, SupportRole, Tenant},
+    db::{DBError, FindIdentity, IdentityError, LoginLink},
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error as ThisError;
+use url::Url;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum CreateLoginLinkError {
+    #[error("Support login is not enabled")]
+    Disabled,
+    #[error("User ({0}) not found")]
+    UserNotFound(Uuid),
+    #[error("A reason is required")]
+    MissingReason,
+    #[error("User has no email to confirm")]
+    MissingEmail,
+    #[error("Failed to generate token: {0}")]
+    TokenGenerator(String),
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for CreateLoginLinkError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            CreateLoginLinkError::Disabled => StatusCode::NOT_FOUND,
+            CreateLoginLinkError::UserNotFound(_) => StatusCode::NOT_FOUND,
+            CreateLoginLinkError::MissingReason => StatusCode::BAD_REQUEST,
+            CreateLoginLinkError::MissingEmail => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct CreateLoginLink {
+    user_id: Uuid,
+    /// Why the link was created, ex. the id of the support ticket. It is recorded in the audit log.
+    reason: String,
+    #[serde(default)]
+    confirm_email: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct LoginLinkInfo {
+    url: Url,
+    expire_at: DateTime<Utc>,
+}
+
+/// Create a single-use login link for a user of the tenant. Only users with the configured role can create links.
+pub(in crate::auth) async fn ep_create_login_link(
+    State(state): State<AuthServiceState>,
+    tenant: Tenant,
+    user: RequireRole<SupportRole>,
+    Json(request): Json<CreateLoginLink>,
+) -> Result<Json<LoginLinkInfo>, CreateLoginLinkError> {
+    let config = state.support_login_config().ok_or(CreateLoginLinkError::Disabled)?;
+
+    let reason = request.reason.trim();
+    if reason.is_empty() {
+        return Err(CreateLoginLinkError::MissingReason);
+    }
+
+    let identity = state
+        .identity_manager()
+        .find(FindIdentity::UserId(request.user_id))
+        .await?
+        .filter(|identity| identity.tenant_id == tenant.id())
+        .ok_or(CreateLoginLinkError::UserNotFound(request.user_id))?;
+    if request.confirm_email && identity.email.is_none() {
+        return Err(CreateLoginLinkError::MissingEmail);
+    }
+
+    let token = state
+        .token()
+        .generate_token()
+        .map_err(|err| CreateLoginLinkError::TokenGenerator(format!("{err}")))?;
+    let link = LoginLink {
+        user_id: identity.user_id,
+        issued_by: user.user_id,
+        confirm_email: request.confirm_email,
+    };
+    let expire_at = state.clock().now() + config.link_duration();
+    state
+        .login_link_manager()
+        .create(&token, &link, config.link_duration())
+        .await?;
+
+    state
+        .audit_log()
+        .record(
+            Some(user.user_id),
+            "support_login.create_link",
+            Some(identity.user_id),
+            json!({
+                "tenantId": tenant.id(),
+                "reason": reason,
+                "confirmEmail": request.confirm_email,
+                "expireAt": expire_at,
+            }),
+        )
+        .await?;
+
+    let mut url = tenant.api_url().clone();
+    url.path_segments_mut()
+        .expect("Auth url shall be a base")
+        .pop_if_empty()
+        .push("support")
+        .push("login");
+    url.query_pairs_mut().append_pair("token", &token);
+
+    Ok(Json(LoginLinkInfo { url, expire_at }))
+}
@@ -0,0 +1,94 @@
+use crate::{
+    auth::{AuthServiceState, RequireRole, SupportRole},
+    db::{DBError, FindIdentity, IdentityError, SupportNote},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+const MAX_NOTE_LENGTH: usize = 4096;
+
+#[derive(Debug, ThisError)]
+pub(in crate::auth) enum SupportNoteError {
+    #[error("User ({0}) not found")]
+    UserNotFound(Uuid),
+    #[error("Note is empty or too long")]
+    InvalidNote,
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl IntoResponse for SupportNoteError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            SupportNoteError::UserNotFound(_) => StatusCode::NOT_FOUND,
+            SupportNoteError::InvalidNote => StatusCode::BAD_REQUEST,
+            SupportNoteError::IdentityError(_) | SupportNoteError::DBError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, format!("{self:?}")).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct AddSupportNote {
+    note: String,
+}
+
+/// Attach a note to a user, ex. the context of a support ticket. Requires the support role.
+pub(in crate::auth) async fn ep_add_support_note(
+    State(state): State<AuthServiceState>,
+    support: RequireRole<SupportRole>,
+    Path(user_id): Path<Uuid>,
+    Json(request): Json<AddSupportNote>,
+) -> Result<(StatusCode, Json<SupportNote>), SupportNoteError> {
+    let note = request.note.trim();
+    if note.is_empty() || note.len() > MAX_NOTE_LENGTH {
+        return Err(SupportNoteError::InvalidNote);
+    }
+    if state
+        .identity_manager()
+        .find(FindIdentity::UserId(user_id))
+        .await?
+        .is_none()
+    {
+        return Err(SupportNoteError::UserNotFound(user_id));
+    }
+
+    let note = state
+        .support_note_manager()
+        .add_note(user_id, note, Some(support.user_id))
+        .await?;
+    // the content of the note is not copied into the audit log
+    state
+        .audit_log()
+        .record(
+            Some(support.user_id),
+            "support_note.add",
+            Some(user_id),
+            json!({ "noteId": note.note_id }),
+        )
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(note)))
+}
+
+/// List the support history of a user, the latest note first. Requires the support role.
+pub(in crate::auth) async fn ep_list_support_notes(
+    State(state): State<AuthServiceState>,
+    _support: RequireRole<SupportRole>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<Vec<SupportNote>>, SupportNoteError> {
+    let notes = state.support_note_manager().list_notes(user_id).await?;
+    Ok(Json(notes))
+}
@@ -0,0 +1,9 @@
+mod ep_create_login_link;
+pub(in crate::auth) use self::ep_create_login_link::*;
+mod ep_support_notes;
+pub(in crate::auth) use self::ep_support_notes::*;
+mod page_support_login;
+pub(in crate::auth) use self::page_support_login::*;
+
+#[cfg(test)]
+mod test_support_flows;
@@ -0,0 +1,156 @@
+use crate::{
+    auth::{AuthError, AuthPage, AuthServiceState, AuthSession, ClientInfo},
+    db::{FindIdentity, LoginLink},
+};
+use axum::{
+    extract::{Form, Query, State},
+    http::StatusCode,
+};
+use serde::Deserialize;
+use serde_json::json;
+use shine_service::service::APP_NAME;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct RequestParams {
+    token: String,
+}
+
+/// Login with a support login link. If the link requires the confirmation of the email, a form is rendered
+/// and the login is completed by the `page_support_login_confirm`.
+pub(in crate::auth) async fn page_support_login(
+    State(state): State<AuthServiceState>,
+    Query(query): Query<RequestParams>,
+    client_info: ClientInfo,
+    auth_session: AuthSession,
+) -> AuthPage {
+    if auth_session.user.is_some() || auth_session.token_login.is_some() {
+        return state.page_error(auth_session, AuthError::LogoutRequired, None);
+    }
+
+    let link = match state.login_link_manager().peek(&query.token).await {
+        Ok(Some(link)) => link,
+        Ok(None) => return state.page_error(auth_session, AuthError::InvalidLoginLink, None),
+        Err(err) => return state.page_internal_error(auth_session, err, None),
+    };
+
+    if link.confirm_email {
+        let mut context = tera::Context::new();
+        context.insert("app_name", APP_NAME);
+        context.insert("token", &query.token);
+        let html = state
+            .tera()
+            .render("support_login.html", &context)
+            .expect("Failed to generate support_login.html template");
+        return AuthPage {
+            status: StatusCode::OK,
+            auth_session: Some(auth_session),
+            html,
+        };
+    }
+
+    let link = match state.login_link_manager().take(&query.token).await {
+        Ok(Some(link)) => link,
+        Ok(None) => return state.page_error(auth_session, AuthError::InvalidLoginLink, None),
+        Err(err) => return state.page_internal_error(auth_session, err, None),
+    };
+    state.complete_support_login(auth_session, link, &client_info).await
+}
+
+#[derive(Deserialize)]
+pub(in crate::auth) struct ConfirmParams {
+    token: String,
+    email: String,
+}
+
+/// Complete the login of a support login link with the email of the user. The link is consumed even if the
+/// email is not matching.
+pub(in crate::auth) async fn page_support_login_confirm(
+    State(state): State<AuthServiceState>,
+    client_info: ClientInfo,
+    auth_session: AuthSession,
+    Form(form): Form<ConfirmParams>,
+) -> AuthPage {
+    if auth_session.user.is_some() || auth_session.token_login.is_some() {
+        return state.page_error(auth_session, AuthError::LogoutRequired, None);
+    }
+
+    let link = match state.login_link_manager().take(&form.token).await {
+        Ok(Some(link)) => link,
+        Ok(None) => return state.page_error(auth_session, AuthError::InvalidLoginLink, None),
+        Err(err) => return state.page_internal_error(auth_session, err, None),
+    };
+
+    let identity = match state
+        .identity_manager()
+        .find(FindIdentity::Email {
+            tenant_id: auth_session.tenant().id(),
+            email: form.email.trim(),
+        })
+        .await
+    {
+        Ok(identity) => identity,
+        Err(err) => return state.page_internal_error(auth_session, err, None),
+    };
+    if identity.map(|identity| identity.user_id) != Some(link.user_id) {
+        if let Err(err) = state
+            .audit_log()
+            .record(
+                Some(link.issued_by),
+                "support_login.email_mismatch",
+                Some(link.user_id),
+                json!({ "tenantId": auth_session.tenant().id() }),
+            )
+            .await
+        {
+            log::error!("Failed to record failed support login of {}: {err}", link.user_id);
+        }
+        return state.page_error(auth_session, AuthError::EmailNotMatching, None);
+    }
+
+    state.complete_support_login(auth_session, link, &client_info).await
+}
+
+impl AuthServiceState {
+    async fn complete_support_login(
+        &self,
+        mut auth_session: AuthSession,
+        link: LoginLink,
+        client_info: &ClientInfo,
+    ) -> AuthPage {
+        let identity = match self.identity_manager().find(FindIdentity::UserId(link.user_id)).await {
+            Ok(Some(identity)) => identity,
+            Ok(None) => return self.page_error(auth_session, AuthError::InvalidLoginLink, None),
+            Err(err) => return self.page_internal_error(auth_session, err, None),
+        };
+
+        // the login is not performed if it cannot be audited
+        if let Err(err) = self
+            .audit_log()
+            .record(
+                Some(link.issued_by),
+                "support_login.login",
+                Some(identity.user_id),
+                json!({
+                    "tenantId": auth_session.tenant().id(),
+                    "confirmEmail": link.confirm_email,
+                    "ip": client_info.ip,
+                    "userAgent": client_info.user_agent,
+                }),
+            )
+            .await
+        {
+            return self.page_internal_error(auth_session, err, None);
+        }
+
+        let user = match self.create_session(&identity, None).await {
+            Ok(user) => user,
+            Err(err) => return self.page_session_error(auth_session, err, None),
+        };
+        self.check_login_device(auth_session.tenant(), &identity, &user, client_info)
+            .await;
+
+        auth_session.user = Some(user);
+        self.page_redirect(auth_session, APP_NAME, None)
+    }
+}
@@ -0,0 +1,36 @@
+use crate::test_support::TestApp;
+use axum::http::StatusCode;
+use chrono::Duration;
+use serde_json::json;
+
+#[tokio::test]
+async fn support_notes_require_support_role() {
+    let app = TestApp::new().await;
+    let (mut support, support_id) = app.client_with_role("support").await;
+
+    let (mut player, player_id) = app.registered_client().await;
+    let path = format!("/api/auth/identities/{player_id}/notes");
+
+    log::info!("Add notes as support...");
+    let response = player.post_json(&path, &json!({ "note": "hello" })).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let response = support
+        .post_json(&path, &json!({ "note": "Lost the 2FA device" }))
+        .await;
+    assert_eq!(response.status, StatusCode::CREATED);
+    app.clock.advance(Duration::minutes(1));
+    let response = support.post_json(&path, &json!({ "note": "Identity verified" })).await;
+    assert_eq!(response.status, StatusCode::CREATED);
+
+    log::info!("The latest note is listed first...");
+    let response = player.get(&path).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let response = support.get(&path).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let notes = response.json();
+    assert_eq!(notes.as_array().unwrap().len(), 2);
+    assert_eq!(notes[0]["note"], json!("Identity verified"));
+    assert_eq!(notes[1]["author"], json!(support_id));
+
+    app.cleanup().await;
+}
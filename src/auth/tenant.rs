@@ -0,0 +1,142 @@
+use crate::auth::{AuthBuildError, AuthSessionMeta};
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequestParts, Path},
+    http::{header, request::Parts, StatusCode},
+    Extension, RequestPartsExt,
+};
+use std::{collections::HashMap, sync::Arc};
+use url::Url;
+
+pub(in crate::auth) struct TenantInfo {
+    pub id: String,
+    pub home_url: Url,
+    /// The public url of the auth pages
+    pub api_url: Url,
+    pub provider_profile: String,
+    pub providers: Vec<String>,
+    pub session_meta: AuthSessionMeta,
+}
+
+/// Tenant (realm) of the service with isolated user namespace, cookies and set of providers.
+#[derive(Clone)]
+pub(in crate::auth) struct Tenant(Arc<TenantInfo>);
+
+impl Tenant {
+    pub fn new(info: TenantInfo) -> Self {
+        Self(Arc::new(info))
+    }
+
+    pub fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    pub fn home_url(&self) -> &Url {
+        &self.0.home_url
+    }
+
+    pub fn api_url(&self) -> &Url {
+        &self.0.api_url
+    }
+
+    /// Get the url of an auth page of the tenant.
+    pub fn page_url(&self, path: &[&str]) -> Url {
+        let mut url = self.0.api_url.clone();
+        url.path_segments_mut()
+            .expect("Auth url shall be a base")
+            .pop_if_empty()
+            .extend(path);
+        url
+    }
+
+    pub fn session_meta(&self) -> &AuthSessionMeta {
+        &self.0.session_meta
+    }
+
+    pub fn provider_profile(&self) -> &str {
+        &self.0.provider_profile
+    }
+
+    pub fn providers(&self) -> &[String] {
+        &self.0.providers
+    }
+
+    pub fn is_provider_enabled(&self, provider: &str) -> bool {
+        self.0.providers.iter().any(|p| p == provider)
+    }
+}
+
+/// Find the tenant of a request. Tenant is selected by the `/t/{tenant}` path prefix, if it is not present,
+/// by the host header. When none of them is matching, the default tenant is used.
+pub(in crate::auth) struct TenantResolver {
+    default: Tenant,
+    by_id: HashMap<String, Tenant>,
+    by_host: HashMap<String, Tenant>,
+}
+
+impl TenantResolver {
+    pub fn new(default: Tenant) -> Self {
+        Self {
+            by_id: HashMap::from([(default.id().to_owned(), default.clone())]),
+            by_host: HashMap::new(),
+            default,
+        }
+    }
+
+    pub fn add(&mut self, tenant: Tenant, hosts: &[String]) -> Result<(), AuthBuildError> {
+        if self.by_id.insert(tenant.id().to_owned(), tenant.clone()).is_some() {
+            return Err(AuthBuildError::TenantConflict(tenant.id().to_owned()));
+        }
+        for host in hosts {
+            if self.by_host.insert(host.clone(), tenant.clone()).is_some() {
+                return Err(AuthBuildError::TenantConflict(host.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve(&self, tenant_id: Option<&str>, host: Option<&str>) -> Option<Tenant> {
+        match tenant_id {
+            Some(tenant_id) => self.by_id.get(tenant_id).cloned(),
+            None => Some(
+                host.and_then(|host| self.by_host.get(host))
+                    .unwrap_or(&self.default)
+                    .clone(),
+            ),
+        }
+    }
+
+    pub fn into_layer(self) -> Extension<Arc<Self>> {
+        Extension(Arc::new(self))
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Tenant
+where
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Extension(resolver) = parts
+            .extract::<Extension<Arc<TenantResolver>>>()
+            .await
+            .expect("Missing TenantResolver extension");
+
+        let tenant_id = parts
+            .extract::<Path<HashMap<String, String>>>()
+            .await
+            .ok()
+            .and_then(|Path(mut params)| params.remove("tenant"));
+        let host = parts
+            .headers
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok())
+            .map(|host| host.split(':').next().unwrap_or(host));
+
+        resolver
+            .resolve(tenant_id.as_deref(), host)
+            .ok_or(StatusCode::NOT_FOUND)
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{
+    db::{Clock, RoleManager},
+    test_support::{TestApp, TestClient},
+};
+use axum::http::{header, HeaderName, StatusCode};
+use chrono::Duration;
+use serde_json::json;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn age_gate_flags_minors() {
+    let app = TestApp::with_config(|config| {
+        config["auth"]["ageGate"] = json!({ "collect": "birthDate", "required": true });
+    })
+    .await;
+    let mut admin = TestClient::new(&app.router);
+    admin.get("/auth/token/login?register=true&birthDate=1990-01-01").await;
+    let admin_info = admin.get("/api/auth/userinfo").await.json();
+    assert_eq!(admin_info["isMinor"], json!(false));
+    let admin_id: Uuid = serde_json::from_value(admin_info["userId"].clone()).unwrap();
+    RoleManager::new(&app.db_pool)
+        .await
+        .unwrap()
+        .grant_role(admin_id, "admin")
+        .await
+        .unwrap();
+
+    log::info!("Registration without an age...");
+    let mut player = TestClient::new(&app.router);
+    player.get("/auth/token/login?register=true").await;
+    assert!(player.cookie("sid").is_none());
+
+    log::info!("Registration of a minor...");
+    let birth_date = (app.clock.now() - Duration::days(365 * 10)).date_naive();
+    player
+        .get(&format!("/auth/token/login?register=true&birthDate={birth_date}"))
+        .await;
+    let player_info = player.get("/api/auth/userinfo").await.json();
+    assert_eq!(player_info["isMinor"], json!(true));
+    let response = player
+        .put_json("/api/auth/user/age", &json!({ "birthDate": "1990-01-01" }))
+        .await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+
+    log::info!("Verify the adult age...");
+    let path = format!(
+        "/api/auth/identities/{}/age/verify-adult",
+        player_info["userId"].as_str().unwrap()
+    );
+    let response = player.post_json(&path, &json!({})).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let response = admin.post_json(&path, &json!({})).await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    let player_info = player.get("/api/auth/userinfo").await.json();
+    assert_eq!(player_info["isMinor"], json!(false));
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn minor_account_requires_parental_consent() {
+    let app = TestApp::with_config(|config| {
+        config["auth"]["ageGate"] = json!({
+            "collect": "ageBracket",
+            "parentalConsent": { "consentPageUrl": "http://localhost/parental-consent" }
+        });
+    })
+    .await;
+    let mut player = TestClient::new(&app.router);
+    player
+        .get("/auth/token/login?register=true&ageBracket=child&parentEmail=parent@example.com")
+        .await;
+    let player_info = player.get("/api/auth/userinfo").await.json();
+    assert_eq!(player_info["restrictions"], json!(["pending-consent"]));
+    let consent = player.get("/api/auth/user/parental-consent").await.json();
+    assert_eq!(consent["parentEmail"], json!("parent@example.com"));
+    assert_eq!(consent["grantedAt"], json!(null));
+
+    log::info!("Request the consent again...");
+    let response = player
+        .post_json(
+            "/api/auth/user/parental-consent",
+            &json!({ "parentEmail": "not an email" }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    let response = player
+        .post_json(
+            "/api/auth/user/parental-consent",
+            &json!({ "parentEmail": "guardian@example.com" }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::ACCEPTED);
+
+    log::info!("Grant with an invalid token...");
+    let response = player
+        .post_json("/api/auth/parental-consent/grant", &json!({ "token": "invalid" }))
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    let player_info = player.get("/api/auth/userinfo").await.json();
+    assert_eq!(player_info["restrictions"], json!(["pending-consent"]));
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn region_policy_is_applied_at_registration() {
+    let app = TestApp::with_config(|config| {
+        config["auth"]["region"] = json!({
+            "header": "x-region",
+            "defaultRegion": "global",
+            "policies": { "eu": { "requireEmail": true } }
+        });
+    })
+    .await;
+
+    log::info!("Guest registration in a region requiring an email...");
+    let mut client = TestClient::new(&app.router);
+    client.set_header(HeaderName::from_static("x-region"), "EU");
+    client.get("/auth/token/login?register=true").await;
+    assert!(client.cookie("sid").is_none());
+
+    log::info!("Guest registration in the default region...");
+    let mut client = TestClient::new(&app.router);
+    client.get("/auth/token/login?register=true").await;
+    assert!(client.cookie("sid").is_some());
+    let user_info = client.get("/api/auth/userinfo").await.json();
+    assert_eq!(user_info["region"], json!("global"));
+
+    app.cleanup().await;
+}
@@ -0,0 +1,581 @@
+use crate::{
+    db::{
+        Clock, EmailNormalizationConfig, FindIdentity, IdentityError, IdentityManager, IdentityStore,
+        RandomIdGenerator, RoleManager, DEFAULT_TENANT_ID,
+    },
+    test_support::{TestApp, TestClient},
+};
+use axum::http::{header, StatusCode};
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
+use chrono::Duration;
+use ring::{rand::SystemRandom, signature::Ed25519KeyPair};
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn register_login_and_delete() {
+    let app = TestApp::new().await;
+    let mut client = TestClient::new(&app.router);
+
+    log::info!("Register a new user with a login token...");
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(client.cookie("tid").is_some());
+    assert!(client.cookie("sid").is_some());
+
+    let response = client.get("/api/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.headers.contains_key(header::ETAG));
+    let user_id: Uuid = serde_json::from_value(response.json()["userId"].clone()).unwrap();
+    assert!(app.identity_exists(user_id).await);
+
+    log::info!("Validate the session as a downstream service...");
+    let session_cookie = client.cookie("sid").unwrap().to_owned();
+    let response = client
+        .post_json("/api/auth/validate", &json!({ "sessionCookie": session_cookie }))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["userId"], json!(user_id));
+
+    log::info!("Get the user info with the session as a bearer token...");
+    let mut native_client = TestClient::new(&app.router);
+    native_client.set_header(header::AUTHORIZATION, &format!("Bearer {session_cookie}"));
+    let response = native_client.get("/api/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["userId"], json!(user_id));
+
+    log::info!("Logout from the session, the token is kept...");
+    client.get("/auth/logout?scope=session").await;
+    assert!(client.cookie("sid").is_none());
+    assert!(client.cookie("tid").is_some());
+    let response = client.get("/api/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = native_client.get("/api/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    log::info!("Login with the token...");
+    let response = client.get("/auth/token/login").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = client.get("/api/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["userId"], json!(user_id));
+
+    log::info!("Delete the user...");
+    let response = client.get("/auth/delete").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(app.identity_exists(user_id).await);
+    let csrf = response.form_value("csrf").expect("Missing CSRF token");
+    let response = client.post_form("/auth/delete", &[("csrf", "invalid")]).await;
+    assert!(response.text().contains("Invalid CSRF state"));
+    assert!(app.identity_exists(user_id).await);
+    let response = client.post_form("/auth/delete", &[("csrf", &csrf)]).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(client.cookie("sid").is_none());
+    assert!(client.cookie("tid").is_none());
+    assert!(!app.identity_exists(user_id).await);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn silent_session_refresh() {
+    let app = TestApp::new().await;
+    let mut client = TestClient::new(&app.router);
+
+    log::info!("Refresh without any cookie...");
+    let response = client.get("/auth/silent").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert!(!response.headers.contains_key(header::LOCATION));
+
+    log::info!("Refresh an active session...");
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = client.get("/auth/silent").await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    let user_id: Uuid =
+        serde_json::from_value(client.get("/api/auth/userinfo").await.json()["userId"].clone()).unwrap();
+
+    log::info!("Refresh with only the login token...");
+    let token = client.cookie("tid").unwrap().to_owned();
+    let mut token_client = TestClient::new(&app.router);
+    token_client.set_cookie("tid", &token);
+    let response = token_client.get("/auth/silent").await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    assert!(token_client.cookie("sid").is_some());
+    let response = token_client.get("/api/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["userId"], json!(user_id));
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn user_name_is_validated() {
+    let app = TestApp::new().await;
+    let mut client = TestClient::new(&app.router);
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    log::info!("Empty and too long names are rejected...");
+    // the ligature is expanded to 3 characters by the normalization
+    let long_names = ["x".repeat(65), "\u{FB03}".repeat(30)];
+    for name in ["", "   ", long_names[0].as_str(), long_names[1].as_str()] {
+        let response = client.put_json("/api/auth/user/name", &json!({ "name": name })).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    log::info!("A valid name is stored trimmed...");
+    let name = "y".repeat(64);
+    let response = client
+        .put_json("/api/auth/user/name", &json!({ "name": format!(" {name} ") }))
+        .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn sessions_are_bound_to_the_tenant() {
+    let app = TestApp::with_config(|config| {
+        // the tenants share the cookie secrets and names
+        config["auth"]["tenants"] = json!({
+            "other": {
+                "homeUrl": "http://localhost/",
+                "apiUrl": "http://localhost/identity/auth",
+                "hosts": []
+            }
+        });
+    })
+    .await;
+    let mut client = TestClient::new(&app.router);
+
+    log::info!("Register a new user in the default tenant...");
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let session_cookie = client.cookie("sid").unwrap().to_owned();
+    let token = client.cookie("tid").unwrap().to_owned();
+    let response = client.get("/api/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    log::info!("The session is not accepted by the other tenant...");
+    let response = client.get("/api/t/other/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let mut native_client = TestClient::new(&app.router);
+    native_client.set_header(header::AUTHORIZATION, &format!("Bearer {session_cookie}"));
+    let response = native_client.get("/api/t/other/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = client
+        .post_json(
+            "/api/t/other/auth/validate",
+            &json!({ "sessionCookie": session_cookie }),
+        )
+        .await;
+    assert_ne!(response.status, StatusCode::OK);
+
+    log::info!("The login token is not accepted by the other tenant...");
+    let mut token_client = TestClient::new(&app.router);
+    token_client.set_cookie("tid", &token);
+    let response = token_client.get("/t/other/auth/silent").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert!(token_client.cookie("sid").is_none());
+    let mut token_client = TestClient::new(&app.router);
+    token_client.set_cookie("tid", &token);
+    token_client.get("/t/other/auth/token/login").await;
+    assert!(token_client.cookie("sid").is_none());
+    assert!(token_client.cookie("tid").is_none());
+    let response = token_client.get("/api/t/other/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn email_is_kept_as_entered() {
+    let app = TestApp::new().await;
+    let identities = IdentityManager::new(
+        &app.db_pool,
+        &EmailNormalizationConfig {
+            ignore_plus: true,
+            ..Default::default()
+        },
+        &B64.encode([0; 32]),
+        app.clock.clone(),
+        Arc::new(RandomIdGenerator),
+    )
+    .await
+    .unwrap();
+
+    log::info!("Create a user with a sub-address...");
+    let user_id = identities.new_user_id();
+    let identity = identities
+        .create_user(
+            DEFAULT_TENANT_ID,
+            user_id,
+            "Email Tester",
+            Some("John.Doe+games@Example.com"),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(identity.email.as_deref(), Some("John.Doe+games@Example.com"));
+
+    log::info!("Find the user by another form of the address...");
+    let found = identities
+        .find(FindIdentity::Email {
+            tenant_id: DEFAULT_TENANT_ID,
+            email: "john.doe@example.com",
+        })
+        .await
+        .unwrap()
+        .expect("Missing identity");
+    assert_eq!(found.user_id, user_id);
+    assert_eq!(found.email.as_deref(), Some("John.Doe+games@Example.com"));
+
+    log::info!("The same mailbox can't be registered again...");
+    let result = identities
+        .create_user(
+            DEFAULT_TENANT_ID,
+            identities.new_user_id(),
+            "Email Tester 2",
+            Some("JOHN.DOE@example.com"),
+            None,
+        )
+        .await;
+    assert!(matches!(result, Err(IdentityError::LinkEmailConflict)));
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn download_security_events() {
+    let app = TestApp::new().await;
+    let mut client = TestClient::new(&app.router);
+
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let response = client.get("/api/auth/user/audit").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let events = response.json();
+    let actions: Vec<_> = events
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| event["action"].as_str().unwrap().to_owned())
+        .collect();
+    assert!(actions.iter().any(|action| action == "login"));
+    assert!(actions.iter().any(|action| action == "token.create"));
+
+    let response = client.get("/api/auth/user/audit?format=csv").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.text().starts_with("time,action,details\r\n"));
+
+    log::info!("Anonymous users have no events...");
+    client.get("/auth/logout").await;
+    let response = client.get("/api/auth/user/audit").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn update_consents() {
+    let app = TestApp::new().await;
+    let mut client = TestClient::new(&app.router);
+
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = client.get("/api/auth/userinfo").await;
+    let user_id = response.json()["userId"].clone();
+
+    let response = client.get("/api/auth/user/consents").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response
+        .json()
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|c| c["granted"] == json!(false)));
+
+    log::info!("Consent to the analytics...");
+    let response = client
+        .put_json("/api/auth/user/consents/analytics", &json!({ "granted": true }))
+        .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    let response = client
+        .post_json(
+            "/api/auth/consents/check",
+            &json!({ "userId": user_id, "purpose": "analytics" }),
+        )
+        .await;
+    assert_eq!(response.json()["granted"], json!(true));
+    let response = client
+        .post_json(
+            "/api/auth/consents/check",
+            &json!({ "userId": user_id, "purpose": "marketingEmail" }),
+        )
+        .await;
+    assert_eq!(response.json()["granted"], json!(false));
+
+    let response = client
+        .put_json("/api/auth/user/consents/unknown", &json!({ "granted": true }))
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn undiscovered_provider_is_hidden() {
+    let app = TestApp::with_config(|config| {
+        config["auth"]["openid"]["offline"] = json!({
+            "discoveryUrl": "http://127.0.0.1:1",
+            "clientId": "test-client",
+            "clientSecret": "test-secret",
+            "scopes": ["openid"]
+        });
+    })
+    .await;
+    let mut client = TestClient::new(&app.router);
+
+    log::info!("The service starts without the discovery of the provider...");
+    let response = client.get("/api/auth/providers").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let providers = response.json();
+    assert!(!providers
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|provider| provider["name"] == json!("offline")));
+
+    let response = client.get("/auth/offline/login").await;
+    assert!(response.redirect_url().is_none());
+    assert!(response.text().contains("Provider is not available"));
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn guest_registration_can_be_disabled() {
+    let app = TestApp::with_config(|config| {
+        config["featureFlags"] = json!({ "enableGuestLogin": false });
+    })
+    .await;
+
+    log::info!("Guest registration is refused...");
+    let mut client = TestClient::new(&app.router);
+    client.get("/auth/token/login?register=true").await;
+    assert!(client.cookie("sid").is_none());
+
+    log::info!("Flags are listed for the admins only...");
+    let response = client.get("/api/auth/feature-flags").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn expired_token_is_rejected() {
+    let app = TestApp::new().await;
+    let mut client = TestClient::new(&app.router);
+
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+    client.get("/auth/logout?scope=session").await;
+    assert!(client.cookie("tid").is_some());
+
+    log::info!("Login after the token has expired...");
+    app.clock.advance(Duration::seconds(86400 + 1));
+    let response = client.get("/auth/token/login").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(client.cookie("sid").is_none());
+    assert!(client.cookie("tid").is_none());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn expired_account_login_is_refused() {
+    let app = TestApp::new().await;
+    let (mut admin, _) = app.client_with_role("admin").await;
+
+    let (mut player, player_id) = app.registered_client().await;
+    let path = format!("/api/auth/identities/{player_id}/expiration");
+
+    log::info!("Limit the account to an hour...");
+    let expire_at = app.clock.now() + Duration::hours(1);
+    let response = admin.put_json(&path, &json!({ "expireAt": expire_at })).await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    player.get("/auth/logout?scope=session").await;
+
+    log::info!("Login after the expiration...");
+    app.clock.advance(Duration::hours(2));
+    let response = player.get("/auth/token/login").await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert!(response.text().contains("Account expired"));
+    assert!(player.cookie("sid").is_none());
+
+    log::info!("Login after the extension...");
+    let expire_at = app.clock.now() + Duration::days(7);
+    let response = admin.put_json(&path, &json!({ "expireAt": expire_at })).await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    let response = player.get("/auth/token/login").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(player.cookie("sid").is_some());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn connection_ticket_single_use() {
+    let signing_key = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let app = TestApp::with_config(|config| {
+        config["auth"]["connectionTicket"] = json!({ "signingKey": B64.encode(signing_key.as_ref()) });
+    })
+    .await;
+    let mut client = TestClient::new(&app.router);
+    let response = client.post_json("/api/auth/ticket", &json!({})).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let user_id = client.get("/api/auth/userinfo").await.json()["userId"].clone();
+
+    log::info!("Redeem a ticket as a realtime server...");
+    let response = client.post_json("/api/auth/ticket", &json!({})).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let ticket = response.json()["ticket"].clone();
+    let response = client
+        .post_json("/api/auth/ticket/redeem", &json!({ "ticket": ticket }))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["userId"], user_id);
+
+    log::info!("The ticket can be redeemed only once...");
+    let response = client
+        .post_json("/api/auth/ticket/redeem", &json!({ "ticket": ticket }))
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    log::info!("Expired tickets are rejected...");
+    let ticket = client.post_json("/api/auth/ticket", &json!({})).await.json()["ticket"].clone();
+    app.clock.advance(Duration::seconds(31));
+    let response = client
+        .post_json("/api/auth/ticket/redeem", &json!({ "ticket": ticket }))
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    app.cleanup().await;
+}
+
+#[cfg(feature = "mock-provider")]
+#[tokio::test]
+async fn link_external_provider() {
+    use crate::mock_provider::{MockProvider, MockProviderConfig, MockUser};
+    use reqwest::redirect::Policy;
+
+    let mock_provider = MockProvider::start(&MockProviderConfig {
+        port: 0,
+        client_id: "test-client".into(),
+        client_secret: "test-secret".into(),
+        users: vec![MockUser {
+            id: "mock-user".into(),
+            name: Some("Mock User".into()),
+            email: Some("mock.user@example.com".into()),
+        }],
+    })
+    .await
+    .unwrap();
+    let issuer = mock_provider.issuer().to_owned();
+
+    let app = TestApp::with_config(|config| {
+        config["auth"]["openid"]["mock"] = json!({
+            "discoveryUrl": issuer,
+            "clientId": "test-client",
+            "clientSecret": "test-secret",
+            "scopes": ["openid", "email", "profile"]
+        });
+    })
+    .await;
+    let mut client = TestClient::new(&app.router);
+
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    log::info!("Link the mock provider...");
+    let response = client.get("/auth/mock/link").await;
+    let authorize_url = response.redirect_url().expect("Missing redirect to the provider");
+    let provider_response = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .unwrap()
+        .get(&authorize_url)
+        .send()
+        .await
+        .unwrap();
+    let callback_url = provider_response
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .expect("Missing redirect to the callback")
+        .to_owned();
+    let response = client.follow(&callback_url).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let response = client.get("/auth/links").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.text().contains("mock"));
+
+    app.cleanup().await;
+}
+
+#[cfg(feature = "fault-injection")]
+#[tokio::test]
+async fn conflicting_inserts_are_rolled_back() {
+    let app = TestApp::with_config(|config| {
+        config["db"]["faultInjection"] = json!({
+            "postgres": { "constraintErrorRate": 1.0 }
+        });
+    })
+    .await;
+    let mut client = TestClient::new(&app.router);
+
+    log::info!("Register while every insert conflicts...");
+    client.get("/auth/token/login?register=true").await;
+    assert!(client.cookie("sid").is_none());
+    assert!(client.cookie("tid").is_none());
+
+    assert_eq!(app.identity_count().await, 0);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn role_change_takes_effect_in_active_session() {
+    let app = TestApp::new().await;
+    let mut client = TestClient::new(&app.router);
+    client.get("/auth/token/login?register=true").await;
+    let user_info = client.get("/api/auth/userinfo").await.json();
+    assert_eq!(user_info["roles"], json!([]));
+    let user_id: Uuid = serde_json::from_value(user_info["userId"].clone()).unwrap();
+    let path = format!("/api/auth/identities/{user_id}/abuse-flags");
+    let response = client.get(&path).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+    log::info!("Grant a role to the logged in user...");
+    RoleManager::new(&app.db_pool)
+        .await
+        .unwrap()
+        .grant_role(user_id, "admin")
+        .await
+        .unwrap();
+
+    log::info!("The role is effective without a new login...");
+    let user_info = client.get("/api/auth/userinfo").await.json();
+    assert_eq!(user_info["roles"], json!(["admin"]));
+    let response = client.get(&path).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    app.cleanup().await;
+}
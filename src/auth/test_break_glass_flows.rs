@@ -0,0 +1,125 @@
+use crate::{
+    db::{EmailNormalizationConfig, IdentityManager, IdentityStore, RandomIdGenerator, DEFAULT_TENANT_ID},
+    test_support::{TestApp, TestClient},
+};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+    Argon2,
+};
+use axum::http::{header::HeaderName, StatusCode};
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Create the application with the emergency access of a new user and return the credential.
+async fn break_glass_app<F>(update: F) -> (TestApp, String)
+where
+    F: FnOnce(&mut serde_json::Value),
+{
+    let credential = Uuid::new_v4().to_string();
+    let salt = SaltString::generate(&mut OsRng);
+    let credential_hash = Argon2::default()
+        .hash_password(credential.as_bytes(), &salt)
+        .unwrap()
+        .to_string();
+    let user_id = Uuid::new_v4();
+
+    let app = TestApp::with_config(|config| {
+        config["auth"]["breakGlass"] = json!({ "credentialHash": credential_hash, "userId": user_id });
+        update(config);
+    })
+    .await;
+
+    let identities = IdentityManager::new(
+        &app.db_pool,
+        &EmailNormalizationConfig::default(),
+        &B64.encode([0; 32]),
+        app.clock.clone(),
+        Arc::new(RandomIdGenerator),
+    )
+    .await
+    .unwrap();
+    identities
+        .create_user(DEFAULT_TENANT_ID, user_id, "Emergency", None, None)
+        .await
+        .unwrap();
+
+    (app, credential)
+}
+
+#[tokio::test]
+async fn break_glass_credential_is_single_use() {
+    let (app, credential) = break_glass_app(|_| {}).await;
+    let mut client = TestClient::new(&app.router);
+
+    log::info!("Login with the credential...");
+    let response = client
+        .post_json("/api/auth/break-glass", &json!({ "credential": credential }))
+        .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    let user_info = client.get("/api/auth/userinfo").await.json();
+    assert_eq!(user_info["roles"], json!(["admin"]));
+
+    log::info!("A logged in user has to logout first...");
+    let response = client
+        .post_json("/api/auth/break-glass", &json!({ "credential": credential }))
+        .await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+    client.get("/auth/logout?scope=session").await;
+
+    log::info!("The credential cannot be used again...");
+    let response = client
+        .post_json("/api/auth/break-glass", &json!({ "credential": credential }))
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert!(response.text().contains("CredentialUsed"));
+    assert!(client.cookie("sid").is_none());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn break_glass_attempts_are_limited() {
+    let (app, credential) = break_glass_app(|_| {}).await;
+    let mut client = TestClient::new(&app.router);
+
+    log::info!("Guess the credential...");
+    for _ in 0..5 {
+        let response = client
+            .post_json("/api/auth/break-glass", &json!({ "credential": "guess" }))
+            .await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+        assert!(response.text().contains("InvalidCredential"));
+    }
+
+    log::info!("The client is blocked even with the valid credential...");
+    let response = client
+        .post_json("/api/auth/break-glass", &json!({ "credential": credential }))
+        .await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(client.cookie("sid").is_none());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn break_glass_requires_client_address() {
+    let (app, credential) = break_glass_app(|config| {
+        config["auth"]["clientIpHeader"] = json!("X-Forwarded-For");
+        config["adminAllowlist"]["trustedProxies"] = json!(["127.0.0.0/8"]);
+    })
+    .await;
+    let mut client = TestClient::new(&app.router);
+
+    log::info!("Login with a malformed address from the proxy...");
+    client.set_header(HeaderName::from_static("x-forwarded-for"), "unknown");
+    let response = client
+        .post_json("/api/auth/break-glass", &json!({ "credential": credential }))
+        .await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert!(response.text().contains("UnknownClient"));
+    assert!(client.cookie("sid").is_none());
+
+    app.cleanup().await;
+}
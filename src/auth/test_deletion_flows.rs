@@ -0,0 +1,84 @@
+use crate::test_support::{TestApp, TestClient};
+use axum::http::StatusCode;
+use chrono::Duration;
+use serde_json::json;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn deletion_grace_period() {
+    let app = TestApp::with_config(|config| {
+        config["deletion"]["gracePeriod"] = json!(3600);
+    })
+    .await;
+    let mut client = TestClient::new(&app.router);
+
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = client.get("/api/auth/userinfo").await;
+    let user_id: Uuid = serde_json::from_value(response.json()["userId"].clone()).unwrap();
+
+    log::info!("Delete the user, it is kept for the grace period...");
+    let response = client.get("/auth/delete").await;
+    let csrf = response.form_value("csrf").expect("Missing CSRF token");
+    let response = client.post_form("/auth/delete", &[("csrf", &csrf)]).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.text().contains("marked for deletion"));
+    assert!(client.cookie("sid").is_none());
+    assert!(app.identity_exists(user_id).await);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn deletion_anonymizes_the_user() {
+    let app = TestApp::with_config(|config| {
+        config["deletion"]["mode"] = json!("anonymize");
+    })
+    .await;
+    let mut client = TestClient::new(&app.router);
+
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let token_cookie = client.cookie("tid").unwrap().to_owned();
+    let response = client.get("/api/auth/userinfo").await;
+    let user_id: Uuid = serde_json::from_value(response.json()["userId"].clone()).unwrap();
+
+    log::info!("Delete the user, only the personal data is removed...");
+    let response = client.get("/auth/delete").await;
+    let csrf = response.form_value("csrf").expect("Missing CSRF token");
+    let response = client.post_form("/auth/delete", &[("csrf", &csrf)]).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(app.identity_exists(user_id).await);
+
+    log::info!("The login token of the anonymized user is rejected...");
+    let mut client = TestClient::new(&app.router);
+    client.set_cookie("tid", &token_cookie);
+    let response = client.get("/auth/token/login").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(client.cookie("sid").is_none());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn deletion_requires_fresh_session() {
+    let app = TestApp::new().await;
+    let mut client = TestClient::new(&app.router);
+
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = client.get("/api/auth/userinfo").await;
+    let user_id: Uuid = serde_json::from_value(response.json()["userId"].clone()).unwrap();
+    let response = client.get("/auth/delete").await;
+    let csrf = response.form_value("csrf").expect("Missing CSRF token");
+
+    log::info!("Delete the user with a stale session...");
+    app.clock.advance(Duration::seconds(301));
+    let response = client.get("/auth/delete").await;
+    assert!(response.form_value("csrf").is_none());
+    let response = client.post_form("/auth/delete", &[("csrf", &csrf)]).await;
+    assert!(response.text().contains("Login again"));
+    assert!(app.identity_exists(user_id).await);
+
+    app.cleanup().await;
+}
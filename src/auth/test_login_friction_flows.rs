@@ -0,0 +1,112 @@
+use crate::test_support::{TestApp, TestClient};
+use axum::http::{HeaderName, StatusCode};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const EMAIL: &str = "player@example.com";
+const PASSWORD: &str = "correct horse battery";
+
+fn enable_friction(config: &mut Value, friction: Value) {
+    config["auth"]["password"] = json!({ "enabled": true });
+    config["auth"]["loginFriction"] = friction;
+}
+
+async fn login(client: &mut TestClient, email: &str, password: &str) -> StatusCode {
+    client
+        .post_json(
+            "/api/auth/password/login",
+            &json!({ "email": email, "password": password }),
+        )
+        .await
+        .status
+}
+
+/// Create a client behind the trusted proxy with the given address.
+fn client_from(app: &TestApp, ip: &str) -> TestClient {
+    let mut client = TestClient::new(&app.router);
+    client.set_header(HeaderName::from_static("x-forwarded-for"), ip);
+    client
+}
+
+#[tokio::test]
+async fn captcha_is_required_after_failures() {
+    let app = TestApp::with_config(|config| {
+        // nothing is listening on the verification url, the check fails closed
+        enable_friction(
+            config,
+            json!({
+                "captchaAfter": 2,
+                "captcha": { "verifyUrl": "http://127.0.0.1:9/siteverify", "secret": "secret", "timeout": 500 },
+                "cooldownAfter": 100,
+                "lockAfter": 100
+            }),
+        );
+        config["auth"]["clientIpHeader"] = json!("X-Forwarded-For");
+        config["adminAllowlist"]["trustedProxies"] = json!(["127.0.0.0/8"]);
+    })
+    .await;
+    app.create_password_user(EMAIL, PASSWORD).await;
+
+    log::info!("Fail the login from different addresses with the spelling variants of the email...");
+    let mut client = client_from(&app, "10.0.0.1");
+    assert_eq!(login(&mut client, EMAIL, "guess").await, StatusCode::UNAUTHORIZED);
+    let mut client = client_from(&app, "10.0.0.2");
+    assert_eq!(
+        login(&mut client, "Player@Example.com", "guess").await,
+        StatusCode::UNAUTHORIZED
+    );
+
+    log::info!("The valid password requires a captcha from a new address...");
+    let mut client = client_from(&app, "10.0.0.3");
+    assert_eq!(
+        login(&mut client, EMAIL, PASSWORD).await,
+        StatusCode::PRECONDITION_REQUIRED
+    );
+    let response = client
+        .post_json(
+            "/api/auth/password/login",
+            &json!({ "email": EMAIL, "password": PASSWORD, "captcha": "response" }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(response.text().contains("InvalidCaptcha"));
+    assert!(client.cookie("sid").is_none());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn login_cools_down_and_locks() {
+    let app = TestApp::with_config(|config| {
+        enable_friction(
+            config,
+            json!({
+                "cooldownAfter": 2,
+                "cooldown": 1,
+                "lockAfter": 3,
+                "lockDuration": 3600,
+                "lockEmail": { "enabled": true }
+            }),
+        )
+    })
+    .await;
+    app.create_password_user(EMAIL, PASSWORD).await;
+    let mut client = TestClient::new(&app.router);
+
+    log::info!("Each failure blocks the login for the cooldown...");
+    assert_eq!(login(&mut client, EMAIL, "guess").await, StatusCode::UNAUTHORIZED);
+    assert_eq!(login(&mut client, EMAIL, "guess").await, StatusCode::UNAUTHORIZED);
+    assert_eq!(login(&mut client, EMAIL, PASSWORD).await, StatusCode::TOO_MANY_REQUESTS);
+
+    log::info!("The login is locked after the cooldown...");
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(login(&mut client, EMAIL, "guess").await, StatusCode::UNAUTHORIZED);
+    assert_eq!(login(&mut client, EMAIL, PASSWORD).await, StatusCode::LOCKED);
+    assert!(client.cookie("sid").is_none());
+
+    log::info!("The owner is notified...");
+    let email = app.mailbox.receive(EMAIL).await;
+    assert!(email.text.contains("locked for 60 minutes"));
+
+    app.cleanup().await;
+}
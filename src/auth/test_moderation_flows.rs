@@ -0,0 +1,87 @@
+use crate::{db::Clock, test_support::TestApp};
+use axum::http::StatusCode;
+use chrono::Duration;
+use serde_json::json;
+
+#[tokio::test]
+async fn abuse_flags_are_reported_and_listed() {
+    let app = TestApp::new().await;
+    let (mut admin, _) = app.client_with_role("admin").await;
+
+    let (mut player, player_id) = app.registered_client().await;
+
+    log::info!("Report an abuse flag as a service...");
+    let report = json!({
+        "userId": player_id,
+        "category": "cheating",
+        "severity": "high",
+        "evidence": "replay:42",
+        "reporter": "anti-cheat",
+    });
+    let response = player.post_json("/api/auth/abuse-flags", &report).await;
+    assert_eq!(response.status, StatusCode::CREATED);
+
+    log::info!("Only the admins can list the flags...");
+    let path = format!("/api/auth/identities/{player_id}/abuse-flags");
+    let response = player.get(&path).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let response = admin.get(&path).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let flags = response.json();
+    assert_eq!(flags.as_array().unwrap().len(), 1);
+    assert_eq!(flags[0]["category"], json!("cheating"));
+    assert_eq!(flags[0]["reporter"], json!("anti-cheat"));
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn restriction_expires() {
+    let app = TestApp::new().await;
+    let (mut admin, _) = app.client_with_role("admin").await;
+
+    let (mut player, player_id) = app.registered_client().await;
+
+    log::info!("Mute the player for an hour...");
+    let path = format!("/api/auth/identities/{player_id}/restrictions/chat:muted");
+    let request = json!({
+        "reason": "spam",
+        "expireAt": app.clock.now() + Duration::hours(1),
+    });
+    let response = player.put_json(&path, &request).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let response = admin.put_json(&path, &request).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let user_info = player.get("/api/auth/userinfo").await.json();
+    assert_eq!(user_info["restrictions"], json!(["chat:muted"]));
+
+    log::info!("The restriction is lifted after the expiration...");
+    app.clock.advance(Duration::hours(2));
+    let user_info = player.get("/api/auth/userinfo").await.json();
+    assert_eq!(user_info["restrictions"], json!([]));
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn identity_tags_are_managed_by_admins() {
+    let app = TestApp::new().await;
+    let (mut admin, _) = app.client_with_role("admin").await;
+
+    let (mut player, player_id) = app.registered_client().await;
+    let path = format!("/api/auth/identities/{player_id}/tags");
+
+    log::info!("Tag the player...");
+    let response = player.put_json(&format!("{path}/beta-tester"), &json!({})).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    for tag in ["press", "beta-tester", "press"] {
+        let response = admin.put_json(&format!("{path}/{tag}"), &json!({})).await;
+        assert_eq!(response.status, StatusCode::NO_CONTENT);
+    }
+    let response = admin.put_json(&format!("{path}/Beta%20Tester"), &json!({})).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(admin.get(&path).await.json(), json!(["beta-tester", "press"]));
+
+    app.cleanup().await;
+}
@@ -0,0 +1,473 @@
+use crate::{
+    db::{Clock, ServiceClientManager},
+    test_support::{TestApp, TestClient},
+};
+use axum::http::{header, StatusCode};
+use base64::{
+    engine::general_purpose::{STANDARD as B64, URL_SAFE_NO_PAD as B64URL},
+    Engine,
+};
+use chrono::Duration;
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+use serde_json::json;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn service_client_credentials() {
+    let signing_key = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let app = TestApp::with_config(|config| {
+        config["auth"]["serviceToken"] = json!({ "signingKey": B64.encode(signing_key.as_ref()) });
+    })
+    .await;
+    let services = ServiceClientManager::new(&app.db_pool, app.clock.clone())
+        .await
+        .unwrap();
+    let scopes = ["builds:read".to_owned(), "builds:write".to_owned()];
+    let service = services
+        .create_service(
+            "default",
+            Uuid::new_v4(),
+            "Build tools",
+            &scopes,
+            Some("test-secret"),
+            None,
+        )
+        .await
+        .unwrap();
+    let client_id = service.user_id.to_string();
+    let mut client = TestClient::new(&app.router);
+
+    log::info!("Request a token with a subset of the scopes...");
+    let response = client
+        .post_form(
+            "/api/auth/service/token",
+            &[
+                ("grant_type", "client_credentials"),
+                ("client_id", &client_id),
+                ("client_secret", "test-secret"),
+                ("scope", "builds:read"),
+            ],
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let token = response.json();
+    assert_eq!(token["token_type"], json!("Bearer"));
+    assert_eq!(token["scope"], json!("builds:read"));
+    assert_eq!(token["access_token"].as_str().unwrap().split('.').count(), 3);
+
+    log::info!("Scopes not allowed for the service are rejected...");
+    let response = client
+        .post_form(
+            "/api/auth/service/token",
+            &[
+                ("grant_type", "client_credentials"),
+                ("client_id", &client_id),
+                ("client_secret", "test-secret"),
+                ("scope", "builds:read admin"),
+            ],
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    log::info!("Invalid secret is rejected...");
+    let response = client
+        .post_form(
+            "/api/auth/service/token",
+            &[
+                ("grant_type", "client_credentials"),
+                ("client_id", &client_id),
+                ("client_secret", "wrong-secret"),
+            ],
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let response = client.get("/api/auth/service/keys").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["keys"][0]["crv"], json!("Ed25519"));
+
+    app.cleanup().await;
+}
+
+/// Create a client assertion (RFC 7523) of a service signed by its key.
+fn client_assertion(key_pair: &Ed25519KeyPair, client_id: &str, audience: &str, expire_at: i64) -> String {
+    let header = B64URL.encode(json!({ "alg": "EdDSA", "typ": "JWT" }).to_string());
+    let claims = json!({
+        "iss": client_id,
+        "sub": client_id,
+        "aud": audience,
+        "exp": expire_at,
+        "jti": Uuid::new_v4(),
+    });
+    let signing_input = format!("{header}.{}", B64URL.encode(claims.to_string()));
+    let signature = B64URL.encode(key_pair.sign(signing_input.as_bytes()).as_ref());
+    format!("{signing_input}.{signature}")
+}
+
+#[tokio::test]
+async fn service_key_pair_authentication() {
+    let signing_key = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let app = TestApp::with_config(|config| {
+        config["auth"]["serviceToken"] = json!({ "signingKey": B64.encode(signing_key.as_ref()) });
+    })
+    .await;
+    let client_key = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let client_key = Ed25519KeyPair::from_pkcs8(client_key.as_ref()).unwrap();
+    let public_key = B64URL.encode(client_key.public_key().as_ref());
+    let services = ServiceClientManager::new(&app.db_pool, app.clock.clone())
+        .await
+        .unwrap();
+    let service = services
+        .create_service(
+            "default",
+            Uuid::new_v4(),
+            "CI pipeline",
+            &["builds:write".to_owned()],
+            None,
+            Some(&public_key),
+        )
+        .await
+        .unwrap();
+    let client_id = service.user_id.to_string();
+    let audience = "http://localhost/identity/auth";
+    let expire_at = (app.clock.now() + Duration::minutes(1)).timestamp();
+    let mut client = TestClient::new(&app.router);
+
+    log::info!("Request a token by a signed assertion...");
+    let assertion = client_assertion(&client_key, &client_id, audience, expire_at);
+    let fields = [
+        ("grant_type", "client_credentials"),
+        (
+            "client_assertion_type",
+            "urn:ietf:params:oauth:client-assertion-type:jwt-bearer",
+        ),
+        ("client_assertion", assertion.as_str()),
+    ];
+    let response = client.post_form("/api/auth/service/token", &fields).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["scope"], json!("builds:write"));
+
+    log::info!("The assertion cannot be replayed...");
+    let response = client.post_form("/api/auth/service/token", &fields).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    log::info!("Assertion signed by another key is rejected...");
+    let other_key = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let other_key = Ed25519KeyPair::from_pkcs8(other_key.as_ref()).unwrap();
+    let assertion = client_assertion(&other_key, &client_id, audience, expire_at);
+    let fields = [
+        ("grant_type", "client_credentials"),
+        (
+            "client_assertion_type",
+            "urn:ietf:params:oauth:client-assertion-type:jwt-bearer",
+        ),
+        ("client_assertion", assertion.as_str()),
+    ];
+    let response = client.post_form("/api/auth/service/token", &fields).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    log::info!("Long-lived assertion is rejected...");
+    let expire_at = (app.clock.now() + Duration::hours(1)).timestamp();
+    let assertion = client_assertion(&client_key, &client_id, audience, expire_at);
+    let fields = [
+        ("grant_type", "client_credentials"),
+        (
+            "client_assertion_type",
+            "urn:ietf:params:oauth:client-assertion-type:jwt-bearer",
+        ),
+        ("client_assertion", assertion.as_str()),
+    ];
+    let response = client.post_form("/api/auth/service/token", &fields).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    log::info!("The service has no secret...");
+    let response = client
+        .post_form(
+            "/api/auth/service/token",
+            &[
+                ("grant_type", "client_credentials"),
+                ("client_id", &client_id),
+                ("client_secret", ""),
+            ],
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn opaque_service_token_introspection() {
+    let signing_key = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let app = TestApp::with_config(|config| {
+        config["auth"]["serviceToken"] = json!({
+            "signingKey": B64.encode(signing_key.as_ref()),
+            "format": "opaque"
+        });
+    })
+    .await;
+    let services = ServiceClientManager::new(&app.db_pool, app.clock.clone())
+        .await
+        .unwrap();
+    let scopes = ["builds:read".to_owned()];
+    let service = services
+        .create_service(
+            "default",
+            Uuid::new_v4(),
+            "Build tools",
+            &scopes,
+            Some("test-secret"),
+            None,
+        )
+        .await
+        .unwrap();
+    let client_id = service.user_id.to_string();
+    let mut client = TestClient::new(&app.router);
+
+    let response = client
+        .post_form(
+            "/api/auth/service/token",
+            &[
+                ("grant_type", "client_credentials"),
+                ("client_id", &client_id),
+                ("client_secret", "test-secret"),
+            ],
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let access_token = response.json()["access_token"].as_str().unwrap().to_owned();
+    assert!(!access_token.contains('.'));
+
+    log::info!("Resolve the opaque token by the introspection...");
+    for (token, active) in [(access_token.as_str(), true), ("unknown-token", false)] {
+        let response = client
+            .post_form(
+                "/api/auth/service/introspect",
+                &[
+                    ("token", token),
+                    ("client_id", &client_id),
+                    ("client_secret", "test-secret"),
+                ],
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        let introspection = response.json();
+        assert_eq!(introspection["active"], json!(active));
+        if active {
+            assert_eq!(introspection["sub"], json!(client_id));
+            assert_eq!(introspection["scope"], json!("builds:read"));
+        }
+    }
+
+    log::info!("Expired token is rejected by the bearer authentication...");
+    app.clock.advance(Duration::seconds(600 + 1));
+    client.set_header(header::AUTHORIZATION, &format!("Bearer {access_token}"));
+    let response = client.get("/api/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn token_exchange_delegation() {
+    let signing_key = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let app = TestApp::with_config(|config| {
+        config["auth"]["serviceToken"] = json!({ "signingKey": B64.encode(signing_key.as_ref()) });
+    })
+    .await;
+    let services = ServiceClientManager::new(&app.db_pool, app.clock.clone())
+        .await
+        .unwrap();
+    let scopes = ["orders:read".to_owned(), "orders:write".to_owned()];
+    let frontend = services
+        .create_service(
+            "default",
+            Uuid::new_v4(),
+            "Frontend",
+            &scopes,
+            Some("frontend-secret"),
+            None,
+        )
+        .await
+        .unwrap();
+    let orders = services
+        .create_service(
+            "default",
+            Uuid::new_v4(),
+            "Orders",
+            &scopes[..1],
+            Some("orders-secret"),
+            None,
+        )
+        .await
+        .unwrap();
+    let (frontend_id, orders_id) = (frontend.user_id.to_string(), orders.user_id.to_string());
+    let mut client = TestClient::new(&app.router);
+
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let session_cookie = client.cookie("sid").unwrap().to_owned();
+
+    log::info!("Exchange the session of the user for a token of the orders service...");
+    let response = client
+        .post_form(
+            "/api/auth/service/token",
+            &[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:token-exchange"),
+                ("client_id", &frontend_id),
+                ("client_secret", "frontend-secret"),
+                ("subject_token", &session_cookie),
+                ("subject_token_type", "urn:shine:params:oauth:token-type:session"),
+                ("audience", "Orders"),
+                ("scope", "orders:read"),
+            ],
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let delegated_token = response.json()["access_token"].as_str().unwrap().to_owned();
+
+    log::info!("Only the audience can exchange the delegated token...");
+    let mut response = None;
+    for (client_id, secret, status) in [
+        (&frontend_id, "frontend-secret", StatusCode::BAD_REQUEST),
+        (&orders_id, "orders-secret", StatusCode::OK),
+    ] {
+        let exchanged = client
+            .post_form(
+                "/api/auth/service/token",
+                &[
+                    ("grant_type", "urn:ietf:params:oauth:grant-type:token-exchange"),
+                    ("client_id", client_id),
+                    ("client_secret", secret),
+                    ("subject_token", &delegated_token),
+                    ("subject_token_type", "urn:ietf:params:oauth:token-type:jwt"),
+                    ("audience", "Frontend"),
+                ],
+            )
+            .await;
+        assert_eq!(exchanged.status, status);
+        response = Some(exchanged);
+    }
+    let response = response.unwrap();
+    let token = response.json();
+    assert_eq!(token["scope"], json!("orders:read"));
+    let claims = token["access_token"].as_str().unwrap().split('.').nth(1).unwrap();
+    let claims: serde_json::Value = serde_json::from_slice(&B64URL.decode(claims).unwrap()).unwrap();
+    assert_eq!(claims["aud"], json!("Frontend"));
+    assert_eq!(claims["act"]["sub"], json!(orders_id));
+    assert_eq!(claims["act"]["act"]["sub"], json!(frontend_id));
+
+    app.cleanup().await;
+}
+
+/// Create a service with a single scope and get a token by its client credentials.
+async fn service_token(app: &TestApp, services: &ServiceClientManager, scope: &str) -> String {
+    let service = services
+        .create_service(
+            "default",
+            Uuid::new_v4(),
+            scope,
+            &[scope.to_owned()],
+            Some("test-secret"),
+            None,
+        )
+        .await
+        .unwrap();
+    let client_id = service.user_id.to_string();
+    let fields = [
+        ("grant_type", "client_credentials"),
+        ("client_id", client_id.as_str()),
+        ("client_secret", "test-secret"),
+    ];
+    let response = TestClient::new(&app.router)
+        .post_form("/api/auth/service/token", &fields)
+        .await;
+    response.json()["access_token"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn join_token_for_matchmaking() {
+    let signing_key = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let app = TestApp::with_config(|config| {
+        config["auth"]["serviceToken"] = json!({ "signingKey": B64.encode(signing_key.as_ref()) });
+        config["auth"]["joinToken"] = json!({});
+    })
+    .await;
+    let services = ServiceClientManager::new(&app.db_pool, app.clock.clone())
+        .await
+        .unwrap();
+    let mut client = TestClient::new(&app.router);
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let user_id = client.get("/api/auth/userinfo").await.json()["userId"].clone();
+
+    let request = json!({ "userId": user_id, "serverId": "eu-west-1-042", "matchId": "match-7" });
+
+    log::info!("Services without the scope are rejected...");
+    let mut other = TestClient::new(&app.router);
+    other.set_header(
+        header::AUTHORIZATION,
+        &format!("Bearer {}", service_token(&app, &services, "builds:read").await),
+    );
+    let response = other.post_json("/api/auth/join-token", &request).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+    log::info!("Request a join token as the matchmaking...");
+    let mut matchmaking = TestClient::new(&app.router);
+    matchmaking.set_header(
+        header::AUTHORIZATION,
+        &format!("Bearer {}", service_token(&app, &services, "game:join").await),
+    );
+    let response = matchmaking.post_json("/api/auth/join-token", &request).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let join_token = response.json()["joinToken"].as_str().unwrap().to_owned();
+
+    log::info!("Verify the join token by the JWKS...");
+    let keys = client.get("/api/auth/service/keys").await.json();
+    let public_key = B64URL.decode(keys["keys"][0]["x"].as_str().unwrap()).unwrap();
+    let (signing_input, signature) = join_token.rsplit_once('.').unwrap();
+    UnparsedPublicKey::new(&ED25519, &public_key)
+        .verify(signing_input.as_bytes(), &B64URL.decode(signature).unwrap())
+        .unwrap();
+    let claims: serde_json::Value =
+        serde_json::from_slice(&B64URL.decode(signing_input.split('.').nth(1).unwrap()).unwrap()).unwrap();
+    assert_eq!(claims["sub"], user_id);
+    assert_eq!(claims["aud"], json!("eu-west-1-042"));
+    assert_eq!(claims["match_id"], json!("match-7"));
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn api_quota_is_enforced() {
+    let app = TestApp::with_config(|config| {
+        config["auth"]["apiQuota"] = json!({ "dailyRequests": 2 });
+    })
+    .await;
+    let mut client = TestClient::new(&app.router);
+    let response = client.get("/auth/token/login?register=true").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    for remaining in ["1", "0"] {
+        let response = client.get("/api/auth/userinfo").await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.headers["x-ratelimit-limit"], "2");
+        assert_eq!(response.headers["x-ratelimit-remaining"], remaining);
+    }
+
+    log::info!("Requests above the quota are rejected...");
+    let response = client.get("/api/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers.contains_key(header::RETRY_AFTER));
+
+    log::info!("The quota is reset on the next day...");
+    app.clock.advance(Duration::days(1));
+    let response = client.get("/api/auth/userinfo").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.headers["x-ratelimit-remaining"], "1");
+
+    app.cleanup().await;
+}
@@ -0,0 +1,192 @@
+use crate::{
+    db::ServiceClientManager,
+    test_support::{TestApp, TestClient},
+};
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
+use ring::{rand::SystemRandom, signature::Ed25519KeyPair};
+use serde_json::json;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn studio_tokens() {
+    let signing_key = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let app = TestApp::with_config(|config| {
+        config["auth"]["serviceToken"] = json!({ "signingKey": B64.encode(signing_key.as_ref()) });
+    })
+    .await;
+    let services = ServiceClientManager::new(&app.db_pool, app.clock.clone())
+        .await
+        .unwrap();
+    let service = services
+        .create_service("default", Uuid::new_v4(), "Builds", &[], Some("test-secret"), None)
+        .await
+        .unwrap();
+    let service_id = service.user_id.to_string();
+
+    let mut owner = TestClient::new(&app.router);
+    owner.get("/auth/token/login?register=true").await;
+    let mut member = TestClient::new(&app.router);
+    member.get("/auth/token/login?register=true").await;
+    let member_id = member.get("/api/auth/userinfo").await.json()["userId"].clone();
+    let mut stranger = TestClient::new(&app.router);
+    stranger.get("/auth/token/login?register=true").await;
+
+    log::info!("Create a studio with a member...");
+    let response = owner
+        .post_json("/api/auth/studios", &json!({ "name": "Indie Studio" }))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let studio_id = response.json()["studioId"].as_str().unwrap().to_owned();
+    let path = format!("/api/auth/studios/{studio_id}/members/{}", member_id.as_str().unwrap());
+    let response = owner.put_json(&path, &json!({})).await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+
+    log::info!("Only the owner can create tokens...");
+    let path = format!("/api/auth/studios/{studio_id}/tokens");
+    let request = json!({ "name": "Build bot", "scopes": ["builds:write"] });
+    let response = member.post_json(&path, &request).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let response = owner.post_json(&path, &request).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let token = response.json()["token"].as_str().unwrap().to_owned();
+    let token_id = response.json()["tokenId"].as_str().unwrap().to_owned();
+
+    log::info!("The token acts on behalf of the studio...");
+    let mut resource_server = TestClient::new(&app.router);
+    let fields = [
+        ("token", token.as_str()),
+        ("client_id", service_id.as_str()),
+        ("client_secret", "test-secret"),
+    ];
+    let claims = resource_server
+        .post_form("/api/auth/service/introspect", &fields)
+        .await
+        .json();
+    assert_eq!(claims["active"], json!(true));
+    assert_eq!(claims["sub"], json!(studio_id));
+    assert_eq!(claims["principal"], json!("studio"));
+    assert_eq!(claims["scope"], json!("builds:write"));
+
+    log::info!("The members can list and revoke the tokens...");
+    let response = stranger.get(&path).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let tokens = member.get(&path).await.json();
+    assert_eq!(tokens[0]["tokenId"], json!(token_id));
+    assert_eq!(tokens[0]["name"], json!("Build bot"));
+    let response = member.delete(&format!("{path}/{token_id}")).await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    let claims = resource_server
+        .post_form("/api/auth/service/introspect", &fields)
+        .await
+        .json();
+    assert_eq!(claims, json!({ "active": false }));
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn studio_transfer_and_deletion() {
+    let signing_key = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let app = TestApp::with_config(|config| {
+        config["auth"]["serviceToken"] = json!({ "signingKey": B64.encode(signing_key.as_ref()) });
+        config["deletion"]["gracePeriod"] = json!(3600);
+    })
+    .await;
+    let services = ServiceClientManager::new(&app.db_pool, app.clock.clone())
+        .await
+        .unwrap();
+    let service = services
+        .create_service("default", Uuid::new_v4(), "Builds", &[], Some("test-secret"), None)
+        .await
+        .unwrap();
+    let service_id = service.user_id.to_string();
+
+    let mut owner = TestClient::new(&app.router);
+    owner.get("/auth/token/login?register=true").await;
+    let mut member = TestClient::new(&app.router);
+    member.get("/auth/token/login?register=true").await;
+    let member_id = member.get("/api/auth/userinfo").await.json()["userId"].clone();
+    let mut stranger = TestClient::new(&app.router);
+    stranger.get("/auth/token/login?register=true").await;
+    let stranger_id = stranger.get("/api/auth/userinfo").await.json()["userId"].clone();
+
+    let response = owner
+        .post_json("/api/auth/studios", &json!({ "name": "Indie Studio" }))
+        .await;
+    let studio_id = response.json()["studioId"].as_str().unwrap().to_owned();
+    let path = format!("/api/auth/studios/{studio_id}/members/{}", member_id.as_str().unwrap());
+    owner.put_json(&path, &json!({})).await;
+    let tokens_path = format!("/api/auth/studios/{studio_id}/tokens");
+    let response = owner
+        .post_json(&tokens_path, &json!({ "name": "Build bot", "scopes": [] }))
+        .await;
+    let token = response.json()["token"].as_str().unwrap().to_owned();
+
+    log::info!("The ownership can be transferred only to a member...");
+    let transfer_path = format!("/api/auth/studios/{studio_id}/transfer");
+    let response = owner
+        .post_json(&transfer_path, &json!({ "newOwnerId": stranger_id }))
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    let response = owner
+        .post_json(&transfer_path, &json!({ "newOwnerId": member_id }))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["newOwnerId"], member_id);
+
+    log::info!("Only the new owner can accept the transfer...");
+    let accept_path = format!("{transfer_path}/accept");
+    let response = stranger.post_json(&accept_path, &json!({})).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let response = owner.post_json(&accept_path, &json!({})).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    let response = member.post_json(&accept_path, &json!({})).await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    let response = member.post_json(&accept_path, &json!({})).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    log::info!("The previous owner remains a member...");
+    let request = json!({ "name": "Release bot", "scopes": [] });
+    let response = owner.post_json(&tokens_path, &request).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let response = owner.get(&tokens_path).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = member.post_json(&tokens_path, &request).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    log::info!("The deleted studio is kept for the grace period, but its tokens are rejected...");
+    let studio_path = format!("/api/auth/studios/{studio_id}");
+    let response = owner.delete(&studio_path).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let response = member.delete(&studio_path).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.json()["purgeAfter"].is_string());
+    let studio_uuid: Uuid = studio_id.parse().unwrap();
+    assert!(app.identity_exists(studio_uuid).await);
+    let mut resource_server = TestClient::new(&app.router);
+    let fields = [
+        ("token", token.as_str()),
+        ("client_id", service_id.as_str()),
+        ("client_secret", "test-secret"),
+    ];
+    let claims = resource_server
+        .post_form("/api/auth/service/introspect", &fields)
+        .await
+        .json();
+    assert_eq!(claims, json!({ "active": false }));
+
+    log::info!("The owner can restore the studio...");
+    let restore_path = format!("{studio_path}/restore");
+    let response = member.post_json(&restore_path, &json!({})).await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+    let response = member.post_json(&restore_path, &json!({})).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    let claims = resource_server
+        .post_form("/api/auth/service/introspect", &fields)
+        .await
+        .json();
+    assert_eq!(claims["active"], json!(true));
+
+    app.cleanup().await;
+}
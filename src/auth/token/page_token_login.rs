@@ -1,34 +1,95 @@
-use crate::auth::{AuthError, AuthPage, AuthServiceState, AuthSession};
-use axum::extract::{Query, State};
+use crate::{
+    auth::{AuthError, AuthPage, AuthServiceState, AuthSession, BotTrap, ClientInfo, RiskDecision},
+    db::{AgeBracket, FeatureFlag},
+    logging::user_hash,
+};
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+};
+use chrono::NaiveDate;
 use serde::Deserialize;
 use shine_service::service::APP_NAME;
 use url::Url;
 
+/// Choice of the user on the account chooser page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) enum AccountChoice {
+    /// Continue with the account of the login token.
+    Continue,
+    /// Forget the login token and use another account.
+    Other,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub(in crate::auth) struct RequestParams {
     register: bool,
+    account: Option<AccountChoice>,
 
     redirect_url: Option<Url>,
     login_url: Option<Url>,
     error_url: Option<Url>,
+
+    /// Bot trap fields of the registration form.
+    website: Option<String>,
+    form_time: Option<i64>,
+
+    /// Age of the registration form, see `AgeGateConfig`.
+    birth_date: Option<NaiveDate>,
+    age_bracket: Option<AgeBracket>,
+    /// Email of a parent to consent to the account of a minor, see `ParentalConsentConfig`.
+    parent_email: Option<String>,
+}
+
+impl RequestParams {
+    fn chooser_url(&self, auth_session: &AuthSession, account: &str) -> Url {
+        let mut url = auth_session.tenant().page_url(&["token", "login"]);
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("register", if self.register { "true" } else { "false" });
+            query.append_pair("account", account);
+            if let Some(redirect_url) = &self.redirect_url {
+                query.append_pair("redirectUrl", redirect_url.as_str());
+            }
+            if let Some(login_url) = &self.login_url {
+                query.append_pair("loginUrl", login_url.as_str());
+            }
+            if let Some(error_url) = &self.error_url {
+                query.append_pair("errorUrl", error_url.as_str());
+            }
+        }
+        url
+    }
 }
 
 pub(in crate::auth) async fn page_token_login(
     State(state): State<AuthServiceState>,
     Query(query): Query<RequestParams>,
+    client_info: ClientInfo,
+    headers: HeaderMap,
     mut auth_session: AuthSession,
 ) -> AuthPage {
     if auth_session.user.is_some() {
         return state.page_error(auth_session, AuthError::LogoutRequired, query.error_url.as_ref());
     }
 
+    if query.account == Some(AccountChoice::Other) {
+        if let Some((user_id, token)) = auth_session.token_login.take().map(|t| (t.user_id, t.token)) {
+            log::debug!("Token dropped by the user, using another account...");
+            if let Err(err) = state.identity_manager().delete_token(user_id, &token).await {
+                log::warn!("Failed to delete the dropped token of {user_id}: {err}");
+            }
+        }
+    }
+
     let identity =
         if let Some((user_id, token)) = auth_session.token_login.as_ref().map(|t| (t.user_id, t.token.clone())) {
             log::debug!("Token found, performing a simple login...");
 
-            let identity = match state.identity_manager().find_token(&token).await {
-                Ok(login_info) => login_info.map(|i| i.0),
+            let identity = match state.find_login_token(auth_session.tenant(), user_id, &token).await {
+                Ok(identity) => identity,
                 Err(err) => return state.page_internal_error(auth_session, err, query.error_url.as_ref()),
             };
 
@@ -38,6 +99,28 @@ pub(in crate::auth) async fn page_token_login(
                         auth_session.token_login = None;
                         return state.page_error(auth_session, AuthError::TokenInvalid, query.error_url.as_ref());
                     }
+                    if state.is_account_chooser_enabled() && query.account.is_none() {
+                        let mut context = tera::Context::new();
+                        context.insert("app_name", APP_NAME);
+                        context.insert("name", &identity.name);
+                        let tenant = auth_session.tenant();
+                        let last_provider = auth_session
+                            .last_provider
+                            .as_deref()
+                            .filter(|provider| tenant.is_provider_enabled(provider));
+                        context.insert("last_provider", &last_provider);
+                        context.insert("continue_url", query.chooser_url(&auth_session, "continue").as_str());
+                        context.insert("other_url", query.chooser_url(&auth_session, "other").as_str());
+                        let html = state
+                            .tera()
+                            .render("account_chooser.html", &context)
+                            .expect("Failed to generate account_chooser.html template");
+                        return AuthPage {
+                            status: StatusCode::OK,
+                            auth_session: Some(auth_session),
+                            html,
+                        };
+                    }
                     identity
                 }
                 None => return state.page_error(auth_session, AuthError::TokenExpired, query.error_url.as_ref()),
@@ -50,14 +133,75 @@ pub(in crate::auth) async fn page_token_login(
                 return state.page_redirect(auth_session, APP_NAME, query.login_url.as_ref());
             }
 
+            let flags = state.feature_flags();
+            if !flags.is_enabled(FeatureFlag::AllowRegistration).await
+                || !flags.is_enabled(FeatureFlag::EnableGuestLogin).await
+            {
+                return state.page_error(auth_session, AuthError::RegistrationDisabled, query.error_url.as_ref());
+            }
+
+            let bot_trap = BotTrap {
+                honeypot: query.website.as_deref(),
+                form_time: query.form_time,
+            };
+            if let Some(reason) = state.detect_bot(&headers, &bot_trap) {
+                tracing::info!(provider = "token", outcome = "bot", reason = %reason, "Registration rejected");
+                return state.page_error(auth_session, AuthError::RegistrationRejected, query.error_url.as_ref());
+            }
+            if state.check_ip_reputation(&client_info).await != RiskDecision::Allow {
+                return state.page_error(auth_session, AuthError::RegistrationRejected, query.error_url.as_ref());
+            }
+            let has_age = query.birth_date.is_some() || query.age_bracket.is_some();
+            if let Some(age_gate) = state.age_gate_config() {
+                let today = state.clock().now().date_naive();
+                if (age_gate.required || has_age)
+                    && !age_gate.is_valid_declaration(query.birth_date, query.age_bracket, today)
+                {
+                    return state.page_error(auth_session, AuthError::AgeRequired, query.error_url.as_ref());
+                }
+            }
+
+            // the guest accounts have no email
+            if flags.is_enabled(FeatureFlag::RequireEmail).await
+                || state
+                    .registration_policy(&client_info)
+                    .map_or(false, |policy| policy.require_email)
+            {
+                return state.page_error(auth_session, AuthError::EmailRequired, query.error_url.as_ref());
+            }
+
             // create a new user
-            let identity = match state.create_user_with_retry(None, None, None).await {
+            let identity = match state
+                .create_user_with_retry(auth_session.tenant().id(), None, None, None)
+                .await
+            {
                 Ok(identity) => identity,
                 Err(err) => return state.page_internal_error(auth_session, err, query.error_url.as_ref()),
             };
+            state.record_region(identity.user_id, &client_info).await;
+            if let Some(age_gate) = state.age_gate_config() {
+                if has_age {
+                    if let Err(err) = state
+                        .age_manager()
+                        .declare(identity.user_id, query.birth_date, query.age_bracket)
+                        .await
+                    {
+                        log::error!("Failed to store the age of {}: {err}", identity.user_id);
+                    }
+                }
+                let today = state.clock().now().date_naive();
+                if age_gate.is_declared_minor(query.birth_date, query.age_bracket, today) == Some(true) {
+                    if let Err(err) = state
+                        .require_parental_consent(identity.user_id, &identity.name, query.parent_email.as_deref())
+                        .await
+                    {
+                        log::error!("Failed to request the parental consent of {}: {err}", identity.user_id);
+                    }
+                }
+            }
 
             // create a new token
-            let token_login = match state.create_token_with_retry(identity.user_id).await {
+            let token_login = match state.create_token_with_retry(&identity, None, &client_info).await {
                 Ok(token_login) => token_login,
                 Err(err) => return state.page_internal_error(auth_session, err, query.error_url.as_ref()),
             };
@@ -67,11 +211,20 @@ pub(in crate::auth) async fn page_token_login(
         };
 
     // create session
-    log::debug!("Identity created: {identity:#?}");
-    let user = match state.session_manager().create(&identity).await {
+    tracing::info!(
+        provider = "token",
+        user = %user_hash(identity.user_id),
+        outcome = "login",
+        "Login with token"
+    );
+    let user = match state.create_session(&identity, None).await {
         Ok(user) => user,
-        Err(err) => return state.page_internal_error(auth_session, err, query.error_url.as_ref()),
+        Err(err) => return state.page_session_error(auth_session, err, query.error_url.as_ref()),
     };
+    state
+        .check_login_device(auth_session.tenant(), &identity, &user, &client_info)
+        .await;
+    state.restore_deleted_account(&identity).await;
     auth_session.user = Some(user);
 
     state.page_redirect(auth_session, APP_NAME, query.redirect_url.as_ref())
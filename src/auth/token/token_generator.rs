@@ -1,4 +1,5 @@
-use chrono::Duration;
+use crate::db::SharedClock;
+use chrono::{DateTime, Duration, Utc};
 use ring::rand::{SecureRandom, SystemRandom};
 use thiserror::Error as ThisError;
 
@@ -10,13 +11,15 @@ pub(in crate::auth) struct TokenGeneratorError(String);
 pub(in crate::auth) struct TokenGenerator {
     token_max_duration: Duration,
     random: SystemRandom,
+    clock: SharedClock,
 }
 
 impl TokenGenerator {
-    pub fn new(token_max_duration: Duration) -> Self {
+    pub fn new(token_max_duration: Duration, clock: SharedClock) -> Self {
         Self {
             token_max_duration,
             random: SystemRandom::new(),
+            clock,
         }
     }
 
@@ -24,6 +27,11 @@ impl TokenGenerator {
         self.token_max_duration
     }
 
+    /// The expiration of a token created now.
+    pub fn expire_at(&self) -> DateTime<Utc> {
+        self.clock.now() + self.token_max_duration
+    }
+
     pub fn generate_token(&self) -> Result<String, TokenGeneratorError> {
         let mut raw = [0_u8; 16];
         self.random
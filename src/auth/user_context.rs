@@ -0,0 +1,93 @@
+use crate::auth::AuthBuildError;
+use base64::{
+    engine::general_purpose::{STANDARD as B64, URL_SAFE_NO_PAD as B64URL},
+    Engine,
+};
+use chrono::{DateTime, Duration, Utc};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Header carrying the signed user context between the services.
+pub(in crate::auth) const USER_CONTEXT_HEADER: &str = "x-shine-user";
+
+fn default_duration() -> u64 {
+    60
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserContextConfig {
+    /// Ed25519 key in PKCS#8 format, base64 encoded.
+    pub signing_key: String,
+    /// Validity of the signed context in seconds.
+    #[serde(default = "default_duration")]
+    pub duration: u64,
+}
+
+impl UserContextConfig {
+    pub fn duration(&self) -> Duration {
+        Duration::seconds(self.duration as i64)
+    }
+}
+
+/// The frozen state of the current user shared along a chain of service calls.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(in crate::auth) struct UserContext<'a> {
+    pub user_id: Uuid,
+    pub name: &'a str,
+    pub roles: &'a [String],
+    /// The active restrictions of the user, ex. `chat:muted`.
+    pub restrictions: &'a [String],
+    pub session_start: DateTime<Utc>,
+    pub expire_at: DateTime<Utc>,
+}
+
+/// Sign the user context, thus the downstream services can verify it offline using the public key.
+/// The signed context has the `{payload}.{signature}` format, both parts are url-safe base64 encoded, the
+/// payload is the json of the context.
+pub(in crate::auth) struct UserContextSigner {
+    key_pair: Ed25519KeyPair,
+    duration: Duration,
+}
+
+impl UserContextSigner {
+    pub fn new(config: &UserContextConfig) -> Result<Self, AuthBuildError> {
+        let key = B64
+            .decode(&config.signing_key)
+            .map_err(|err| AuthBuildError::UserContext(format!("{err}")))?;
+        let key_pair = Ed25519KeyPair::from_pkcs8(&key).map_err(|err| AuthBuildError::UserContext(format!("{err}")))?;
+
+        Ok(Self {
+            key_pair,
+            duration: config.duration(),
+        })
+    }
+
+    /// The public key to verify the signature, url-safe base64 encoded.
+    pub fn public_key(&self) -> String {
+        B64URL.encode(self.key_pair.public_key().as_ref())
+    }
+
+    pub fn sign(
+        &self,
+        user_id: Uuid,
+        name: &str,
+        roles: &[String],
+        restrictions: &[String],
+        session_start: DateTime<Utc>,
+    ) -> String {
+        let context = UserContext {
+            user_id,
+            name,
+            roles,
+            restrictions,
+            session_start,
+            expire_at: Utc::now() + self.duration,
+        };
+        let payload = B64URL.encode(serde_json::to_vec(&context).expect("Failed to serialize user context"));
+        let signature = B64URL.encode(self.key_pair.sign(payload.as_bytes()).as_ref());
+        format!("{payload}.{signature}")
+    }
+}
@@ -0,0 +1,364 @@
+use crate::db::{DBError, DBPool, PGError, SharedClock, SqlPool, SqlitePool};
+use bytes::BytesMut;
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use tokio_postgres::{
+    types::{accepts, to_sql_checked, FromSql, IsNull, ToSql, Type},
+    Row,
+};
+use uuid::Uuid;
+
+pg_prepared_statement!( InsertFlag => r#"
+    INSERT INTO abuse_flags (flag_id, user_id, category, severity, evidence, reporter, created)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+"#, [UUID, UUID, INT2, INT2, VARCHAR, VARCHAR, TIMESTAMPTZ] );
+
+pg_prepared_statement!( ListFlags => r#"
+    SELECT flag_id, user_id, category, severity, evidence, reporter, created FROM abuse_flags
+        WHERE user_id = $1 ORDER BY created DESC
+"#, [UUID] );
+
+pg_prepared_statement!( MaxSeverity => r#"
+    SELECT max(severity) FROM abuse_flags WHERE user_id = $1 AND created >= $2
+"#, [UUID, TIMESTAMPTZ] );
+
+const SQLITE_INSERT_FLAG: &str = r#"
+    INSERT INTO abuse_flags (flag_id, user_id, category, severity, evidence, reporter, created)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+"#;
+
+const SQLITE_LIST_FLAGS: &str = r#"
+    SELECT flag_id, user_id, category, severity, evidence, reporter, created FROM abuse_flags
+        WHERE user_id = ?1 ORDER BY created DESC
+"#;
+
+const SQLITE_MAX_SEVERITY: &str = r#"
+    SELECT max(severity) FROM abuse_flags WHERE user_id = ?1 AND created >= ?2
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AbuseCategory {
+    Spam,
+    Cheating,
+    Harassment,
+}
+
+impl ToSql for AbuseCategory {
+    fn to_sql(&self, ty: &Type, out: &mut BytesMut) -> Result<IsNull, PGError> {
+        let value = match self {
+            AbuseCategory::Spam => 1_i16,
+            AbuseCategory::Cheating => 2_i16,
+            AbuseCategory::Harassment => 3_i16,
+        };
+        value.to_sql(ty, out)
+    }
+
+    accepts!(INT2);
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for AbuseCategory {
+    fn from_sql(ty: &Type, raw: &[u8]) -> Result<AbuseCategory, PGError> {
+        let value = i16::from_sql(ty, raw)?;
+        match value {
+            1 => Ok(AbuseCategory::Spam),
+            2 => Ok(AbuseCategory::Cheating),
+            3 => Ok(AbuseCategory::Harassment),
+            _ => Err(PGError::from("Invalid value for AbuseCategory")),
+        }
+    }
+
+    accepts!(INT2);
+}
+
+impl rusqlite::types::ToSql for AbuseCategory {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        let value = match self {
+            AbuseCategory::Spam => 1_i64,
+            AbuseCategory::Cheating => 2_i64,
+            AbuseCategory::Harassment => 3_i64,
+        };
+        Ok(value.into())
+    }
+}
+
+impl rusqlite::types::FromSql for AbuseCategory {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        match value.as_i64()? {
+            1 => Ok(AbuseCategory::Spam),
+            2 => Ok(AbuseCategory::Cheating),
+            3 => Ok(AbuseCategory::Harassment),
+            value => Err(rusqlite::types::FromSqlError::OutOfRange(value)),
+        }
+    }
+}
+
+/// The severity of the flags, ordered from the least severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AbuseSeverity {
+    Low,
+    Medium,
+    High,
+}
+
+impl AbuseSeverity {
+    /// The weight of the severity, 1 for the lowest.
+    pub fn level(self) -> u32 {
+        match self {
+            AbuseSeverity::Low => 1,
+            AbuseSeverity::Medium => 2,
+            AbuseSeverity::High => 3,
+        }
+    }
+}
+
+impl ToSql for AbuseSeverity {
+    fn to_sql(&self, ty: &Type, out: &mut BytesMut) -> Result<IsNull, PGError> {
+        (self.level() as i16).to_sql(ty, out)
+    }
+
+    accepts!(INT2);
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for AbuseSeverity {
+    fn from_sql(ty: &Type, raw: &[u8]) -> Result<AbuseSeverity, PGError> {
+        let value = i16::from_sql(ty, raw)?;
+        match value {
+            1 => Ok(AbuseSeverity::Low),
+            2 => Ok(AbuseSeverity::Medium),
+            3 => Ok(AbuseSeverity::High),
+            _ => Err(PGError::from("Invalid value for AbuseSeverity")),
+        }
+    }
+
+    accepts!(INT2);
+}
+
+impl rusqlite::types::ToSql for AbuseSeverity {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok((self.level() as i64).into())
+    }
+}
+
+impl rusqlite::types::FromSql for AbuseSeverity {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        match value.as_i64()? {
+            1 => Ok(AbuseSeverity::Low),
+            2 => Ok(AbuseSeverity::Medium),
+            3 => Ok(AbuseSeverity::High),
+            value => Err(rusqlite::types::FromSqlError::OutOfRange(value)),
+        }
+    }
+}
+
+/// A report of an abusive behavior of a user by an other service.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AbuseFlag {
+    pub flag_id: Uuid,
+    pub user_id: Uuid,
+    pub category: AbuseCategory,
+    pub severity: AbuseSeverity,
+    /// Reference to the evidence kept by the reporter, ex. the id of a chat message or a match replay.
+    pub evidence: Option<String>,
+    /// The name of the reporting service.
+    pub reporter: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AbuseFlag {
+    fn from_row(row: &Row) -> Self {
+        Self {
+            flag_id: row.get(0),
+            user_id: row.get(1),
+            category: row.get(2),
+            severity: row.get(3),
+            evidence: row.get(4),
+            reporter: row.get(5),
+            created_at: row.get(6),
+        }
+    }
+
+    fn from_sqlite_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            flag_id: row.get(0)?,
+            user_id: row.get(1)?,
+            category: row.get(2)?,
+            severity: row.get(3)?,
+            evidence: row.get(4)?,
+            reporter: row.get(5)?,
+            created_at: row.get(6)?,
+        })
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum AbuseFlagBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for AbuseFlagBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_insert_flag: InsertFlag,
+    stmt_list_flags: ListFlags,
+    stmt_max_severity: MaxSeverity,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    clock: SharedClock,
+}
+
+/// Store of the abuse flags reported on the identities, the flags are kept until the identity is deleted.
+#[derive(Clone)]
+pub struct AbuseFlagManager(Arc<Inner>);
+
+impl AbuseFlagManager {
+    pub async fn new(pool: &DBPool, clock: SharedClock) -> Result<Self, AbuseFlagBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_insert_flag = InsertFlag::new(&client).await?;
+                let stmt_list_flags = ListFlags::new(&client).await?;
+                let stmt_max_severity = MaxSeverity::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_insert_flag,
+                    stmt_list_flags,
+                    stmt_max_severity,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(Inner { store, clock })))
+    }
+
+    pub async fn add_flag(
+        &self,
+        user_id: Uuid,
+        category: AbuseCategory,
+        severity: AbuseSeverity,
+        evidence: Option<&str>,
+        reporter: &str,
+    ) -> Result<AbuseFlag, DBError> {
+        let flag = AbuseFlag {
+            flag_id: Uuid::new_v4(),
+            user_id,
+            category,
+            severity,
+            evidence: evidence.map(str::to_owned),
+            reporter: reporter.to_owned(),
+            created_at: self.0.clock.now(),
+        };
+
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_insert_flag.get(&client).await?;
+                client
+                    .execute(
+                        &stmt,
+                        &[
+                            &flag.flag_id,
+                            &flag.user_id,
+                            &flag.category,
+                            &flag.severity,
+                            &flag.evidence,
+                            &flag.reporter,
+                            &flag.created_at,
+                        ],
+                    )
+                    .await?;
+            }
+            Store::Sqlite(sqlite) => {
+                let row = flag.clone();
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(
+                            SQLITE_INSERT_FLAG,
+                            params![
+                                row.flag_id,
+                                row.user_id,
+                                row.category,
+                                row.severity,
+                                row.evidence,
+                                row.reporter,
+                                row.created_at
+                            ],
+                        )?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+
+        Ok(flag)
+    }
+
+    /// List the flags of a user, the latest first.
+    pub async fn list_flags(&self, user_id: Uuid) -> Result<Vec<AbuseFlag>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_list_flags.get(&client).await?;
+                let rows = client.query(&stmt, &[&user_id]).await?;
+                Ok(rows.iter().map(AbuseFlag::from_row).collect())
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Vec<AbuseFlag>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_LIST_FLAGS)?;
+                        let flags = stmt
+                            .query_map(params![user_id], AbuseFlag::from_sqlite_row)?
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(flags)
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Get the highest severity of the flags of a user reported since the given time.
+    pub async fn max_severity_since(
+        &self,
+        user_id: Uuid,
+        since: DateTime<Utc>,
+    ) -> Result<Option<AbuseSeverity>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_max_severity.get(&client).await?;
+                let row = client.query_one(&stmt, &[&user_id, &since]).await?;
+                Ok(row.get(0))
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Option<AbuseSeverity>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_MAX_SEVERITY)?;
+                        Ok(stmt.query_row(params![user_id, since], |row| row.get(0))?)
+                    })
+                    .await
+            }
+        }
+    }
+}
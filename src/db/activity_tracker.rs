@@ -0,0 +1,157 @@
+use crate::db::{DBError, DBPool, SharedClock, SqlPool, SqlitePool};
+use chrono::NaiveDate;
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::{
+    collections::HashMap,
+    mem,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+pg_prepared_statement!( UpdateLastSeen => r#"
+    UPDATE identities SET last_seen = seen.day
+        FROM unnest($1::uuid[], $2::date[]) AS seen(user_id, day)
+        WHERE identities.user_id = seen.user_id AND (identities.last_seen IS NULL OR identities.last_seen < seen.day)
+"#, [UUID_ARRAY, DATE_ARRAY] );
+
+const SQLITE_UPDATE_LAST_SEEN: &str = r#"
+    UPDATE identities SET last_seen = ?2
+        WHERE user_id = ?1 AND (last_seen IS NULL OR last_seen < ?2)
+"#;
+
+/// The pending activities are written to the database with this period.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, ThisError)]
+pub enum ActivityTrackerBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for ActivityTrackerBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+#[derive(Default)]
+struct Activities {
+    /// The last recorded day of the users, the entries of the past days are dropped on the day change.
+    recorded: HashMap<Uuid, NaiveDate>,
+    /// The activities not written to the database yet.
+    pending: Vec<(Uuid, NaiveDate)>,
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_update_last_seen: UpdateLastSeen,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    clock: SharedClock,
+    activities: Mutex<Activities>,
+}
+
+/// Track the last day the users were active. The activity is recorded at most once a day for a user and it is
+/// written to the database periodically in a batch, thus the authenticated requests don't update the identities.
+#[derive(Clone)]
+pub struct ActivityTracker(Arc<Inner>);
+
+impl ActivityTracker {
+    pub async fn new(pool: &DBPool, clock: SharedClock) -> Result<Self, ActivityTrackerBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_update_last_seen = UpdateLastSeen::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_update_last_seen,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        let tracker = Self(Arc::new(Inner {
+            store,
+            clock,
+            activities: Mutex::new(Activities::default()),
+        }));
+
+        let flusher = tracker.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(err) = flusher.flush().await {
+                    log::warn!("Failed to store the user activities: {err:?}");
+                }
+            }
+        });
+
+        Ok(tracker)
+    }
+
+    /// Record the activity of a user, it does not access the database.
+    pub fn record(&self, user_id: Uuid) {
+        let today = self.0.clock.now().date_naive();
+        let mut activities = self.0.activities.lock().unwrap();
+        match activities.recorded.insert(user_id, today) {
+            Some(day) if day == today => {}
+            Some(_) => {
+                activities.recorded.retain(|_, day| *day == today);
+                activities.pending.push((user_id, today));
+            }
+            None => activities.pending.push((user_id, today)),
+        }
+    }
+
+    /// Write the pending activities to the database. On failure the activities are kept for the next attempt.
+    pub async fn flush(&self) -> Result<(), DBError> {
+        let pending = mem::take(&mut self.0.activities.lock().unwrap().pending);
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let result = self.store(&pending).await;
+        if result.is_err() {
+            self.0.activities.lock().unwrap().pending.extend(pending);
+        }
+        result
+    }
+
+    async fn store(&self, activities: &[(Uuid, NaiveDate)]) -> Result<(), DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let (user_ids, days): (Vec<Uuid>, Vec<NaiveDate>) = activities.iter().cloned().unzip();
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_update_last_seen.get(&client).await?;
+                client.execute(&stmt, &[&user_ids, &days]).await?;
+            }
+            Store::Sqlite(sqlite) => {
+                let activities = activities.to_vec();
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        let transaction = conn.transaction()?;
+                        {
+                            let mut stmt = transaction.prepare(SQLITE_UPDATE_LAST_SEEN)?;
+                            for (user_id, day) in activities {
+                                stmt.execute((user_id, day))?;
+                            }
+                        }
+                        transaction.commit()?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+use crate::db::{DBError, DBPool, PGError, SharedClock, SqlPool, SqlitePool};
+use bytes::BytesMut;
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use tokio_postgres::types::{accepts, to_sql_checked, FromSql, IsNull, ToSql, Type};
+use uuid::Uuid;
+
+pg_prepared_statement!( DeclareAge => r#"
+    INSERT INTO identity_ages (user_id, birth_date, age_bracket, declared)
+        VALUES ($1, $2, $3, $4)
+    ON CONFLICT (user_id) DO NOTHING
+"#, [UUID, DATE, INT2, TIMESTAMPTZ] );
+
+pg_prepared_statement!( VerifyAdult => r#"
+    INSERT INTO identity_ages (user_id, declared, verified_adult)
+        VALUES ($1, $2, $2)
+    ON CONFLICT (user_id) DO UPDATE SET verified_adult = $2
+"#, [UUID, TIMESTAMPTZ] );
+
+pg_prepared_statement!( FindAge => r#"
+    SELECT birth_date, age_bracket, declared, verified_adult FROM identity_ages WHERE user_id = $1
+"#, [UUID] );
+
+const SQLITE_DECLARE_AGE: &str = r#"
+    INSERT INTO identity_ages (user_id, birth_date, age_bracket, declared)
+        VALUES (?1, ?2, ?3, ?4)
+    ON CONFLICT (user_id) DO NOTHING
+"#;
+
+const SQLITE_VERIFY_ADULT: &str = r#"
+    INSERT INTO identity_ages (user_id, declared, verified_adult)
+        VALUES (?1, ?2, ?2)
+    ON CONFLICT (user_id) DO UPDATE SET verified_adult = ?2
+"#;
+
+const SQLITE_FIND_AGE: &str = r#"
+    SELECT birth_date, age_bracket, declared, verified_adult FROM identity_ages WHERE user_id = ?1
+"#;
+
+/// The declared age of a user when only a bracket is collected. The limit of the adults is configured by the
+/// deployment, see `AgeGateConfig::adult_age`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AgeBracket {
+    /// Under 13.
+    Child,
+    /// From 13 until the adult age.
+    Teen,
+    Adult,
+}
+
+impl ToSql for AgeBracket {
+    fn to_sql(&self, ty: &Type, out: &mut BytesMut) -> Result<IsNull, PGError> {
+        let value = match self {
+            AgeBracket::Child => 1_i16,
+            AgeBracket::Teen => 2_i16,
+            AgeBracket::Adult => 3_i16,
+        };
+        value.to_sql(ty, out)
+    }
+
+    accepts!(INT2);
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for AgeBracket {
+    fn from_sql(ty: &Type, raw: &[u8]) -> Result<AgeBracket, PGError> {
+        let value = i16::from_sql(ty, raw)?;
+        match value {
+            1 => Ok(AgeBracket::Child),
+            2 => Ok(AgeBracket::Teen),
+            3 => Ok(AgeBracket::Adult),
+            _ => Err(PGError::from("Invalid value for AgeBracket")),
+        }
+    }
+
+    accepts!(INT2);
+}
+
+impl rusqlite::types::ToSql for AgeBracket {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        let value = match self {
+            AgeBracket::Child => 1_i64,
+            AgeBracket::Teen => 2_i64,
+            AgeBracket::Adult => 3_i64,
+        };
+        Ok(value.into())
+    }
+}
+
+impl rusqlite::types::FromSql for AgeBracket {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        match value.as_i64()? {
+            1 => Ok(AgeBracket::Child),
+            2 => Ok(AgeBracket::Teen),
+            3 => Ok(AgeBracket::Adult),
+            value => Err(rusqlite::types::FromSqlError::OutOfRange(value)),
+        }
+    }
+}
+
+/// The age of a user as declared at the registration.
+#[derive(Debug, Clone)]
+pub struct AgeInfo {
+    pub birth_date: Option<NaiveDate>,
+    pub age_bracket: Option<AgeBracket>,
+    pub declared_at: DateTime<Utc>,
+    /// The time the adult age was verified, ex. by an identity check of the support.
+    pub verified_adult_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, ThisError)]
+pub enum AgeManagerBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for AgeManagerBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_declare_age: DeclareAge,
+    stmt_verify_adult: VerifyAdult,
+    stmt_find_age: FindAge,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    clock: SharedClock,
+}
+
+/// Store of the declared ages of the users. The age can be declared only once by the user, it can be changed only
+/// by the verification of the adult age.
+#[derive(Clone)]
+pub struct AgeManager(Arc<Inner>);
+
+impl AgeManager {
+    pub async fn new(pool: &DBPool, clock: SharedClock) -> Result<Self, AgeManagerBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_declare_age = DeclareAge::new(&client).await?;
+                let stmt_verify_adult = VerifyAdult::new(&client).await?;
+                let stmt_find_age = FindAge::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_declare_age,
+                    stmt_verify_adult,
+                    stmt_find_age,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(Inner { store, clock })))
+    }
+
+    /// Store the declared age of a user, false is returned if the age has already been declared.
+    pub async fn declare(
+        &self,
+        user_id: Uuid,
+        birth_date: Option<NaiveDate>,
+        age_bracket: Option<AgeBracket>,
+    ) -> Result<bool, DBError> {
+        let now = self.0.clock.now();
+        let count = match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_declare_age.get(&client).await?;
+                client
+                    .execute(&stmt, &[&user_id, &birth_date, &age_bracket, &now])
+                    .await? as usize
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<usize, DBError> {
+                        Ok(conn.execute(SQLITE_DECLARE_AGE, params![user_id, birth_date, age_bracket, now])?)
+                    })
+                    .await?
+            }
+        };
+        Ok(count > 0)
+    }
+
+    /// Mark the user as a verified adult.
+    pub async fn verify_adult(&self, user_id: Uuid) -> Result<(), DBError> {
+        let now = self.0.clock.now();
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_verify_adult.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &now]).await?;
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(SQLITE_VERIFY_ADULT, params![user_id, now])?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn find(&self, user_id: Uuid) -> Result<Option<AgeInfo>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_find_age.get(&client).await?;
+                let row = client.query_opt(&stmt, &[&user_id]).await?;
+                Ok(row.map(|row| AgeInfo {
+                    birth_date: row.get(0),
+                    age_bracket: row.get(1),
+                    declared_at: row.get(2),
+                    verified_adult_at: row.get(3),
+                }))
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Option<AgeInfo>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_FIND_AGE)?;
+                        let mut rows = stmt.query(params![user_id])?;
+                        match rows.next()? {
+                            Some(row) => Ok(Some(AgeInfo {
+                                birth_date: row.get(0)?,
+                                age_bracket: row.get(1)?,
+                                declared_at: row.get(2)?,
+                                verified_adult_at: row.get(3)?,
+                            })),
+                            None => Ok(None),
+                        }
+                    })
+                    .await
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::db::{DBPool, SharedClock};
+use chrono::{DateTime, Utc};
+use redis::AsyncCommands;
+use serde::Serialize;
+use shine_service::service::RedisConnectionPool;
+use uuid::Uuid;
+
+/// The redis channel the analytics events are published on.
+pub const ANALYTICS_CHANNEL: &str = "auth-analytics";
+
+/// The steps of the login and registration flows.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AnalyticsEvent {
+    LoginStarted,
+    ProviderRedirect,
+    CallbackReceived,
+    UserCreated,
+    UserLinked,
+    Error,
+}
+
+impl AnalyticsEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnalyticsEvent::LoginStarted => "loginStarted",
+            AnalyticsEvent::ProviderRedirect => "providerRedirect",
+            AnalyticsEvent::CallbackReceived => "callbackReceived",
+            AnalyticsEvent::UserCreated => "userCreated",
+            AnalyticsEvent::UserLinked => "userLinked",
+            AnalyticsEvent::Error => "error",
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AnalyticsMessage<'a> {
+    flow_id: Uuid,
+    event: AnalyticsEvent,
+    provider: &'a str,
+    time: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+/// Publish the steps of the login flows as json on the `auth-analytics` channel. The events of a flow share the
+/// same flow id, thus the drop outs of the onboarding can be found. The events are published in the background,
+/// a failure is only logged.
+#[derive(Clone)]
+pub struct AnalyticsEvents {
+    redis: RedisConnectionPool,
+    clock: SharedClock,
+}
+
+impl AnalyticsEvents {
+    pub fn new(pool: &DBPool, clock: SharedClock) -> Self {
+        Self {
+            redis: pool.redis.clone(),
+            clock,
+        }
+    }
+
+    pub fn emit(
+        &self,
+        flow_id: Uuid,
+        provider: &str,
+        event: AnalyticsEvent,
+        user_id: Option<Uuid>,
+        error: Option<&str>,
+    ) {
+        let message = AnalyticsMessage {
+            flow_id,
+            event,
+            provider,
+            time: self.clock.now(),
+            user_id,
+            error,
+        };
+        let message = match serde_json::to_string(&message) {
+            Ok(message) => message,
+            Err(err) => {
+                log::warn!("Failed to serialize the analytics event: {err}");
+                return;
+            }
+        };
+
+        let redis = self.redis.clone();
+        tokio::spawn(async move {
+            let result = match redis.get().await {
+                Ok(mut client) => client
+                    .publish::<_, _, ()>(ANALYTICS_CHANNEL, message)
+                    .await
+                    .map_err(|err| format!("{err}")),
+                Err(err) => Err(format!("{err}")),
+            };
+            if let Err(err) = result {
+                log::warn!("Failed to publish the analytics event: {err}");
+            }
+        });
+    }
+}
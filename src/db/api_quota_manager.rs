@@ -0,0 +1,195 @@
+use crate::db::{DBError, DBPool, SharedClock, SqlPool, SqlitePool};
+use chrono::NaiveDate;
+use redis::Script;
+use shine_service::{
+    pg_prepared_statement,
+    service::{PGConnectionPool, RedisConnectionPool},
+};
+use std::{
+    collections::HashSet,
+    mem,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+pg_prepared_statement!( StoreUsage => r#"
+    INSERT INTO api_usage (user_id, day, requests)
+        SELECT usage.user_id, usage.day, usage.requests
+            FROM unnest($1::uuid[], $2::date[], $3::int8[]) AS usage(user_id, day, requests)
+            WHERE EXISTS (SELECT 1 FROM identities WHERE identities.user_id = usage.user_id)
+        ON CONFLICT (user_id, day) DO UPDATE SET requests = GREATEST(api_usage.requests, EXCLUDED.requests)
+"#, [UUID_ARRAY, DATE_ARRAY, INT8_ARRAY] );
+
+const SQLITE_STORE_USAGE: &str = r#"
+    INSERT INTO api_usage (user_id, day, requests)
+        SELECT ?1, ?2, ?3 WHERE EXISTS (SELECT 1 FROM identities WHERE user_id = ?1)
+        ON CONFLICT (user_id, day) DO UPDATE SET requests = max(api_usage.requests, excluded.requests)
+"#;
+
+/// The counters of the pending days are copied to the database with this period.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The counters are kept in redis for this duration, thus the previous day can still be flushed after the day change.
+const COUNTER_TTL_SECONDS: i64 = 2 * 24 * 60 * 60;
+
+#[derive(Debug, ThisError)]
+pub enum ApiQuotaManagerBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for ApiQuotaManagerBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_store_usage: StoreUsage,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    redis: RedisConnectionPool,
+    key_prefix: String,
+    store: Store,
+    clock: SharedClock,
+    /// The counters changed since the last flush.
+    pending: Mutex<HashSet<(Uuid, NaiveDate)>>,
+}
+
+/// Count the daily API requests of the identities. The counters are shared by all the instances of the service in
+/// redis and they are copied periodically to the database for the reporting. As the counters are shared, the
+/// instances write the same (or a newer) value, thus the larger one is kept.
+#[derive(Clone)]
+pub struct ApiQuotaManager(Arc<Inner>);
+
+impl ApiQuotaManager {
+    pub async fn new(pool: &DBPool, clock: SharedClock) -> Result<Self, ApiQuotaManagerBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_store_usage = StoreUsage::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_store_usage,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        let manager = Self(Arc::new(Inner {
+            redis: pool.redis.clone(),
+            key_prefix: pool.redis_key_prefix.clone(),
+            store,
+            clock,
+            pending: Mutex::new(HashSet::new()),
+        }));
+
+        let flusher = manager.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(err) = flusher.flush().await {
+                    log::warn!("Failed to store the API usage: {err:?}");
+                }
+            }
+        });
+
+        Ok(manager)
+    }
+
+    fn counter_key(&self, user_id: Uuid, day: NaiveDate) -> String {
+        format!("{}api-quota:{}:{day}", self.0.key_prefix, user_id.as_simple())
+    }
+
+    /// Count a request of the identity and return the number of its requests on the current (UTC) day.
+    pub async fn consume(&self, user_id: Uuid) -> Result<u64, DBError> {
+        let today = self.0.clock.now().date_naive();
+        let mut client = self.0.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let lua_script = r#"
+local count = redis.call('INCR', KEYS[1])
+if count == 1 then
+    redis.call('EXPIRE', KEYS[1], ARGV[1])
+end
+return count
+"#;
+
+        let count: u64 = Script::new(lua_script)
+            .key(self.counter_key(user_id, today))
+            .arg(COUNTER_TTL_SECONDS)
+            .invoke_async(&mut *client)
+            .await
+            .map_err(DBError::RedisError)?;
+
+        self.0.pending.lock().unwrap().insert((user_id, today));
+        Ok(count)
+    }
+
+    /// Copy the changed counters to the database. On failure the counters are kept for the next attempt.
+    pub async fn flush(&self) -> Result<(), DBError> {
+        let pending: Vec<_> = mem::take(&mut *self.0.pending.lock().unwrap()).into_iter().collect();
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let result = self.store(&pending).await;
+        if result.is_err() {
+            self.0.pending.lock().unwrap().extend(pending);
+        }
+        result
+    }
+
+    async fn store(&self, pending: &[(Uuid, NaiveDate)]) -> Result<(), DBError> {
+        let counts: Vec<Option<i64>> = {
+            let mut client = self.0.redis.get().await.map_err(DBError::RedisPoolError)?;
+            let mut pipe = redis::pipe();
+            for (user_id, day) in pending {
+                pipe.get(self.counter_key(*user_id, *day));
+            }
+            pipe.query_async(&mut *client).await.map_err(DBError::RedisError)?
+        };
+        // the counters expired since the last attempt are lost
+        let usages: Vec<(Uuid, NaiveDate, i64)> = pending
+            .iter()
+            .zip(counts)
+            .filter_map(|(&(user_id, day), count)| count.map(|count| (user_id, day, count)))
+            .collect();
+
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let user_ids: Vec<Uuid> = usages.iter().map(|usage| usage.0).collect();
+                let days: Vec<NaiveDate> = usages.iter().map(|usage| usage.1).collect();
+                let requests: Vec<i64> = usages.iter().map(|usage| usage.2).collect();
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_store_usage.get(&client).await?;
+                client.execute(&stmt, &[&user_ids, &days, &requests]).await?;
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        let transaction = conn.transaction()?;
+                        {
+                            let mut stmt = transaction.prepare(SQLITE_STORE_USAGE)?;
+                            for usage in usages {
+                                stmt.execute(usage)?;
+                            }
+                        }
+                        transaction.commit()?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
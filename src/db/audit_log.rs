@@ -0,0 +1,163 @@
+use crate::db::{DBError, DBPool, SqlPool, SqlitePool};
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use serde_json::Value;
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+pg_prepared_statement!( InsertEntry => r#"
+    INSERT INTO audit_log (created, actor_id, action, target_id, details)
+        VALUES (now(), $1, $2, $3, $4)
+"#, [UUID, VARCHAR, UUID, JSONB] );
+
+pg_prepared_statement!( ListTargetEntries => r#"
+    SELECT created, action, details FROM audit_log
+        WHERE target_id = $1 AND action = ANY($2)
+        ORDER BY created DESC
+        LIMIT $3
+"#, [UUID, VARCHAR_ARRAY, INT8] );
+
+const SQLITE_INSERT_ENTRY: &str = r#"
+    INSERT INTO audit_log (created, actor_id, action, target_id, details)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+"#;
+
+// The actions are passed as a json array as there are no array parameters in SQLite
+const SQLITE_LIST_TARGET_ENTRIES: &str = r#"
+    SELECT created, action, details FROM audit_log
+        WHERE target_id = ?1 AND action IN (SELECT value FROM json_each(?2))
+        ORDER BY created DESC
+        LIMIT ?3
+"#;
+
+/// A recorded action affecting a user.
+#[derive(Debug)]
+pub struct AuditEntry {
+    pub created_at: DateTime<Utc>,
+    pub action: String,
+    pub details: Value,
+}
+
+#[derive(Debug, ThisError)]
+pub enum AuditLogBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for AuditLogBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_insert_entry: InsertEntry,
+    stmt_list_target_entries: ListTargetEntries,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+/// Persistent log of the security relevant actions.
+#[derive(Clone)]
+pub struct AuditLog(Arc<Store>);
+
+impl AuditLog {
+    pub async fn new(pool: &DBPool) -> Result<Self, AuditLogBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_insert_entry = InsertEntry::new(&client).await?;
+                let stmt_list_target_entries = ListTargetEntries::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_insert_entry,
+                    stmt_list_target_entries,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(store)))
+    }
+
+    /// Record an action. The actor is the user performing the action, the target is the user affected by it.
+    pub async fn record(
+        &self,
+        actor_id: Option<Uuid>,
+        action: &str,
+        target_id: Option<Uuid>,
+        details: Value,
+    ) -> Result<(), DBError> {
+        match &*self.0 {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_insert_entry.get(&client).await?;
+                client
+                    .execute(&stmt, &[&actor_id, &action, &target_id, &details])
+                    .await?;
+            }
+            Store::Sqlite(sqlite) => {
+                let action = action.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(
+                            SQLITE_INSERT_ENTRY,
+                            params![Utc::now(), actor_id, action, target_id, details],
+                        )?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the latest entries of the given actions affecting a user, the newest first.
+    pub async fn list_target_entries(
+        &self,
+        target_id: Uuid,
+        actions: &[&str],
+        limit: usize,
+    ) -> Result<Vec<AuditEntry>, DBError> {
+        let limit = limit as i64;
+        match &*self.0 {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_list_target_entries.get(&client).await?;
+                let rows = client.query(&stmt, &[&target_id, &actions, &limit]).await?;
+                Ok(rows
+                    .iter()
+                    .map(|row| AuditEntry {
+                        created_at: row.get(0),
+                        action: row.get(1),
+                        details: row.get(2),
+                    })
+                    .collect())
+            }
+            Store::Sqlite(sqlite) => {
+                let actions = serde_json::json!(actions);
+                sqlite
+                    .call(move |conn| -> Result<Vec<AuditEntry>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_LIST_TARGET_ENTRIES)?;
+                        let entries = stmt
+                            .query_map(params![target_id, actions, limit], |row| {
+                                Ok(AuditEntry {
+                                    created_at: row.get(0)?,
+                                    action: row.get(1)?,
+                                    details: row.get(2)?,
+                                })
+                            })?
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(entries)
+                    })
+                    .await
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use crate::db::{DBError, DBPool};
+use redis::AsyncCommands;
+use shine_service::service::RedisConnectionPool;
+
+/// Track the use of the break-glass credentials. A credential can be used only once, it has to be rotated after
+/// the use, thus the used credentials are remembered without an expiration.
+#[derive(Clone)]
+pub struct BreakGlassStore {
+    redis: RedisConnectionPool,
+    key_prefix: String,
+}
+
+impl BreakGlassStore {
+    pub fn new(pool: &DBPool) -> Self {
+        Self {
+            redis: pool.redis.clone(),
+            key_prefix: pool.redis_key_prefix.clone(),
+        }
+    }
+
+    fn used_key(&self, credential_id: &str) -> String {
+        format!("{}break-glass-used:{credential_id}", self.key_prefix)
+    }
+
+    pub async fn is_used(&self, credential_id: &str) -> Result<bool, DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+        let used: bool = client
+            .exists(self.used_key(credential_id))
+            .await
+            .map_err(DBError::RedisError)?;
+        Ok(used)
+    }
+
+    /// Mark a credential as used. Returns false if it has already been used.
+    pub async fn mark_used(&self, credential_id: &str) -> Result<bool, DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+        let marked: bool = client
+            .set_nx(self.used_key(credential_id), chrono::Utc::now().to_rfc3339())
+            .await
+            .map_err(DBError::RedisError)?;
+        Ok(marked)
+    }
+}
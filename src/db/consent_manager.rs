@@ -0,0 +1,208 @@
+use crate::db::{DBError, DBPool, SharedClock, SqlPool, SqlitePool};
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+pg_prepared_statement!( UpsertConsent => r#"
+    INSERT INTO consents (user_id, purpose, granted, version, updated)
+        VALUES ($1, $2, $3, $4, $5)
+    ON CONFLICT (user_id, purpose) DO UPDATE SET granted = $3, version = $4, updated = $5
+"#, [UUID, VARCHAR, BOOL, VARCHAR, TIMESTAMPTZ] );
+
+pg_prepared_statement!( ListConsents => r#"
+    SELECT purpose, granted, version, updated FROM consents WHERE user_id = $1 ORDER BY purpose
+"#, [UUID] );
+
+pg_prepared_statement!( FindConsent => r#"
+    SELECT purpose, granted, version, updated FROM consents WHERE user_id = $1 AND purpose = $2
+"#, [UUID, VARCHAR] );
+
+const SQLITE_UPSERT_CONSENT: &str = r#"
+    INSERT INTO consents (user_id, purpose, granted, version, updated)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+    ON CONFLICT (user_id, purpose) DO UPDATE SET granted = ?3, version = ?4, updated = ?5
+"#;
+
+const SQLITE_LIST_CONSENTS: &str = r#"
+    SELECT purpose, granted, version, updated FROM consents WHERE user_id = ?1 ORDER BY purpose
+"#;
+
+const SQLITE_FIND_CONSENT: &str = r#"
+    SELECT purpose, granted, version, updated FROM consents WHERE user_id = ?1 AND purpose = ?2
+"#;
+
+/// The decision of a user on a data processing purpose.
+#[derive(Debug, Clone)]
+pub struct ConsentInfo {
+    pub purpose: String,
+    pub granted: bool,
+    /// The version of the purpose the decision was made on.
+    pub version: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ConsentInfo {
+    fn from_sqlite_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            purpose: row.get(0)?,
+            granted: row.get(1)?,
+            version: row.get(2)?,
+            updated_at: row.get(3)?,
+        })
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum ConsentBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for ConsentBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_upsert_consent: UpsertConsent,
+    stmt_list_consents: ListConsents,
+    stmt_find_consent: FindConsent,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    clock: SharedClock,
+}
+
+/// Store of the consents of the users on the optional data processing, only the latest decision is kept for
+/// each purpose.
+#[derive(Clone)]
+pub struct ConsentManager(Arc<Inner>);
+
+impl ConsentManager {
+    pub async fn new(pool: &DBPool, clock: SharedClock) -> Result<Self, ConsentBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_upsert_consent = UpsertConsent::new(&client).await?;
+                let stmt_list_consents = ListConsents::new(&client).await?;
+                let stmt_find_consent = FindConsent::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_upsert_consent,
+                    stmt_list_consents,
+                    stmt_find_consent,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(Inner { store, clock })))
+    }
+
+    /// Record the decision of a user on a purpose, the previous decision is replaced.
+    pub async fn set_consent(
+        &self,
+        user_id: Uuid,
+        purpose: &str,
+        granted: bool,
+        version: &str,
+    ) -> Result<ConsentInfo, DBError> {
+        let now = self.0.clock.now();
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_upsert_consent.get(&client).await?;
+                client
+                    .execute(&stmt, &[&user_id, &purpose, &granted, &version, &now])
+                    .await?;
+            }
+            Store::Sqlite(sqlite) => {
+                let purpose = purpose.to_owned();
+                let version = version.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(SQLITE_UPSERT_CONSENT, params![user_id, purpose, granted, version, now])?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+
+        Ok(ConsentInfo {
+            purpose: purpose.to_owned(),
+            granted,
+            version: version.to_owned(),
+            updated_at: now,
+        })
+    }
+
+    pub async fn list_consents(&self, user_id: Uuid) -> Result<Vec<ConsentInfo>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_list_consents.get(&client).await?;
+                let rows = client.query(&stmt, &[&user_id]).await?;
+                Ok(rows
+                    .iter()
+                    .map(|row| ConsentInfo {
+                        purpose: row.get(0),
+                        granted: row.get(1),
+                        version: row.get(2),
+                        updated_at: row.get(3),
+                    })
+                    .collect())
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Vec<ConsentInfo>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_LIST_CONSENTS)?;
+                        let consents = stmt
+                            .query_map(params![user_id], ConsentInfo::from_sqlite_row)?
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(consents)
+                    })
+                    .await
+            }
+        }
+    }
+
+    pub async fn find_consent(&self, user_id: Uuid, purpose: &str) -> Result<Option<ConsentInfo>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_find_consent.get(&client).await?;
+                let row = client.query_opt(&stmt, &[&user_id, &purpose]).await?;
+                Ok(row.map(|row| ConsentInfo {
+                    purpose: row.get(0),
+                    granted: row.get(1),
+                    version: row.get(2),
+                    updated_at: row.get(3),
+                }))
+            }
+            Store::Sqlite(sqlite) => {
+                let purpose = purpose.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<Option<ConsentInfo>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_FIND_CONSENT)?;
+                        let mut rows = stmt.query(params![user_id, purpose])?;
+                        match rows.next()? {
+                            Some(row) => Ok(Some(ConsentInfo::from_sqlite_row(row)?)),
+                            None => Ok(None),
+                        }
+                    })
+                    .await
+            }
+        }
+    }
+}
@@ -1,8 +1,47 @@
+#[cfg(feature = "fault-injection")]
+use crate::db::FaultInjectionConfig;
 use serde::{Deserialize, Serialize};
 
+/// Prefix of the SQLite connection strings, ex. `sqlite://identity.db` or `sqlite::memory:`.
+const SQLITE_PREFIX: &str = "sqlite:";
+
+/// The SQL backend selected by the connection string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SqlBackend {
+    Postgres,
+    /// Path of the database file, or `:memory:` for an in-memory database.
+    Sqlite(String),
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DBConfig {
+    /// Connection string of the SQL database. Postgres is used by default, SQLite is selected by the `sqlite:`
+    /// prefix and it is intended only for the local development and the tests.
     pub sql_cns: String,
     pub redis_cns: String,
+    /// Prefix of the Redis keys of the service, ex. to share a Redis server between the environments. The session
+    /// keys are shared with the session validator of the other services, they are not prefixed.
+    #[serde(default)]
+    pub redis_key_prefix: String,
+    /// Timeout of the SQL queries in seconds, a query running longer is cancelled.
+    #[serde(default = "default_query_timeout")]
+    pub query_timeout: u64,
+
+    #[cfg(feature = "fault-injection")]
+    #[serde(default)]
+    pub fault_injection: Option<FaultInjectionConfig>,
+}
+
+fn default_query_timeout() -> u64 {
+    30
+}
+
+impl DBConfig {
+    pub fn sql_backend(&self) -> SqlBackend {
+        match self.sql_cns.strip_prefix(SQLITE_PREFIX) {
+            Some(path) => SqlBackend::Sqlite(path.strip_prefix("//").unwrap_or(path).to_owned()),
+            None => SqlBackend::Postgres,
+        }
+    }
 }
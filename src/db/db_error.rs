@@ -1,3 +1,5 @@
+#[cfg(feature = "fault-injection")]
+use crate::db::FaultLayer;
 use shine_service::service::{PGConnectionError, RedisConnectionError};
 use thiserror::Error as ThisError;
 
@@ -9,9 +11,22 @@ pub enum DBError {
     PostgresError(#[from] tokio_postgres::Error),
     #[error(transparent)]
     SqlMigration(#[from] refinery::Error),
+    #[error("Data migration failed: {0}")]
+    DataMigration(String),
+    #[error("Query timed out")]
+    Timeout,
+
+    #[error(transparent)]
+    SqliteError(#[from] rusqlite::Error),
+    #[error("SQLite task failed: {0}")]
+    SqliteTaskError(String),
 
     #[error("Failed to get pooled redis connection")]
     RedisPoolError(#[source] RedisConnectionError),
     #[error(transparent)]
     RedisError(#[from] redis::RedisError),
+
+    #[cfg(feature = "fault-injection")]
+    #[error("Injected fault in {0:?}")]
+    InjectedFault(FaultLayer),
 }
@@ -1,37 +1,172 @@
-use crate::db::{DBConfig, DBError};
-use shine_service::service::{self, PGConnectionPool, RedisConnectionPool};
+use crate::db::{
+    normalize_name, DBConfig, DBError, EmailNormalizationConfig, EmailNormalizer, FaultInjector, SqlBackend, SqlitePool,
+};
+use shine_service::service::{self, PGConnectionPool, PGErrorChecks, RedisConnectionPool};
+use std::time::Duration;
+use uuid::Uuid;
 
 mod embedded {
     use refinery::embed_migrations;
     embed_migrations!("./sql_migrations");
 }
 
+mod embedded_sqlite {
+    use refinery::embed_migrations;
+    embed_migrations!("./sql_migrations_sqlite");
+}
+
+/// A data change completed by the service after the schema migrations, as it cannot be expressed in SQL. The
+/// migration creating the column also fills the pending table with the users to update.
+struct Normalization {
+    what: &'static str,
+    list: &'static str,
+    update: &'static str,
+    clear: &'static str,
+    index: &'static str,
+}
+
+const NAME_NORMALIZATION: Normalization = Normalization {
+    what: "names",
+    list: r#"
+        SELECT i.user_id, i.name FROM identities i
+            JOIN pending_name_normalizations p ON p.user_id = i.user_id
+    "#,
+    update: "UPDATE identities SET normalized_name = $2 WHERE user_id = $1",
+    clear: "DELETE FROM pending_name_normalizations",
+    index: "idx_name",
+};
+
+const EMAIL_NORMALIZATION: Normalization = Normalization {
+    what: "emails",
+    list: r#"
+        SELECT i.user_id, i.email FROM identities i
+            JOIN pending_email_normalizations p ON p.user_id = i.user_id
+            WHERE i.email IS NOT NULL
+    "#,
+    update: "UPDATE identities SET normalized_email = $2 WHERE user_id = $1",
+    clear: "DELETE FROM pending_email_normalizations",
+    index: "idx_email",
+};
+
+#[derive(Clone)]
+pub enum SqlPool {
+    Postgres(PGConnectionPool),
+    Sqlite(SqlitePool),
+}
+
 #[derive(Clone)]
 pub struct DBPool {
-    pub postgres: PGConnectionPool,
+    pub sql: SqlPool,
     pub redis: RedisConnectionPool,
+    pub redis_key_prefix: String,
+    pub faults: FaultInjector,
+    pub query_timeout: Duration,
 }
 
 impl DBPool {
-    pub async fn new(config: &DBConfig) -> Result<Self, DBError> {
-        let postgres = service::create_postgres_pool(config.sql_cns.as_str())
-            .await
-            .map_err(DBError::PostgresPoolError)?;
+    pub async fn new(config: &DBConfig, email_config: &EmailNormalizationConfig) -> Result<Self, DBError> {
+        let sql = match config.sql_backend() {
+            SqlBackend::Postgres => SqlPool::Postgres(
+                service::create_postgres_pool(config.sql_cns.as_str())
+                    .await
+                    .map_err(DBError::PostgresPoolError)?,
+            ),
+            SqlBackend::Sqlite(path) => {
+                log::warn!("Using SQLite database ({path}), it is not intended for production");
+                SqlPool::Sqlite(SqlitePool::open(&path)?)
+            }
+        };
 
         let redis = service::create_redis_pool(config.redis_cns.as_str())
             .await
             .map_err(DBError::RedisPoolError)?;
 
-        let pool = Self { postgres, redis };
-        pool.migrate().await?;
+        #[cfg(feature = "fault-injection")]
+        let faults = FaultInjector::new(config.fault_injection.as_ref());
+        #[cfg(not(feature = "fault-injection"))]
+        let faults = FaultInjector::new(None);
+
+        let pool = Self {
+            sql,
+            redis,
+            redis_key_prefix: config.redis_key_prefix.clone(),
+            faults,
+            query_timeout: Duration::from_secs(config.query_timeout),
+        };
+        pool.migrate(&EmailNormalizer::new(email_config)).await?;
         Ok(pool)
     }
 
-    async fn migrate(&self) -> Result<(), DBError> {
-        let mut backend = self.postgres.get().await.map_err(DBError::PostgresPoolError)?;
-        log::info!("migrations: {:#?}", embedded::migrations::runner().get_migrations());
-        let client = &mut **backend;
-        embedded::migrations::runner().run_async(client).await?;
+    async fn migrate(&self, email_normalizer: &EmailNormalizer) -> Result<(), DBError> {
+        match &self.sql {
+            SqlPool::Postgres(postgres) => {
+                let mut backend = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                log::info!("migrations: {:#?}", embedded::migrations::runner().get_migrations());
+                let client = &mut **backend;
+                embedded::migrations::runner().run_async(client).await?;
+                Self::normalize(client, &NAME_NORMALIZATION, normalize_name).await?;
+                Self::normalize(client, &EMAIL_NORMALIZATION, |email| email_normalizer.normalize(email)).await?;
+            }
+            SqlPool::Sqlite(sqlite) => {
+                sqlite
+                    .call(|connection| -> Result<(), DBError> {
+                        log::info!(
+                            "migrations: {:#?}",
+                            embedded_sqlite::migrations::runner().get_migrations()
+                        );
+                        embedded_sqlite::migrations::runner().run(connection)?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Complete a normalization started by the migrations. The rows are updated in a single transaction and any
+    /// conflict fails the startup, the users have to be fixed manually before the service can start.
+    async fn normalize<F>(
+        client: &mut tokio_postgres::Client,
+        normalization: &Normalization,
+        normalize: F,
+    ) -> Result<(), DBError>
+    where
+        F: Fn(&str) -> String,
+    {
+        let mut transaction = client.transaction().await?;
+        let rows = transaction.query(normalization.list, &[]).await?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        log::info!("Normalizing {} {}", rows.len(), normalization.what);
+        let mut conflicts = Vec::new();
+        for row in rows {
+            let user_id: Uuid = row.get(0);
+            let value: String = row.get(1);
+            let normalized = normalize(&value);
+
+            let savepoint = transaction.savepoint("normalize").await?;
+            match savepoint.execute(normalization.update, &[&user_id, &normalized]).await {
+                Ok(_) => savepoint.commit().await?,
+                Err(err) if err.is_constraint("identities", normalization.index) => {
+                    savepoint.rollback().await?;
+                    conflicts.push(user_id);
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        if !conflicts.is_empty() {
+            let users = conflicts.iter().map(Uuid::to_string).collect::<Vec<_>>().join(", ");
+            return Err(DBError::DataMigration(format!(
+                "Normalized {} are not unique, fix the users: {users}",
+                normalization.what
+            )));
+        }
+
+        transaction.execute(normalization.clear, &[]).await?;
+        transaction.commit().await?;
         Ok(())
     }
 }
@@ -0,0 +1,561 @@
+use crate::{
+    db::{
+        AuditLog, DBError, DBPool, FindIdentity, Identity, IdentityError, IdentityKind, SharedClock,
+        SharedIdentityStore, SqlPool, SqlitePool, StudioManager, UserChange, UserInvalidation,
+    },
+    error_reporting::ErrorReporting,
+};
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+/// What is left of a deleted account.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeletionMode {
+    /// The account is deleted with all the related data.
+    #[default]
+    Delete,
+    /// The personal data and the login methods are removed, but the user id is kept for the references of the
+    /// other services.
+    Anonymize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletionConfig {
+    #[serde(default)]
+    pub mode: DeletionMode,
+    /// The time in seconds the deleted accounts are kept and can be restored by a login. When zero, the accounts
+    /// are deleted immediately.
+    #[serde(default = "DeletionConfig::default_grace_period")]
+    pub grace_period: u64,
+    /// The period of the purge of the accounts with an expired grace period in seconds.
+    #[serde(default = "DeletionConfig::default_purge_interval")]
+    pub purge_interval: u64,
+    /// The maximum age of the session in seconds to delete the account, with an older session the user has to
+    /// log in again.
+    #[serde(default = "DeletionConfig::default_reauth_window")]
+    pub reauth_window: u64,
+}
+
+impl DeletionConfig {
+    fn default_grace_period() -> u64 {
+        30 * 24 * 60 * 60
+    }
+
+    fn default_purge_interval() -> u64 {
+        3600
+    }
+
+    fn default_reauth_window() -> u64 {
+        300
+    }
+
+    pub fn grace_period(&self) -> Duration {
+        Duration::seconds(self.grace_period as i64)
+    }
+}
+
+impl Default for DeletionConfig {
+    fn default() -> Self {
+        Self {
+            mode: DeletionMode::default(),
+            grace_period: Self::default_grace_period(),
+            purge_interval: Self::default_purge_interval(),
+            reauth_window: Self::default_reauth_window(),
+        }
+    }
+}
+
+/// The number of accounts purged in a batch.
+const PURGE_BATCH_SIZE: i64 = 100;
+
+pg_prepared_statement!( ScheduleDeletion => r#"
+    INSERT INTO identity_deletions (user_id, requested, purge_after)
+        VALUES ($1, $2, $3)
+    ON CONFLICT (user_id) DO UPDATE SET requested = identity_deletions.requested
+    RETURNING purge_after
+"#, [UUID, TIMESTAMPTZ, TIMESTAMPTZ] );
+
+pg_prepared_statement!( CancelDeletion => r#"
+    DELETE FROM identity_deletions WHERE user_id = $1
+"#, [UUID] );
+
+pg_prepared_statement!( ListDueDeletions => r#"
+    SELECT d.user_id, EXISTS(SELECT 1 FROM legal_holds h WHERE h.user_id = d.user_id)
+        FROM identity_deletions d
+        WHERE d.purge_after <= $1
+        ORDER BY d.purge_after
+        LIMIT $2
+"#, [TIMESTAMPTZ, INT8] );
+
+pg_prepared_statement!( PostponeDeletion => r#"
+    UPDATE identity_deletions SET purge_after = $2 WHERE user_id = $1
+"#, [UUID, TIMESTAMPTZ] );
+
+pg_prepared_statement!( PlaceLegalHold => r#"
+    INSERT INTO legal_holds (user_id, reason, placed, placed_by)
+        VALUES ($1, $2, $3, $4)
+    ON CONFLICT (user_id) DO UPDATE SET reason = $2, placed = $3, placed_by = $4
+"#, [UUID, TEXT, TIMESTAMPTZ, UUID] );
+
+pg_prepared_statement!( ReleaseLegalHold => r#"
+    DELETE FROM legal_holds WHERE user_id = $1
+"#, [UUID] );
+
+pg_prepared_statement!( FindLegalHold => r#"
+    SELECT reason, placed, placed_by FROM legal_holds WHERE user_id = $1
+"#, [UUID] );
+
+const SQLITE_SCHEDULE_DELETION: &str = r#"
+    INSERT INTO identity_deletions (user_id, requested, purge_after)
+        VALUES (?1, ?2, ?3)
+    ON CONFLICT (user_id) DO UPDATE SET requested = identity_deletions.requested
+    RETURNING purge_after
+"#;
+
+const SQLITE_CANCEL_DELETION: &str = r#"
+    DELETE FROM identity_deletions WHERE user_id = ?1
+"#;
+
+const SQLITE_LIST_DUE_DELETIONS: &str = r#"
+    SELECT d.user_id, EXISTS(SELECT 1 FROM legal_holds h WHERE h.user_id = d.user_id)
+        FROM identity_deletions d
+        WHERE d.purge_after <= ?1
+        ORDER BY d.purge_after
+        LIMIT ?2
+"#;
+
+const SQLITE_POSTPONE_DELETION: &str = r#"
+    UPDATE identity_deletions SET purge_after = ?2 WHERE user_id = ?1
+"#;
+
+const SQLITE_PLACE_LEGAL_HOLD: &str = r#"
+    INSERT INTO legal_holds (user_id, reason, placed, placed_by)
+        VALUES (?1, ?2, ?3, ?4)
+    ON CONFLICT (user_id) DO UPDATE SET reason = ?2, placed = ?3, placed_by = ?4
+"#;
+
+const SQLITE_RELEASE_LEGAL_HOLD: &str = r#"
+    DELETE FROM legal_holds WHERE user_id = ?1
+"#;
+
+const SQLITE_FIND_LEGAL_HOLD: &str = r#"
+    SELECT reason, placed, placed_by FROM legal_holds WHERE user_id = ?1
+"#;
+
+/// A marker of an account that shall not be deleted.
+#[derive(Debug)]
+pub struct LegalHoldInfo {
+    pub reason: String,
+    pub placed_at: DateTime<Utc>,
+    /// The user who placed the hold.
+    pub placed_by: Option<Uuid>,
+}
+
+#[derive(Debug, ThisError)]
+pub enum DeletionError {
+    #[error("Account is under legal hold")]
+    LegalHold,
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for DeletionError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum DeletionBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for DeletionBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_schedule_deletion: ScheduleDeletion,
+    stmt_cancel_deletion: CancelDeletion,
+    stmt_list_due_deletions: ListDueDeletions,
+    stmt_postpone_deletion: PostponeDeletion,
+    stmt_place_legal_hold: PlaceLegalHold,
+    stmt_release_legal_hold: ReleaseLegalHold,
+    stmt_find_legal_hold: FindLegalHold,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    identity_manager: SharedIdentityStore,
+    studio_manager: StudioManager,
+    user_invalidation: UserInvalidation,
+    audit_log: AuditLog,
+    clock: SharedClock,
+    mode: DeletionMode,
+    grace_period: Duration,
+    reauth_window: Duration,
+}
+
+/// The two-phase deletion of the accounts. The deleted accounts are only marked for a grace period, a login
+/// restores them, and a background job purges them after the grace period. The accounts under legal hold are
+/// not deleted. The studios are deleted the same way, but they are restored by their owners.
+#[derive(Clone)]
+pub struct DeletionManager(Arc<Inner>);
+
+impl DeletionManager {
+    pub async fn new(
+        pool: &DBPool,
+        identity_manager: SharedIdentityStore,
+        studio_manager: StudioManager,
+        user_invalidation: UserInvalidation,
+        audit_log: AuditLog,
+        error_reporting: ErrorReporting,
+        clock: SharedClock,
+        config: &DeletionConfig,
+    ) -> Result<Self, DeletionBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_schedule_deletion = ScheduleDeletion::new(&client).await?;
+                let stmt_cancel_deletion = CancelDeletion::new(&client).await?;
+                let stmt_list_due_deletions = ListDueDeletions::new(&client).await?;
+                let stmt_postpone_deletion = PostponeDeletion::new(&client).await?;
+                let stmt_place_legal_hold = PlaceLegalHold::new(&client).await?;
+                let stmt_release_legal_hold = ReleaseLegalHold::new(&client).await?;
+                let stmt_find_legal_hold = FindLegalHold::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_schedule_deletion,
+                    stmt_cancel_deletion,
+                    stmt_list_due_deletions,
+                    stmt_postpone_deletion,
+                    stmt_place_legal_hold,
+                    stmt_release_legal_hold,
+                    stmt_find_legal_hold,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        let manager = Self(Arc::new(Inner {
+            store,
+            identity_manager,
+            studio_manager,
+            user_invalidation,
+            audit_log,
+            clock,
+            mode: config.mode,
+            grace_period: config.grace_period(),
+            reauth_window: Duration::seconds(config.reauth_window as i64),
+        }));
+
+        if config.grace_period > 0 {
+            let purger = manager.clone();
+            let purge_interval = std::time::Duration::from_secs(config.purge_interval);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(purge_interval);
+                loop {
+                    interval.tick().await;
+                    match purger.purge().await {
+                        Ok(0) => {}
+                        Ok(count) => log::info!("Purged {count} deleted accounts"),
+                        Err(err) => {
+                            log::warn!("Failed to purge the deleted accounts: {err:?}");
+                            error_reporting.report("db.purge", &format!("{err:?}"), &[]);
+                        }
+                    }
+                }
+            });
+        }
+
+        Ok(manager)
+    }
+
+    /// The maximum age of a session that can delete the account.
+    pub fn reauth_window(&self) -> Duration {
+        self.0.reauth_window
+    }
+
+    /// Delete an account. With a grace period the account is only marked for deletion and the time of the purge
+    /// is returned, otherwise it is deleted immediately and None is returned. The deletion of an account under
+    /// legal hold is refused and recorded in the audit log. The configured grace period can be shortened, ex. by a
+    /// regional policy.
+    pub async fn delete(
+        &self,
+        user_id: Uuid,
+        max_grace_period: Option<Duration>,
+    ) -> Result<Option<DateTime<Utc>>, DeletionError> {
+        let inner = &*self.0;
+        if let Some(hold) = self.find_legal_hold(user_id).await? {
+            inner
+                .audit_log
+                .record(
+                    Some(user_id),
+                    "legal_hold.delete_refused",
+                    Some(user_id),
+                    json!({ "reason": hold.reason }),
+                )
+                .await?;
+            return Err(DeletionError::LegalHold);
+        }
+
+        let grace_period = match max_grace_period {
+            Some(max_grace_period) => inner.grace_period.min(max_grace_period),
+            None => inner.grace_period,
+        };
+        if grace_period <= Duration::zero() {
+            self.purge_user(user_id).await?;
+            return Ok(None);
+        }
+
+        let now = inner.clock.now();
+        let purge_after = now + grace_period;
+        let purge_after = match &inner.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_schedule_deletion.get(&client).await?;
+                let row = client.query_one(&stmt, &[&user_id, &now, &purge_after]).await?;
+                row.get(0)
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<DateTime<Utc>, DBError> {
+                        Ok(
+                            conn.query_row(SQLITE_SCHEDULE_DELETION, params![user_id, now, purge_after], |row| {
+                                row.get(0)
+                            })?,
+                        )
+                    })
+                    .await?
+            }
+        };
+        Ok(Some(purge_after))
+    }
+
+    /// Cancel the pending deletion of an account. Returns false if the account was not marked for deletion.
+    pub async fn restore(&self, user_id: Uuid) -> Result<bool, DBError> {
+        let count = match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_cancel_deletion.get(&client).await?;
+                client.execute(&stmt, &[&user_id]).await? as usize
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<usize, DBError> {
+                        Ok(conn.execute(SQLITE_CANCEL_DELETION, params![user_id])?)
+                    })
+                    .await?
+            }
+        };
+        Ok(count > 0)
+    }
+
+    /// Delete the accounts with an expired grace period and return the number of the deleted accounts. The
+    /// failed deletions are retried by the next purge. The accounts under legal hold are skipped and their purge is
+    /// postponed by another grace period.
+    pub async fn purge(&self) -> Result<usize, DBError> {
+        let now = self.0.clock.now();
+        let due: Vec<(Uuid, bool)> = match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_list_due_deletions.get(&client).await?;
+                let rows = client.query(&stmt, &[&now, &PURGE_BATCH_SIZE]).await?;
+                rows.iter().map(|row| (row.get(0), row.get(1))).collect()
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Vec<(Uuid, bool)>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_LIST_DUE_DELETIONS)?;
+                        let due = stmt
+                            .query_map(params![now, PURGE_BATCH_SIZE], |row| Ok((row.get(0)?, row.get(1)?)))?
+                            .collect::<Result<Vec<(Uuid, bool)>, _>>()?;
+                        Ok(due)
+                    })
+                    .await?
+            }
+        };
+
+        let mut count = 0;
+        for (user_id, is_held) in due {
+            if is_held {
+                if let Err(err) = self.postpone_held(user_id).await {
+                    log::warn!("Failed to postpone the purge of the held account {user_id}: {err}");
+                }
+                continue;
+            }
+            match self.purge_user(user_id).await {
+                Ok(()) => count += 1,
+                Err(err) => log::warn!("Failed to purge the deleted account {user_id}: {err}"),
+            }
+        }
+        Ok(count)
+    }
+
+    async fn postpone_held(&self, user_id: Uuid) -> Result<(), DBError> {
+        let inner = &*self.0;
+        let purge_after = inner.clock.now() + inner.grace_period;
+        match &inner.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_postpone_deletion.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &purge_after]).await?;
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(SQLITE_POSTPONE_DELETION, params![user_id, purge_after])?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        inner
+            .audit_log
+            .record(
+                None,
+                "legal_hold.purge_skipped",
+                Some(user_id),
+                json!({ "purgeAfter": purge_after }),
+            )
+            .await
+    }
+
+    /// Place a legal hold on an account, an existing hold is updated.
+    pub async fn place_legal_hold(&self, user_id: Uuid, reason: &str, placed_by: Option<Uuid>) -> Result<(), DBError> {
+        let now = self.0.clock.now();
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_place_legal_hold.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &reason, &now, &placed_by]).await?;
+            }
+            Store::Sqlite(sqlite) => {
+                let reason = reason.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(SQLITE_PLACE_LEGAL_HOLD, params![user_id, reason, now, placed_by])?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Release the legal hold of an account. Returns false if the account was not held.
+    pub async fn release_legal_hold(&self, user_id: Uuid) -> Result<bool, DBError> {
+        let count = match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_release_legal_hold.get(&client).await?;
+                client.execute(&stmt, &[&user_id]).await? as usize
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<usize, DBError> {
+                        Ok(conn.execute(SQLITE_RELEASE_LEGAL_HOLD, params![user_id])?)
+                    })
+                    .await?
+            }
+        };
+        Ok(count > 0)
+    }
+
+    pub async fn find_legal_hold(&self, user_id: Uuid) -> Result<Option<LegalHoldInfo>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_find_legal_hold.get(&client).await?;
+                let row = client.query_opt(&stmt, &[&user_id]).await?;
+                Ok(row.map(|row| LegalHoldInfo {
+                    reason: row.get(0),
+                    placed_at: row.get(1),
+                    placed_by: row.get(2),
+                }))
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Option<LegalHoldInfo>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_FIND_LEGAL_HOLD)?;
+                        let mut rows = stmt.query(params![user_id])?;
+                        match rows.next()? {
+                            Some(row) => Ok(Some(LegalHoldInfo {
+                                reason: row.get(0)?,
+                                placed_at: row.get(1)?,
+                                placed_by: row.get(2)?,
+                            })),
+                            None => Ok(None),
+                        }
+                    })
+                    .await
+            }
+        }
+    }
+
+    async fn purge_user(&self, user_id: Uuid) -> Result<(), IdentityError> {
+        let identity = self.0.identity_manager.find(FindIdentity::UserId(user_id)).await?;
+        if matches!(
+            identity,
+            Some(Identity {
+                kind: IdentityKind::Studio,
+                ..
+            })
+        ) {
+            return self.purge_studio(user_id).await;
+        }
+
+        let change = match self.0.mode {
+            DeletionMode::Delete => {
+                self.0.identity_manager.cascaded_delete(user_id).await?;
+                UserChange::Deleted
+            }
+            DeletionMode::Anonymize => {
+                let placeholder_name = format!("deleted-{}", user_id.as_simple());
+                self.0.identity_manager.anonymize(user_id, &placeholder_name).await?;
+                UserChange::Anonymized
+            }
+        };
+        if let Err(err) = self.0.user_invalidation.invalidate(user_id, change).await {
+            log::error!("Failed to publish the deletion of {user_id}: {err}");
+        }
+        Ok(())
+    }
+
+    /// A studio has no personal data, thus it is always deleted. The members are removed first, thus a failed
+    /// deletion is retried by the next purge without leaving members of a deleted studio behind.
+    async fn purge_studio(&self, studio_id: Uuid) -> Result<(), IdentityError> {
+        let members = self.0.studio_manager.remove_members(studio_id).await?;
+        self.0.identity_manager.cascaded_delete(studio_id).await?;
+
+        let user_invalidation = &self.0.user_invalidation;
+        if let Err(err) = user_invalidation.invalidate(studio_id, UserChange::Deleted).await {
+            log::error!("Failed to publish the deletion of the studio {studio_id}: {err}");
+        }
+        for member_id in members {
+            if let Err(err) = user_invalidation.invalidate(member_id, UserChange::Studios).await {
+                log::error!("Failed to publish the studio change of {member_id}: {err}");
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+use crate::db::{
+    DBError, ExternalLoginInfo, FindIdentity, IdentityError, IdentityStore, PasswordError, PasswordManager,
+    RoleManager, TokenKind, TokenMeta, DEFAULT_TENANT_ID,
+};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+fn default_tenant_id() -> String {
+    DEFAULT_TENANT_ID.to_owned()
+}
+
+fn default_token_duration() -> u64 {
+    30 * 24 * 60 * 60
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeedProvider {
+    pub provider: String,
+    pub provider_id: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeedToken {
+    /// The raw token, it can be set as the `tid` cookie to login without any provider.
+    pub token: String,
+    pub name: Option<String>,
+    /// Validity of the token in seconds.
+    #[serde(default = "default_token_duration")]
+    pub duration: u64,
+}
+
+impl SeedToken {
+    pub fn duration(&self) -> Duration {
+        Duration::seconds(self.duration as i64)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeedIdentity {
+    pub user_id: Uuid,
+    pub name: String,
+    pub email: Option<String>,
+    #[serde(default = "default_tenant_id")]
+    pub tenant_id: String,
+    pub password: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub providers: Vec<SeedProvider>,
+    #[serde(default)]
+    pub tokens: Vec<SeedToken>,
+}
+
+/// Fake data created at startup for the development environments. Never set it in production, the
+/// passwords and tokens are stored in plain text in the configuration.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DevSeedConfig {
+    #[serde(default)]
+    pub identities: Vec<SeedIdentity>,
+}
+
+#[derive(Debug, ThisError)]
+pub enum DevSeedError {
+    #[error("Failed to seed {0}: {1}")]
+    Identity(Uuid, #[source] IdentityError),
+    #[error(transparent)]
+    PasswordError(#[from] PasswordError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+/// Populate the database with the identities of the development seed. Identities already in the database are
+/// skipped, thus the seeding can be repeated on each start.
+pub struct DevSeeder<'a> {
+    identity_manager: &'a dyn IdentityStore,
+    password_manager: &'a PasswordManager,
+    role_manager: &'a RoleManager,
+}
+
+impl<'a> DevSeeder<'a> {
+    pub fn new(
+        identity_manager: &'a dyn IdentityStore,
+        password_manager: &'a PasswordManager,
+        role_manager: &'a RoleManager,
+    ) -> Self {
+        Self {
+            identity_manager,
+            password_manager,
+            role_manager,
+        }
+    }
+
+    pub async fn seed(&self, config: &DevSeedConfig) -> Result<(), DevSeedError> {
+        log::warn!("Seeding development data, this must not be enabled in production");
+
+        for seed in &config.identities {
+            if self
+                .identity_manager
+                .find(FindIdentity::UserId(seed.user_id))
+                .await
+                .map_err(|err| DevSeedError::Identity(seed.user_id, err))?
+                .is_some()
+            {
+                log::debug!("Seed identity {} ({}) already exists", seed.user_id, seed.name);
+                continue;
+            }
+
+            self.seed_identity(seed)
+                .await
+                .map_err(|err| DevSeedError::Identity(seed.user_id, err))?;
+            if let Some(password) = &seed.password {
+                self.password_manager.set_password(seed.user_id, password).await?;
+            }
+            for role in &seed.roles {
+                self.role_manager.grant_role(seed.user_id, role).await?;
+            }
+            log::info!("Seed identity {} ({}) created", seed.user_id, seed.name);
+        }
+
+        Ok(())
+    }
+
+    async fn seed_identity(&self, seed: &SeedIdentity) -> Result<(), IdentityError> {
+        self.identity_manager
+            .create_user(&seed.tenant_id, seed.user_id, &seed.name, seed.email.as_deref(), None)
+            .await?;
+
+        for provider in &seed.providers {
+            let external_login = ExternalLoginInfo {
+                tenant_id: seed.tenant_id.clone(),
+                provider: provider.provider.clone(),
+                provider_id: provider.provider_id.clone(),
+            };
+            self.identity_manager.link_user(seed.user_id, &external_login).await?;
+        }
+
+        for token in &seed.tokens {
+            let meta = TokenMeta {
+                kind: TokenKind::Persistent,
+                name: token.name.as_deref(),
+                creation_ip: None,
+                user_agent: None,
+            };
+            self.identity_manager
+                .create_token(seed.user_id, &token.token, Utc::now() + token.duration(), &meta)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
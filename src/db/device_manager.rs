@@ -0,0 +1,389 @@
+use crate::db::{DBError, DBPool, SqlPool, SqlitePool};
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+/// Result of a login compared to the previous logins of the user.
+#[derive(Debug)]
+pub struct DeviceCheck {
+    /// This is the first recorded login of the user.
+    pub is_first_login: bool,
+    pub is_new_device: bool,
+    pub is_new_country: bool,
+}
+
+impl DeviceCheck {
+    fn new(has_login: bool, is_known_device: bool, is_known_country: bool, country: &str) -> Self {
+        Self {
+            is_first_login: !has_login,
+            is_new_device: has_login && !is_known_device,
+            is_new_country: has_login && !country.is_empty() && !is_known_country,
+        }
+    }
+}
+
+/// A browser allowed to skip the second factor.
+#[derive(Debug)]
+pub struct TrustedDeviceInfo {
+    pub device_id: Uuid,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expire_at: DateTime<Utc>,
+}
+
+pg_prepared_statement!( CheckDevice => r#"
+    SELECT count(*) > 0,
+           coalesce(bool_or(fingerprint = $2), false),
+           coalesce(bool_or(country = $3), false)
+        FROM known_devices
+        WHERE user_id = $1
+"#, [UUID, VARCHAR, VARCHAR] );
+
+pg_prepared_statement!( UpsertDevice => r#"
+    INSERT INTO known_devices (user_id, fingerprint, country, first_seen, last_seen)
+        VALUES ($1, $2, $3, now(), now())
+    ON CONFLICT (user_id, fingerprint, country) DO UPDATE SET last_seen = now()
+"#, [UUID, VARCHAR, VARCHAR] );
+
+pg_prepared_statement!( InsertTrustedDevice => r#"
+    INSERT INTO trusted_devices (user_id, device_id, user_agent, created, expire)
+        VALUES ($1, $2, $3, now(), $4)
+"#, [UUID, UUID, VARCHAR, TIMESTAMPTZ] );
+
+pg_prepared_statement!( FindTrustedDevice => r#"
+    SELECT count(*) > 0 FROM trusted_devices
+        WHERE user_id = $1 AND device_id = $2 AND expire > $3
+"#, [UUID, UUID, TIMESTAMPTZ] );
+
+pg_prepared_statement!( ListTrustedDevices => r#"
+    SELECT device_id, user_agent, created, expire FROM trusted_devices
+        WHERE user_id = $1 AND expire > $2
+        ORDER BY created
+"#, [UUID, TIMESTAMPTZ] );
+
+pg_prepared_statement!( DeleteTrustedDevice => r#"
+    DELETE FROM trusted_devices WHERE user_id = $1 AND device_id = $2
+"#, [UUID, UUID] );
+
+pg_prepared_statement!( DeleteTrustedDevices => r#"
+    DELETE FROM trusted_devices WHERE user_id = $1
+"#, [UUID] );
+
+const SQLITE_CHECK_DEVICE: &str = r#"
+    SELECT count(*) > 0,
+           coalesce(max(fingerprint = ?2), 0),
+           coalesce(max(country = ?3), 0)
+        FROM known_devices
+        WHERE user_id = ?1
+"#;
+
+const SQLITE_UPSERT_DEVICE: &str = r#"
+    INSERT INTO known_devices (user_id, fingerprint, country, first_seen, last_seen)
+        VALUES (?1, ?2, ?3, ?4, ?4)
+    ON CONFLICT (user_id, fingerprint, country) DO UPDATE SET last_seen = ?4
+"#;
+
+const SQLITE_INSERT_TRUSTED_DEVICE: &str = r#"
+    INSERT INTO trusted_devices (user_id, device_id, user_agent, created, expire)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+"#;
+
+const SQLITE_FIND_TRUSTED_DEVICE: &str = r#"
+    SELECT count(*) > 0 FROM trusted_devices
+        WHERE user_id = ?1 AND device_id = ?2 AND expire > ?3
+"#;
+
+const SQLITE_LIST_TRUSTED_DEVICES: &str = r#"
+    SELECT device_id, user_agent, created, expire FROM trusted_devices
+        WHERE user_id = ?1 AND expire > ?2
+        ORDER BY created
+"#;
+
+const SQLITE_DELETE_TRUSTED_DEVICE: &str = r#"
+    DELETE FROM trusted_devices WHERE user_id = ?1 AND device_id = ?2
+"#;
+
+const SQLITE_DELETE_TRUSTED_DEVICES: &str = r#"
+    DELETE FROM trusted_devices WHERE user_id = ?1
+"#;
+
+#[derive(Debug, ThisError)]
+pub enum DeviceBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for DeviceBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_check_device: CheckDevice,
+    stmt_upsert_device: UpsertDevice,
+    stmt_insert_trusted_device: InsertTrustedDevice,
+    stmt_find_trusted_device: FindTrustedDevice,
+    stmt_list_trusted_devices: ListTrustedDevices,
+    stmt_delete_trusted_device: DeleteTrustedDevice,
+    stmt_delete_trusted_devices: DeleteTrustedDevices,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+/// Keep track of the devices and countries the users have logged in from.
+#[derive(Clone)]
+pub struct DeviceManager(Arc<Store>);
+
+impl DeviceManager {
+    pub async fn new(pool: &DBPool) -> Result<Self, DeviceBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_check_device = CheckDevice::new(&client).await?;
+                let stmt_upsert_device = UpsertDevice::new(&client).await?;
+                let stmt_insert_trusted_device = InsertTrustedDevice::new(&client).await?;
+                let stmt_find_trusted_device = FindTrustedDevice::new(&client).await?;
+                let stmt_list_trusted_devices = ListTrustedDevices::new(&client).await?;
+                let stmt_delete_trusted_device = DeleteTrustedDevice::new(&client).await?;
+                let stmt_delete_trusted_devices = DeleteTrustedDevices::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_check_device,
+                    stmt_upsert_device,
+                    stmt_insert_trusted_device,
+                    stmt_find_trusted_device,
+                    stmt_list_trusted_devices,
+                    stmt_delete_trusted_device,
+                    stmt_delete_trusted_devices,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(store)))
+    }
+
+    /// Compare a login from the given device and country to the previous logins without recording it.
+    pub async fn check_device(
+        &self,
+        user_id: Uuid,
+        fingerprint: &str,
+        country: Option<&str>,
+    ) -> Result<DeviceCheck, DBError> {
+        let country = country.unwrap_or_default();
+
+        match &*self.0 {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_check_device = pg.stmt_check_device.get(&client).await?;
+
+                let row = client
+                    .query_one(&stmt_check_device, &[&user_id, &fingerprint, &country])
+                    .await?;
+                Ok(DeviceCheck::new(row.get(0), row.get(1), row.get(2), country))
+            }
+            Store::Sqlite(sqlite) => {
+                let fingerprint = fingerprint.to_owned();
+                let country = country.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<DeviceCheck, DBError> {
+                        let (has_login, is_known_device, is_known_country): (bool, bool, bool) =
+                            conn.query_row(SQLITE_CHECK_DEVICE, params![user_id, fingerprint, country], |row| {
+                                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                            })?;
+                        Ok(DeviceCheck::new(has_login, is_known_device, is_known_country, &country))
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Record a login from the given device and country. When the country is not known,
+    /// it is never reported as a new country.
+    pub async fn record_login(
+        &self,
+        user_id: Uuid,
+        fingerprint: &str,
+        country: Option<&str>,
+    ) -> Result<DeviceCheck, DBError> {
+        let country = country.unwrap_or_default();
+
+        match &*self.0 {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_check_device = pg.stmt_check_device.get(&client).await?;
+                let stmt_upsert_device = pg.stmt_upsert_device.get(&client).await?;
+
+                let row = client
+                    .query_one(&stmt_check_device, &[&user_id, &fingerprint, &country])
+                    .await?;
+                let has_login: bool = row.get(0);
+                let is_known_device: bool = row.get(1);
+                let is_known_country: bool = row.get(2);
+
+                client
+                    .execute(&stmt_upsert_device, &[&user_id, &fingerprint, &country])
+                    .await?;
+
+                Ok(DeviceCheck::new(has_login, is_known_device, is_known_country, country))
+            }
+            Store::Sqlite(sqlite) => {
+                let fingerprint = fingerprint.to_owned();
+                let country = country.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<DeviceCheck, DBError> {
+                        let (has_login, is_known_device, is_known_country): (bool, bool, bool) =
+                            conn.query_row(SQLITE_CHECK_DEVICE, params![user_id, fingerprint, country], |row| {
+                                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                            })?;
+                        conn.execute(SQLITE_UPSERT_DEVICE, params![user_id, fingerprint, country, Utc::now()])?;
+                        Ok(DeviceCheck::new(has_login, is_known_device, is_known_country, &country))
+                    })
+                    .await
+            }
+        }
+    }
+    /// Allow the browser of the given device id to skip the second factor until it expires.
+    pub async fn trust_device(
+        &self,
+        user_id: Uuid,
+        device_id: Uuid,
+        user_agent: Option<&str>,
+        expire_at: DateTime<Utc>,
+    ) -> Result<(), DBError> {
+        match &*self.0 {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_insert_trusted_device.get(&client).await?;
+                client
+                    .execute(&stmt, &[&user_id, &device_id, &user_agent, &expire_at])
+                    .await?;
+            }
+            Store::Sqlite(sqlite) => {
+                let user_agent = user_agent.map(str::to_owned);
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(
+                            SQLITE_INSERT_TRUSTED_DEVICE,
+                            params![user_id, device_id, user_agent, Utc::now(), expire_at],
+                        )?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if a device is trusted by the user and it has not expired nor been revoked.
+    pub async fn is_trusted_device(&self, user_id: Uuid, device_id: Uuid, now: DateTime<Utc>) -> Result<bool, DBError> {
+        match &*self.0 {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_find_trusted_device.get(&client).await?;
+                let row = client.query_one(&stmt, &[&user_id, &device_id, &now]).await?;
+                Ok(row.get(0))
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<bool, DBError> {
+                        Ok(
+                            conn.query_row(SQLITE_FIND_TRUSTED_DEVICE, params![user_id, device_id, now], |row| {
+                                row.get(0)
+                            })?,
+                        )
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Get the not expired trusted devices of a user.
+    pub async fn list_trusted_devices(
+        &self,
+        user_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<TrustedDeviceInfo>, DBError> {
+        match &*self.0 {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_list_trusted_devices.get(&client).await?;
+                let rows = client.query(&stmt, &[&user_id, &now]).await?;
+                Ok(rows
+                    .iter()
+                    .map(|row| TrustedDeviceInfo {
+                        device_id: row.get(0),
+                        user_agent: row.get(1),
+                        created_at: row.get(2),
+                        expire_at: row.get(3),
+                    })
+                    .collect())
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Vec<TrustedDeviceInfo>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_LIST_TRUSTED_DEVICES)?;
+                        let devices = stmt
+                            .query_map(params![user_id, now], |row| {
+                                Ok(TrustedDeviceInfo {
+                                    device_id: row.get(0)?,
+                                    user_agent: row.get(1)?,
+                                    created_at: row.get(2)?,
+                                    expire_at: row.get(3)?,
+                                })
+                            })?
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(devices)
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Revoke a trusted device of the user. Returns false if no such device was found.
+    pub async fn revoke_trusted_device(&self, user_id: Uuid, device_id: Uuid) -> Result<bool, DBError> {
+        let count = match &*self.0 {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_delete_trusted_device.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &device_id]).await? as usize
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<usize, DBError> {
+                        Ok(conn.execute(SQLITE_DELETE_TRUSTED_DEVICE, params![user_id, device_id])?)
+                    })
+                    .await?
+            }
+        };
+        Ok(count > 0)
+    }
+
+    /// Revoke all the trusted devices of the user.
+    pub async fn revoke_trusted_devices(&self, user_id: Uuid) -> Result<(), DBError> {
+        match &*self.0 {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_delete_trusted_devices.get(&client).await?;
+                client.execute(&stmt, &[&user_id]).await?;
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(SQLITE_DELETE_TRUSTED_DEVICES, params![user_id])?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+
+/// Address folding rules of a mail provider.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailDomainRuleConfig {
+    pub domains: Vec<String>,
+    /// Dots are ignored in the local part, ex. `john.doe` is the same as `johndoe`.
+    #[serde(default)]
+    pub ignore_dots: bool,
+    /// The sub-address is ignored, ex. `john+games` is the same as `john`.
+    #[serde(default)]
+    pub ignore_plus: bool,
+    /// Domain to use instead of the aliases, ex. `googlemail.com` is the same as `gmail.com`.
+    pub canonical_domain: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailNormalizationConfig {
+    /// Convert the whole address to lowercase. The domain part is always lowercased.
+    #[serde(default = "default_lowercase")]
+    pub lowercase: bool,
+    /// The sub-address is ignored for all the domains.
+    #[serde(default)]
+    pub ignore_plus: bool,
+    #[serde(default)]
+    pub domains: Vec<EmailDomainRuleConfig>,
+}
+
+fn default_lowercase() -> bool {
+    true
+}
+
+impl Default for EmailNormalizationConfig {
+    fn default() -> Self {
+        Self {
+            lowercase: default_lowercase(),
+            ignore_plus: false,
+            domains: Vec::new(),
+        }
+    }
+}
+
+/// Normalize the email addresses to detect the different forms of the same mailbox.
+#[derive(Clone)]
+pub struct EmailNormalizer {
+    config: EmailNormalizationConfig,
+}
+
+impl EmailNormalizer {
+    pub fn new(config: &EmailNormalizationConfig) -> Self {
+        let mut config = config.clone();
+        for rule in &mut config.domains {
+            rule.domains
+                .iter_mut()
+                .for_each(|domain| *domain = domain.to_lowercase());
+        }
+        Self { config }
+    }
+
+    pub fn normalize(&self, email: &str) -> String {
+        let email = email.trim();
+        let (local, domain) = match email.rsplit_once('@') {
+            Some(parts) => parts,
+            None => return email.to_owned(),
+        };
+
+        let mut domain = domain.to_lowercase();
+        let mut local = if self.config.lowercase {
+            local.to_lowercase()
+        } else {
+            local.to_owned()
+        };
+
+        let rule = self
+            .config
+            .domains
+            .iter()
+            .find(|rule| rule.domains.iter().any(|d| *d == domain));
+
+        if self.config.ignore_plus || rule.map(|rule| rule.ignore_plus).unwrap_or(false) {
+            if let Some((base, _)) = local.split_once('+') {
+                local = base.to_owned();
+            }
+        }
+
+        if let Some(rule) = rule {
+            if rule.ignore_dots {
+                local.retain(|c| c != '.');
+            }
+            if let Some(canonical_domain) = &rule.canonical_domain {
+                domain = canonical_domain.to_lowercase();
+            }
+        }
+
+        format!("{local}@{domain}")
+    }
+}
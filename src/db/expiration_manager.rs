@@ -0,0 +1,308 @@
+use crate::{
+    db::{
+        AuditLog, DBError, DBPool, IdentityError, SharedClock, SharedIdentityStore, SharedSessionStore, SqlPool,
+        SqlitePool, UserChange, UserInvalidation,
+    },
+    error_reporting::ErrorReporting,
+};
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use serde_json::json;
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::{sync::Arc, time::Duration};
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+/// The period of the deactivation of the expired accounts.
+const DEACTIVATION_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// The number of accounts deactivated in a batch.
+const DEACTIVATION_BATCH_SIZE: i64 = 100;
+
+pg_prepared_statement!( SetExpiration => r#"
+    INSERT INTO identity_expirations (user_id, expire_at, deactivated)
+        VALUES ($1, $2, NULL)
+    ON CONFLICT (user_id) DO UPDATE SET expire_at = $2, deactivated = NULL
+"#, [UUID, TIMESTAMPTZ] );
+
+pg_prepared_statement!( ClearExpiration => r#"
+    DELETE FROM identity_expirations WHERE user_id = $1
+"#, [UUID] );
+
+pg_prepared_statement!( FindExpiration => r#"
+    SELECT expire_at FROM identity_expirations WHERE user_id = $1
+"#, [UUID] );
+
+pg_prepared_statement!( ListDueExpirations => r#"
+    SELECT user_id FROM identity_expirations
+        WHERE expire_at <= $1 AND deactivated IS NULL
+        ORDER BY expire_at
+        LIMIT $2
+"#, [TIMESTAMPTZ, INT8] );
+
+pg_prepared_statement!( MarkDeactivated => r#"
+    UPDATE identity_expirations SET deactivated = $2 WHERE user_id = $1
+"#, [UUID, TIMESTAMPTZ] );
+
+const SQLITE_SET_EXPIRATION: &str = r#"
+    INSERT INTO identity_expirations (user_id, expire_at, deactivated)
+        VALUES (?1, ?2, NULL)
+    ON CONFLICT (user_id) DO UPDATE SET expire_at = ?2, deactivated = NULL
+"#;
+
+const SQLITE_CLEAR_EXPIRATION: &str = r#"
+    DELETE FROM identity_expirations WHERE user_id = ?1
+"#;
+
+const SQLITE_FIND_EXPIRATION: &str = r#"
+    SELECT expire_at FROM identity_expirations WHERE user_id = ?1
+"#;
+
+const SQLITE_LIST_DUE_EXPIRATIONS: &str = r#"
+    SELECT user_id FROM identity_expirations
+        WHERE expire_at <= ?1 AND deactivated IS NULL
+        ORDER BY expire_at
+        LIMIT ?2
+"#;
+
+const SQLITE_MARK_DEACTIVATED: &str = r#"
+    UPDATE identity_expirations SET deactivated = ?2 WHERE user_id = ?1
+"#;
+
+#[derive(Debug, ThisError)]
+pub enum ExpirationError {
+    #[error(transparent)]
+    IdentityError(#[from] IdentityError),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for ExpirationError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum ExpirationBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for ExpirationBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_set_expiration: SetExpiration,
+    stmt_clear_expiration: ClearExpiration,
+    stmt_find_expiration: FindExpiration,
+    stmt_list_due_expirations: ListDueExpirations,
+    stmt_mark_deactivated: MarkDeactivated,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    identity_manager: SharedIdentityStore,
+    session_manager: SharedSessionStore,
+    user_invalidation: UserInvalidation,
+    audit_log: AuditLog,
+    clock: SharedClock,
+}
+
+/// The time-limited accounts, ex. for a closed beta or the trial press accounts. The login to an expired account
+/// is refused and a background job removes the sessions and the login tokens of the expired accounts. Extending
+/// the expiration makes the account usable again.
+#[derive(Clone)]
+pub struct ExpirationManager(Arc<Inner>);
+
+impl ExpirationManager {
+    pub async fn new(
+        pool: &DBPool,
+        identity_manager: SharedIdentityStore,
+        session_manager: SharedSessionStore,
+        user_invalidation: UserInvalidation,
+        audit_log: AuditLog,
+        error_reporting: ErrorReporting,
+        clock: SharedClock,
+    ) -> Result<Self, ExpirationBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_set_expiration = SetExpiration::new(&client).await?;
+                let stmt_clear_expiration = ClearExpiration::new(&client).await?;
+                let stmt_find_expiration = FindExpiration::new(&client).await?;
+                let stmt_list_due_expirations = ListDueExpirations::new(&client).await?;
+                let stmt_mark_deactivated = MarkDeactivated::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_set_expiration,
+                    stmt_clear_expiration,
+                    stmt_find_expiration,
+                    stmt_list_due_expirations,
+                    stmt_mark_deactivated,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        let manager = Self(Arc::new(Inner {
+            store,
+            identity_manager,
+            session_manager,
+            user_invalidation,
+            audit_log,
+            clock,
+        }));
+
+        let deactivator = manager.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(DEACTIVATION_INTERVAL);
+            loop {
+                interval.tick().await;
+                match deactivator.deactivate_expired().await {
+                    Ok(0) => {}
+                    Ok(count) => log::info!("Deactivated {count} expired accounts"),
+                    Err(err) => {
+                        log::warn!("Failed to deactivate the expired accounts: {err:?}");
+                        error_reporting.report("db.deactivation", &format!("{err:?}"), &[]);
+                    }
+                }
+            }
+        });
+
+        Ok(manager)
+    }
+
+    /// Set the expiration of an account, without an expiration the account is permanent. Setting a new expiration
+    /// also reactivates an expired account.
+    pub async fn set_expiration(&self, user_id: Uuid, expire_at: Option<DateTime<Utc>>) -> Result<(), DBError> {
+        match (&self.0.store, expire_at) {
+            (Store::Postgres(pg), Some(expire_at)) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_set_expiration.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &expire_at]).await?;
+            }
+            (Store::Postgres(pg), None) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_clear_expiration.get(&client).await?;
+                client.execute(&stmt, &[&user_id]).await?;
+            }
+            (Store::Sqlite(sqlite), expire_at) => {
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        match expire_at {
+                            Some(expire_at) => conn.execute(SQLITE_SET_EXPIRATION, params![user_id, expire_at])?,
+                            None => conn.execute(SQLITE_CLEAR_EXPIRATION, params![user_id])?,
+                        };
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the expiration of an account, None is returned for the permanent accounts.
+    pub async fn find_expiration(&self, user_id: Uuid) -> Result<Option<DateTime<Utc>>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_find_expiration.get(&client).await?;
+                let row = client.query_opt(&stmt, &[&user_id]).await?;
+                Ok(row.map(|row| row.get(0)))
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Option<DateTime<Utc>>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_FIND_EXPIRATION)?;
+                        let mut rows = stmt.query(params![user_id])?;
+                        match rows.next()? {
+                            Some(row) => Ok(Some(row.get(0)?)),
+                            None => Ok(None),
+                        }
+                    })
+                    .await
+            }
+        }
+    }
+
+    pub async fn is_expired(&self, user_id: Uuid) -> Result<bool, DBError> {
+        let now = self.0.clock.now();
+        Ok(self
+            .find_expiration(user_id)
+            .await?
+            .map_or(false, |expire_at| expire_at <= now))
+    }
+
+    /// Remove the sessions and the login tokens of the expired accounts and return the number of the deactivated
+    /// accounts. The failed deactivations are retried by the next run.
+    pub async fn deactivate_expired(&self) -> Result<usize, DBError> {
+        let now = self.0.clock.now();
+        let due: Vec<Uuid> = match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_list_due_expirations.get(&client).await?;
+                let rows = client.query(&stmt, &[&now, &DEACTIVATION_BATCH_SIZE]).await?;
+                rows.iter().map(|row| row.get(0)).collect()
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Vec<Uuid>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_LIST_DUE_EXPIRATIONS)?;
+                        let due = stmt
+                            .query_map(params![now, DEACTIVATION_BATCH_SIZE], |row| row.get(0))?
+                            .collect::<Result<Vec<Uuid>, _>>()?;
+                        Ok(due)
+                    })
+                    .await?
+            }
+        };
+
+        let mut count = 0;
+        for user_id in due {
+            match self.deactivate_user(user_id, now).await {
+                Ok(()) => count += 1,
+                Err(err) => log::warn!("Failed to deactivate the expired account {user_id}: {err}"),
+            }
+        }
+        Ok(count)
+    }
+
+    async fn deactivate_user(&self, user_id: Uuid, now: DateTime<Utc>) -> Result<(), ExpirationError> {
+        let inner = &*self.0;
+        inner.session_manager.remove_all(user_id).await?;
+        inner.identity_manager.delete_all_tokens(user_id).await?;
+        match &inner.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_mark_deactivated.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &now]).await?;
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(SQLITE_MARK_DEACTIVATED, params![user_id, now])?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        inner
+            .audit_log
+            .record(None, "identity.expire", Some(user_id), json!({}))
+            .await?;
+        if let Err(err) = inner.user_invalidation.invalidate(user_id, UserChange::Expired).await {
+            log::error!("Failed to publish the expiration of {user_id}: {err}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use crate::db::DBError;
+use serde::{Deserialize, Serialize};
+
+/// The storage layer where a fault is injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultLayer {
+    Postgres,
+    Redis,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FaultConfig {
+    /// Probability (0.0 - 1.0) of delaying an operation.
+    #[serde(default)]
+    pub latency_rate: f64,
+    /// Maximum of the injected delay in milliseconds, the actual delay is uniform in the range.
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// Probability of failing to get a connection.
+    #[serde(default)]
+    pub drop_rate: f64,
+    /// Probability of reporting a conflicting (unique constraint) insert.
+    #[serde(default)]
+    pub constraint_error_rate: f64,
+}
+
+/// Faults injected into the storage layers for the chaos and load tests. It is available only with the
+/// `fault-injection` feature, never enable it in production.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FaultInjectionConfig {
+    pub postgres: Option<FaultConfig>,
+    pub redis: Option<FaultConfig>,
+}
+
+/// Inject the configured faults. Without the `fault-injection` feature all the operations are no-op.
+#[derive(Clone, Default)]
+pub struct FaultInjector {
+    #[cfg(feature = "fault-injection")]
+    config: Option<std::sync::Arc<FaultInjectionConfig>>,
+}
+
+impl FaultInjector {
+    #[cfg(feature = "fault-injection")]
+    pub fn new(config: Option<&FaultInjectionConfig>) -> Self {
+        if config.is_some() {
+            log::warn!("Fault injection is enabled, this must not be enabled in production");
+        }
+        Self {
+            config: config.cloned().map(std::sync::Arc::new),
+        }
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    pub fn new(_config: Option<&FaultInjectionConfig>) -> Self {
+        Self {}
+    }
+
+    #[cfg(feature = "fault-injection")]
+    fn layer_config(&self, layer: FaultLayer) -> Option<&FaultConfig> {
+        let config = self.config.as_ref()?;
+        match layer {
+            FaultLayer::Postgres => config.postgres.as_ref(),
+            FaultLayer::Redis => config.redis.as_ref(),
+        }
+    }
+
+    /// Delay and drop the connection of an operation.
+    #[cfg(feature = "fault-injection")]
+    pub async fn inject(&self, layer: FaultLayer) -> Result<(), DBError> {
+        use rand::Rng;
+
+        let config = match self.layer_config(layer) {
+            Some(config) => config,
+            None => return Ok(()),
+        };
+
+        let (delay, is_dropped) = {
+            let mut rng = rand::thread_rng();
+            let delay = if config.latency_ms > 0 && rng.gen_bool(config.latency_rate.clamp(0., 1.)) {
+                Some(rng.gen_range(0..=config.latency_ms))
+            } else {
+                None
+            };
+            (delay, rng.gen_bool(config.drop_rate.clamp(0., 1.)))
+        };
+
+        if let Some(delay) = delay {
+            log::debug!("Injected {delay}ms latency into {layer:?}");
+            tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+        }
+        if is_dropped {
+            log::debug!("Injected connection drop into {layer:?}");
+            return Err(DBError::InjectedFault(layer));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    pub async fn inject(&self, _layer: FaultLayer) -> Result<(), DBError> {
+        Ok(())
+    }
+
+    /// Check if an insert should fail as if a unique constraint was violated.
+    #[cfg(feature = "fault-injection")]
+    pub fn is_constraint_violated(&self, layer: FaultLayer) -> bool {
+        use rand::Rng;
+
+        match self.layer_config(layer) {
+            Some(config) if rand::thread_rng().gen_bool(config.constraint_error_rate.clamp(0., 1.)) => {
+                log::debug!("Injected constraint error into {layer:?}");
+                true
+            }
+            _ => false,
+        }
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    pub fn is_constraint_violated(&self, _layer: FaultLayer) -> bool {
+        false
+    }
+}
@@ -0,0 +1,191 @@
+use crate::db::{DBError, DBPool};
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use shine_service::service::RedisConnectionPool;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The redis hash of the overrides, ex. `HSET feature-flags allowRegistration false`.
+pub const FEATURE_FLAGS_KEY: &str = "feature-flags";
+
+/// The behaviors of the auth service that can be switched at runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FeatureFlag {
+    /// New accounts can be created, with any of the providers.
+    AllowRegistration,
+    /// The registration requires an email, thus the guest accounts are refused and the external providers have to
+    /// share the email of the user.
+    RequireEmail,
+    /// Guest accounts (without an external provider) can be created.
+    EnableGuestLogin,
+}
+
+impl FeatureFlag {
+    pub const ALL: [FeatureFlag; 3] = [
+        FeatureFlag::AllowRegistration,
+        FeatureFlag::RequireEmail,
+        FeatureFlag::EnableGuestLogin,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeatureFlag::AllowRegistration => "allowRegistration",
+            FeatureFlag::RequireEmail => "requireEmail",
+            FeatureFlag::EnableGuestLogin => "enableGuestLogin",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureFlagsConfig {
+    #[serde(default = "FeatureFlagsConfig::default_enabled")]
+    pub allow_registration: bool,
+    #[serde(default)]
+    pub require_email: bool,
+    #[serde(default = "FeatureFlagsConfig::default_enabled")]
+    pub enable_guest_login: bool,
+    /// Time in seconds the overrides are cached. An override becomes visible in all the instances after this time.
+    #[serde(default = "FeatureFlagsConfig::default_cache_ttl")]
+    pub cache_ttl: u64,
+}
+
+impl FeatureFlagsConfig {
+    fn default_enabled() -> bool {
+        true
+    }
+
+    fn default_cache_ttl() -> u64 {
+        5
+    }
+
+    fn default_value(&self, flag: FeatureFlag) -> bool {
+        match flag {
+            FeatureFlag::AllowRegistration => self.allow_registration,
+            FeatureFlag::RequireEmail => self.require_email,
+            FeatureFlag::EnableGuestLogin => self.enable_guest_login,
+        }
+    }
+}
+
+impl Default for FeatureFlagsConfig {
+    fn default() -> Self {
+        Self {
+            allow_registration: Self::default_enabled(),
+            require_email: false,
+            enable_guest_login: Self::default_enabled(),
+            cache_ttl: Self::default_cache_ttl(),
+        }
+    }
+}
+
+/// The effective state of a flag.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureFlagState {
+    pub flag: FeatureFlag,
+    pub enabled: bool,
+    /// The value of the configuration.
+    pub default: bool,
+    /// The value of the redis override, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overridden: Option<bool>,
+}
+
+struct Inner {
+    redis: RedisConnectionPool,
+    key_prefix: String,
+    config: FeatureFlagsConfig,
+    cache: Mutex<Option<(HashMap<FeatureFlag, bool>, Instant)>>,
+    cache_ttl: Duration,
+}
+
+/// The feature flags of the service. The defaults are given by the configuration and they can be overridden for all
+/// the instances in redis. The overrides are cached locally.
+#[derive(Clone)]
+pub struct FeatureFlags(Arc<Inner>);
+
+impl FeatureFlags {
+    pub fn new(pool: &DBPool, config: &FeatureFlagsConfig) -> Self {
+        Self(Arc::new(Inner {
+            redis: pool.redis.clone(),
+            key_prefix: pool.redis_key_prefix.clone(),
+            config: config.clone(),
+            cache: Mutex::new(None),
+            cache_ttl: Duration::from_secs(config.cache_ttl),
+        }))
+    }
+
+    async fn overrides(&self) -> Result<HashMap<FeatureFlag, bool>, DBError> {
+        let inner = &*self.0;
+        {
+            let cache = inner.cache.lock().unwrap();
+            if let Some((overrides, checked)) = &*cache {
+                if checked.elapsed() < inner.cache_ttl {
+                    return Ok(overrides.clone());
+                }
+            }
+        }
+
+        let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
+        let values: HashMap<String, String> = client
+            .hgetall(format!("{}{FEATURE_FLAGS_KEY}", inner.key_prefix))
+            .await
+            .map_err(DBError::RedisError)?;
+        let overrides: HashMap<FeatureFlag, bool> = FeatureFlag::ALL
+            .iter()
+            .filter_map(|flag| {
+                let value = values.get(flag.as_str())?;
+                match value.as_str() {
+                    "true" | "1" => Some((*flag, true)),
+                    "false" | "0" => Some((*flag, false)),
+                    _ => {
+                        log::warn!("Invalid override of the feature flag {}: {value}", flag.as_str());
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        let mut cache = inner.cache.lock().unwrap();
+        *cache = Some((overrides.clone(), Instant::now()));
+        Ok(overrides)
+    }
+
+    /// Check if a flag is enabled. When the overrides are not available, the configured value is used.
+    pub async fn is_enabled(&self, flag: FeatureFlag) -> bool {
+        let overrides = match self.overrides().await {
+            Ok(overrides) => overrides,
+            Err(err) => {
+                log::error!("Failed to get the overrides of the feature flags: {err}");
+                HashMap::new()
+            }
+        };
+        overrides
+            .get(&flag)
+            .copied()
+            .unwrap_or_else(|| self.0.config.default_value(flag))
+    }
+
+    /// Get the effective state of all the flags.
+    pub async fn list(&self) -> Result<Vec<FeatureFlagState>, DBError> {
+        let overrides = self.overrides().await?;
+        Ok(FeatureFlag::ALL
+            .iter()
+            .map(|flag| {
+                let default = self.0.config.default_value(*flag);
+                let overridden = overrides.get(flag).copied();
+                FeatureFlagState {
+                    flag: *flag,
+                    enabled: overridden.unwrap_or(default),
+                    default,
+                    overridden,
+                }
+            })
+            .collect())
+    }
+}
@@ -1,22 +1,41 @@
-use crate::db::{DBError, DBPool, PGError};
+use crate::{
+    db::{
+        normalize_name, DBError, DBPool, EmailNormalizationConfig, EmailNormalizer, FaultInjector, FaultLayer,
+        IdentityStore, PGError, SharedClock, SharedIdGenerator, SqlPool, SqliteErrorChecks, SqlitePool,
+    },
+    logging::{Redacted, RedactedEmail},
+};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
 use bytes::BytesMut;
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Utc};
+use ring::hmac;
+use rusqlite::{params, params_from_iter, OptionalExtension};
+use serde::Serialize;
 use shine_service::{
     pg_prepared_statement,
     service::{PGConnectionPool, PGErrorChecks, QueryBuilder},
 };
-use std::sync::Arc;
+use std::{fmt, future::Future, net::IpAddr, sync::Arc, time::Duration};
 use thiserror::Error as ThisError;
+use tokio::sync::OnceCell;
 use tokio_postgres::{
+    error::SqlState,
     types::{accepts, to_sql_checked, FromSql, IsNull, ToSql, Type},
-    Row,
+    CancelToken, NoTls, Row,
 };
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Copy)]
+/// The tenant of the identities when multi-tenancy is not configured.
+pub const DEFAULT_TENANT_ID: &str = "default";
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub enum IdentityKind {
     User,
     Studio,
+    /// Machine identity of an internal service authenticated by client credentials.
+    Service,
 }
 
 impl ToSql for IdentityKind {
@@ -24,6 +43,7 @@ impl ToSql for IdentityKind {
         let value = match self {
             IdentityKind::User => 1_i16,
             IdentityKind::Studio => 2_i16,
+            IdentityKind::Service => 3_i16,
         };
         value.to_sql(ty, out)
     }
@@ -38,6 +58,7 @@ impl<'a> FromSql<'a> for IdentityKind {
         match value {
             1 => Ok(IdentityKind::User),
             2 => Ok(IdentityKind::Studio),
+            3 => Ok(IdentityKind::Service),
             _ => Err(PGError::from("Invalid value for IdentityKind")),
         }
     }
@@ -45,7 +66,27 @@ impl<'a> FromSql<'a> for IdentityKind {
     accepts!(INT2);
 }
 
-#[derive(Debug)]
+impl rusqlite::types::ToSql for IdentityKind {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        let value = match self {
+            IdentityKind::User => 1_i64,
+            IdentityKind::Studio => 2_i64,
+            IdentityKind::Service => 3_i64,
+        };
+        Ok(value.into())
+    }
+}
+
+impl rusqlite::types::FromSql for IdentityKind {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        match value.as_i64()? {
+            1 => Ok(IdentityKind::User),
+            2 => Ok(IdentityKind::Studio),
+            3 => Ok(IdentityKind::Service),
+            value => Err(rusqlite::types::FromSqlError::OutOfRange(value)),
+        }
+    }
+}
 
 pub struct Identity {
     pub user_id: Uuid,
@@ -54,6 +95,21 @@ pub struct Identity {
     pub email: Option<String>,
     pub is_email_confirmed: bool,
     pub creation: DateTime<Utc>,
+    pub tenant_id: String,
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Identity")
+            .field("user_id", &self.user_id)
+            .field("kind", &self.kind)
+            .field("name", &Redacted(&self.name))
+            .field("email", &self.email.as_deref().map(RedactedEmail))
+            .field("is_email_confirmed", &self.is_email_confirmed)
+            .field("creation", &self.creation)
+            .field("tenant_id", &self.tenant_id)
+            .finish()
+    }
 }
 
 impl Identity {
@@ -65,33 +121,147 @@ impl Identity {
             email: row.try_get(3)?,
             is_email_confirmed: row.try_get(4)?,
             creation: row.try_get(5)?,
+            tenant_id: row.try_get(6)?,
+        })
+    }
+
+    fn from_sqlite_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            user_id: row.get(0)?,
+            kind: row.get(1)?,
+            name: row.get(2)?,
+            email: row.get(3)?,
+            is_email_confirmed: row.get(4)?,
+            creation: row.get(5)?,
+            tenant_id: row.get(6)?,
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ExternalLoginInfo {
+    pub tenant_id: String,
+    pub provider: String,
+    pub provider_id: String,
+}
+
+/// An external provider linked to a user.
+#[derive(Debug)]
+pub struct LinkedProvider {
     pub provider: String,
     pub provider_id: String,
+    pub linked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TokenKind {
+    /// Long-lived token of the "remember me" logins.
+    Persistent,
+}
+
+impl ToSql for TokenKind {
+    fn to_sql(&self, ty: &Type, out: &mut BytesMut) -> Result<IsNull, PGError> {
+        let value = match self {
+            TokenKind::Persistent => 1_i16,
+        };
+        value.to_sql(ty, out)
+    }
+
+    accepts!(INT2);
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for TokenKind {
+    fn from_sql(ty: &Type, raw: &[u8]) -> Result<TokenKind, PGError> {
+        let value = i16::from_sql(ty, raw)?;
+        match value {
+            1 => Ok(TokenKind::Persistent),
+            _ => Err(PGError::from("Invalid value for TokenKind")),
+        }
+    }
+
+    accepts!(INT2);
+}
+
+impl rusqlite::types::ToSql for TokenKind {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        let value = match self {
+            TokenKind::Persistent => 1_i64,
+        };
+        Ok(value.into())
+    }
+}
+
+impl rusqlite::types::FromSql for TokenKind {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        match value.as_i64()? {
+            1 => Ok(TokenKind::Persistent),
+            value => Err(rusqlite::types::FromSqlError::OutOfRange(value)),
+        }
+    }
+}
+
+/// Details recorded about the issue of a token.
+#[derive(Debug)]
+pub struct TokenMeta<'a> {
+    pub kind: TokenKind,
+    /// Label of the token given by the user.
+    pub name: Option<&'a str>,
+    pub creation_ip: Option<IpAddr>,
+    pub user_agent: Option<&'a str>,
 }
 
 #[derive(Debug)]
 pub struct LoginTokenInfo {
     pub user_id: Uuid,
-    pub token: String,
+    pub token_id: Uuid,
+    pub kind: TokenKind,
+    pub token_hash: String,
     pub created_at: DateTime<Utc>,
     pub expire_at: DateTime<Utc>,
     pub is_expired: bool,
+    pub last_used: Option<DateTime<Utc>>,
+    pub name: Option<String>,
+    pub creation_ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
 }
 
 impl LoginTokenInfo {
-    fn from_find_row(row: &Row) -> Result<Self, IdentityError> {
+    /// Parse the token columns starting at the given column.
+    fn from_row(row: &Row, first: usize) -> Result<Self, IdentityError> {
         Ok(Self {
-            user_id: row.try_get(0)?,
-            token: row.try_get(6)?,
-            created_at: row.try_get(7)?,
-            expire_at: row.try_get(8)?,
-            is_expired: row.try_get(9)?,
+            user_id: row.try_get(first)?,
+            token_id: row.try_get(first + 1)?,
+            kind: row.try_get(first + 2)?,
+            token_hash: row.try_get(first + 3)?,
+            created_at: row.try_get(first + 4)?,
+            expire_at: row.try_get(first + 5)?,
+            is_expired: row.try_get(first + 6)?,
+            last_used: row.try_get(first + 7)?,
+            name: row.try_get(first + 8)?,
+            creation_ip: row.try_get(first + 9)?,
+            user_agent: row.try_get(first + 10)?,
+        })
+    }
+
+    /// Parse the token columns starting at the given column. SQLite has no inet type, the ip is stored as text
+    /// and the expiration is checked here.
+    fn from_sqlite_row(row: &rusqlite::Row, first: usize, now: DateTime<Utc>) -> rusqlite::Result<Self> {
+        let expire_at: DateTime<Utc> = row.get(first + 5)?;
+        let creation_ip: Option<String> = row.get(first + 8)?;
+        Ok(Self {
+            user_id: row.get(first)?,
+            token_id: row.get(first + 1)?,
+            kind: row.get(first + 2)?,
+            token_hash: row.get(first + 3)?,
+            created_at: row.get(first + 4)?,
+            expire_at,
+            is_expired: expire_at < now,
+            last_used: row.get(first + 6)?,
+            name: row.get(first + 7)?,
+            creation_ip: creation_ip.and_then(|ip| ip.parse().ok()),
+            user_agent: row.get(first + 9)?,
         })
     }
 }
@@ -118,12 +288,19 @@ impl From<tokio_postgres::Error> for IdentityError {
     }
 }
 
-/// Identity query options
+impl From<rusqlite::Error> for IdentityError {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+/// Identity query options. User id and token are unique accross the tenants,
+/// the other properties are unique only within a tenant.
 #[derive(Debug)]
 pub enum FindIdentity<'a> {
     UserId(Uuid),
-    Email(&'a str),
-    Name(&'a str),
+    Email { tenant_id: &'a str, email: &'a str },
+    Name { tenant_id: &'a str, name: &'a str },
     ExternalLogin(&'a ExternalLoginInfo),
     Token(&'a str),
 }
@@ -133,86 +310,312 @@ pub enum SearchIdentityOrder {
     UserId(Option<Uuid>),
     Email(Option<(String, Uuid)>),
     Name(Option<(String, Uuid)>),
+    /// The newest identities first.
+    Created(Option<(DateTime<Utc>, Uuid)>),
 }
 
-#[derive(Debug)]
 pub struct SearchIdentity<'a> {
     pub order: SearchIdentityOrder,
     pub count: Option<usize>,
 
+    pub tenant_id: Option<&'a str>,
     pub user_ids: Option<&'a [Uuid]>,
     pub emails: Option<&'a [String]>,
     pub names: Option<&'a [String]>,
+    /// Match the identities having any of the tags.
+    pub tags: Option<&'a [String]>,
+
+    /// Count the matching identities too, see `SearchIdentityResult::approx_total`.
+    pub include_total: bool,
+}
+
+impl<'a> fmt::Debug for SearchIdentity<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SearchIdentity")
+            .field("order", &self.order)
+            .field("count", &self.count)
+            .field("tenant_id", &self.tenant_id)
+            .field("user_ids", &self.user_ids)
+            .field(
+                "emails",
+                &self.emails.map(|emails| {
+                    emails
+                        .iter()
+                        .map(|email| RedactedEmail(email.as_str()))
+                        .collect::<Vec<_>>()
+                }),
+            )
+            .field("names", &self.names.map(|names| Redacted(names.len())))
+            .field("tags", &self.tags)
+            .field("include_total", &self.include_total)
+            .finish()
+    }
+}
+
+/// A page of the identity search.
+#[derive(Debug)]
+pub struct SearchIdentityResult {
+    pub items: Vec<Identity>,
+    /// The order with the continuation to query the next page, it is None when there are no more items.
+    pub next_cursor: Option<SearchIdentityOrder>,
+    /// The number of all the matching identities ignoring the continuation. The count is capped, for the
+    /// large results it is only a lower bound.
+    pub approx_total: Option<usize>,
 }
 
 pg_prepared_statement!( InsertIdentity => r#"
-    INSERT INTO identities (user_id, kind, created, name, email) 
-        VALUES ($1, $2, now(), $3, $4)
+    INSERT INTO identities (user_id, kind, created, name, normalized_name, email, normalized_email, tenant_id) 
+        VALUES ($1, $2, now(), $3, $4, $5, $6, $7)
         RETURNING created
-"#, [UUID, INT2, VARCHAR, VARCHAR] );
+"#, [UUID, INT2, VARCHAR, VARCHAR, VARCHAR, VARCHAR, VARCHAR] );
 
 pg_prepared_statement!( InsertToken => r#"
-    INSERT INTO login_tokens (user_id, token, created, expire) 
-        VALUES ($1, $2, now(), now() + $3 * interval '1 seconds')
-    RETURNING created, expire
-"#, [UUID, VARCHAR, INT4] );
+    INSERT INTO login_tokens (user_id, token_id, kind, token_hash, name, creation_ip, user_agent, created, expire) 
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+"#, [UUID, UUID, INT2, VARCHAR, VARCHAR, INET, TEXT, TIMESTAMPTZ, TIMESTAMPTZ] );
 
 pg_prepared_statement!( InsertExternalLogin => r#"
-    INSERT INTO external_logins (user_id, provider, provider_id, linked) 
-        VALUES ($1, $2, $3, now())
+    INSERT INTO external_logins (user_id, provider, provider_id, linked, tenant_id) 
+        VALUES ($1, $2, $3, now(), $4)
     RETURNING linked
-"#, [UUID, VARCHAR, VARCHAR] );
+"#, [UUID, VARCHAR, VARCHAR, VARCHAR] );
+
+pg_prepared_statement!( ListLinks => r#"
+    SELECT provider, provider_id, linked
+        FROM external_logins
+        WHERE user_id = $1
+        ORDER BY provider
+"#, [UUID] );
+
+// The link is kept if it is the last login method of the user
+pg_prepared_statement!( DeleteLink => r#"
+    DELETE FROM external_logins WHERE user_id = $1 AND provider = $2
+        AND (
+            EXISTS(SELECT 1 FROM external_logins WHERE user_id = $1 AND provider <> $2)
+            OR EXISTS(SELECT 1 FROM passwords WHERE user_id = $1)
+        )
+"#, [UUID, VARCHAR] );
 
 pg_prepared_statement!( CascadedDelete => r#"
     -- DELETE FROM external_logins WHERE user_id = $1; fkey constraint shall trigger a cascaded delete
     DELETE FROM identities WHERE user_id = $1;
 "#, [UUID] );
 
+// The deletion of the other tables is in the same statement, thus the anonymization is atomic
+pg_prepared_statement!( Anonymize => r#"
+    WITH
+        del_links AS (DELETE FROM external_logins WHERE user_id = $1),
+        del_tokens AS (DELETE FROM login_tokens WHERE user_id = $1),
+        del_passwords AS (DELETE FROM passwords WHERE user_id = $1),
+        del_mfa AS (DELETE FROM mfa_methods WHERE user_id = $1),
+        del_known_devices AS (DELETE FROM known_devices WHERE user_id = $1),
+        del_trusted_devices AS (DELETE FROM trusted_devices WHERE user_id = $1),
+        del_roles AS (DELETE FROM user_roles WHERE user_id = $1),
+        del_grants AS (DELETE FROM grants WHERE user_id = $1),
+        del_consents AS (DELETE FROM consents WHERE user_id = $1),
+        del_deletions AS (DELETE FROM identity_deletions WHERE user_id = $1)
+    UPDATE identities
+        SET name = $2, normalized_name = $3, email = NULL, normalized_email = NULL, email_confirmed = false,
+            profile_image = NULL
+        WHERE user_id = $1
+"#, [UUID, VARCHAR, VARCHAR] );
+
+pg_prepared_statement!( UpdateName => r#"
+    UPDATE identities SET name = $2, normalized_name = $3 WHERE user_id = $1
+"#, [UUID, VARCHAR, VARCHAR] );
+
 pg_prepared_statement!( FindById => r#"
-    SELECT user_id, kind, name, email, email_confirmed, created 
+    SELECT user_id, kind, name, email, email_confirmed, created, tenant_id
         FROM identities
         WHERE user_id = $1
 "#, [UUID] );
 
 pg_prepared_statement!( FindByEmail => r#"
-    SELECT user_id, kind, name, email, email_confirmed, created 
+    SELECT user_id, kind, name, email, email_confirmed, created, tenant_id
             FROM identities
-            WHERE email = $1
-"#, [VARCHAR] );
+            WHERE tenant_id = $1 AND normalized_email = $2
+"#, [VARCHAR, VARCHAR] );
 
 pg_prepared_statement!( FindByName => r#"
-    SELECT user_id, kind, name, email, email_confirmed, created 
+    SELECT user_id, kind, name, email, email_confirmed, created, tenant_id
             FROM identities
-            WHERE name = $1
-"#, [VARCHAR] );
+            WHERE tenant_id = $1 AND normalized_name = $2
+"#, [VARCHAR, VARCHAR] );
 
 pg_prepared_statement!( FindByLink => r#"
-    SELECT i.user_id, i.kind, i.name, i.email, i.email_confirmed, i.created,
+    SELECT i.user_id, i.kind, i.name, i.email, i.email_confirmed, i.created, i.tenant_id,
            e.provider, e.provider_id, e.linked
         FROM external_logins e, identities i
         WHERE e.user_id = i.user_id
-            AND e.provider = $1
-            AND e.provider_id = $2
-"#, [VARCHAR, VARCHAR] );
+            AND e.tenant_id = $1
+            AND e.provider = $2
+            AND e.provider_id = $3
+"#, [VARCHAR, VARCHAR, VARCHAR] );
 
 pg_prepared_statement!( FindByToken => r#"
-    SELECT i.user_id, i.kind, i.name, i.email, i.email_confirmed, i.created,
-           t.token, t.created, t.expire, t.expire < now() is_expired
+    SELECT i.user_id, i.kind, i.name, i.email, i.email_confirmed, i.created, i.tenant_id,
+           t.user_id, t.token_id, t.kind, t.token_hash, t.created, t.expire, t.expire < $2 is_expired,
+           t.last_used, t.name, t.creation_ip, t.user_agent
         FROM login_tokens t, identities i
         WHERE t.user_id = i.user_id
-            AND t.token = $1
-"#, [VARCHAR] );
+            AND t.token_hash = $1
+"#, [VARCHAR, TIMESTAMPTZ] );
 
 pg_prepared_statement!( DeleteToken => r#"
-    DELETE FROM login_tokens WHERE user_id = $1 AND token = $2
+    DELETE FROM login_tokens WHERE user_id = $1 AND token_hash = $2
 "#, [UUID, VARCHAR] );
 
+pg_prepared_statement!( ListPlainTokens => r#"
+    SELECT token FROM login_tokens WHERE token_hash IS NULL AND token IS NOT NULL
+"#, [] );
+
+pg_prepared_statement!( HashPlainToken => r#"
+    UPDATE login_tokens SET token_hash = $2, token = NULL WHERE token = $1
+"#, [VARCHAR, VARCHAR] );
+
 pg_prepared_statement!( DeleteAllTokens => r#"
     DELETE FROM login_tokens WHERE user_id = $1
 "#, [UUID] );
 
+pg_prepared_statement!( DeleteTokenById => r#"
+    DELETE FROM login_tokens WHERE user_id = $1 AND token_id = $2
+"#, [UUID, UUID] );
+
+pg_prepared_statement!( ListTokens => r#"
+    SELECT user_id, token_id, kind, token_hash, created, expire, expire < $2 is_expired,
+           last_used, name, creation_ip, user_agent
+        FROM login_tokens
+        WHERE user_id = $1
+        ORDER BY created DESC
+"#, [UUID, TIMESTAMPTZ] );
+
+pg_prepared_statement!( TouchToken => r#"
+    UPDATE login_tokens SET last_used = $2 WHERE token_hash = $1
+"#, [VARCHAR, TIMESTAMPTZ] );
+
+const SQLITE_INSERT_IDENTITY: &str = r#"
+    INSERT INTO identities (user_id, kind, created, name, normalized_name, email, normalized_email, tenant_id)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+"#;
+
+const SQLITE_INSERT_TOKEN: &str = r#"
+    INSERT INTO login_tokens (user_id, token_id, kind, token_hash, name, creation_ip, user_agent, created, expire)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+"#;
+
+const SQLITE_INSERT_EXTERNAL_LOGIN: &str = r#"
+    INSERT INTO external_logins (user_id, provider, provider_id, linked, tenant_id)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+"#;
+
+const SQLITE_LIST_LINKS: &str = r#"
+    SELECT provider, provider_id, linked
+        FROM external_logins
+        WHERE user_id = ?1
+        ORDER BY provider
+"#;
+
+const SQLITE_DELETE_LINK: &str = r#"
+    DELETE FROM external_logins WHERE user_id = ?1 AND provider = ?2
+        AND (
+            EXISTS(SELECT 1 FROM external_logins WHERE user_id = ?1 AND provider <> ?2)
+            OR EXISTS(SELECT 1 FROM passwords WHERE user_id = ?1)
+        )
+"#;
+
+const SQLITE_CASCADED_DELETE: &str = r#"
+    DELETE FROM identities WHERE user_id = ?1
+"#;
+
+const SQLITE_ANONYMIZE: &str = r#"
+    UPDATE identities
+        SET name = ?2, normalized_name = ?3, email = NULL, normalized_email = NULL, email_confirmed = 0,
+            profile_image = NULL
+        WHERE user_id = ?1
+"#;
+
+/// The login methods and the personal data removed by the anonymization beside the identity.
+const SQLITE_ANONYMIZE_CLEANUP: &[&str] = &[
+    "DELETE FROM external_logins WHERE user_id = ?1",
+    "DELETE FROM login_tokens WHERE user_id = ?1",
+    "DELETE FROM passwords WHERE user_id = ?1",
+    "DELETE FROM mfa_methods WHERE user_id = ?1",
+    "DELETE FROM known_devices WHERE user_id = ?1",
+    "DELETE FROM trusted_devices WHERE user_id = ?1",
+    "DELETE FROM user_roles WHERE user_id = ?1",
+    "DELETE FROM grants WHERE user_id = ?1",
+    "DELETE FROM consents WHERE user_id = ?1",
+    "DELETE FROM identity_deletions WHERE user_id = ?1",
+];
+
+const SQLITE_UPDATE_NAME: &str = r#"
+    UPDATE identities SET name = ?2, normalized_name = ?3 WHERE user_id = ?1
+"#;
+
+const SQLITE_FIND_BY_ID: &str = r#"
+    SELECT user_id, kind, name, email, email_confirmed, created, tenant_id
+        FROM identities
+        WHERE user_id = ?1
+"#;
+
+const SQLITE_FIND_BY_EMAIL: &str = r#"
+    SELECT user_id, kind, name, email, email_confirmed, created, tenant_id
+        FROM identities
+        WHERE tenant_id = ?1 AND normalized_email = ?2
+"#;
+
+const SQLITE_FIND_BY_NAME: &str = r#"
+    SELECT user_id, kind, name, email, email_confirmed, created, tenant_id
+        FROM identities
+        WHERE tenant_id = ?1 AND normalized_name = ?2
+"#;
+
+const SQLITE_FIND_BY_LINK: &str = r#"
+    SELECT i.user_id, i.kind, i.name, i.email, i.email_confirmed, i.created, i.tenant_id
+        FROM external_logins e, identities i
+        WHERE e.user_id = i.user_id
+            AND e.tenant_id = ?1
+            AND e.provider = ?2
+            AND e.provider_id = ?3
+"#;
+
+const SQLITE_FIND_BY_TOKEN: &str = r#"
+    SELECT i.user_id, i.kind, i.name, i.email, i.email_confirmed, i.created, i.tenant_id,
+           t.user_id, t.token_id, t.kind, t.token_hash, t.created, t.expire,
+           t.last_used, t.name, t.creation_ip, t.user_agent
+        FROM login_tokens t, identities i
+        WHERE t.user_id = i.user_id
+            AND t.token_hash = ?1
+"#;
+
+const SQLITE_DELETE_TOKEN: &str = r#"
+    DELETE FROM login_tokens WHERE user_id = ?1 AND token_hash = ?2
+"#;
+
+const SQLITE_DELETE_ALL_TOKENS: &str = r#"
+    DELETE FROM login_tokens WHERE user_id = ?1
+"#;
+
+const SQLITE_DELETE_TOKEN_BY_ID: &str = r#"
+    DELETE FROM login_tokens WHERE user_id = ?1 AND token_id = ?2
+"#;
+
+const SQLITE_LIST_TOKENS: &str = r#"
+    SELECT user_id, token_id, kind, token_hash, created, expire,
+           last_used, name, creation_ip, user_agent
+        FROM login_tokens
+        WHERE user_id = ?1
+        ORDER BY created DESC
+"#;
+
+const SQLITE_TOUCH_TOKEN: &str = r#"
+    UPDATE login_tokens SET last_used = ?2 WHERE token_hash = ?1
+"#;
+
 #[derive(Debug, ThisError)]
 pub enum IdentityBuildError {
+    #[error("Invalid token hash secret: {0}")]
+    InvalidTokenSecret(String),
     #[error(transparent)]
     DBError(#[from] DBError),
 }
@@ -223,12 +626,16 @@ impl From<tokio_postgres::Error> for IdentityBuildError {
     }
 }
 
-struct Inner {
+struct PgStore {
     postgres: PGConnectionPool,
     stmt_insert_identity: InsertIdentity,
     stmt_insert_external_link: InsertExternalLogin,
     stmt_insert_token: InsertToken,
+    stmt_list_links: ListLinks,
+    stmt_delete_link: DeleteLink,
     stmt_cascaded_delete: CascadedDelete,
+    stmt_anonymize: Anonymize,
+    stmt_update_name: UpdateName,
     stmt_find_by_id: FindById,
     stmt_find_by_email: FindByEmail,
     stmt_find_by_name: FindByName,
@@ -236,18 +643,22 @@ struct Inner {
     stmt_find_by_token: FindByToken,
     stmt_delete_token: DeleteToken,
     stmt_delete_all_tokens: DeleteAllTokens,
+    stmt_delete_token_by_id: DeleteTokenById,
+    stmt_list_tokens: ListTokens,
+    stmt_touch_token: TouchToken,
 }
 
-#[derive(Clone)]
-pub struct IdentityManager(Arc<Inner>);
-
-impl IdentityManager {
-    pub async fn new(pool: &DBPool) -> Result<Self, IdentityBuildError> {
-        let client = pool.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+impl PgStore {
+    async fn new(postgres: &PGConnectionPool, token_key: &hmac::Key) -> Result<Self, DBError> {
+        let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
         let stmt_insert_identity = InsertIdentity::new(&client).await?;
         let stmt_insert_external_link = InsertExternalLogin::new(&client).await?;
         let stmt_insert_token = InsertToken::new(&client).await?;
+        let stmt_list_links = ListLinks::new(&client).await?;
+        let stmt_delete_link = DeleteLink::new(&client).await?;
         let stmt_cascaded_delete = CascadedDelete::new(&client).await?;
+        let stmt_anonymize = Anonymize::new(&client).await?;
+        let stmt_update_name = UpdateName::new(&client).await?;
         let stmt_find_by_id = FindById::new(&client).await?;
         let stmt_find_by_email = FindByEmail::new(&client).await?;
         let stmt_find_by_name = FindByName::new(&client).await?;
@@ -255,13 +666,38 @@ impl IdentityManager {
         let stmt_find_by_token = FindByToken::new(&client).await?;
         let stmt_delete_token = DeleteToken::new(&client).await?;
         let stmt_delete_all_tokens = DeleteAllTokens::new(&client).await?;
+        let stmt_delete_token_by_id = DeleteTokenById::new(&client).await?;
+        let stmt_list_tokens = ListTokens::new(&client).await?;
+        let stmt_touch_token = TouchToken::new(&client).await?;
 
-        Ok(Self(Arc::new(Inner {
-            postgres: pool.postgres.clone(),
+        // replace the plain tokens created before the hashing was introduced
+        {
+            let stmt_list = ListPlainTokens::new(&client).await?;
+            let stmt_list = stmt_list.get(&client).await?;
+            let stmt_hash = HashPlainToken::new(&client).await?;
+            let stmt_hash = stmt_hash.get(&client).await?;
+
+            let rows = client.query(&stmt_list, &[]).await?;
+            if !rows.is_empty() {
+                log::info!("Hashing {} plain login tokens", rows.len());
+            }
+            for row in rows {
+                let token: String = row.get(0);
+                let token_hash = IdentityManager::hash_token_with_key(token_key, &token);
+                client.execute(&stmt_hash, &[&token, &token_hash]).await?;
+            }
+        }
+
+        Ok(Self {
+            postgres: postgres.clone(),
             stmt_insert_identity,
             stmt_insert_external_link,
             stmt_insert_token,
+            stmt_list_links,
+            stmt_delete_link,
             stmt_cascaded_delete,
+            stmt_anonymize,
+            stmt_update_name,
             stmt_find_by_id,
             stmt_find_by_email,
             stmt_find_by_name,
@@ -269,31 +705,207 @@ impl IdentityManager {
             stmt_find_by_token,
             stmt_delete_token,
             stmt_delete_all_tokens,
+            stmt_delete_token_by_id,
+            stmt_list_tokens,
+            stmt_touch_token,
+        })
+    }
+}
+
+/// The number of attempts of a transaction failing with a serialization conflict.
+const MAX_TRANSACTION_ATTEMPTS: usize = 3;
+
+fn is_transaction_conflict(err: &IdentityError) -> bool {
+    match err {
+        IdentityError::DBError(DBError::PostgresError(err)) => matches!(
+            err.code(),
+            Some(&SqlState::T_R_SERIALIZATION_FAILURE) | Some(&SqlState::T_R_DEADLOCK_DETECTED)
+        ),
+        _ => false,
+    }
+}
+
+/// The statements are prepared on the first use, thus the service can start while the database is unavailable. A
+/// failed preparation is retried by the next query. The prepared statements are bound to the connections, `get` of
+/// the statements prepares them again for a new connection, ex. after a reconnect or a failover.
+struct LazyPgStore {
+    postgres: PGConnectionPool,
+    token_key: hmac::Key,
+    store: OnceCell<PgStore>,
+}
+
+impl LazyPgStore {
+    fn new(postgres: &PGConnectionPool, token_key: &hmac::Key) -> Self {
+        Self {
+            postgres: postgres.clone(),
+            token_key: token_key.clone(),
+            store: OnceCell::new(),
+        }
+    }
+
+    async fn get(&self) -> Result<&PgStore, DBError> {
+        self.store
+            .get_or_try_init(|| PgStore::new(&self.postgres, &self.token_key))
+            .await
+    }
+}
+
+enum Store {
+    Postgres(LazyPgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    email_normalizer: EmailNormalizer,
+    token_key: hmac::Key,
+    clock: SharedClock,
+    ids: SharedIdGenerator,
+    faults: FaultInjector,
+    query_timeout: Duration,
+}
+
+impl Inner {
+    /// Run a Postgres query with the configured timeout. A query running too long is cancelled on the server,
+    /// thus a stuck query can't hold a pooled connection forever.
+    async fn timeout<T, F>(
+        &self,
+        cancel_token: CancelToken,
+        query: F,
+    ) -> Result<Result<T, tokio_postgres::Error>, DBError>
+    where
+        F: Future<Output = Result<T, tokio_postgres::Error>>,
+    {
+        match tokio::time::timeout(self.query_timeout, query).await {
+            Ok(result) => Ok(result),
+            Err(_) => {
+                log::warn!("Query timed out after {:?}, cancelling", self.query_timeout);
+                // the cancel request is sent on a new connection without TLS, on the servers requiring TLS it fails
+                // and the query is ended only by the server side statement_timeout (if any)
+                tokio::spawn(async move {
+                    if let Err(err) = cancel_token.cancel_query(NoTls).await {
+                        log::warn!("Failed to cancel the query: {err}");
+                    }
+                });
+                Err(DBError::Timeout)
+            }
+        }
+    }
+
+    /// Run a Postgres transaction and retry it on the serialization failures and deadlocks. These errors are
+    /// transient under the concurrent writes, ex. parallel registrations, and the transaction can be repeated as is.
+    async fn retry_conflicts<T, F, R>(&self, mut transaction: F) -> Result<T, IdentityError>
+    where
+        F: FnMut() -> R,
+        R: Future<Output = Result<T, IdentityError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match transaction().await {
+                Err(err) if attempt < MAX_TRANSACTION_ATTEMPTS && is_transaction_conflict(&err) => {
+                    log::info!("Transaction conflict (attempt {attempt}), retrying: {err}");
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// The identity store backed by the SQL database.
+#[derive(Clone)]
+pub struct IdentityManager(Arc<Inner>);
+
+impl IdentityManager {
+    pub async fn new(
+        pool: &DBPool,
+        email_config: &EmailNormalizationConfig,
+        token_hash_secret: &str,
+        clock: SharedClock,
+        ids: SharedIdGenerator,
+    ) -> Result<Self, IdentityBuildError> {
+        let token_key = {
+            let key = B64
+                .decode(token_hash_secret)
+                .map_err(|err| IdentityBuildError::InvalidTokenSecret(format!("{err}")))?;
+            hmac::Key::new(hmac::HMAC_SHA256, &key)
+        };
+
+        let email_normalizer = EmailNormalizer::new(email_config);
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let store = LazyPgStore::new(postgres, &token_key);
+                if let Err(err) = store.get().await {
+                    log::warn!("Failed to prepare the identity queries, retrying on the first use: {err}");
+                }
+                Store::Postgres(store)
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(Inner {
+            store,
+            email_normalizer,
+            token_key,
+            clock,
+            ids,
+            faults: pool.faults.clone(),
+            query_timeout: pool.query_timeout,
         })))
     }
 
-    pub async fn create_user(
+    fn hash_token_with_key(key: &hmac::Key, token: &str) -> String {
+        hex::encode(hmac::sign(key, token.as_bytes()).as_ref())
+    }
+
+    /// Get the keyed hash of a login token. Only the hash is stored, thus the tokens can't be recovered from
+    /// the database without the key.
+    fn hash_token(&self, token: &str) -> String {
+        Self::hash_token_with_key(&self.0.token_key, token)
+    }
+
+    /// Create the user and the external link in a single transaction, returns the creation time.
+    async fn pg_create_user(
         &self,
+        pg: &PgStore,
+        tenant_id: &str,
         user_id: Uuid,
         user_name: &str,
         email: Option<&str>,
+        normalized_email: Option<&str>,
         external_login: Option<&ExternalLoginInfo>,
-    ) -> Result<Identity, IdentityError> {
-        //let email = email.map(|e| e.normalize_email());
+    ) -> Result<DateTime<Utc>, IdentityError> {
         let inner = &*self.0;
+        let mut client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+        let stmt_insert_identity = pg.stmt_insert_identity.get(&client).await?;
+        let stmt_insert_external_link = pg.stmt_insert_external_link.get(&client).await?;
 
-        let mut client = inner.postgres.get().await.map_err(DBError::PostgresPoolError)?;
-        let stmt_insert_identity = inner.stmt_insert_identity.get(&client).await?;
-        let stmt_insert_external_link = inner.stmt_insert_external_link.get(&client).await?;
-
+        let cancel_token = client.cancel_token();
         let transaction = client.transaction().await?;
 
-        let created_at: DateTime<Utc> = match transaction
-            .query_one(
-                &stmt_insert_identity,
-                &[&user_id, &IdentityKind::User, &user_name, &email],
+        if inner.faults.is_constraint_violated(FaultLayer::Postgres) {
+            log::info!("Conflicting user id: {}, rolling back user creation", user_id);
+            transaction.rollback().await?;
+            return Err(IdentityError::UserIdConflict);
+        }
+
+        let created_at: DateTime<Utc> = match inner
+            .timeout(
+                cancel_token.clone(),
+                transaction.query_one(
+                    &stmt_insert_identity,
+                    &[
+                        &user_id,
+                        &IdentityKind::User,
+                        &user_name,
+                        &normalize_name(user_name),
+                        &email,
+                        &normalized_email,
+                        &tenant_id,
+                    ],
+                ),
             )
-            .await
+            .await?
         {
             Ok(row) => row.get(0),
             Err(err) if err.is_constraint("identities", "identities_pkey") => {
@@ -317,12 +929,20 @@ impl IdentityManager {
         };
 
         if let Some(external_login) = external_login {
-            if let Err(err) = transaction
-                .execute(
-                    &stmt_insert_external_link,
-                    &[&user_id, &external_login.provider, &external_login.provider_id],
+            if let Err(err) = inner
+                .timeout(
+                    cancel_token.clone(),
+                    transaction.execute(
+                        &stmt_insert_external_link,
+                        &[
+                            &user_id,
+                            &external_login.provider,
+                            &external_login.provider_id,
+                            &external_login.tenant_id,
+                        ],
+                    ),
                 )
-                .await
+                .await?
             {
                 if err.is_constraint("external_logins", "idx_provider_provider_id") {
                     transaction.rollback().await?;
@@ -334,222 +954,1017 @@ impl IdentityManager {
         }
 
         transaction.commit().await?;
+        Ok(created_at)
+    }
+}
+
+#[async_trait]
+impl IdentityStore for IdentityManager {
+    fn new_user_id(&self) -> Uuid {
+        self.0.ids.new_id()
+    }
+
+    fn normalize_email(&self, email: &str) -> String {
+        self.0.email_normalizer.normalize(email)
+    }
+
+    async fn create_user(
+        &self,
+        tenant_id: &str,
+        user_id: Uuid,
+        user_name: &str,
+        email: Option<&str>,
+        external_login: Option<&ExternalLoginInfo>,
+    ) -> Result<Identity, IdentityError> {
+        let inner = &*self.0;
+        let normalized_email = email.map(|email| inner.email_normalizer.normalize(email));
+
+        inner.faults.inject(FaultLayer::Postgres).await?;
+        let created_at = match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let normalized_email = normalized_email.as_deref();
+                inner
+                    .retry_conflicts(move || {
+                        self.pg_create_user(
+                            pg,
+                            tenant_id,
+                            user_id,
+                            user_name,
+                            email,
+                            normalized_email,
+                            external_login,
+                        )
+                    })
+                    .await?
+            }
+            Store::Sqlite(sqlite) => {
+                if inner.faults.is_constraint_violated(FaultLayer::Postgres) {
+                    log::info!("Conflicting user id: {}, rolling back user creation", user_id);
+                    return Err(IdentityError::UserIdConflict);
+                }
+
+                let created_at = inner.clock.now();
+                let user_name = user_name.to_owned();
+                let email = email.map(str::to_owned);
+                let normalized_email = normalized_email.clone();
+                let tenant_id = tenant_id.to_owned();
+                let external_login = external_login.cloned();
+                sqlite
+                    .call(move |conn| -> Result<(), IdentityError> {
+                        // the transaction is rolled back when it is dropped without a commit
+                        let transaction = conn.transaction()?;
+
+                        match transaction.execute(
+                            SQLITE_INSERT_IDENTITY,
+                            params![
+                                user_id,
+                                IdentityKind::User,
+                                created_at,
+                                user_name,
+                                normalize_name(&user_name),
+                                email,
+                                normalized_email,
+                                tenant_id
+                            ],
+                        ) {
+                            Ok(_) => {}
+                            Err(err) if err.is_constraint("identities", "user_id") => {
+                                log::info!("Conflicting user id: {}, rolling back user creation", user_id);
+                                return Err(IdentityError::UserIdConflict);
+                            }
+                            Err(err) if err.is_constraint("identities", "normalized_name") => {
+                                log::info!("Conflicting name: {}, rolling back user creation", user_name);
+                                return Err(IdentityError::NameConflict);
+                            }
+                            Err(err) if err.is_constraint("identities", "normalized_email") => {
+                                log::info!("Conflicting email: {}, rolling back user creation", user_id);
+                                return Err(IdentityError::LinkEmailConflict);
+                            }
+                            Err(err) => return Err(err.into()),
+                        };
+
+                        if let Some(external_login) = external_login {
+                            match transaction.execute(
+                                SQLITE_INSERT_EXTERNAL_LOGIN,
+                                params![
+                                    user_id,
+                                    external_login.provider,
+                                    external_login.provider_id,
+                                    created_at,
+                                    external_login.tenant_id
+                                ],
+                            ) {
+                                Ok(_) => {}
+                                Err(err) if err.is_constraint("external_logins", "provider_id") => {
+                                    return Err(IdentityError::LinkProviderConflict);
+                                }
+                                Err(err) => return Err(err.into()),
+                            };
+                        }
+
+                        transaction.commit()?;
+                        Ok(())
+                    })
+                    .await?;
+                created_at
+            }
+        };
+
         Ok(Identity {
             user_id,
             name: user_name.to_owned(),
-            email: email.map(String::from),
+            email: email.map(str::to_owned),
             is_email_confirmed: false,
             kind: IdentityKind::User,
             creation: created_at,
+            tenant_id: tenant_id.to_owned(),
         })
     }
 
-    pub async fn find(&self, find: FindIdentity<'_>) -> Result<Option<Identity>, IdentityError> {
+    async fn find(&self, find: FindIdentity<'_>) -> Result<Option<Identity>, IdentityError> {
         let inner = &*self.0;
-        let client = inner.postgres.get().await.map_err(DBError::PostgresPoolError)?;
-
-        let identity = match find {
-            FindIdentity::UserId(id) => {
-                let stmt = inner.stmt_find_by_id.get(&client).await?;
-                client.query_opt(&stmt, &[&id]).await?
-            }
-            FindIdentity::Email(email) => {
-                let stmt = inner.stmt_find_by_email.get(&client).await?;
-                client.query_opt(&stmt, &[&email]).await?
-            }
-            FindIdentity::Name(name) => {
-                let stmt = inner.stmt_find_by_name.get(&client).await?;
-                client.query_opt(&stmt, &[&name]).await?
-            }
-            FindIdentity::ExternalLogin(external_login) => {
-                let stmt = inner.stmt_find_by_link.get(&client).await?;
-                client
-                    .query_opt(&stmt, &[&external_login.provider, &external_login.provider_id])
-                    .await?
+        inner.faults.inject(FaultLayer::Postgres).await?;
+
+        match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+
+                let identity = match find {
+                    FindIdentity::UserId(id) => {
+                        let stmt = pg.stmt_find_by_id.get(&client).await?;
+                        inner
+                            .timeout(client.cancel_token(), client.query_opt(&stmt, &[&id]))
+                            .await??
+                    }
+                    FindIdentity::Email { tenant_id, email } => {
+                        let stmt = pg.stmt_find_by_email.get(&client).await?;
+                        let email = inner.email_normalizer.normalize(email);
+                        inner
+                            .timeout(client.cancel_token(), client.query_opt(&stmt, &[&tenant_id, &email]))
+                            .await??
+                    }
+                    FindIdentity::Name { tenant_id, name } => {
+                        let stmt = pg.stmt_find_by_name.get(&client).await?;
+                        inner
+                            .timeout(
+                                client.cancel_token(),
+                                client.query_opt(&stmt, &[&tenant_id, &normalize_name(name)]),
+                            )
+                            .await??
+                    }
+                    FindIdentity::ExternalLogin(external_login) => {
+                        let stmt = pg.stmt_find_by_link.get(&client).await?;
+                        inner
+                            .timeout(
+                                client.cancel_token(),
+                                client.query_opt(
+                                    &stmt,
+                                    &[
+                                        &external_login.tenant_id,
+                                        &external_login.provider,
+                                        &external_login.provider_id,
+                                    ],
+                                ),
+                            )
+                            .await??
+                    }
+                    FindIdentity::Token(token) => {
+                        let stmt = pg.stmt_find_by_token.get(&client).await?;
+                        inner
+                            .timeout(
+                                client.cancel_token(),
+                                client.query_opt(&stmt, &[&self.hash_token(token)]),
+                            )
+                            .await??
+                    }
+                };
+
+                if let Some(identity) = identity {
+                    Ok(Some(Identity::from_row(&identity)?))
+                } else {
+                    Ok(None)
+                }
             }
-            FindIdentity::Token(token) => {
-                let stmt = inner.stmt_find_by_token.get(&client).await?;
-                client.query_opt(&stmt, &[&token]).await?
+            Store::Sqlite(sqlite) => {
+                let (query, params): (&'static str, Vec<rusqlite::types::Value>) = match find {
+                    FindIdentity::UserId(id) => (SQLITE_FIND_BY_ID, vec![id.into()]),
+                    FindIdentity::Email { tenant_id, email } => (
+                        SQLITE_FIND_BY_EMAIL,
+                        vec![
+                            tenant_id.to_owned().into(),
+                            inner.email_normalizer.normalize(email).into(),
+                        ],
+                    ),
+                    FindIdentity::Name { tenant_id, name } => (
+                        SQLITE_FIND_BY_NAME,
+                        vec![tenant_id.to_owned().into(), normalize_name(name).into()],
+                    ),
+                    FindIdentity::ExternalLogin(external_login) => (
+                        SQLITE_FIND_BY_LINK,
+                        vec![
+                            external_login.tenant_id.clone().into(),
+                            external_login.provider.clone().into(),
+                            external_login.provider_id.clone().into(),
+                        ],
+                    ),
+                    FindIdentity::Token(token) => (SQLITE_FIND_BY_TOKEN, vec![self.hash_token(token).into()]),
+                };
+
+                sqlite
+                    .call(move |conn| -> Result<Option<Identity>, IdentityError> {
+                        let mut stmt = conn.prepare_cached(query)?;
+                        Ok(stmt
+                            .query_row(params_from_iter(params), Identity::from_sqlite_row)
+                            .optional()?)
+                    })
+                    .await
             }
-        };
-
-        if let Some(identity) = identity {
-            Ok(Some(Identity::from_row(&identity)?))
-        } else {
-            Ok(None)
         }
     }
 
-    pub async fn search(&self, search: SearchIdentity<'_>) -> Result<Vec<Identity>, IdentityError> {
+    async fn search(&self, search: SearchIdentity<'_>) -> Result<SearchIdentityResult, IdentityError> {
         const MAX_COUNT: usize = 100;
+        const MAX_TOTAL: usize = 10_000;
 
-        log::info!("{search:?}");
+        log::debug!("{search:?}");
 
         let inner = &*self.0;
-        let client = inner.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+        inner.faults.inject(FaultLayer::Postgres).await?;
+
+        let normalized_names = search
+            .names
+            .map(|names| names.iter().map(|name| normalize_name(name)).collect::<Vec<_>>());
+        let normalized_emails = search.emails.map(|emails| {
+            emails
+                .iter()
+                .map(|email| inner.email_normalizer.normalize(email))
+                .collect::<Vec<_>>()
+        });
+        let count = usize::min(MAX_COUNT, search.count.unwrap_or(MAX_COUNT));
+
+        // one more item is queried to check if there are more results
+        let (mut items, approx_total) = match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+
+                let filtered = |select: &'static str| {
+                    let mut builder = QueryBuilder::new(select);
+
+                    if let Some(tenant_id) = &search.tenant_id {
+                        builder.and_where(|b| format!("tenant_id = ${b}"), [tenant_id]);
+                    }
+
+                    if let Some(user_ids) = &search.user_ids {
+                        builder.and_where(|b| format!("user_id = ANY(${b})"), [user_ids]);
+                    }
+
+                    if let Some(names) = &normalized_names {
+                        builder.and_where(|b| format!("normalized_name = ANY(${b})"), [names]);
+                    }
+
+                    if let Some(emails) = &normalized_emails {
+                        builder.and_where(|b| format!("normalized_email = ANY(${b})"), [emails]);
+                    }
+
+                    if let Some(tags) = &search.tags {
+                        builder.and_where(
+                            |b| format!("user_id IN (SELECT user_id FROM identity_tags WHERE tag = ANY(${b}))"),
+                            [tags],
+                        );
+                    }
+
+                    builder
+                };
+
+                let mut builder =
+                    filtered("SELECT user_id, kind, name, email, email_confirmed, created, tenant_id FROM identities");
+                match &search.order {
+                    SearchIdentityOrder::UserId(start) => {
+                        if let Some(user_id) = start {
+                            builder.and_where(|b| format!("user_id > ${b}"), [user_id]);
+                        }
+                    }
+                    SearchIdentityOrder::Email(start) => {
+                        if let Some((email, user_id)) = start {
+                            builder.and_where(
+                                |b1, b2| format!("(email > ${b1} OR (email = ${b1} AND user_id > ${b2}))"),
+                                [email, user_id],
+                            );
+                        }
+                        builder.order_by("email");
+                    }
+                    SearchIdentityOrder::Name(start) => {
+                        if let Some((name, user_id)) = start {
+                            builder.and_where(
+                                |b1, b2| format!("(name > ${b1} OR (name = ${b1} AND user_id > ${b2}))"),
+                                [name, user_id],
+                            );
+                        }
+                        builder.order_by("name");
+                    }
+                    SearchIdentityOrder::Created(start) => {
+                        if let Some((created, user_id)) = start {
+                            builder.and_where(
+                                |b1, b2| format!("(created < ${b1} OR (created = ${b1} AND user_id > ${b2}))"),
+                                [created, user_id],
+                            );
+                        }
+                        builder.order_by("created DESC");
+                    }
+                };
+                builder.order_by("user_id");
+                builder.limit(count + 1);
+
+                let (stmt, params) = builder.build();
+                log::info!("{stmt:?}");
+
+                if search.include_total {
+                    // the count ignores the continuation and it is capped, thus it stays cheap for the large tables
+                    let mut builder = filtered("SELECT count(*) FROM (SELECT 1 FROM identities");
+                    builder.limit(MAX_TOTAL);
+                    let (total_stmt, total_params) = builder.build();
+                    let total_stmt = format!("{total_stmt}) AS matches");
+                    log::info!("{total_stmt:?}");
+
+                    let (rows, total) = tokio::try_join!(
+                        inner.timeout(client.cancel_token(), client.query(&stmt, &params)),
+                        inner.timeout(client.cancel_token(), client.query_one(&total_stmt, &total_params)),
+                    )?;
+                    let total: i64 = total?.try_get(0)?;
+                    let items = rows?.iter().map(Identity::from_row).collect::<Result<Vec<_>, _>>()?;
+                    (items, Some(total as usize))
+                } else {
+                    let rows = inner
+                        .timeout(client.cancel_token(), client.query(&stmt, &params))
+                        .await??;
+                    let items = rows.iter().map(Identity::from_row).collect::<Result<Vec<_>, _>>()?;
+                    (items, None)
+                }
+            }
+            Store::Sqlite(sqlite) => {
+                use rusqlite::types::Value;
 
-        let mut builder = QueryBuilder::new("SELECT user_id, kind, name, created FROM identities");
+                fn in_list(column: &str, count: usize) -> String {
+                    format!("{column} IN ({})", vec!["?"; count].join(", "))
+                }
 
-        if let Some(user_ids) = &search.user_ids {
-            builder.and_where(|b| format!("user_id = ANY(${b})"), [user_ids]);
-        }
+                let mut conditions = Vec::new();
+                let mut params: Vec<Value> = Vec::new();
 
-        if let Some(names) = &search.names {
-            builder.and_where(|b| format!("name = ANY(${b})"), [names]);
-        }
+                if let Some(tenant_id) = search.tenant_id {
+                    conditions.push("tenant_id = ?".to_owned());
+                    params.push(tenant_id.to_owned().into());
+                }
 
-        if let Some(emails) = &search.emails {
-            builder.and_where(|b| format!("email = ANY(${b})"), [emails]);
-        }
+                if let Some(user_ids) = search.user_ids {
+                    conditions.push(in_list("user_id", user_ids.len()));
+                    params.extend(user_ids.iter().map(|user_id| Value::from(*user_id)));
+                }
 
-        match &search.order {
-            SearchIdentityOrder::UserId(start) => {
-                if let Some(user_id) = start {
-                    builder.and_where(|b| format!("user_id > ${b}"), [user_id]);
+                if let Some(names) = normalized_names {
+                    conditions.push(in_list("normalized_name", names.len()));
+                    params.extend(names.into_iter().map(Value::from));
                 }
-            }
-            SearchIdentityOrder::Email(start) => {
-                if let Some((email, user_id)) = start {
-                    builder.and_where(
-                        |b1, b2| format!("(email > ${b1} OR (email == ${b1} AND user_id > ${b2}))"),
-                        [email, user_id],
-                    );
+
+                if let Some(emails) = normalized_emails {
+                    conditions.push(in_list("normalized_email", emails.len()));
+                    params.extend(emails.into_iter().map(Value::from));
                 }
-                builder.order_by("email");
-            }
-            SearchIdentityOrder::Name(start) => {
-                if let Some((name, user_id)) = start {
-                    builder.and_where(
-                        |b1, b2| format!("(name > ${b1} OR (name == ${b1} AND user_id > ${b2}))"),
-                        [name, user_id],
-                    );
+
+                if let Some(tags) = search.tags {
+                    conditions.push(format!(
+                        "user_id IN (SELECT user_id FROM identity_tags WHERE {})",
+                        in_list("tag", tags.len())
+                    ));
+                    params.extend(tags.iter().map(|tag| Value::from(tag.clone())));
                 }
-                builder.order_by("name");
+
+                fn where_clause(conditions: &[String]) -> String {
+                    if conditions.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" WHERE {}", conditions.join(" AND "))
+                    }
+                }
+
+                // the count ignores the continuation and it is capped, thus it stays cheap for the large tables
+                let total_query = search.include_total.then(|| {
+                    (
+                        format!(
+                            "SELECT count(*) FROM (SELECT 1 FROM identities{} LIMIT {MAX_TOTAL})",
+                            where_clause(&conditions)
+                        ),
+                        params.clone(),
+                    )
+                });
+
+                let mut order_by = Vec::new();
+                match &search.order {
+                    SearchIdentityOrder::UserId(start) => {
+                        if let Some(user_id) = start {
+                            conditions.push("user_id > ?".to_owned());
+                            params.push((*user_id).into());
+                        }
+                    }
+                    SearchIdentityOrder::Email(start) => {
+                        if let Some((email, user_id)) = start {
+                            conditions.push("(email > ? OR (email = ? AND user_id > ?))".to_owned());
+                            params.extend([email.clone().into(), email.clone().into(), (*user_id).into()]);
+                        }
+                        order_by.push("email");
+                    }
+                    SearchIdentityOrder::Name(start) => {
+                        if let Some((name, user_id)) = start {
+                            conditions.push("(name > ? OR (name = ? AND user_id > ?))".to_owned());
+                            params.extend([name.clone().into(), name.clone().into(), (*user_id).into()]);
+                        }
+                        order_by.push("name");
+                    }
+                    SearchIdentityOrder::Created(start) => {
+                        if let Some((created, user_id)) = start {
+                            conditions.push("(created < ? OR (created = ? AND user_id > ?))".to_owned());
+                            // the same format as the ToSql of the rusqlite chrono types
+                            let created = created.format("%F %T%.f%:z").to_string();
+                            params.extend([created.clone().into(), created.into(), (*user_id).into()]);
+                        }
+                        order_by.push("created DESC");
+                    }
+                };
+                order_by.push("user_id");
+
+                let query = format!(
+                    "SELECT user_id, kind, name, email, email_confirmed, created, tenant_id FROM identities{} \
+                        ORDER BY {} LIMIT {}",
+                    where_clause(&conditions),
+                    order_by.join(", "),
+                    count + 1
+                );
+                log::info!("{query:?}");
+
+                sqlite
+                    .call(move |conn| -> Result<(Vec<Identity>, Option<usize>), IdentityError> {
+                        let mut stmt = conn.prepare(&query)?;
+                        let identities = stmt
+                            .query_map(params_from_iter(params), Identity::from_sqlite_row)?
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let total = match total_query {
+                            Some((total_query, total_params)) => {
+                                let total: i64 =
+                                    conn.query_row(&total_query, params_from_iter(total_params), |row| row.get(0))?;
+                                Some(total as usize)
+                            }
+                            None => None,
+                        };
+                        Ok((identities, total))
+                    })
+                    .await?
             }
         };
-        builder.order_by("user_id");
 
-        let count = usize::min(MAX_COUNT, search.count.unwrap_or(MAX_COUNT));
-        builder.limit(count);
-
-        let (stmt, params) = builder.build();
-        log::info!("{stmt:?}");
-        let rows = client.query(&stmt, &params).await?;
+        let next_cursor = if items.len() > count {
+            items.truncate(count);
+            items.last().and_then(|last| match &search.order {
+                SearchIdentityOrder::UserId(_) => Some(SearchIdentityOrder::UserId(Some(last.user_id))),
+                // the identities without email are ordered last and they can't be continued by the email
+                SearchIdentityOrder::Email(_) => last
+                    .email
+                    .clone()
+                    .map(|email| SearchIdentityOrder::Email(Some((email, last.user_id)))),
+                SearchIdentityOrder::Name(_) => {
+                    Some(SearchIdentityOrder::Name(Some((last.name.clone(), last.user_id))))
+                }
+                SearchIdentityOrder::Created(_) => {
+                    Some(SearchIdentityOrder::Created(Some((last.creation, last.user_id))))
+                }
+            })
+        } else {
+            None
+        };
 
-        let identities = rows
-            .into_iter()
-            .map(|row| Identity::from_row(&row))
-            .collect::<Result<Vec<_>, _>>()?;
-        Ok(identities)
+        Ok(SearchIdentityResult {
+            items,
+            next_cursor,
+            approx_total,
+        })
     }
 
-    pub async fn cascaded_delete(&self, user_id: Uuid) -> Result<(), IdentityError> {
+    async fn cascaded_delete(&self, user_id: Uuid) -> Result<(), IdentityError> {
         let inner = &*self.0;
-        let client = inner.postgres.get().await.map_err(DBError::PostgresPoolError)?;
-        let stmt = inner.stmt_cascaded_delete.get(&client).await?;
+        inner.faults.inject(FaultLayer::Postgres).await?;
 
-        client
-            .execute(&stmt, &[&user_id])
-            .await
-            .map_err(|err| IdentityError::DBError(err.into()))?;
+        match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_cascaded_delete.get(&client).await?;
+
+                inner
+                    .timeout(client.cancel_token(), client.execute(&stmt, &[&user_id]))
+                    .await?
+                    .map_err(|err| IdentityError::DBError(err.into()))?;
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<(), IdentityError> {
+                        conn.execute(SQLITE_CASCADED_DELETE, params![user_id])?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
         Ok(())
     }
 
-    pub async fn link_user(&self, user_id: Uuid, external_login: &ExternalLoginInfo) -> Result<(), IdentityError> {
+    async fn anonymize(&self, user_id: Uuid, placeholder_name: &str) -> Result<(), IdentityError> {
         let inner = &*self.0;
-        let client = inner.postgres.get().await.map_err(DBError::PostgresPoolError)?;
-        let stmt_insert_external_link = inner.stmt_insert_external_link.get(&client).await?;
+        inner.faults.inject(FaultLayer::Postgres).await?;
+
+        let normalized_name = normalize_name(placeholder_name);
+        match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_anonymize.get(&client).await?;
+
+                inner
+                    .timeout(
+                        client.cancel_token(),
+                        client.execute(&stmt, &[&user_id, &placeholder_name, &normalized_name]),
+                    )
+                    .await?
+                    .map_err(|err| IdentityError::DBError(err.into()))?;
+            }
+            Store::Sqlite(sqlite) => {
+                let placeholder_name = placeholder_name.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<(), IdentityError> {
+                        // the transaction is rolled back when it is dropped without a commit
+                        let transaction = conn.transaction()?;
+                        for cleanup in SQLITE_ANONYMIZE_CLEANUP {
+                            transaction.execute(cleanup, params![user_id])?;
+                        }
+                        transaction.execute(SQLITE_ANONYMIZE, params![user_id, placeholder_name, normalized_name])?;
+                        transaction.commit()?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
 
-        match client
-            .execute(
-                &stmt_insert_external_link,
-                &[&user_id, &external_login.provider, &external_login.provider_id],
-            )
-            .await
-        {
-            Ok(_) => Ok(()),
-            Err(err) => {
-                if err.is_constraint("external_logins", "idx_provider_provider_id") {
-                    Err(IdentityError::LinkProviderConflict)
-                } else {
-                    Err(IdentityError::DBError(err.into()))
+    async fn update_name(&self, user_id: Uuid, user_name: &str) -> Result<bool, IdentityError> {
+        let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Postgres).await?;
+
+        match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_update_name.get(&client).await?;
+
+                match inner
+                    .timeout(
+                        client.cancel_token(),
+                        client.execute(&stmt, &[&user_id, &user_name, &normalize_name(user_name)]),
+                    )
+                    .await?
+                {
+                    Ok(count) => Ok(count == 1),
+                    Err(err) => {
+                        if err.is_constraint("identities", "idx_name") {
+                            Err(IdentityError::NameConflict)
+                        } else {
+                            Err(IdentityError::DBError(err.into()))
+                        }
+                    }
                 }
             }
+            Store::Sqlite(sqlite) => {
+                let user_name = user_name.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<bool, IdentityError> {
+                        match conn.execute(
+                            SQLITE_UPDATE_NAME,
+                            params![user_id, user_name, normalize_name(&user_name)],
+                        ) {
+                            Ok(count) => Ok(count == 1),
+                            Err(err) if err.is_constraint("identities", "normalized_name") => {
+                                Err(IdentityError::NameConflict)
+                            }
+                            Err(err) => Err(err.into()),
+                        }
+                    })
+                    .await
+            }
         }
     }
 
-    /*pub async fn unlink_user(&self, user_id: Uuid, external_login: &ExternalLogin) -> Result<(), IdentityError> {
-        todo!()
+    async fn link_user(&self, user_id: Uuid, external_login: &ExternalLoginInfo) -> Result<(), IdentityError> {
+        let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Postgres).await?;
+
+        match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_insert_external_link = pg.stmt_insert_external_link.get(&client).await?;
+                let client = &client;
+                let stmt_insert_external_link = &stmt_insert_external_link;
+
+                inner
+                    .retry_conflicts(move || async move {
+                        match inner
+                            .timeout(
+                                client.cancel_token(),
+                                client.execute(
+                                    stmt_insert_external_link,
+                                    &[
+                                        &user_id,
+                                        &external_login.provider,
+                                        &external_login.provider_id,
+                                        &external_login.tenant_id,
+                                    ],
+                                ),
+                            )
+                            .await?
+                        {
+                            Ok(_) => Ok(()),
+                            Err(err) => {
+                                if err.is_constraint("external_logins", "idx_provider_provider_id") {
+                                    Err(IdentityError::LinkProviderConflict)
+                                } else {
+                                    Err(IdentityError::DBError(err.into()))
+                                }
+                            }
+                        }
+                    })
+                    .await
+            }
+            Store::Sqlite(sqlite) => {
+                let external_login = external_login.clone();
+                let linked_at = inner.clock.now();
+                sqlite
+                    .call(move |conn| -> Result<(), IdentityError> {
+                        match conn.execute(
+                            SQLITE_INSERT_EXTERNAL_LOGIN,
+                            params![
+                                user_id,
+                                external_login.provider,
+                                external_login.provider_id,
+                                linked_at,
+                                external_login.tenant_id
+                            ],
+                        ) {
+                            Ok(_) => Ok(()),
+                            Err(err) if err.is_constraint("external_logins", "provider_id") => {
+                                Err(IdentityError::LinkProviderConflict)
+                            }
+                            Err(err) => Err(err.into()),
+                        }
+                    })
+                    .await
+            }
+        }
     }
 
-    pub async fn get_links(&self, user_id: Uuid) -> Result<Vec<ExternalLogin>, IdentityError> {
-        todo!()
-    }*/
+    async fn unlink_user(&self, user_id: Uuid, provider: &str) -> Result<bool, IdentityError> {
+        let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Postgres).await?;
+
+        let count = match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_delete_link.get(&client).await?;
+                inner
+                    .timeout(client.cancel_token(), client.execute(&stmt, &[&user_id, &provider]))
+                    .await?? as usize
+            }
+            Store::Sqlite(sqlite) => {
+                let provider = provider.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<usize, IdentityError> {
+                        Ok(conn.execute(SQLITE_DELETE_LINK, params![user_id, provider])?)
+                    })
+                    .await?
+            }
+        };
+        Ok(count > 0)
+    }
+
+    async fn get_links(&self, user_id: Uuid) -> Result<Vec<LinkedProvider>, IdentityError> {
+        let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Postgres).await?;
+
+        match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_list_links.get(&client).await?;
+
+                let rows = inner
+                    .timeout(client.cancel_token(), client.query(&stmt, &[&user_id]))
+                    .await??;
+                rows.iter()
+                    .map(|row| {
+                        Ok(LinkedProvider {
+                            provider: row.try_get(0)?,
+                            provider_id: row.try_get(1)?,
+                            linked_at: row.try_get(2)?,
+                        })
+                    })
+                    .collect()
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Vec<LinkedProvider>, IdentityError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_LIST_LINKS)?;
+                        let links = stmt
+                            .query_map(params![user_id], |row| {
+                                Ok(LinkedProvider {
+                                    provider: row.get(0)?,
+                                    provider_id: row.get(1)?,
+                                    linked_at: row.get(2)?,
+                                })
+                            })?
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(links)
+                    })
+                    .await
+            }
+        }
+    }
 
-    pub async fn create_token(
+    async fn create_token(
         &self,
         user_id: Uuid,
         token: &str,
-        duration: &Duration,
+        expire_at: DateTime<Utc>,
+        meta: &TokenMeta<'_>,
     ) -> Result<LoginTokenInfo, IdentityError> {
         let inner = &*self.0;
 
-        let client = inner.postgres.get().await.map_err(DBError::PostgresPoolError)?;
-        let stmt = inner.stmt_insert_token.get(&client).await?;
+        inner.faults.inject(FaultLayer::Postgres).await?;
 
-        let duration = duration.num_seconds() as i32;
-        assert!(duration > 0);
-        let (created_at, expire_at): (DateTime<Utc>, DateTime<Utc>) =
-            match client.query_one(&stmt, &[&user_id, &token, &duration]).await {
-                Ok(row) => (row.get(0), row.get(1)),
-                Err(err) if err.is_constraint("login_tokens", "idx_token") => {
-                    return Err(IdentityError::TokenConflict);
-                }
-                Err(err) => {
+        let token_id = inner.ids.new_id();
+        let token_hash = self.hash_token(token);
+        let created_at = inner.clock.now();
+        assert!(expire_at > created_at);
+        if inner.faults.is_constraint_violated(FaultLayer::Postgres) {
+            return Err(IdentityError::TokenConflict);
+        }
+
+        match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_insert_token.get(&client).await?;
+
+                if let Err(err) = inner
+                    .timeout(
+                        client.cancel_token(),
+                        client.execute(
+                            &stmt,
+                            &[
+                                &user_id,
+                                &token_id,
+                                &meta.kind,
+                                &token_hash,
+                                &meta.name,
+                                &meta.creation_ip,
+                                &meta.user_agent,
+                                &created_at,
+                                &expire_at,
+                            ],
+                        ),
+                    )
+                    .await?
+                {
+                    if err.is_constraint("login_tokens", "idx_token_hash") {
+                        return Err(IdentityError::TokenConflict);
+                    }
                     return Err(IdentityError::DBError(err.into()));
                 }
-            };
+            }
+            Store::Sqlite(sqlite) => {
+                let kind = meta.kind;
+                let token_hash = token_hash.clone();
+                let name = meta.name.map(str::to_owned);
+                let creation_ip = meta.creation_ip.map(|ip| ip.to_string());
+                let user_agent = meta.user_agent.map(str::to_owned);
+                sqlite
+                    .call(move |conn| -> Result<(), IdentityError> {
+                        match conn.execute(
+                            SQLITE_INSERT_TOKEN,
+                            params![
+                                user_id,
+                                token_id,
+                                kind,
+                                token_hash,
+                                name,
+                                creation_ip,
+                                user_agent,
+                                created_at,
+                                expire_at
+                            ],
+                        ) {
+                            Ok(_) => Ok(()),
+                            Err(err) if err.is_constraint("login_tokens", "token_hash") => {
+                                Err(IdentityError::TokenConflict)
+                            }
+                            Err(err) => Err(err.into()),
+                        }
+                    })
+                    .await?;
+            }
+        }
 
         Ok(LoginTokenInfo {
             user_id,
-            token: token.to_owned(),
+            token_id,
+            kind: meta.kind,
+            token_hash,
             created_at,
             expire_at,
             is_expired: false,
+            last_used: None,
+            name: meta.name.map(str::to_owned),
+            creation_ip: meta.creation_ip,
+            user_agent: meta.user_agent.map(str::to_owned),
         })
     }
 
-    pub async fn find_token(&self, token: &str) -> Result<Option<(Identity, LoginTokenInfo)>, IdentityError> {
+    async fn find_token(&self, token: &str) -> Result<Option<(Identity, LoginTokenInfo)>, IdentityError> {
         let inner = &*self.0;
-        let client = inner.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+        inner.faults.inject(FaultLayer::Postgres).await?;
+
+        let token_hash = self.hash_token(token);
+        let now = inner.clock.now();
+        let found = match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_find_by_token.get(&client).await?;
+                match inner
+                    .timeout(client.cancel_token(), client.query_opt(&stmt, &[&token_hash, &now]))
+                    .await??
+                {
+                    Some(row) => Some((Identity::from_row(&row)?, LoginTokenInfo::from_row(&row, 7)?)),
+                    None => None,
+                }
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<_, IdentityError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_FIND_BY_TOKEN)?;
+                        Ok(stmt
+                            .query_row(params![token_hash], |row| {
+                                Ok((
+                                    Identity::from_sqlite_row(row)?,
+                                    LoginTokenInfo::from_sqlite_row(row, 7, now)?,
+                                ))
+                            })
+                            .optional()?)
+                    })
+                    .await?
+            }
+        };
 
-        let stmt = inner.stmt_find_by_token.get(&client).await?;
-        let row = client.query_opt(&stmt, &[&token]).await?;
+        Ok(found)
+    }
 
-        if let Some(row) = row {
-            let identity = Identity::from_row(&row)?;
-            let token_info = LoginTokenInfo::from_find_row(&row)?;
-            Ok(Some((identity, token_info)))
-        } else {
-            Ok(None)
+    async fn delete_token(&self, user_id: Uuid, token: &str) -> Result<(), IdentityError> {
+        let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Postgres).await?;
+
+        let token_hash = self.hash_token(token);
+        match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_delete_token.get(&client).await?;
+                inner
+                    .timeout(client.cancel_token(), client.execute(&stmt, &[&user_id, &token_hash]))
+                    .await??;
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<(), IdentityError> {
+                        conn.execute(SQLITE_DELETE_TOKEN, params![user_id, token_hash])?;
+                        Ok(())
+                    })
+                    .await?;
+            }
         }
+        Ok(())
     }
 
-    pub async fn delete_token(&self, user_id: Uuid, token: &str) -> Result<(), IdentityError> {
+    async fn touch_token(&self, token: &str) -> Result<(), IdentityError> {
         let inner = &*self.0;
-        let client = inner.postgres.get().await.map_err(DBError::PostgresPoolError)?;
-        let stmt = inner.stmt_delete_token.get(&client).await?;
-
-        client.execute(&stmt, &[&user_id, &token]).await?;
+        inner.faults.inject(FaultLayer::Postgres).await?;
+
+        let token_hash = self.hash_token(token);
+        let now = inner.clock.now();
+        match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_touch_token.get(&client).await?;
+                inner
+                    .timeout(client.cancel_token(), client.execute(&stmt, &[&token_hash, &now]))
+                    .await??;
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<(), IdentityError> {
+                        conn.execute(SQLITE_TOUCH_TOKEN, params![token_hash, now])?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
         Ok(())
     }
 
-    pub async fn delete_all_tokens(&self, user_id: Uuid) -> Result<(), IdentityError> {
+    async fn list_tokens(&self, user_id: Uuid) -> Result<Vec<LoginTokenInfo>, IdentityError> {
         let inner = &*self.0;
-        let client = inner.postgres.get().await.map_err(DBError::PostgresPoolError)?;
-        let stmt = inner.stmt_delete_all_tokens.get(&client).await?;
+        inner.faults.inject(FaultLayer::Postgres).await?;
+
+        let now = inner.clock.now();
+        match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_list_tokens.get(&client).await?;
+                let rows = inner
+                    .timeout(client.cancel_token(), client.query(&stmt, &[&user_id, &now]))
+                    .await??;
+                rows.iter().map(|row| LoginTokenInfo::from_row(row, 0)).collect()
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Vec<LoginTokenInfo>, IdentityError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_LIST_TOKENS)?;
+                        let tokens = stmt
+                            .query_map(params![user_id], |row| LoginTokenInfo::from_sqlite_row(row, 0, now))?
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(tokens)
+                    })
+                    .await
+            }
+        }
+    }
 
-        client.execute(&stmt, &[&user_id]).await?;
+    async fn delete_token_by_id(&self, user_id: Uuid, token_id: Uuid) -> Result<bool, IdentityError> {
+        let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Postgres).await?;
+
+        let count = match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_delete_token_by_id.get(&client).await?;
+                inner
+                    .timeout(client.cancel_token(), client.execute(&stmt, &[&user_id, &token_id]))
+                    .await?? as usize
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<usize, IdentityError> {
+                        Ok(conn.execute(SQLITE_DELETE_TOKEN_BY_ID, params![user_id, token_id])?)
+                    })
+                    .await?
+            }
+        };
+        Ok(count > 0)
+    }
+
+    async fn delete_all_tokens(&self, user_id: Uuid) -> Result<(), IdentityError> {
+        let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Postgres).await?;
+
+        match &inner.store {
+            Store::Postgres(pg) => {
+                let pg = pg.get().await?;
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_delete_all_tokens.get(&client).await?;
+                inner
+                    .timeout(client.cancel_token(), client.execute(&stmt, &[&user_id]))
+                    .await??;
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<(), IdentityError> {
+                        conn.execute(SQLITE_DELETE_ALL_TOKENS, params![user_id])?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
         Ok(())
     }
 }
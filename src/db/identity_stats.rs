@@ -0,0 +1,293 @@
+use crate::db::{DBError, DBPool, IdentityKind, SharedClock, SqlPool, SqlitePool};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use thiserror::Error as ThisError;
+
+pg_prepared_statement!( CountByKind => r#"
+    SELECT kind, count(*) FROM identities GROUP BY kind
+"#, [] );
+
+pg_prepared_statement!( CountByProvider => r#"
+    SELECT provider, count(*) FROM external_logins GROUP BY provider ORDER BY provider
+"#, [] );
+
+pg_prepared_statement!( CountByEmailConfirmed => r#"
+    SELECT email_confirmed, count(*) FROM identities WHERE email IS NOT NULL GROUP BY email_confirmed
+"#, [] );
+
+pg_prepared_statement!( CountActive => r#"
+    SELECT count(*) FILTER (WHERE last_seen >= $1), count(*) FILTER (WHERE last_seen >= $2) FROM identities
+"#, [DATE, DATE] );
+
+pg_prepared_statement!( CountRegistrations => r#"
+    SELECT created::date AS day, count(*) FROM identities WHERE created >= $1 GROUP BY day ORDER BY day
+"#, [TIMESTAMPTZ] );
+
+const SQLITE_COUNT_BY_KIND: &str = r#"
+    SELECT kind, count(*) FROM identities GROUP BY kind
+"#;
+
+const SQLITE_COUNT_BY_PROVIDER: &str = r#"
+    SELECT provider, count(*) FROM external_logins GROUP BY provider ORDER BY provider
+"#;
+
+const SQLITE_COUNT_BY_EMAIL_CONFIRMED: &str = r#"
+    SELECT email_confirmed, count(*) FROM identities WHERE email IS NOT NULL GROUP BY email_confirmed
+"#;
+
+const SQLITE_COUNT_ACTIVE: &str = r#"
+    SELECT count(*) FILTER (WHERE last_seen >= ?1), count(*) FILTER (WHERE last_seen >= ?2) FROM identities
+"#;
+
+const SQLITE_COUNT_REGISTRATIONS: &str = r#"
+    SELECT date(created) AS day, count(*) FROM identities WHERE created >= ?1 GROUP BY day ORDER BY day
+"#;
+
+/// The number of days counted for the monthly active users, including today.
+const MONTHLY_ACTIVE_DAYS: i64 = 30;
+
+/// The statistics are cached for this duration.
+const CACHE_DURATION_SECONDS: i64 = 60;
+
+#[derive(Debug, ThisError)]
+pub enum IdentityStatsBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for IdentityStatsBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KindCount {
+    pub kind: IdentityKind,
+    pub count: i64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderCount {
+    pub provider: String,
+    pub count: i64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyRegistrations {
+    pub day: NaiveDate,
+    pub count: i64,
+}
+
+/// Aggregated statistics of the identities.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentityStats {
+    pub computed_at: DateTime<Utc>,
+    pub by_kind: Vec<KindCount>,
+    pub by_provider: Vec<ProviderCount>,
+    pub confirmed_emails: i64,
+    pub unconfirmed_emails: i64,
+    /// Users active today, the activity is written to the database with some delay.
+    pub daily_active: i64,
+    /// Users active in the last 30 days.
+    pub monthly_active: i64,
+    /// Registrations of the last days, the days without registration are omitted.
+    pub registrations: Vec<DailyRegistrations>,
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_count_by_kind: CountByKind,
+    stmt_count_by_provider: CountByProvider,
+    stmt_count_by_email_confirmed: CountByEmailConfirmed,
+    stmt_count_active: CountActive,
+    stmt_count_registrations: CountRegistrations,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    clock: SharedClock,
+    /// The cached statistics by the number of days of the registrations.
+    cache: Mutex<HashMap<u32, IdentityStats>>,
+}
+
+/// Compute the statistics of the identities for the admin endpoints.
+#[derive(Clone)]
+pub struct IdentityStatsManager(Arc<Inner>);
+
+impl IdentityStatsManager {
+    pub async fn new(pool: &DBPool, clock: SharedClock) -> Result<Self, IdentityStatsBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_count_by_kind: CountByKind::new(&client).await?,
+                    stmt_count_by_provider: CountByProvider::new(&client).await?,
+                    stmt_count_by_email_confirmed: CountByEmailConfirmed::new(&client).await?,
+                    stmt_count_active: CountActive::new(&client).await?,
+                    stmt_count_registrations: CountRegistrations::new(&client).await?,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(Inner {
+            store,
+            clock,
+            cache: Mutex::new(HashMap::new()),
+        })))
+    }
+
+    /// Get the statistics with the registrations of the last `days` days. The result is cached for a minute.
+    pub async fn get(&self, days: u32) -> Result<IdentityStats, DBError> {
+        let inner = &*self.0;
+        let now = inner.clock.now();
+
+        if let Some(stats) = inner.cache.lock().unwrap().get(&days) {
+            if stats.computed_at + Duration::seconds(CACHE_DURATION_SECONDS) > now {
+                return Ok(stats.clone());
+            }
+        }
+
+        let stats = self.compute(days, now).await?;
+        inner.cache.lock().unwrap().insert(days, stats.clone());
+        Ok(stats)
+    }
+
+    async fn compute(&self, days: u32, now: DateTime<Utc>) -> Result<IdentityStats, DBError> {
+        let since = now - Duration::days(i64::from(days));
+        let today = now.date_naive();
+        let month_start = today - Duration::days(MONTHLY_ACTIVE_DAYS - 1);
+
+        let (by_kind, by_provider, by_email_confirmed, (daily_active, monthly_active), registrations) =
+            match &self.0.store {
+                Store::Postgres(pg) => {
+                    let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                    let stmt_count_by_kind = pg.stmt_count_by_kind.get(&client).await?;
+                    let stmt_count_by_provider = pg.stmt_count_by_provider.get(&client).await?;
+                    let stmt_count_by_email_confirmed = pg.stmt_count_by_email_confirmed.get(&client).await?;
+                    let stmt_count_active = pg.stmt_count_active.get(&client).await?;
+                    let stmt_count_registrations = pg.stmt_count_registrations.get(&client).await?;
+
+                    let (by_kind, by_provider, by_email_confirmed, active, registrations) = tokio::try_join!(
+                        client.query(&stmt_count_by_kind, &[]),
+                        client.query(&stmt_count_by_provider, &[]),
+                        client.query(&stmt_count_by_email_confirmed, &[]),
+                        client.query_one(&stmt_count_active, &[&today, &month_start]),
+                        client.query(&stmt_count_registrations, &[&since]),
+                    )?;
+
+                    (
+                        by_kind
+                            .iter()
+                            .map(|row| {
+                                Ok(KindCount {
+                                    kind: row.try_get(0)?,
+                                    count: row.try_get(1)?,
+                                })
+                            })
+                            .collect::<Result<Vec<_>, DBError>>()?,
+                        by_provider
+                            .iter()
+                            .map(|row| {
+                                Ok(ProviderCount {
+                                    provider: row.try_get(0)?,
+                                    count: row.try_get(1)?,
+                                })
+                            })
+                            .collect::<Result<Vec<_>, DBError>>()?,
+                        by_email_confirmed
+                            .iter()
+                            .map(|row| Ok((row.try_get(0)?, row.try_get(1)?)))
+                            .collect::<Result<Vec<(bool, i64)>, DBError>>()?,
+                        (active.try_get(0)?, active.try_get(1)?),
+                        registrations
+                            .iter()
+                            .map(|row| {
+                                Ok(DailyRegistrations {
+                                    day: row.try_get(0)?,
+                                    count: row.try_get(1)?,
+                                })
+                            })
+                            .collect::<Result<Vec<_>, DBError>>()?,
+                    )
+                }
+                Store::Sqlite(sqlite) => {
+                    sqlite
+                        .call(move |conn| -> Result<_, DBError> {
+                            let by_kind = conn
+                                .prepare(SQLITE_COUNT_BY_KIND)?
+                                .query_map([], |row| {
+                                    Ok(KindCount {
+                                        kind: row.get(0)?,
+                                        count: row.get(1)?,
+                                    })
+                                })?
+                                .collect::<Result<Vec<_>, _>>()?;
+                            let by_provider = conn
+                                .prepare(SQLITE_COUNT_BY_PROVIDER)?
+                                .query_map([], |row| {
+                                    Ok(ProviderCount {
+                                        provider: row.get(0)?,
+                                        count: row.get(1)?,
+                                    })
+                                })?
+                                .collect::<Result<Vec<_>, _>>()?;
+                            let by_email_confirmed = conn
+                                .prepare(SQLITE_COUNT_BY_EMAIL_CONFIRMED)?
+                                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                                .collect::<Result<Vec<(bool, i64)>, _>>()?;
+                            let active: (i64, i64) =
+                                conn.query_row(SQLITE_COUNT_ACTIVE, (today, month_start), |row| {
+                                    Ok((row.get(0)?, row.get(1)?))
+                                })?;
+                            let registrations = conn
+                                .prepare(SQLITE_COUNT_REGISTRATIONS)?
+                                .query_map([since], |row| {
+                                    Ok(DailyRegistrations {
+                                        day: row.get(0)?,
+                                        count: row.get(1)?,
+                                    })
+                                })?
+                                .collect::<Result<Vec<_>, _>>()?;
+                            Ok((by_kind, by_provider, by_email_confirmed, active, registrations))
+                        })
+                        .await?
+                }
+            };
+
+        let email_count = |confirmed: bool| {
+            by_email_confirmed
+                .iter()
+                .filter(|(is_confirmed, _)| *is_confirmed == confirmed)
+                .map(|(_, count)| count)
+                .sum::<i64>()
+        };
+
+        Ok(IdentityStats {
+            computed_at: now,
+            by_kind,
+            by_provider,
+            confirmed_emails: email_count(true),
+            unconfirmed_emails: email_count(false),
+            daily_active,
+            monthly_active,
+            registrations,
+        })
+    }
+}
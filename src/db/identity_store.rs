@@ -0,0 +1,78 @@
+use crate::db::{
+    ExternalLoginInfo, FindIdentity, Identity, IdentityError, LinkedProvider, LoginTokenInfo, SearchIdentity,
+    SearchIdentityResult, TokenMeta,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Storage of the identities, their external links and login tokens. The services depend only on this trait,
+/// thus alternative stores (ex. CockroachDB, in-memory for the tests) can be provided. The default
+/// implementation is the `IdentityManager` backed by Postgres (or SQLite).
+#[async_trait]
+pub trait IdentityStore: 'static + Send + Sync {
+    /// Generate the id of a new user.
+    fn new_user_id(&self) -> Uuid;
+
+    /// Get the normalized form of an email, the different forms of the same mailbox have the same normalized form.
+    fn normalize_email(&self, email: &str) -> String;
+
+    /// Create a new user optionally linked to an external provider. The user and the link are created
+    /// atomically.
+    async fn create_user(
+        &self,
+        tenant_id: &str,
+        user_id: Uuid,
+        user_name: &str,
+        email: Option<&str>,
+        external_login: Option<&ExternalLoginInfo>,
+    ) -> Result<Identity, IdentityError>;
+
+    async fn find(&self, find: FindIdentity<'_>) -> Result<Option<Identity>, IdentityError>;
+
+    /// Search the identities, the result is paginated by the order of the search.
+    async fn search(&self, search: SearchIdentity<'_>) -> Result<SearchIdentityResult, IdentityError>;
+
+    /// Delete a user with all the links and tokens.
+    async fn cascaded_delete(&self, user_id: Uuid) -> Result<(), IdentityError>;
+
+    /// Remove the personal data and all the login methods of a user, but keep the user id, thus the references
+    /// of the other services remain valid. The name is replaced by the given placeholder.
+    async fn anonymize(&self, user_id: Uuid, placeholder_name: &str) -> Result<(), IdentityError>;
+
+    /// Change the name of a user. Returns false if the user was not found.
+    async fn update_name(&self, user_id: Uuid, user_name: &str) -> Result<bool, IdentityError>;
+
+    async fn link_user(&self, user_id: Uuid, external_login: &ExternalLoginInfo) -> Result<(), IdentityError>;
+
+    /// Remove the link of a provider. The last login method (link or password) of the user is never removed.
+    /// Returns false if the provider was not linked or it is the last login method.
+    async fn unlink_user(&self, user_id: Uuid, provider: &str) -> Result<bool, IdentityError>;
+
+    async fn get_links(&self, user_id: Uuid) -> Result<Vec<LinkedProvider>, IdentityError>;
+
+    async fn create_token(
+        &self,
+        user_id: Uuid,
+        token: &str,
+        expire_at: DateTime<Utc>,
+        meta: &TokenMeta<'_>,
+    ) -> Result<LoginTokenInfo, IdentityError>;
+
+    async fn find_token(&self, token: &str) -> Result<Option<(Identity, LoginTokenInfo)>, IdentityError>;
+
+    async fn delete_token(&self, user_id: Uuid, token: &str) -> Result<(), IdentityError>;
+
+    /// Record the use of a token.
+    async fn touch_token(&self, token: &str) -> Result<(), IdentityError>;
+
+    async fn list_tokens(&self, user_id: Uuid) -> Result<Vec<LoginTokenInfo>, IdentityError>;
+
+    /// Delete a token of a user by its id, returns false if no such token was found.
+    async fn delete_token_by_id(&self, user_id: Uuid, token_id: Uuid) -> Result<bool, IdentityError>;
+
+    async fn delete_all_tokens(&self, user_id: Uuid) -> Result<(), IdentityError>;
+}
+
+pub type SharedIdentityStore = Arc<dyn IdentityStore>;
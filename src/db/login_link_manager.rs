@@ -0,0 +1,69 @@
+use crate::db::{DBError, DBPool};
+use chrono::Duration;
+use redis::AsyncCommands;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use shine_service::service::RedisConnectionPool;
+use uuid::Uuid;
+
+/// A single-use login link issued for a user by someone else, ex. by the support.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginLink {
+    pub user_id: Uuid,
+    pub issued_by: Uuid,
+    /// The user has to enter the email of the account to complete the login.
+    pub confirm_email: bool,
+}
+
+/// Manage the login links, only the hash of the link tokens is stored.
+#[derive(Clone)]
+pub struct LoginLinkManager {
+    redis: RedisConnectionPool,
+    key_prefix: String,
+}
+
+impl LoginLinkManager {
+    pub fn new(pool: &DBPool) -> Self {
+        Self {
+            redis: pool.redis.clone(),
+            key_prefix: pool.redis_key_prefix.clone(),
+        }
+    }
+
+    fn key(&self, token: &str) -> String {
+        let hash = digest::digest(&digest::SHA256, token.as_bytes());
+        format!("{}login-link:{}", self.key_prefix, hex::encode(hash.as_ref()))
+    }
+
+    pub async fn create(&self, token: &str, link: &LoginLink, duration: Duration) -> Result<(), DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+        let link = serde_json::to_string(link).expect("Failed to serialize login link");
+        let _: () = client
+            .set_ex(self.key(token), link, duration.num_seconds() as usize)
+            .await
+            .map_err(DBError::RedisError)?;
+        Ok(())
+    }
+
+    /// Get a link without consuming it.
+    pub async fn peek(&self, token: &str) -> Result<Option<LoginLink>, DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+        let link: Option<String> = client.get(self.key(token)).await.map_err(DBError::RedisError)?;
+        Ok(link.and_then(|link| serde_json::from_str(&link).ok()))
+    }
+
+    /// Consume a link, it can be taken only once.
+    pub async fn take(&self, token: &str) -> Result<Option<LoginLink>, DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+        let key = self.key(token);
+        let link: Option<String> = client.get(&key).await.map_err(DBError::RedisError)?;
+        let deleted: u32 = client.del(&key).await.map_err(DBError::RedisError)?;
+
+        // when the key was deleted concurrently, the link has already been used
+        if deleted == 0 {
+            return Ok(None);
+        }
+        Ok(link.and_then(|link| serde_json::from_str(&link).ok()))
+    }
+}
@@ -0,0 +1,446 @@
+use crate::db::{DBError, DBPool, SharedClock, SqlPool, SqlitePool};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, OptionalExtension};
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+pg_prepared_statement!( InsertMergeRequest => r#"
+    INSERT INTO merge_requests (request_id, user_id, source_user_id, provider, created)
+        VALUES ($1, $2, $3, $4, $5)
+    ON CONFLICT (user_id, source_user_id) DO UPDATE SET provider = merge_requests.provider
+    RETURNING request_id
+"#, [UUID, UUID, UUID, VARCHAR, TIMESTAMPTZ] );
+
+pg_prepared_statement!( ListMergeRequests => r#"
+    SELECT request_id, user_id, source_user_id, provider, created
+        FROM merge_requests
+        ORDER BY created
+        LIMIT $1
+"#, [INT8] );
+
+pg_prepared_statement!( FindMergeSource => r#"
+    SELECT email, normalized_email, email_confirmed, profile_image,
+           EXISTS(SELECT 1 FROM legal_holds h WHERE h.user_id = $1)
+        FROM identities
+        WHERE user_id = $1
+        FOR UPDATE
+"#, [UUID] );
+
+// The rows of the source with a unique counterpart at the target are left behind and removed with the source.
+pg_prepared_statement!( MoveMergeSource => r#"
+    WITH
+        mv_links AS (UPDATE external_logins SET user_id = $1 WHERE user_id = $2),
+        mv_tokens AS (UPDATE login_tokens SET user_id = $1 WHERE user_id = $2),
+        mv_passwords AS (
+            UPDATE passwords SET user_id = $1
+                WHERE user_id = $2 AND NOT EXISTS (SELECT 1 FROM passwords p WHERE p.user_id = $1)),
+        mv_mfa AS (
+            UPDATE mfa_methods SET user_id = $1
+                WHERE user_id = $2
+                    AND method NOT IN (SELECT m.method FROM mfa_methods m WHERE m.user_id = $1)),
+        mv_known_devices AS (
+            UPDATE known_devices SET user_id = $1
+                WHERE user_id = $2
+                    AND NOT EXISTS (SELECT 1 FROM known_devices d
+                        WHERE d.user_id = $1 AND d.fingerprint = known_devices.fingerprint
+                            AND d.country = known_devices.country)),
+        mv_trusted_devices AS (UPDATE trusted_devices SET user_id = $1 WHERE user_id = $2),
+        mv_roles AS (
+            UPDATE user_roles SET user_id = $1
+                WHERE user_id = $2 AND role NOT IN (SELECT r.role FROM user_roles r WHERE r.user_id = $1)),
+        mv_grants AS (
+            UPDATE grants SET user_id = $1
+                WHERE user_id = $2
+                    AND NOT EXISTS (SELECT 1 FROM grants g
+                        WHERE g.user_id = $1 AND g.action = grants.action
+                            AND g.resource_type = grants.resource_type AND g.resource_id = grants.resource_id)),
+        mv_consents AS (
+            UPDATE consents SET user_id = $1
+                WHERE user_id = $2 AND purpose NOT IN (SELECT c.purpose FROM consents c WHERE c.user_id = $1)),
+        mv_tombstones AS (UPDATE identity_tombstones SET user_id = $1 WHERE user_id = $2)
+    INSERT INTO identity_tombstones (old_user_id, user_id, merged) VALUES ($2, $1, $3)
+"#, [UUID, UUID, TIMESTAMPTZ] );
+
+pg_prepared_statement!( DeleteMergeSource => r#"
+    DELETE FROM identities WHERE user_id = $1
+"#, [UUID] );
+
+pg_prepared_statement!( UpdateMergeTarget => r#"
+    UPDATE identities
+        SET email = COALESCE(email, $2),
+            normalized_email = CASE WHEN email IS NULL THEN $3 ELSE normalized_email END,
+            email_confirmed = CASE WHEN email IS NULL THEN $4 ELSE email_confirmed END,
+            profile_image = COALESCE(profile_image, $5)
+        WHERE user_id = $1
+"#, [UUID, VARCHAR, VARCHAR, BOOL, TEXT] );
+
+pg_prepared_statement!( FindTombstone => r#"
+    SELECT user_id FROM identity_tombstones WHERE old_user_id = $1
+"#, [UUID] );
+
+const SQLITE_INSERT_MERGE_REQUEST: &str = r#"
+    INSERT INTO merge_requests (request_id, user_id, source_user_id, provider, created)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+    ON CONFLICT (user_id, source_user_id) DO UPDATE SET provider = merge_requests.provider
+    RETURNING request_id
+"#;
+
+const SQLITE_LIST_MERGE_REQUESTS: &str = r#"
+    SELECT request_id, user_id, source_user_id, provider, created
+        FROM merge_requests
+        ORDER BY created
+        LIMIT ?1
+"#;
+
+const SQLITE_FIND_MERGE_SOURCE: &str = r#"
+    SELECT email, normalized_email, email_confirmed, profile_image,
+           EXISTS(SELECT 1 FROM legal_holds h WHERE h.user_id = ?1)
+        FROM identities
+        WHERE user_id = ?1
+"#;
+
+/// The data moved from the source to the target by the merge, see `MoveMergeSource`.
+const SQLITE_MOVE_MERGE_SOURCE: &[&str] = &[
+    "UPDATE external_logins SET user_id = ?1 WHERE user_id = ?2",
+    "UPDATE login_tokens SET user_id = ?1 WHERE user_id = ?2",
+    r#"UPDATE passwords SET user_id = ?1
+        WHERE user_id = ?2 AND NOT EXISTS (SELECT 1 FROM passwords p WHERE p.user_id = ?1)"#,
+    r#"UPDATE mfa_methods SET user_id = ?1
+        WHERE user_id = ?2 AND method NOT IN (SELECT m.method FROM mfa_methods m WHERE m.user_id = ?1)"#,
+    r#"UPDATE known_devices SET user_id = ?1
+        WHERE user_id = ?2
+            AND NOT EXISTS (SELECT 1 FROM known_devices d
+                WHERE d.user_id = ?1 AND d.fingerprint = known_devices.fingerprint
+                    AND d.country = known_devices.country)"#,
+    "UPDATE trusted_devices SET user_id = ?1 WHERE user_id = ?2",
+    r#"UPDATE user_roles SET user_id = ?1
+        WHERE user_id = ?2 AND role NOT IN (SELECT r.role FROM user_roles r WHERE r.user_id = ?1)"#,
+    r#"UPDATE grants SET user_id = ?1
+        WHERE user_id = ?2
+            AND NOT EXISTS (SELECT 1 FROM grants g
+                WHERE g.user_id = ?1 AND g.action = grants.action
+                    AND g.resource_type = grants.resource_type AND g.resource_id = grants.resource_id)"#,
+    r#"UPDATE consents SET user_id = ?1
+        WHERE user_id = ?2 AND purpose NOT IN (SELECT c.purpose FROM consents c WHERE c.user_id = ?1)"#,
+    "UPDATE identity_tombstones SET user_id = ?1 WHERE user_id = ?2",
+];
+
+const SQLITE_INSERT_TOMBSTONE: &str = r#"
+    INSERT INTO identity_tombstones (old_user_id, user_id, merged) VALUES (?2, ?1, ?3)
+"#;
+
+const SQLITE_DELETE_MERGE_SOURCE: &str = r#"
+    DELETE FROM identities WHERE user_id = ?1
+"#;
+
+const SQLITE_UPDATE_MERGE_TARGET: &str = r#"
+    UPDATE identities
+        SET email = COALESCE(email, ?2),
+            normalized_email = CASE WHEN email IS NULL THEN ?3 ELSE normalized_email END,
+            email_confirmed = CASE WHEN email IS NULL THEN ?4 ELSE email_confirmed END,
+            profile_image = COALESCE(profile_image, ?5)
+        WHERE user_id = ?1
+"#;
+
+const SQLITE_FIND_TOMBSTONE: &str = r#"
+    SELECT user_id FROM identity_tombstones WHERE old_user_id = ?1
+"#;
+
+/// A request of a user to merge another identity into their own.
+#[derive(Debug)]
+pub struct MergeRequestInfo {
+    pub request_id: Uuid,
+    pub user_id: Uuid,
+    pub source_user_id: Uuid,
+    /// The provider of the link conflict the request was created from.
+    pub provider: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl MergeRequestInfo {
+    fn from_sqlite_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            request_id: row.get(0)?,
+            user_id: row.get(1)?,
+            source_user_id: row.get(2)?,
+            provider: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    }
+}
+
+/// The attributes of the source identity taken over by the target, if the target has none.
+struct MergeSource {
+    email: Option<String>,
+    normalized_email: Option<String>,
+    email_confirmed: bool,
+    profile_image: Option<String>,
+    is_held: bool,
+}
+
+#[derive(Debug, ThisError)]
+pub enum MergeError {
+    #[error("Source user not found")]
+    SourceNotFound,
+    #[error("Source user is under legal hold")]
+    LegalHold,
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for MergeError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+impl From<rusqlite::Error> for MergeError {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum MergeBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for MergeBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_insert_merge_request: InsertMergeRequest,
+    stmt_list_merge_requests: ListMergeRequests,
+    stmt_find_merge_source: FindMergeSource,
+    stmt_move_merge_source: MoveMergeSource,
+    stmt_delete_merge_source: DeleteMergeSource,
+    stmt_update_merge_target: UpdateMergeTarget,
+    stmt_find_tombstone: FindTombstone,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    clock: SharedClock,
+}
+
+/// Merge of the identities of the same person, ex. when an external account has been registered as a separate
+/// identity. The users can only request a merge, the requests are resolved by the administrators.
+#[derive(Clone)]
+pub struct MergeManager(Arc<Inner>);
+
+impl MergeManager {
+    pub async fn new(pool: &DBPool, clock: SharedClock) -> Result<Self, MergeBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_insert_merge_request = InsertMergeRequest::new(&client).await?;
+                let stmt_list_merge_requests = ListMergeRequests::new(&client).await?;
+                let stmt_find_merge_source = FindMergeSource::new(&client).await?;
+                let stmt_move_merge_source = MoveMergeSource::new(&client).await?;
+                let stmt_delete_merge_source = DeleteMergeSource::new(&client).await?;
+                let stmt_update_merge_target = UpdateMergeTarget::new(&client).await?;
+                let stmt_find_tombstone = FindTombstone::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_insert_merge_request,
+                    stmt_list_merge_requests,
+                    stmt_find_merge_source,
+                    stmt_move_merge_source,
+                    stmt_delete_merge_source,
+                    stmt_update_merge_target,
+                    stmt_find_tombstone,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(Inner { store, clock })))
+    }
+
+    /// Request the merge of the source identity into the identity of the user. A repeated request returns the id
+    /// of the existing request.
+    pub async fn create_request(&self, user_id: Uuid, source_user_id: Uuid, provider: &str) -> Result<Uuid, DBError> {
+        let request_id = Uuid::new_v4();
+        let now = self.0.clock.now();
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_insert_merge_request.get(&client).await?;
+                let row = client
+                    .query_one(&stmt, &[&request_id, &user_id, &source_user_id, &provider, &now])
+                    .await?;
+                Ok(row.get(0))
+            }
+            Store::Sqlite(sqlite) => {
+                let provider = provider.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<Uuid, DBError> {
+                        Ok(conn.query_row(
+                            SQLITE_INSERT_MERGE_REQUEST,
+                            params![request_id, user_id, source_user_id, provider, now],
+                            |row| row.get(0),
+                        )?)
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Get the pending merge requests, the oldest first.
+    pub async fn list_requests(&self, limit: usize) -> Result<Vec<MergeRequestInfo>, DBError> {
+        let limit = limit as i64;
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_list_merge_requests.get(&client).await?;
+                let rows = client.query(&stmt, &[&limit]).await?;
+                Ok(rows
+                    .into_iter()
+                    .map(|row| MergeRequestInfo {
+                        request_id: row.get(0),
+                        user_id: row.get(1),
+                        source_user_id: row.get(2),
+                        provider: row.get(3),
+                        created_at: row.get(4),
+                    })
+                    .collect())
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Vec<MergeRequestInfo>, DBError> {
+                        let mut stmt = conn.prepare(SQLITE_LIST_MERGE_REQUESTS)?;
+                        let requests = stmt
+                            .query_map(params![limit], MergeRequestInfo::from_sqlite_row)?
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(requests)
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Merge the source identity into the user. The login methods, tokens, devices, roles, grants and consents
+    /// are moved to the user, the email and profile image are taken over only if the user has none. The source
+    /// identity is deleted and a tombstone maps its id to the user. The caller is responsible for the sessions of
+    /// the source and the notification of the other services.
+    pub async fn merge(&self, user_id: Uuid, source_user_id: Uuid) -> Result<(), MergeError> {
+        let now = self.0.clock.now();
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let mut client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_find_source = pg.stmt_find_merge_source.get(&client).await?;
+                let stmt_move_source = pg.stmt_move_merge_source.get(&client).await?;
+                let stmt_delete_source = pg.stmt_delete_merge_source.get(&client).await?;
+                let stmt_update_target = pg.stmt_update_merge_target.get(&client).await?;
+
+                let transaction = client.transaction().await?;
+                let source = match transaction.query_opt(&stmt_find_source, &[&source_user_id]).await? {
+                    Some(row) => MergeSource {
+                        email: row.get(0),
+                        normalized_email: row.get(1),
+                        email_confirmed: row.get(2),
+                        profile_image: row.get(3),
+                        is_held: row.get(4),
+                    },
+                    None => return Err(MergeError::SourceNotFound),
+                };
+                if source.is_held {
+                    return Err(MergeError::LegalHold);
+                }
+                transaction
+                    .execute(&stmt_move_source, &[&user_id, &source_user_id, &now])
+                    .await?;
+                // delete the source before the update of the target to release its unique email
+                transaction.execute(&stmt_delete_source, &[&source_user_id]).await?;
+                transaction
+                    .execute(
+                        &stmt_update_target,
+                        &[
+                            &user_id,
+                            &source.email,
+                            &source.normalized_email,
+                            &source.email_confirmed,
+                            &source.profile_image,
+                        ],
+                    )
+                    .await?;
+                transaction.commit().await?;
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<(), MergeError> {
+                        // the transaction is rolled back when it is dropped without a commit
+                        let transaction = conn.transaction()?;
+                        let source = transaction
+                            .query_row(SQLITE_FIND_MERGE_SOURCE, params![source_user_id], |row| {
+                                Ok(MergeSource {
+                                    email: row.get(0)?,
+                                    normalized_email: row.get(1)?,
+                                    email_confirmed: row.get(2)?,
+                                    profile_image: row.get(3)?,
+                                    is_held: row.get(4)?,
+                                })
+                            })
+                            .optional()?
+                            .ok_or(MergeError::SourceNotFound)?;
+                        if source.is_held {
+                            return Err(MergeError::LegalHold);
+                        }
+                        for statement in SQLITE_MOVE_MERGE_SOURCE {
+                            transaction.execute(statement, params![user_id, source_user_id])?;
+                        }
+                        transaction.execute(SQLITE_INSERT_TOMBSTONE, params![user_id, source_user_id, now])?;
+                        // delete the source before the update of the target to release its unique email
+                        transaction.execute(SQLITE_DELETE_MERGE_SOURCE, params![source_user_id])?;
+                        transaction.execute(
+                            SQLITE_UPDATE_MERGE_TARGET,
+                            params![
+                                user_id,
+                                source.email,
+                                source.normalized_email,
+                                source.email_confirmed,
+                                source.profile_image
+                            ],
+                        )?;
+                        transaction.commit()?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the user an identity was merged into.
+    pub async fn find_tombstone(&self, old_user_id: Uuid) -> Result<Option<Uuid>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_find_tombstone.get(&client).await?;
+                let row = client.query_opt(&stmt, &[&old_user_id]).await?;
+                Ok(row.map(|row| row.get(0)))
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Option<Uuid>, DBError> {
+                        Ok(conn
+                            .query_row(SQLITE_FIND_TOMBSTONE, params![old_user_id], |row| row.get(0))
+                            .optional()?)
+                    })
+                    .await
+            }
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use crate::db::{DBError, DBPool, PGError, SqlPool, SqlitePool};
+use bytes::BytesMut;
+use chrono::{Duration, Utc};
+use redis::Script;
+use ring::digest;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use shine_service::{
+    pg_prepared_statement,
+    service::{PGConnectionPool, RedisConnectionPool},
+};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use tokio_postgres::types::{accepts, to_sql_checked, FromSql, IsNull, ToSql, Type};
+use uuid::Uuid;
+
+/// The second factors of the login.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MfaMethod {
+    Email,
+}
+
+impl ToSql for MfaMethod {
+    fn to_sql(&self, ty: &Type, out: &mut BytesMut) -> Result<IsNull, PGError> {
+        let value = match self {
+            MfaMethod::Email => 1_i16,
+        };
+        value.to_sql(ty, out)
+    }
+
+    accepts!(INT2);
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for MfaMethod {
+    fn from_sql(ty: &Type, raw: &[u8]) -> Result<MfaMethod, PGError> {
+        let value = i16::from_sql(ty, raw)?;
+        match value {
+            1 => Ok(MfaMethod::Email),
+            _ => Err(PGError::from("Invalid value for MfaMethod")),
+        }
+    }
+
+    accepts!(INT2);
+}
+
+impl rusqlite::types::ToSql for MfaMethod {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        let value = match self {
+            MfaMethod::Email => 1_i64,
+        };
+        Ok(value.into())
+    }
+}
+
+impl rusqlite::types::FromSql for MfaMethod {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        match value.as_i64()? {
+            1 => Ok(MfaMethod::Email),
+            value => Err(rusqlite::types::FromSqlError::OutOfRange(value)),
+        }
+    }
+}
+
+/// Result of a one-time-code verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MfaCodeCheck {
+    Valid,
+    Invalid,
+    /// There is no active code, it has expired, was used or the attempts were exhausted.
+    Expired,
+}
+
+#[derive(Debug, ThisError)]
+pub enum MfaBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for MfaBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+pg_prepared_statement!( InsertMethod => r#"
+    INSERT INTO mfa_methods (user_id, method, created)
+        VALUES ($1, $2, now())
+    ON CONFLICT (user_id, method) DO NOTHING
+"#, [UUID, INT2] );
+
+pg_prepared_statement!( DeleteMethod => r#"
+    DELETE FROM mfa_methods WHERE user_id = $1 AND method = $2
+"#, [UUID, INT2] );
+
+pg_prepared_statement!( ListMethods => r#"
+    SELECT method FROM mfa_methods WHERE user_id = $1 ORDER BY method
+"#, [UUID] );
+
+const SQLITE_INSERT_METHOD: &str = r#"
+    INSERT INTO mfa_methods (user_id, method, created)
+        VALUES (?1, ?2, ?3)
+    ON CONFLICT (user_id, method) DO NOTHING
+"#;
+
+const SQLITE_DELETE_METHOD: &str = r#"
+    DELETE FROM mfa_methods WHERE user_id = ?1 AND method = ?2
+"#;
+
+const SQLITE_LIST_METHODS: &str = r#"
+    SELECT method FROM mfa_methods WHERE user_id = ?1 ORDER BY method
+"#;
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_insert_method: InsertMethod,
+    stmt_delete_method: DeleteMethod,
+    stmt_list_methods: ListMethods,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    redis: RedisConnectionPool,
+    key_prefix: String,
+}
+
+/// Manage the second factors enabled by the users and the one-time-codes sent to them.
+#[derive(Clone)]
+pub struct MfaManager(Arc<Inner>);
+
+impl MfaManager {
+    pub async fn new(pool: &DBPool) -> Result<Self, MfaBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_insert_method = InsertMethod::new(&client).await?;
+                let stmt_delete_method = DeleteMethod::new(&client).await?;
+                let stmt_list_methods = ListMethods::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_insert_method,
+                    stmt_delete_method,
+                    stmt_list_methods,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(Inner {
+            store,
+            redis: pool.redis.clone(),
+            key_prefix: pool.redis_key_prefix.clone(),
+        })))
+    }
+
+    pub async fn enable_method(&self, user_id: Uuid, method: MfaMethod) -> Result<(), DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_insert_method.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &method]).await?;
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(SQLITE_INSERT_METHOD, params![user_id, method, Utc::now()])?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn disable_method(&self, user_id: Uuid, method: MfaMethod) -> Result<(), DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_delete_method.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &method]).await?;
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(SQLITE_DELETE_METHOD, params![user_id, method])?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the second factors enabled by a user.
+    pub async fn list_methods(&self, user_id: Uuid) -> Result<Vec<MfaMethod>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_list_methods.get(&client).await?;
+                let rows = client.query(&stmt, &[&user_id]).await?;
+                Ok(rows.iter().map(|row| row.get(0)).collect())
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Vec<MfaMethod>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_LIST_METHODS)?;
+                        let methods = stmt
+                            .query_map(params![user_id], |row| row.get(0))?
+                            .collect::<Result<Vec<MfaMethod>, _>>()?;
+                        Ok(methods)
+                    })
+                    .await
+            }
+        }
+    }
+
+    fn code_key(&self, user_id: Uuid, method: MfaMethod) -> String {
+        format!(
+            "{}mfa-code:{}:{}",
+            self.0.key_prefix,
+            method as i16,
+            user_id.as_simple()
+        )
+    }
+
+    fn code_hash(user_id: Uuid, code: &str) -> String {
+        let hash = digest::digest(&digest::SHA256, format!("{}:{code}", user_id.as_simple()).as_bytes());
+        hex::encode(hash.as_ref())
+    }
+
+    /// Store a one-time-code of a user replacing the previous one.
+    pub async fn create_code(
+        &self,
+        user_id: Uuid,
+        method: MfaMethod,
+        code: &str,
+        duration: Duration,
+    ) -> Result<(), DBError> {
+        let inner = &*self.0;
+        let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let _: () = redis::pipe()
+            .atomic()
+            .del(self.code_key(user_id, method))
+            .ignore()
+            .hset_multiple(
+                self.code_key(user_id, method),
+                &[("hash", Self::code_hash(user_id, code)), ("attempts", "0".to_string())],
+            )
+            .ignore()
+            .expire(self.code_key(user_id, method), duration.num_seconds() as usize)
+            .ignore()
+            .query_async(&mut *client)
+            .await
+            .map_err(DBError::RedisError)?;
+        Ok(())
+    }
+
+    /// Verify a one-time-code. A valid code can be used only once, and after the given number of failed attempts
+    /// the code is dropped.
+    pub async fn verify_code(
+        &self,
+        user_id: Uuid,
+        method: MfaMethod,
+        code: &str,
+        max_attempts: u32,
+    ) -> Result<MfaCodeCheck, DBError> {
+        let inner = &*self.0;
+        let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let lua_script = r#"
+local hash = redis.call('HGET', KEYS[1], 'hash')
+if not hash then
+    return 0
+end
+if hash == ARGV[1] then
+    redis.call('DEL', KEYS[1])
+    return 1
+end
+local attempts = redis.call('HINCRBY', KEYS[1], 'attempts', 1)
+if attempts >= tonumber(ARGV[2]) then
+    redis.call('DEL', KEYS[1])
+end
+return 2
+"#;
+
+        let result: i32 = Script::new(lua_script)
+            .key(self.code_key(user_id, method))
+            .arg(Self::code_hash(user_id, code))
+            .arg(max_attempts)
+            .invoke_async(&mut *client)
+            .await
+            .map_err(DBError::RedisError)?;
+
+        Ok(match result {
+            1 => MfaCodeCheck::Valid,
+            2 => MfaCodeCheck::Invalid,
+            _ => MfaCodeCheck::Expired,
+        })
+    }
+}
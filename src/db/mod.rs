@@ -4,11 +4,96 @@ mod db_error;
 pub use self::db_error::*;
 mod db_pool;
 pub use self::db_pool::*;
+mod sqlite_pool;
+pub use self::sqlite_pool::*;
+mod fault_injection;
+pub use self::fault_injection::*;
 
+mod clock;
+pub use self::clock::*;
+
+mod activity_tracker;
+pub use self::activity_tracker::*;
+mod analytics_events;
+pub use self::analytics_events::*;
+mod audit_log;
+pub use self::audit_log::*;
+mod break_glass_store;
+pub use self::break_glass_store::*;
+mod consent_manager;
+pub use self::consent_manager::*;
+mod deletion_manager;
+pub use self::deletion_manager::*;
+mod dev_seeder;
+pub use self::dev_seeder::*;
+mod feature_flags;
+pub use self::feature_flags::*;
+mod expiration_manager;
+pub use self::expiration_manager::*;
+mod email_normalizer;
+pub use self::email_normalizer::*;
+mod identity_store;
+pub use self::identity_store::*;
 mod identity_manager;
 pub use self::identity_manager::*;
+mod identity_stats;
+pub use self::identity_stats::*;
+mod device_manager;
+pub use self::device_manager::*;
+mod login_link_manager;
+pub use self::login_link_manager::*;
+mod merge_manager;
+pub use self::merge_manager::*;
+mod mfa_manager;
+pub use self::mfa_manager::*;
+mod password_manager;
+pub use self::password_manager::*;
+mod permission_manager;
+pub use self::permission_manager::*;
+mod rate_limiter;
+pub use self::rate_limiter::*;
+mod token_revocation;
+pub use self::token_revocation::*;
+mod role_manager;
+pub use self::role_manager::*;
+mod service_client_manager;
+pub use self::service_client_manager::*;
+mod studio_manager;
+pub use self::studio_manager::*;
+mod opaque_token_store;
+pub use self::opaque_token_store::*;
+mod native_login_manager;
+pub use self::native_login_manager::*;
+mod sso_handoff_manager;
+pub use self::sso_handoff_manager::*;
+mod ticket_redemption;
+pub use self::ticket_redemption::*;
+mod api_quota_manager;
+pub use self::api_quota_manager::*;
+mod abuse_flag_manager;
+pub use self::abuse_flag_manager::*;
+mod restriction_manager;
+pub use self::restriction_manager::*;
+mod support_note_manager;
+pub use self::support_note_manager::*;
+mod tag_manager;
+pub use self::tag_manager::*;
+mod age_manager;
+pub use self::age_manager::*;
+mod parental_consent_manager;
+pub use self::parental_consent_manager::*;
+mod region_manager;
+pub use self::region_manager::*;
+mod session_store;
+pub use self::session_store::*;
 mod session_manager;
 pub use self::session_manager::*;
+mod user_invalidation;
+pub use self::user_invalidation::*;
+mod name_normalizer;
+pub use self::name_normalizer::*;
+mod name_filter;
+pub use self::name_filter::*;
 mod name_generator;
 pub use self::name_generator::*;
 
@@ -0,0 +1,86 @@
+use crate::db::normalize_name;
+use regex::{Regex, RegexSet};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, fs, path::PathBuf};
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub enum NameFilterError {
+    #[error("Failed to load word list from {0:?}: {1}")]
+    WordList(PathBuf, String),
+    #[error("Invalid name pattern: {0}")]
+    Pattern(#[from] regex::Error),
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NameFilterConfig {
+    /// Handles that cannot be used as a name, matched case-insensitively against the whole name.
+    #[serde(default)]
+    pub reserved_names: Vec<String>,
+    /// Words that cannot appear anywhere in a name.
+    #[serde(default)]
+    pub blocked_words: Vec<String>,
+    /// Files with additional blocked words, one word per line.
+    #[serde(default)]
+    pub blocked_word_files: Vec<PathBuf>,
+    /// Regular expressions that cannot match any part of a name.
+    #[serde(default)]
+    pub blocked_patterns: Vec<String>,
+}
+
+/// Reject names that are reserved or contain some blocked word or pattern.
+pub struct NameFilter {
+    reserved_names: HashSet<String>,
+    blocked_words: Vec<String>,
+    blocked_patterns: RegexSet,
+    separators: Regex,
+}
+
+impl NameFilter {
+    pub fn new(config: &NameFilterConfig) -> Result<Self, NameFilterError> {
+        let reserved_names = config.reserved_names.iter().map(|name| normalize_name(name)).collect();
+
+        let mut blocked_words: Vec<String> = config.blocked_words.iter().map(|word| normalize_name(word)).collect();
+        for path in &config.blocked_word_files {
+            let words =
+                fs::read_to_string(path).map_err(|err| NameFilterError::WordList(path.clone(), format!("{err}")))?;
+            blocked_words.extend(
+                words
+                    .lines()
+                    .map(|word| word.trim())
+                    .filter(|word| !word.is_empty() && !word.starts_with('#'))
+                    .map(normalize_name),
+            );
+        }
+
+        let blocked_patterns = RegexSet::new(config.blocked_patterns.iter().map(|pattern| format!("(?i){pattern}")))?;
+
+        Ok(Self {
+            reserved_names,
+            blocked_words,
+            blocked_patterns,
+            separators: Regex::new(r"[^\p{L}\p{N}]+")?,
+        })
+    }
+
+    /// Check if a name is allowed. Names and words are compared in their normalized form and also with
+    /// the separators removed to catch the simple workarounds like `a_d_m_i_n` or `аdmin`.
+    pub fn is_allowed(&self, name: &str) -> bool {
+        if self.blocked_patterns.is_match(name) {
+            return false;
+        }
+
+        let name = normalize_name(name);
+        let compact = self.separators.replace_all(&name, "");
+
+        if self.reserved_names.contains(&name) || self.reserved_names.contains(compact.as_ref()) {
+            return false;
+        }
+
+        !self
+            .blocked_words
+            .iter()
+            .any(|word| name.contains(word.as_str()) || compact.contains(word.as_str()))
+    }
+}
@@ -1,10 +1,16 @@
-use crate::db::{DBError, DBPool};
+use crate::db::{normalize_name, DBError, DBPool, NameFilter, NameFilterConfig, NameFilterError, SqlPool, SqlitePool};
 use harsh::Harsh;
+use rand::{seq::SliceRandom, Rng};
+use rusqlite::params;
 use serde::{Deserialize, Serialize};
 use shine_service::{pg_prepared_statement, service::PGConnectionPool, utils::Optimus};
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 use thiserror::Error as ThisError;
 
+/// The maximum length of a name in characters, both the name and its normalized form are stored in columns of
+/// this length.
+const MAX_NAME_LENGTH: usize = 64;
+
 #[derive(Debug, ThisError)]
 pub enum NameGeneratorError {
     #[error(transparent)]
@@ -13,6 +19,14 @@ pub enum NameGeneratorError {
     BaseGenerator(String),
     #[error("Id encoder error: {0}")]
     IdEncoder(String),
+    #[error("Name strategy error: {0}")]
+    Strategy(String),
+    #[error(transparent)]
+    NameFilter(#[from] NameFilterError),
+    #[error("Name ({0}) is not allowed")]
+    NameRejected(String),
+    #[error("Retry limit reach for name generation")]
+    RetryLimitReached,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -71,13 +85,104 @@ impl IdEncoderConfig {
     }
 }
 
+/// Word lists used to compose a name from an adjective and a noun.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WordListConfig {
+    pub adjectives: Vec<String>,
+    pub nouns: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(tag = "strategy")]
+pub enum NameStrategyConfig {
+    /// A fixed base name with an obfuscated sequence number, ex. `Freshman_x8sd1`.
+    #[serde(rename_all = "camelCase")]
+    Sequence {
+        #[serde(flatten)]
+        base_generator: BaseGeneratorConfig,
+        #[serde(flatten)]
+        id_encoder: IdEncoderConfig,
+    },
+
+    /// A random adjective and noun with a number, ex. `BraveOtter42`.
+    #[serde(rename_all = "camelCase")]
+    AdjectiveNoun {
+        #[serde(flatten)]
+        words: WordListConfig,
+        max_number: u32,
+    },
+
+    /// Some random syllables with a number, ex. `Kalomir7`.
+    #[serde(rename_all = "camelCase")]
+    Syllable {
+        syllables: Vec<String>,
+        min_syllables: usize,
+        max_syllables: usize,
+        max_number: u32,
+    },
+
+    /// Adjective and noun with the word lists selected by a theme, ex. for each game (tenant).
+    /// When no or an unknown theme is requested, the default theme is used.
+    #[serde(rename_all = "camelCase")]
+    Themed {
+        themes: HashMap<String, WordListConfig>,
+        default_theme: String,
+        max_number: u32,
+    },
+}
+
+impl NameStrategyConfig {
+    fn create_strategy(&self) -> Result<NameStrategy, NameGeneratorError> {
+        match self {
+            NameStrategyConfig::Sequence {
+                base_generator,
+                id_encoder,
+            } => Ok(NameStrategy::Sequence {
+                base: base_generator.create_generator()?,
+                id_encoder: id_encoder.create_encoder()?,
+            }),
+            NameStrategyConfig::AdjectiveNoun { words, max_number } => Ok(NameStrategy::Random(Box::new(
+                ThemedGenerator::new(HashMap::new(), words.clone(), *max_number)?,
+            ))),
+            NameStrategyConfig::Syllable {
+                syllables,
+                min_syllables,
+                max_syllables,
+                max_number,
+            } => Ok(NameStrategy::Random(Box::new(SyllableGenerator::new(
+                syllables.clone(),
+                *min_syllables,
+                *max_syllables,
+                *max_number,
+            )?))),
+            NameStrategyConfig::Themed {
+                themes,
+                default_theme,
+                max_number,
+            } => {
+                let mut themes = themes.clone();
+                let default = themes.remove(default_theme).ok_or_else(|| {
+                    NameGeneratorError::Strategy(format!("Missing word lists for the default theme ({default_theme})"))
+                })?;
+                Ok(NameStrategy::Random(Box::new(ThemedGenerator::new(
+                    themes,
+                    default,
+                    *max_number,
+                )?)))
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NameGeneratorConfig {
     #[serde(flatten)]
-    base_generator: BaseGeneratorConfig,
-    #[serde(flatten)]
-    id_encoder: IdEncoderConfig,
+    strategy: NameStrategyConfig,
+    #[serde(default)]
+    filter: NameFilterConfig,
 }
 
 /// Trait to generate some base name use as the prefix
@@ -108,15 +213,161 @@ impl IdEncoder for Harsh {
     }
 }
 
+/// Trait to generate some random, not necessarily unique names
+trait RandomGenerator: 'static + Send + Sync {
+    fn generate(&self, theme: Option<&str>) -> String;
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn random_number(max_number: u32) -> String {
+    if max_number > 0 {
+        rand::thread_rng().gen_range(0..=max_number).to_string()
+    } else {
+        String::new()
+    }
+}
+
+struct ThemedGenerator {
+    themes: HashMap<String, WordListConfig>,
+    default: WordListConfig,
+    max_number: u32,
+}
+
+impl ThemedGenerator {
+    fn new(
+        themes: HashMap<String, WordListConfig>,
+        default: WordListConfig,
+        max_number: u32,
+    ) -> Result<Self, NameGeneratorError> {
+        if let Some(theme) = themes
+            .values()
+            .chain([&default])
+            .find(|words| words.adjectives.is_empty() || words.nouns.is_empty())
+        {
+            return Err(NameGeneratorError::Strategy(format!(
+                "Word lists should not be empty: {theme:?}"
+            )));
+        }
+
+        Ok(Self {
+            themes,
+            default,
+            max_number,
+        })
+    }
+}
+
+impl RandomGenerator for ThemedGenerator {
+    fn generate(&self, theme: Option<&str>) -> String {
+        let words = theme.and_then(|theme| self.themes.get(theme)).unwrap_or(&self.default);
+
+        let mut rng = rand::thread_rng();
+        let adjective = words
+            .adjectives
+            .choose(&mut rng)
+            .map(String::as_str)
+            .unwrap_or_default();
+        let noun = words.nouns.choose(&mut rng).map(String::as_str).unwrap_or_default();
+        format!(
+            "{}{}{}",
+            capitalize(adjective),
+            capitalize(noun),
+            random_number(self.max_number)
+        )
+    }
+}
+
+struct SyllableGenerator {
+    syllables: Vec<String>,
+    min_syllables: usize,
+    max_syllables: usize,
+    max_number: u32,
+}
+
+impl SyllableGenerator {
+    fn new(
+        syllables: Vec<String>,
+        min_syllables: usize,
+        max_syllables: usize,
+        max_number: u32,
+    ) -> Result<Self, NameGeneratorError> {
+        if syllables.is_empty() {
+            Err(NameGeneratorError::Strategy("Syllable list should not be empty".into()))
+        } else if min_syllables == 0 || min_syllables > max_syllables {
+            Err(NameGeneratorError::Strategy(
+                "Syllable count should be in the range [1,maxSyllables]".into(),
+            ))
+        } else {
+            Ok(Self {
+                syllables,
+                min_syllables,
+                max_syllables,
+                max_number,
+            })
+        }
+    }
+}
+
+impl RandomGenerator for SyllableGenerator {
+    fn generate(&self, _theme: Option<&str>) -> String {
+        let mut rng = rand::thread_rng();
+        let count = rng.gen_range(self.min_syllables..=self.max_syllables);
+        let name: String = (0..count)
+            .filter_map(|_| self.syllables.choose(&mut rng))
+            .map(String::as_str)
+            .collect();
+        format!("{}{}", capitalize(&name), random_number(self.max_number))
+    }
+}
+
+enum NameStrategy {
+    /// Names are unique by construction
+    Sequence {
+        base: Box<dyn BaseGenerator>,
+        id_encoder: Box<dyn IdEncoder>,
+    },
+    /// Names have to be checked for collision
+    Random(Box<dyn RandomGenerator>),
+}
+
 pg_prepared_statement!( GetNextId => r#"
     SELECT nextval('user_id_counter')
 "#, [] );
 
-struct Inner {
+pg_prepared_statement!( IsNameUsed => r#"
+    SELECT EXISTS(SELECT 1 FROM identities WHERE normalized_name = $1)
+"#, [VARCHAR] );
+
+const SQLITE_GET_NEXT_ID: &str = r#"
+    INSERT INTO user_id_counter DEFAULT VALUES
+"#;
+
+const SQLITE_IS_NAME_USED: &str = r#"
+    SELECT EXISTS(SELECT 1 FROM identities WHERE normalized_name = ?1)
+"#;
+
+struct PgStore {
     postgres: PGConnectionPool,
     stmt_next_id: GetNextId,
-    base: Box<dyn BaseGenerator>,
-    id_encoder: Box<dyn IdEncoder>,
+    stmt_is_name_used: IsNameUsed,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    strategy: NameStrategy,
+    filter: NameFilter,
 }
 
 #[derive(Clone)]
@@ -124,35 +375,116 @@ pub struct NameGenerator(Arc<Inner>);
 
 impl NameGenerator {
     pub async fn new(config: &NameGeneratorConfig, pool: &DBPool) -> Result<Self, NameGeneratorError> {
-        let client = pool.postgres.get().await.map_err(DBError::PostgresPoolError)?;
-        let stmt_next_id = GetNextId::new(&client).await.map_err(DBError::from)?;
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_next_id = GetNextId::new(&client).await.map_err(DBError::from)?;
+                let stmt_is_name_used = IsNameUsed::new(&client).await.map_err(DBError::from)?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_next_id,
+                    stmt_is_name_used,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
 
         Ok(Self(Arc::new(Inner {
-            postgres: pool.postgres.clone(),
-            stmt_next_id,
-            base: config.base_generator.create_generator()?,
-            id_encoder: config.id_encoder.create_encoder()?,
+            store,
+            strategy: config.strategy.create_strategy()?,
+            filter: NameFilter::new(&config.filter)?,
         })))
     }
 
-    pub async fn generate_name(&self) -> Result<String, NameGeneratorError> {
+    /// Check if the name is not empty, fits the storage and passes the configured blocklist.
+    pub fn validate_name(&self, name: &str) -> Result<(), NameGeneratorError> {
+        let is_valid = !name.trim().is_empty()
+            && name.chars().count() <= MAX_NAME_LENGTH
+            && normalize_name(name).chars().count() <= MAX_NAME_LENGTH
+            && self.0.filter.is_allowed(name);
+        if is_valid {
+            Ok(())
+        } else {
+            Err(NameGeneratorError::NameRejected(name.to_owned()))
+        }
+    }
+
+    async fn next_id(&self) -> Result<u64, DBError> {
+        let id = match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_next_id = pg.stmt_next_id.get(&client).await?;
+                let row = client.query_one(&stmt_next_id, &[]).await?;
+                row.get::<_, i64>(0)
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(|conn| -> Result<i64, DBError> {
+                        conn.execute(SQLITE_GET_NEXT_ID, [])?;
+                        Ok(conn.last_insert_rowid())
+                    })
+                    .await?
+            }
+        };
+        Ok(id as u64)
+    }
+
+    async fn is_name_used(&self, name: &str) -> Result<bool, DBError> {
+        let normalized_name = normalize_name(name);
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_is_name_used = pg.stmt_is_name_used.get(&client).await?;
+                let row = client.query_one(&stmt_is_name_used, &[&normalized_name]).await?;
+                Ok(row.get(0))
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<bool, DBError> {
+                        Ok(conn.query_row(SQLITE_IS_NAME_USED, params![normalized_name], |row| row.get(0))?)
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Generate a new name using the configured strategy. The theme is used only by the themed
+    /// strategy to select the word lists, ex. the tenant of the user.
+    pub async fn generate_name(&self, theme: Option<&str>) -> Result<String, NameGeneratorError> {
         // some alternatives and sources:
         // - <https://datatracker.ietf.org/doc/html/rfc1751>
         // - <https://github.com/archer884/harsh>
         // - <https://github.com/pjebs/optimus-go>
 
+        const MAX_RETRY_COUNT: usize = 10;
+
         let inner = &*self.0;
 
-        let client = inner.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+        for _ in 0..MAX_RETRY_COUNT {
+            let name = match &inner.strategy {
+                NameStrategy::Sequence { base, id_encoder } => {
+                    let prefix = base.generate();
+                    let suffix = id_encoder.encode(self.next_id().await?);
+                    format!("{}_{}", prefix, suffix)
+                }
+                NameStrategy::Random(generator) => generator.generate(theme),
+            };
 
-        let prefix = inner.base.generate();
-        let suffix = {
-            let stmt_next_id = inner.stmt_next_id.get(&client).await.map_err(DBError::from)?;
-            let row = client.query_one(&stmt_next_id, &[]).await.map_err(DBError::from)?;
-            let id: i64 = row.get(0);
-            inner.id_encoder.encode(id as u64)
-        };
+            if !inner.filter.is_allowed(&name) {
+                log::debug!("Generated name ({name}) rejected by the name filter");
+                continue;
+            }
+
+            if let NameStrategy::Random(_) = &inner.strategy {
+                if self.is_name_used(&name).await? {
+                    log::debug!("Generated name ({name}) is already used");
+                    continue;
+                }
+            }
+
+            return Ok(name);
+        }
 
-        Ok(format!("{}_{}", prefix, suffix))
+        Err(NameGeneratorError::RetryLimitReached)
     }
 }
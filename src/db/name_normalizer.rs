@@ -0,0 +1,10 @@
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::skeleton;
+
+/// Get the form of a name used for the uniqueness checks. The name is NFKC normalized, case folded and
+/// the confusable characters are replaced by their prototype (UTS #39 skeleton), thus names like `Admin`
+/// and `аdmin` (with a Cyrillic а) have the same normalized form.
+pub fn normalize_name(name: &str) -> String {
+    let folded = name.nfkc().collect::<String>().to_lowercase();
+    skeleton(&folded).collect::<String>().to_lowercase()
+}
@@ -0,0 +1,64 @@
+use crate::db::{DBError, DBPool};
+use chrono::Duration;
+use redis::AsyncCommands;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use shine_service::service::RedisConnectionPool;
+use uuid::Uuid;
+
+/// A single-use code of a native app login, the app exchanges it for a session.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NativeLoginCode {
+    pub user_id: Uuid,
+    pub tenant_id: String,
+    /// The target the code was sent to, the exchange shall be requested with the same target.
+    pub redirect_url: String,
+    /// The PKCE challenge (S256) of the app.
+    pub code_challenge: String,
+}
+
+/// Manage the codes of the native app logins, only the hash of the codes is stored.
+#[derive(Clone)]
+pub struct NativeLoginManager {
+    redis: RedisConnectionPool,
+    key_prefix: String,
+}
+
+impl NativeLoginManager {
+    pub fn new(pool: &DBPool) -> Self {
+        Self {
+            redis: pool.redis.clone(),
+            key_prefix: pool.redis_key_prefix.clone(),
+        }
+    }
+
+    fn key(&self, code: &str) -> String {
+        let hash = digest::digest(&digest::SHA256, code.as_bytes());
+        format!("{}native-login:{}", self.key_prefix, hex::encode(hash.as_ref()))
+    }
+
+    pub async fn create(&self, code: &str, login: &NativeLoginCode, duration: Duration) -> Result<(), DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+        let login = serde_json::to_string(login).expect("Failed to serialize native login");
+        let _: () = client
+            .set_ex(self.key(code), login, duration.num_seconds() as usize)
+            .await
+            .map_err(DBError::RedisError)?;
+        Ok(())
+    }
+
+    /// Consume a code, it can be taken only once.
+    pub async fn take(&self, code: &str) -> Result<Option<NativeLoginCode>, DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+        let key = self.key(code);
+        let login: Option<String> = client.get(&key).await.map_err(DBError::RedisError)?;
+        let deleted: u32 = client.del(&key).await.map_err(DBError::RedisError)?;
+
+        // when the key was deleted concurrently, the code has already been used
+        if deleted == 0 {
+            return Ok(None);
+        }
+        Ok(login.and_then(|login| serde_json::from_str(&login).ok()))
+    }
+}
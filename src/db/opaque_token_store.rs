@@ -0,0 +1,47 @@
+use crate::db::{DBError, DBPool};
+use chrono::Duration;
+use redis::AsyncCommands;
+use ring::digest;
+use shine_service::service::RedisConnectionPool;
+
+/// Store of the opaque access tokens. The tokens carry no information, their (serialized) claims are kept in redis
+/// until the token expires. Only the hash of the token is stored, thus a leaked key cannot be used as a token.
+#[derive(Clone)]
+pub struct OpaqueTokenStore {
+    redis: RedisConnectionPool,
+    key_prefix: String,
+}
+
+impl OpaqueTokenStore {
+    pub fn new(pool: &DBPool) -> Self {
+        Self {
+            redis: pool.redis.clone(),
+            key_prefix: pool.redis_key_prefix.clone(),
+        }
+    }
+
+    fn token_key(&self, token: &str) -> String {
+        format!(
+            "{}opaque-token:{}",
+            self.key_prefix,
+            hex::encode(digest::digest(&digest::SHA256, token.as_bytes()).as_ref())
+        )
+    }
+
+    /// Store the claims of a token for the given duration.
+    pub async fn store(&self, token: &str, claims: &str, duration: Duration) -> Result<(), DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+        let _: () = client
+            .set_ex(self.token_key(token), claims, duration.num_seconds().max(1) as usize)
+            .await
+            .map_err(DBError::RedisError)?;
+        Ok(())
+    }
+
+    /// Get the claims of a token, None if the token is unknown or has expired.
+    pub async fn find(&self, token: &str) -> Result<Option<String>, DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+        let claims: Option<String> = client.get(self.token_key(token)).await.map_err(DBError::RedisError)?;
+        Ok(claims)
+    }
+}
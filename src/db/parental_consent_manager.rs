@@ -0,0 +1,195 @@
+use crate::db::{DBError, DBPool, SharedClock, SqlPool, SqlitePool};
+use chrono::{DateTime, Duration, Utc};
+use ring::digest;
+use rusqlite::params;
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+pg_prepared_statement!( RequestConsent => r#"
+    INSERT INTO parental_consents (user_id, parent_email, token_hash, requested)
+        VALUES ($1, $2, $3, $4)
+    ON CONFLICT (user_id) DO UPDATE SET parent_email = $2, token_hash = $3, requested = $4
+        WHERE parental_consents.granted IS NULL
+"#, [UUID, VARCHAR, VARCHAR, TIMESTAMPTZ] );
+
+pg_prepared_statement!( GrantConsent => r#"
+    UPDATE parental_consents SET granted = $2, token_hash = NULL
+        WHERE token_hash = $1 AND granted IS NULL AND requested > $3
+    RETURNING user_id
+"#, [VARCHAR, TIMESTAMPTZ, TIMESTAMPTZ] );
+
+pg_prepared_statement!( FindConsent => r#"
+    SELECT parent_email, requested, granted FROM parental_consents WHERE user_id = $1
+"#, [UUID] );
+
+const SQLITE_REQUEST_CONSENT: &str = r#"
+    INSERT INTO parental_consents (user_id, parent_email, token_hash, requested)
+        VALUES (?1, ?2, ?3, ?4)
+    ON CONFLICT (user_id) DO UPDATE SET parent_email = ?2, token_hash = ?3, requested = ?4
+        WHERE parental_consents.granted IS NULL
+"#;
+
+const SQLITE_GRANT_CONSENT: &str = r#"
+    UPDATE parental_consents SET granted = ?2, token_hash = NULL
+        WHERE token_hash = ?1 AND granted IS NULL AND requested > ?3
+    RETURNING user_id
+"#;
+
+const SQLITE_FIND_CONSENT: &str = r#"
+    SELECT parent_email, requested, granted FROM parental_consents WHERE user_id = ?1
+"#;
+
+/// The consent of a parent to the account of a minor.
+#[derive(Debug, Clone)]
+pub struct ParentalConsent {
+    pub parent_email: String,
+    pub requested_at: DateTime<Utc>,
+    pub granted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, ThisError)]
+pub enum ParentalConsentBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for ParentalConsentBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_request_consent: RequestConsent,
+    stmt_grant_consent: GrantConsent,
+    stmt_find_consent: FindConsent,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    clock: SharedClock,
+}
+
+/// Store of the consents of the parents to the accounts of the minors. The parent gets a single-use consent link by
+/// email, only the hash of the link token is stored.
+#[derive(Clone)]
+pub struct ParentalConsentManager(Arc<Inner>);
+
+impl ParentalConsentManager {
+    pub async fn new(pool: &DBPool, clock: SharedClock) -> Result<Self, ParentalConsentBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_request_consent = RequestConsent::new(&client).await?;
+                let stmt_grant_consent = GrantConsent::new(&client).await?;
+                let stmt_find_consent = FindConsent::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_request_consent,
+                    stmt_grant_consent,
+                    stmt_find_consent,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(Inner { store, clock })))
+    }
+
+    fn token_hash(token: &str) -> String {
+        let hash = digest::digest(&digest::SHA256, token.as_bytes());
+        hex::encode(hash.as_ref())
+    }
+
+    /// Store a new consent request of a user replacing the previous link, false is returned if the consent has
+    /// already been granted.
+    pub async fn request_consent(&self, user_id: Uuid, parent_email: &str, token: &str) -> Result<bool, DBError> {
+        let now = self.0.clock.now();
+        let token_hash = Self::token_hash(token);
+        let count = match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_request_consent.get(&client).await?;
+                client
+                    .execute(&stmt, &[&user_id, &parent_email, &token_hash, &now])
+                    .await? as usize
+            }
+            Store::Sqlite(sqlite) => {
+                let parent_email = parent_email.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<usize, DBError> {
+                        Ok(conn.execute(SQLITE_REQUEST_CONSENT, params![user_id, parent_email, token_hash, now])?)
+                    })
+                    .await?
+            }
+        };
+        Ok(count > 0)
+    }
+
+    /// Grant the consent of a link requested in the given duration, the user of the consent is returned if the
+    /// link was valid.
+    pub async fn grant_consent(&self, token: &str, duration: Duration) -> Result<Option<Uuid>, DBError> {
+        let now = self.0.clock.now();
+        let requested_after = now - duration;
+        let token_hash = Self::token_hash(token);
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_grant_consent.get(&client).await?;
+                let row = client.query_opt(&stmt, &[&token_hash, &now, &requested_after]).await?;
+                Ok(row.map(|row| row.get(0)))
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Option<Uuid>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_GRANT_CONSENT)?;
+                        let mut rows = stmt.query(params![token_hash, now, requested_after])?;
+                        match rows.next()? {
+                            Some(row) => Ok(Some(row.get(0)?)),
+                            None => Ok(None),
+                        }
+                    })
+                    .await
+            }
+        }
+    }
+
+    pub async fn find_consent(&self, user_id: Uuid) -> Result<Option<ParentalConsent>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_find_consent.get(&client).await?;
+                let row = client.query_opt(&stmt, &[&user_id]).await?;
+                Ok(row.map(|row| ParentalConsent {
+                    parent_email: row.get(0),
+                    requested_at: row.get(1),
+                    granted_at: row.get(2),
+                }))
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Option<ParentalConsent>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_FIND_CONSENT)?;
+                        let mut rows = stmt.query(params![user_id])?;
+                        match rows.next()? {
+                            Some(row) => Ok(Some(ParentalConsent {
+                                parent_email: row.get(0)?,
+                                requested_at: row.get(1)?,
+                                granted_at: row.get(2)?,
+                            })),
+                            None => Ok(None),
+                        }
+                    })
+                    .await
+            }
+        }
+    }
+}
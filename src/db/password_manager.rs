@@ -0,0 +1,236 @@
+use crate::db::{DBError, DBPool, SqlPool, SqlitePool};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use chrono::{Duration, Utc};
+use redis::AsyncCommands;
+use ring::digest;
+use rusqlite::{params, OptionalExtension};
+use shine_service::{
+    pg_prepared_statement,
+    service::{PGConnectionPool, RedisConnectionPool},
+};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use tokio::task;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub enum PasswordError {
+    #[error("Failed to hash password: {0}")]
+    Hash(String),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for PasswordError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+pg_prepared_statement!( UpsertPassword => r#"
+    INSERT INTO passwords (user_id, password_hash, updated)
+        VALUES ($1, $2, now())
+    ON CONFLICT (user_id) DO UPDATE SET password_hash = $2, updated = now()
+"#, [UUID, VARCHAR] );
+
+pg_prepared_statement!( GetPassword => r#"
+    SELECT password_hash FROM passwords WHERE user_id = $1
+"#, [UUID] );
+
+const SQLITE_UPSERT_PASSWORD: &str = r#"
+    INSERT INTO passwords (user_id, password_hash, updated)
+        VALUES (?1, ?2, ?3)
+    ON CONFLICT (user_id) DO UPDATE SET password_hash = ?2, updated = ?3
+"#;
+
+const SQLITE_GET_PASSWORD: &str = r#"
+    SELECT password_hash FROM passwords WHERE user_id = ?1
+"#;
+
+#[derive(Debug, ThisError)]
+pub enum PasswordBuildError {
+    #[error("Failed to hash the dummy password: {0}")]
+    Hash(String),
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for PasswordBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_upsert_password: UpsertPassword,
+    stmt_get_password: GetPassword,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    redis: RedisConnectionPool,
+    key_prefix: String,
+    /// Hash verified for the unknown users, thus the timing of a login does not tell if the account exists.
+    dummy_hash: String,
+}
+
+/// Manage the password credentials of the users and the single-use password reset tokens.
+#[derive(Clone)]
+pub struct PasswordManager(Arc<Inner>);
+
+impl PasswordManager {
+    pub async fn new(pool: &DBPool) -> Result<Self, PasswordBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_upsert_password = UpsertPassword::new(&client).await?;
+                let stmt_get_password = GetPassword::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_upsert_password,
+                    stmt_get_password,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        let dummy_hash = Self::hash_password("dummy-password")
+            .await
+            .map_err(|err| PasswordBuildError::Hash(format!("{err}")))?;
+
+        Ok(Self(Arc::new(Inner {
+            store,
+            redis: pool.redis.clone(),
+            key_prefix: pool.redis_key_prefix.clone(),
+            dummy_hash,
+        })))
+    }
+
+    async fn hash_password(password: &str) -> Result<String, PasswordError> {
+        let password = password.to_owned();
+        task::spawn_blocking(move || {
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::default()
+                .hash_password(password.as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+        })
+        .await
+        .map_err(|err| PasswordError::Hash(format!("{err}")))?
+        .map_err(|err| PasswordError::Hash(format!("{err}")))
+    }
+
+    async fn verify_hash(password_hash: String, password: &str) -> Result<bool, PasswordError> {
+        let password = password.to_owned();
+        task::spawn_blocking(move || -> Result<bool, PasswordError> {
+            let password_hash =
+                PasswordHash::new(&password_hash).map_err(|err| PasswordError::Hash(format!("{err}")))?;
+            Ok(Argon2::default()
+                .verify_password(password.as_bytes(), &password_hash)
+                .is_ok())
+        })
+        .await
+        .map_err(|err| PasswordError::Hash(format!("{err}")))?
+    }
+
+    /// Set or replace the password of a user.
+    pub async fn set_password(&self, user_id: Uuid, password: &str) -> Result<(), PasswordError> {
+        let password_hash = Self::hash_password(password).await?;
+
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_upsert_password.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &password_hash]).await?;
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(SQLITE_UPSERT_PASSWORD, params![user_id, password_hash, Utc::now()])?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_password_hash(&self, user_id: Uuid) -> Result<Option<String>, PasswordError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_get_password.get(&client).await?;
+                Ok(client.query_opt(&stmt, &[&user_id]).await?.map(|row| row.get(0)))
+            }
+            Store::Sqlite(sqlite) => Ok(sqlite
+                .call(move |conn| -> Result<Option<String>, DBError> {
+                    Ok(conn
+                        .query_row(SQLITE_GET_PASSWORD, params![user_id], |row| row.get(0))
+                        .optional()?)
+                })
+                .await?),
+        }
+    }
+
+    pub async fn has_password(&self, user_id: Uuid) -> Result<bool, PasswordError> {
+        Ok(self.get_password_hash(user_id).await?.is_some())
+    }
+
+    /// Check the password of a user. Users without a password are never verified, but the time of a verification
+    /// is spent on them too.
+    pub async fn verify_password(&self, user_id: Uuid, password: &str) -> Result<bool, PasswordError> {
+        match self.get_password_hash(user_id).await? {
+            Some(password_hash) => Self::verify_hash(password_hash, password).await,
+            None => self.verify_unknown(password).await,
+        }
+    }
+
+    /// Spend the time of a password verification on a login of an unknown user, the result is always false.
+    pub async fn verify_unknown(&self, password: &str) -> Result<bool, PasswordError> {
+        Self::verify_hash(self.0.dummy_hash.clone(), password).await?;
+        Ok(false)
+    }
+
+    fn reset_key(&self, token: &str) -> String {
+        // only the hash of the token is stored, thus the tokens can't be recovered from the storage
+        let hash = digest::digest(&digest::SHA256, token.as_bytes());
+        format!("{}password-reset:{}", self.0.key_prefix, hex::encode(hash.as_ref()))
+    }
+
+    /// Store a password reset token of a user.
+    pub async fn create_reset_token(&self, token: &str, user_id: Uuid, duration: Duration) -> Result<(), DBError> {
+        let inner = &*self.0;
+        let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let key = self.reset_key(token);
+        let _: () = client
+            .set_ex(&key, user_id.as_simple().to_string(), duration.num_seconds() as usize)
+            .await
+            .map_err(DBError::RedisError)?;
+        Ok(())
+    }
+
+    /// Consume a password reset token returning the user of the token if it was valid.
+    pub async fn take_reset_token(&self, token: &str) -> Result<Option<Uuid>, DBError> {
+        let inner = &*self.0;
+        let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let key = self.reset_key(token);
+        let user_id: Option<String> = client.get(&key).await.map_err(DBError::RedisError)?;
+        let deleted: u32 = client.del(&key).await.map_err(DBError::RedisError)?;
+
+        // when the key was deleted concurrently, the token has already been used
+        if deleted == 0 {
+            return Ok(None);
+        }
+        Ok(user_id.and_then(|user_id| Uuid::parse_str(&user_id).ok()))
+    }
+}
@@ -0,0 +1,255 @@
+use crate::db::{DBError, DBPool, SqlPool, SqlitePool};
+use chrono::Utc;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+pg_prepared_statement!( InsertGrant => r#"
+    INSERT INTO grants (user_id, action, resource_type, resource_id, granted, granted_by)
+        VALUES ($1, $2, $3, $4, now(), $5)
+    ON CONFLICT (user_id, action, resource_type, resource_id) DO NOTHING
+"#, [UUID, VARCHAR, VARCHAR, VARCHAR, UUID] );
+
+pg_prepared_statement!( DeleteGrant => r#"
+    DELETE FROM grants WHERE user_id = $1 AND action = $2 AND resource_type = $3 AND resource_id = $4
+"#, [UUID, VARCHAR, VARCHAR, VARCHAR] );
+
+pg_prepared_statement!( HasGrant => r#"
+    SELECT EXISTS(
+        SELECT 1 FROM grants WHERE user_id = $1 AND action = $2 AND resource_type = $3 AND resource_id = $4
+    )
+"#, [UUID, VARCHAR, VARCHAR, VARCHAR] );
+
+pg_prepared_statement!( FilterGranted => r#"
+    SELECT resource_id FROM grants
+        WHERE user_id = $1 AND action = $2 AND resource_type = $3 AND resource_id = ANY($4)
+"#, [UUID, VARCHAR, VARCHAR, VARCHAR_ARRAY] );
+
+const SQLITE_INSERT_GRANT: &str = r#"
+    INSERT INTO grants (user_id, action, resource_type, resource_id, granted, granted_by)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+    ON CONFLICT (user_id, action, resource_type, resource_id) DO NOTHING
+"#;
+
+const SQLITE_DELETE_GRANT: &str = r#"
+    DELETE FROM grants WHERE user_id = ?1 AND action = ?2 AND resource_type = ?3 AND resource_id = ?4
+"#;
+
+const SQLITE_HAS_GRANT: &str = r#"
+    SELECT EXISTS(
+        SELECT 1 FROM grants WHERE user_id = ?1 AND action = ?2 AND resource_type = ?3 AND resource_id = ?4
+    )
+"#;
+
+// The ids are passed as a json array as there are no array parameters in SQLite
+const SQLITE_FILTER_GRANTED: &str = r#"
+    SELECT resource_id FROM grants
+        WHERE user_id = ?1 AND action = ?2 AND resource_type = ?3
+            AND resource_id IN (SELECT value FROM json_each(?4))
+"#;
+
+/// A resource a permission is scoped to, ex. a studio or a game.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Resource {
+    pub resource_type: String,
+    pub resource_id: String,
+}
+
+#[derive(Debug, ThisError)]
+pub enum PermissionBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for PermissionBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_insert_grant: InsertGrant,
+    stmt_delete_grant: DeleteGrant,
+    stmt_has_grant: HasGrant,
+    stmt_filter_granted: FilterGranted,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+/// Manage the permissions of the users scoped to resources.
+#[derive(Clone)]
+pub struct PermissionManager(Arc<Store>);
+
+impl PermissionManager {
+    pub async fn new(pool: &DBPool) -> Result<Self, PermissionBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_insert_grant = InsertGrant::new(&client).await?;
+                let stmt_delete_grant = DeleteGrant::new(&client).await?;
+                let stmt_has_grant = HasGrant::new(&client).await?;
+                let stmt_filter_granted = FilterGranted::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_insert_grant,
+                    stmt_delete_grant,
+                    stmt_has_grant,
+                    stmt_filter_granted,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(store)))
+    }
+
+    /// Grant the permission of an action on a resource. Granting an existing permission is not an error.
+    pub async fn grant(
+        &self,
+        user_id: Uuid,
+        action: &str,
+        resource: &Resource,
+        granted_by: Option<Uuid>,
+    ) -> Result<(), DBError> {
+        match &*self.0 {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_insert_grant.get(&client).await?;
+                client
+                    .execute(
+                        &stmt,
+                        &[
+                            &user_id,
+                            &action,
+                            &resource.resource_type,
+                            &resource.resource_id,
+                            &granted_by,
+                        ],
+                    )
+                    .await?;
+            }
+            Store::Sqlite(sqlite) => {
+                let action = action.to_owned();
+                let resource = resource.clone();
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(
+                            SQLITE_INSERT_GRANT,
+                            params![
+                                user_id,
+                                action,
+                                resource.resource_type,
+                                resource.resource_id,
+                                Utc::now(),
+                                granted_by
+                            ],
+                        )?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Revoke a permission. Returns false if the permission was not granted.
+    pub async fn revoke(&self, user_id: Uuid, action: &str, resource: &Resource) -> Result<bool, DBError> {
+        let count = match &*self.0 {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_delete_grant.get(&client).await?;
+                client
+                    .execute(
+                        &stmt,
+                        &[&user_id, &action, &resource.resource_type, &resource.resource_id],
+                    )
+                    .await? as usize
+            }
+            Store::Sqlite(sqlite) => {
+                let action = action.to_owned();
+                let resource = resource.clone();
+                sqlite
+                    .call(move |conn| -> Result<usize, DBError> {
+                        Ok(conn.execute(
+                            SQLITE_DELETE_GRANT,
+                            params![user_id, action, resource.resource_type, resource.resource_id],
+                        )?)
+                    })
+                    .await?
+            }
+        };
+        Ok(count > 0)
+    }
+
+    pub async fn has_permission(&self, user_id: Uuid, action: &str, resource: &Resource) -> Result<bool, DBError> {
+        match &*self.0 {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_has_grant.get(&client).await?;
+                let row = client
+                    .query_one(
+                        &stmt,
+                        &[&user_id, &action, &resource.resource_type, &resource.resource_id],
+                    )
+                    .await?;
+                Ok(row.get(0))
+            }
+            Store::Sqlite(sqlite) => {
+                let action = action.to_owned();
+                let resource = resource.clone();
+                sqlite
+                    .call(move |conn| -> Result<bool, DBError> {
+                        Ok(conn.query_row(
+                            SQLITE_HAS_GRANT,
+                            params![user_id, action, resource.resource_type, resource.resource_id],
+                            |row| row.get(0),
+                        )?)
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Evaluate the permission for a batch of resources of the same type. The ids of the resources the action is
+    /// permitted on are returned.
+    pub async fn filter_permitted(
+        &self,
+        user_id: Uuid,
+        action: &str,
+        resource_type: &str,
+        resource_ids: &[String],
+    ) -> Result<Vec<String>, DBError> {
+        match &*self.0 {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_filter_granted.get(&client).await?;
+                let rows = client
+                    .query(&stmt, &[&user_id, &action, &resource_type, &resource_ids])
+                    .await?;
+                Ok(rows.iter().map(|row| row.get(0)).collect())
+            }
+            Store::Sqlite(sqlite) => {
+                let action = action.to_owned();
+                let resource_type = resource_type.to_owned();
+                let resource_ids = serde_json::json!(resource_ids);
+                sqlite
+                    .call(move |conn| -> Result<Vec<String>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_FILTER_GRANTED)?;
+                        let resource_ids = stmt
+                            .query_map(params![user_id, action, resource_type, resource_ids], |row| row.get(0))?
+                            .collect::<Result<Vec<String>, _>>()?;
+                        Ok(resource_ids)
+                    })
+                    .await
+            }
+        }
+    }
+}
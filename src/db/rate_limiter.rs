@@ -0,0 +1,122 @@
+use crate::db::{DBError, DBPool};
+use chrono::Duration;
+use redis::Script;
+use shine_service::service::RedisConnectionPool;
+
+/// The failures of a key in the current window.
+#[derive(Debug)]
+pub struct FailureState {
+    pub count: u32,
+    /// The remaining time of the block of the key.
+    pub blocked_for: Option<Duration>,
+}
+
+/// Fixed window rate limiter shared by all the instances of the service.
+#[derive(Clone)]
+pub struct RateLimiter {
+    redis: RedisConnectionPool,
+    key_prefix: String,
+}
+
+impl RateLimiter {
+    pub fn new(pool: &DBPool) -> Self {
+        Self {
+            redis: pool.redis.clone(),
+            key_prefix: pool.redis_key_prefix.clone(),
+        }
+    }
+
+    /// Register an attempt for the given key and check if it is still in the limit of the current window.
+    pub async fn check(&self, key: &str, limit: u32, window: Duration) -> Result<bool, DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let lua_script = r#"
+local count = redis.call('INCR', KEYS[1])
+if count == 1 then
+    redis.call('EXPIRE', KEYS[1], ARGV[1])
+end
+return count
+"#;
+
+        let count: u32 = Script::new(lua_script)
+            .key(format!("{}rate-limit:{key}", self.key_prefix))
+            .arg(window.num_seconds())
+            .invoke_async(&mut *client)
+            .await
+            .map_err(DBError::RedisError)?;
+
+        Ok(count <= limit)
+    }
+    /// Get the failures of the given key in the current window and the remaining time of its block.
+    pub async fn failures(&self, key: &str) -> Result<FailureState, DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let (count, block_ttl): (Option<u32>, i64) = redis::pipe()
+            .get(format!("{}failures:{key}", self.key_prefix))
+            .ttl(format!("{}failures-block:{key}", self.key_prefix))
+            .query_async(&mut *client)
+            .await
+            .map_err(DBError::RedisError)?;
+
+        Ok(FailureState {
+            count: count.unwrap_or(0),
+            blocked_for: (block_ttl > 0).then(|| Duration::seconds(block_ttl)),
+        })
+    }
+
+    /// Register a failure for the given key and return the number of failures in the current window.
+    pub async fn record_failure(&self, key: &str, window: Duration) -> Result<u32, DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let lua_script = r#"
+local count = redis.call('INCR', KEYS[1])
+if count == 1 then
+    redis.call('EXPIRE', KEYS[1], ARGV[1])
+end
+return count
+"#;
+
+        let count: u32 = Script::new(lua_script)
+            .key(format!("{}failures:{key}", self.key_prefix))
+            .arg(window.num_seconds())
+            .invoke_async(&mut *client)
+            .await
+            .map_err(DBError::RedisError)?;
+
+        Ok(count)
+    }
+
+    /// Block the given key for a duration. A longer existing block is not shortened.
+    pub async fn block(&self, key: &str, duration: Duration) -> Result<(), DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let lua_script = r#"
+if redis.call('TTL', KEYS[1]) < tonumber(ARGV[1]) then
+    redis.call('SET', KEYS[1], 1, 'EX', ARGV[1])
+end
+return 0
+"#;
+
+        let _: i32 = Script::new(lua_script)
+            .key(format!("{}failures-block:{key}", self.key_prefix))
+            .arg(duration.num_seconds())
+            .invoke_async(&mut *client)
+            .await
+            .map_err(DBError::RedisError)?;
+
+        Ok(())
+    }
+
+    /// Forget the failures of the given key, the active block is kept.
+    pub async fn clear_failures(&self, key: &str) -> Result<(), DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let _: () = redis::cmd("DEL")
+            .arg(format!("{}failures:{key}", self.key_prefix))
+            .query_async(&mut *client)
+            .await
+            .map_err(DBError::RedisError)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use crate::db::{DBError, DBPool, SharedClock, SqlPool, SqlitePool};
+use rusqlite::params;
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+pg_prepared_statement!( InsertRegion => r#"
+    INSERT INTO identity_regions (user_id, region, created) VALUES ($1, $2, $3)
+    ON CONFLICT (user_id) DO NOTHING
+"#, [UUID, VARCHAR, TIMESTAMPTZ] );
+
+pg_prepared_statement!( FindRegion => r#"
+    SELECT region FROM identity_regions WHERE user_id = $1
+"#, [UUID] );
+
+const SQLITE_INSERT_REGION: &str = r#"
+    INSERT INTO identity_regions (user_id, region, created) VALUES (?1, ?2, ?3)
+    ON CONFLICT (user_id) DO NOTHING
+"#;
+
+const SQLITE_FIND_REGION: &str = r#"
+    SELECT region FROM identity_regions WHERE user_id = ?1
+"#;
+
+#[derive(Debug, ThisError)]
+pub enum RegionManagerBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for RegionManagerBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_insert_region: InsertRegion,
+    stmt_find_region: FindRegion,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    clock: SharedClock,
+}
+
+/// Store of the regions of the users recorded at the registration. The region is not changed later, thus the
+/// regional policies of a user are stable.
+#[derive(Clone)]
+pub struct RegionManager(Arc<Inner>);
+
+impl RegionManager {
+    pub async fn new(pool: &DBPool, clock: SharedClock) -> Result<Self, RegionManagerBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_insert_region = InsertRegion::new(&client).await?;
+                let stmt_find_region = FindRegion::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_insert_region,
+                    stmt_find_region,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(Inner { store, clock })))
+    }
+
+    /// Record the region of a user, an already recorded region is kept.
+    pub async fn set_region(&self, user_id: Uuid, region: &str) -> Result<(), DBError> {
+        let now = self.0.clock.now();
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_insert_region.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &region, &now]).await?;
+            }
+            Store::Sqlite(sqlite) => {
+                let region = region.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(SQLITE_INSERT_REGION, params![user_id, region, now])?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn find_region(&self, user_id: Uuid) -> Result<Option<String>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_find_region.get(&client).await?;
+                let row = client.query_opt(&stmt, &[&user_id]).await?;
+                Ok(row.map(|row| row.get(0)))
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Option<String>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_FIND_REGION)?;
+                        let mut rows = stmt.query(params![user_id])?;
+                        match rows.next()? {
+                            Some(row) => Ok(Some(row.get(0)?)),
+                            None => Ok(None),
+                        }
+                    })
+                    .await
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use crate::db::{DBError, DBPool, SharedClock, SqlPool, SqlitePool};
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use serde::Serialize;
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+pg_prepared_statement!( UpsertRestriction => r#"
+    INSERT INTO identity_restrictions (user_id, restriction, reason, expire_at, created, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6)
+    ON CONFLICT (user_id, restriction) DO UPDATE SET reason = $3, expire_at = $4, created = $5, created_by = $6
+"#, [UUID, VARCHAR, TEXT, TIMESTAMPTZ, TIMESTAMPTZ, UUID] );
+
+pg_prepared_statement!( DeleteRestriction => r#"
+    DELETE FROM identity_restrictions WHERE user_id = $1 AND restriction = $2
+"#, [UUID, VARCHAR] );
+
+pg_prepared_statement!( ListRestrictions => r#"
+    SELECT restriction, reason, expire_at, created, created_by FROM identity_restrictions
+        WHERE user_id = $1 AND (expire_at IS NULL OR expire_at > $2) ORDER BY restriction
+"#, [UUID, TIMESTAMPTZ] );
+
+const SQLITE_UPSERT_RESTRICTION: &str = r#"
+    INSERT INTO identity_restrictions (user_id, restriction, reason, expire_at, created, created_by)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+    ON CONFLICT (user_id, restriction) DO UPDATE SET reason = ?3, expire_at = ?4, created = ?5, created_by = ?6
+"#;
+
+const SQLITE_DELETE_RESTRICTION: &str = r#"
+    DELETE FROM identity_restrictions WHERE user_id = ?1 AND restriction = ?2
+"#;
+
+const SQLITE_LIST_RESTRICTIONS: &str = r#"
+    SELECT restriction, reason, expire_at, created, created_by FROM identity_restrictions
+        WHERE user_id = ?1 AND (expire_at IS NULL OR expire_at > ?2) ORDER BY restriction
+"#;
+
+/// A restriction of a user, ex. `chat:muted`. Unlike a ban, the user can still log in, the restriction is
+/// honored by the other services.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Restriction {
+    pub restriction: String,
+    pub reason: String,
+    /// The restriction is permanent when not set.
+    pub expire_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub created_by: Option<Uuid>,
+}
+
+impl Restriction {
+    fn from_sqlite_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            restriction: row.get(0)?,
+            reason: row.get(1)?,
+            expire_at: row.get(2)?,
+            created_at: row.get(3)?,
+            created_by: row.get(4)?,
+        })
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum RestrictionBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for RestrictionBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_upsert_restriction: UpsertRestriction,
+    stmt_delete_restriction: DeleteRestriction,
+    stmt_list_restrictions: ListRestrictions,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    clock: SharedClock,
+}
+
+/// Store of the restrictions of the users. The expired restrictions are ignored, they are kept until the
+/// restriction is set again.
+#[derive(Clone)]
+pub struct RestrictionManager(Arc<Inner>);
+
+impl RestrictionManager {
+    pub async fn new(pool: &DBPool, clock: SharedClock) -> Result<Self, RestrictionBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_upsert_restriction = UpsertRestriction::new(&client).await?;
+                let stmt_delete_restriction = DeleteRestriction::new(&client).await?;
+                let stmt_list_restrictions = ListRestrictions::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_upsert_restriction,
+                    stmt_delete_restriction,
+                    stmt_list_restrictions,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(Inner { store, clock })))
+    }
+
+    /// Restrict a user, an existing restriction of the same kind is replaced.
+    pub async fn set_restriction(
+        &self,
+        user_id: Uuid,
+        restriction: &str,
+        reason: &str,
+        expire_at: Option<DateTime<Utc>>,
+        created_by: Option<Uuid>,
+    ) -> Result<Restriction, DBError> {
+        let now = self.0.clock.now();
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_upsert_restriction.get(&client).await?;
+                client
+                    .execute(&stmt, &[&user_id, &restriction, &reason, &expire_at, &now, &created_by])
+                    .await?;
+            }
+            Store::Sqlite(sqlite) => {
+                let restriction = restriction.to_owned();
+                let reason = reason.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(
+                            SQLITE_UPSERT_RESTRICTION,
+                            params![user_id, restriction, reason, expire_at, now, created_by],
+                        )?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+
+        Ok(Restriction {
+            restriction: restriction.to_owned(),
+            reason: reason.to_owned(),
+            expire_at,
+            created_at: now,
+            created_by,
+        })
+    }
+
+    /// Lift a restriction of a user, false is returned if the user had no such restriction.
+    pub async fn remove_restriction(&self, user_id: Uuid, restriction: &str) -> Result<bool, DBError> {
+        let count = match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_delete_restriction.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &restriction]).await? as usize
+            }
+            Store::Sqlite(sqlite) => {
+                let restriction = restriction.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<usize, DBError> {
+                        Ok(conn.execute(SQLITE_DELETE_RESTRICTION, params![user_id, restriction])?)
+                    })
+                    .await?
+            }
+        };
+        Ok(count > 0)
+    }
+
+    /// List the active restrictions of a user.
+    pub async fn list_restrictions(&self, user_id: Uuid) -> Result<Vec<Restriction>, DBError> {
+        let now = self.0.clock.now();
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_list_restrictions.get(&client).await?;
+                let rows = client.query(&stmt, &[&user_id, &now]).await?;
+                Ok(rows
+                    .iter()
+                    .map(|row| Restriction {
+                        restriction: row.get(0),
+                        reason: row.get(1),
+                        expire_at: row.get(2),
+                        created_at: row.get(3),
+                        created_by: row.get(4),
+                    })
+                    .collect())
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Vec<Restriction>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_LIST_RESTRICTIONS)?;
+                        let restrictions = stmt
+                            .query_map(params![user_id, now], Restriction::from_sqlite_row)?
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(restrictions)
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Get the names of the active restrictions of a user, as shared with the other services.
+    pub async fn active_restrictions(&self, user_id: Uuid) -> Result<Vec<String>, DBError> {
+        Ok(self
+            .list_restrictions(user_id)
+            .await?
+            .into_iter()
+            .map(|restriction| restriction.restriction)
+            .collect())
+    }
+}
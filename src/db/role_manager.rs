@@ -0,0 +1,130 @@
+use crate::db::{DBError, DBPool, SqlPool, SqlitePool};
+use chrono::Utc;
+use rusqlite::params;
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+// The effective roles are the granted roles and all their ancestors. UNION removes the duplicates, thus the
+// recursion terminates even for a cyclic hierarchy.
+pg_prepared_statement!( GetRoles => r#"
+    WITH RECURSIVE effective(role) AS (
+        SELECT role FROM user_roles WHERE user_id = $1
+        UNION
+        SELECT r.parent FROM roles r JOIN effective e ON r.role = e.role WHERE r.parent IS NOT NULL
+    )
+    SELECT role FROM effective ORDER BY role
+"#, [UUID] );
+
+pg_prepared_statement!( InsertRole => r#"
+    INSERT INTO user_roles (user_id, role, granted)
+        VALUES ($1, $2, now())
+    ON CONFLICT (user_id, role) DO NOTHING
+"#, [UUID, VARCHAR] );
+
+const SQLITE_GET_ROLES: &str = r#"
+    WITH RECURSIVE effective(role) AS (
+        SELECT role FROM user_roles WHERE user_id = ?1
+        UNION
+        SELECT r.parent FROM roles r JOIN effective e ON r.role = e.role WHERE r.parent IS NOT NULL
+    )
+    SELECT role FROM effective ORDER BY role
+"#;
+
+const SQLITE_INSERT_ROLE: &str = r#"
+    INSERT INTO user_roles (user_id, role, granted)
+        VALUES (?1, ?2, ?3)
+    ON CONFLICT (user_id, role) DO NOTHING
+"#;
+
+#[derive(Debug, ThisError)]
+pub enum RoleBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for RoleBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_get_roles: GetRoles,
+    stmt_insert_role: InsertRole,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+/// Manage the roles granted to the users. A role implies all the roles up in the role hierarchy.
+#[derive(Clone)]
+pub struct RoleManager(Arc<Store>);
+
+impl RoleManager {
+    pub async fn new(pool: &DBPool) -> Result<Self, RoleBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_get_roles = GetRoles::new(&client).await?;
+                let stmt_insert_role = InsertRole::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_get_roles,
+                    stmt_insert_role,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(store)))
+    }
+
+    /// Grant a role to a user. Granting an existing role is not an error.
+    pub async fn grant_role(&self, user_id: Uuid, role: &str) -> Result<(), DBError> {
+        match &*self.0 {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_insert_role.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &role]).await?;
+            }
+            Store::Sqlite(sqlite) => {
+                let role = role.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(SQLITE_INSERT_ROLE, params![user_id, role, Utc::now()])?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the effective roles of a user.
+    pub async fn get_roles(&self, user_id: Uuid) -> Result<Vec<String>, DBError> {
+        match &*self.0 {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_get_roles.get(&client).await?;
+                let rows = client.query(&stmt, &[&user_id]).await?;
+                Ok(rows.iter().map(|row| row.get(0)).collect())
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Vec<String>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_GET_ROLES)?;
+                        let roles = stmt
+                            .query_map(params![user_id], |row| row.get(0))?
+                            .collect::<Result<Vec<String>, _>>()?;
+                        Ok(roles)
+                    })
+                    .await
+            }
+        }
+    }
+}
@@ -0,0 +1,413 @@
+use crate::db::{normalize_name, DBError, DBPool, IdentityKind, SharedClock, SqlPool, SqliteErrorChecks, SqlitePool};
+use chrono::{DateTime, Utc};
+use ring::{constant_time, digest};
+use rusqlite::{params, OptionalExtension};
+use shine_service::{
+    pg_prepared_statement,
+    service::{PGConnectionPool, PGErrorChecks},
+};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+pg_prepared_statement!( InsertServiceIdentity => r#"
+    INSERT INTO identities (user_id, kind, created, name, normalized_name, tenant_id)
+        VALUES ($1, $2, $3, $4, $5, $6)
+"#, [UUID, INT2, TIMESTAMPTZ, VARCHAR, VARCHAR, VARCHAR] );
+
+pg_prepared_statement!( InsertServiceClient => r#"
+    INSERT INTO service_clients (user_id, secret_hash, public_key, scopes, created)
+        VALUES ($1, $2, $3, $4, $5)
+"#, [UUID, VARCHAR, VARCHAR, TEXT, TIMESTAMPTZ] );
+
+pg_prepared_statement!( FindServiceClient => r#"
+    SELECT i.user_id, i.tenant_id, i.name, c.scopes, c.created, c.public_key, c.secret_hash
+        FROM service_clients c, identities i
+        WHERE c.user_id = $1 AND i.user_id = c.user_id
+"#, [UUID] );
+
+pg_prepared_statement!( ListServiceClients => r#"
+    SELECT i.user_id, i.tenant_id, i.name, c.scopes, c.created, c.public_key
+        FROM service_clients c, identities i
+        WHERE i.tenant_id = $1 AND i.user_id = c.user_id
+        ORDER BY i.name
+"#, [VARCHAR] );
+
+pg_prepared_statement!( UpdateServiceSecret => r#"
+    UPDATE service_clients SET secret_hash = $2 WHERE user_id = $1
+"#, [UUID, VARCHAR] );
+
+pg_prepared_statement!( UpdateServicePublicKey => r#"
+    UPDATE service_clients SET public_key = $2 WHERE user_id = $1
+"#, [UUID, VARCHAR] );
+
+const SQLITE_INSERT_SERVICE_IDENTITY: &str = r#"
+    INSERT INTO identities (user_id, kind, created, name, normalized_name, tenant_id)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+"#;
+
+const SQLITE_INSERT_SERVICE_CLIENT: &str = r#"
+    INSERT INTO service_clients (user_id, secret_hash, public_key, scopes, created)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+"#;
+
+const SQLITE_FIND_SERVICE_CLIENT: &str = r#"
+    SELECT i.user_id, i.tenant_id, i.name, c.scopes, c.created, c.public_key, c.secret_hash
+        FROM service_clients c, identities i
+        WHERE c.user_id = ?1 AND i.user_id = c.user_id
+"#;
+
+const SQLITE_LIST_SERVICE_CLIENTS: &str = r#"
+    SELECT i.user_id, i.tenant_id, i.name, c.scopes, c.created, c.public_key
+        FROM service_clients c, identities i
+        WHERE i.tenant_id = ?1 AND i.user_id = c.user_id
+        ORDER BY i.name
+"#;
+
+const SQLITE_UPDATE_SERVICE_SECRET: &str = r#"
+    UPDATE service_clients SET secret_hash = ?2 WHERE user_id = ?1
+"#;
+
+const SQLITE_UPDATE_SERVICE_PUBLIC_KEY: &str = r#"
+    UPDATE service_clients SET public_key = ?2 WHERE user_id = ?1
+"#;
+
+/// A service identity with its client credentials. The client id of the service is its user id.
+#[derive(Debug)]
+pub struct ServiceClientInfo {
+    pub user_id: Uuid,
+    pub tenant_id: String,
+    pub name: String,
+    /// The scopes the service can request in its tokens.
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    /// The Ed25519 public key (base64url encoded) of the service authenticating by signed assertions.
+    pub public_key: Option<String>,
+}
+
+impl ServiceClientInfo {
+    fn from_sqlite_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            user_id: row.get(0)?,
+            tenant_id: row.get(1)?,
+            name: row.get(2)?,
+            scopes: split_scopes(&row.get::<_, String>(3)?),
+            created_at: row.get(4)?,
+            public_key: row.get(5)?,
+        })
+    }
+
+    fn from_pg_row(row: &tokio_postgres::Row) -> Self {
+        Self {
+            user_id: row.get(0),
+            tenant_id: row.get(1),
+            name: row.get(2),
+            scopes: split_scopes(row.get(3)),
+            created_at: row.get(4),
+            public_key: row.get(5),
+        }
+    }
+}
+
+fn join_scopes(scopes: &[String]) -> String {
+    scopes.join(" ")
+}
+
+fn split_scopes(scopes: &str) -> Vec<String> {
+    scopes.split_whitespace().map(str::to_owned).collect()
+}
+
+/// The secrets are random tokens with a high entropy, thus a fast hash is sufficient, only the hash is stored.
+fn hash_secret(secret: &str) -> String {
+    hex::encode(digest::digest(&digest::SHA256, secret.as_bytes()).as_ref())
+}
+
+/// The secret hash of the services without a secret, it is not matching any secret.
+const NO_SECRET_HASH: &str = "";
+
+#[derive(Debug, ThisError)]
+pub enum ServiceClientError {
+    #[error("Name already taken")]
+    NameConflict,
+    #[error("Conflicting user id")]
+    UserIdConflict,
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for ServiceClientError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+impl From<rusqlite::Error> for ServiceClientError {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum ServiceClientBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for ServiceClientBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_insert_identity: InsertServiceIdentity,
+    stmt_insert_client: InsertServiceClient,
+    stmt_find_client: FindServiceClient,
+    stmt_list_clients: ListServiceClients,
+    stmt_update_secret: UpdateServiceSecret,
+    stmt_update_public_key: UpdateServicePublicKey,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    clock: SharedClock,
+}
+
+/// Manage the service identities of the internal services and their client credentials. The services are
+/// deleted as any other identity, the credentials are removed by the cascaded delete.
+#[derive(Clone)]
+pub struct ServiceClientManager(Arc<Inner>);
+
+impl ServiceClientManager {
+    pub async fn new(pool: &DBPool, clock: SharedClock) -> Result<Self, ServiceClientBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_insert_identity = InsertServiceIdentity::new(&client).await?;
+                let stmt_insert_client = InsertServiceClient::new(&client).await?;
+                let stmt_find_client = FindServiceClient::new(&client).await?;
+                let stmt_list_clients = ListServiceClients::new(&client).await?;
+                let stmt_update_secret = UpdateServiceSecret::new(&client).await?;
+                let stmt_update_public_key = UpdateServicePublicKey::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_insert_identity,
+                    stmt_insert_client,
+                    stmt_find_client,
+                    stmt_list_clients,
+                    stmt_update_secret,
+                    stmt_update_public_key,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(Inner { store, clock })))
+    }
+
+    /// Create a service identity with its client credentials atomically. A service without a secret can
+    /// authenticate only by the assertions signed by its key.
+    pub async fn create_service(
+        &self,
+        tenant_id: &str,
+        user_id: Uuid,
+        name: &str,
+        scopes: &[String],
+        secret: Option<&str>,
+        public_key: Option<&str>,
+    ) -> Result<ServiceClientInfo, ServiceClientError> {
+        let now = self.0.clock.now();
+        let secret_hash = secret.map_or_else(|| NO_SECRET_HASH.to_owned(), hash_secret);
+        let public_key = public_key.map(ToOwned::to_owned);
+        let joined_scopes = join_scopes(scopes);
+        let normalized_name = normalize_name(name);
+
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let mut client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_insert_identity = pg.stmt_insert_identity.get(&client).await?;
+                let stmt_insert_client = pg.stmt_insert_client.get(&client).await?;
+
+                let transaction = client.transaction().await?;
+                match transaction
+                    .execute(
+                        &stmt_insert_identity,
+                        &[
+                            &user_id,
+                            &IdentityKind::Service,
+                            &now,
+                            &name,
+                            &normalized_name,
+                            &tenant_id,
+                        ],
+                    )
+                    .await
+                {
+                    Ok(_) => {}
+                    Err(err) if err.is_constraint("identities", "identities_pkey") => {
+                        return Err(ServiceClientError::UserIdConflict)
+                    }
+                    Err(err) if err.is_constraint("identities", "idx_name") => {
+                        return Err(ServiceClientError::NameConflict)
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+                transaction
+                    .execute(
+                        &stmt_insert_client,
+                        &[&user_id, &secret_hash, &public_key, &joined_scopes, &now],
+                    )
+                    .await?;
+                transaction.commit().await?;
+            }
+            Store::Sqlite(sqlite) => {
+                let (tenant_id, name, public_key) = (tenant_id.to_owned(), name.to_owned(), public_key.clone());
+                sqlite
+                    .call(move |conn| -> Result<(), ServiceClientError> {
+                        // the transaction is rolled back when it is dropped without a commit
+                        let transaction = conn.transaction()?;
+                        match transaction.execute(
+                            SQLITE_INSERT_SERVICE_IDENTITY,
+                            params![user_id, IdentityKind::Service, now, name, normalized_name, tenant_id],
+                        ) {
+                            Ok(_) => {}
+                            Err(err) if err.is_constraint("identities", "user_id") => {
+                                return Err(ServiceClientError::UserIdConflict)
+                            }
+                            Err(err) if err.is_constraint("identities", "normalized_name") => {
+                                return Err(ServiceClientError::NameConflict)
+                            }
+                            Err(err) => return Err(err.into()),
+                        }
+                        transaction.execute(
+                            SQLITE_INSERT_SERVICE_CLIENT,
+                            params![user_id, secret_hash, public_key, joined_scopes, now],
+                        )?;
+                        transaction.commit()?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+
+        Ok(ServiceClientInfo {
+            user_id,
+            tenant_id: tenant_id.to_owned(),
+            name: name.to_owned(),
+            scopes: scopes.to_vec(),
+            created_at: now,
+            public_key,
+        })
+    }
+
+    async fn find_client(&self, client_id: Uuid) -> Result<Option<(ServiceClientInfo, String)>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_find_client.get(&client).await?;
+                Ok(client
+                    .query_opt(&stmt, &[&client_id])
+                    .await?
+                    .map(|row| (ServiceClientInfo::from_pg_row(&row), row.get::<_, String>(6))))
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Option<(ServiceClientInfo, String)>, DBError> {
+                        Ok(conn
+                            .query_row(SQLITE_FIND_SERVICE_CLIENT, params![client_id], |row| {
+                                Ok((ServiceClientInfo::from_sqlite_row(row)?, row.get(6)?))
+                            })
+                            .optional()?)
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Find a service by its client credentials. None is returned if the client is not found or the secret is not
+    /// matching.
+    pub async fn authenticate(&self, client_id: Uuid, secret: &str) -> Result<Option<ServiceClientInfo>, DBError> {
+        let found = self.find_client(client_id).await?;
+        Ok(found.and_then(|(info, secret_hash)| {
+            constant_time::verify_slices_are_equal(secret_hash.as_bytes(), hash_secret(secret).as_bytes())
+                .ok()
+                .map(|_| info)
+        }))
+    }
+
+    /// Find a service by its client id without checking the credentials, ex. to get the key to verify an assertion.
+    pub async fn find_service(&self, client_id: Uuid) -> Result<Option<ServiceClientInfo>, DBError> {
+        Ok(self.find_client(client_id).await?.map(|(info, _)| info))
+    }
+
+    /// Get the services of a tenant ordered by name.
+    pub async fn list_services(&self, tenant_id: &str) -> Result<Vec<ServiceClientInfo>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_list_clients.get(&client).await?;
+                let rows = client.query(&stmt, &[&tenant_id]).await?;
+                Ok(rows.iter().map(ServiceClientInfo::from_pg_row).collect())
+            }
+            Store::Sqlite(sqlite) => {
+                let tenant_id = tenant_id.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<Vec<ServiceClientInfo>, DBError> {
+                        let mut stmt = conn.prepare(SQLITE_LIST_SERVICE_CLIENTS)?;
+                        let services = stmt
+                            .query_map(params![tenant_id], ServiceClientInfo::from_sqlite_row)?
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(services)
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Replace the secret of a service, the previous secret is invalidated immediately. Returns false if the
+    /// service was not found.
+    pub async fn set_secret(&self, user_id: Uuid, secret: &str) -> Result<bool, DBError> {
+        let secret_hash = hash_secret(secret);
+        let updated = match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_update_secret.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &secret_hash]).await? as usize
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<usize, DBError> {
+                        Ok(conn.execute(SQLITE_UPDATE_SERVICE_SECRET, params![user_id, secret_hash])?)
+                    })
+                    .await?
+            }
+        };
+        Ok(updated == 1)
+    }
+    /// Set or remove the public key of a service. The services with a key can authenticate by signed assertions
+    /// beside the secret, if any. Returns false if the service was not found.
+    pub async fn set_public_key(&self, user_id: Uuid, public_key: Option<&str>) -> Result<bool, DBError> {
+        let public_key = public_key.map(ToOwned::to_owned);
+        let updated = match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_update_public_key.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &public_key]).await? as usize
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<usize, DBError> {
+                        Ok(conn.execute(SQLITE_UPDATE_SERVICE_PUBLIC_KEY, params![user_id, public_key])?)
+                    })
+                    .await?
+            }
+        };
+        Ok(updated == 1)
+    }
+}
@@ -1,5 +1,8 @@
-use crate::db::Identity;
-use crate::db::{DBError, DBPool};
+use crate::db::{
+    DBError, DBPool, FaultInjector, FaultLayer, Identity, SessionAuthContext, SessionInfo, SessionStore, SharedClock,
+    DEFAULT_TENANT_ID,
+};
+use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
 use redis::{AsyncCommands, Script};
 use ring::rand::SystemRandom;
@@ -14,6 +17,11 @@ use uuid::Uuid;
 pub enum DBSessionError {
     #[error("Failed to create session, conflicting keys")]
     KeyConflict,
+    #[error("Too many active sessions")]
+    SessionLimitReached,
+    /// The login to an expired time-limited account is refused.
+    #[error("Account has expired")]
+    AccountExpired,
 
     #[error(transparent)]
     SessionKeyError(#[from] SessionKeyError),
@@ -27,14 +35,36 @@ struct StoredSession {
     pub session_start: DateTime<Utc>,
     pub name: String,
     pub is_email_confirmed: bool,
+    /// Roles granted only to the session, ex. the role of the emergency access. The roles of the user are not
+    /// stored, they are resolved on each check, thus a role change takes effect in the active sessions immediately.
+    #[serde(default)]
+    pub session_roles: Vec<String>,
+    /// The authentication context reported by the external provider at the login.
+    #[serde(default)]
+    pub auth_context: Option<SessionAuthContext>,
+    /// The tenant of the user, the sessions created before the tenants were stored belong to the default tenant.
+    #[serde(default = "default_tenant_id")]
+    pub tenant_id: String,
+}
+
+fn default_tenant_id() -> String {
+    DEFAULT_TENANT_ID.to_owned()
 }
 
 impl StoredSession {
-    fn from_identity(identity: &Identity, session_start: DateTime<Utc>) -> Self {
+    fn from_identity(
+        identity: &Identity,
+        session_roles: Vec<String>,
+        auth_context: Option<SessionAuthContext>,
+        session_start: DateTime<Utc>,
+    ) -> Self {
         Self {
             session_start,
             name: identity.name.clone(),
             is_email_confirmed: identity.is_email_confirmed,
+            session_roles,
+            auth_context,
+            tenant_id: identity.tenant_id.clone(),
         }
     }
 
@@ -49,6 +79,24 @@ impl StoredSession {
     }
 }
 
+/// What to do when a new session would exceed the limit of active sessions.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SessionLimitPolicy {
+    /// Reject the new login.
+    Reject,
+    /// Remove the oldest active sessions to make room for the new one.
+    EvictOldest,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionLimitConfig {
+    /// Maximum number of simultaneous active sessions of a user.
+    pub max_sessions: usize,
+    pub policy: SessionLimitPolicy,
+}
+
 #[derive(Debug, ThisError)]
 pub enum SessionBuildError {
     #[error(transparent)]
@@ -57,59 +105,214 @@ pub enum SessionBuildError {
 
 pub struct Inner {
     redis: RedisConnectionPool,
+    key_prefix: String,
     session_duration: usize,
+    session_limit: Option<SessionLimitConfig>,
     random: SystemRandom,
+    clock: SharedClock,
+    faults: FaultInjector,
 }
 
+/// The session store backed by Redis.
 #[derive(Clone)]
 pub struct SessionManager(Arc<Inner>);
 
 impl SessionManager {
-    pub async fn new(pool: &DBPool, session_duration: Duration) -> Result<Self, SessionBuildError> {
+    pub async fn new(
+        pool: &DBPool,
+        session_duration: Duration,
+        session_limit: Option<SessionLimitConfig>,
+        clock: SharedClock,
+    ) -> Result<Self, SessionBuildError> {
         Ok(SessionManager(Arc::new(Inner {
             redis: pool.redis.clone(),
+            key_prefix: pool.redis_key_prefix.clone(),
             random: SystemRandom::new(),
             session_duration: session_duration.num_seconds() as usize,
+            session_limit,
+            clock,
+            faults: pool.faults.clone(),
         })))
     }
+}
 
-    pub async fn create(&self, identity: &Identity) -> Result<CurrentUser, DBSessionError> {
-        let created_at = Utc::now();
+#[async_trait]
+impl SessionStore for SessionManager {
+    async fn create(
+        &self,
+        identity: &Identity,
+        auth_context: Option<SessionAuthContext>,
+    ) -> Result<(CurrentUser, Vec<String>), DBSessionError> {
+        let duration = Duration::seconds(self.0.session_duration as i64);
+        self.create_with_duration(identity, Vec::new(), auth_context, duration)
+            .await
+    }
 
+    async fn create_with_duration(
+        &self,
+        identity: &Identity,
+        session_roles: Vec<String>,
+        auth_context: Option<SessionAuthContext>,
+        duration: Duration,
+    ) -> Result<(CurrentUser, Vec<String>), DBSessionError> {
         let inner = &*self.0;
+        let created_at = inner.clock.now();
+
+        inner.faults.inject(FaultLayer::Redis).await?;
         let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
 
         let session_key = SessionKey::new_random(&inner.random)?;
-        let key = format!("session:{}:{}", identity.user_id.as_simple(), session_key.to_hex());
-
-        let session = StoredSession::from_identity(identity, created_at);
-
-        let created: bool = client.set_nx(&key, &session).await.map_err(DBError::RedisError)?;
-        if created {
-            client
-                .expire(&key, inner.session_duration)
-                .await
-                .map_err(DBError::RedisError)?;
-            Ok(session.into_current_user(identity.user_id, session_key))
-        } else {
-            Err(DBSessionError::KeyConflict)
+        let key_prefix = format!("session:{}:", identity.user_id.as_simple());
+        let key = format!("{key_prefix}{}", session_key.to_hex());
+
+        let session = StoredSession::from_identity(identity, session_roles, auth_context, created_at);
+        if inner.faults.is_constraint_violated(FaultLayer::Redis) {
+            return Err(DBSessionError::KeyConflict);
+        }
+
+        let (max_sessions, evict) = match &inner.session_limit {
+            Some(limit) => (
+                limit.max_sessions,
+                matches!(limit.policy, SessionLimitPolicy::EvictOldest),
+            ),
+            None => (0, false),
+        };
+
+        // All the sessions have the same duration, thus the oldest session is the one expiring first.
+        let lua_script = r#"
+local max = tonumber(ARGV[4])
+local evicted = {}
+if max > 0 then
+    local keys = redis.call('KEYS', ARGV[1] .. '*')
+    if #keys >= max then
+        if ARGV[5] ~= '1' then
+            return {'limit'}
+        end
+        local sessions = {}
+        for _, key in ipairs(keys) do
+            table.insert(sessions, { key = key, ttl = redis.call('TTL', key) })
+        end
+        table.sort(sessions, function(a, b) return a.ttl < b.ttl end)
+        for i = 1, #sessions - max + 1 do
+            redis.call('DEL', sessions[i].key)
+            table.insert(evicted, string.sub(sessions[i].key, #ARGV[1] + 1))
+        end
+    end
+end
+if not redis.call('SET', ARGV[2], ARGV[3], 'NX', 'EX', ARGV[6]) then
+    return {'conflict'}
+end
+table.insert(evicted, 1, 'ok')
+return evicted
+"#;
+
+        let mut result: Vec<String> = Script::new(lua_script)
+            .arg(&key_prefix)
+            .arg(&key)
+            .arg(&session)
+            .arg(max_sessions)
+            .arg(if evict { "1" } else { "0" })
+            .arg(duration.num_seconds())
+            .invoke_async(&mut *client)
+            .await
+            .map_err(DBError::RedisError)?;
+
+        match result.first().map(String::as_str) {
+            Some("ok") => {
+                let evicted = result.split_off(1);
+                Ok((session.into_current_user(identity.user_id, session_key), evicted))
+            }
+            Some("limit") => Err(DBSessionError::SessionLimitReached),
+            _ => Err(DBSessionError::KeyConflict),
         }
     }
 
-    pub async fn find_session(&self, user_id: Uuid, session_key: SessionKey) -> Result<Option<CurrentUser>, DBError> {
+    async fn find_session(
+        &self,
+        tenant_id: &str,
+        user_id: Uuid,
+        session_key: SessionKey,
+    ) -> Result<Option<CurrentUser>, DBError> {
         let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Redis).await?;
         let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
 
         let key = format!("session:{}:{}", user_id.as_simple(), session_key.to_hex());
         let session: Option<StoredSession> = client.get(&key).await.map_err(DBError::RedisError)?;
-        let session = session.map(|session| session.into_current_user(user_id, session_key));
+        let session = session
+            .filter(|session| session.tenant_id == tenant_id)
+            .map(|session| session.into_current_user(user_id, session_key));
 
         Ok(session)
     }
 
-    /// Remove an active session of the given user.
-    pub async fn remove(&self, user_id: Uuid, session_key: SessionKey) -> Result<(), DBError> {
+    async fn find_session_roles(&self, user_id: Uuid, session_key: SessionKey) -> Result<Vec<String>, DBError> {
+        let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Redis).await?;
+        let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let key = format!("session:{}:{}", user_id.as_simple(), session_key.to_hex());
+        let session: Option<StoredSession> = client.get(&key).await.map_err(DBError::RedisError)?;
+        Ok(session.map(|session| session.session_roles).unwrap_or_default())
+    }
+
+    async fn find_session_auth_context(
+        &self,
+        user_id: Uuid,
+        session_key: SessionKey,
+    ) -> Result<Option<SessionAuthContext>, DBError> {
+        let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Redis).await?;
+        let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let key = format!("session:{}:{}", user_id.as_simple(), session_key.to_hex());
+        let session: Option<StoredSession> = client.get(&key).await.map_err(DBError::RedisError)?;
+        Ok(session.and_then(|session| session.auth_context))
+    }
+
+    async fn touch(&self, user_id: Uuid, session_key: SessionKey) -> Result<bool, DBError> {
         let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Redis).await?;
+        let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let key = format!("session:{}:{}", user_id.as_simple(), session_key.to_hex());
+        let touched: bool = client
+            .expire(&key, inner.session_duration)
+            .await
+            .map_err(DBError::RedisError)?;
+        Ok(touched)
+    }
+
+    async fn list_sessions(&self, user_id: Uuid) -> Result<Vec<SessionInfo>, DBError> {
+        let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Redis).await?;
+        let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let key_prefix = format!("session:{}:", user_id.as_simple());
+        let keys: Vec<String> = client
+            .keys(format!("{key_prefix}*"))
+            .await
+            .map_err(DBError::RedisError)?;
+
+        let mut sessions = Vec::with_capacity(keys.len());
+        for key in keys {
+            // the session may expire between the two queries
+            let session: Option<StoredSession> = client.get(&key).await.map_err(DBError::RedisError)?;
+            if let Some(session) = session {
+                sessions.push(SessionInfo {
+                    session_key: key[key_prefix.len()..].to_owned(),
+                    session_start: session.session_start,
+                    name: session.name,
+                });
+            }
+        }
+        sessions.sort_by_key(|session| session.session_start);
+        Ok(sessions)
+    }
+
+    async fn remove(&self, user_id: Uuid, session_key: SessionKey) -> Result<(), DBError> {
+        let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Redis).await?;
         let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
 
         let key = format!("session:{}:{}", user_id.as_simple(), session_key.to_hex());
@@ -117,9 +320,64 @@ impl SessionManager {
         Ok(())
     }
 
-    /// Remove all the active session of the given user.
-    pub async fn remove_all(&self, user_id: Uuid) -> Result<(), DBError> {
+    async fn create_revoke_token(
+        &self,
+        token: &str,
+        user_id: Uuid,
+        session_key: SessionKey,
+        duration: Duration,
+    ) -> Result<(), DBError> {
+        let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Redis).await?;
+        let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let key = format!("{}session-revoke:{token}", inner.key_prefix);
+        let session = format!("{}:{}", user_id.as_simple(), session_key.to_hex());
+        let _: () = client
+            .set_ex(&key, session, duration.num_seconds() as usize)
+            .await
+            .map_err(DBError::RedisError)?;
+        Ok(())
+    }
+
+    async fn revoke_by_token(&self, token: &str) -> Result<Option<Uuid>, DBError> {
+        let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Redis).await?;
+        let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        // the token is taken atomically, thus it can be used only once even by concurrent requests
+        let lua_script = r#"
+local session = redis.call('GET', KEYS[1])
+if session then
+    redis.call('DEL', KEYS[1])
+end
+return session
+"#;
+
+        let key = format!("{}session-revoke:{token}", inner.key_prefix);
+        let session: Option<String> = Script::new(lua_script)
+            .key(key)
+            .invoke_async(&mut *client)
+            .await
+            .map_err(DBError::RedisError)?;
+
+        let (user_id, session_key) = match session.as_deref().and_then(|session| session.split_once(':')) {
+            Some(session) => session,
+            None => return Ok(None),
+        };
+        let user_id = match Uuid::parse_str(user_id) {
+            Ok(user_id) => user_id,
+            Err(_) => return Ok(None),
+        };
+
+        let key = format!("session:{}:{}", user_id.as_simple(), session_key);
+        let _: () = client.del(&key).await.map_err(DBError::RedisError)?;
+        Ok(Some(user_id))
+    }
+
+    async fn remove_all(&self, user_id: Uuid) -> Result<(), DBError> {
         let inner = &*self.0;
+        inner.faults.inject(FaultLayer::Redis).await?;
         let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
 
         let lua_script = r#"
@@ -0,0 +1,100 @@
+use crate::db::{DBError, DBSessionError, Identity};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use shine_service::service::{CurrentUser, SessionKey};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// An active session of a user.
+#[derive(Debug)]
+pub struct SessionInfo {
+    /// Hex encoded key of the session.
+    pub session_key: String,
+    pub session_start: DateTime<Utc>,
+    pub name: String,
+}
+
+/// The details of the authentication reported by the external provider, ex. for the policies requiring a
+/// multi-factor login at the provider. Only the OpenID Connect providers report these claims.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionAuthContext {
+    /// The authentication context class reference (`acr` claim).
+    pub acr: Option<String>,
+    /// The authentication method references (`amr` claim), ex. `pwd`, `mfa`, `hwk`.
+    #[serde(default)]
+    pub amr: Vec<String>,
+    /// The time of the authentication at the provider (`auth_time` claim).
+    pub auth_time: Option<DateTime<Utc>>,
+}
+
+/// Storage of the active sessions, the implementation is the `SessionManager` backed by Redis.
+#[async_trait]
+pub trait SessionStore: 'static + Send + Sync {
+    /// Create a new session for the identity with the authentication context of the provider. When the limit of the
+    /// active sessions is reached and the oldest sessions are evicted, the keys of the removed sessions are also
+    /// returned.
+    async fn create(
+        &self,
+        identity: &Identity,
+        auth_context: Option<SessionAuthContext>,
+    ) -> Result<(CurrentUser, Vec<String>), DBSessionError>;
+
+    /// Create a new session with a custom duration and with roles granted only to this session, see `create`.
+    async fn create_with_duration(
+        &self,
+        identity: &Identity,
+        session_roles: Vec<String>,
+        auth_context: Option<SessionAuthContext>,
+        duration: Duration,
+    ) -> Result<(CurrentUser, Vec<String>), DBSessionError>;
+
+    /// Find an active session of the user. The session of another tenant is not found, the cookies of the tenants
+    /// may share the secrets, thus a session has to be checked against the tenant of the request.
+    async fn find_session(
+        &self,
+        tenant_id: &str,
+        user_id: Uuid,
+        session_key: SessionKey,
+    ) -> Result<Option<CurrentUser>, DBError>;
+
+    /// Get the roles granted only to an active session, see `create_with_duration`.
+    async fn find_session_roles(&self, user_id: Uuid, session_key: SessionKey) -> Result<Vec<String>, DBError>;
+
+    /// Get the authentication context of an active session. It is None if the session was not found or the login
+    /// was not reporting it.
+    async fn find_session_auth_context(
+        &self,
+        user_id: Uuid,
+        session_key: SessionKey,
+    ) -> Result<Option<SessionAuthContext>, DBError>;
+
+    /// Extend the expiration of an active session by the session duration. Returns false if the session was not
+    /// found.
+    async fn touch(&self, user_id: Uuid, session_key: SessionKey) -> Result<bool, DBError>;
+
+    /// Get the active sessions of a user.
+    async fn list_sessions(&self, user_id: Uuid) -> Result<Vec<SessionInfo>, DBError>;
+
+    /// Remove an active session of the given user.
+    async fn remove(&self, user_id: Uuid, session_key: SessionKey) -> Result<(), DBError>;
+
+    /// Remove all the active session of the given user.
+    async fn remove_all(&self, user_id: Uuid) -> Result<(), DBError>;
+
+    /// Store a token that can revoke the given session without a login, ex. from a login alert email.
+    async fn create_revoke_token(
+        &self,
+        token: &str,
+        user_id: Uuid,
+        session_key: SessionKey,
+        duration: Duration,
+    ) -> Result<(), DBError>;
+
+    /// Remove the session of a revoke token. The token can be used only once and the user of the
+    /// session is returned if the token was valid.
+    async fn revoke_by_token(&self, token: &str) -> Result<Option<Uuid>, DBError>;
+}
+
+pub type SharedSessionStore = Arc<dyn SessionStore>;
@@ -0,0 +1,56 @@
+use crate::db::DBError;
+use rusqlite::{Connection, ErrorCode};
+use std::sync::{Arc, Mutex};
+use tokio::task;
+
+/// A single shared SQLite connection. The queries are executed on the blocking thread pool one after the other,
+/// it is good enough for the local development and the tests, but not for production.
+#[derive(Clone)]
+pub struct SqlitePool(Arc<Mutex<Connection>>);
+
+impl SqlitePool {
+    pub fn open(path: &str) -> Result<Self, DBError> {
+        let connection = if path == ":memory:" {
+            Connection::open_in_memory()?
+        } else {
+            Connection::open(path)?
+        };
+        connection.pragma_update(None, "foreign_keys", "ON")?;
+        Ok(Self(Arc::new(Mutex::new(connection))))
+    }
+
+    /// Run the queries of the closure with exclusive access to the connection.
+    pub async fn call<F, T, E>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, E> + Send + 'static,
+        T: Send + 'static,
+        E: From<DBError> + Send + 'static,
+    {
+        let connection = self.0.clone();
+        task::spawn_blocking(move || {
+            // a panic in a previous query does not leave the connection in an inconsistent state,
+            // an open transaction is rolled back on drop
+            let mut connection = connection.lock().unwrap_or_else(|err| err.into_inner());
+            f(&mut connection)
+        })
+        .await
+        .map_err(|err| DBError::SqliteTaskError(format!("{err}")))?
+    }
+}
+
+pub trait SqliteErrorChecks {
+    /// Check if the error is the violation of a unique index on the given column.
+    fn is_constraint(&self, table: &str, column: &str) -> bool;
+}
+
+impl SqliteErrorChecks for rusqlite::Error {
+    fn is_constraint(&self, table: &str, column: &str) -> bool {
+        match self {
+            rusqlite::Error::SqliteFailure(err, Some(message)) if err.code == ErrorCode::ConstraintViolation => message
+                .strip_prefix("UNIQUE constraint failed: ")
+                .map(|columns| columns.split(", ").any(|c| c == format!("{table}.{column}")))
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use crate::db::{DBError, DBPool};
+use chrono::Duration;
+use redis::AsyncCommands;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use shine_service::service::RedisConnectionPool;
+use uuid::Uuid;
+
+/// A single-use code handing the login of the auth domain over to a first-party app, the app redeems it for a local
+/// session.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SsoHandoffCode {
+    pub user_id: Uuid,
+    pub tenant_id: String,
+    /// The app the code was issued to, it can be redeemed only by the same app.
+    pub app_id: String,
+}
+
+/// Manage the handoff codes of the single sign-on of the apps, only the hash of the codes is stored.
+#[derive(Clone)]
+pub struct SsoHandoffManager {
+    redis: RedisConnectionPool,
+    key_prefix: String,
+}
+
+impl SsoHandoffManager {
+    pub fn new(pool: &DBPool) -> Self {
+        Self {
+            redis: pool.redis.clone(),
+            key_prefix: pool.redis_key_prefix.clone(),
+        }
+    }
+
+    fn key(&self, code: &str) -> String {
+        let hash = digest::digest(&digest::SHA256, code.as_bytes());
+        format!("{}sso-handoff:{}", self.key_prefix, hex::encode(hash.as_ref()))
+    }
+
+    pub async fn create(&self, code: &str, handoff: &SsoHandoffCode, duration: Duration) -> Result<(), DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+        let handoff = serde_json::to_string(handoff).expect("Failed to serialize sso handoff");
+        let _: () = client
+            .set_ex(self.key(code), handoff, duration.num_seconds() as usize)
+            .await
+            .map_err(DBError::RedisError)?;
+        Ok(())
+    }
+
+    /// Consume a code, it can be taken only once.
+    pub async fn take(&self, code: &str) -> Result<Option<SsoHandoffCode>, DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+        let key = self.key(code);
+        let handoff: Option<String> = client.get(&key).await.map_err(DBError::RedisError)?;
+        let deleted: u32 = client.del(&key).await.map_err(DBError::RedisError)?;
+
+        // when the key was deleted concurrently, the code has already been used
+        if deleted == 0 {
+            return Ok(None);
+        }
+        Ok(handoff.and_then(|handoff| serde_json::from_str(&handoff).ok()))
+    }
+}
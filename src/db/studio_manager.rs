@@ -0,0 +1,732 @@
+use crate::db::{
+    normalize_name, DBError, DBPool, IdentityKind, Resource, SharedClock, SqlPool, SqliteErrorChecks, SqlitePool,
+};
+use chrono::{DateTime, Utc};
+use ring::digest;
+use rusqlite::{params, OptionalExtension};
+use shine_service::{
+    pg_prepared_statement,
+    service::{PGConnectionPool, PGErrorChecks},
+};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+/// The resource type of the grants on the studios.
+pub const STUDIO_RESOURCE: &str = "studio";
+/// The action granted to the owners of a studio, the owners are also members.
+pub const STUDIO_OWNER: &str = "owner";
+/// The action granted to the members of a studio.
+pub const STUDIO_MEMBER: &str = "member";
+
+/// Get the resource of a studio to check the grants of the members.
+pub fn studio_resource(studio_id: Uuid) -> Resource {
+    Resource {
+        resource_type: STUDIO_RESOURCE.to_owned(),
+        resource_id: studio_id.to_string(),
+    }
+}
+
+pg_prepared_statement!( InsertStudioIdentity => r#"
+    INSERT INTO identities (user_id, kind, created, name, normalized_name, tenant_id)
+        VALUES ($1, $2, $3, $4, $5, $6)
+"#, [UUID, INT2, TIMESTAMPTZ, VARCHAR, VARCHAR, VARCHAR] );
+
+pg_prepared_statement!( InsertStudioGrant => r#"
+    INSERT INTO grants (user_id, action, resource_type, resource_id, granted, granted_by)
+        VALUES ($1, $2, $3, $4, $5, $1)
+    ON CONFLICT (user_id, action, resource_type, resource_id) DO NOTHING
+"#, [UUID, VARCHAR, VARCHAR, VARCHAR, TIMESTAMPTZ] );
+
+pg_prepared_statement!( InsertStudioToken => r#"
+    INSERT INTO studio_tokens (token_id, studio_id, token_hash, name, scopes, created, created_by, expire)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+"#, [UUID, UUID, VARCHAR, VARCHAR, TEXT, TIMESTAMPTZ, UUID, TIMESTAMPTZ] );
+
+pg_prepared_statement!( FindStudioToken => r#"
+    SELECT t.token_id, t.studio_id, i.tenant_id, t.name, t.scopes, t.created, t.created_by, t.expire
+        FROM studio_tokens t, identities i
+        WHERE t.token_hash = $1 AND i.user_id = t.studio_id
+            AND NOT EXISTS(SELECT 1 FROM identity_deletions d WHERE d.user_id = t.studio_id)
+"#, [VARCHAR] );
+
+pg_prepared_statement!( ListStudioTokens => r#"
+    SELECT t.token_id, t.studio_id, i.tenant_id, t.name, t.scopes, t.created, t.created_by, t.expire
+        FROM studio_tokens t, identities i
+        WHERE t.studio_id = $1 AND i.user_id = t.studio_id
+        ORDER BY t.created
+"#, [UUID] );
+
+pg_prepared_statement!( DeleteStudioToken => r#"
+    DELETE FROM studio_tokens WHERE studio_id = $1 AND token_id = $2
+"#, [UUID, UUID] );
+
+pg_prepared_statement!( DeleteStudioGrants => r#"
+    DELETE FROM grants WHERE resource_type = $1 AND resource_id = $2
+    RETURNING user_id
+"#, [VARCHAR, VARCHAR] );
+
+pg_prepared_statement!( RevokeStudioOwners => r#"
+    DELETE FROM grants WHERE action = $1 AND resource_type = $2 AND resource_id = $3 AND user_id <> $4
+    RETURNING user_id
+"#, [VARCHAR, VARCHAR, VARCHAR, UUID] );
+
+pg_prepared_statement!( UpsertStudioTransfer => r#"
+    INSERT INTO studio_transfers (studio_id, new_owner_id, requested_by, requested, expire)
+        VALUES ($1, $2, $3, $4, $5)
+    ON CONFLICT (studio_id) DO UPDATE
+        SET new_owner_id = excluded.new_owner_id, requested_by = excluded.requested_by,
+            requested = excluded.requested, expire = excluded.expire
+"#, [UUID, UUID, UUID, TIMESTAMPTZ, TIMESTAMPTZ] );
+
+pg_prepared_statement!( FindStudioTransfer => r#"
+    SELECT studio_id, new_owner_id, requested_by, requested, expire FROM studio_transfers WHERE studio_id = $1
+"#, [UUID] );
+
+pg_prepared_statement!( DeleteStudioTransfer => r#"
+    DELETE FROM studio_transfers WHERE studio_id = $1
+"#, [UUID] );
+
+pg_prepared_statement!( CompleteStudioTransfer => r#"
+    DELETE FROM studio_transfers WHERE studio_id = $1 AND new_owner_id = $2 AND expire > $3
+"#, [UUID, UUID, TIMESTAMPTZ] );
+
+const SQLITE_INSERT_STUDIO_IDENTITY: &str = r#"
+    INSERT INTO identities (user_id, kind, created, name, normalized_name, tenant_id)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+"#;
+
+const SQLITE_INSERT_STUDIO_GRANT: &str = r#"
+    INSERT INTO grants (user_id, action, resource_type, resource_id, granted, granted_by)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?1)
+    ON CONFLICT (user_id, action, resource_type, resource_id) DO NOTHING
+"#;
+
+const SQLITE_INSERT_STUDIO_TOKEN: &str = r#"
+    INSERT INTO studio_tokens (token_id, studio_id, token_hash, name, scopes, created, created_by, expire)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+"#;
+
+const SQLITE_FIND_STUDIO_TOKEN: &str = r#"
+    SELECT t.token_id, t.studio_id, i.tenant_id, t.name, t.scopes, t.created, t.created_by, t.expire
+        FROM studio_tokens t, identities i
+        WHERE t.token_hash = ?1 AND i.user_id = t.studio_id
+            AND NOT EXISTS(SELECT 1 FROM identity_deletions d WHERE d.user_id = t.studio_id)
+"#;
+
+const SQLITE_LIST_STUDIO_TOKENS: &str = r#"
+    SELECT t.token_id, t.studio_id, i.tenant_id, t.name, t.scopes, t.created, t.created_by, t.expire
+        FROM studio_tokens t, identities i
+        WHERE t.studio_id = ?1 AND i.user_id = t.studio_id
+        ORDER BY t.created
+"#;
+
+const SQLITE_DELETE_STUDIO_TOKEN: &str = r#"
+    DELETE FROM studio_tokens WHERE studio_id = ?1 AND token_id = ?2
+"#;
+
+const SQLITE_DELETE_STUDIO_GRANTS: &str = r#"
+    DELETE FROM grants WHERE resource_type = ?1 AND resource_id = ?2
+    RETURNING user_id
+"#;
+
+const SQLITE_REVOKE_STUDIO_OWNERS: &str = r#"
+    DELETE FROM grants WHERE action = ?1 AND resource_type = ?2 AND resource_id = ?3 AND user_id <> ?4
+    RETURNING user_id
+"#;
+
+const SQLITE_UPSERT_STUDIO_TRANSFER: &str = r#"
+    INSERT INTO studio_transfers (studio_id, new_owner_id, requested_by, requested, expire)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+    ON CONFLICT (studio_id) DO UPDATE
+        SET new_owner_id = excluded.new_owner_id, requested_by = excluded.requested_by,
+            requested = excluded.requested, expire = excluded.expire
+"#;
+
+const SQLITE_FIND_STUDIO_TRANSFER: &str = r#"
+    SELECT studio_id, new_owner_id, requested_by, requested, expire FROM studio_transfers WHERE studio_id = ?1
+"#;
+
+const SQLITE_DELETE_STUDIO_TRANSFER: &str = r#"
+    DELETE FROM studio_transfers WHERE studio_id = ?1
+"#;
+
+const SQLITE_COMPLETE_STUDIO_TRANSFER: &str = r#"
+    DELETE FROM studio_transfers WHERE studio_id = ?1 AND new_owner_id = ?2 AND expire > ?3
+"#;
+
+/// An API token acting on behalf of a studio. The token itself is never stored, only its hash.
+#[derive(Debug)]
+pub struct StudioTokenInfo {
+    pub token_id: Uuid,
+    pub studio_id: Uuid,
+    pub tenant_id: String,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    /// The member who created the token.
+    pub created_by: Option<Uuid>,
+    pub expire_at: DateTime<Utc>,
+}
+
+impl StudioTokenInfo {
+    fn from_pg_row(row: &tokio_postgres::Row) -> Self {
+        Self {
+            token_id: row.get(0),
+            studio_id: row.get(1),
+            tenant_id: row.get(2),
+            name: row.get(3),
+            scopes: split_scopes(row.get(4)),
+            created_at: row.get(5),
+            created_by: row.get(6),
+            expire_at: row.get(7),
+        }
+    }
+
+    fn from_sqlite_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            token_id: row.get(0)?,
+            studio_id: row.get(1)?,
+            tenant_id: row.get(2)?,
+            name: row.get(3)?,
+            scopes: split_scopes(&row.get::<_, String>(4)?),
+            created_at: row.get(5)?,
+            created_by: row.get(6)?,
+            expire_at: row.get(7)?,
+        })
+    }
+}
+
+/// A pending transfer of the ownership of a studio, waiting for the acceptance of the new owner.
+#[derive(Debug)]
+pub struct StudioTransferInfo {
+    pub studio_id: Uuid,
+    pub new_owner_id: Uuid,
+    /// The owner who requested the transfer.
+    pub requested_by: Uuid,
+    pub requested_at: DateTime<Utc>,
+    pub expire_at: DateTime<Utc>,
+}
+
+fn split_scopes(scopes: &str) -> Vec<String> {
+    scopes.split_whitespace().map(str::to_owned).collect()
+}
+
+/// The tokens are random with a high entropy, thus a fast hash is sufficient.
+fn hash_token(token: &str) -> String {
+    hex::encode(digest::digest(&digest::SHA256, token.as_bytes()).as_ref())
+}
+
+#[derive(Debug, ThisError)]
+pub enum StudioError {
+    #[error("Name already taken")]
+    NameConflict,
+    #[error("Conflicting user id")]
+    UserIdConflict,
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for StudioError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+impl From<rusqlite::Error> for StudioError {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum StudioBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for StudioBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_insert_identity: InsertStudioIdentity,
+    stmt_insert_grant: InsertStudioGrant,
+    stmt_insert_token: InsertStudioToken,
+    stmt_find_token: FindStudioToken,
+    stmt_list_tokens: ListStudioTokens,
+    stmt_delete_token: DeleteStudioToken,
+    stmt_delete_grants: DeleteStudioGrants,
+    stmt_revoke_owners: RevokeStudioOwners,
+    stmt_upsert_transfer: UpsertStudioTransfer,
+    stmt_find_transfer: FindStudioTransfer,
+    stmt_delete_transfer: DeleteStudioTransfer,
+    stmt_complete_transfer: CompleteStudioTransfer,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    clock: SharedClock,
+}
+
+/// Manage the studio identities and their API tokens. The members of a studio are given by the grants of the
+/// `member` (and `owner`) action on the studio resource, see `PermissionManager`. The studios are deleted as any
+/// other identity by the `DeletionManager`, the tokens are removed by the cascaded delete, and the grants of the
+/// members are removed by `remove_members`. The tokens of a studio marked for deletion are rejected.
+#[derive(Clone)]
+pub struct StudioManager(Arc<Inner>);
+
+impl StudioManager {
+    pub async fn new(pool: &DBPool, clock: SharedClock) -> Result<Self, StudioBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_insert_identity = InsertStudioIdentity::new(&client).await?;
+                let stmt_insert_grant = InsertStudioGrant::new(&client).await?;
+                let stmt_insert_token = InsertStudioToken::new(&client).await?;
+                let stmt_find_token = FindStudioToken::new(&client).await?;
+                let stmt_list_tokens = ListStudioTokens::new(&client).await?;
+                let stmt_delete_token = DeleteStudioToken::new(&client).await?;
+                let stmt_delete_grants = DeleteStudioGrants::new(&client).await?;
+                let stmt_revoke_owners = RevokeStudioOwners::new(&client).await?;
+                let stmt_upsert_transfer = UpsertStudioTransfer::new(&client).await?;
+                let stmt_find_transfer = FindStudioTransfer::new(&client).await?;
+                let stmt_delete_transfer = DeleteStudioTransfer::new(&client).await?;
+                let stmt_complete_transfer = CompleteStudioTransfer::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_insert_identity,
+                    stmt_insert_grant,
+                    stmt_insert_token,
+                    stmt_find_token,
+                    stmt_list_tokens,
+                    stmt_delete_token,
+                    stmt_delete_grants,
+                    stmt_revoke_owners,
+                    stmt_upsert_transfer,
+                    stmt_find_transfer,
+                    stmt_delete_transfer,
+                    stmt_complete_transfer,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(Inner { store, clock })))
+    }
+
+    /// Create a studio identity with its owner atomically. The owner is granted both the owner and the member
+    /// actions.
+    pub async fn create_studio(
+        &self,
+        tenant_id: &str,
+        studio_id: Uuid,
+        name: &str,
+        owner_id: Uuid,
+    ) -> Result<(), StudioError> {
+        let now = self.0.clock.now();
+        let normalized_name = normalize_name(name);
+        let resource_id = studio_id.to_string();
+
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let mut client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_insert_identity = pg.stmt_insert_identity.get(&client).await?;
+                let stmt_insert_grant = pg.stmt_insert_grant.get(&client).await?;
+
+                let transaction = client.transaction().await?;
+                match transaction
+                    .execute(
+                        &stmt_insert_identity,
+                        &[
+                            &studio_id,
+                            &IdentityKind::Studio,
+                            &now,
+                            &name,
+                            &normalized_name,
+                            &tenant_id,
+                        ],
+                    )
+                    .await
+                {
+                    Ok(_) => {}
+                    Err(err) if err.is_constraint("identities", "identities_pkey") => {
+                        return Err(StudioError::UserIdConflict)
+                    }
+                    Err(err) if err.is_constraint("identities", "idx_name") => return Err(StudioError::NameConflict),
+                    Err(err) => return Err(err.into()),
+                }
+                for action in [STUDIO_OWNER, STUDIO_MEMBER] {
+                    transaction
+                        .execute(
+                            &stmt_insert_grant,
+                            &[&owner_id, &action, &STUDIO_RESOURCE, &resource_id, &now],
+                        )
+                        .await?;
+                }
+                transaction.commit().await?;
+            }
+            Store::Sqlite(sqlite) => {
+                let (tenant_id, name) = (tenant_id.to_owned(), name.to_owned());
+                sqlite
+                    .call(move |conn| -> Result<(), StudioError> {
+                        // the transaction is rolled back when it is dropped without a commit
+                        let transaction = conn.transaction()?;
+                        match transaction.execute(
+                            SQLITE_INSERT_STUDIO_IDENTITY,
+                            params![studio_id, IdentityKind::Studio, now, name, normalized_name, tenant_id],
+                        ) {
+                            Ok(_) => {}
+                            Err(err) if err.is_constraint("identities", "user_id") => {
+                                return Err(StudioError::UserIdConflict)
+                            }
+                            Err(err) if err.is_constraint("identities", "normalized_name") => {
+                                return Err(StudioError::NameConflict)
+                            }
+                            Err(err) => return Err(err.into()),
+                        }
+                        for action in [STUDIO_OWNER, STUDIO_MEMBER] {
+                            transaction.execute(
+                                SQLITE_INSERT_STUDIO_GRANT,
+                                params![owner_id, action, STUDIO_RESOURCE, resource_id, now],
+                            )?;
+                        }
+                        transaction.commit()?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Store a new API token of a studio.
+    pub async fn create_token(
+        &self,
+        studio_id: Uuid,
+        token: &str,
+        name: &str,
+        scopes: &[String],
+        created_by: Uuid,
+        expire_at: DateTime<Utc>,
+    ) -> Result<Uuid, DBError> {
+        let now = self.0.clock.now();
+        let token_id = Uuid::new_v4();
+        let token_hash = hash_token(token);
+        let joined_scopes = scopes.join(" ");
+
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_insert_token.get(&client).await?;
+                client
+                    .execute(
+                        &stmt,
+                        &[
+                            &token_id,
+                            &studio_id,
+                            &token_hash,
+                            &name,
+                            &joined_scopes,
+                            &now,
+                            &created_by,
+                            &expire_at,
+                        ],
+                    )
+                    .await?;
+            }
+            Store::Sqlite(sqlite) => {
+                let name = name.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(
+                            SQLITE_INSERT_STUDIO_TOKEN,
+                            params![
+                                token_id,
+                                studio_id,
+                                token_hash,
+                                name,
+                                joined_scopes,
+                                now,
+                                created_by,
+                                expire_at
+                            ],
+                        )?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+
+        Ok(token_id)
+    }
+
+    /// Find a token of a studio, the expired tokens are also returned.
+    pub async fn find_token(&self, token: &str) -> Result<Option<StudioTokenInfo>, DBError> {
+        let token_hash = hash_token(token);
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_find_token.get(&client).await?;
+                Ok(client
+                    .query_opt(&stmt, &[&token_hash])
+                    .await?
+                    .map(|row| StudioTokenInfo::from_pg_row(&row)))
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Option<StudioTokenInfo>, DBError> {
+                        Ok(conn
+                            .query_row(
+                                SQLITE_FIND_STUDIO_TOKEN,
+                                params![token_hash],
+                                StudioTokenInfo::from_sqlite_row,
+                            )
+                            .optional()?)
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Get the tokens of a studio ordered by the creation.
+    pub async fn list_tokens(&self, studio_id: Uuid) -> Result<Vec<StudioTokenInfo>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_list_tokens.get(&client).await?;
+                let rows = client.query(&stmt, &[&studio_id]).await?;
+                Ok(rows.iter().map(StudioTokenInfo::from_pg_row).collect())
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Vec<StudioTokenInfo>, DBError> {
+                        let mut stmt = conn.prepare(SQLITE_LIST_STUDIO_TOKENS)?;
+                        let tokens = stmt
+                            .query_map(params![studio_id], StudioTokenInfo::from_sqlite_row)?
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(tokens)
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Revoke a token of a studio, it is rejected immediately. Returns false if the token was not found.
+    pub async fn delete_token(&self, studio_id: Uuid, token_id: Uuid) -> Result<bool, DBError> {
+        let deleted = match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_delete_token.get(&client).await?;
+                client.execute(&stmt, &[&studio_id, &token_id]).await? as usize
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<usize, DBError> {
+                        Ok(conn.execute(SQLITE_DELETE_STUDIO_TOKEN, params![studio_id, token_id])?)
+                    })
+                    .await?
+            }
+        };
+        Ok(deleted == 1)
+    }
+
+    /// Request the transfer of the ownership to a member of the studio. A pending transfer of the studio is replaced.
+    pub async fn request_transfer(
+        &self,
+        studio_id: Uuid,
+        new_owner_id: Uuid,
+        requested_by: Uuid,
+        expire_at: DateTime<Utc>,
+    ) -> Result<(), DBError> {
+        let now = self.0.clock.now();
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_upsert_transfer.get(&client).await?;
+                client
+                    .execute(&stmt, &[&studio_id, &new_owner_id, &requested_by, &now, &expire_at])
+                    .await?;
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(
+                            SQLITE_UPSERT_STUDIO_TRANSFER,
+                            params![studio_id, new_owner_id, requested_by, now, expire_at],
+                        )?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the pending transfer of a studio, an expired transfer is also returned.
+    pub async fn find_transfer(&self, studio_id: Uuid) -> Result<Option<StudioTransferInfo>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_find_transfer.get(&client).await?;
+                Ok(client
+                    .query_opt(&stmt, &[&studio_id])
+                    .await?
+                    .map(|row| StudioTransferInfo {
+                        studio_id: row.get(0),
+                        new_owner_id: row.get(1),
+                        requested_by: row.get(2),
+                        requested_at: row.get(3),
+                        expire_at: row.get(4),
+                    }))
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Option<StudioTransferInfo>, DBError> {
+                        Ok(conn
+                            .query_row(SQLITE_FIND_STUDIO_TRANSFER, params![studio_id], |row| {
+                                Ok(StudioTransferInfo {
+                                    studio_id: row.get(0)?,
+                                    new_owner_id: row.get(1)?,
+                                    requested_by: row.get(2)?,
+                                    requested_at: row.get(3)?,
+                                    expire_at: row.get(4)?,
+                                })
+                            })
+                            .optional()?)
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Cancel (or decline) the pending transfer of a studio. Returns false if there was no pending transfer.
+    pub async fn cancel_transfer(&self, studio_id: Uuid) -> Result<bool, DBError> {
+        let deleted = match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_delete_transfer.get(&client).await?;
+                client.execute(&stmt, &[&studio_id]).await? as usize
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<usize, DBError> {
+                        Ok(conn.execute(SQLITE_DELETE_STUDIO_TRANSFER, params![studio_id])?)
+                    })
+                    .await?
+            }
+        };
+        Ok(deleted == 1)
+    }
+
+    /// Complete the pending transfer of a studio atomically: the new owner is granted the owner action and it is
+    /// revoked from the previous owners, who remain members of the studio. Returns the previous owners, or None if
+    /// there is no pending (and not expired) transfer to the new owner.
+    pub async fn accept_transfer(&self, studio_id: Uuid, new_owner_id: Uuid) -> Result<Option<Vec<Uuid>>, DBError> {
+        let now = self.0.clock.now();
+        let resource_id = studio_id.to_string();
+
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let mut client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_complete_transfer = pg.stmt_complete_transfer.get(&client).await?;
+                let stmt_revoke_owners = pg.stmt_revoke_owners.get(&client).await?;
+                let stmt_insert_grant = pg.stmt_insert_grant.get(&client).await?;
+
+                let transaction = client.transaction().await?;
+                if transaction
+                    .execute(&stmt_complete_transfer, &[&studio_id, &new_owner_id, &now])
+                    .await?
+                    != 1
+                {
+                    return Ok(None);
+                }
+                let previous_owners = transaction
+                    .query(
+                        &stmt_revoke_owners,
+                        &[&STUDIO_OWNER, &STUDIO_RESOURCE, &resource_id, &new_owner_id],
+                    )
+                    .await?
+                    .iter()
+                    .map(|row| row.get(0))
+                    .collect();
+                for action in [STUDIO_OWNER, STUDIO_MEMBER] {
+                    transaction
+                        .execute(
+                            &stmt_insert_grant,
+                            &[&new_owner_id, &action, &STUDIO_RESOURCE, &resource_id, &now],
+                        )
+                        .await?;
+                }
+                transaction.commit().await?;
+                Ok(Some(previous_owners))
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Option<Vec<Uuid>>, DBError> {
+                        // the transaction is rolled back when it is dropped without a commit
+                        let transaction = conn.transaction()?;
+                        if transaction
+                            .execute(SQLITE_COMPLETE_STUDIO_TRANSFER, params![studio_id, new_owner_id, now])?
+                            != 1
+                        {
+                            return Ok(None);
+                        }
+                        let previous_owners = transaction
+                            .prepare(SQLITE_REVOKE_STUDIO_OWNERS)?
+                            .query_map(
+                                params![STUDIO_OWNER, STUDIO_RESOURCE, resource_id, new_owner_id],
+                                |row| row.get(0),
+                            )?
+                            .collect::<Result<Vec<Uuid>, _>>()?;
+                        for action in [STUDIO_OWNER, STUDIO_MEMBER] {
+                            transaction.execute(
+                                SQLITE_INSERT_STUDIO_GRANT,
+                                params![new_owner_id, action, STUDIO_RESOURCE, resource_id, now],
+                            )?;
+                        }
+                        transaction.commit()?;
+                        Ok(Some(previous_owners))
+                    })
+                    .await
+            }
+        }
+    }
+
+    /// Revoke all the grants on a studio, it is part of the purge of a deleted studio. Returns the former members.
+    pub async fn remove_members(&self, studio_id: Uuid) -> Result<Vec<Uuid>, DBError> {
+        let resource_id = studio_id.to_string();
+        let mut members: Vec<Uuid> = match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_delete_grants.get(&client).await?;
+                let rows = client.query(&stmt, &[&STUDIO_RESOURCE, &resource_id]).await?;
+                rows.iter().map(|row| row.get(0)).collect()
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Vec<Uuid>, DBError> {
+                        let mut stmt = conn.prepare(SQLITE_DELETE_STUDIO_GRANTS)?;
+                        let members = stmt
+                            .query_map(params![STUDIO_RESOURCE, resource_id], |row| row.get(0))?
+                            .collect::<Result<Vec<Uuid>, _>>()?;
+                        Ok(members)
+                    })
+                    .await?
+            }
+        };
+        // the owners have both the owner and the member grants
+        members.sort();
+        members.dedup();
+        Ok(members)
+    }
+}
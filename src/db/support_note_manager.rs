@@ -0,0 +1,171 @@
+use crate::db::{DBError, DBPool, SharedClock, SqlPool, SqlitePool};
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use serde::Serialize;
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+pg_prepared_statement!( InsertNote => r#"
+    INSERT INTO support_notes (note_id, user_id, note, author, created) VALUES ($1, $2, $3, $4, $5)
+"#, [UUID, UUID, TEXT, UUID, TIMESTAMPTZ] );
+
+pg_prepared_statement!( ListNotes => r#"
+    SELECT note_id, user_id, note, author, created FROM support_notes WHERE user_id = $1 ORDER BY created DESC
+"#, [UUID] );
+
+const SQLITE_INSERT_NOTE: &str = r#"
+    INSERT INTO support_notes (note_id, user_id, note, author, created) VALUES (?1, ?2, ?3, ?4, ?5)
+"#;
+
+const SQLITE_LIST_NOTES: &str = r#"
+    SELECT note_id, user_id, note, author, created FROM support_notes WHERE user_id = ?1 ORDER BY created DESC
+"#;
+
+/// A note of the support staff on a user, ex. the context of a ticket.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupportNote {
+    pub note_id: Uuid,
+    pub user_id: Uuid,
+    pub note: String,
+    pub author: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl SupportNote {
+    fn from_row(row: &Row) -> Self {
+        Self {
+            note_id: row.get(0),
+            user_id: row.get(1),
+            note: row.get(2),
+            author: row.get(3),
+            created_at: row.get(4),
+        }
+    }
+
+    fn from_sqlite_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            note_id: row.get(0)?,
+            user_id: row.get(1)?,
+            note: row.get(2)?,
+            author: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub enum SupportNoteBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for SupportNoteBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_insert_note: InsertNote,
+    stmt_list_notes: ListNotes,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    clock: SharedClock,
+}
+
+/// Store of the support history of the identities. The notes are append-only, they are kept until the identity is
+/// deleted.
+#[derive(Clone)]
+pub struct SupportNoteManager(Arc<Inner>);
+
+impl SupportNoteManager {
+    pub async fn new(pool: &DBPool, clock: SharedClock) -> Result<Self, SupportNoteBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_insert_note = InsertNote::new(&client).await?;
+                let stmt_list_notes = ListNotes::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_insert_note,
+                    stmt_list_notes,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(Inner { store, clock })))
+    }
+
+    pub async fn add_note(&self, user_id: Uuid, note: &str, author: Option<Uuid>) -> Result<SupportNote, DBError> {
+        let note = SupportNote {
+            note_id: Uuid::new_v4(),
+            user_id,
+            note: note.to_owned(),
+            author,
+            created_at: self.0.clock.now(),
+        };
+
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_insert_note.get(&client).await?;
+                client
+                    .execute(
+                        &stmt,
+                        &[&note.note_id, &note.user_id, &note.note, &note.author, &note.created_at],
+                    )
+                    .await?;
+            }
+            Store::Sqlite(sqlite) => {
+                let row = note.clone();
+                sqlite
+                    .call(move |conn| -> Result<(), DBError> {
+                        conn.execute(
+                            SQLITE_INSERT_NOTE,
+                            params![row.note_id, row.user_id, row.note, row.author, row.created_at],
+                        )?;
+                        Ok(())
+                    })
+                    .await?;
+            }
+        }
+
+        Ok(note)
+    }
+
+    /// List the notes of a user, the latest first.
+    pub async fn list_notes(&self, user_id: Uuid) -> Result<Vec<SupportNote>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_list_notes.get(&client).await?;
+                let rows = client.query(&stmt, &[&user_id]).await?;
+                Ok(rows.iter().map(SupportNote::from_row).collect())
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Vec<SupportNote>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_LIST_NOTES)?;
+                        let notes = stmt
+                            .query_map(params![user_id], SupportNote::from_sqlite_row)?
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(notes)
+                    })
+                    .await
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::db::{DBError, DBPool, SharedClock, SqlPool, SqlitePool};
+use rusqlite::params;
+use shine_service::{pg_prepared_statement, service::PGConnectionPool};
+use std::sync::Arc;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+pg_prepared_statement!( InsertTag => r#"
+    INSERT INTO identity_tags (user_id, tag, created) VALUES ($1, $2, $3)
+    ON CONFLICT (user_id, tag) DO NOTHING
+"#, [UUID, VARCHAR, TIMESTAMPTZ] );
+
+pg_prepared_statement!( DeleteTag => r#"
+    DELETE FROM identity_tags WHERE user_id = $1 AND tag = $2
+"#, [UUID, VARCHAR] );
+
+pg_prepared_statement!( ListTags => r#"
+    SELECT tag FROM identity_tags WHERE user_id = $1 ORDER BY tag
+"#, [UUID] );
+
+const SQLITE_INSERT_TAG: &str = r#"
+    INSERT INTO identity_tags (user_id, tag, created) VALUES (?1, ?2, ?3)
+    ON CONFLICT (user_id, tag) DO NOTHING
+"#;
+
+const SQLITE_DELETE_TAG: &str = r#"
+    DELETE FROM identity_tags WHERE user_id = ?1 AND tag = ?2
+"#;
+
+const SQLITE_LIST_TAGS: &str = r#"
+    SELECT tag FROM identity_tags WHERE user_id = ?1 ORDER BY tag
+"#;
+
+#[derive(Debug, ThisError)]
+pub enum TagManagerBuildError {
+    #[error(transparent)]
+    DBError(#[from] DBError),
+}
+
+impl From<tokio_postgres::Error> for TagManagerBuildError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::DBError(err.into())
+    }
+}
+
+struct PgStore {
+    postgres: PGConnectionPool,
+    stmt_insert_tag: InsertTag,
+    stmt_delete_tag: DeleteTag,
+    stmt_list_tags: ListTags,
+}
+
+enum Store {
+    Postgres(PgStore),
+    Sqlite(SqlitePool),
+}
+
+struct Inner {
+    store: Store,
+    clock: SharedClock,
+}
+
+/// Store of the tags of the identities (ex. `beta-tester`, `press`) to manage the cohorts. The identities can be
+/// searched by the tags, see `SearchIdentity::tags`.
+#[derive(Clone)]
+pub struct TagManager(Arc<Inner>);
+
+impl TagManager {
+    pub async fn new(pool: &DBPool, clock: SharedClock) -> Result<Self, TagManagerBuildError> {
+        let store = match &pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt_insert_tag = InsertTag::new(&client).await?;
+                let stmt_delete_tag = DeleteTag::new(&client).await?;
+                let stmt_list_tags = ListTags::new(&client).await?;
+                Store::Postgres(PgStore {
+                    postgres: postgres.clone(),
+                    stmt_insert_tag,
+                    stmt_delete_tag,
+                    stmt_list_tags,
+                })
+            }
+            SqlPool::Sqlite(sqlite) => Store::Sqlite(sqlite.clone()),
+        };
+
+        Ok(Self(Arc::new(Inner { store, clock })))
+    }
+
+    /// Tag a user, false is returned if the user already had the tag.
+    pub async fn add_tag(&self, user_id: Uuid, tag: &str) -> Result<bool, DBError> {
+        let now = self.0.clock.now();
+        let count = match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_insert_tag.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &tag, &now]).await? as usize
+            }
+            Store::Sqlite(sqlite) => {
+                let tag = tag.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<usize, DBError> {
+                        Ok(conn.execute(SQLITE_INSERT_TAG, params![user_id, tag, now])?)
+                    })
+                    .await?
+            }
+        };
+        Ok(count > 0)
+    }
+
+    /// Remove a tag of a user, false is returned if the user had no such tag.
+    pub async fn remove_tag(&self, user_id: Uuid, tag: &str) -> Result<bool, DBError> {
+        let count = match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_delete_tag.get(&client).await?;
+                client.execute(&stmt, &[&user_id, &tag]).await? as usize
+            }
+            Store::Sqlite(sqlite) => {
+                let tag = tag.to_owned();
+                sqlite
+                    .call(move |conn| -> Result<usize, DBError> {
+                        Ok(conn.execute(SQLITE_DELETE_TAG, params![user_id, tag])?)
+                    })
+                    .await?
+            }
+        };
+        Ok(count > 0)
+    }
+
+    pub async fn list_tags(&self, user_id: Uuid) -> Result<Vec<String>, DBError> {
+        match &self.0.store {
+            Store::Postgres(pg) => {
+                let client = pg.postgres.get().await.map_err(DBError::PostgresPoolError)?;
+                let stmt = pg.stmt_list_tags.get(&client).await?;
+                let rows = client.query(&stmt, &[&user_id]).await?;
+                Ok(rows.iter().map(|row| row.get(0)).collect())
+            }
+            Store::Sqlite(sqlite) => {
+                sqlite
+                    .call(move |conn| -> Result<Vec<String>, DBError> {
+                        let mut stmt = conn.prepare_cached(SQLITE_LIST_TAGS)?;
+                        let tags = stmt
+                            .query_map(params![user_id], |row| row.get(0))?
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(tags)
+                    })
+                    .await
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use crate::db::{DBError, DBPool};
+use chrono::Duration;
+use shine_service::service::RedisConnectionPool;
+use uuid::Uuid;
+
+/// The redeemed single-use tickets shared by all the instances of the service. A ticket is kept until it would
+/// expire, thus it cannot be redeemed again.
+#[derive(Clone)]
+pub struct TicketRedemption {
+    redis: RedisConnectionPool,
+    key_prefix: String,
+}
+
+impl TicketRedemption {
+    pub fn new(pool: &DBPool) -> Self {
+        Self {
+            redis: pool.redis.clone(),
+            key_prefix: pool.redis_key_prefix.clone(),
+        }
+    }
+
+    /// Mark a ticket as redeemed, false is returned if it has been redeemed already. The duration shall be at
+    /// least the remaining lifetime of the ticket.
+    pub async fn redeem(&self, ticket_id: Uuid, duration: Duration) -> Result<bool, DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+        let result: Option<String> = redis::cmd("SET")
+            .arg(format!("{}redeemed-ticket:{}", self.key_prefix, ticket_id.as_simple()))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(duration.num_seconds().max(1))
+            .query_async(&mut *client)
+            .await
+            .map_err(DBError::RedisError)?;
+        Ok(result.is_some())
+    }
+}
@@ -0,0 +1,170 @@
+use crate::db::{DBError, DBPool};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use lru::LruCache;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use shine_service::service::RedisConnectionPool;
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    time::{Duration as StdDuration, Instant},
+};
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenRevocationConfig {
+    /// Number of tokens and users kept in the local cache.
+    #[serde(default = "TokenRevocationConfig::default_cache_size")]
+    pub cache_size: usize,
+    /// Time in seconds a lookup is cached. A revocation made by another instance of the service
+    /// becomes visible after this time.
+    #[serde(default = "TokenRevocationConfig::default_cache_ttl")]
+    pub cache_ttl: u64,
+}
+
+impl TokenRevocationConfig {
+    fn default_cache_size() -> usize {
+        10000
+    }
+
+    fn default_cache_ttl() -> u64 {
+        5
+    }
+}
+
+impl Default for TokenRevocationConfig {
+    fn default() -> Self {
+        Self {
+            cache_size: Self::default_cache_size(),
+            cache_ttl: Self::default_cache_ttl(),
+        }
+    }
+}
+
+struct Cache {
+    /// Revoked state of the tokens.
+    tokens: LruCache<Uuid, (bool, Instant)>,
+    /// The time of the last "revoke all" of the users.
+    users: LruCache<Uuid, (Option<DateTime<Utc>>, Instant)>,
+}
+
+struct Inner {
+    redis: RedisConnectionPool,
+    key_prefix: String,
+    cache: Mutex<Cache>,
+    cache_ttl: StdDuration,
+}
+
+/// Revocation list of the tokens shared by all the instances of the service. Tokens can be revoked one by one
+/// or all the tokens issued for a user before a given time. The revocations are stored in redis until the revoked
+/// tokens expire, and the lookups are cached locally.
+#[derive(Clone)]
+pub struct TokenRevocation(Arc<Inner>);
+
+impl TokenRevocation {
+    pub fn new(pool: &DBPool, config: &TokenRevocationConfig) -> Self {
+        let cache_size = NonZeroUsize::new(config.cache_size.max(1)).unwrap();
+        Self(Arc::new(Inner {
+            redis: pool.redis.clone(),
+            key_prefix: pool.redis_key_prefix.clone(),
+            cache: Mutex::new(Cache {
+                tokens: LruCache::new(cache_size),
+                users: LruCache::new(cache_size),
+            }),
+            cache_ttl: StdDuration::from_secs(config.cache_ttl),
+        }))
+    }
+
+    fn token_key(&self, token_id: Uuid) -> String {
+        format!("{}revoked-token:{}", self.0.key_prefix, token_id.as_simple())
+    }
+
+    fn user_key(&self, user_id: Uuid) -> String {
+        format!("{}revoked-user:{}", self.0.key_prefix, user_id.as_simple())
+    }
+
+    /// Revoke a single token. The duration shall be at least the remaining lifetime of the token.
+    pub async fn revoke_token(&self, token_id: Uuid, duration: Duration) -> Result<(), DBError> {
+        let inner = &*self.0;
+        let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let _: () = client
+            .set_ex(self.token_key(token_id), 1, duration.num_seconds().max(1) as usize)
+            .await
+            .map_err(DBError::RedisError)?;
+
+        let mut cache = inner.cache.lock().unwrap();
+        cache.tokens.put(token_id, (true, Instant::now()));
+        Ok(())
+    }
+
+    /// Revoke all the tokens of a user issued up to now. The duration shall be at least the maximum
+    /// lifetime of the tokens.
+    pub async fn revoke_user(&self, user_id: Uuid, duration: Duration) -> Result<(), DBError> {
+        let inner = &*self.0;
+        let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let now = Utc::now();
+        let _: () = client
+            .set_ex(
+                self.user_key(user_id),
+                now.timestamp_millis(),
+                duration.num_seconds().max(1) as usize,
+            )
+            .await
+            .map_err(DBError::RedisError)?;
+
+        let mut cache = inner.cache.lock().unwrap();
+        cache.users.put(user_id, (Some(now), Instant::now()));
+        Ok(())
+    }
+
+    /// Check if a token issued at the given time has been revoked.
+    pub async fn is_revoked(&self, token_id: Uuid, user_id: Uuid, issued_at: DateTime<Utc>) -> Result<bool, DBError> {
+        let inner = &*self.0;
+
+        let (token_revoked, user_revoked_at) = {
+            let mut cache = inner.cache.lock().unwrap();
+            let now = Instant::now();
+            // a revoked token remains revoked, only the valid state has to be refreshed
+            let token_revoked = cache
+                .tokens
+                .get(&token_id)
+                .filter(|(revoked, checked)| *revoked || now.duration_since(*checked) < inner.cache_ttl)
+                .map(|(revoked, _)| *revoked);
+            let user_revoked_at = cache
+                .users
+                .get(&user_id)
+                .filter(|(_, checked)| now.duration_since(*checked) < inner.cache_ttl)
+                .map(|(revoked_at, _)| *revoked_at);
+            (token_revoked, user_revoked_at)
+        };
+
+        let (token_revoked, user_revoked_at) = match (token_revoked, user_revoked_at) {
+            (Some(token_revoked), Some(user_revoked_at)) => (token_revoked, user_revoked_at),
+            _ => {
+                let mut client = inner.redis.get().await.map_err(DBError::RedisPoolError)?;
+                let (token_revoked, user_revoked_at): (Option<i64>, Option<i64>) = redis::pipe()
+                    .get(self.token_key(token_id))
+                    .get(self.user_key(user_id))
+                    .query_async(&mut *client)
+                    .await
+                    .map_err(DBError::RedisError)?;
+                let token_revoked = token_revoked.is_some();
+                let user_revoked_at = user_revoked_at.and_then(|millis| Utc.timestamp_millis_opt(millis).single());
+
+                let mut cache = inner.cache.lock().unwrap();
+                let now = Instant::now();
+                cache.tokens.put(token_id, (token_revoked, now));
+                cache.users.put(user_id, (user_revoked_at, now));
+                (token_revoked, user_revoked_at)
+            }
+        };
+
+        Ok(token_revoked
+            || user_revoked_at
+                .map(|revoked_at| issued_at <= revoked_at)
+                .unwrap_or(false))
+    }
+}
@@ -0,0 +1,110 @@
+use crate::db::{DBError, DBPool};
+use redis::{AsyncCommands, Script};
+use shine_service::service::RedisConnectionPool;
+use uuid::Uuid;
+
+/// The redis channel the invalidations are published on.
+pub const USER_INVALIDATION_CHANNEL: &str = "user-invalidation";
+
+/// The reason of an invalidation.
+#[derive(Debug, Clone, Copy)]
+pub enum UserChange {
+    Name,
+    Roles,
+    Restrictions,
+    Deleted,
+    Anonymized,
+    /// The time-limited account has expired.
+    Expired,
+    /// The user was merged into another user, the message also has a `mergedInto` field with the id of the other
+    /// user.
+    Merged,
+    /// The age was declared or the adult age was verified.
+    Age,
+    /// The studio memberships of the user have changed, ex. the ownership of a studio was transferred or a studio
+    /// was deleted.
+    Studios,
+}
+
+impl UserChange {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserChange::Name => "name",
+            UserChange::Roles => "roles",
+            UserChange::Restrictions => "restrictions",
+            UserChange::Deleted => "deleted",
+            UserChange::Anonymized => "anonymized",
+            UserChange::Expired => "expired",
+            UserChange::Merged => "merged",
+            UserChange::Age => "age",
+            UserChange::Studios => "studios",
+        }
+    }
+}
+
+/// Notify the other services about the change of a user, thus the cached copies of the user can be dropped
+/// immediately. Each change increments the version of the user stored in redis and the new version is published
+/// on the `user-invalidation` channel as a `{"userId", "version", "change"}` json.
+#[derive(Clone)]
+pub struct UserInvalidation {
+    redis: RedisConnectionPool,
+    key_prefix: String,
+}
+
+impl UserInvalidation {
+    pub fn new(pool: &DBPool) -> Self {
+        Self {
+            redis: pool.redis.clone(),
+            key_prefix: pool.redis_key_prefix.clone(),
+        }
+    }
+
+    fn version_key(&self, user_id: Uuid) -> String {
+        format!("{}user-version:{}", self.key_prefix, user_id.as_simple())
+    }
+
+    /// Get the current version of a user.
+    pub async fn version(&self, user_id: Uuid) -> Result<u64, DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+        let version: Option<u64> = client
+            .get(self.version_key(user_id))
+            .await
+            .map_err(DBError::RedisError)?;
+        Ok(version.unwrap_or(0))
+    }
+
+    /// Increment the version of a user and publish the invalidation. The new version is returned.
+    pub async fn invalidate(&self, user_id: Uuid, change: UserChange) -> Result<u64, DBError> {
+        self.publish(user_id, change, None).await
+    }
+
+    /// Publish the merge of a user into another user, thus the other services can remap the ownership of their data.
+    pub async fn invalidate_merged(&self, user_id: Uuid, merged_into: Uuid) -> Result<u64, DBError> {
+        self.publish(user_id, UserChange::Merged, Some(merged_into)).await
+    }
+
+    async fn publish(&self, user_id: Uuid, change: UserChange, merged_into: Option<Uuid>) -> Result<u64, DBError> {
+        let mut client = self.redis.get().await.map_err(DBError::RedisPoolError)?;
+
+        let lua_script = r#"
+local version = redis.call('INCR', KEYS[1])
+local message = { userId = ARGV[2], version = version, change = ARGV[3] }
+if ARGV[4] ~= '' then
+    message.mergedInto = ARGV[4]
+end
+redis.call('PUBLISH', ARGV[1], cjson.encode(message))
+return version
+"#;
+
+        let version: u64 = Script::new(lua_script)
+            .key(self.version_key(user_id))
+            .arg(USER_INVALIDATION_CHANNEL)
+            .arg(user_id.to_string())
+            .arg(change.as_str())
+            .arg(merged_into.map(|id| id.to_string()).unwrap_or_default())
+            .invoke_async(&mut *client)
+            .await
+            .map_err(DBError::RedisError)?;
+        Ok(version)
+    }
+}
@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(tag = "type")]
+pub enum EmailTransportConfig {
+    /// Send emails through an SMTP relay, the connection is always encrypted (TLS or STARTTLS).
+    #[serde(rename_all = "camelCase")]
+    Smtp {
+        host: String,
+        port: Option<u16>,
+        #[serde(default)]
+        starttls: bool,
+        user: Option<String>,
+        password: Option<String>,
+    },
+
+    /// Send emails using the Amazon SES API. Credentials are taken from the standard AWS environment.
+    #[serde(rename_all = "camelCase")]
+    Ses { region: Option<String> },
+
+    /// Log the emails instead of sending them, for development only.
+    Log,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailConfig {
+    /// The sender mailbox, ex. `Scytta <no-reply@scytta.com>`
+    pub from: String,
+    pub transport: EmailTransportConfig,
+
+    /// The maximum number of the emails waiting for delivery.
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize,
+    /// The number of delivery attempts of an email.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry in seconds, it is doubled for each further attempt.
+    #[serde(default = "default_retry_delay")]
+    pub retry_delay: u64,
+}
+
+fn default_queue_size() -> usize {
+    1000
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+fn default_retry_delay() -> u64 {
+    10
+}
+
+/// Configuration of an email notification sent by the services.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailNotificationConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Override the default template of the notification.
+    pub template: Option<String>,
+}
+
+impl EmailNotificationConfig {
+    pub fn template<'a>(&'a self, default_template: &'a str) -> &'a str {
+        self.template.as_deref().unwrap_or(default_template)
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{
+    email::{EmailConfig, EmailTransportConfig, LogTransport, SesTransport, SmtpTransport},
+    logging::RedactedEmail,
+};
+use async_trait::async_trait;
+use lettre::{message::Mailbox, message::MultiPart, Message};
+use std::{sync::Arc, time::Duration};
+use tera::{Context, Tera};
+use thiserror::Error as ThisError;
+use tokio::sync::mpsc;
+
+#[derive(Debug, ThisError)]
+pub enum EmailError {
+    #[error("Invalid mailbox: {0}")]
+    InvalidMailbox(String),
+    #[error("Failed to render email template")]
+    Template(#[from] tera::Error),
+    #[error("Failed to compose email: {0}")]
+    Message(String),
+    #[error("Failed to send email: {0}")]
+    Transport(String),
+    #[error("Email queue is full")]
+    QueueFull,
+}
+
+/// Trait to deliver a composed email.
+#[async_trait]
+pub trait EmailTransport: 'static + Send + Sync {
+    async fn send(&self, message: &Message) -> Result<(), EmailError>;
+}
+
+struct QueuedEmail {
+    message: Message,
+    attempt: u32,
+}
+
+struct Inner {
+    tera: Tera,
+    from: Mailbox,
+    queue: mpsc::Sender<QueuedEmail>,
+}
+
+/// Compose emails from templates and deliver them in the background with retry.
+#[derive(Clone)]
+pub struct EmailSender(Arc<Inner>);
+
+impl EmailSender {
+    pub async fn new(config: &EmailConfig, tera: Tera) -> Result<Self, EmailError> {
+        let transport: Arc<dyn EmailTransport> = match &config.transport {
+            EmailTransportConfig::Smtp {
+                host,
+                port,
+                starttls,
+                user,
+                password,
+            } => Arc::new(SmtpTransport::new(
+                host,
+                *port,
+                *starttls,
+                user.as_deref(),
+                password.as_deref(),
+            )?),
+            EmailTransportConfig::Ses { region } => Arc::new(SesTransport::new(region.as_deref()).await),
+            EmailTransportConfig::Log => Arc::new(LogTransport),
+        };
+
+        Self::with_transport(config, tera, transport)
+    }
+
+    /// Create the sender with a custom transport, the transport of the configuration is ignored.
+    pub fn with_transport(
+        config: &EmailConfig,
+        tera: Tera,
+        transport: Arc<dyn EmailTransport>,
+    ) -> Result<Self, EmailError> {
+        let from = config
+            .from
+            .parse::<Mailbox>()
+            .map_err(|err| EmailError::InvalidMailbox(format!("{err}")))?;
+
+        let (queue, receiver) = mpsc::channel(config.queue_size);
+        tokio::spawn(deliver(
+            transport,
+            queue.clone(),
+            receiver,
+            config.max_attempts,
+            Duration::from_secs(config.retry_delay),
+        ));
+
+        Ok(Self(Arc::new(Inner { tera, from, queue })))
+    }
+
+    /// Compose an email from the `email/{template}.subject.txt`, `email/{template}.txt` and the optional
+    /// `email/{template}.html` templates and queue it for delivery.
+    pub fn send(&self, to: &str, template: &str, context: &Context) -> Result<(), EmailError> {
+        let inner = &*self.0;
+
+        let to = to
+            .parse::<Mailbox>()
+            .map_err(|err| EmailError::InvalidMailbox(format!("{err}")))?;
+        let subject = inner.tera.render(&format!("email/{template}.subject.txt"), context)?;
+        let text = inner.tera.render(&format!("email/{template}.txt"), context)?;
+        let html_template = format!("email/{template}.html");
+        let html = if inner.tera.get_template_names().any(|name| name == html_template) {
+            Some(inner.tera.render(&html_template, context)?)
+        } else {
+            None
+        };
+
+        let builder = Message::builder()
+            .from(inner.from.clone())
+            .to(to)
+            .subject(subject.trim());
+        let message = match html {
+            Some(html) => builder.multipart(MultiPart::alternative_plain_html(text, html)),
+            None => builder.body(text),
+        }
+        .map_err(|err| EmailError::Message(format!("{err}")))?;
+
+        inner
+            .queue
+            .try_send(QueuedEmail { message, attempt: 0 })
+            .map_err(|_| EmailError::QueueFull)
+    }
+}
+
+/// Deliver the queued emails. Failed emails are queued again after an exponential backoff.
+async fn deliver(
+    transport: Arc<dyn EmailTransport>,
+    queue: mpsc::Sender<QueuedEmail>,
+    mut receiver: mpsc::Receiver<QueuedEmail>,
+    max_attempts: u32,
+    retry_delay: Duration,
+) {
+    while let Some(mut email) = receiver.recv().await {
+        email.attempt += 1;
+        let recipients = email
+            .message
+            .envelope()
+            .to()
+            .iter()
+            .map(|address| RedactedEmail(address.as_ref()).to_string())
+            .collect::<Vec<_>>();
+        match transport.send(&email.message).await {
+            Ok(()) => log::debug!("Email sent to {:?}", recipients),
+            Err(err) if email.attempt < max_attempts => {
+                let delay = retry_delay * 2u32.saturating_pow(email.attempt - 1);
+                log::warn!(
+                    "Failed to send email to {:?} (attempt {}), retry in {delay:?}: {err}",
+                    recipients,
+                    email.attempt
+                );
+                let queue = queue.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    if queue.send(email).await.is_err() {
+                        log::error!("Email queue is closed, email dropped");
+                    }
+                });
+            }
+            Err(err) => log::error!(
+                "Failed to send email to {:?}, giving up after {} attempts: {err}",
+                recipients,
+                email.attempt
+            ),
+        }
+    }
+}
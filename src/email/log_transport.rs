@@ -0,0 +1,18 @@
+use crate::email::{EmailError, EmailTransport};
+use async_trait::async_trait;
+use lettre::Message;
+
+/// Transport logging the emails instead of sending them.
+pub struct LogTransport;
+
+#[async_trait]
+impl EmailTransport for LogTransport {
+    async fn send(&self, message: &Message) -> Result<(), EmailError> {
+        log::info!(
+            "Email to {:?}:\n{}",
+            message.envelope().to(),
+            String::from_utf8_lossy(&message.formatted())
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,11 @@
+mod email_config;
+pub use self::email_config::*;
+mod email_sender;
+pub use self::email_sender::*;
+
+mod log_transport;
+pub use self::log_transport::*;
+mod ses_transport;
+pub use self::ses_transport::*;
+mod smtp_transport;
+pub use self::smtp_transport::*;
@@ -0,0 +1,41 @@
+use crate::email::{EmailError, EmailTransport};
+use async_trait::async_trait;
+use aws_sdk_sesv2::{
+    primitives::Blob,
+    types::{EmailContent, RawMessage},
+    Client,
+};
+use lettre::Message;
+
+pub struct SesTransport {
+    client: Client,
+}
+
+impl SesTransport {
+    pub async fn new(region: Option<&str>) -> Self {
+        let mut loader = aws_config::from_env();
+        if let Some(region) = region {
+            loader = loader.region(aws_sdk_sesv2::config::Region::new(region.to_owned()));
+        }
+        let config = loader.load().await;
+
+        Self {
+            client: Client::new(&config),
+        }
+    }
+}
+
+#[async_trait]
+impl EmailTransport for SesTransport {
+    async fn send(&self, message: &Message) -> Result<(), EmailError> {
+        // the message is composed by lettre and sent as a raw MIME message to have the same content for all the transports
+        let raw = RawMessage::builder().data(Blob::new(message.formatted())).build();
+        self.client
+            .send_email()
+            .content(EmailContent::builder().raw(raw).build())
+            .send()
+            .await
+            .map_err(|err| EmailError::Transport(format!("{err}")))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use crate::email::{EmailError, EmailTransport};
+use async_trait::async_trait;
+use lettre::{
+    transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+pub struct SmtpTransport {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn new(
+        host: &str,
+        port: Option<u16>,
+        starttls: bool,
+        user: Option<&str>,
+        password: Option<&str>,
+    ) -> Result<Self, EmailError> {
+        let mut builder = if starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+        }
+        .map_err(|err| EmailError::Transport(format!("{err}")))?;
+
+        if let Some(port) = port {
+            builder = builder.port(port);
+        }
+        if let Some(user) = user {
+            builder = builder.credentials(Credentials::new(
+                user.to_owned(),
+                password.unwrap_or_default().to_owned(),
+            ));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+}
+
+#[async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, message: &Message) -> Result<(), EmailError> {
+        self.transport
+            .send(message.clone())
+            .await
+            .map_err(|err| EmailError::Transport(format!("{err}")))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{
+    error_reporting::{ErrorReporterConfig, LogReporter, SentryReporter},
+    logging::{current_request_id, RedactedEmail, RedactedToken},
+};
+use async_trait::async_trait;
+use axum::{
+    extract::{MatchedPath, State},
+    http::Request,
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use regex::{Captures, Regex};
+use std::{collections::BTreeMap, sync::Arc};
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+#[derive(Debug, ThisError)]
+pub enum ErrorReportingError {
+    #[error("Invalid DSN: {0}")]
+    InvalidDsn(String),
+    #[error("Failed to send error report: {0}")]
+    Transport(String),
+}
+
+/// An unexpected error with a context free of secrets and personal data.
+#[derive(Debug)]
+pub struct ErrorReport {
+    pub event_id: Uuid,
+    pub time: DateTime<Utc>,
+    /// The part of the service reporting the error, ex. `auth.page`, `http`, `db.purge`.
+    pub source: &'static str,
+    pub message: String,
+    /// The correlation id of the request, if the error happened during a request.
+    pub request_id: Option<String>,
+    pub context: BTreeMap<String, String>,
+}
+
+/// Trait to deliver an error report to an error tracking service.
+#[async_trait]
+pub trait ErrorReporter: 'static + Send + Sync {
+    async fn report(&self, report: &ErrorReport) -> Result<(), ErrorReportingError>;
+}
+
+struct Inner {
+    reporter: Arc<dyn ErrorReporter>,
+    email_pattern: Regex,
+    token_pattern: Regex,
+}
+
+/// Report the unexpected errors in the background. The messages and the context are scrubbed before the reporting:
+/// the emails and the token-like values (tokens, codes, ids) are masked.
+#[derive(Clone)]
+pub struct ErrorReporting(Arc<Inner>);
+
+impl ErrorReporting {
+    pub fn new(config: Option<&ErrorReporterConfig>, release: &str) -> Result<Self, ErrorReportingError> {
+        let reporter: Arc<dyn ErrorReporter> = match config {
+            Some(ErrorReporterConfig::Sentry { dsn, environment }) => {
+                Arc::new(SentryReporter::new(dsn, environment.as_deref(), release)?)
+            }
+            Some(ErrorReporterConfig::Log) | None => Arc::new(LogReporter),
+        };
+
+        Ok(Self(Arc::new(Inner {
+            reporter,
+            email_pattern: Regex::new(r"[^\s@'\x22()<>,;:]+@[^\s@'\x22()<>,;:]+\.[A-Za-z]{2,}").unwrap(),
+            token_pattern: Regex::new(r"[A-Za-z0-9_\-]{20,}").unwrap(),
+        })))
+    }
+
+    /// Mask the emails and the token-like values of a text.
+    pub fn scrub(&self, text: &str) -> String {
+        let text = self
+            .0
+            .email_pattern
+            .replace_all(text, |captures: &Captures| RedactedEmail(&captures[0]).to_string());
+        self.0
+            .token_pattern
+            .replace_all(&text, |captures: &Captures| RedactedToken(&captures[0]).to_string())
+            .into_owned()
+    }
+
+    /// Report an error in the background, a failure of the reporting is only logged.
+    pub fn report(&self, source: &'static str, message: &str, context: &[(&str, &str)]) {
+        let report = ErrorReport {
+            event_id: Uuid::new_v4(),
+            time: Utc::now(),
+            source,
+            message: self.scrub(message),
+            request_id: current_request_id(),
+            context: context
+                .iter()
+                .map(|(key, value)| ((*key).to_owned(), self.scrub(value)))
+                .collect(),
+        };
+
+        let reporter = self.0.reporter.clone();
+        tokio::spawn(async move {
+            if let Err(err) = reporter.report(&report).await {
+                log::warn!("Failed to report the error {}: {err}", report.event_id);
+            }
+        });
+    }
+}
+
+/// Middleware reporting the server errors of the routes, ex. the failures of the database in the API.
+pub async fn report_server_errors<B>(
+    State(error_reporting): State<ErrorReporting>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let method = request.method().clone();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned());
+
+    let response = next.run(request).await;
+
+    let status = response.status();
+    if status.is_server_error() {
+        error_reporting.report(
+            "http",
+            &format!("{method} {} failed with {status}", route.as_deref().unwrap_or("-")),
+            &[("method", method.as_str()), ("status", status.as_str())],
+        );
+    }
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::ErrorReporting;
+
+    #[test]
+    fn reports_are_scrubbed() {
+        let error_reporting = ErrorReporting::new(None, "test").unwrap();
+        assert_eq!(
+            error_reporting.scrub("Key (email)=(jane.doe@example.com) already exists"),
+            "Key (email)=(j***@example.com) already exists"
+        );
+        assert_eq!(
+            error_reporting.scrub("Invalid token AbCdEfGhIjKlMnOpQrStUvWxYz"),
+            "Invalid token AbCd***(26)"
+        );
+    }
+}
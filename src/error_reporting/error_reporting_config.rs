@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(tag = "type")]
+pub enum ErrorReporterConfig {
+    /// Send the errors to Sentry (or a service compatible with the Sentry store API).
+    #[serde(rename_all = "camelCase")]
+    Sentry {
+        /// The DSN of the project, ex. `https://<key>@o0.ingest.sentry.io/<project>`
+        dsn: String,
+        environment: Option<String>,
+    },
+
+    /// Log the errors only, it is the default without a configuration.
+    Log,
+}
@@ -0,0 +1,20 @@
+use crate::error_reporting::{ErrorReport, ErrorReporter, ErrorReportingError};
+use async_trait::async_trait;
+
+/// Reporter logging the errors instead of sending them to a service.
+pub struct LogReporter;
+
+#[async_trait]
+impl ErrorReporter for LogReporter {
+    async fn report(&self, report: &ErrorReport) -> Result<(), ErrorReportingError> {
+        log::error!(
+            "Error in {} (event: {}, request: {}): {} {:?}",
+            report.source,
+            report.event_id,
+            report.request_id.as_deref().unwrap_or("-"),
+            report.message,
+            report.context
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,9 @@
+mod error_reporting_config;
+pub use self::error_reporting_config::*;
+mod error_reporter;
+pub use self::error_reporter::*;
+
+mod log_reporter;
+pub use self::log_reporter::*;
+mod sentry_reporter;
+pub use self::sentry_reporter::*;
@@ -0,0 +1,84 @@
+use crate::{
+    app_config::SERVICE_NAME,
+    error_reporting::{ErrorReport, ErrorReporter, ErrorReportingError},
+};
+use async_trait::async_trait;
+use serde_json::json;
+use url::Url;
+
+/// Reporter sending the errors to the store API of Sentry, see https://develop.sentry.dev/sdk/store/.
+pub struct SentryReporter {
+    client: reqwest::Client,
+    store_url: Url,
+    auth_header: String,
+    environment: Option<String>,
+    release: String,
+}
+
+impl SentryReporter {
+    pub fn new(dsn: &str, environment: Option<&str>, release: &str) -> Result<Self, ErrorReportingError> {
+        // the dsn has the form of `{scheme}://{public_key}@{host}{/path}/{project_id}`
+        let dsn = Url::parse(dsn).map_err(|err| ErrorReportingError::InvalidDsn(format!("{err}")))?;
+        let public_key = dsn.username();
+        if public_key.is_empty() {
+            return Err(ErrorReportingError::InvalidDsn("Missing public key".into()));
+        }
+        let (path, project_id) = dsn
+            .path()
+            .rsplit_once('/')
+            .filter(|(_, project_id)| !project_id.is_empty())
+            .ok_or_else(|| ErrorReportingError::InvalidDsn("Missing project id".into()))?;
+
+        let mut store_url = dsn.clone();
+        store_url
+            .set_username("")
+            .and_then(|_| store_url.set_password(None))
+            .map_err(|_| ErrorReportingError::InvalidDsn("Invalid host".into()))?;
+        store_url.set_path(&format!("{path}/api/{project_id}/store/"));
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            auth_header: format!(
+                "Sentry sentry_version=7, sentry_client={SERVICE_NAME}/{release}, sentry_key={public_key}"
+            ),
+            store_url,
+            environment: environment.map(ToOwned::to_owned),
+            release: format!("{SERVICE_NAME}@{release}"),
+        })
+    }
+}
+
+#[async_trait]
+impl ErrorReporter for SentryReporter {
+    async fn report(&self, report: &ErrorReport) -> Result<(), ErrorReportingError> {
+        let mut tags = json!({ "service": SERVICE_NAME, "source": report.source });
+        if let Some(request_id) = &report.request_id {
+            tags["request_id"] = json!(request_id);
+        }
+        let event = json!({
+            "event_id": report.event_id.as_simple().to_string(),
+            "timestamp": report.time.to_rfc3339(),
+            "level": "error",
+            "platform": "other",
+            "logger": report.source,
+            "message": { "formatted": report.message },
+            "environment": self.environment,
+            "release": self.release,
+            "tags": tags,
+            "extra": report.context,
+        });
+
+        let response = self
+            .client
+            .post(self.store_url.clone())
+            .header("X-Sentry-Auth", &self.auth_header)
+            .json(&event)
+            .send()
+            .await
+            .map_err(|err| ErrorReportingError::Transport(format!("{err}")))?;
+        if !response.status().is_success() {
+            return Err(ErrorReportingError::Transport(format!("({})", response.status())));
+        }
+        Ok(())
+    }
+}
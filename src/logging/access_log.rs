@@ -0,0 +1,151 @@
+use axum::{
+    extract::{MatchedPath, State},
+    http::Request,
+    middleware::Next,
+    response::Response,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The upper bounds of the latency buckets in milliseconds, the last bucket of the histograms has no upper bound.
+pub const LATENCY_BUCKETS_MS: [u64; 10] = [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+/// The route of the requests not matching any of the routes.
+const UNMATCHED_ROUTE: &str = "<unmatched>";
+
+/// The access log of the HTTP requests. Only the method, the route, the status and the latency are logged, the path
+/// parameters, the query, the headers (cookies, authorization) and the bodies are never included.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessLogConfig {
+    /// The ratio of the logged requests in the [0, 1] range.
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: f64,
+    /// The sample rate by the route overriding the default, ex. `{"/identity/info/ready": 0.0}`.
+    #[serde(default)]
+    pub routes: HashMap<String, f64>,
+}
+
+fn default_sample_rate() -> f64 {
+    1.0
+}
+
+/// The distribution of the latencies of a route.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencyHistogram {
+    /// The number of the requests in the buckets of `LATENCY_BUCKETS_MS`, the last bucket is for the slower requests.
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub sum_ms: u64,
+}
+
+impl LatencyHistogram {
+    fn new() -> Self {
+        Self {
+            buckets: vec![0; LATENCY_BUCKETS_MS.len() + 1],
+            count: 0,
+            sum_ms: 0,
+        }
+    }
+
+    fn record(&mut self, latency_ms: u64) {
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| latency_ms <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum_ms += latency_ms;
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatencyReport {
+    pub bucket_bounds_ms: Vec<u64>,
+    /// The histograms by `<method> <route>`.
+    pub routes: BTreeMap<String, LatencyHistogram>,
+}
+
+struct Inner {
+    config: AccessLogConfig,
+    histograms: Mutex<HashMap<String, LatencyHistogram>>,
+}
+
+/// Access log of the requests and the latency histograms of the routes. The latency of all the requests is recorded,
+/// the sampling applies only to the log output.
+#[derive(Clone)]
+pub struct AccessLog(Arc<Inner>);
+
+impl AccessLog {
+    pub fn new(config: &AccessLogConfig) -> Self {
+        Self(Arc::new(Inner {
+            config: config.clone(),
+            histograms: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    fn sample_rate(&self, route: &str) -> f64 {
+        self.0
+            .config
+            .routes
+            .get(route)
+            .copied()
+            .unwrap_or(self.0.config.sample_rate)
+    }
+
+    fn record(&self, key: String, latency: Duration) {
+        let latency_ms = u64::try_from(latency.as_millis()).unwrap_or(u64::MAX);
+        let mut histograms = self.0.histograms.lock().unwrap();
+        histograms
+            .entry(key)
+            .or_insert_with(LatencyHistogram::new)
+            .record(latency_ms);
+    }
+
+    /// Get the latency histograms of the routes since the start of the service.
+    pub fn latencies(&self) -> LatencyReport {
+        let histograms = self.0.histograms.lock().unwrap();
+        LatencyReport {
+            bucket_bounds_ms: LATENCY_BUCKETS_MS.to_vec(),
+            routes: histograms
+                .iter()
+                .map(|(route, histogram)| (route.clone(), histogram.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// Middleware logging the sampled requests and recording the latency of all the requests.
+pub async fn log_access<B>(State(access_log): State<AccessLog>, request: Request<B>, next: Next<B>) -> Response {
+    let method = request.method().clone();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| UNMATCHED_ROUTE.to_owned());
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let latency = start.elapsed();
+
+    let sample_rate = access_log.sample_rate(&route);
+    if sample_rate >= 1.0 || (sample_rate > 0.0 && rand::thread_rng().gen_bool(sample_rate)) {
+        tracing::info!(
+            method = %method,
+            route = %route,
+            status = response.status().as_u16(),
+            latency_ms = latency.as_millis() as u64,
+            "Access"
+        );
+    }
+    access_log.record(format!("{method} {route}"), latency);
+
+    response
+}
@@ -0,0 +1,9 @@
+mod access_log;
+pub use self::access_log::*;
+mod redact;
+pub use self::redact::*;
+mod request_id;
+pub use self::request_id::*;
+
+#[cfg(test)]
+mod test_logging_flows;
@@ -0,0 +1,97 @@
+use crate::logging::user_hash;
+use shine_service::service::CurrentUser;
+use std::fmt;
+
+/// The number of characters of a token kept in the logs to tell the tokens apart.
+const TOKEN_PREFIX_LENGTH: usize = 4;
+
+/// Mask a value in the logs completely.
+pub struct Redacted<T>(pub T);
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+impl<T> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Mask an email in the logs keeping only the first character of the local part and the domain,
+/// ex. `j***@example.com`.
+pub struct RedactedEmail<'a>(pub &'a str);
+
+impl<'a> fmt::Debug for RedactedEmail<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.rsplit_once('@') {
+            Some((local, domain)) => match local.chars().next() {
+                Some(first) => write!(f, "{first}***@{domain}"),
+                None => write!(f, "***@{domain}"),
+            },
+            None => f.write_str("***"),
+        }
+    }
+}
+
+impl<'a> fmt::Display for RedactedEmail<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Mask a secret (token, code, nonce) in the logs. Only a short prefix of the long secrets is kept to tell them apart,
+/// ex. `AbCd***(43)`.
+pub struct RedactedToken<'a>(pub &'a str);
+
+impl<'a> fmt::Debug for RedactedToken<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let length = self.0.chars().count();
+        if length >= 4 * TOKEN_PREFIX_LENGTH {
+            let prefix: String = self.0.chars().take(TOKEN_PREFIX_LENGTH).collect();
+            write!(f, "{prefix}***({length})")
+        } else {
+            write!(f, "***({length})")
+        }
+    }
+}
+
+impl<'a> fmt::Display for RedactedToken<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Log a session user without the session key and the name, the user is given by the hash of the id.
+pub struct RedactedUser<'a>(pub &'a CurrentUser);
+
+impl<'a> fmt::Debug for RedactedUser<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CurrentUser")
+            .field("user", &user_hash(self.0.user_id))
+            .field("session_start", &self.0.session_start)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Redacted, RedactedEmail, RedactedToken};
+
+    #[test]
+    fn secrets_are_masked() {
+        assert_eq!(
+            format!("{:?}", RedactedEmail("jane.doe@example.com")),
+            "j***@example.com"
+        );
+        assert_eq!(format!("{:?}", RedactedEmail("not-an-email")), "***");
+        assert_eq!(
+            format!("{:?}", RedactedToken("AbCdEfGhIjKlMnOpQrStUvWxYz")),
+            "AbCd***(26)"
+        );
+        assert_eq!(format!("{:?}", RedactedToken("short")), "***(5)");
+        assert_eq!(format!("{:?}", Some(Redacted("secret"))), "Some(<redacted>)");
+    }
+}
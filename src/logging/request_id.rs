@@ -0,0 +1,65 @@
+use axum::{
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use ring::digest;
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// Header of the correlation id of the requests, an id from the gateway is kept, otherwise a new one is generated.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The maximum length of a correlation id accepted from the clients.
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+fn is_valid_request_id(request_id: &str) -> bool {
+    !request_id.is_empty()
+        && request_id.len() <= MAX_REQUEST_ID_LENGTH
+        && request_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// Get the correlation id of the request in progress, ex. to show it on the error pages for the support.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|request_id| request_id.clone()).ok()
+}
+
+/// Get a stable, non-reversible identifier of a user for the logs, thus the logs can be correlated without storing
+/// the user ids.
+pub fn user_hash(user_id: Uuid) -> String {
+    let hash = digest::digest(&digest::SHA256, user_id.as_bytes());
+    hex::encode(&hash.as_ref()[..8])
+}
+
+/// Middleware assigning a correlation id to each request. All the logs of the request are in a `request` span with
+/// the `request_id` field and the id is returned in the `x-request-id` header of the response.
+pub async fn propagate_request_id<B>(request: Request<B>, next: Next<B>) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|request_id| is_valid_request_id(request_id))
+        .map(|request_id| request_id.to_owned())
+        .unwrap_or_else(|| Uuid::new_v4().as_simple().to_string());
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method(),
+        path = %request.uri().path()
+    );
+    let mut response = REQUEST_ID
+        .scope(request_id.clone(), next.run(request).instrument(span))
+        .await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
@@ -0,0 +1,24 @@
+use crate::test_support::{TestApp, TestClient};
+use axum::http::HeaderName;
+
+#[tokio::test]
+async fn request_id_is_propagated() {
+    let app = TestApp::new().await;
+
+    log::info!("A new request id is generated...");
+    let mut client = TestClient::new(&app.router);
+    let response = client.get("/api/auth/providers").await;
+    let request_id = response.headers.get("x-request-id").expect("Missing request id");
+    assert_eq!(request_id.len(), 32);
+
+    log::info!("The request id of the gateway is kept...");
+    client.set_header(HeaderName::from_static("x-request-id"), "gateway-1234");
+    let response = client.get("/api/auth/providers").await;
+    assert_eq!(response.headers["x-request-id"], "gateway-1234");
+
+    log::info!("The request id is shown on the error pages...");
+    let response = client.get("/auth/links").await;
+    assert!(response.text().contains("Reference: gateway-1234"));
+
+    app.cleanup().await;
+}
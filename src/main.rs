@@ -1,17 +1,40 @@
+mod admin;
 mod app_config;
 mod auth;
 mod db;
+mod email;
+mod error_reporting;
+mod logging;
+#[cfg(feature = "mock-provider")]
+mod mock_provider;
+mod secrets;
 mod services;
+#[cfg(test)]
+mod test_support;
 
 use crate::{
+    admin::{enforce_ip_allowlist, AdminServiceBuilder, AdminServiceDependencies, IpAllowlist, LogFilter, TlsReloader},
     app_config::{AppConfig, SERVICE_NAME},
-    auth::{AuthServiceBuilder, AuthServiceDependencies},
-    db::{DBPool, IdentityManager, NameGenerator, SessionManager},
+    auth::{track_activity, AuthServiceBuilder, AuthServiceDependencies},
+    db::{
+        AbuseFlagManager, ActivityTracker, AgeManager, AnalyticsEvents, ApiQuotaManager, AuditLog, BreakGlassStore,
+        ConsentManager, DBPool, DeletionManager, DevSeeder, DeviceManager, ExpirationManager, FeatureFlags,
+        IdentityManager, IdentityStatsManager, LoginLinkManager, MergeManager, MfaManager, NameGenerator,
+        NativeLoginManager, OpaqueTokenStore, ParentalConsentManager, PasswordManager, PermissionManager,
+        RandomIdGenerator, RateLimiter, RegionManager, RestrictionManager, RoleManager, ServiceClientManager,
+        SessionManager, SharedClock, SharedIdGenerator, SharedIdentityStore, SharedSessionStore, SsoHandoffManager,
+        StudioManager, SupportNoteManager, SystemClock, TagManager, TicketRedemption, TokenRevocation,
+        UserInvalidation,
+    },
+    email::{EmailSender, EmailTransport},
+    error_reporting::{report_server_errors, ErrorReporting},
+    logging::{log_access, propagate_request_id, AccessLog, REQUEST_ID_HEADER},
     services::{IdentityServiceBuilder, IdentityServiceDependencies},
 };
 use anyhow::{anyhow, Error as AnyError};
 use axum::{
-    http::{header, Method},
+    http::{header, HeaderName, Method},
+    middleware,
     routing::get,
     Router,
 };
@@ -23,7 +46,7 @@ use shine_service::{
     },
     service::UserSessionValidator,
 };
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc};
 use tera::Tera;
 use tokio::{
     runtime::{Handle as RtHandle, Runtime},
@@ -46,6 +69,197 @@ fn service_path(path: &str) -> String {
     format!("/{SERVICE_NAME}{path}")
 }
 
+/// Create the routes of the services. The tracing and the top level layers are added by the caller. The email transport
+/// replaces the configured one, ex. to capture the emails in the tests.
+async fn create_app(
+    config: &AppConfig,
+    db_pool: &DBPool,
+    ip_allowlist: Arc<IpAllowlist>,
+    tls_reloader: Option<TlsReloader>,
+    log_filter: Option<LogFilter>,
+    clock: SharedClock,
+    ids: SharedIdGenerator,
+    email_transport: Option<Arc<dyn EmailTransport>>,
+) -> Result<Router, AnyError> {
+    let tera = {
+        let mut tera = Tera::new("tera_templates/**/*").map_err(|e| anyhow!(e))?;
+        tera.autoescape_on(vec![".html"]);
+        tera
+    };
+
+    let auth_config = &config.auth.auth_session;
+    let error_reporting = ErrorReporting::new(config.error_reporting.as_ref(), &config.core.version)?;
+
+    let user_session = UserSessionValidator::new(None, &auth_config.session_secret, db_pool.redis.clone())?;
+    let identity_manager: SharedIdentityStore = Arc::new(
+        IdentityManager::new(
+            db_pool,
+            &config.email_normalization,
+            &auth_config.token_hash_secret,
+            clock.clone(),
+            ids,
+        )
+        .await?,
+    );
+    let session_max_duration = Duration::seconds(i64::try_from(auth_config.session_max_duration)?);
+    let session_manager: SharedSessionStore = Arc::new(
+        SessionManager::new(
+            db_pool,
+            session_max_duration,
+            auth_config.session_limit.clone(),
+            clock.clone(),
+        )
+        .await?,
+    );
+    let name_generator = NameGenerator::new(&config.user_name, db_pool).await?;
+    let device_manager = DeviceManager::new(db_pool).await?;
+    let password_manager = PasswordManager::new(db_pool).await?;
+    let mfa_manager = MfaManager::new(db_pool).await?;
+    let rate_limiter = RateLimiter::new(db_pool);
+    let token_revocation = TokenRevocation::new(db_pool, &config.token_revocation);
+    let feature_flags = FeatureFlags::new(db_pool, &config.feature_flags);
+    let role_manager = RoleManager::new(db_pool).await?;
+    let permission_manager = PermissionManager::new(db_pool).await?;
+    let audit_log = AuditLog::new(db_pool).await?;
+    let break_glass_store = BreakGlassStore::new(db_pool);
+    let login_link_manager = LoginLinkManager::new(db_pool);
+    let user_invalidation = UserInvalidation::new(db_pool);
+    let analytics = AnalyticsEvents::new(db_pool, clock.clone());
+    let studio_manager = StudioManager::new(db_pool, clock.clone()).await?;
+    let deletion_manager = DeletionManager::new(
+        db_pool,
+        identity_manager.clone(),
+        studio_manager.clone(),
+        user_invalidation.clone(),
+        audit_log.clone(),
+        error_reporting.clone(),
+        clock.clone(),
+        &config.deletion,
+    )
+    .await?;
+    let expiration_manager = ExpirationManager::new(
+        db_pool,
+        identity_manager.clone(),
+        session_manager.clone(),
+        user_invalidation.clone(),
+        audit_log.clone(),
+        error_reporting.clone(),
+        clock.clone(),
+    )
+    .await?;
+    let consent_manager = ConsentManager::new(db_pool, clock.clone()).await?;
+    let merge_manager = MergeManager::new(db_pool, clock.clone()).await?;
+    let service_client_manager = ServiceClientManager::new(db_pool, clock.clone()).await?;
+    let opaque_token_store = OpaqueTokenStore::new(db_pool);
+    let native_login_manager = NativeLoginManager::new(db_pool);
+    let sso_handoff_manager = SsoHandoffManager::new(db_pool);
+    let ticket_redemption = TicketRedemption::new(db_pool);
+    let api_quota_manager = ApiQuotaManager::new(db_pool, clock.clone()).await?;
+    let abuse_flag_manager = AbuseFlagManager::new(db_pool, clock.clone()).await?;
+    let restriction_manager = RestrictionManager::new(db_pool, clock.clone()).await?;
+    let support_note_manager = SupportNoteManager::new(db_pool, clock.clone()).await?;
+    let tag_manager = TagManager::new(db_pool, clock.clone()).await?;
+    let age_manager = AgeManager::new(db_pool, clock.clone()).await?;
+    let parental_consent_manager = ParentalConsentManager::new(db_pool, clock.clone()).await?;
+    let region_manager = RegionManager::new(db_pool, clock.clone()).await?;
+    let email_sender = match email_transport {
+        Some(email_transport) => EmailSender::with_transport(&config.email, tera.clone(), email_transport)?,
+        None => EmailSender::new(&config.email, tera.clone()).await?,
+    };
+    let identity_stats = IdentityStatsManager::new(db_pool, clock.clone()).await?;
+    let activity_tracker = ActivityTracker::new(db_pool, clock.clone()).await?;
+
+    if let Some(dev_seed) = &config.dev_seed {
+        DevSeeder::new(&*identity_manager, &password_manager, &role_manager)
+            .seed(dev_seed)
+            .await?;
+    }
+
+    let (auth_pages, auth_api) = {
+        let auth_state = AuthServiceDependencies {
+            tera: tera.clone(),
+            identity_manager: identity_manager.clone(),
+            session_manager: session_manager.clone(),
+            name_generator: name_generator.clone(),
+            device_manager: device_manager.clone(),
+            password_manager: password_manager.clone(),
+            mfa_manager: mfa_manager.clone(),
+            rate_limiter: rate_limiter.clone(),
+            token_revocation: token_revocation.clone(),
+            role_manager: role_manager.clone(),
+            permission_manager: permission_manager.clone(),
+            audit_log: audit_log.clone(),
+            break_glass_store: break_glass_store.clone(),
+            login_link_manager: login_link_manager.clone(),
+            user_invalidation: user_invalidation.clone(),
+            analytics: analytics.clone(),
+            deletion_manager: deletion_manager.clone(),
+            expiration_manager: expiration_manager.clone(),
+            consent_manager: consent_manager.clone(),
+            merge_manager: merge_manager.clone(),
+            service_client_manager: service_client_manager.clone(),
+            studio_manager: studio_manager.clone(),
+            opaque_token_store: opaque_token_store.clone(),
+            native_login_manager: native_login_manager.clone(),
+            sso_handoff_manager: sso_handoff_manager.clone(),
+            ticket_redemption: ticket_redemption.clone(),
+            api_quota_manager: api_quota_manager.clone(),
+            abuse_flag_manager: abuse_flag_manager.clone(),
+            restriction_manager: restriction_manager.clone(),
+            support_note_manager: support_note_manager.clone(),
+            tag_manager: tag_manager.clone(),
+            age_manager: age_manager.clone(),
+            parental_consent_manager: parental_consent_manager.clone(),
+            region_manager: region_manager.clone(),
+            feature_flags: feature_flags.clone(),
+            email_sender: email_sender.clone(),
+            error_reporting: error_reporting.clone(),
+            clock: clock.clone(),
+            ip_allowlist: ip_allowlist.clone(),
+        };
+        AuthServiceBuilder::new(auth_state, &config.auth).await?.into_router()
+    };
+
+    let identity_api = {
+        let identity_state = IdentityServiceDependencies {
+            identity_manager: identity_manager.clone(),
+            name_generator: name_generator.clone(),
+            db: db_pool.clone(),
+        };
+        IdentityServiceBuilder::new(identity_state).into_router()
+    };
+
+    let access_log = config.access_log.as_ref().map(AccessLog::new);
+
+    let admin_api = {
+        let admin_state = AdminServiceDependencies {
+            app_config: config.clone(),
+            ip_allowlist: ip_allowlist.clone(),
+            tls_reloader: tls_reloader.clone(),
+            log_filter,
+            email_sender: email_sender.clone(),
+            identity_stats: identity_stats.clone(),
+            access_log: access_log.clone(),
+        };
+        AdminServiceBuilder::new(admin_state).into_router()
+    };
+
+    let mut router = Router::new()
+        .route(&service_path("/info/ready"), get(health_check))
+        .nest(&service_path(""), auth_pages)
+        .nest(&service_path("/api/admin"), admin_api)
+        .nest(&service_path("/api"), identity_api)
+        .nest(&service_path("/api"), auth_api)
+        .layer(middleware::from_fn_with_state(activity_tracker, track_activity))
+        .layer(user_session.into_layer())
+        .layer(middleware::from_fn_with_state(error_reporting, report_server_errors));
+    if let Some(access_log) = access_log {
+        router = router.layer(middleware::from_fn_with_state(access_log, log_access));
+    }
+
+    Ok(router.layer(middleware::from_fn(propagate_request_id)))
+}
+
 async fn async_main(_rt_handle: RtHandle) -> Result<(), AnyError> {
     let (config, tracing_service) = {
         // initialize a pre-init logger
@@ -89,79 +303,77 @@ async fn async_main(_rt_handle: RtHandle) -> Result<(), AnyError> {
     let cors = CorsLayer::default()
         .allow_origin(allow_origins)
         .allow_methods([Method::GET, Method::POST])
-        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION, header::ACCEPT])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::AUTHORIZATION,
+            header::ACCEPT,
+            header::IF_NONE_MATCH,
+            HeaderName::from_static(REQUEST_ID_HEADER),
+        ])
+        .expose_headers([header::ETAG, HeaderName::from_static(REQUEST_ID_HEADER)])
         .allow_credentials(true);
     let powered_by = PoweredBy::from_service_info(SERVICE_NAME, &config.core.version)?;
 
+    let ip_allowlist = Arc::new(IpAllowlist::new(&config.admin_allowlist));
     let tracing_router = tracing_service.into_router();
+    let log_filter = LogFilter::new(tracing_router.clone());
+    let tracing_router = tracing_router.layer(middleware::from_fn_with_state(
+        ip_allowlist.clone(),
+        enforce_ip_allowlist,
+    ));
     let tracing_layer = OtelAxumLayer::default().filter(|a| {
         println!("FFFF: {a}");
         true
     });
 
-    let tera = {
-        let mut tera = Tera::new("tera_templates/**/*").map_err(|e| anyhow!(e))?;
-        tera.autoescape_on(vec![".html"]);
-        tera
-    };
-
-    let auth_config = &config.auth.auth_session;
-
-    let db_pool = DBPool::new(&config.db).await?;
-    let user_session = UserSessionValidator::new(None, &auth_config.session_secret, db_pool.redis.clone())?;
-    let identity_manager = IdentityManager::new(&db_pool).await?;
-    let session_max_duration = Duration::seconds(i64::try_from(auth_config.session_max_duration)?);
-    let session_manager = SessionManager::new(&db_pool, session_max_duration).await?;
-    let name_generator = NameGenerator::new(&config.user_name, &db_pool).await?;
+    let db_pool = DBPool::new(&config.db, &config.email_normalization).await?;
 
-    let (auth_pages, auth_api) = {
-        let auth_state = AuthServiceDependencies {
-            tera: tera.clone(),
-            identity_manager: identity_manager.clone(),
-            session_manager: session_manager.clone(),
-            name_generator: name_generator.clone(),
-        };
-        AuthServiceBuilder::new(auth_state, &config.auth).await?.into_router()
+    // the provider has to run before the discovery of the auth service
+    #[cfg(feature = "mock-provider")]
+    let _mock_provider = match &config.mock_provider {
+        Some(mock_config) => {
+            let mock_provider = mock_provider::MockProvider::start(mock_config).await?;
+            log::warn!("Mock identity provider is running at {}", mock_provider.issuer());
+            Some(mock_provider)
+        }
+        None => None,
     };
 
-    let identity_api = {
-        let identity_state = IdentityServiceDependencies {
-            identity_manager: identity_manager.clone(),
-            name_generator: name_generator.clone(),
-            db: db_pool.clone(),
-        };
-        IdentityServiceBuilder::new(identity_state).into_router()
+    let tls_reloader = match &config.tls {
+        Some(tls_config) => Some(TlsReloader::new(tls_config).await?),
+        None => None,
     };
 
-    let app = Router::new()
-        .route(&service_path("/info/ready"), get(health_check))
-        .nest(&service_path(""), auth_pages)
-        .nest(&service_path("/api/tracing"), tracing_router)
-        .nest(&service_path("/api"), identity_api)
-        .nest(&service_path("/api"), auth_api)
-        .layer(user_session.into_layer())
-        .layer(powered_by)
-        .layer(cors)
-        .layer(tracing_layer);
+    let app = create_app(
+        &config,
+        &db_pool,
+        ip_allowlist,
+        tls_reloader.clone(),
+        Some(log_filter),
+        Arc::new(SystemClock),
+        Arc::new(RandomIdGenerator),
+        None,
+    )
+    .await?
+    .nest(&service_path("/api/tracing"), tracing_router)
+    .layer(powered_by)
+    .layer(cors)
+    .layer(tracing_layer);
 
     let addr = SocketAddr::from(([0, 0, 0, 0], config.control_port));
 
-    if let Some(tls_config) = config.tls {
+    if let Some(tls_reloader) = tls_reloader {
         log::info!("Starting service on {addr:?} using tls");
-        let cert = tls_config.cert.as_bytes().to_vec();
-        let key = tls_config.key.as_bytes().to_vec();
-        let config = axum_server::tls_rustls::RustlsConfig::from_pem(cert, key)
-            .await
-            .map_err(|e| anyhow!(e))?;
-        axum_server::bind_rustls(addr, config)
-            .serve(app.into_make_service())
+        tls_reloader.spawn_watcher();
+        axum_server::bind_rustls(addr, tls_reloader.rustls_config())
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
             //.with_graceful_shutdown(shutdown_signal())
             .await
             .map_err(|e| anyhow!(e))
     } else {
         log::info!("Starting service on {addr:?}");
         axum::Server::bind(&addr)
-            .serve(app.into_make_service())
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
             .with_graceful_shutdown(shutdown_signal())
             .await
             .map_err(|e| anyhow!(e))
@@ -0,0 +1,414 @@
+use axum::{
+    extract::{Form, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use base64::{
+    engine::general_purpose::{STANDARD as B64, URL_SAFE_NO_PAD as B64URL},
+    Engine,
+};
+use chrono::{Duration, Utc};
+use ring::{
+    digest,
+    rand::{SecureRandom, SystemRandom},
+    signature::{RsaKeyPair, RSA_PKCS1_SHA256},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, TcpListener},
+    sync::{Arc, Mutex},
+};
+use thiserror::Error as ThisError;
+use tokio::sync::oneshot;
+use url::Url;
+
+/// RSA key signing the id tokens. It is a test key, never use it for anything else.
+const SIGNING_KEY: &[u8] = include_bytes!("mock_key.pk8");
+/// The public modulus of the `SIGNING_KEY`, url-safe base64 encoded for the jwks.
+const SIGNING_KEY_MODULUS: &str = "s10s_oVHPgvSm-mFUn0w37mukq6h76gopaw5z56yZ0drSHJ-RXGcUZDH7MSimfA7ii25ziH1x8IvIomnR4A6T-S0EeJB-dw0QUxbXdjoNCyido66-a2bPsUu92Y5gn6Ksjlqy7UrSJQO1oOcg_ZOk8NWayETZ9zi0A6EQgOWKa8gOwcP7Zg27wJtnkv86B0E0HR4mWOjrjbfSVK--obB-T09ncfnfWKQTmm5-W-hoD6tAa-fvNR4SQMp0uJ41K6ASH0XN04NDoo7bcFsm3PM8FMb3TkwEixDBzuq65pwWbM83qwQ2SLnwMrIs35qK4rCN2D5H141H0Z_h1WEFAmV4Q";
+const SIGNING_KEY_EXPONENT: &str = "AQAB";
+const SIGNING_KEY_ID: &str = "mock";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MockUser {
+    pub id: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MockProviderConfig {
+    /// Port of the provider on the localhost, with 0 a free port is picked.
+    pub port: u16,
+    pub client_id: String,
+    pub client_secret: String,
+    /// The users of the provider. The `login_hint` of the authorization request selects the user by id,
+    /// without a hint the first user is logged in.
+    pub users: Vec<MockUser>,
+}
+
+#[derive(Debug, ThisError)]
+pub enum MockProviderBuildError {
+    #[error("Failed to bind mock provider: {0}")]
+    Bind(#[from] std::io::Error),
+    #[error("Invalid signing key: {0}")]
+    SigningKey(String),
+    #[error("Failed to start mock provider: {0}")]
+    Server(String),
+}
+
+#[derive(Debug, ThisError)]
+enum MockProviderError {
+    #[error("invalid_request")]
+    InvalidRequest,
+    #[error("invalid_client")]
+    InvalidClient,
+    #[error("invalid_grant")]
+    InvalidGrant,
+    #[error("invalid_token")]
+    InvalidToken,
+    #[error("access_denied")]
+    AccessDenied,
+    #[error("server_error")]
+    ServerError,
+}
+
+impl IntoResponse for MockProviderError {
+    fn into_response(self) -> Response {
+        let status_code = match &self {
+            MockProviderError::InvalidClient | MockProviderError::InvalidToken => StatusCode::UNAUTHORIZED,
+            MockProviderError::AccessDenied => StatusCode::FORBIDDEN,
+            MockProviderError::ServerError => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        };
+
+        (status_code, Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+struct PendingCode {
+    user: MockUser,
+    redirect_uri: String,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+}
+
+struct Inner {
+    issuer: String,
+    config: MockProviderConfig,
+    key_pair: RsaKeyPair,
+    random: SystemRandom,
+    codes: Mutex<HashMap<String, PendingCode>>,
+    access_tokens: Mutex<HashMap<String, MockUser>>,
+}
+
+#[derive(Clone)]
+struct MockProviderState(Arc<Inner>);
+
+impl MockProviderState {
+    fn generate_secret(&self) -> Result<String, MockProviderError> {
+        let mut raw = [0_u8; 16];
+        self.0
+            .random
+            .fill(&mut raw)
+            .map_err(|_| MockProviderError::ServerError)?;
+        Ok(hex::encode(raw))
+    }
+
+    fn check_client(&self, client_id: &str, client_secret: &str) -> Result<(), MockProviderError> {
+        let config = &self.0.config;
+        if config.client_id == client_id && config.client_secret == client_secret {
+            Ok(())
+        } else {
+            Err(MockProviderError::InvalidClient)
+        }
+    }
+
+    fn create_id_token(&self, user: &MockUser, nonce: Option<&str>) -> Result<String, MockProviderError> {
+        let now = Utc::now();
+        let header = json!({ "alg": "RS256", "typ": "JWT", "kid": SIGNING_KEY_ID });
+        let claims = json!({
+            "iss": self.0.issuer,
+            "sub": user.id,
+            "aud": [self.0.config.client_id],
+            "iat": now.timestamp(),
+            "exp": (now + Duration::hours(1)).timestamp(),
+            "nonce": nonce,
+            "name": user.name,
+            "email": user.email,
+            "email_verified": user.email.is_some(),
+        });
+
+        let message = format!(
+            "{}.{}",
+            B64URL.encode(header.to_string()),
+            B64URL.encode(claims.to_string())
+        );
+        let mut signature = vec![0; self.0.key_pair.public_modulus_len()];
+        self.0
+            .key_pair
+            .sign(&RSA_PKCS1_SHA256, &self.0.random, message.as_bytes(), &mut signature)
+            .map_err(|_| MockProviderError::ServerError)?;
+        Ok(format!("{message}.{}", B64URL.encode(signature)))
+    }
+}
+
+async fn ep_discovery(State(state): State<MockProviderState>) -> Json<Value> {
+    let issuer = &state.0.issuer;
+    Json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{issuer}/authorize"),
+        "token_endpoint": format!("{issuer}/token"),
+        "userinfo_endpoint": format!("{issuer}/userinfo"),
+        "jwks_uri": format!("{issuer}/jwks"),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["RS256"],
+        "scopes_supported": ["openid", "profile", "email"],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post"],
+        "claims_supported": ["sub", "name", "email", "email_verified"],
+        "code_challenge_methods_supported": ["S256", "plain"],
+    }))
+}
+
+async fn ep_jwks() -> Json<Value> {
+    Json(json!({
+        "keys": [{
+            "kty": "RSA",
+            "use": "sig",
+            "alg": "RS256",
+            "kid": SIGNING_KEY_ID,
+            "n": SIGNING_KEY_MODULUS,
+            "e": SIGNING_KEY_EXPONENT,
+        }]
+    }))
+}
+
+#[derive(Deserialize)]
+struct AuthorizeRequest {
+    client_id: String,
+    redirect_uri: String,
+    state: Option<String>,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+    login_hint: Option<String>,
+}
+
+/// Authorize the user immediately without any interaction and redirect back to the client with the code.
+async fn ep_authorize(
+    State(state): State<MockProviderState>,
+    Query(request): Query<AuthorizeRequest>,
+) -> Result<Redirect, MockProviderError> {
+    if request.client_id != state.0.config.client_id {
+        return Err(MockProviderError::InvalidClient);
+    }
+
+    let users = &state.0.config.users;
+    let user = match &request.login_hint {
+        Some(hint) => users.iter().find(|user| &user.id == hint),
+        None => users.first(),
+    }
+    .ok_or(MockProviderError::AccessDenied)?;
+
+    let code_challenge = match (request.code_challenge, request.code_challenge_method.as_deref()) {
+        (Some(challenge), Some("S256")) => Some(challenge),
+        (Some(challenge), None | Some("plain")) => {
+            Some(B64URL.encode(digest::digest(&digest::SHA256, challenge.as_bytes())))
+        }
+        (Some(_), Some(_)) => return Err(MockProviderError::InvalidRequest),
+        (None, _) => None,
+    };
+
+    let mut redirect_url = Url::parse(&request.redirect_uri).map_err(|_| MockProviderError::InvalidRequest)?;
+    let code = state.generate_secret()?;
+    {
+        let mut query = redirect_url.query_pairs_mut();
+        query.append_pair("code", &code);
+        if let Some(csrf_state) = &request.state {
+            query.append_pair("state", csrf_state);
+        }
+    }
+
+    log::debug!("Mock provider authorized {}", user.id);
+    state.0.codes.lock().unwrap().insert(
+        code,
+        PendingCode {
+            user: user.clone(),
+            redirect_uri: request.redirect_uri,
+            nonce: request.nonce,
+            code_challenge,
+        },
+    );
+
+    Ok(Redirect::to(redirect_url.as_str()))
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    grant_type: String,
+    code: String,
+    redirect_uri: Option<String>,
+    code_verifier: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+/// Exchange the code to an access token and an id token.
+async fn ep_token(
+    State(state): State<MockProviderState>,
+    headers: HeaderMap,
+    Form(request): Form<TokenRequest>,
+) -> Result<Json<Value>, MockProviderError> {
+    let basic_auth = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|auth| auth.to_str().ok())
+        .and_then(|auth| auth.strip_prefix("Basic "))
+        .and_then(|auth| B64.decode(auth).ok())
+        .and_then(|auth| String::from_utf8(auth).ok());
+    match (&basic_auth, &request.client_id, &request.client_secret) {
+        (Some(auth), _, _) => {
+            let (client_id, client_secret) = auth.split_once(':').ok_or(MockProviderError::InvalidClient)?;
+            state.check_client(client_id, client_secret)?;
+        }
+        (None, Some(client_id), Some(client_secret)) => state.check_client(client_id, client_secret)?,
+        _ => return Err(MockProviderError::InvalidClient),
+    }
+
+    if request.grant_type != "authorization_code" {
+        return Err(MockProviderError::InvalidRequest);
+    }
+
+    let pending = state
+        .0
+        .codes
+        .lock()
+        .unwrap()
+        .remove(&request.code)
+        .ok_or(MockProviderError::InvalidGrant)?;
+    if request.redirect_uri.as_ref() != Some(&pending.redirect_uri) {
+        return Err(MockProviderError::InvalidGrant);
+    }
+    if let Some(challenge) = &pending.code_challenge {
+        let verifier = request.code_verifier.ok_or(MockProviderError::InvalidGrant)?;
+        if B64URL.encode(digest::digest(&digest::SHA256, verifier.as_bytes())) != *challenge {
+            return Err(MockProviderError::InvalidGrant);
+        }
+    }
+
+    let id_token = state.create_id_token(&pending.user, pending.nonce.as_deref())?;
+    let access_token = state.generate_secret()?;
+    state
+        .0
+        .access_tokens
+        .lock()
+        .unwrap()
+        .insert(access_token.clone(), pending.user);
+
+    Ok(Json(json!({
+        "access_token": access_token,
+        "token_type": "bearer",
+        "expires_in": 3600,
+        "id_token": id_token,
+    })))
+}
+
+/// Get the user of an access token. Both the `sub` and `id` fields are present, thus it can be used with
+/// the OpenID Connect and with the plain OAuth2 user info mapping.
+async fn ep_user_info(
+    State(state): State<MockProviderState>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, MockProviderError> {
+    let access_token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|auth| auth.to_str().ok())
+        .and_then(|auth| auth.strip_prefix("Bearer ").or_else(|| auth.strip_prefix("bearer ")))
+        .ok_or(MockProviderError::InvalidToken)?;
+    let user = state
+        .0
+        .access_tokens
+        .lock()
+        .unwrap()
+        .get(access_token)
+        .cloned()
+        .ok_or(MockProviderError::InvalidToken)?;
+
+    Ok(Json(json!({
+        "sub": user.id,
+        "id": user.id,
+        "name": user.name,
+        "email": user.email,
+        "email_verified": user.email.is_some(),
+    })))
+}
+
+/// An in-process OpenID Connect and OAuth2 provider to exercise the login and link flows without third-party
+/// credentials. The server is stopped when the provider is dropped.
+pub struct MockProvider {
+    issuer: String,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockProvider {
+    pub async fn start(config: &MockProviderConfig) -> Result<Self, MockProviderBuildError> {
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], config.port)))?;
+        let issuer = format!("http://{}", listener.local_addr()?);
+        let key_pair =
+            RsaKeyPair::from_pkcs8(SIGNING_KEY).map_err(|err| MockProviderBuildError::SigningKey(format!("{err}")))?;
+
+        let state = MockProviderState(Arc::new(Inner {
+            issuer: issuer.clone(),
+            config: config.clone(),
+            key_pair,
+            random: SystemRandom::new(),
+            codes: Mutex::new(HashMap::new()),
+            access_tokens: Mutex::new(HashMap::new()),
+        }));
+
+        let router = Router::new()
+            .route("/.well-known/openid-configuration", get(ep_discovery))
+            .route("/jwks", get(ep_jwks))
+            .route("/authorize", get(ep_authorize))
+            .route("/token", post(ep_token))
+            .route("/userinfo", get(ep_user_info))
+            .with_state(state);
+
+        let (shutdown, shutdown_signal) = oneshot::channel::<()>();
+        let server = axum::Server::from_tcp(listener)
+            .map_err(|err| MockProviderBuildError::Server(format!("{err}")))?
+            .serve(router.into_make_service())
+            .with_graceful_shutdown(async {
+                let _ = shutdown_signal.await;
+            });
+        tokio::spawn(async move {
+            if let Err(err) = server.await {
+                log::error!("Mock provider failed: {err}");
+            }
+        });
+
+        Ok(Self {
+            issuer,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// The issuer, it is also the discovery url of the OpenID Connect clients. For the OAuth2 clients the
+    /// endpoints are `{issuer}/authorize`, `{issuer}/token` and `{issuer}/userinfo`.
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+}
+
+impl Drop for MockProvider {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
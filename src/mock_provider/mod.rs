@@ -0,0 +1,2 @@
+mod mock_server;
+pub use self::mock_server::*;
@@ -0,0 +1,58 @@
+use crate::secrets::{SecretError, SecretSource};
+use async_trait::async_trait;
+use azure_core::auth::TokenCredential;
+use azure_identity::DefaultAzureCredential;
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+const KEYVAULT_RESOURCE: &str = "https://vault.azure.net";
+const KEYVAULT_API_VERSION: &str = "7.4";
+
+/// Secrets stored in Azure Key Vault, the reference has the form `vault-name/secret-name`.
+#[derive(Default)]
+pub struct AzureKeyVaultSecretSource {
+    credential: DefaultAzureCredential,
+}
+
+#[async_trait]
+impl SecretSource for AzureKeyVaultSecretSource {
+    async fn get_secret(&self, reference: &str) -> Result<String, SecretError> {
+        let source_error = |err: String| SecretError::SourceError(reference.to_owned(), err);
+
+        let (vault, name) = reference
+            .split_once('/')
+            .ok_or_else(|| source_error("Invalid reference, expected vault-name/secret-name".into()))?;
+
+        let token = self
+            .credential
+            .get_token(KEYVAULT_RESOURCE)
+            .await
+            .map_err(|err| source_error(format!("{err}")))?;
+
+        let url = format!("https://{vault}.vault.azure.net/secrets/{name}?api-version={KEYVAULT_API_VERSION}");
+        let response = reqwest::Client::new()
+            .get(url)
+            .bearer_auth(token.token.secret())
+            .send()
+            .await
+            .map_err(|err| source_error(format!("{err}")))?;
+
+        #[derive(Deserialize)]
+        struct KeyVaultSecret {
+            value: String,
+        }
+
+        match response.status() {
+            status if status.is_success() => response
+                .json::<KeyVaultSecret>()
+                .await
+                .map(|secret| secret.value)
+                .map_err(|err| source_error(format!("{err}"))),
+            StatusCode::NOT_FOUND => Err(SecretError::NotFound(reference.to_owned())),
+            status => Err(source_error(format!(
+                "({status}), {}",
+                response.text().await.unwrap_or_default()
+            ))),
+        }
+    }
+}
@@ -0,0 +1,12 @@
+use crate::secrets::{SecretError, SecretSource};
+use async_trait::async_trait;
+
+/// Secrets stored in environment variables, the reference is the name of the variable.
+pub struct EnvSecretSource;
+
+#[async_trait]
+impl SecretSource for EnvSecretSource {
+    async fn get_secret(&self, reference: &str) -> Result<String, SecretError> {
+        std::env::var(reference).map_err(|_| SecretError::NotFound(reference.to_owned()))
+    }
+}
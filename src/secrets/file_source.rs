@@ -0,0 +1,18 @@
+use crate::secrets::{SecretError, SecretSource};
+use async_trait::async_trait;
+use std::{fs, io};
+
+/// Secrets stored in files (ex. docker or kubernetes secrets), the reference is the path of the file.
+/// The trailing whitespaces (new lines) are trimmed from the content.
+pub struct FileSecretSource;
+
+#[async_trait]
+impl SecretSource for FileSecretSource {
+    async fn get_secret(&self, reference: &str) -> Result<String, SecretError> {
+        match fs::read_to_string(reference) {
+            Ok(content) => Ok(content.trim_end().to_owned()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Err(SecretError::NotFound(reference.to_owned())),
+            Err(err) => Err(SecretError::SourceError(reference.to_owned(), format!("{err}"))),
+        }
+    }
+}
@@ -0,0 +1,11 @@
+mod secret_source;
+pub use self::secret_source::*;
+
+mod azure_keyvault_source;
+pub use self::azure_keyvault_source::*;
+mod env_source;
+pub use self::env_source::*;
+mod file_source;
+pub use self::file_source::*;
+mod vault_source;
+pub use self::vault_source::*;
@@ -0,0 +1,76 @@
+use crate::secrets::{AzureKeyVaultSecretSource, EnvSecretSource, FileSecretSource, VaultSecretSource};
+use async_trait::async_trait;
+use config::{Value, ValueKind};
+use std::collections::HashMap;
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub enum SecretError {
+    #[error("Unknown secret source: {0}")]
+    UnknownSource(String),
+    #[error("Secret ({0}) not found")]
+    NotFound(String),
+    #[error("Failed to get secret ({0}): {1}")]
+    SourceError(String, String),
+}
+
+/// Backend storing secrets that can be referenced from the configuration.
+#[async_trait]
+pub trait SecretSource: 'static + Send + Sync {
+    /// Get the value of the secret. The format of the reference is specific to the source.
+    async fn get_secret(&self, reference: &str) -> Result<String, SecretError>;
+}
+
+/// Parse a secret reference of the form `${source:reference}`.
+fn parse_reference(value: &str) -> Option<(&str, &str)> {
+    value.strip_prefix("${")?.strip_suffix('}')?.split_once(':')
+}
+
+/// Resolve the secret references in the configuration. Any string value of the form `${source:reference}` is
+/// replaced by the secret retrieved from the given source, other values are kept as they are.
+pub struct SecretResolver {
+    sources: HashMap<&'static str, Box<dyn SecretSource>>,
+}
+
+impl Default for SecretResolver {
+    fn default() -> Self {
+        let mut sources = HashMap::<_, Box<dyn SecretSource>>::new();
+        sources.insert("env", Box::new(EnvSecretSource));
+        sources.insert("file", Box::new(FileSecretSource));
+        sources.insert("keyvault", Box::new(AzureKeyVaultSecretSource::default()));
+        sources.insert("vault", Box::new(VaultSecretSource::from_env()));
+        Self { sources }
+    }
+}
+
+impl SecretResolver {
+    fn collect_references<'a>(value: &'a mut Value, references: &mut Vec<&'a mut String>) {
+        match &mut value.kind {
+            ValueKind::String(value) if parse_reference(value).is_some() => references.push(value),
+            ValueKind::Table(table) => table
+                .values_mut()
+                .for_each(|value| Self::collect_references(value, references)),
+            ValueKind::Array(array) => array
+                .iter_mut()
+                .for_each(|value| Self::collect_references(value, references)),
+            _ => {}
+        }
+    }
+
+    pub async fn resolve(&self, config: &mut Value) -> Result<(), SecretError> {
+        let mut references = Vec::new();
+        Self::collect_references(config, &mut references);
+
+        for value in references {
+            let (source, reference) = parse_reference(value).unwrap();
+            log::debug!("Resolving secret {reference} from {source}");
+            let source = self
+                .sources
+                .get(source)
+                .ok_or_else(|| SecretError::UnknownSource(source.to_owned()))?;
+            *value = source.get_secret(reference).await?;
+        }
+
+        Ok(())
+    }
+}
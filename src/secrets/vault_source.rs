@@ -0,0 +1,73 @@
+use crate::secrets::{SecretError, SecretSource};
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde_json::Value as JsonValue;
+
+const VAULT_ADDR: &str = "VAULT_ADDR";
+const VAULT_TOKEN: &str = "VAULT_TOKEN";
+
+/// Secrets stored in HashiCorp Vault, the reference has the form `path#field`. The address and the
+/// token of the vault is taken from the standard `VAULT_ADDR` and `VAULT_TOKEN` environment variables.
+/// Both the KV version 1 and version 2 engines are supported.
+pub struct VaultSecretSource {
+    address: Option<String>,
+    token: Option<String>,
+}
+
+impl VaultSecretSource {
+    pub fn from_env() -> Self {
+        Self {
+            address: std::env::var(VAULT_ADDR).ok(),
+            token: std::env::var(VAULT_TOKEN).ok(),
+        }
+    }
+}
+
+#[async_trait]
+impl SecretSource for VaultSecretSource {
+    async fn get_secret(&self, reference: &str) -> Result<String, SecretError> {
+        let source_error = |err: String| SecretError::SourceError(reference.to_owned(), err);
+
+        let address = self
+            .address
+            .as_deref()
+            .ok_or_else(|| source_error(format!("Missing {VAULT_ADDR}")))?;
+        let token = self
+            .token
+            .as_deref()
+            .ok_or_else(|| source_error(format!("Missing {VAULT_TOKEN}")))?;
+        let (path, field) = reference
+            .split_once('#')
+            .ok_or_else(|| source_error("Invalid reference, expected path#field".into()))?;
+
+        let url = format!("{}/v1/{}", address.trim_end_matches('/'), path.trim_start_matches('/'));
+        let response = reqwest::Client::new()
+            .get(url)
+            .header("X-Vault-Token", token)
+            .send()
+            .await
+            .map_err(|err| source_error(format!("{err}")))?;
+
+        let content = match response.status() {
+            status if status.is_success() => response
+                .json::<JsonValue>()
+                .await
+                .map_err(|err| source_error(format!("{err}")))?,
+            StatusCode::NOT_FOUND => return Err(SecretError::NotFound(reference.to_owned())),
+            status => {
+                return Err(source_error(format!(
+                    "({status}), {}",
+                    response.text().await.unwrap_or_default()
+                )))
+            }
+        };
+
+        // KV v2 nests the secret data into an extra data object
+        let data = &content["data"];
+        let data = if data["data"].is_object() { &data["data"] } else { data };
+        data[field]
+            .as_str()
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| SecretError::NotFound(reference.to_owned()))
+    }
+}
@@ -1,11 +1,17 @@
 use crate::services::IdentityServiceState;
 use axum::{
-    extract::State,
+    extract::{Query, State},
     response::{IntoResponse, Response},
     Json,
 };
 use reqwest::StatusCode;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub(in crate::services) struct UserNameRequest {
+    /// Select the word lists of the themed name generator
+    theme: Option<String>,
+}
 
 #[derive(Serialize)]
 pub struct UserName {
@@ -14,10 +20,11 @@ pub struct UserName {
 
 pub(in crate::services) async fn get_username(
     State(state): State<IdentityServiceState>,
+    Query(query): Query<UserNameRequest>,
 ) -> Result<Json<UserName>, Response> {
     let name = state
         .name_generator()
-        .generate_name()
+        .generate_name(query.theme.as_deref())
         .await
         .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, format!("{err:?}")).into_response())?;
 
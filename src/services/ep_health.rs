@@ -1,4 +1,4 @@
-use crate::services::IdentityServiceState;
+use crate::{db::SqlPool, services::IdentityServiceState};
 use axum::{extract::State, Json};
 use bb8::State as BB8PoolState;
 use serde::Serialize;
@@ -20,11 +20,18 @@ impl From<BB8PoolState> for DBState {
 }
 
 pub(in crate::services) async fn status(State(state): State<IdentityServiceState>) -> Json<Value> {
-    let json = json!
-    ( {
-        "postgres": DBState::from(state.db().postgres.state()),
-        "redis": DBState::from(state.db().redis.state())
-    });
+    let json = match &state.db().sql {
+        SqlPool::Postgres(postgres) => json!
+        ( {
+            "postgres": DBState::from(postgres.state()),
+            "redis": DBState::from(state.db().redis.state())
+        }),
+        SqlPool::Sqlite(_) => json!
+        ( {
+            "sqlite": DBState { connections: 1, idle_connections: 0 },
+            "redis": DBState::from(state.db().redis.state())
+        }),
+    };
 
     Json(json)
 }
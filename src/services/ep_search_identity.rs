@@ -29,6 +29,11 @@ impl IntoResponse for Error {
 #[derive(Deserialize)]
 pub(in crate::services) struct SearchIdentityRequest {
     count: Option<usize>,
+    tenant: Option<String>,
+    /// Comma separated list of tags, the identities having any of them are matched.
+    tags: Option<String>,
+    #[serde(default)]
+    include_total: bool,
 }
 
 pub(in crate::services) async fn search_identity(
@@ -37,17 +42,24 @@ pub(in crate::services) async fn search_identity(
     //session: AppSession,
 ) -> Result<Response, Error> {
     //let session_data = session.g();
+    let tags = query
+        .tags
+        .as_deref()
+        .map(|tags| tags.split(',').map(|tag| tag.trim().to_owned()).collect::<Vec<_>>());
     let identities = state
         .identity_manager()
         .search(SearchIdentity {
             order: SearchIdentityOrder::UserId(None),
             count: query.count,
+            tenant_id: query.tenant.as_deref(),
             user_ids: None,
             emails: None,
             names: None,
+            tags: tags.as_deref(),
+            include_total: query.include_total,
         })
         .await?;
-    log::info!("identities: {:?}", identities);
+    log::debug!("identities: {:?}", identities.items);
 
     Ok(().into_response())
 }
@@ -1,12 +1,12 @@
 use crate::{
-    db::{DBPool, IdentityManager, NameGenerator},
+    db::{DBPool, IdentityStore, NameGenerator, SharedIdentityStore},
     services::{ep_generate_user_name, ep_health, ep_search_identity},
 };
 use axum::{routing::get, Router};
 use std::sync::Arc;
 
 struct Inner {
-    identity_manager: IdentityManager,
+    identity_manager: SharedIdentityStore,
     name_generator: NameGenerator,
     db: DBPool,
 }
@@ -15,8 +15,8 @@ struct Inner {
 pub struct IdentityServiceState(Arc<Inner>);
 
 impl IdentityServiceState {
-    pub fn identity_manager(&self) -> &IdentityManager {
-        &self.0.identity_manager
+    pub fn identity_manager(&self) -> &dyn IdentityStore {
+        &*self.0.identity_manager
     }
 
     pub fn name_generator(&self) -> &NameGenerator {
@@ -29,7 +29,7 @@ impl IdentityServiceState {
 }
 
 pub struct IdentityServiceDependencies {
-    pub identity_manager: IdentityManager,
+    pub identity_manager: SharedIdentityStore,
     pub name_generator: NameGenerator,
     pub db: DBPool,
 }
@@ -0,0 +1,8 @@
+mod test_app;
+pub use self::test_app::*;
+mod test_clock;
+pub use self::test_clock::*;
+mod test_client;
+pub use self::test_client::*;
+mod test_mailbox;
+pub use self::test_mailbox::*;
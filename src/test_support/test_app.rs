@@ -0,0 +1,285 @@
+use crate::{
+    admin::IpAllowlist,
+    app_config::AppConfig,
+    create_app,
+    db::{
+        DBError, DBPool, EmailNormalizationConfig, IdentityManager, IdentityStore, PasswordManager, RandomIdGenerator,
+        RoleManager, SqlPool, DEFAULT_TENANT_ID,
+    },
+    test_support::{TestClient, TestClock, TestMailbox},
+};
+use axum::Router;
+use axum_extra::extract::cookie::Key;
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
+use chrono::Utc;
+use redis::Script;
+use serde_json::{json, Value};
+use std::{env, sync::Arc};
+use tokio_postgres::NoTls;
+use uuid::Uuid;
+
+/// Connection string of the Postgres server used by the tests. When it is not given, each test runs on its own
+/// in-memory SQLite database.
+pub const TEST_SQL_CNS: &str = "SHINE_TEST_SQL_CNS";
+/// Connection string of the Redis server used by the tests, it is required.
+pub const TEST_REDIS_CNS: &str = "SHINE_TEST_REDIS_CNS";
+/// The database of the tests without a Postgres server.
+const TEST_SQLITE_CNS: &str = "sqlite::memory:";
+
+fn generate_secret() -> String {
+    B64.encode(Key::generate().master())
+}
+
+/// Add the search path of the schema to a Postgres connection string, both the key-value and the url format
+/// are supported.
+fn with_search_path(cns: &str, schema: &str) -> String {
+    if cns.contains("://") {
+        let separator = if cns.contains('?') { '&' } else { '?' };
+        format!("{cns}{separator}options=-c%20search_path%3D{schema}")
+    } else {
+        format!("{cns} options='-c search_path={schema}'")
+    }
+}
+
+/// The configuration of the tests, the providers are not set and all the optional features are disabled.
+pub fn test_config(sql_cns: &str, redis_cns: &str) -> Value {
+    json!({
+        "version": "test",
+        "tracing": {
+            "allowReconfigure": false,
+            "enableConsoleLog": false,
+            "telemetry": { "type": "stdOut" }
+        },
+        "db": {
+            "sqlCns": sql_cns,
+            "redisCns": redis_cns
+        },
+        "auth": {
+            "homeUrl": "http://localhost/",
+            "apiUrl": "http://localhost/identity/auth",
+            "sessionSecret": generate_secret(),
+            "externalLoginSecret": generate_secret(),
+            "tokenLoginSecret": generate_secret(),
+            "mfaPendingSecret": generate_secret(),
+            "tokenHashSecret": generate_secret(),
+            "sessionMaxDuration": 3600,
+            "tokenMaxDuration": 86400,
+            "openid": {},
+            "oauth2": {}
+        },
+        "userName": {
+            "strategy": "sequence",
+            "baseGenerator": "fixed",
+            "baseName": "Tester",
+            "idEncoder": "harsh"
+        },
+        "deletion": {
+            "gracePeriod": 0
+        },
+        "email": {
+            "from": "Test <test@localhost>",
+            "transport": { "type": "log" }
+        },
+        "controlPort": 0,
+        "allowOrigins": [],
+        "adminAllowlist": {
+            "allowedRanges": ["127.0.0.0/8"],
+            "trustedProxies": []
+        }
+    })
+}
+
+/// The full application running against an isolated Postgres schema (or SQLite database). The Redis server is
+/// shared by the tests, the keys of each test have their own prefix, thus the keys not bound to a user (ex. the rate
+/// limits of the client address or of an email, the feature flags) do not interfere. The time is frozen, it moves
+/// only when the clock is advanced by the test. The emails are kept in the mailbox instead of sending them.
+pub struct TestApp {
+    pub db_pool: DBPool,
+    pub clock: Arc<TestClock>,
+    pub mailbox: Arc<TestMailbox>,
+    pub router: Router,
+    sql_cns: String,
+    schema: Option<String>,
+}
+
+impl TestApp {
+    /// Create the application with the default test configuration. The test fails when the Redis server is not
+    /// given in the environment.
+    pub async fn new() -> Self {
+        Self::with_config(|_| {}).await
+    }
+
+    /// Create the application with a customized configuration, see `test_config`.
+    pub async fn with_config<F>(update: F) -> Self
+    where
+        F: FnOnce(&mut Value),
+    {
+        let redis_cns = env::var(TEST_REDIS_CNS)
+            .unwrap_or_else(|_| panic!("{TEST_REDIS_CNS} is required by the tests, see the readme"));
+        let sql_cns = env::var(TEST_SQL_CNS).unwrap_or_else(|_| TEST_SQLITE_CNS.to_owned());
+        let test_id = format!("test_{}", Uuid::new_v4().as_simple());
+
+        let (test_cns, schema) = if sql_cns.starts_with("sqlite:") {
+            (sql_cns.clone(), None)
+        } else {
+            Self::execute(&sql_cns, &format!("CREATE SCHEMA {test_id}")).await;
+            (with_search_path(&sql_cns, &test_id), Some(test_id.clone()))
+        };
+
+        let mut config = test_config(&test_cns, &redis_cns);
+        config["db"]["redisKeyPrefix"] = json!(format!("{test_id}:"));
+        update(&mut config);
+        let config: AppConfig = serde_json::from_value(config).expect("Invalid test configuration");
+
+        let db_pool = DBPool::new(&config.db, &config.email_normalization)
+            .await
+            .expect("Failed to create test database");
+        let ip_allowlist = Arc::new(IpAllowlist::new(&config.admin_allowlist));
+        let clock = Arc::new(TestClock::new(Utc::now()));
+        let mailbox = Arc::new(TestMailbox::default());
+        let router = create_app(
+            &config,
+            &db_pool,
+            ip_allowlist,
+            None,
+            None,
+            clock.clone(),
+            Arc::new(RandomIdGenerator),
+            Some(mailbox.clone()),
+        )
+        .await
+        .expect("Failed to create test application");
+
+        Self {
+            db_pool,
+            clock,
+            mailbox,
+            router,
+            sql_cns,
+            schema,
+        }
+    }
+
+    /// Register a new user and return the client logged in with the user.
+    pub async fn registered_client(&self) -> (TestClient, Uuid) {
+        let mut client = TestClient::new(&self.router);
+        client.get("/auth/token/login?register=true").await;
+        let user_info = client.get("/api/auth/userinfo").await.json();
+        let user_id = serde_json::from_value(user_info["userId"].clone()).expect("Missing user id");
+        (client, user_id)
+    }
+
+    /// Register a new user with a role, see `registered_client`.
+    pub async fn client_with_role(&self, role: &str) -> (TestClient, Uuid) {
+        let (client, user_id) = self.registered_client().await;
+        RoleManager::new(&self.db_pool)
+            .await
+            .expect("Failed to create role manager")
+            .grant_role(user_id, role)
+            .await
+            .expect("Failed to grant role");
+        (client, user_id)
+    }
+
+    /// Create a user with an email and a password for the password logins, the user is not logged in.
+    pub async fn create_password_user(&self, email: &str, password: &str) -> Uuid {
+        let identities = IdentityManager::new(
+            &self.db_pool,
+            &EmailNormalizationConfig::default(),
+            &generate_secret(),
+            self.clock.clone(),
+            Arc::new(RandomIdGenerator),
+        )
+        .await
+        .expect("Failed to create identity manager");
+        let user_id = identities.new_user_id();
+        identities
+            .create_user(DEFAULT_TENANT_ID, user_id, "Password Tester", Some(email), None)
+            .await
+            .expect("Failed to create user");
+        PasswordManager::new(&self.db_pool)
+            .await
+            .expect("Failed to create password manager")
+            .set_password(user_id, password)
+            .await
+            .expect("Failed to set password");
+        user_id
+    }
+
+    /// Check if the identity of a user is stored.
+    pub async fn identity_exists(&self, user_id: Uuid) -> bool {
+        match &self.db_pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.unwrap();
+                client
+                    .query_opt("SELECT user_id FROM identities WHERE user_id = $1", &[&user_id])
+                    .await
+                    .unwrap()
+                    .is_some()
+            }
+            SqlPool::Sqlite(sqlite) => sqlite
+                .call(move |conn| -> Result<bool, DBError> {
+                    Ok(conn.query_row(
+                        "SELECT EXISTS(SELECT 1 FROM identities WHERE user_id = ?1)",
+                        [user_id],
+                        |row| row.get(0),
+                    )?)
+                })
+                .await
+                .unwrap(),
+        }
+    }
+
+    /// Get the number of the stored identities.
+    #[cfg(feature = "fault-injection")]
+    pub async fn identity_count(&self) -> i64 {
+        match &self.db_pool.sql {
+            SqlPool::Postgres(postgres) => {
+                let client = postgres.get().await.unwrap();
+                let row = client.query_one("SELECT count(*) FROM identities", &[]).await.unwrap();
+                row.get(0)
+            }
+            SqlPool::Sqlite(sqlite) => sqlite
+                .call(|conn| -> Result<i64, DBError> {
+                    Ok(conn.query_row("SELECT count(*) FROM identities", [], |row| row.get(0))?)
+                })
+                .await
+                .unwrap(),
+        }
+    }
+
+    /// Remove the schema and the Redis keys of the test.
+    pub async fn cleanup(self) {
+        if let Some(schema) = &self.schema {
+            Self::execute(&self.sql_cns, &format!("DROP SCHEMA {schema} CASCADE")).await;
+        }
+
+        let lua_script = r#"
+for _, key in ipairs(redis.call('KEYS', ARGV[1] .. '*')) do
+    redis.call('DEL', key)
+end
+"#;
+        let mut client = self
+            .db_pool
+            .redis
+            .get()
+            .await
+            .expect("Failed to connect to the test Redis");
+        let _: () = Script::new(lua_script)
+            .arg(&self.db_pool.redis_key_prefix)
+            .invoke_async(&mut *client)
+            .await
+            .expect("Failed to remove the test keys");
+    }
+
+    async fn execute(sql_cns: &str, query: &str) {
+        let (client, connection) = tokio_postgres::connect(sql_cns, NoTls)
+            .await
+            .expect("Failed to connect to the test database");
+        tokio::spawn(connection);
+        client
+            .batch_execute(query)
+            .await
+            .expect("Failed to prepare the test database");
+    }
+}
@@ -0,0 +1,192 @@
+use crate::service_path;
+use axum::{
+    body::{Body, Bytes},
+    extract::ConnectInfo,
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode},
+    Router,
+};
+use serde_json::Value;
+use std::{collections::HashMap, net::SocketAddr};
+use tower::ServiceExt;
+use url::Url;
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl TestResponse {
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).expect("Response is not a json")
+    }
+
+    /// Get the value of a hidden input of the form pages.
+    pub fn form_value(&self, name: &str) -> Option<String> {
+        let text = self.text();
+        let (_, value) = text.split_once(&format!("name=\"{name}\" value=\""))?;
+        let (value, _) = value.split_once('"')?;
+        Some(value.to_owned())
+    }
+
+    /// Get the target of the redirect pages.
+    pub fn redirect_url(&self) -> Option<String> {
+        let text = self.text();
+        let (_, url) = text.split_once("url='")?;
+        let (url, _) = url.split_once('\'')?;
+        Some(url.to_owned())
+    }
+}
+
+/// Client sending the requests directly to the router. The cookies set by the responses are kept between the
+/// requests similar to a browser, but all the cookie attributes (domain, path, expiration) are ignored.
+pub struct TestClient {
+    router: Router,
+    peer: SocketAddr,
+    cookies: HashMap<String, String>,
+    headers: HeaderMap,
+}
+
+impl TestClient {
+    pub fn new(router: &Router) -> Self {
+        Self {
+            router: router.clone(),
+            peer: SocketAddr::from(([127, 0, 0, 1], 12345)),
+            cookies: HashMap::new(),
+            headers: HeaderMap::new(),
+        }
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.cookies.get(name).map(String::as_str)
+    }
+
+    /// Set a cookie, ex. to replay a cookie of another client.
+    pub fn set_cookie(&mut self, name: &str, value: &str) {
+        self.cookies.insert(name.to_owned(), value.to_owned());
+    }
+
+    /// Set a header sent with all the following requests, ex. the authorization of a non-browser client.
+    pub fn set_header(&mut self, name: HeaderName, value: &str) {
+        self.headers
+            .insert(name, HeaderValue::from_str(value).expect("Invalid header value"));
+    }
+
+    /// Send a GET request to a path of the service, ex. `/auth/logout`.
+    pub async fn get(&mut self, path: &str) -> TestResponse {
+        self.send(Method::GET, &service_path(path), None).await
+    }
+
+    pub async fn post_json(&mut self, path: &str, body: &Value) -> TestResponse {
+        self.send(
+            Method::POST,
+            &service_path(path),
+            Some(("application/json", body.to_string())),
+        )
+        .await
+    }
+
+    pub async fn put_json(&mut self, path: &str, body: &Value) -> TestResponse {
+        self.send(
+            Method::PUT,
+            &service_path(path),
+            Some(("application/json", body.to_string())),
+        )
+        .await
+    }
+
+    pub async fn delete(&mut self, path: &str) -> TestResponse {
+        self.send(Method::DELETE, &service_path(path), None).await
+    }
+
+    /// Submit a form to a path of the service.
+    pub async fn post_form(&mut self, path: &str, fields: &[(&str, &str)]) -> TestResponse {
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(fields)
+            .finish();
+        self.send(
+            Method::POST,
+            &service_path(path),
+            Some(("application/x-www-form-urlencoded", body)),
+        )
+        .await
+    }
+
+    /// Follow a redirect to the service given by an absolute url, ex. the callback of an external provider.
+    pub async fn follow(&mut self, url: &str) -> TestResponse {
+        let url = Url::parse(url).expect("Invalid url");
+        let uri = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_owned(),
+        };
+        self.send(Method::GET, &uri, None).await
+    }
+
+    async fn send(&mut self, method: Method, uri: &str, body: Option<(&str, String)>) -> TestResponse {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::USER_AGENT, "shine-identity-test");
+        if !self.cookies.is_empty() {
+            let cookies = self
+                .cookies
+                .iter()
+                .map(|(name, value)| format!("{name}={value}"))
+                .collect::<Vec<_>>()
+                .join("; ");
+            request = request.header(header::COOKIE, cookies);
+        }
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let mut request = match body {
+            Some((content_type, body)) => request
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(body)),
+            None => request.body(Body::empty()),
+        }
+        .expect("Failed to create request");
+        request.extensions_mut().insert(ConnectInfo(self.peer));
+
+        let response = self
+            .router
+            .clone()
+            .oneshot(request)
+            .await
+            .expect("Failed to send request");
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .expect("Failed to read response");
+
+        for set_cookie in headers.get_all(header::SET_COOKIE) {
+            self.store_cookie(set_cookie.to_str().expect("Invalid cookie"));
+        }
+
+        TestResponse { status, headers, body }
+    }
+
+    fn store_cookie(&mut self, set_cookie: &str) {
+        let mut parts = set_cookie.split(';').map(str::trim);
+        let (name, value) = match parts.next().and_then(|cookie| cookie.split_once('=')) {
+            Some(cookie) => cookie,
+            None => return,
+        };
+        let is_removed = value.is_empty()
+            || parts.any(|attribute| {
+                let attribute = attribute.to_lowercase();
+                attribute == "max-age=0" || attribute.starts_with("expires=thu, 01 jan 1970")
+            });
+
+        if is_removed {
+            self.cookies.remove(name);
+        } else {
+            self.cookies.insert(name.to_owned(), value.to_owned());
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::email::{EmailError, EmailTransport};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
+use lettre::Message;
+use std::{sync::Mutex, time::Duration};
+
+/// An email delivered by the service.
+pub struct TestEmail {
+    pub to: Vec<String>,
+    /// The decoded plain text part of the email.
+    pub text: String,
+}
+
+impl TestEmail {
+    /// Find the value of a query parameter of a link in the email, ex. the `token` of the password reset link.
+    pub fn link_param(&self, name: &str) -> Option<String> {
+        let prefix = format!("{name}=");
+        self.text
+            .split(|c: char| c.is_whitespace() || c == '?' || c == '&')
+            .find_map(|word| word.strip_prefix(&prefix))
+            .map(str::to_owned)
+    }
+
+    /// Find the first word of the email made of the given number of digits, ex. the one-time-code.
+    pub fn code(&self, digits: usize) -> Option<String> {
+        self.text
+            .split_whitespace()
+            .find(|word| word.len() == digits && word.chars().all(|c| c.is_ascii_digit()))
+            .map(str::to_owned)
+    }
+}
+
+/// Transport keeping the emails for the tests instead of sending them.
+#[derive(Default)]
+pub struct TestMailbox(Mutex<Vec<TestEmail>>);
+
+impl TestMailbox {
+    /// Take the oldest email of the recipient. The emails are delivered in the background, thus it is waited for
+    /// a while and the test fails when no email arrives.
+    pub async fn receive(&self, to: &str) -> TestEmail {
+        for _ in 0..100 {
+            {
+                let mut emails = self.0.lock().unwrap();
+                if let Some(index) = emails
+                    .iter()
+                    .position(|email| email.to.iter().any(|address| address == to))
+                {
+                    return emails.remove(index);
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("No email has been sent to {to}");
+    }
+}
+
+#[async_trait]
+impl EmailTransport for TestMailbox {
+    async fn send(&self, message: &Message) -> Result<(), EmailError> {
+        let to = message
+            .envelope()
+            .to()
+            .iter()
+            .map(|address| address.to_string())
+            .collect();
+        let formatted = String::from_utf8_lossy(&message.formatted()).into_owned();
+        let text = plain_text(&formatted).ok_or_else(|| EmailError::Message("Missing plain text part".into()))?;
+        self.0.lock().unwrap().push(TestEmail { to, text });
+        Ok(())
+    }
+}
+
+/// Find and decode the plain text part of a formatted email, either a single part or a multipart email.
+fn plain_text(formatted: &str) -> Option<String> {
+    formatted.split("\r\n--").find_map(|part| {
+        let (headers, body) = part.split_once("\r\n\r\n")?;
+        let headers = headers.to_ascii_lowercase();
+        if !headers.contains("content-type: text/plain") {
+            return None;
+        }
+        let text = if headers.contains("content-transfer-encoding: base64") {
+            let body: String = body.split_whitespace().collect();
+            String::from_utf8_lossy(&B64.decode(body).ok()?).into_owned()
+        } else if headers.contains("content-transfer-encoding: quoted-printable") {
+            decode_quoted_printable(body)
+        } else {
+            body.to_owned()
+        };
+        Some(text.replace("\r\n", "\n"))
+    })
+}
+
+fn decode_quoted_printable(body: &str) -> String {
+    let body = body.replace("=\r\n", "");
+    let mut bytes = Vec::with_capacity(body.len());
+    let mut rest = body.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'=')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(value) => {
+                bytes.push(value);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}